@@ -0,0 +1,62 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Converts stdin to stdout between raw bytes, hex, base64 and JSON, or
+//! from any of them to CBOR or MessagePack.
+//!
+//! ```text
+//! $ echo -n foo | cargo run --example convert -- raw base64
+//! Zm9v
+//! ```
+
+extern crate rustc_serialize;
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use rustc_serialize::base64;
+use rustc_serialize::convert::{convert, Format, Options};
+
+fn usage() -> ! {
+    let _ = writeln!(io::stderr(),
+                     "usage: convert [--pretty] [--url-safe] <from> <to>\n\
+                      formats: raw, hex, base64, json, and for output only cbor, msgpack");
+    process::exit(2);
+}
+
+fn main() {
+    let mut opts = Options::default();
+    let mut formats = Vec::new();
+    for arg in env::args().skip(1) {
+        match &arg[..] {
+            "--pretty" => opts.pretty = true,
+            "--url-safe" => opts.base64 = base64::URL_SAFE,
+            "-h" | "--help" => usage(),
+            _ => match arg.parse::<Format>() {
+                Ok(format) => formats.push(format),
+                Err(e) => {
+                    let _ = writeln!(io::stderr(), "convert: {}", e);
+                    usage();
+                }
+            },
+        }
+    }
+    if formats.len() != 2 {
+        usage();
+    }
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    if let Err(e) = convert(stdin.lock(), formats[0], stdout.lock(), formats[1], &opts) {
+        let _ = writeln!(io::stderr(), "convert: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,746 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Avro binary encoding, driven by a schema given at runtime
+//!
+//! A `Schema` is parsed from the JSON form of an Avro schema, and values
+//! are `Json` values: records and maps are objects, enum symbols are
+//! strings, and `bytes` and `fixed` values are strings with one character
+//! from U+0000 to U+00FF per byte, as in Avro's JSON encoding. A value for
+//! a union is written with the first branch it matches, and record fields
+//! missing from a value are written with their default.
+//!
+//! Only the binary encoding of single values is covered: there is no
+//! object container file support and no resolution between a writer's and
+//! a reader's schema. Logical types are encoded as their underlying type.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::avro::Schema;
+//! use rustc_serialize::json::Json;
+//!
+//! fn main() {
+//!     let schema = Schema::parse_str(r#"{
+//!         "type": "record", "name": "User",
+//!         "fields": [
+//!             {"name": "name", "type": "string"},
+//!             {"name": "age", "type": ["null", "int"], "default": null}
+//!         ]
+//!     }"#).unwrap();
+//!     let user = Json::from_str(r#"{"name": "ann", "age": 42}"#).unwrap();
+//!     let bytes = schema.encode(&user).unwrap();
+//!     assert_eq!(bytes, b"\x06ann\x02\x54");
+//!     assert_eq!(schema.decode(&bytes).unwrap(), user);
+//!
+//!     let anon = schema.encode(&Json::from_str(r#"{"name": ""}"#).unwrap()).unwrap();
+//!     assert_eq!(anon, b"\x00\x00");
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::str;
+
+use json::{self, Json};
+
+/// How deeply values may nest while being encoded or decoded, counting
+/// references to named types.
+pub const MAX_DEPTH: usize = 128;
+
+/// A parsed schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    root: Type,
+    names: BTreeMap<String, Type>,
+}
+
+/// A type in a schema.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    /// `null`
+    Null,
+    /// `boolean`
+    Boolean,
+    /// `int`, 32 bits
+    Int,
+    /// `long`, 64 bits
+    Long,
+    /// `float`
+    Float,
+    /// `double`
+    Double,
+    /// `bytes`
+    Bytes,
+    /// `string`
+    String,
+    /// An array with the given item type
+    Array(Box<Type>),
+    /// A map with string keys and the given value type
+    Map(Box<Type>),
+    /// A union of the given types
+    Union(Vec<Type>),
+    /// A record with the given fields
+    Record(Vec<RecordField>),
+    /// An enum with the given symbols
+    Enum(Vec<String>),
+    /// A fixed-size byte string of the given size
+    Fixed(usize),
+    /// A reference to a named type (a record, enum or fixed), by its full
+    /// name
+    Named(String),
+}
+
+/// A field of a record type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordField {
+    /// The field name
+    pub name: String,
+    /// The field type
+    pub ty: Type,
+    /// The default, written when a value has no such field
+    pub default: Option<Json>,
+}
+
+/// Errors that can occur when parsing a schema or when encoding or
+/// decoding a value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AvroError {
+    /// The schema was not valid; the message says why
+    InvalidSchema(String),
+    /// A value to encode did not match the schema; the message says where
+    Mismatch(String),
+    /// The input ended in the middle of a value
+    Truncated,
+    /// The input was not a valid encoding of a value; the message says why
+    InvalidData(String),
+    /// Values nested more than `MAX_DEPTH` deep
+    RecursionLimitExceeded,
+}
+
+impl fmt::Display for AvroError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AvroError::InvalidSchema(ref msg) => write!(f, "invalid schema: {}", msg),
+            AvroError::Mismatch(ref msg) => write!(f, "value does not match schema: {}", msg),
+            AvroError::Truncated => write!(f, "truncated input"),
+            AvroError::InvalidData(ref msg) => write!(f, "invalid input: {}", msg),
+            AvroError::RecursionLimitExceeded => {
+                write!(f, "values nested more than {} deep", MAX_DEPTH)
+            }
+        }
+    }
+}
+
+impl error::Error for AvroError {
+    fn description(&self) -> &str {
+        match *self {
+            AvroError::InvalidSchema(_) => "invalid schema",
+            AvroError::Mismatch(_) => "value does not match schema",
+            AvroError::Truncated => "truncated input",
+            AvroError::InvalidData(_) => "invalid input",
+            AvroError::RecursionLimitExceeded => "recursion limit exceeded",
+        }
+    }
+}
+
+fn invalid_schema<T>(msg: String) -> Result<T, AvroError> {
+    Err(AvroError::InvalidSchema(msg))
+}
+
+impl Schema {
+    /// Parses a schema from its JSON form.
+    pub fn parse(json: &Json) -> Result<Schema, AvroError> {
+        let mut names = BTreeMap::new();
+        let root = try!(parse_type(json, None, &mut names));
+        Ok(Schema { root: root, names: names })
+    }
+
+    /// Parses a schema from JSON text.
+    pub fn parse_str(s: &str) -> Result<Schema, AvroError> {
+        match Json::from_str(s) {
+            Ok(json) => Schema::parse(&json),
+            Err(e) => invalid_schema(e.to_string()),
+        }
+    }
+
+    /// Returns the top-level type.
+    pub fn root(&self) -> &Type {
+        &self.root
+    }
+
+    /// Returns the named type with the full name `name`.
+    pub fn named(&self, name: &str) -> Option<&Type> {
+        self.names.get(name)
+    }
+
+    /// Encodes `value` in the Avro binary format.
+    pub fn encode(&self, value: &Json) -> Result<Vec<u8>, AvroError> {
+        let mut out = Vec::new();
+        try!(self.write(&self.root, value, &mut out, 0));
+        Ok(out)
+    }
+
+    /// Decodes a value in the Avro binary format, which must take up all of
+    /// `bytes`.
+    pub fn decode(&self, bytes: &[u8]) -> Result<Json, AvroError> {
+        let mut reader = Reader { schema: self, bytes: bytes, pos: 0 };
+        let value = try!(reader.read(&self.root, 0));
+        if reader.pos != bytes.len() {
+            return Err(AvroError::InvalidData(format!("{} trailing bytes",
+                                                      bytes.len() - reader.pos)));
+        }
+        Ok(value)
+    }
+
+    // Resolves named type references.
+    fn resolve<'a>(&'a self, ty: &'a Type) -> &'a Type {
+        match *ty {
+            Type::Named(ref name) => &self.names[name],
+            ref ty => ty,
+        }
+    }
+
+    fn write(&self, ty: &Type, value: &Json, out: &mut Vec<u8>, depth: usize)
+             -> Result<(), AvroError> {
+        if depth == MAX_DEPTH {
+            return Err(AvroError::RecursionLimitExceeded);
+        }
+        match (self.resolve(ty), value) {
+            (&Type::Null, &Json::Null) => {}
+            (&Type::Boolean, &Json::Boolean(b)) => out.push(b as u8),
+            (&Type::Int, v) if int(v).is_some() => write_long(int(v).unwrap() as i64, out),
+            (&Type::Long, v) if v.as_i64().is_some() => write_long(v.as_i64().unwrap(), out),
+            (&Type::Float, v) if v.is_number() => {
+                write_fixed((v.as_f64().unwrap() as f32).to_bits() as u64, 4, out)
+            }
+            (&Type::Double, v) if v.is_number() => {
+                write_fixed(v.as_f64().unwrap().to_bits(), 8, out)
+            }
+            (&Type::String, Json::String(s)) => {
+                write_long(s.len() as i64, out);
+                out.extend_from_slice(s.as_bytes());
+            }
+            (&Type::Bytes, v) if byte_string(v).is_some() => {
+                let bytes = byte_string(v).unwrap();
+                write_long(bytes.len() as i64, out);
+                out.extend_from_slice(&bytes);
+            }
+            (&Type::Fixed(size), v) if byte_string(v).map(|b| b.len()) == Some(size) => {
+                out.extend_from_slice(&byte_string(v).unwrap());
+            }
+            (Type::Enum(symbols), Json::String(s))
+                if symbols.iter().any(|sym| *sym == **s) => {
+                write_long(symbols.iter().position(|sym| *sym == **s).unwrap() as i64, out);
+            }
+            (Type::Array(item), Json::Array(items)) => {
+                if !items.is_empty() {
+                    write_long(items.len() as i64, out);
+                    for v in items {
+                        try!(self.write(item, v, out, depth + 1));
+                    }
+                }
+                out.push(0);
+            }
+            (Type::Map(item), Json::Object(obj)) => {
+                if !obj.is_empty() {
+                    write_long(obj.len() as i64, out);
+                    for (k, v) in obj {
+                        write_long(k.len() as i64, out);
+                        out.extend_from_slice(k.as_bytes());
+                        try!(self.write(item, v, out, depth + 1));
+                    }
+                }
+                out.push(0);
+            }
+            (Type::Record(fields), Json::Object(obj)) => {
+                for field in fields {
+                    let v = match (obj.get(&field.name), field.default.as_ref()) {
+                        (Some(v), _) | (None, Some(v)) => v,
+                        (None, None) => {
+                            return Err(AvroError::Mismatch(format!("missing field `{}`",
+                                                                   field.name)));
+                        }
+                    };
+                    try!(self.write(&field.ty, v, out, depth + 1));
+                }
+            }
+            (Type::Union(branches), v) => {
+                match branches.iter().position(|b| self.matches(b, v)) {
+                    Some(idx) => {
+                        write_long(idx as i64, out);
+                        try!(self.write(&branches[idx], v, out, depth + 1));
+                    }
+                    None => return Err(mismatch(ty, v)),
+                }
+            }
+            (_, v) => return Err(mismatch(ty, v)),
+        }
+        Ok(())
+    }
+
+    // Whether `value` has the right shape for `ty`, without looking inside
+    // arrays and objects.
+    fn matches(&self, ty: &Type, value: &Json) -> bool {
+        match (self.resolve(ty), value) {
+            (&Type::Null, &Json::Null) |
+            (&Type::Boolean, &Json::Boolean(_)) |
+            (&Type::String, &Json::String(_)) |
+            (&Type::Array(_), &Json::Array(_)) |
+            (&Type::Map(_), &Json::Object(_)) => true,
+            (&Type::Int, v) => int(v).is_some(),
+            (&Type::Long, v) => v.as_i64().is_some(),
+            (&Type::Float, v) | (&Type::Double, v) => v.is_number(),
+            (&Type::Bytes, v) => byte_string(v).is_some(),
+            (&Type::Fixed(size), v) => byte_string(v).map(|b| b.len()) == Some(size),
+            (Type::Enum(symbols), Json::String(s)) => {
+                symbols.iter().any(|sym| *sym == **s)
+            }
+            (Type::Record(fields), Json::Object(obj)) => {
+                fields.iter().all(|f| f.default.is_some() || obj.contains_key(&f.name))
+            }
+            _ => false,
+        }
+    }
+}
+
+fn mismatch(ty: &Type, value: &Json) -> AvroError {
+    let ty = match *ty {
+        Type::Named(ref name) => name.clone(),
+        ref ty => format!("{:?}", ty).split('(').next().unwrap().to_lowercase(),
+    };
+    let mut value = value.to_string();
+    if value.len() > 40 {
+        let mut end = 37;
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        value.truncate(end);
+        value.push_str("...");
+    }
+    AvroError::Mismatch(format!("expected {}, found {}", ty, value))
+}
+
+fn int(value: &Json) -> Option<i32> {
+    match value.as_i64() {
+        Some(n) if n >= i32::MIN as i64 && n <= i32::MAX as i64 => Some(n as i32),
+        _ => None,
+    }
+}
+
+// The bytes of a string with one character per byte.
+fn byte_string(value: &Json) -> Option<Vec<u8>> {
+    match *value {
+        Json::String(ref s) if s.chars().all(|c| (c as u32) < 0x100) => {
+            Some(s.chars().map(|c| c as u8).collect())
+        }
+        _ => None,
+    }
+}
+
+fn write_long(n: i64, out: &mut Vec<u8>) {
+    let mut v = ((n << 1) ^ (n >> 63)) as u64;
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn write_fixed(v: u64, n: usize, out: &mut Vec<u8>) {
+    for i in 0..n {
+        out.push((v >> (8 * i)) as u8);
+    }
+}
+
+fn parse_type(json: &Json, namespace: Option<&str>, names: &mut BTreeMap<String, Type>)
+              -> Result<Type, AvroError> {
+    match *json {
+        Json::String(ref name) => parse_name_ref(name, namespace, names),
+        Json::Array(ref branches) => {
+            let mut types = Vec::new();
+            for b in branches {
+                let ty = try!(parse_type(b, namespace, names));
+                if let Type::Union(_) = ty {
+                    return invalid_schema("unions may not contain unions".to_string());
+                }
+                types.push(ty);
+            }
+            Ok(Type::Union(types))
+        }
+        Json::Object(ref obj) => {
+            let ty = match obj.get("type") {
+                Some(Json::String(ty)) => ty,
+                Some(ty) => return parse_type(ty, namespace, names),
+                None => return invalid_schema("missing `type`".to_string()),
+            };
+            match &ty[..] {
+                "array" => match obj.get("items") {
+                    Some(items) => Ok(Type::Array(Box::new(try!(parse_type(items, namespace,
+                                                                           names))))),
+                    None => invalid_schema("array without `items`".to_string()),
+                },
+                "map" => match obj.get("values") {
+                    Some(values) => Ok(Type::Map(Box::new(try!(parse_type(values, namespace,
+                                                                          names))))),
+                    None => invalid_schema("map without `values`".to_string()),
+                },
+                "record" | "error" | "enum" | "fixed" => parse_named(obj, ty, namespace, names),
+                _ => parse_name_ref(ty, namespace, names),
+            }
+        }
+        _ => invalid_schema(format!("unexpected {}", json)),
+    }
+}
+
+fn parse_name_ref(name: &str, namespace: Option<&str>, names: &BTreeMap<String, Type>)
+                  -> Result<Type, AvroError> {
+    Ok(match name {
+        "null" => Type::Null,
+        "boolean" => Type::Boolean,
+        "int" => Type::Int,
+        "long" => Type::Long,
+        "float" => Type::Float,
+        "double" => Type::Double,
+        "bytes" => Type::Bytes,
+        "string" => Type::String,
+        _ => {
+            let full = full_name(name, namespace);
+            if names.contains_key(&full) {
+                Type::Named(full)
+            } else if names.contains_key(name) {
+                Type::Named(name.to_string())
+            } else {
+                return invalid_schema(format!("unknown type `{}`", name));
+            }
+        }
+    })
+}
+
+fn full_name(name: &str, namespace: Option<&str>) -> String {
+    match namespace {
+        Some(ns) if !name.contains('.') && !ns.is_empty() => format!("{}.{}", ns, name),
+        _ => name.to_string(),
+    }
+}
+
+// Parses a record, enum or fixed definition, registering its name.
+fn parse_named(obj: &json::Object, kind: &str, namespace: Option<&str>,
+               names: &mut BTreeMap<String, Type>) -> Result<Type, AvroError> {
+    let name = match obj.get("name") {
+        Some(Json::String(name)) if !name.is_empty() => name,
+        _ => return invalid_schema(format!("{} without a `name`", kind)),
+    };
+    let namespace = match obj.get("namespace") {
+        Some(Json::String(ns)) => Some(&ns[..]),
+        _ => namespace,
+    };
+    let full = full_name(name, namespace);
+    if names.contains_key(&full) {
+        return invalid_schema(format!("`{}` is defined twice", full));
+    }
+    // Names are relative to the namespace of the type they appear in.
+    let inner_ns = full.rfind('.').map(|idx| full[..idx].to_string());
+    let inner_ns = inner_ns.as_ref().map(|ns| &ns[..]);
+    // Register the name first so that records can refer to themselves.
+    names.insert(full.clone(), Type::Null);
+    let ty = match kind {
+        "enum" => match obj.get("symbols") {
+            Some(Json::Array(symbols)) => {
+                let mut syms = Vec::new();
+                for s in symbols {
+                    match *s {
+                        Json::String(ref s) if !syms.iter().any(|sym| *sym == **s) => {
+                            syms.push(s.to_string())
+                        }
+                        _ => return invalid_schema(format!("invalid symbols in `{}`", full)),
+                    }
+                }
+                Type::Enum(syms)
+            }
+            _ => return invalid_schema(format!("enum `{}` without `symbols`", full)),
+        },
+        "fixed" => match obj.get("size").and_then(|s| s.as_u64()) {
+            Some(size) => Type::Fixed(size as usize),
+            None => return invalid_schema(format!("fixed `{}` without `size`", full)),
+        },
+        _ => match obj.get("fields") {
+            Some(Json::Array(fields)) => {
+                let mut parsed = Vec::new();
+                for field in fields {
+                    let (name, ty) = match (field.find("name"), field.find("type")) {
+                        (Some(Json::String(name)), Some(ty)) => (name, ty),
+                        _ => return invalid_schema(format!("invalid field in `{}`", full)),
+                    };
+                    parsed.push(RecordField {
+                        name: name.to_string(),
+                        ty: try!(parse_type(ty, inner_ns, names)),
+                        default: field.find("default").cloned(),
+                    });
+                }
+                Type::Record(parsed)
+            }
+            _ => return invalid_schema(format!("record `{}` without `fields`", full)),
+        },
+    };
+    names.insert(full.clone(), ty);
+    Ok(Type::Named(full))
+}
+
+struct Reader<'a> {
+    schema: &'a Schema,
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, ty: &Type, depth: usize) -> Result<Json, AvroError> {
+        if depth == MAX_DEPTH {
+            return Err(AvroError::RecursionLimitExceeded);
+        }
+        Ok(match *self.schema.resolve(ty) {
+            Type::Null => Json::Null,
+            Type::Boolean => match try!(self.take(1))[0] {
+                0 => Json::Boolean(false),
+                1 => Json::Boolean(true),
+                b => return Err(AvroError::InvalidData(format!("invalid boolean {}", b))),
+            },
+            Type::Int => {
+                let n = try!(self.long());
+                if n < i32::MIN as i64 || n > i32::MAX as i64 {
+                    return Err(AvroError::InvalidData(format!("int {} out of range", n)));
+                }
+                integer(n)
+            }
+            Type::Long => integer(try!(self.long())),
+            Type::Float => Json::F64(f32::from_bits(try!(self.fixed(4)) as u32) as f64),
+            Type::Double => Json::F64(f64::from_bits(try!(self.fixed(8)))),
+            Type::String => {
+                let bytes = try!(self.bytes());
+                match str::from_utf8(bytes) {
+                    Ok(s) => Json::from(s),
+                    Err(_) => return Err(AvroError::InvalidData("invalid UTF-8".to_string())),
+                }
+            }
+            Type::Bytes => Json::String(try!(self.bytes()).iter().map(|&b| b as char).collect()),
+            Type::Fixed(size) => {
+                Json::String(try!(self.take(size)).iter().map(|&b| b as char).collect())
+            }
+            Type::Enum(ref symbols) => {
+                Json::from(symbols[try!(self.index(symbols.len()))].as_str())
+            }
+            Type::Array(ref item) => {
+                let mut items = Vec::new();
+                while let Some(count) = try!(self.block()) {
+                    for _ in 0..count {
+                        items.push(try!(self.read(item, depth + 1)));
+                    }
+                }
+                Json::Array(items)
+            }
+            Type::Map(ref item) => {
+                let mut obj = BTreeMap::new();
+                while let Some(count) = try!(self.block()) {
+                    for _ in 0..count {
+                        let key = try!(self.read(&Type::String, depth + 1));
+                        let value = try!(self.read(item, depth + 1));
+                        obj.insert(key.as_string().unwrap().to_string(), value);
+                    }
+                }
+                Json::Object(obj)
+            }
+            Type::Union(ref branches) => {
+                let idx = try!(self.index(branches.len()));
+                try!(self.read(&branches[idx], depth + 1))
+            }
+            Type::Record(ref fields) => {
+                let mut obj = BTreeMap::new();
+                for field in fields {
+                    obj.insert(field.name.clone(), try!(self.read(&field.ty, depth + 1)));
+                }
+                Json::Object(obj)
+            }
+            Type::Named(_) => unreachable!(),
+        })
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], AvroError> {
+        if self.bytes.len() - self.pos < n {
+            return Err(AvroError::Truncated);
+        }
+        let bytes = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn long(&mut self) -> Result<i64, AvroError> {
+        let mut v = 0u64;
+        for i in 0..10 {
+            let b = try!(self.take(1))[0];
+            v |= ((b & 0x7f) as u64) << (7 * i);
+            if b & 0x80 == 0 {
+                return Ok((v >> 1) as i64 ^ -((v & 1) as i64));
+            }
+        }
+        Err(AvroError::InvalidData("varint longer than 10 bytes".to_string()))
+    }
+
+    fn fixed(&mut self, n: usize) -> Result<u64, AvroError> {
+        let bytes = try!(self.take(n));
+        Ok(bytes.iter().rev().fold(0, |v, &b| v << 8 | b as u64))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], AvroError> {
+        let len = try!(self.long());
+        if len < 0 {
+            return Err(AvroError::InvalidData(format!("negative length {}", len)));
+        }
+        if len as u64 > (self.bytes.len() - self.pos) as u64 {
+            return Err(AvroError::Truncated);
+        }
+        self.take(len as usize)
+    }
+
+    fn index(&mut self, len: usize) -> Result<usize, AvroError> {
+        let idx = try!(self.long());
+        if idx < 0 || idx as u64 >= len as u64 {
+            return Err(AvroError::InvalidData(format!("index {} out of range", idx)));
+        }
+        Ok(idx as usize)
+    }
+
+    // Reads the count of the next block of an array or map, or None at the
+    // end. A negative count is followed by the block's size in bytes.
+    fn block(&mut self) -> Result<Option<u64>, AvroError> {
+        let count = try!(self.long());
+        if count == 0 {
+            return Ok(None);
+        }
+        if count < 0 {
+            try!(self.long());
+        }
+        let count = count.wrapping_abs() as u64;
+        // Every item but a null takes at least a byte; rejecting longer
+        // blocks keeps a bogus count from making us loop for a long time.
+        if count > (self.bytes.len() - self.pos) as u64 {
+            return Err(AvroError::InvalidData(format!("block of {} items is too long", count)));
+        }
+        Ok(Some(count))
+    }
+}
+
+// Integers are read as `Json::U64` if not negative, as `Json::from_str`
+// does.
+fn integer(n: i64) -> Json {
+    if n < 0 { Json::I64(n) } else { Json::U64(n as u64) }
+}
+
+#[cfg(test)]
+mod tests {
+    use avro::{AvroError, Schema, Type, MAX_DEPTH};
+    use json::Json;
+
+    fn json(s: &str) -> Json {
+        Json::from_str(s).unwrap()
+    }
+
+    fn round_trip(schema: &str, value: &str, encoded: &[u8]) {
+        let schema = Schema::parse_str(schema).unwrap();
+        assert_eq!(schema.encode(&json(value)).unwrap(), encoded);
+        assert_eq!(schema.decode(encoded).unwrap(), json(value));
+    }
+
+    #[test]
+    fn test_primitives() {
+        round_trip("\"null\"", "null", b"");
+        round_trip("\"boolean\"", "true", b"\x01");
+        round_trip("\"int\"", "-1", b"\x01");
+        round_trip("{\"type\": \"int\"}", "64", b"\x80\x01");
+        round_trip("\"long\"", "-9223372036854775808", b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01");
+        round_trip("\"float\"", "1.5", b"\x00\x00\xc0\x3f");
+        round_trip("\"double\"", "-2.0", b"\x00\x00\x00\x00\x00\x00\x00\xc0");
+        round_trip("\"string\"", "\"\u{e9}\"", b"\x04\xc3\xa9");
+        round_trip("\"bytes\"", "\"\\u0000\u{ff}\"", b"\x04\x00\xff");
+        round_trip("{\"type\": \"fixed\", \"name\": \"F\", \"size\": 2}", "\"ab\"", b"ab");
+        round_trip("{\"type\": \"long\", \"logicalType\": \"timestamp-millis\"}", "5", b"\x0a");
+    }
+
+    #[test]
+    fn test_complex() {
+        round_trip("{\"type\": \"array\", \"items\": \"int\"}", "[1, 2]", b"\x04\x02\x04\x00");
+        round_trip("{\"type\": \"array\", \"items\": \"int\"}", "[]", b"\x00");
+        round_trip("{\"type\": \"map\", \"values\": \"boolean\"}", "{\"a\": true}",
+                   b"\x02\x02a\x01\x00");
+        round_trip("{\"type\": \"enum\", \"name\": \"E\", \"symbols\": [\"A\", \"B\"]}",
+                   "\"B\"", b"\x02");
+        round_trip("[\"null\", \"string\", {\"type\": \"array\", \"items\": \"long\"}]",
+                   "[3]", b"\x04\x02\x06\x00");
+        // A linked list, referring to its own type from a nested namespace.
+        round_trip("{\"type\": \"record\", \"name\": \"List\", \"namespace\": \"x\", \"fields\": [
+                       {\"name\": \"v\", \"type\": \"int\"},
+                       {\"name\": \"next\", \"type\": [\"null\", \"x.List\"]}]}",
+                   "{\"v\": 1, \"next\": {\"v\": 2, \"next\": null}}", b"\x02\x02\x04\x00");
+
+        // Blocks with negative counts carry their size.
+        let schema = Schema::parse_str("{\"type\": \"array\", \"items\": \"int\"}").unwrap();
+        assert_eq!(schema.decode(b"\x03\x04\x02\x04\x02\x06\x00").unwrap(), json("[1, 2, 3]"));
+        assert_eq!(schema.named("List"), None);
+        assert_eq!(*schema.root(), Type::Array(Box::new(Type::Int)));
+    }
+
+    #[test]
+    fn test_defaults_and_errors() {
+        let schema = Schema::parse_str("{\"type\": \"record\", \"name\": \"R\", \"fields\": [
+            {\"name\": \"a\", \"type\": \"int\", \"default\": 7},
+            {\"name\": \"b\", \"type\": \"string\"}]}").unwrap();
+        assert_eq!(schema.encode(&json("{\"b\": \"\"}")).unwrap(), b"\x0e\x00");
+        assert_eq!(schema.encode(&json("{\"a\": 1}")),
+                   Err(AvroError::Mismatch("missing field `b`".to_string())));
+        assert_eq!(schema.encode(&json("{\"a\": 1.5, \"b\": \"\"}")),
+                   Err(AvroError::Mismatch("expected int, found 1.5".to_string())));
+        assert_eq!(schema.decode(b"\x0e"), Err(AvroError::Truncated));
+        assert_eq!(schema.decode(b"\x0e\x00\x00"),
+                   Err(AvroError::InvalidData("1 trailing bytes".to_string())));
+
+        let int = Schema::parse_str("\"int\"").unwrap();
+        assert!(int.encode(&json("2147483648")).is_err());
+        assert!(int.decode(b"\x80\x80\x80\x80\x10").is_err());
+        let union = Schema::parse_str("[\"null\", \"int\"]").unwrap();
+        assert!(union.decode(b"\x04").is_err());
+        assert!(union.encode(&json("\"s\"")).is_err());
+
+        for bad in ["\"nope\"", "{\"type\": \"record\", \"name\": \"R\"}", "[[\"int\"]]",
+                    "{\"type\": \"enum\", \"name\": \"E\", \"symbols\": [\"A\", \"A\"]}",
+                    "[{\"type\": \"fixed\", \"name\": \"F\", \"size\": 1},
+                      {\"type\": \"fixed\", \"name\": \"F\", \"size\": 1}]", "{", "3"].iter() {
+            match Schema::parse_str(bad) {
+                Err(AvroError::InvalidSchema(_)) => {}
+                other => panic!("{}: {:?}", bad, other),
+            }
+        }
+
+        // A record which contains itself can never be written, and reading
+        // one stops at the recursion limit rather than overflowing the stack.
+        let endless = Schema::parse_str("{\"type\": \"record\", \"name\": \"R\", \"fields\": [
+            {\"name\": \"r\", \"type\": \"R\"}]}").unwrap();
+        assert_eq!(endless.decode(b""), Err(AvroError::RecursionLimitExceeded));
+        let tree = Schema::parse_str("{\"type\": \"record\", \"name\": \"T\", \"fields\": [
+            {\"name\": \"c\", \"type\": {\"type\": \"array\", \"items\": \"T\"}}]}").unwrap();
+        let mut deep = json("{\"c\": []}");
+        for _ in 0..MAX_DEPTH / 2 {
+            let mut obj = ::std::collections::BTreeMap::new();
+            obj.insert("c".to_string(), Json::Array(vec![deep]));
+            deep = Json::Object(obj);
+        }
+        assert_eq!(tree.encode(&deep), Err(AvroError::RecursionLimitExceeded));
+    }
+}
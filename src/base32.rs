@@ -0,0 +1,263 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Base32 binary-to-text encoding
+//!
+//! `Base32Codec` is a streaming `codec::Codec` for the RFC 4648 base32
+//! alphabet, `A`-`Z` and `2`-`7`. Encoding writes upper case, padded with
+//! `=` to a multiple of eight characters unless padding is turned off.
+//! Decoding accepts either case, with or without padding, and ignores line
+//! breaks.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use std::io::{Read, Write};
+//! use rustc_serialize::base32::Base32Codec;
+//! use rustc_serialize::codec::{CodecReader, CodecWriter};
+//!
+//! fn main() {
+//!     let mut writer = CodecWriter::new(Vec::new(), Base32Codec::new(true));
+//!     writer.write_all(b"foobar").unwrap();
+//!     let encoded = writer.finish().unwrap();
+//!     assert_eq!(encoded, b"MZXW6YTBOI======");
+//!
+//!     let mut decoded = Vec::new();
+//!     let mut reader = CodecReader::new(&b"mzxw6ytboi"[..], Base32Codec::new(false));
+//!     reader.read_to_end(&mut decoded).unwrap();
+//!     assert_eq!(decoded, b"foobar");
+//! }
+//! ```
+
+pub use self::FromBase32Error::*;
+
+use std::error;
+use std::fmt;
+
+use codec::Codec;
+
+static CHARS: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Errors that can occur when decoding a base32 encoded string
+#[derive(Clone, Copy, PartialEq)]
+pub enum FromBase32Error {
+    /// The input contained a character not part of the base32 format, at
+    /// the given byte offset
+    InvalidBase32Byte(u8, usize),
+    /// The input had an invalid length
+    InvalidBase32Length,
+}
+
+impl fmt::Debug for FromBase32Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvalidBase32Byte(ch, idx) =>
+                write!(f, "Invalid character '{}' at position {}", ch, idx),
+            InvalidBase32Length => write!(f, "Invalid length"),
+        }
+    }
+}
+
+impl error::Error for FromBase32Error {
+    fn description(&self) -> &str {
+        match *self {
+            InvalidBase32Byte(_, _) => "invalid character",
+            InvalidBase32Length => "invalid length",
+        }
+    }
+}
+
+impl fmt::Display for FromBase32Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self, f)
+    }
+}
+
+/// A streaming base32 `Codec`, for use with `codec::CodecReader` and
+/// `codec::CodecWriter`.
+///
+/// Error positions are byte offsets from the start of the stream.
+#[derive(Clone, Copy, Debug)]
+pub struct Base32Codec {
+    pad: bool,
+    // Encoding: the bytes of an incomplete group of five.
+    rem: [u8; 5],
+    rem_len: usize,
+    // Decoding: the bits of an incomplete group of eight characters.
+    buf: u64,
+    count: usize,
+    seen_equals: bool,
+    pos: usize,
+}
+
+impl Base32Codec {
+    /// Creates a new base32 codec, which pads its output if `pad` is true.
+    pub fn new(pad: bool) -> Base32Codec {
+        Base32Codec {
+            pad: pad,
+            rem: [0; 5],
+            rem_len: 0,
+            buf: 0,
+            count: 0,
+            seen_equals: false,
+            pos: 0,
+        }
+    }
+
+    // Encodes the first `len` bytes of `rem`.
+    fn encode_group(&self, len: usize, output: &mut Vec<u8>) {
+        let mut n = 0u64;
+        for i in 0..5 {
+            n = n << 8 | if i < len { self.rem[i] as u64 } else { 0 };
+        }
+        let chars = (len * 8 + 4) / 5;
+        for i in 0..chars {
+            output.push(CHARS[(n >> (35 - 5 * i)) as usize & 31]);
+        }
+        if self.pad {
+            for _ in chars..8 {
+                output.push(b'=');
+            }
+        }
+    }
+}
+
+impl Codec for Base32Codec {
+    type Error = FromBase32Error;
+
+    fn encode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), FromBase32Error> {
+        output.reserve((input.len() + 4) / 5 * 8);
+        for &byte in input.iter() {
+            self.rem[self.rem_len] = byte;
+            self.rem_len += 1;
+            if self.rem_len == 5 {
+                self.encode_group(5, output);
+                self.rem_len = 0;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), FromBase32Error> {
+        for (idx, &byte) in input.iter().enumerate() {
+            if self.seen_equals {
+                match byte {
+                    b'=' | b'\r' | b'\n' => continue,
+                    _ => return Err(InvalidBase32Byte(byte, self.pos + idx)),
+                }
+            }
+            let code = match byte {
+                b'A'...b'Z' => byte - b'A',
+                b'a'...b'z' => byte - b'a',
+                b'2'...b'7' => byte - b'2' + 26,
+                b'=' => { self.seen_equals = true; continue }
+                b'\r' | b'\n' => continue,
+                _ => return Err(InvalidBase32Byte(byte, self.pos + idx)),
+            };
+            self.buf = self.buf << 5 | code as u64;
+            self.count += 1;
+            if self.count == 8 {
+                for i in 0..5 {
+                    output.push((self.buf >> (32 - 8 * i)) as u8);
+                }
+                self.buf = 0;
+                self.count = 0;
+            }
+        }
+        self.pos += input.len();
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), FromBase32Error> {
+        // Encoding: emit the final partial group.
+        if self.rem_len > 0 {
+            let len = self.rem_len;
+            self.encode_group(len, output);
+            self.rem_len = 0;
+        }
+
+        // Decoding: flush the final partial group, which must hold a whole
+        // number of bytes.
+        let count = self.count;
+        self.count = 0;
+        match count {
+            0 => {}
+            2 | 4 | 5 | 7 => {
+                let n = self.buf << (40 - 5 * count);
+                for i in 0..count * 5 / 8 {
+                    output.push((n >> (32 - 8 * i)) as u8);
+                }
+            }
+            _ => return Err(InvalidBase32Length),
+        }
+        self.buf = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+    use base32::{Base32Codec, FromBase32Error};
+    use codec::{CodecReader, CodecWriter};
+
+    fn encode(data: &[u8], pad: bool, piece: usize) -> String {
+        let mut w = CodecWriter::new(Vec::new(), Base32Codec::new(pad));
+        for chunk in data.chunks(piece) {
+            w.write_all(chunk).unwrap();
+        }
+        String::from_utf8(w.finish().unwrap()).unwrap()
+    }
+
+    fn decode(text: &str) -> Result<Vec<u8>, FromBase32Error> {
+        let mut decoded = Vec::new();
+        let mut r = CodecReader::new(text.as_bytes(), Base32Codec::new(true));
+        match r.read_to_end(&mut decoded) {
+            Ok(_) => Ok(decoded),
+            Err(e) => Err(*e.get_ref().unwrap().downcast_ref::<FromBase32Error>().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_rfc4648_vectors() {
+        let vectors = [("", ""), ("f", "MY======"), ("fo", "MZXQ===="), ("foo", "MZXW6==="),
+                       ("foob", "MZXW6YQ="), ("fooba", "MZXW6YTB"),
+                       ("foobar", "MZXW6YTBOI======")];
+        for &(plain, encoded) in vectors.iter() {
+            assert_eq!(encode(plain.as_bytes(), true, 3), encoded);
+            assert_eq!(encode(plain.as_bytes(), false, 1), encoded.trim_end_matches('='));
+            assert_eq!(decode(encoded).unwrap(), plain.as_bytes());
+            assert_eq!(decode(&encoded.trim_end_matches('=').to_lowercase()).unwrap(),
+                       plain.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..256).map(|i| i as u8).collect();
+        for piece in 1..7 {
+            let encoded = encode(&data, true, piece);
+            assert_eq!(encoded.len(), (256 + 4) / 5 * 8);
+            assert_eq!(decode(&encoded).unwrap(), data);
+        }
+        assert_eq!(decode("MZXW\r\n6YTB\n").unwrap(), b"fooba");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(decode("MZXW1"), Err(FromBase32Error::InvalidBase32Byte(b'1', 4)));
+        assert_eq!(decode("MY==x"), Err(FromBase32Error::InvalidBase32Byte(b'x', 4)));
+        assert_eq!(decode("M"), Err(FromBase32Error::InvalidBase32Length));
+        assert_eq!(decode("MZXW6Y"), Err(FromBase32Error::InvalidBase32Length));
+    }
+}
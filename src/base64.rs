@@ -18,6 +18,8 @@ pub use self::CharacterSet::*;
 use std::fmt;
 use std::error;
 
+use codec::Codec;
+
 /// Available encoding character sets
 #[derive(Clone, Copy, Debug)]
 pub enum CharacterSet {
@@ -57,6 +59,12 @@ pub static STANDARD: Config =
 pub static URL_SAFE: Config =
     Config {char_set: UrlSafe, newline: Newline::CRLF, pad: false, line_length: None};
 
+/// Configuration for unpadded RFC 4648 base64url encoding, the form used
+/// by JWS, JWT and other URL and header values (RFC 7515 section 2). It is
+/// the same as `URL_SAFE`, but says so at the point of use.
+pub static URL_SAFE_NO_PAD: Config =
+    Config {char_set: UrlSafe, newline: Newline::CRLF, pad: false, line_length: None};
+
 /// Configuration for RFC 2045 MIME base64 encoding
 pub static MIME: Config =
     Config {char_set: Standard, newline: Newline::CRLF, pad: true, line_length: Some(76)};
@@ -329,6 +337,343 @@ impl<'a, T: ?Sized + FromBase64> FromBase64 for &'a T {
     }
 }
 
+/// The parts of a JWS compact serialization, `header.payload.signature`,
+/// decoded from base64url.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JwsParts<'a> {
+    /// The decoded protected header (JSON text, unparsed)
+    pub header: Vec<u8>,
+    /// The decoded payload
+    pub payload: Vec<u8>,
+    /// The decoded signature
+    pub signature: Vec<u8>,
+    /// The encoded `header.payload`, the input the signature is over
+    pub signing_input: &'a str,
+}
+
+/// Errors that can occur splitting a JWS compact serialization
+#[derive(Clone, Copy, Debug)]
+pub enum JwsError {
+    /// The token did not have exactly three parts; the number is given
+    WrongPartCount(usize),
+    /// A part (0 for the header, 1 the payload, 2 the signature) was not
+    /// unpadded base64url
+    InvalidPart(usize, FromBase64Error),
+}
+
+impl fmt::Display for JwsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JwsError::WrongPartCount(n) => write!(f, "expected 3 parts, found {}", n),
+            JwsError::InvalidPart(idx, ref e) => {
+                let part = ["header", "payload", "signature"][idx];
+                write!(f, "invalid {}: {}", part, e)
+            }
+        }
+    }
+}
+
+impl error::Error for JwsError {
+    fn description(&self) -> &str { "invalid JWS compact serialization" }
+}
+
+/// Splits a JWS compact serialization into its decoded parts.
+///
+/// Each part must be strictly unpadded base64url: padding, line breaks and
+/// the standard alphabet's `+` and `/` are rejected.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::base64;
+///
+/// fn main() {
+///     let token = base64::join_jws(b"{\"alg\":\"none\"}", b"hi", b"");
+///     assert_eq!(token, "eyJhbGciOiJub25lIn0.aGk.");
+///     let parts = base64::split_jws(&token).unwrap();
+///     assert_eq!(parts.payload, b"hi");
+///     assert_eq!(parts.signing_input, "eyJhbGciOiJub25lIn0.aGk");
+/// }
+/// ```
+pub fn split_jws(token: &str) -> Result<JwsParts<'_>, JwsError> {
+    let mut parts = token.split('.');
+    let (header, payload, signature) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(h), Some(p), Some(s)) if parts.next().is_none() => (h, p, s),
+        _ => return Err(JwsError::WrongPartCount(token.split('.').count())),
+    };
+    let decode = |idx: usize, part: &str| {
+        let base = part.as_ptr() as usize - token.as_ptr() as usize;
+        for (i, &b) in part.as_bytes().iter().enumerate() {
+            match b {
+                b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'_' => {}
+                _ => return Err(JwsError::InvalidPart(idx, InvalidBase64Byte(b, base + i))),
+            }
+        }
+        part.from_base64().map_err(|e| JwsError::InvalidPart(idx, e))
+    };
+    Ok(JwsParts {
+        header: try!(decode(0, header)),
+        payload: try!(decode(1, payload)),
+        signature: try!(decode(2, signature)),
+        signing_input: &token[..header.len() + 1 + payload.len()],
+    })
+}
+
+/// Joins the parts of a JWS into its compact serialization, encoding each
+/// as unpadded base64url.
+pub fn join_jws(header: &[u8], payload: &[u8], signature: &[u8]) -> String {
+    let mut token = jws_signing_input(header, payload);
+    token.push('.');
+    token.push_str(&signature.to_base64(URL_SAFE_NO_PAD));
+    token
+}
+
+/// Returns the length of the base64 encoding of `len` bytes, without line
+/// wrapping.
+pub const fn encoded_len(len: usize, pad: bool) -> usize {
+    if pad {
+        (len + 2) / 3 * 4
+    } else {
+        len / 3 * 4 + [0, 2, 3][len % 3]
+    }
+}
+
+/// Encodes `bytes` as base64 with the given character set, padded or not
+/// and without line wrapping, in a constant expression.
+///
+/// `M` must be `encoded_len(N, pad)`; any other length fails to evaluate,
+/// which in a constant is a compile-time error.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::base64::{self, CharacterSet};
+///
+/// const KEY: [u8; 4] = [0xfb, 0xff, 0x00, 0x01];
+/// const KEY_B64: [u8; base64::encoded_len(4, false)] =
+///     base64::encode_const(&KEY, CharacterSet::UrlSafe, false);
+///
+/// fn main() {
+///     assert_eq!(&KEY_B64, b"-_8AAQ");
+/// }
+/// ```
+pub const fn encode_const<const N: usize, const M: usize>(bytes: &[u8; N],
+                                                          char_set: CharacterSet,
+                                                          pad: bool) -> [u8; M] {
+    // The character tables are statics, which constant functions cannot
+    // read.
+    const STANDARD: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+                                  abcdefghijklmnopqrstuvwxyz0123456789+/";
+    const URLSAFE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+                                 abcdefghijklmnopqrstuvwxyz0123456789-_";
+    // Fails if `M` is not the encoded length: `assert!` cannot be used in
+    // constant functions before Rust 1.57.
+    let _ = [0][(M != encoded_len(N, pad)) as usize];
+    let chars = match char_set {
+        CharacterSet::Standard => STANDARD,
+        CharacterSet::UrlSafe => URLSAFE,
+    };
+    let mut out = [b'='; M];
+    let (mut i, mut o) = (0, 0);
+    while i < N {
+        let n = (bytes[i] as u32) << 16 |
+                if i + 1 < N { (bytes[i + 1] as u32) << 8 } else { 0 } |
+                if i + 2 < N { bytes[i + 2] as u32 } else { 0 };
+        // One more character than there are bytes in this group.
+        let len = if N - i < 3 { N - i + 1 } else { 4 };
+        let mut j = 0;
+        while j < len {
+            out[o + j] = chars[((n >> (18 - 6 * j)) & 63) as usize];
+            j += 1;
+        }
+        i += 3;
+        o += 4;
+    }
+    out
+}
+
+/// Returns the encoded `header.payload` of a JWS, the input to sign.
+pub fn jws_signing_input(header: &[u8], payload: &[u8]) -> String {
+    let mut input = header.to_base64(URL_SAFE_NO_PAD);
+    input.push('.');
+    input.push_str(&payload.to_base64(URL_SAFE_NO_PAD));
+    input
+}
+
+/// A streaming base64 `Codec`, for use with `codec::CodecReader` and
+/// `codec::CodecWriter`.
+///
+/// Encoding produces the same output as `ToBase64` with the given
+/// configuration, regardless of how the input is split into chunks.
+/// Decoding accepts the same input as `FromBase64`; error positions are byte
+/// offsets from the start of the stream.
+#[derive(Clone, Copy, Debug)]
+pub struct Base64Codec {
+    config: Config,
+    // Encoding state: leftover input bytes and the current line length.
+    rem: [u8; 2],
+    rem_len: usize,
+    cur_length: usize,
+    // Decoding state.
+    buf: u32,
+    modulus: usize,
+    seen_equals: bool,
+    pos: usize,
+}
+
+impl Base64Codec {
+    /// Creates a new base64 codec using `config` for encoding.
+    pub fn new(config: Config) -> Base64Codec {
+        Base64Codec {
+            config: config,
+            rem: [0; 2],
+            rem_len: 0,
+            cur_length: 0,
+            buf: 0,
+            modulus: 0,
+            seen_equals: false,
+            pos: 0,
+        }
+    }
+
+    fn chars(&self) -> &'static [u8] {
+        match self.config.char_set {
+            Standard => STANDARD_CHARS,
+            UrlSafe => URLSAFE_CHARS
+        }
+    }
+
+    fn line_break(&mut self, output: &mut Vec<u8>) {
+        if let Some(line_length) = self.config.line_length {
+            if self.cur_length >= line_length {
+                match self.config.newline {
+                    Newline::LF => output.push(b'\n'),
+                    Newline::CRLF => output.extend_from_slice(b"\r\n"),
+                }
+                self.cur_length = 0;
+            }
+        }
+    }
+
+    fn encode_triple(&mut self, first: u8, second: u8, third: u8,
+                     output: &mut Vec<u8>) {
+        let bytes = self.chars();
+        self.line_break(output);
+        let n = (first as u32) << 16 | (second as u32) << 8 | third as u32;
+        output.push(bytes[((n >> 18) & 63) as usize]);
+        output.push(bytes[((n >> 12) & 63) as usize]);
+        output.push(bytes[((n >> 6) & 63) as usize]);
+        output.push(bytes[(n & 63) as usize]);
+        self.cur_length += 4;
+    }
+}
+
+impl Codec for Base64Codec {
+    type Error = FromBase64Error;
+
+    fn encode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), FromBase64Error> {
+        let mut input = input;
+        // Complete a triple left over from the previous chunk first.
+        while self.rem_len > 0 && !input.is_empty() {
+            if self.rem_len == 2 {
+                let (first, second) = (self.rem[0], self.rem[1]);
+                self.encode_triple(first, second, input[0], output);
+                self.rem_len = 0;
+            } else {
+                self.rem[1] = input[0];
+                self.rem_len = 2;
+            }
+            input = &input[1..];
+        }
+
+        let full = input.len() - input.len() % 3;
+        for triple in input[..full].chunks(3) {
+            self.encode_triple(triple[0], triple[1], triple[2], output);
+        }
+        for &b in input[full..].iter() {
+            self.rem[self.rem_len] = b;
+            self.rem_len += 1;
+        }
+        Ok(())
+    }
+
+    fn decode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), FromBase64Error> {
+        for (idx, &byte) in input.iter().enumerate() {
+            if self.seen_equals {
+                match byte {
+                    b'=' | b'\r' | b'\n' => continue,
+                    _ => return Err(InvalidBase64Byte(byte, self.pos + idx)),
+                }
+            }
+            let code = DECODE_TABLE[byte as usize];
+            if code >= SPECIAL_CODES_START {
+                match code {
+                    NEWLINE_CODE => continue,
+                    EQUALS_CODE => { self.seen_equals = true; continue }
+                    INVALID_CODE => {
+                        return Err(InvalidBase64Byte(byte, self.pos + idx))
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            self.buf = (self.buf | code as u32) << 6;
+            self.modulus += 1;
+            if self.modulus == 4 {
+                self.modulus = 0;
+                output.push((self.buf >> 22) as u8);
+                output.push((self.buf >> 14) as u8);
+                output.push((self.buf >> 6 ) as u8);
+                self.buf = 0;
+            }
+        }
+        self.pos += input.len();
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), FromBase64Error> {
+        // Encoding: emit the final partial group.
+        if self.rem_len > 0 {
+            let bytes = self.chars();
+            self.line_break(output);
+            let n = match self.rem_len {
+                1 => (self.rem[0] as u32) << 16,
+                _ => (self.rem[0] as u32) << 16 | (self.rem[1] as u32) << 8,
+            };
+            output.push(bytes[((n >> 18) & 63) as usize]);
+            output.push(bytes[((n >> 12) & 63) as usize]);
+            if self.rem_len == 2 {
+                output.push(bytes[((n >> 6) & 63) as usize]);
+            }
+            if self.config.pad {
+                for _ in self.rem_len..3 {
+                    output.push(b'=');
+                }
+            }
+            self.rem_len = 0;
+        }
+
+        // Decoding: flush the final partial quantum.
+        let modulus = self.modulus;
+        self.modulus = 0;
+        match modulus {
+            2 => {
+                output.push((self.buf >> 10) as u8);
+            }
+            3 => {
+                output.push((self.buf >> 16) as u8);
+                output.push((self.buf >> 8 ) as u8);
+            }
+            0 => (),
+            _ => return Err(InvalidBase64Length),
+        }
+        Ok(())
+    }
+}
+
 /// Base64 decoding lookup table, generated using:
 ///
 /// ```
@@ -377,7 +722,9 @@ const SPECIAL_CODES_START: u8 = NEWLINE_CODE;
 
 #[cfg(test)]
 mod tests {
-    use base64::{Config, Newline, FromBase64, ToBase64, STANDARD, URL_SAFE};
+    use base64::{Config, Newline, FromBase64, ToBase64, STANDARD, URL_SAFE, URL_SAFE_NO_PAD};
+    use base64::{join_jws, split_jws, InvalidBase64Byte, JwsError};
+    use base64::{encode_const, encoded_len, CharacterSet};
 
     #[test]
     fn test_to_base64_basic() {
@@ -423,6 +770,69 @@ mod tests {
         assert_eq!([251, 255].to_base64(STANDARD), "+/8=");
     }
 
+    #[test]
+    fn test_encode_const() {
+        const EMPTY: [u8; 0] = encode_const(&[], CharacterSet::Standard, true);
+        const FOOB: [u8; 8] = encode_const(b"foob", CharacterSet::Standard, true);
+        const FOOB_NO_PAD: [u8; 6] = encode_const(b"foob", CharacterSet::Standard, false);
+        assert_eq!(EMPTY, [0u8; 0]);
+        assert_eq!(&FOOB, b"Zm9vYg==");
+        assert_eq!(&FOOB_NO_PAD, b"Zm9vYg");
+
+        let bytes: [u8; 5] = [0xfb, 0xff, 0xbf, 0x00, 0x3e];
+        for &pad in [true, false].iter() {
+            let config = Config { pad: pad, ..URL_SAFE };
+            assert_eq!(encoded_len(5, pad), bytes.to_base64(config).len());
+        }
+        assert_eq!(&encode_const::<5, 8>(&bytes, CharacterSet::Standard, true)[..],
+                   bytes.to_base64(STANDARD).as_bytes());
+        assert_eq!(&encode_const::<5, 7>(&bytes, CharacterSet::UrlSafe, false)[..],
+                   bytes.to_base64(URL_SAFE).as_bytes());
+    }
+
+    #[test]
+    fn test_to_base64_url_safe_no_pad() {
+        assert_eq!([251, 255].to_base64(URL_SAFE_NO_PAD), "-_8");
+        assert_eq!(b"f".to_base64(URL_SAFE_NO_PAD), "Zg");
+        assert_eq!(b"foo\xff".to_base64(URL_SAFE_NO_PAD), b"foo\xff".to_base64(URL_SAFE));
+    }
+
+    #[test]
+    fn test_jws() {
+        // RFC 7515 appendix A.1
+        let token = "eyJ0eXAiOiJKV1QiLA0KICJhbGciOiJIUzI1NiJ9\
+                     .eyJpc3MiOiJqb2UiLA0KICJleHAiOjEzMDA4MTkzODAsDQogImh0dHA6Ly9leGFt\
+                     cGxlLmNvbS9pc19yb290Ijp0cnVlfQ\
+                     .dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        let parts = split_jws(token).unwrap();
+        assert_eq!(parts.header, b"{\"typ\":\"JWT\",\r\n \"alg\":\"HS256\"}");
+        assert_eq!(&parts.payload[..12], b"{\"iss\":\"joe\"");
+        assert_eq!(parts.signature.len(), 32);
+        assert_eq!(parts.signing_input, &token[..token.rfind('.').unwrap()]);
+        assert_eq!(join_jws(&parts.header, &parts.payload, &parts.signature), token);
+
+        match split_jws("a.b") {
+            Err(JwsError::WrongPartCount(2)) => {}
+            other => panic!("{:?}", other),
+        }
+        match split_jws("a.b.c.d") {
+            Err(JwsError::WrongPartCount(4)) => {}
+            other => panic!("{:?}", other),
+        }
+        match split_jws("Zg.Zg==.") {
+            Err(JwsError::InvalidPart(1, InvalidBase64Byte(b'=', 5))) => {}
+            other => panic!("{:?}", other),
+        }
+        match split_jws("Zg.Zg.+/8") {
+            Err(JwsError::InvalidPart(2, InvalidBase64Byte(b'+', 6))) => {}
+            other => panic!("{:?}", other),
+        }
+        match split_jws("Z.Zg.") {
+            Err(JwsError::InvalidPart(0, _)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
     #[test]
     fn test_to_base64_empty_line_length() {
         [].to_base64(Config {line_length: Some(72), ..STANDARD});
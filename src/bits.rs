@@ -0,0 +1,273 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reading and writing streams of bits
+//!
+//! `BitReader` reads values of any width up to 64 bits from a byte stream,
+//! and `BitWriter` writes them, for formats whose fields do not fall on
+//! byte boundaries, such as compressed data. Bits are taken from each byte
+//! in one of two orders:
+//!
+//! * `BitOrder::MsbFirst` starts at a byte's most significant bit, and
+//!   reads values most significant bit first, as in JPEG or H.264;
+//! * `BitOrder::LsbFirst` starts at a byte's least significant bit, and
+//!   reads values least significant bit first, as in DEFLATE.
+//!
+//! Both read and write a byte at a time, so a `BufReader` or `BufWriter`
+//! is worth putting underneath them.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::bits::{BitOrder, BitReader, BitWriter};
+//!
+//! fn main() {
+//!     let mut writer = BitWriter::new(Vec::new(), BitOrder::MsbFirst);
+//!     writer.write_bits(0b101, 3).unwrap();
+//!     writer.write_bits(0x1FF, 9).unwrap();
+//!     let bytes = writer.finish().unwrap();
+//!     assert_eq!(bytes, [0b1011_1111, 0b1111_0000]);
+//!
+//!     let mut reader = BitReader::new(&bytes[..], BitOrder::MsbFirst);
+//!     assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+//!     assert_eq!(reader.read_bits(9).unwrap(), 0x1FF);
+//! }
+//! ```
+
+use std::io::{self, Read, Write};
+
+/// The order in which bits are taken from each byte; see the module
+/// documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitOrder {
+    /// Most significant bit first.
+    MsbFirst,
+    /// Least significant bit first.
+    LsbFirst,
+}
+
+// The lowest `n` bits set, for `n` up to 8.
+fn mask(n: u32) -> u64 {
+    (1 << n) - 1
+}
+
+/// A reader of bits from a byte stream.
+pub struct BitReader<R> {
+    inner: R,
+    order: BitOrder,
+    byte: u8,
+    // The number of bits of `byte` not yet read.
+    left: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    /// Creates a reader of the bytes of `inner`, in `order`.
+    pub fn new(inner: R, order: BitOrder) -> BitReader<R> {
+        BitReader { inner: inner, order: order, byte: 0, left: 0 }
+    }
+
+    /// Reads a value `n` bits wide. The input ending first is an
+    /// `UnexpectedEof` error, the bits read up to then being lost.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is above 64.
+    pub fn read_bits(&mut self, n: u32) -> io::Result<u64> {
+        assert!(n <= 64, "cannot read {} bits at once", n);
+        let mut value = 0;
+        let mut done = 0;
+        while done < n {
+            if self.left == 0 {
+                let mut buf = [0];
+                try!(self.inner.read_exact(&mut buf));
+                self.byte = buf[0];
+                self.left = 8;
+            }
+            let k = (n - done).min(self.left);
+            let byte = self.byte as u64;
+            match self.order {
+                BitOrder::MsbFirst => {
+                    let chunk = byte >> (self.left - k) & mask(k);
+                    value = value << k | chunk;
+                }
+                BitOrder::LsbFirst => {
+                    let chunk = byte >> (8 - self.left) & mask(k);
+                    value |= chunk << done;
+                }
+            }
+            self.left -= k;
+            done += k;
+        }
+        Ok(value)
+    }
+
+    /// Reads a single bit.
+    pub fn read_bit(&mut self) -> io::Result<bool> {
+        self.read_bits(1).map(|b| b == 1)
+    }
+
+    /// Whether the next bit to read is the first of a byte.
+    pub fn is_aligned(&self) -> bool {
+        self.left == 0
+    }
+
+    /// Skips the rest of the current byte, if any, so that the next bit
+    /// read is the first of a byte.
+    pub fn align_to_byte(&mut self) {
+        self.left = 0;
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwraps this reader, returning the underlying reader. The rest of
+    /// the current byte, if any, is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// A writer of bits to a byte stream.
+///
+/// A byte is written once all of its bits are; `finish` or `pad_to_byte`
+/// write the last one, padded with zero bits.
+pub struct BitWriter<W: Write> {
+    inner: W,
+    order: BitOrder,
+    byte: u8,
+    // The number of bits of `byte` written.
+    used: u32,
+}
+
+impl<W: Write> BitWriter<W> {
+    /// Creates a writer of bytes to `inner`, in `order`.
+    pub fn new(inner: W, order: BitOrder) -> BitWriter<W> {
+        BitWriter { inner: inner, order: order, byte: 0, used: 0 }
+    }
+
+    /// Writes the lowest `n` bits of `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is above 64.
+    pub fn write_bits(&mut self, value: u64, n: u32) -> io::Result<()> {
+        assert!(n <= 64, "cannot write {} bits at once", n);
+        let mut done = 0;
+        while done < n {
+            let k = (n - done).min(8 - self.used);
+            match self.order {
+                BitOrder::MsbFirst => {
+                    let chunk = value >> (n - done - k) & mask(k);
+                    self.byte |= (chunk << (8 - self.used - k)) as u8;
+                }
+                BitOrder::LsbFirst => {
+                    let chunk = value >> done & mask(k);
+                    self.byte |= (chunk << self.used) as u8;
+                }
+            }
+            self.used += k;
+            done += k;
+            if self.used == 8 {
+                try!(self.write_byte());
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a single bit.
+    pub fn write_bit(&mut self, bit: bool) -> io::Result<()> {
+        self.write_bits(bit as u64, 1)
+    }
+
+    fn write_byte(&mut self) -> io::Result<()> {
+        try!(self.inner.write_all(&[self.byte]));
+        self.byte = 0;
+        self.used = 0;
+        Ok(())
+    }
+
+    /// Whether the next bit to write is the first of a byte.
+    pub fn is_aligned(&self) -> bool {
+        self.used == 0
+    }
+
+    /// Fills the rest of the current byte, if any, with zero bits and
+    /// writes it, so that the next bit written is the first of a byte.
+    pub fn pad_to_byte(&mut self) -> io::Result<()> {
+        if self.used == 0 {
+            return Ok(());
+        }
+        self.write_byte()
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Pads and writes the last byte, as `pad_to_byte` does, flushes the
+    /// underlying writer and returns it.
+    pub fn finish(mut self) -> io::Result<W> {
+        try!(self.pad_to_byte());
+        try!(self.inner.flush());
+        Ok(self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::{BitOrder, BitReader, BitWriter};
+
+    #[test]
+    fn test_bits() {
+        let fields = [(0b101, 3), (1, 1), (0xF, 4), (0x3FF, 10), (0, 2),
+                      (0x0123_4567_89AB_CDEF, 64), (u64::MAX, 64), (0x5A, 7), (1, 1)];
+        for &order in [BitOrder::MsbFirst, BitOrder::LsbFirst].iter() {
+            let mut writer = BitWriter::new(Vec::new(), order);
+            for &(value, n) in fields.iter() {
+                writer.write_bits(value, n).unwrap();
+            }
+            assert!(!writer.is_aligned());
+            let bytes = writer.finish().unwrap();
+            assert_eq!(bytes.len(), 20);
+
+            let mut reader = BitReader::new(&bytes[..], order);
+            for &(value, n) in fields.iter() {
+                assert_eq!(reader.read_bits(n).unwrap(), value, "{:?}", order);
+            }
+            assert_eq!(reader.read_bits(0).unwrap(), 0);
+            reader.align_to_byte();
+            assert!(reader.is_aligned());
+            assert_eq!(reader.read_bit().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        }
+
+        // Bits fill each byte from its most or least significant end.
+        let mut writer = BitWriter::new(Vec::new(), BitOrder::MsbFirst);
+        writer.write_bits(0b101, 3).unwrap();
+        writer.write_bit(true).unwrap();
+        writer.write_bits(0b0110, 4).unwrap();
+        assert!(writer.is_aligned());
+        assert_eq!(writer.get_ref(), &[0b1011_0110]);
+        let mut writer = BitWriter::new(Vec::new(), BitOrder::LsbFirst);
+        writer.write_bits(0b101, 3).unwrap();
+        writer.write_bit(true).unwrap();
+        writer.write_bits(0b0110, 4).unwrap();
+        assert_eq!(writer.get_ref(), &[0b0110_1101]);
+
+        let mut reader = BitReader::new(&[0b0110_1101, 0xFF][..], BitOrder::LsbFirst);
+        assert_eq!(reader.read_bits(2).unwrap(), 0b01);
+        reader.align_to_byte();
+        assert_eq!(reader.read_bits(8).unwrap(), 0xFF);
+    }
+}
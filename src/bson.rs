@@ -0,0 +1,1353 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! BSON serialization
+//!
+//! BSON is the binary document format used by MongoDB. Any `Encodable`
+//! type which encodes as a struct or map can be written as a BSON document
+//! with `encode`, and read back with `decode`; `Bson` is the generic
+//! value, like `json::Json`.
+//!
+//! The element types with no counterpart in the `Encodable` data model are
+//! `ObjectId`, `Binary` and `DateTime`. Each encodes as a struct in the
+//! shape of MongoDB's extended JSON (e.g. `{"$oid": "..."}`), which this
+//! module's encoder and decoder turn into and out of the real BSON element,
+//! so the same types also encode sensibly as JSON.
+//!
+//! Enums are encoded as by the `json` module: a variant without fields is
+//! its name, and other variants are `{"variant": name, "fields": [...]}`.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+//! use rustc_serialize::bson::{self, ObjectId};
+//!
+//! #[derive(PartialEq, Debug)]
+//! struct User {
+//!     _id: ObjectId,
+//!     name: String,
+//!     tags: Vec<String>,
+//! }
+//!
+//! impl Encodable for User {
+//!     fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+//!         s.emit_struct("User", 3, |s| {
+//!             try!(s.emit_struct_field("_id", 0, |s| self._id.encode(s)));
+//!             try!(s.emit_struct_field("name", 1, |s| self.name.encode(s)));
+//!             s.emit_struct_field("tags", 2, |s| self.tags.encode(s))
+//!         })
+//!     }
+//! }
+//!
+//! impl Decodable for User {
+//!     fn decode<D: Decoder>(d: &mut D) -> Result<User, D::Error> {
+//!         d.read_struct("User", 3, |d| {
+//!             Ok(User {
+//!                 _id: try!(d.read_struct_field("_id", 0, Decodable::decode)),
+//!                 name: try!(d.read_struct_field("name", 1, Decodable::decode)),
+//!                 tags: try!(d.read_struct_field("tags", 2, Decodable::decode)),
+//!             })
+//!         })
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let user = User {
+//!         _id: "5f2b6e4c9d1e8a0012345678".parse().unwrap(),
+//!         name: "ann".to_string(),
+//!         tags: vec!["admin".to_string()],
+//!     };
+//!     let bytes = bson::encode(&user).unwrap();
+//!     let decoded: User = bson::decode(&bytes).unwrap();
+//!     assert_eq!(decoded, user);
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::str::{self, FromStr};
+
+use Encodable;
+use Decodable;
+use base64::{FromBase64, ToBase64, STANDARD};
+use hex::{FromHex, ToHex};
+
+/// The elements of a document, in order.
+pub type Document = Vec<(String, Bson)>;
+
+/// How deeply documents and arrays may nest in the input of
+/// `decode_document`.
+pub const MAX_DEPTH: usize = 100;
+
+/// A BSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Bson {
+    /// A 64-bit float (element type 0x01)
+    Double(f64),
+    /// A UTF-8 string (0x02)
+    String(String),
+    /// An embedded document (0x03)
+    Document(Document),
+    /// An array (0x04)
+    Array(Vec<Bson>),
+    /// Binary data with its subtype (0x05)
+    Binary(u8, Vec<u8>),
+    /// An ObjectId (0x07)
+    ObjectId(ObjectId),
+    /// A boolean (0x08)
+    Boolean(bool),
+    /// A UTC datetime in milliseconds since the Unix epoch (0x09)
+    DateTime(i64),
+    /// Null (0x0A)
+    Null,
+    /// A 32-bit integer (0x10)
+    Int32(i32),
+    /// An internal MongoDB timestamp (0x11)
+    Timestamp(u64),
+    /// A 64-bit integer (0x12)
+    Int64(i64),
+}
+
+/// A 12-byte MongoDB ObjectId.
+///
+/// It encodes as a struct with one field, `$oid`, holding its hex form, and
+/// parses from and displays as that hex form.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId(pub [u8; 12]);
+
+impl FromStr for ObjectId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ObjectId, String> {
+        match s.from_hex() {
+            Ok(ref bytes) if bytes.len() == 12 => {
+                let mut id = [0; 12];
+                id.copy_from_slice(bytes);
+                Ok(ObjectId(id))
+            }
+            _ => Err(format!("invalid ObjectId `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0.to_hex())
+    }
+}
+
+impl Encodable for ObjectId {
+    fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("ObjectId", 1, |s| {
+            s.emit_struct_field("$oid", 0, |s| s.emit_str(&self.to_string()))
+        })
+    }
+}
+
+impl Decodable for ObjectId {
+    fn decode<D: ::Decoder>(d: &mut D) -> Result<ObjectId, D::Error> {
+        let hex = try!(d.read_struct("ObjectId", 1, |d| {
+            d.read_struct_field("$oid", 0, |d| d.read_str())
+        }));
+        hex.parse().map_err(|e: String| d.error(&e))
+    }
+}
+
+/// Binary data with a BSON subtype (0 for generic data).
+///
+/// It encodes as a struct with the fields `$binary`, the data in base64,
+/// and `$type`, the subtype as two hex digits.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Binary {
+    /// The subtype
+    pub subtype: u8,
+    /// The data
+    pub bytes: Vec<u8>,
+}
+
+impl Encodable for Binary {
+    fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("Binary", 2, |s| {
+            try!(s.emit_struct_field("$binary", 0, |s| s.emit_str(&self.bytes.to_base64(STANDARD))));
+            s.emit_struct_field("$type", 1, |s| s.emit_str(&format!("{:02x}", self.subtype)))
+        })
+    }
+}
+
+impl Decodable for Binary {
+    fn decode<D: ::Decoder>(d: &mut D) -> Result<Binary, D::Error> {
+        let (data, subtype) = try!(d.read_struct("Binary", 2, |d| {
+            let data = try!(d.read_struct_field("$binary", 0, |d| d.read_str()));
+            let subtype = try!(d.read_struct_field("$type", 1, |d| d.read_str()));
+            Ok((data, subtype))
+        }));
+        match (data.from_base64(), u8::from_str_radix(&subtype, 16)) {
+            (Ok(bytes), Ok(subtype)) => Ok(Binary { subtype: subtype, bytes: bytes }),
+            _ => Err(d.error("invalid Binary")),
+        }
+    }
+}
+
+/// A UTC datetime, in milliseconds since the Unix epoch.
+///
+/// It encodes as a struct with one field, `$date`, holding the number of
+/// milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DateTime(pub i64);
+
+impl Encodable for DateTime {
+    fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("DateTime", 1, |s| s.emit_struct_field("$date", 0, |s| s.emit_i64(self.0)))
+    }
+}
+
+impl Decodable for DateTime {
+    fn decode<D: ::Decoder>(d: &mut D) -> Result<DateTime, D::Error> {
+        d.read_struct("DateTime", 1, |d| d.read_struct_field("$date", 0, |d| d.read_i64()))
+         .map(DateTime)
+    }
+}
+
+/// Errors that can occur when encoding a value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EncoderError {
+    /// The value was not a struct or map, so cannot be a document
+    NotADocument,
+    /// A map key was not a string, number or boolean
+    BadMapKey,
+    /// A key contained a NUL character
+    NulInKey(String),
+    /// An unsigned integer was too large for a 64-bit integer
+    IntegerOverflow(u64),
+}
+
+impl fmt::Display for EncoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncoderError::NotADocument => write!(f, "top-level value is not a document"),
+            EncoderError::BadMapKey => write!(f, "map key is not a string"),
+            EncoderError::NulInKey(ref key) => write!(f, "key {:?} contains NUL", key),
+            EncoderError::IntegerOverflow(v) => write!(f, "{} does not fit in an int64", v),
+        }
+    }
+}
+
+impl error::Error for EncoderError {
+    fn description(&self) -> &str { "BSON encoding error" }
+}
+
+/// Errors that can occur when decoding a value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecoderError {
+    /// The input was not valid BSON; the message says why
+    InvalidBson(String),
+    /// A value of the first kind was expected but the second was found
+    ExpectedError(String, String),
+    /// A struct field was missing
+    MissingFieldError(String),
+    /// An enum variant name was not known
+    UnknownVariantError(String),
+    /// An error reported by a `Decodable` impl
+    ApplicationError(String),
+    /// There was no value left to decode
+    EOF,
+}
+
+impl fmt::Display for DecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecoderError::InvalidBson(ref msg) => write!(f, "invalid BSON: {}", msg),
+            DecoderError::ExpectedError(ref expected, ref found) => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            DecoderError::MissingFieldError(ref name) => write!(f, "missing field `{}`", name),
+            DecoderError::UnknownVariantError(ref name) => {
+                write!(f, "unknown variant `{}`", name)
+            }
+            DecoderError::ApplicationError(ref msg) => write!(f, "{}", msg),
+            DecoderError::EOF => write!(f, "no value left to decode"),
+        }
+    }
+}
+
+impl error::Error for DecoderError {
+    fn description(&self) -> &str { "BSON decoding error" }
+}
+
+/// Encodes `value`, which must encode as a struct or map, as a BSON
+/// document.
+pub fn encode<T: Encodable>(value: &T) -> Result<Vec<u8>, EncoderError> {
+    let mut encoder = Encoder::new();
+    try!(value.encode(&mut encoder));
+    match encoder.into_bson() {
+        Bson::Document(doc) => {
+            let mut out = Vec::new();
+            try!(encode_document(&doc, &mut out));
+            Ok(out)
+        }
+        _ => Err(EncoderError::NotADocument),
+    }
+}
+
+/// Decodes a value from a BSON document, which must take up all of
+/// `bytes`.
+pub fn decode<T: Decodable>(bytes: &[u8]) -> Result<T, DecoderError> {
+    let doc = try!(decode_document(bytes));
+    Decodable::decode(&mut Decoder::new(Bson::Document(doc)))
+}
+
+/// Appends the BSON encoding of `doc` to `out`.
+pub fn encode_document(doc: &Document, out: &mut Vec<u8>) -> Result<(), EncoderError> {
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    for (key, value) in doc {
+        try!(encode_element(key, value, out));
+    }
+    out.push(0);
+    let len = (out.len() - start) as i32;
+    write_le(&mut out[start..start + 4], len as u64);
+    Ok(())
+}
+
+fn encode_element(key: &str, value: &Bson, out: &mut Vec<u8>) -> Result<(), EncoderError> {
+    if key.contains('\0') {
+        return Err(EncoderError::NulInKey(key.to_string()));
+    }
+    let tag = match *value {
+        Bson::Double(_) => 0x01,
+        Bson::String(_) => 0x02,
+        Bson::Document(_) => 0x03,
+        Bson::Array(_) => 0x04,
+        Bson::Binary(..) => 0x05,
+        Bson::ObjectId(_) => 0x07,
+        Bson::Boolean(_) => 0x08,
+        Bson::DateTime(_) => 0x09,
+        Bson::Null => 0x0a,
+        Bson::Int32(_) => 0x10,
+        Bson::Timestamp(_) => 0x11,
+        Bson::Int64(_) => 0x12,
+    };
+    out.push(tag);
+    out.extend_from_slice(key.as_bytes());
+    out.push(0);
+    match *value {
+        Bson::Double(v) => push_le(out, v.to_bits(), 8),
+        Bson::String(ref s) => {
+            push_le(out, s.len() as u64 + 1, 4);
+            out.extend_from_slice(s.as_bytes());
+            out.push(0);
+        }
+        Bson::Document(ref doc) => try!(encode_document(doc, out)),
+        Bson::Array(ref items) => {
+            let doc = items.iter().enumerate().map(|(i, v)| (i.to_string(), v.clone())).collect();
+            try!(encode_document(&doc, out));
+        }
+        Bson::Binary(subtype, ref bytes) => {
+            push_le(out, bytes.len() as u64, 4);
+            out.push(subtype);
+            out.extend_from_slice(bytes);
+        }
+        Bson::ObjectId(ref id) => out.extend_from_slice(&id.0),
+        Bson::Boolean(b) => out.push(b as u8),
+        Bson::DateTime(v) | Bson::Int64(v) => push_le(out, v as u64, 8),
+        Bson::Null => {}
+        Bson::Int32(v) => push_le(out, v as u32 as u64, 4),
+        Bson::Timestamp(v) => push_le(out, v, 8),
+    }
+    Ok(())
+}
+
+fn push_le(out: &mut Vec<u8>, v: u64, n: usize) {
+    for i in 0..n {
+        out.push((v >> (8 * i)) as u8);
+    }
+}
+
+fn write_le(buf: &mut [u8], v: u64) {
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (v >> (8 * i)) as u8;
+    }
+}
+
+fn invalid<T>(msg: &str) -> Result<T, DecoderError> {
+    Err(DecoderError::InvalidBson(msg.to_string()))
+}
+
+/// Decodes a BSON document, which must take up all of `bytes`.
+pub fn decode_document(bytes: &[u8]) -> Result<Document, DecoderError> {
+    let mut reader = Reader { bytes: bytes, pos: 0 };
+    let doc = try!(reader.document(0));
+    if reader.pos != bytes.len() {
+        return invalid("trailing bytes after document");
+    }
+    Ok(doc)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecoderError> {
+        if self.bytes.len() - self.pos < n {
+            return invalid("truncated input");
+        }
+        let bytes = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn le(&mut self, n: usize) -> Result<u64, DecoderError> {
+        let bytes = try!(self.take(n));
+        Ok(bytes.iter().rev().fold(0, |v, &b| v << 8 | b as u64))
+    }
+
+    fn length(&mut self) -> Result<usize, DecoderError> {
+        let len = try!(self.le(4)) as u32 as i32;
+        if len < 0 {
+            return invalid("negative length");
+        }
+        Ok(len as usize)
+    }
+
+    fn cstring(&mut self) -> Result<String, DecoderError> {
+        let len = match self.bytes[self.pos..].iter().position(|&b| b == 0) {
+            Some(len) => len,
+            None => return invalid("unterminated key"),
+        };
+        let bytes = try!(self.take(len + 1));
+        match str::from_utf8(&bytes[..len]) {
+            Ok(s) => Ok(s.to_string()),
+            Err(_) => invalid("invalid UTF-8"),
+        }
+    }
+
+    fn document(&mut self, depth: usize) -> Result<Document, DecoderError> {
+        if depth == MAX_DEPTH {
+            return invalid("documents nested too deeply");
+        }
+        let start = self.pos;
+        let len = try!(self.length());
+        if len < 5 || len > self.bytes.len() - start {
+            return invalid("invalid document length");
+        }
+        let end = start + len - 1;
+        let mut doc = Vec::new();
+        while self.pos < end {
+            let tag = try!(self.take(1))[0];
+            let key = try!(self.cstring());
+            let value = try!(self.value(tag, depth));
+            doc.push((key, value));
+        }
+        if self.pos != end || self.bytes[end] != 0 {
+            return invalid("document length does not match its contents");
+        }
+        self.pos = end + 1;
+        Ok(doc)
+    }
+
+    fn value(&mut self, tag: u8, depth: usize) -> Result<Bson, DecoderError> {
+        Ok(match tag {
+            0x01 => Bson::Double(f64::from_bits(try!(self.le(8)))),
+            0x02 => {
+                let len = try!(self.length());
+                let bytes = try!(self.take(len));
+                if bytes.last() != Some(&0) {
+                    return invalid("unterminated string");
+                }
+                match str::from_utf8(&bytes[..len - 1]) {
+                    Ok(s) => Bson::String(s.to_string()),
+                    Err(_) => return invalid("invalid UTF-8"),
+                }
+            }
+            0x03 => Bson::Document(try!(self.document(depth + 1))),
+            0x04 => Bson::Array(try!(self.document(depth + 1)).into_iter().map(|e| e.1).collect()),
+            0x05 => {
+                let len = try!(self.length());
+                let subtype = try!(self.take(1))[0];
+                Bson::Binary(subtype, try!(self.take(len)).to_vec())
+            }
+            0x07 => {
+                let mut id = [0; 12];
+                id.copy_from_slice(try!(self.take(12)));
+                Bson::ObjectId(ObjectId(id))
+            }
+            0x08 => match try!(self.take(1))[0] {
+                0 => Bson::Boolean(false),
+                1 => Bson::Boolean(true),
+                _ => return invalid("invalid boolean"),
+            },
+            0x09 => Bson::DateTime(try!(self.le(8)) as i64),
+            0x0a => Bson::Null,
+            0x10 => Bson::Int32(try!(self.le(4)) as u32 as i32),
+            0x11 => Bson::Timestamp(try!(self.le(8))),
+            0x12 => Bson::Int64(try!(self.le(8)) as i64),
+            tag => {
+                return Err(DecoderError::InvalidBson(format!("unsupported element type \
+                                                              0x{:02x}", tag)))
+            }
+        })
+    }
+}
+
+enum Frame {
+    Document(Document),
+    Array(Vec<Bson>),
+    // A map being built, and the key of the value to come.
+    Map(Document, Option<String>),
+}
+
+/// An encoder building a `Bson` value.
+pub struct Encoder {
+    stack: Vec<Frame>,
+    value: Option<Bson>,
+}
+
+pub type EncodeResult = Result<(), EncoderError>;
+
+impl Encoder {
+    /// Creates a new encoder.
+    pub fn new() -> Encoder {
+        Encoder { stack: Vec::new(), value: None }
+    }
+
+    /// Returns the encoded value.
+    pub fn into_bson(self) -> Bson {
+        self.value.unwrap_or(Bson::Null)
+    }
+
+    fn set(&mut self, value: Bson) -> EncodeResult {
+        self.value = Some(value);
+        Ok(())
+    }
+
+    // Runs `f` and returns the value it emitted.
+    fn capture<F>(&mut self, f: F) -> Result<Bson, EncoderError>
+        where F: FnOnce(&mut Encoder) -> EncodeResult
+    {
+        try!(f(self));
+        Ok(self.value.take().unwrap_or(Bson::Null))
+    }
+
+    fn push_item<F>(&mut self, f: F) -> EncodeResult
+        where F: FnOnce(&mut Encoder) -> EncodeResult
+    {
+        let value = try!(self.capture(f));
+        if let Some(&mut Frame::Array(ref mut items)) = self.stack.last_mut() {
+            items.push(value);
+        }
+        Ok(())
+    }
+
+    fn collect<F>(&mut self, frame: Frame, f: F) -> Result<Frame, EncoderError>
+        where F: FnOnce(&mut Encoder) -> EncodeResult
+    {
+        self.stack.push(frame);
+        try!(f(self));
+        Ok(self.stack.pop().unwrap())
+    }
+
+    fn emit_int(&mut self, v: i64) -> EncodeResult {
+        if v >= i32::MIN as i64 && v <= i32::MAX as i64 {
+            self.set(Bson::Int32(v as i32))
+        } else {
+            self.set(Bson::Int64(v))
+        }
+    }
+
+    fn emit_uint(&mut self, v: u64) -> EncodeResult {
+        if v > i64::MAX as u64 {
+            return Err(EncoderError::IntegerOverflow(v));
+        }
+        self.emit_int(v as i64)
+    }
+}
+
+impl Default for Encoder {
+    fn default() -> Encoder { Encoder::new() }
+}
+
+// Turns the documents written by the `Encodable` impls of `ObjectId`,
+// `Binary` and `DateTime` into their elements.
+fn special(name: &str, doc: Document) -> Bson {
+    let value = match (name, &doc[..]) {
+        ("ObjectId", &[(ref k, Bson::String(ref hex))]) if k == "$oid" => {
+            hex.parse().ok().map(Bson::ObjectId)
+        }
+        ("DateTime", &[(ref k, ref ms)]) if k == "$date" => match *ms {
+            Bson::Int32(ms) => Some(Bson::DateTime(ms as i64)),
+            Bson::Int64(ms) => Some(Bson::DateTime(ms)),
+            _ => None,
+        },
+        ("Binary", &[(ref k1, Bson::String(ref data)), (ref k2, Bson::String(ref subtype))])
+            if k1 == "$binary" && k2 == "$type" => {
+            match (data.from_base64(), u8::from_str_radix(subtype, 16)) {
+                (Ok(bytes), Ok(subtype)) => Some(Bson::Binary(subtype, bytes)),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    value.unwrap_or(Bson::Document(doc))
+}
+
+impl ::Encoder for Encoder {
+    type Error = EncoderError;
+
+    fn emit_nil(&mut self) -> EncodeResult { self.set(Bson::Null) }
+
+    fn emit_usize(&mut self, v: usize) -> EncodeResult { self.emit_uint(v as u64) }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult { self.emit_uint(v) }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult { self.emit_int(v as i64) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult { self.emit_int(v as i64) }
+    fn emit_u8(&mut self, v: u8) -> EncodeResult { self.emit_int(v as i64) }
+
+    fn emit_isize(&mut self, v: isize) -> EncodeResult { self.emit_int(v as i64) }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult { self.emit_int(v) }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult { self.emit_int(v as i64) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult { self.emit_int(v as i64) }
+    fn emit_i8(&mut self, v: i8) -> EncodeResult { self.emit_int(v as i64) }
+
+    // 128-bit integers which an `Int64` cannot hold are strings.
+    fn emit_u128(&mut self, v: u128) -> EncodeResult {
+        if v <= i64::MAX as u128 {
+            self.emit_int(v as i64)
+        } else {
+            self.emit_str(&v.to_string())
+        }
+    }
+    fn emit_i128(&mut self, v: i128) -> EncodeResult {
+        if v >= i64::MIN as i128 && v <= i64::MAX as i128 {
+            self.emit_int(v as i64)
+        } else {
+            self.emit_str(&v.to_string())
+        }
+    }
+
+    fn emit_bool(&mut self, v: bool) -> EncodeResult { self.set(Bson::Boolean(v)) }
+    fn emit_f64(&mut self, v: f64) -> EncodeResult { self.set(Bson::Double(v)) }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult { self.set(Bson::Double(v as f64)) }
+    fn emit_char(&mut self, v: char) -> EncodeResult { self.set(Bson::String(v.to_string())) }
+    fn emit_str(&mut self, v: &str) -> EncodeResult { self.set(Bson::String(v.to_string())) }
+
+    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_enum_variant<F>(&mut self, name: &str, _id: usize, cnt: usize, f: F)
+                            -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        if cnt == 0 {
+            return self.set(Bson::String(name.to_string()));
+        }
+        match try!(self.collect(Frame::Array(Vec::new()), f)) {
+            Frame::Array(fields) => self.set(Bson::Document(vec![
+                ("variant".to_string(), Bson::String(name.to_string())),
+                ("fields".to_string(), Bson::Array(fields)),
+            ])),
+            _ => unreachable!(),
+        }
+    }
+
+    fn emit_enum_variant_arg<F>(&mut self, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.push_item(f)
+    }
+
+    fn emit_enum_struct_variant<F>(&mut self, name: &str, id: usize, cnt: usize, f: F)
+                                   -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_enum_variant(name, id, cnt, f)
+    }
+
+    fn emit_enum_struct_variant_field<F>(&mut self, _: &str, idx: usize, f: F)
+                                         -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_enum_variant_arg(idx, f)
+    }
+
+    fn emit_struct<F>(&mut self, name: &str, _len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        match try!(self.collect(Frame::Document(Vec::new()), f)) {
+            Frame::Document(doc) => {
+                let value = special(name, doc);
+                self.set(value)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn emit_struct_field<F>(&mut self, name: &str, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let value = try!(self.capture(f));
+        if let Some(&mut Frame::Document(ref mut doc)) = self.stack.last_mut() {
+            doc.push((name.to_string(), value));
+        }
+        Ok(())
+    }
+
+    fn emit_tuple<F>(&mut self, len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_seq(len, f)
+    }
+
+    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_tuple_struct<F>(&mut self, _: &str, len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_seq(len, f)
+    }
+
+    fn emit_tuple_struct_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_option_none(&mut self) -> EncodeResult { self.emit_nil() }
+
+    fn emit_option_some<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_seq<F>(&mut self, _len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        match try!(self.collect(Frame::Array(Vec::new()), f)) {
+            Frame::Array(items) => self.set(Bson::Array(items)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn emit_seq_elt<F>(&mut self, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.push_item(f)
+    }
+
+    fn emit_map<F>(&mut self, _len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        match try!(self.collect(Frame::Map(Vec::new(), None), f)) {
+            Frame::Map(doc, _) => self.set(Bson::Document(doc)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn emit_map_elt_key<F>(&mut self, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let key = match try!(self.capture(f)) {
+            Bson::String(s) => s,
+            Bson::Int32(v) => v.to_string(),
+            Bson::Int64(v) => v.to_string(),
+            Bson::Double(v) => v.to_string(),
+            Bson::Boolean(v) => v.to_string(),
+            _ => return Err(EncoderError::BadMapKey),
+        };
+        if let Some(&mut Frame::Map(_, ref mut pending)) = self.stack.last_mut() {
+            *pending = Some(key);
+        }
+        Ok(())
+    }
+
+    fn emit_map_elt_val<F>(&mut self, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let value = try!(self.capture(f));
+        if let Some(&mut Frame::Map(ref mut doc, ref mut pending)) = self.stack.last_mut() {
+            if let Some(key) = pending.take() {
+                doc.push((key, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A decoder reading from a `Bson` value.
+pub struct Decoder {
+    stack: Vec<Bson>,
+}
+
+pub type DecodeResult<T> = Result<T, DecoderError>;
+
+impl Decoder {
+    /// Creates a new decoder for `bson`.
+    pub fn new(bson: Bson) -> Decoder {
+        Decoder { stack: vec![bson] }
+    }
+
+    fn pop(&mut self) -> DecodeResult<Bson> {
+        match self.stack.pop() {
+            Some(v) => Ok(v),
+            None => Err(DecoderError::EOF),
+        }
+    }
+
+    // Pops a document, expanding the elements which `ObjectId`, `Binary`
+    // and `DateTime` decode from structs.
+    fn pop_document(&mut self) -> DecodeResult<Document> {
+        match try!(self.pop()) {
+            Bson::Document(doc) => Ok(doc),
+            Bson::ObjectId(id) => Ok(vec![("$oid".to_string(), Bson::String(id.to_string()))]),
+            Bson::DateTime(ms) => Ok(vec![("$date".to_string(), Bson::Int64(ms))]),
+            Bson::Binary(subtype, bytes) => Ok(vec![
+                ("$binary".to_string(), Bson::String(bytes.to_base64(STANDARD))),
+                ("$type".to_string(), Bson::String(format!("{:02x}", subtype))),
+            ]),
+            other => Err(expected("Document", &other)),
+        }
+    }
+
+    fn read_int(&mut self) -> DecodeResult<i64> {
+        match try!(self.pop()) {
+            Bson::Int32(v) => Ok(v as i64),
+            Bson::Int64(v) | Bson::DateTime(v) => Ok(v),
+            Bson::Timestamp(v) if v <= i64::MAX as u64 => Ok(v as i64),
+            // Map keys are strings.
+            Bson::String(s) => match s.parse() {
+                Ok(v) => Ok(v),
+                Err(_) => Err(DecoderError::ExpectedError("Integer".to_string(), s)),
+            },
+            other => Err(expected("Integer", &other)),
+        }
+    }
+}
+
+fn expected(what: &str, found: &Bson) -> DecoderError {
+    let found = match *found {
+        Bson::Double(v) => v.to_string(),
+        Bson::String(ref s) => format!("{:?}", s),
+        Bson::Document(_) => "Document".to_string(),
+        Bson::Array(_) => "Array".to_string(),
+        Bson::Binary(..) => "Binary".to_string(),
+        Bson::ObjectId(ref id) => format!("ObjectId({})", id),
+        Bson::Boolean(b) => b.to_string(),
+        Bson::DateTime(ms) => format!("DateTime({})", ms),
+        Bson::Null => "null".to_string(),
+        Bson::Int32(v) => v.to_string(),
+        Bson::Timestamp(v) => format!("Timestamp({})", v),
+        Bson::Int64(v) => v.to_string(),
+    };
+    DecoderError::ExpectedError(what.to_string(), found)
+}
+
+macro_rules! read_int {
+    ($name:ident, $ty:ident) => {
+        #[allow(unused_comparisons)]
+        fn $name(&mut self) -> DecodeResult<$ty> {
+            let v = try!(self.read_int());
+            let other = v as $ty;
+            if v == other as i64 && (other > 0) == (v > 0) {
+                Ok(other)
+            } else {
+                Err(DecoderError::ExpectedError(stringify!($ty).to_string(), v.to_string()))
+            }
+        }
+    }
+}
+
+// Reads a 128-bit integer from an integer element, or from the string
+// `Encoder` writes for one that does not fit an `Int64`.
+macro_rules! read_int128 {
+    ($name:ident, $ty:ident) => {
+        #[allow(unused_comparisons)]
+        fn $name(&mut self) -> DecodeResult<$ty> {
+            let v = match try!(self.pop()) {
+                Bson::String(s) => return match s.parse() {
+                    Ok(v) => Ok(v),
+                    Err(_) => Err(DecoderError::ExpectedError(stringify!($ty).to_string(), s)),
+                },
+                other => {
+                    self.stack.push(other);
+                    try!(self.read_int())
+                }
+            };
+            let other = v as $ty;
+            if (other > 0) == (v > 0) {
+                Ok(other)
+            } else {
+                Err(DecoderError::ExpectedError(stringify!($ty).to_string(), v.to_string()))
+            }
+        }
+    }
+}
+
+impl ::Decoder for Decoder {
+    type Error = DecoderError;
+
+    fn read_nil(&mut self) -> DecodeResult<()> {
+        match try!(self.pop()) {
+            Bson::Null => Ok(()),
+            other => Err(expected("Null", &other)),
+        }
+    }
+
+    read_int! { read_usize, usize }
+    read_int! { read_u8, u8 }
+    read_int! { read_u16, u16 }
+    read_int! { read_u32, u32 }
+    read_int! { read_u64, u64 }
+    read_int! { read_isize, isize }
+    read_int! { read_i8, i8 }
+    read_int! { read_i16, i16 }
+    read_int! { read_i32, i32 }
+    read_int! { read_i64, i64 }
+    read_int128! { read_u128, u128 }
+    read_int128! { read_i128, i128 }
+
+    fn read_f32(&mut self) -> DecodeResult<f32> {
+        self.read_f64().map(|v| v as f32)
+    }
+
+    fn read_f64(&mut self) -> DecodeResult<f64> {
+        match try!(self.pop()) {
+            Bson::Double(v) => Ok(v),
+            Bson::Int32(v) => Ok(v as f64),
+            Bson::Int64(v) => Ok(v as f64),
+            Bson::String(s) => match s.parse() {
+                Ok(v) => Ok(v),
+                Err(_) => Err(DecoderError::ExpectedError("Number".to_string(), s)),
+            },
+            Bson::Null => Ok(f64::NAN),
+            other => Err(expected("Number", &other)),
+        }
+    }
+
+    fn read_bool(&mut self) -> DecodeResult<bool> {
+        match try!(self.pop()) {
+            Bson::Boolean(b) => Ok(b),
+            Bson::String(ref s) if s == "true" => Ok(true),
+            Bson::String(ref s) if s == "false" => Ok(false),
+            other => Err(expected("Boolean", &other)),
+        }
+    }
+
+    fn read_char(&mut self) -> DecodeResult<char> {
+        let s = try!(self.read_str());
+        let mut it = s.chars();
+        match (it.next(), it.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(DecoderError::ExpectedError("single character string".to_string(), s)),
+        }
+    }
+
+    fn read_str(&mut self) -> DecodeResult<String> {
+        match try!(self.pop()) {
+            Bson::String(s) => Ok(s),
+            other => Err(expected("String", &other)),
+        }
+    }
+
+    fn read_enum<T, F>(&mut self, _name: &str, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_enum_variant<T, F>(&mut self, names: &[&str], mut f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder, usize) -> DecodeResult<T>,
+    {
+        let name = match try!(self.pop()) {
+            Bson::String(s) => s,
+            Bson::Document(doc) => {
+                let mut name = None;
+                let mut fields = None;
+                for (key, value) in doc {
+                    match (&key[..], value) {
+                        ("variant", Bson::String(s)) => name = Some(s),
+                        ("fields", Bson::Array(a)) => fields = Some(a),
+                        (_, value) => return Err(expected("variant or fields", &value)),
+                    }
+                }
+                match (name, fields) {
+                    (Some(name), Some(fields)) => {
+                        for field in fields.into_iter().rev() {
+                            self.stack.push(field);
+                        }
+                        name
+                    }
+                    (None, _) => return Err(DecoderError::MissingFieldError("variant".to_string())),
+                    (_, None) => return Err(DecoderError::MissingFieldError("fields".to_string())),
+                }
+            }
+            other => return Err(expected("String or Document", &other)),
+        };
+        match names.iter().position(|n| *n == name) {
+            Some(idx) => f(self, idx),
+            None => Err(DecoderError::UnknownVariantError(name)),
+        }
+    }
+
+    fn read_enum_variant_arg<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder, usize) -> DecodeResult<T>,
+    {
+        self.read_enum_variant(names, f)
+    }
+
+    fn read_enum_struct_variant_field<T, F>(&mut self, _name: &str, idx: usize, f: F)
+                                            -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_enum_variant_arg(idx, f)
+    }
+
+    fn read_struct<T, F>(&mut self, _name: &str, _len: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        let doc = try!(self.pop_document());
+        self.stack.push(Bson::Document(doc));
+        let value = try!(f(self));
+        try!(self.pop());
+        Ok(value)
+    }
+
+    fn read_struct_field<T, F>(&mut self, name: &str, _idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        let mut doc = try!(self.pop_document());
+        let value = match doc.iter().position(|e| e.0 == name) {
+            None => {
+                // As in `json::Decoder`, a missing field decodes as an
+                // `Option`'s None.
+                self.stack.push(Bson::Null);
+                match f(self) {
+                    Ok(x) => x,
+                    Err(_) => return Err(DecoderError::MissingFieldError(name.to_string())),
+                }
+            }
+            Some(idx) => {
+                let (_, value) = doc.remove(idx);
+                self.stack.push(value);
+                try!(f(self))
+            }
+        };
+        self.stack.push(Bson::Document(doc));
+        Ok(value)
+    }
+
+    fn read_tuple<T, F>(&mut self, tuple_len: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_seq(move |d, len| {
+            if len == tuple_len {
+                f(d)
+            } else {
+                Err(DecoderError::ExpectedError(format!("Tuple{}", tuple_len),
+                                                format!("Tuple{}", len)))
+            }
+        })
+    }
+
+    fn read_tuple_arg<T, F>(&mut self, idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_seq_elt(idx, f)
+    }
+
+    fn read_tuple_struct<T, F>(&mut self, _name: &str, len: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_tuple(len, f)
+    }
+
+    fn read_tuple_struct_arg<T, F>(&mut self, idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_tuple_arg(idx, f)
+    }
+
+    fn read_option<T, F>(&mut self, mut f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder, bool) -> DecodeResult<T>,
+    {
+        match try!(self.pop()) {
+            Bson::Null => f(self, false),
+            value => { self.stack.push(value); f(self, true) }
+        }
+    }
+
+    fn read_seq<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder, usize) -> DecodeResult<T>,
+    {
+        let items = match try!(self.pop()) {
+            Bson::Array(items) => items,
+            other => return Err(expected("Array", &other)),
+        };
+        let len = items.len();
+        for v in items.into_iter().rev() {
+            self.stack.push(v);
+        }
+        f(self, len)
+    }
+
+    fn read_seq_elt<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_map<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder, usize) -> DecodeResult<T>,
+    {
+        let doc = try!(self.pop_document());
+        let len = doc.len();
+        for (key, value) in doc.into_iter().rev() {
+            self.stack.push(value);
+            self.stack.push(Bson::String(key));
+        }
+        f(self, len)
+    }
+
+    fn read_map_elt_key<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_map_elt_val<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn error(&mut self, err: &str) -> DecoderError {
+        DecoderError::ApplicationError(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use bson::{decode, decode_document, encode, encode_document, Binary, Bson, DateTime};
+    use bson::{DecoderError, EncoderError, ObjectId, MAX_DEPTH};
+    use json;
+    use {Decodable, Decoder, Encodable, Encoder};
+
+    #[derive(PartialEq, Debug)]
+    enum Shape {
+        Point,
+        Circle(f64),
+    }
+
+    impl Encodable for Shape {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_enum("Shape", |s| {
+                match *self {
+                    Shape::Point => s.emit_enum_variant("Point", 0, 0, |_| Ok(())),
+                    Shape::Circle(ref v) => s.emit_enum_variant("Circle", 1, 1, |s| {
+                        s.emit_enum_variant_arg(0, |s| v.encode(s))
+                    }),
+                }
+            })
+        }
+    }
+
+    impl Decodable for Shape {
+        fn decode<D: Decoder>(d: &mut D) -> Result<Shape, D::Error> {
+            d.read_enum("Shape", |d| {
+                d.read_enum_variant(&["Point", "Circle"], |d, idx| {
+                    match idx {
+                        0 => Ok(Shape::Point),
+                        1 => d.read_enum_variant_arg(0, Decodable::decode).map(Shape::Circle),
+                        _ => panic!("Internal error"),
+                    }
+                })
+            })
+        }
+    }
+
+    #[derive(PartialEq, Debug)]
+    struct Record {
+        id: ObjectId,
+        blob: Binary,
+        at: DateTime,
+        small: u8,
+        big: i64,
+        ratio: f32,
+        flag: bool,
+        note: Option<String>,
+        shapes: Vec<Shape>,
+        counts: BTreeMap<u32, String>,
+        pair: (i32, char),
+    }
+
+    impl Encodable for Record {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_struct("Record", 11, |s| {
+                try!(s.emit_struct_field("id", 0, |s| self.id.encode(s)));
+                try!(s.emit_struct_field("blob", 1, |s| self.blob.encode(s)));
+                try!(s.emit_struct_field("at", 2, |s| self.at.encode(s)));
+                try!(s.emit_struct_field("small", 3, |s| self.small.encode(s)));
+                try!(s.emit_struct_field("big", 4, |s| self.big.encode(s)));
+                try!(s.emit_struct_field("ratio", 5, |s| self.ratio.encode(s)));
+                try!(s.emit_struct_field("flag", 6, |s| self.flag.encode(s)));
+                try!(s.emit_struct_field("note", 7, |s| self.note.encode(s)));
+                try!(s.emit_struct_field("shapes", 8, |s| self.shapes.encode(s)));
+                try!(s.emit_struct_field("counts", 9, |s| self.counts.encode(s)));
+                s.emit_struct_field("pair", 10, |s| self.pair.encode(s))
+            })
+        }
+    }
+
+    impl Decodable for Record {
+        fn decode<D: Decoder>(d: &mut D) -> Result<Record, D::Error> {
+            d.read_struct("Record", 11, |d| {
+                Ok(Record {
+                    id: try!(d.read_struct_field("id", 0, Decodable::decode)),
+                    blob: try!(d.read_struct_field("blob", 1, Decodable::decode)),
+                    at: try!(d.read_struct_field("at", 2, Decodable::decode)),
+                    small: try!(d.read_struct_field("small", 3, Decodable::decode)),
+                    big: try!(d.read_struct_field("big", 4, Decodable::decode)),
+                    ratio: try!(d.read_struct_field("ratio", 5, Decodable::decode)),
+                    flag: try!(d.read_struct_field("flag", 6, Decodable::decode)),
+                    note: try!(d.read_struct_field("note", 7, Decodable::decode)),
+                    shapes: try!(d.read_struct_field("shapes", 8, Decodable::decode)),
+                    counts: try!(d.read_struct_field("counts", 9, Decodable::decode)),
+                    pair: try!(d.read_struct_field("pair", 10, Decodable::decode)),
+                })
+            })
+        }
+    }
+
+    fn record() -> Record {
+        let mut counts = BTreeMap::new();
+        counts.insert(3, "three".to_string());
+        Record {
+            id: ObjectId([1; 12]),
+            blob: Binary { subtype: 4, bytes: vec![0, 255] },
+            at: DateTime(-1),
+            small: 7,
+            big: 1 << 40,
+            ratio: 0.5,
+            flag: true,
+            note: None,
+            shapes: vec![Shape::Point, Shape::Circle(2.0)],
+            counts: counts,
+            pair: (-3, 'x'),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = encode(&record()).unwrap();
+        let doc = decode_document(&bytes).unwrap();
+        assert_eq!(doc[0], ("id".to_string(), Bson::ObjectId(ObjectId([1; 12]))));
+        assert_eq!(doc[1], ("blob".to_string(), Bson::Binary(4, vec![0, 255])));
+        assert_eq!(doc[2], ("at".to_string(), Bson::DateTime(-1)));
+        assert_eq!(doc[4], ("big".to_string(), Bson::Int64(1 << 40)));
+        assert_eq!(doc[7], ("note".to_string(), Bson::Null));
+        let mut out = Vec::new();
+        encode_document(&doc, &mut out).unwrap();
+        assert_eq!(out, bytes);
+        assert_eq!(decode::<Record>(&bytes).unwrap(), record());
+
+        // 128-bit integers beyond an `Int64` are strings.
+        let mut wide = BTreeMap::new();
+        wide.insert("max".to_string(), u128::MAX);
+        wide.insert("small".to_string(), 7);
+        let bytes = encode(&wide).unwrap();
+        assert_eq!(decode_document(&bytes).unwrap()[0],
+                   ("max".to_string(), Bson::String(u128::MAX.to_string())));
+        assert_eq!(decode::<BTreeMap<String, u128>>(&bytes).unwrap(), wide);
+        let mut wide = BTreeMap::new();
+        wide.insert("min".to_string(), i128::MIN);
+        wide.insert("neg".to_string(), -7);
+        let bytes = encode(&wide).unwrap();
+        assert_eq!(decode::<BTreeMap<String, i128>>(&bytes).unwrap(), wide);
+
+        // The same types encode as extended JSON.
+        let json = json::encode(&record().id).unwrap();
+        assert_eq!(json, "{\"$oid\":\"010101010101010101010101\"}");
+    }
+
+    #[test]
+    fn test_wire_format() {
+        // The example from bsonspec.org: {"hello": "world"}
+        let doc = vec![("hello".to_string(), Bson::String("world".to_string()))];
+        let bytes = b"\x16\x00\x00\x00\x02hello\x00\x06\x00\x00\x00world\x00\x00";
+        let mut out = Vec::new();
+        encode_document(&doc, &mut out).unwrap();
+        assert_eq!(&out[..], &bytes[..]);
+        assert_eq!(decode_document(bytes).unwrap(), doc);
+
+        let doc = vec![("a".to_string(), Bson::Array(vec![Bson::Int32(1), Bson::Boolean(false)])),
+                       ("t".to_string(), Bson::Timestamp(5))];
+        let mut out = Vec::new();
+        encode_document(&doc, &mut out).unwrap();
+        assert_eq!(decode_document(&out).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(encode(&5u8), Err(EncoderError::NotADocument));
+        let mut map = BTreeMap::new();
+        map.insert("a\0b".to_string(), 1);
+        assert_eq!(encode(&map), Err(EncoderError::NulInKey("a\0b".to_string())));
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), u64::MAX);
+        assert_eq!(encode(&map), Err(EncoderError::IntegerOverflow(u64::MAX)));
+
+        let bad: &[&[u8]] = &[b"", b"\x05\x00\x00\x00", b"\x05\x00\x00\x00\x01",
+                              b"\x06\x00\x00\x00\x0a\x00", b"\x09\x00\x00\x00\x0aa\x00\x00",
+                              b"\x08\x00\x00\x00\x06a\x00\x00", b"\x05\x00\x00\x00\x00\x00"];
+        for bytes in bad {
+            match decode_document(bytes) {
+                Err(DecoderError::InvalidBson(_)) => {}
+                other => panic!("{:?}: {:?}", bytes, other),
+            }
+        }
+        let mut deep = vec![("x".to_string(), Bson::Null)];
+        for _ in 0..MAX_DEPTH {
+            deep = vec![("x".to_string(), Bson::Document(deep))];
+        }
+        let mut out = Vec::new();
+        encode_document(&deep, &mut out).unwrap();
+        assert!(decode_document(&out).is_err());
+
+        let bytes = encode(&record()).unwrap();
+        let mut doc = decode_document(&bytes).unwrap();
+        doc.remove(6);
+        let mut out = Vec::new();
+        encode_document(&doc, &mut out).unwrap();
+        assert_eq!(decode::<Record>(&out),
+                   Err(DecoderError::MissingFieldError("flag".to_string())));
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reading and writing numbers in little- or big-endian byte order
+//!
+//! `ReadBytesExt` and `WriteBytesExt` add methods to every `Read` and
+//! `Write` for the fixed-width integers and floats of binary formats, such
+//! as `read_u32_le` and `write_f64_be`, named for the type and the byte
+//! order. Floats are read and written as the bits of their IEEE 754
+//! encoding.
+//!
+//! Reading a number from a reader which ends first is an `UnexpectedEof`
+//! error, the bytes read up to then being lost.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::byteorder::{ReadBytesExt, WriteBytesExt};
+//!
+//! fn main() {
+//!     let mut bytes = Vec::new();
+//!     bytes.write_u16_be(0x0102).unwrap();
+//!     bytes.write_i32_le(-2).unwrap();
+//!     assert_eq!(bytes, [0x01, 0x02, 0xFE, 0xFF, 0xFF, 0xFF]);
+//!
+//!     let mut reader = &bytes[..];
+//!     assert_eq!(reader.read_u16_be().unwrap(), 0x0102);
+//!     assert_eq!(reader.read_i32_le().unwrap(), -2);
+//!     assert!(reader.read_u8().is_err());
+//! }
+//! ```
+
+use std::io::{self, Read, Write};
+
+// The docs are given whole, as `concat!` cannot be used in attributes
+// before Rust 1.54.
+macro_rules! read_methods {
+    ($($le:ident, $be:ident -> $t:ident, $n:expr, $le_doc:expr, $be_doc:expr;)*) => {$(
+        #[doc = $le_doc]
+        fn $le(&mut self) -> io::Result<$t> {
+            let mut buf = [0; $n];
+            try!(self.read_exact(&mut buf));
+            Ok($t::from_le_bytes(buf))
+        }
+
+        #[doc = $be_doc]
+        fn $be(&mut self) -> io::Result<$t> {
+            let mut buf = [0; $n];
+            try!(self.read_exact(&mut buf));
+            Ok($t::from_be_bytes(buf))
+        }
+    )*}
+}
+
+macro_rules! write_methods {
+    ($($le:ident, $be:ident -> $t:ident, $le_doc:expr, $be_doc:expr;)*) => {$(
+        #[doc = $le_doc]
+        fn $le(&mut self, v: $t) -> io::Result<()> {
+            self.write_all(&v.to_le_bytes())
+        }
+
+        #[doc = $be_doc]
+        fn $be(&mut self, v: $t) -> io::Result<()> {
+            self.write_all(&v.to_be_bytes())
+        }
+    )*}
+}
+
+/// Methods reading numbers from any `Read`; see the module documentation.
+pub trait ReadBytesExt: Read {
+    /// Reads a `u8`.
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut buf = [0];
+        try!(self.read_exact(&mut buf));
+        Ok(buf[0])
+    }
+
+    /// Reads an `i8`.
+    fn read_i8(&mut self) -> io::Result<i8> {
+        self.read_u8().map(|b| b as i8)
+    }
+
+    read_methods! {
+        read_u16_le, read_u16_be -> u16, 2,
+            "Reads a little-endian `u16`.", "Reads a big-endian `u16`.";
+        read_i16_le, read_i16_be -> i16, 2,
+            "Reads a little-endian `i16`.", "Reads a big-endian `i16`.";
+        read_u32_le, read_u32_be -> u32, 4,
+            "Reads a little-endian `u32`.", "Reads a big-endian `u32`.";
+        read_i32_le, read_i32_be -> i32, 4,
+            "Reads a little-endian `i32`.", "Reads a big-endian `i32`.";
+        read_u64_le, read_u64_be -> u64, 8,
+            "Reads a little-endian `u64`.", "Reads a big-endian `u64`.";
+        read_i64_le, read_i64_be -> i64, 8,
+            "Reads a little-endian `i64`.", "Reads a big-endian `i64`.";
+        read_f32_le, read_f32_be -> f32, 4,
+            "Reads a little-endian `f32`.", "Reads a big-endian `f32`.";
+        read_f64_le, read_f64_be -> f64, 8,
+            "Reads a little-endian `f64`.", "Reads a big-endian `f64`.";
+    }
+}
+
+impl<R: Read + ?Sized> ReadBytesExt for R {}
+
+/// Methods writing numbers to any `Write`; see the module documentation.
+pub trait WriteBytesExt: Write {
+    /// Writes a `u8`.
+    fn write_u8(&mut self, v: u8) -> io::Result<()> {
+        self.write_all(&[v])
+    }
+
+    /// Writes an `i8`.
+    fn write_i8(&mut self, v: i8) -> io::Result<()> {
+        self.write_all(&[v as u8])
+    }
+
+    write_methods! {
+        write_u16_le, write_u16_be -> u16,
+            "Writes a little-endian `u16`.", "Writes a big-endian `u16`.";
+        write_i16_le, write_i16_be -> i16,
+            "Writes a little-endian `i16`.", "Writes a big-endian `i16`.";
+        write_u32_le, write_u32_be -> u32,
+            "Writes a little-endian `u32`.", "Writes a big-endian `u32`.";
+        write_i32_le, write_i32_be -> i32,
+            "Writes a little-endian `i32`.", "Writes a big-endian `i32`.";
+        write_u64_le, write_u64_be -> u64,
+            "Writes a little-endian `u64`.", "Writes a big-endian `u64`.";
+        write_i64_le, write_i64_be -> i64,
+            "Writes a little-endian `i64`.", "Writes a big-endian `i64`.";
+        write_f32_le, write_f32_be -> f32,
+            "Writes a little-endian `f32`.", "Writes a big-endian `f32`.";
+        write_f64_le, write_f64_be -> f64,
+            "Writes a little-endian `f64`.", "Writes a big-endian `f64`.";
+    }
+}
+
+impl<W: Write + ?Sized> WriteBytesExt for W {}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::{ReadBytesExt, WriteBytesExt};
+
+    #[test]
+    fn test_byteorder() {
+        let mut out = Vec::new();
+        out.write_u8(0xAB).unwrap();
+        out.write_i8(-1).unwrap();
+        out.write_u16_le(0x0102).unwrap();
+        out.write_u16_be(0x0102).unwrap();
+        out.write_u32_le(0x0102_0304).unwrap();
+        out.write_u32_be(0x0102_0304).unwrap();
+        out.write_i64_be(-2).unwrap();
+        out.write_f64_le(1.5).unwrap();
+        out.write_f32_be(-0.25).unwrap();
+        {
+            // A trait object has the methods too.
+            let w: &mut io::Write = &mut out;
+            w.write_i16_be(-300).unwrap();
+        }
+        assert_eq!(&out[..12], &[0xAB, 0xFF, 0x02, 0x01, 0x01, 0x02,
+                                 0x04, 0x03, 0x02, 0x01, 0x01, 0x02]);
+        assert_eq!(&out[26..], &[0, 0, 0xF8, 0x3F, 0xBE, 0x80, 0, 0, 0xFE, 0xD4]);
+
+        let mut r = &out[..];
+        assert_eq!(r.read_u8().unwrap(), 0xAB);
+        assert_eq!(r.read_i8().unwrap(), -1);
+        assert_eq!(r.read_u16_le().unwrap(), 0x0102);
+        assert_eq!(r.read_u16_be().unwrap(), 0x0102);
+        assert_eq!(r.read_u32_le().unwrap(), 0x0102_0304);
+        assert_eq!(r.read_u32_be().unwrap(), 0x0102_0304);
+        assert_eq!(r.read_i64_be().unwrap(), -2);
+        assert_eq!(r.read_f64_le().unwrap(), 1.5);
+        assert_eq!(r.read_f32_be().unwrap(), -0.25);
+        assert_eq!(r.read_i16_be().unwrap(), -300);
+        assert_eq!(r.read_u8().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut short = &[1, 2, 3][..];
+        assert_eq!(short.read_u32_be().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,233 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Helpers for byte strings which are usually, but not always, UTF-8
+//!
+//! These work on in-memory `&[u8]`; see the `utf8` module for readers and
+//! writers.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::bytes;
+//!
+//! fn main() {
+//!     let data = b"caf\xc3\xa9 \xff";
+//!     assert_eq!(format!("{:?}", bytes::debug(data)), "\"caf\u{e9} \\xff\"");
+//!     assert_eq!(bytes::chars_lossy(data).collect::<String>(), "caf\u{e9} \u{fffd}");
+//!     assert_eq!(bytes::find(data, " "), Some(5));
+//! }
+//! ```
+
+use std::char;
+use std::fmt;
+use std::str;
+
+/// Returns a wrapper whose `Debug` output shows `bytes` as a string
+/// literal: valid UTF-8 as text (escaped like `str`'s `Debug`) and every
+/// other byte as a `\xNN` escape, so no information is lost.
+pub fn debug<'a>(bytes: &'a [u8]) -> DebugBytes<'a> {
+    DebugBytes { bytes: bytes }
+}
+
+/// The wrapper returned by `debug`.
+pub struct DebugBytes<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> fmt::Debug for DebugBytes<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "\""));
+        for chunk in Utf8Chunks::new(self.bytes) {
+            for c in chunk.valid.chars() {
+                // Quotes are escaped, unlike in `char::escape_debug`.
+                if c == '"' {
+                    try!(write!(f, "\\\""));
+                } else {
+                    try!(write!(f, "{}", c.escape_debug()));
+                }
+            }
+            for b in chunk.invalid {
+                try!(write!(f, "\\x{:02x}", b));
+            }
+        }
+        write!(f, "\"")
+    }
+}
+
+/// Returns an iterator over the characters of `bytes`, replacing each
+/// invalid UTF-8 sequence with U+FFFD as `String::from_utf8_lossy` does.
+pub fn chars_lossy<'a>(bytes: &'a [u8]) -> CharsLossy<'a> {
+    CharsLossy { chunks: Utf8Chunks::new(bytes), chars: "".chars(), replace: false }
+}
+
+/// The iterator returned by `chars_lossy`.
+pub struct CharsLossy<'a> {
+    chunks: Utf8Chunks<'a>,
+    chars: str::Chars<'a>,
+    // Whether the current valid run is followed by an invalid sequence.
+    replace: bool,
+}
+
+impl<'a> Iterator for CharsLossy<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            if let Some(c) = self.chars.next() {
+                return Some(c);
+            }
+            if self.replace {
+                self.replace = false;
+                return Some(char::REPLACEMENT_CHARACTER);
+            }
+            match self.chunks.next() {
+                Some(chunk) => {
+                    self.chars = chunk.valid.chars();
+                    self.replace = !chunk.invalid.is_empty();
+                }
+                None => return None,
+            }
+        }
+    }
+}
+
+/// Returns the byte offset of the first occurrence of `needle` in
+/// `haystack` which starts on a character boundary.
+///
+/// Matches are only looked for in the valid UTF-8 parts of `haystack`.
+pub fn find(haystack: &[u8], needle: &str) -> Option<usize> {
+    let mut offset = 0;
+    for chunk in Utf8Chunks::new(haystack) {
+        if let Some(idx) = chunk.valid.find(needle) {
+            return Some(offset + idx);
+        }
+        offset += chunk.valid.len() + chunk.invalid.len();
+    }
+    None
+}
+
+/// Returns an iterator over the pieces of `haystack` separated by `sep`,
+/// found as by `find`.
+///
+/// # Panics
+///
+/// Panics if `sep` is empty.
+pub fn split<'a>(haystack: &'a [u8], sep: &'a str) -> Split<'a> {
+    assert!(!sep.is_empty(), "empty separator");
+    Split { rest: Some(haystack), sep: sep }
+}
+
+/// The iterator returned by `split`.
+pub struct Split<'a> {
+    rest: Option<&'a [u8]>,
+    sep: &'a str,
+}
+
+impl<'a> Iterator for Split<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let rest = match self.rest {
+            Some(rest) => rest,
+            None => return None,
+        };
+        match find(rest, self.sep) {
+            Some(idx) => {
+                self.rest = Some(&rest[idx + self.sep.len()..]);
+                Some(&rest[..idx])
+            }
+            None => {
+                self.rest = None;
+                Some(rest)
+            }
+        }
+    }
+}
+
+// A run of valid UTF-8 followed by the invalid sequence ending it, if any.
+struct Utf8Chunk<'a> {
+    valid: &'a str,
+    invalid: &'a [u8],
+}
+
+// Splits bytes into `Utf8Chunk`s. Each invalid part is one maximal invalid
+// sequence, as replaced by one U+FFFD in `String::from_utf8_lossy`.
+struct Utf8Chunks<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Utf8Chunks<'a> {
+    fn new(bytes: &'a [u8]) -> Utf8Chunks<'a> {
+        Utf8Chunks { bytes: bytes }
+    }
+}
+
+impl<'a> Iterator for Utf8Chunks<'a> {
+    type Item = Utf8Chunk<'a>;
+
+    fn next(&mut self) -> Option<Utf8Chunk<'a>> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let bytes = self.bytes;
+        match str::from_utf8(bytes) {
+            Ok(s) => {
+                self.bytes = &[];
+                Some(Utf8Chunk { valid: s, invalid: &[] })
+            }
+            Err(e) => {
+                let valid = e.valid_up_to();
+                let invalid = e.error_len().unwrap_or(bytes.len() - valid);
+                self.bytes = &bytes[valid + invalid..];
+                Some(Utf8Chunk {
+                    valid: str::from_utf8(&bytes[..valid]).unwrap(),
+                    invalid: &bytes[valid..valid + invalid],
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{chars_lossy, debug, find, split};
+
+    #[test]
+    fn test_debug() {
+        assert_eq!(format!("{:?}", debug(b"")), "\"\"");
+        assert_eq!(format!("{:?}", debug(b"a\"b\\\n\x01")), "\"a\\\"b\\\\\\n\\u{1}\"");
+        assert_eq!(format!("{:?}", debug(b"\xe2\x82\xff\xc3\xa9\xed\xa0\x80")),
+                   "\"\\xe2\\x82\\xff\u{e9}\\xed\\xa0\\x80\"");
+    }
+
+    #[test]
+    fn test_chars_lossy() {
+        let cases: &[&[u8]] = &[b"", b"plain", b"\xff", b"a\xe2\x82", b"\xe2\x82(\xf0\x9f\x98",
+                                b"\xed\xa0\x80\xc3\xa9", b"\xc0\xaf"];
+        for bytes in cases {
+            assert_eq!(chars_lossy(bytes).collect::<String>(), String::from_utf8_lossy(bytes));
+        }
+    }
+
+    #[test]
+    fn test_find_split() {
+        let data = b"a,\xff,b\xe2\x82,,\xc3\xa9";
+        assert_eq!(find(data, ","), Some(1));
+        assert_eq!(find(data, "\u{e9}"), Some(9));
+        assert_eq!(find(data, "x"), None);
+        // U+0082 is not found inside the invalid, truncated U+20AC.
+        assert_eq!(find(b"\xe2\x82", "\u{82}"), None);
+        let pieces: Vec<_> = split(data, ",").collect();
+        assert_eq!(pieces, [&b"a"[..], b"\xff", b"b\xe2\x82", b"", b"\xc3\xa9"]);
+        assert_eq!(split(b"", ",").count(), 1);
+    }
+}
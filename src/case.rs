@@ -0,0 +1,229 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Converting identifiers between naming conventions
+//!
+//! A `Case` rewrites names such as struct field names into a convention
+//! like `camelCase` or `kebab-case`. Names are split into words at `_`,
+//! `-` and spaces, before an uppercase letter following a lowercase letter
+//! or digit, and before the last of a run of uppercase letters followed by
+//! a lowercase one, so `"HTTPServer"` is the words `HTTP` and `Server`.
+//! Digits stay with the word they follow.
+//!
+//! `Case::convert` borrows its argument when it is already in the case, and
+//! `Case::convert_into` appends to a buffer, so names can be converted
+//! repeatedly without allocating for each.
+//!
+//! The `json` encoder and decoder can apply a case to every struct field
+//! name with `set_rename_all`.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::case::Case;
+//!
+//! fn main() {
+//!     assert_eq!(Case::Camel.convert("max_retry_count"), "maxRetryCount");
+//!     assert_eq!(Case::Snake.convert("HTTPServerURL"), "http_server_url");
+//!     assert_eq!(Case::Kebab.convert("content_type"), "content-type");
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::fmt::{self, Write};
+
+/// A naming convention.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Case {
+    /// `snake_case`
+    Snake,
+    /// `SCREAMING_SNAKE_CASE`
+    ScreamingSnake,
+    /// `kebab-case`
+    Kebab,
+    /// `camelCase`
+    Camel,
+    /// `PascalCase`
+    Pascal,
+}
+
+impl Case {
+    /// Converts `s` to this case, borrowing it if it already is.
+    pub fn convert(self, s: &str) -> Cow<'_, str> {
+        if self.matches(s) {
+            Cow::Borrowed(s)
+        } else {
+            let mut out = String::with_capacity(s.len() + 4);
+            self.convert_into(s, &mut out);
+            Cow::Owned(out)
+        }
+    }
+
+    /// Appends `s`, converted to this case, to `out`.
+    pub fn convert_into(self, s: &str, out: &mut String) {
+        self.write(s, out).unwrap()
+    }
+
+    /// Whether `s` is already in this case, i.e. converting it would leave
+    /// it as it is.
+    pub fn matches(self, s: &str) -> bool {
+        let mut cmp = Compare { rest: s, same: true };
+        self.write(s, &mut cmp).unwrap();
+        cmp.same && cmp.rest.is_empty()
+    }
+
+    fn write<W: Write>(self, s: &str, out: &mut W) -> fmt::Result {
+        let (separator, first, rest) = match self {
+            Case::Snake => (Some('_'), Word::Lower, Word::Lower),
+            Case::ScreamingSnake => (Some('_'), Word::Upper, Word::Upper),
+            Case::Kebab => (Some('-'), Word::Lower, Word::Lower),
+            Case::Camel => (None, Word::Lower, Word::Capitalized),
+            Case::Pascal => (None, Word::Capitalized, Word::Capitalized),
+        };
+        words(s, |i, word| {
+            if i == 0 {
+                return first.write(word, out);
+            }
+            if let Some(c) = separator {
+                try!(out.write_char(c));
+            }
+            rest.write(word, out)
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Word {
+    Lower,
+    Upper,
+    Capitalized,
+}
+
+impl Word {
+    fn write<W: Write>(self, word: &str, out: &mut W) -> fmt::Result {
+        for (i, c) in word.chars().enumerate() {
+            let upper = match self {
+                Word::Lower => false,
+                Word::Upper => true,
+                Word::Capitalized => i == 0,
+            };
+            if upper {
+                for c in c.to_uppercase() {
+                    try!(out.write_char(c));
+                }
+            } else {
+                for c in c.to_lowercase() {
+                    try!(out.write_char(c));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Calls `f` with the index and text of each word of `s`, in order.
+fn words<F>(s: &str, mut f: F) -> fmt::Result
+    where F: FnMut(usize, &str) -> fmt::Result
+{
+    let mut n = 0;
+    let mut start = None;
+    let mut chars = s.char_indices().peekable();
+    let mut prev: Option<char> = None;
+    while let Some((i, c)) = chars.next() {
+        if c == '_' || c == '-' || c == ' ' {
+            if let Some(start) = start.take() {
+                try!(f(n, &s[start..i]));
+                n += 1;
+            }
+            prev = None;
+            continue;
+        }
+        let boundary = match prev {
+            Some(p) if c.is_uppercase() => {
+                !p.is_uppercase() ||
+                    chars.peek().map_or(false, |&(_, next)| next.is_lowercase())
+            }
+            _ => false,
+        };
+        if boundary {
+            if let Some(start) = start.take() {
+                try!(f(n, &s[start..i]));
+                n += 1;
+            }
+        }
+        if start.is_none() {
+            start = Some(i);
+        }
+        prev = Some(c);
+    }
+    match start {
+        Some(start) => f(n, &s[start..]),
+        None => Ok(()),
+    }
+}
+
+// A writer checking that what is written is a prefix of `rest`, and
+// consuming it.
+struct Compare<'a> {
+    rest: &'a str,
+    same: bool,
+}
+
+impl<'a> Write for Compare<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.same && self.rest.starts_with(s) {
+            self.rest = &self.rest[s.len()..];
+        } else {
+            self.same = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use super::Case;
+
+    #[test]
+    fn test_convert() {
+        let names = ["max_retry_count", "maxRetryCount", "MaxRetryCount", "max-retry-count",
+                     "MAX_RETRY_COUNT", "max retry  count", "__max_retry_count"];
+        for name in &names {
+            assert_eq!(Case::Snake.convert(name), "max_retry_count");
+            assert_eq!(Case::ScreamingSnake.convert(name), "MAX_RETRY_COUNT");
+            assert_eq!(Case::Kebab.convert(name), "max-retry-count");
+            assert_eq!(Case::Camel.convert(name), "maxRetryCount");
+            assert_eq!(Case::Pascal.convert(name), "MaxRetryCount");
+        }
+
+        assert_eq!(Case::Snake.convert("HTTPServer"), "http_server");
+        assert_eq!(Case::Snake.convert("userID"), "user_id");
+        assert_eq!(Case::Snake.convert("ipv4Addr"), "ipv4_addr");
+        assert_eq!(Case::Camel.convert("v2_api"), "v2Api");
+        assert_eq!(Case::Camel.convert("\u{c9}t\u{e9}_d\u{e9}j\u{e0}"),
+                   "\u{e9}t\u{e9}D\u{e9}j\u{e0}");
+        assert_eq!(Case::Camel.convert(""), "");
+        assert_eq!(Case::Kebab.convert("contentType"), "content-type");
+
+        match Case::Camel.convert("alreadyCamel") {
+            Cow::Borrowed(s) => assert_eq!(s, "alreadyCamel"),
+            Cow::Owned(_) => panic!("allocated for a name already in the case"),
+        }
+        assert!(Case::Snake.matches("a_b"));
+        assert!(!Case::Snake.matches("a_b_"));
+        assert!(!Case::Snake.matches("a_"));
+
+        let mut buf = String::from("x:");
+        Case::Pascal.convert_into("the_end", &mut buf);
+        assert_eq!(buf, "x:TheEnd");
+    }
+}
@@ -0,0 +1,776 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Deterministic CBOR encoding
+//!
+//! This module writes CBOR (RFC 8949) in its deterministic form, as
+//! needed for signing (e.g. COSE), where the same value must always encode
+//! to the same bytes:
+//!
+//! * Integers, lengths and tags take the shortest head that fits them.
+//! * Floats take the shortest of half, single and double precision which
+//!   keeps their value exactly, and every NaN is `0xf97e00`.
+//! * Map entries are sorted by the bytes of their encoded keys, and a map
+//!   may not have two equal keys.
+//! * Lengths are always definite.
+//!
+//! `Value` is a CBOR value; `Value::from_json` converts a `Json`, and
+//! `Encoder` builds one from any `Encodable` type, encoding enums as the
+//! `json` module does. Values encoded with `Encoder::emit_tagged` (see
+//! `ExtensionTag`) become CBOR tags, and `Value::from_json` converts the
+//! JSON form of such values, `{"$tag": n, "value": ...}`, likewise. There
+//! is no decoder.
+//!
+//! With `Encoder::set_pack_arrays`, long arrays of integers are delta or
+//! run-length packed as `packed` describes; `Value::from_json` converts the
+//! JSON form of packed arrays to the same tags.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::cbor::Value;
+//! use rustc_serialize::hex::ToHex;
+//!
+//! fn main() {
+//!     let value = Value::Map(vec![
+//!         (Value::Text("b".to_string()), Value::Float(1.5)),
+//!         (Value::Text("a".to_string()), Value::Unsigned(1000)),
+//!     ]);
+//!     assert_eq!(value.to_canonical().unwrap().to_hex(), "a261611903e86162f93e00");
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+
+use Encodable;
+use json::Json;
+use packed::{self, Packing};
+
+/// A CBOR value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// An unsigned integer (major type 0)
+    Unsigned(u64),
+    /// The negative integer `-1 - n` (major type 1)
+    Negative(u64),
+    /// A byte string (major type 2)
+    Bytes(Vec<u8>),
+    /// A text string (major type 3)
+    Text(String),
+    /// An array (major type 4)
+    Array(Vec<Value>),
+    /// A map, in any order (major type 5)
+    Map(Vec<(Value, Value)>),
+    /// A tagged value (major type 6)
+    Tag(u64, Box<Value>),
+    /// `false` or `true`
+    Bool(bool),
+    /// `null`
+    Null,
+    /// `undefined`
+    Undefined,
+    /// A floating-point number
+    Float(f64),
+}
+
+/// Errors that can occur in deterministic encoding.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CanonicalError {
+    /// A map had two keys which encode the same; the key is given
+    DuplicateKey(Value),
+    /// The buffer given to `encode_to_slice` was too small; the number of
+    /// bytes needed is given
+    BufferTooSmall(usize),
+}
+
+impl fmt::Display for CanonicalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CanonicalError::DuplicateKey(ref key) => write!(f, "duplicate map key {:?}", key),
+            CanonicalError::BufferTooSmall(n) => write!(f, "buffer too small, {} bytes needed", n),
+        }
+    }
+}
+
+impl error::Error for CanonicalError {
+    fn description(&self) -> &str {
+        match *self {
+            CanonicalError::DuplicateKey(_) => "value has no deterministic CBOR encoding",
+            CanonicalError::BufferTooSmall(_) => "buffer too small",
+        }
+    }
+}
+
+impl Value {
+    /// Converts a JSON value. Integers become `Unsigned` or `Negative`,
+    /// and objects become maps with text keys.
+    pub fn from_json(json: &Json) -> Value {
+        match *json {
+            Json::I64(v) if v < 0 => Value::Negative(!(v as u64)),
+            Json::I64(v) => Value::Unsigned(v as u64),
+            Json::U64(v) => Value::Unsigned(v),
+            Json::F64(v) => Value::Float(v),
+            Json::String(ref s) => Value::Text(s.to_string()),
+            Json::Wtf8(ref s) => Value::Text(s.to_string_lossy().into_owned()),
+            Json::Boolean(b) => Value::Bool(b),
+            Json::Array(ref a) => Value::Array(a.iter().map(Value::from_json).collect()),
+            // The convention of `Encoder::emit_tagged`
+            Json::Object(ref o) if o.len() == 2 && o.contains_key("value") &&
+                                   o.get("$tag").map_or(false, |t| t.is_u64()) => {
+                let tag = o["$tag"].as_u64().unwrap();
+                let packed = match o["value"] {
+                    Json::Array(ref a) if Packing::from_tag(tag).is_some() => {
+                        packed::integers(a)
+                    }
+                    _ => None,
+                };
+                match packed {
+                    Some(packed) => packed_array(tag, &packed),
+                    None => Value::Tag(tag, Box::new(Value::from_json(&o["value"]))),
+                }
+            }
+            Json::Object(ref o) => {
+                Value::Map(o.iter().map(|(k, v)| (Value::Text(k.clone()), Value::from_json(v)))
+                            .collect())
+            }
+            Json::Null => Value::Null,
+        }
+    }
+
+    /// Returns the deterministic encoding of this value.
+    pub fn to_canonical(&self) -> Result<Vec<u8>, CanonicalError> {
+        let mut out = Vec::new();
+        try!(self.write_canonical(&mut out));
+        Ok(out)
+    }
+
+    /// Appends the deterministic encoding of this value to `out`.
+    pub fn write_canonical(&self, out: &mut Vec<u8>) -> Result<(), CanonicalError> {
+        match *self {
+            Value::Unsigned(v) => write_head(out, 0, v),
+            Value::Negative(v) => write_head(out, 1, v),
+            Value::Bytes(ref b) => {
+                write_head(out, 2, b.len() as u64);
+                out.extend_from_slice(b);
+            }
+            Value::Text(ref s) => {
+                write_head(out, 3, s.len() as u64);
+                out.extend_from_slice(s.as_bytes());
+            }
+            Value::Array(ref items) => {
+                write_head(out, 4, items.len() as u64);
+                for item in items {
+                    try!(item.write_canonical(out));
+                }
+            }
+            Value::Map(ref entries) => {
+                let mut encoded = Vec::with_capacity(entries.len());
+                for (key, value) in entries {
+                    encoded.push((try!(key.to_canonical()), key, value));
+                }
+                encoded.sort_by(|a, b| a.0.cmp(&b.0));
+                if let Some(pair) = encoded.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+                    return Err(CanonicalError::DuplicateKey(pair[0].1.clone()));
+                }
+                write_head(out, 5, entries.len() as u64);
+                for (key, _, value) in encoded {
+                    out.extend_from_slice(&key);
+                    try!(value.write_canonical(out));
+                }
+            }
+            Value::Tag(tag, ref value) => {
+                write_head(out, 6, tag);
+                try!(value.write_canonical(out));
+            }
+            Value::Bool(false) => out.push(0xf4),
+            Value::Bool(true) => out.push(0xf5),
+            Value::Null => out.push(0xf6),
+            Value::Undefined => out.push(0xf7),
+            Value::Float(v) => write_float(out, v),
+        }
+        Ok(())
+    }
+}
+
+// A packed array: its packed numbers as zigzag varints, tagged.
+fn packed_array(tag: u64, packed: &[i64]) -> Value {
+    let mut bytes = Vec::new();
+    packed::write_varints(packed, &mut bytes);
+    Value::Tag(tag, Box::new(Value::Bytes(bytes)))
+}
+
+// Writes the head of a data item of major type `major` with argument `v`,
+// in the fewest bytes.
+fn write_head(out: &mut Vec<u8>, major: u8, v: u64) {
+    let major = major << 5;
+    if v < 24 {
+        out.push(major | v as u8);
+    } else if v <= 0xff {
+        out.push(major | 24);
+        out.push(v as u8);
+    } else if v <= 0xffff {
+        out.push(major | 25);
+        push_be(out, v, 2);
+    } else if v <= 0xffff_ffff {
+        out.push(major | 26);
+        push_be(out, v, 4);
+    } else {
+        out.push(major | 27);
+        push_be(out, v, 8);
+    }
+}
+
+fn push_be(out: &mut Vec<u8>, v: u64, n: usize) {
+    for i in (0..n).rev() {
+        out.push((v >> (8 * i)) as u8);
+    }
+}
+
+fn write_float(out: &mut Vec<u8>, v: f64) {
+    if v.is_nan() {
+        out.extend_from_slice(&[0xf9, 0x7e, 0x00]);
+        return;
+    }
+    let single = v as f32;
+    if single as f64 != v {
+        out.push(0xfb);
+        push_be(out, v.to_bits(), 8);
+        return;
+    }
+    match to_half(single) {
+        Some(half) => {
+            out.push(0xf9);
+            push_be(out, half as u64, 2);
+        }
+        None => {
+            out.push(0xfa);
+            push_be(out, single.to_bits() as u64, 4);
+        }
+    }
+}
+
+// Returns the bits of the half-precision float equal to `v`, if any.
+fn to_half(v: f32) -> Option<u16> {
+    let bits = v.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exp == 0xff {
+        // Infinity; NaN is handled by the caller.
+        return Some(sign | 0x7c00);
+    }
+    if exp == 0 {
+        // Zero, or a single-precision subnormal, far below the half range.
+        return if mantissa == 0 { Some(sign) } else { None };
+    }
+    let e = exp - 127;
+    if (-14..=15).contains(&e) {
+        if mantissa & 0x1fff != 0 {
+            return None;
+        }
+        return Some(sign | (((e + 15) as u16) << 10) | (mantissa >> 13) as u16);
+    }
+    if (-24..-14).contains(&e) {
+        // A half-precision subnormal: a multiple of 2^-24.
+        let full = 0x80_0000 | mantissa;
+        let shift = (-e - 1) as u32;
+        if full & ((1 << shift) - 1) != 0 {
+            return None;
+        }
+        return Some(sign | (full >> shift) as u16);
+    }
+    None
+}
+
+/// Encodes `object` as a CBOR value and returns its deterministic
+/// encoding.
+pub fn encode_canonical<T: Encodable>(object: &T) -> Result<Vec<u8>, CanonicalError> {
+    let mut encoder = Encoder::new();
+    match object.encode(&mut encoder) {
+        Ok(()) => {}
+        Err(never) => match never {},
+    }
+    encoder.into_value().to_canonical()
+}
+
+/// Writes the deterministic encoding of `object` into `buf`, and returns
+/// the number of bytes written.
+///
+/// If the encoding does not fit, the error is `BufferTooSmall` with the
+/// number of bytes it needs, and `buf` is left as it was. The value is
+/// still built in memory first, so this does allocate.
+pub fn encode_to_slice<T: Encodable>(object: &T, buf: &mut [u8]) -> Result<usize, CanonicalError> {
+    let bytes = try!(encode_canonical(object));
+    if bytes.len() > buf.len() {
+        return Err(CanonicalError::BufferTooSmall(bytes.len()));
+    }
+    buf[..bytes.len()].copy_from_slice(&bytes);
+    Ok(bytes.len())
+}
+
+/// The error of `Encoder`, which cannot fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncoderError {}
+
+impl fmt::Display for EncoderError {
+    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+        match *self {}
+    }
+}
+
+impl error::Error for EncoderError {
+    fn description(&self) -> &str { match *self {} }
+}
+
+pub type EncodeResult = Result<(), EncoderError>;
+
+enum Frame {
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>, Option<Value>),
+}
+
+/// An encoder building a `Value`.
+pub struct Encoder {
+    stack: Vec<Frame>,
+    value: Option<Value>,
+    pack_arrays: bool,
+}
+
+impl Encoder {
+    /// Creates a new encoder.
+    pub fn new() -> Encoder {
+        Encoder { stack: Vec::new(), value: None, pack_arrays: false }
+    }
+
+    /// Pack sequences of integers which `Packing::choose` packs, as tags
+    /// holding a byte string of the packed numbers as zigzag varints; see
+    /// `packed`. Tuples are never packed. This should be set before
+    /// encoding.
+    pub fn set_pack_arrays(&mut self, pack: bool) {
+        self.pack_arrays = pack;
+    }
+
+    /// Returns the encoded value.
+    pub fn into_value(self) -> Value {
+        self.value.unwrap_or(Value::Null)
+    }
+
+    fn set(&mut self, value: Value) -> EncodeResult {
+        self.value = Some(value);
+        Ok(())
+    }
+
+    fn capture<F>(&mut self, f: F) -> Result<Value, EncoderError>
+        where F: FnOnce(&mut Encoder) -> EncodeResult
+    {
+        try!(f(self));
+        Ok(self.value.take().unwrap_or(Value::Null))
+    }
+
+    fn collect<F>(&mut self, frame: Frame, f: F) -> Result<Frame, EncoderError>
+        where F: FnOnce(&mut Encoder) -> EncodeResult
+    {
+        self.stack.push(frame);
+        try!(f(self));
+        Ok(self.stack.pop().unwrap())
+    }
+
+    fn push_item<F>(&mut self, f: F) -> EncodeResult
+        where F: FnOnce(&mut Encoder) -> EncodeResult
+    {
+        let value = try!(self.capture(f));
+        if let Some(&mut Frame::Array(ref mut items)) = self.stack.last_mut() {
+            items.push(value);
+        }
+        Ok(())
+    }
+
+    fn push_entry<F>(&mut self, key: Value, f: F) -> EncodeResult
+        where F: FnOnce(&mut Encoder) -> EncodeResult
+    {
+        let value = try!(self.capture(f));
+        if let Some(&mut Frame::Map(ref mut entries, _)) = self.stack.last_mut() {
+            entries.push((key, value));
+        }
+        Ok(())
+    }
+
+    fn emit_int(&mut self, v: i64) -> EncodeResult {
+        if v < 0 {
+            self.set(Value::Negative(!(v as u64)))
+        } else {
+            self.set(Value::Unsigned(v as u64))
+        }
+    }
+
+    fn array<F>(&mut self, f: F) -> Result<Vec<Value>, EncoderError>
+        where F: FnOnce(&mut Encoder) -> EncodeResult
+    {
+        match try!(self.collect(Frame::Array(Vec::new()), f)) {
+            Frame::Array(items) => Ok(items),
+            _ => unreachable!(),
+        }
+    }
+
+    fn map<F>(&mut self, f: F) -> Result<Vec<(Value, Value)>, EncoderError>
+        where F: FnOnce(&mut Encoder) -> EncodeResult
+    {
+        match try!(self.collect(Frame::Map(Vec::new(), None), f)) {
+            Frame::Map(entries, _) => Ok(entries),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Default for Encoder {
+    fn default() -> Encoder { Encoder::new() }
+}
+
+fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+impl ::Encoder for Encoder {
+    type Error = EncoderError;
+
+    fn emit_nil(&mut self) -> EncodeResult { self.set(Value::Null) }
+
+    fn emit_usize(&mut self, v: usize) -> EncodeResult { self.set(Value::Unsigned(v as u64)) }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult { self.set(Value::Unsigned(v)) }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult { self.set(Value::Unsigned(v as u64)) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult { self.set(Value::Unsigned(v as u64)) }
+    fn emit_u8(&mut self, v: u8) -> EncodeResult { self.set(Value::Unsigned(v as u64)) }
+
+    fn emit_isize(&mut self, v: isize) -> EncodeResult { self.emit_int(v as i64) }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult { self.emit_int(v) }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult { self.emit_int(v as i64) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult { self.emit_int(v as i64) }
+    fn emit_i8(&mut self, v: i8) -> EncodeResult { self.emit_int(v as i64) }
+
+    fn emit_bool(&mut self, v: bool) -> EncodeResult { self.set(Value::Bool(v)) }
+    fn emit_f64(&mut self, v: f64) -> EncodeResult { self.set(Value::Float(v)) }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult { self.set(Value::Float(v as f64)) }
+    fn emit_char(&mut self, v: char) -> EncodeResult { self.set(Value::Text(v.to_string())) }
+    fn emit_str(&mut self, v: &str) -> EncodeResult { self.set(text(v)) }
+
+    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_enum_variant<F>(&mut self, name: &str, _id: usize, cnt: usize, f: F)
+                            -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        if cnt == 0 {
+            return self.set(text(name));
+        }
+        let fields = try!(self.array(f));
+        self.set(Value::Map(vec![(text("variant"), text(name)),
+                                 (text("fields"), Value::Array(fields))]))
+    }
+
+    fn emit_enum_variant_arg<F>(&mut self, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.push_item(f)
+    }
+
+    fn emit_enum_struct_variant<F>(&mut self, name: &str, id: usize, cnt: usize, f: F)
+                                   -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_enum_variant(name, id, cnt, f)
+    }
+
+    fn emit_enum_struct_variant_field<F>(&mut self, _: &str, idx: usize, f: F)
+                                         -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_enum_variant_arg(idx, f)
+    }
+
+    fn emit_struct<F>(&mut self, _: &str, _len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let entries = try!(self.map(f));
+        self.set(Value::Map(entries))
+    }
+
+    fn emit_struct_field<F>(&mut self, name: &str, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.push_entry(text(name), f)
+    }
+
+    fn emit_tuple<F>(&mut self, _len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let items = try!(self.array(f));
+        self.set(Value::Array(items))
+    }
+
+    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_tuple_struct<F>(&mut self, _: &str, len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_tuple(len, f)
+    }
+
+    fn emit_tuple_struct_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_option_none(&mut self) -> EncodeResult { self.emit_nil() }
+
+    fn emit_option_some<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_seq<F>(&mut self, _len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let items = try!(self.array(f));
+        if self.pack_arrays {
+            let values: Option<Vec<i64>> = items.iter().map(|item| match *item {
+                Value::Unsigned(v) if v <= i64::MAX as u64 => Some(v as i64),
+                Value::Negative(v) if v <= i64::MAX as u64 => Some(!(v as i64)),
+                _ => None,
+            }).collect();
+            if let Some(values) = values {
+                if let Some(packing) = Packing::choose(&values) {
+                    return self.set(packed_array(packing.tag(), &packing.pack(&values)));
+                }
+            }
+        }
+        self.set(Value::Array(items))
+    }
+
+    fn emit_seq_elt<F>(&mut self, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.push_item(f)
+    }
+
+    fn emit_map<F>(&mut self, _len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let entries = try!(self.map(f));
+        self.set(Value::Map(entries))
+    }
+
+    fn emit_map_elt_key<F>(&mut self, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let key = try!(self.capture(f));
+        if let Some(&mut Frame::Map(_, ref mut pending)) = self.stack.last_mut() {
+            *pending = Some(key);
+        }
+        Ok(())
+    }
+
+    fn emit_map_elt_val<F>(&mut self, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let key = match self.stack.last_mut() {
+            Some(&mut Frame::Map(_, ref mut pending)) => pending.take(),
+            _ => None,
+        };
+        self.push_entry(key.unwrap_or(Value::Null), f)
+    }
+
+    fn emit_tagged<F>(&mut self, tag: u64, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let value = try!(self.capture(f));
+        self.set(Value::Tag(tag, Box::new(value)))
+    }
+
+    fn format(&self) -> Option<&'static str> {
+        Some("cbor")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::f64;
+    use cbor::{encode_canonical, encode_to_slice, CanonicalError, Value};
+    use hex::ToHex;
+    use json::Json;
+    use {Encodable, Encoder};
+
+    fn hex(value: &Value) -> String {
+        value.to_canonical().unwrap().to_hex()
+    }
+
+    #[test]
+    fn test_rfc_examples() {
+        let ints: &[(i64, &str)] = &[(0, "00"), (23, "17"), (24, "1818"), (100, "1864"),
+                                     (1000, "1903e8"), (1000000, "1a000f4240"),
+                                     (1000000000000, "1b000000e8d4a51000"), (-1, "20"),
+                                     (-100, "3863"), (-1000, "3903e7")];
+        for &(v, expected) in ints {
+            assert_eq!(hex(&Value::from_json(&Json::I64(v))), expected);
+        }
+        assert_eq!(hex(&Value::Unsigned(u64::MAX)), "1bffffffffffffffff");
+        assert_eq!(hex(&Value::Negative(u64::MAX)), "3bffffffffffffffff");
+
+        let floats = [(0.0, "f90000"), (-0.0, "f98000"), (1.0, "f93c00"),
+                      (1.1, "fb3ff199999999999a"), (1.5, "f93e00"), (65504.0, "f97bff"),
+                      (100000.0, "fa47c35000"), (3.4028234663852886e+38, "fa7f7fffff"),
+                      (1.0e+300, "fb7e37e43c8800759c"), (5.960464477539063e-8, "f90001"),
+                      (0.00006103515625, "f90400"), (-4.0, "f9c400"),
+                      (-4.1, "fbc010666666666666"), (f64::INFINITY, "f97c00"),
+                      (f64::NEG_INFINITY, "f9fc00"), (f64::NAN, "f97e00"),
+                      (65505.0, "fa477fe100"), (1.0e-7, "fb3e7ad7f29abcaf48")];
+        for &(v, expected) in floats.iter() {
+            assert_eq!(hex(&Value::Float(v)), expected, "{}", v);
+        }
+
+        assert_eq!(hex(&Value::Bytes(vec![1, 2, 3, 4])), "4401020304");
+        assert_eq!(hex(&Value::Text("\u{6c34}".to_string())), "63e6b0b4");
+        assert_eq!(hex(&Value::Tag(1, Box::new(Value::Unsigned(1363896240)))), "c11a514b67b0");
+        let json = Json::from_str(r#"{"a": 1, "b": [2, 3], "c": [true, false, null]}"#).unwrap();
+        assert_eq!(hex(&Value::from_json(&json)), "a36161016162820203616383f5f4f6");
+    }
+
+    #[test]
+    fn test_map_order() {
+        let keys = vec![Value::Bool(false), Value::Array(vec![Value::Negative(0)]),
+                        Value::Text("aa".to_string()), Value::Negative(0),
+                        Value::Array(vec![Value::Unsigned(100)]), Value::Unsigned(100),
+                        Value::Text("z".to_string()), Value::Unsigned(10)];
+        let map = Value::Map(keys.into_iter().map(|k| (k, Value::Null)).collect());
+        assert_eq!(hex(&map), "a80af61864f620f6617af6626161f6811864f68120f6f4f6");
+        let dup = Value::Map(vec![(Value::Unsigned(1), Value::Null),
+                                  (Value::Unsigned(1), Value::Bool(true))]);
+        assert_eq!(dup.to_canonical(), Err(CanonicalError::DuplicateKey(Value::Unsigned(1))));
+    }
+
+    struct Claims {
+        sub: String,
+        iat: u32,
+        offset: i8,
+        scores: Vec<f32>,
+        extra: BTreeMap<u8, bool>,
+        kind: Option<char>,
+    }
+
+    impl Encodable for Claims {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_struct("Claims", 6, |s| {
+                try!(s.emit_struct_field("sub", 0, |s| self.sub.encode(s)));
+                try!(s.emit_struct_field("iat", 1, |s| self.iat.encode(s)));
+                try!(s.emit_struct_field("offset", 2, |s| self.offset.encode(s)));
+                try!(s.emit_struct_field("scores", 3, |s| self.scores.encode(s)));
+                try!(s.emit_struct_field("extra", 4, |s| self.extra.encode(s)));
+                s.emit_struct_field("kind", 5, |s| self.kind.encode(s))
+            })
+        }
+    }
+
+    #[test]
+    fn test_encodable() {
+        let mut extra = BTreeMap::new();
+        extra.insert(2, true);
+        let claims = Claims {
+            sub: "me".to_string(),
+            iat: 1500000000,
+            offset: -2,
+            scores: vec![0.5],
+            extra: extra,
+            kind: None,
+        };
+        // Text keys sort by their heads first, so shorter keys come first.
+        assert_eq!(encode_canonical(&claims).unwrap().to_hex(),
+                   "a6636961741a59682f0063737562626d65646b696e64f6656578747261\
+                    a102f5666f6666736574216673636f72657381f93800");
+    }
+
+    struct Epoch {
+        secs: u64,
+    }
+
+    impl Encodable for Epoch {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_struct("Epoch", 1, |s| {
+                s.emit_struct_field("secs", 0, |s| self.secs.encode(s))
+            })
+        }
+    }
+
+    impl ::ExtensionTag for Epoch {
+        const TAG: u64 = 1;
+    }
+
+    #[test]
+    fn test_tagged() {
+        let epoch = ::Tagged(Epoch { secs: 1363896240 });
+        assert_eq!(encode_canonical(&epoch).unwrap().to_hex(), "c1a164736563731a514b67b0");
+        let json = Json::from_str("[{\"$tag\":1,\"value\":{\"secs\":1363896240}},\
+                                    {\"$tag\":-1,\"value\":0}]").unwrap();
+        assert_eq!(Value::from_json(&json).to_canonical().unwrap().to_hex(),
+                   "82c1a164736563731a514b67b0a26424746167206576616c756500");
+    }
+
+    #[test]
+    fn test_pack_arrays() {
+        use cbor::Encoder;
+        use packed::{self, Packing};
+        use Encodable;
+
+        let times: Vec<i64> = (0..8).map(|i| -1_000_000 + 60 * i).collect();
+        let series = (times.clone(), vec![3u8; 8], vec![1u8, 2]);
+        let mut encoder = Encoder::new();
+        encoder.set_pack_arrays(true);
+        series.encode(&mut encoder).unwrap();
+        let value = encoder.into_value();
+        // -1000000 is 1999999 as a zigzag varint, 0xff887a, and 60 is 0x78.
+        let expected = "83d99d084aff887a78787878787878d99d09420610820102";
+        assert_eq!(hex(&value), expected);
+
+        // The JSON form of the same packed arrays converts to the same tags.
+        let mut json = ::json::to_value(&series).unwrap();
+        packed::pack_json(&mut json);
+        assert_eq!(json[1].to_string(), format!("{{\"$tag\":{},\"value\":[3,8]}}",
+                                                Packing::RunLength.tag()));
+        assert_eq!(hex(&Value::from_json(&json)), expected);
+    }
+
+    #[test]
+    fn test_encode_to_slice() {
+        let epoch = ::Tagged(Epoch { secs: 1363896240 });
+        let mut buf = [0xff; 12];
+        assert_eq!(encode_to_slice(&epoch, &mut buf[..11]),
+                   Err(CanonicalError::BufferTooSmall(12)));
+        assert_eq!(buf, [0xff; 12]);
+        assert_eq!(encode_to_slice(&epoch, &mut buf), Ok(12));
+        assert_eq!(buf.to_hex(), "c1a164736563731a514b67b0");
+    }
+}
@@ -0,0 +1,435 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! HTTP/1.1 chunked transfer coding
+//!
+//! `ChunkedReader` decodes a chunked message body, including any trailer
+//! fields, and `ChunkedWriter` produces one. Unlike the `codec` adapters,
+//! the reader never reads past the end of the body, so the underlying
+//! stream can be used for the next message.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use std::io::{Read, Write};
+//! use rustc_serialize::chunked::{ChunkedReader, ChunkedWriter};
+//!
+//! fn main() {
+//!     let mut writer = ChunkedWriter::new(Vec::new());
+//!     writer.write_all(b"hello, ").unwrap();
+//!     writer.write_all(b"world").unwrap();
+//!     let body = writer.finish_with_trailers(&[("Expires", "never")]).unwrap();
+//!     assert_eq!(body, &b"7\r\nhello, \r\n5\r\nworld\r\n0\r\nExpires: never\r\n\r\n"[..]);
+//!
+//!     let mut reader = ChunkedReader::new(&body[..]);
+//!     let mut decoded = String::new();
+//!     reader.read_to_string(&mut decoded).unwrap();
+//!     assert_eq!(decoded, "hello, world");
+//!     assert_eq!(reader.trailers(), &[("Expires".to_string(), "never".to_string())]);
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::str;
+
+/// The longest chunk size or trailer line accepted, in bytes, excluding
+/// the line ending.
+pub const MAX_LINE: usize = 4096;
+
+/// The most trailer fields accepted.
+pub const MAX_TRAILERS: usize = 64;
+
+/// Errors that can occur when decoding a chunked body. They are reported as
+/// `io::Error`s of kind `InvalidData` wrapping a `ChunkedError`, except that
+/// a truncated body is an `UnexpectedEof` error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkedError {
+    /// A chunk size was not a hex number, or too large
+    InvalidChunkSize,
+    /// A chunk's data was not followed by a line ending
+    MissingLineEnding,
+    /// A trailer field was malformed. Also returned by `ChunkedWriter` for
+    /// trailers which cannot be written.
+    InvalidTrailer,
+    /// A line was longer than `MAX_LINE`
+    LineTooLong,
+    /// There were more than `MAX_TRAILERS` trailer fields
+    TooManyTrailers,
+}
+
+impl ChunkedError {
+    /// Returns the `ChunkedError` wrapped by `err`, if any.
+    pub fn from_io_error(err: &io::Error) -> Option<ChunkedError> {
+        err.get_ref().and_then(|e| e.downcast_ref::<ChunkedError>()).cloned()
+    }
+}
+
+impl fmt::Display for ChunkedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChunkedError::InvalidChunkSize => write!(f, "invalid chunk size"),
+            ChunkedError::MissingLineEnding => write!(f, "missing line ending after chunk"),
+            ChunkedError::InvalidTrailer => write!(f, "invalid trailer field"),
+            ChunkedError::LineTooLong => write!(f, "line longer than {} bytes", MAX_LINE),
+            ChunkedError::TooManyTrailers => {
+                write!(f, "more than {} trailer fields", MAX_TRAILERS)
+            }
+        }
+    }
+}
+
+impl error::Error for ChunkedError {
+    fn description(&self) -> &str { "invalid chunked body" }
+}
+
+fn invalid_data(err: ChunkedError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    // Expecting a chunk size line.
+    Size,
+    // Inside a chunk, with this many bytes left.
+    Data(u64),
+    // Expecting the line ending after a chunk.
+    DataEnd,
+    Done,
+}
+
+/// A reader decoding a chunked body read from `R`.
+///
+/// Chunk extensions are ignored, and lone LF line endings are accepted as
+/// well as CRLF. Once the last chunk and the trailers have been read,
+/// reads return 0 and `trailers` returns the trailer fields.
+pub struct ChunkedReader<R> {
+    inner: R,
+    state: State,
+    trailers: Vec<(String, String)>,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    /// Creates a new reader decoding the body at the start of `inner`.
+    pub fn new(inner: R) -> ChunkedReader<R> {
+        ChunkedReader { inner: inner, state: State::Size, trailers: Vec::new() }
+    }
+
+    /// Returns true once the whole body has been read.
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Returns the trailer fields as `(name, value)` pairs, in the order
+    /// read. Empty until the whole body has been read.
+    pub fn trailers(&self) -> &[(String, String)] {
+        &self.trailers
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwraps this reader, returning the underlying reader. If the body
+    /// was read to the end, it is positioned just after it.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // Reads a line, without its line ending.
+    fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<()> {
+        line.clear();
+        try!((&mut self.inner).take(MAX_LINE as u64 + 2).read_until(b'\n', line));
+        if line.last() != Some(&b'\n') {
+            if line.len() > MAX_LINE {
+                return Err(invalid_data(ChunkedError::LineTooLong));
+            }
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated chunked body"));
+        }
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        if line.len() > MAX_LINE {
+            return Err(invalid_data(ChunkedError::LineTooLong));
+        }
+        Ok(())
+    }
+
+    fn read_size(&mut self) -> io::Result<u64> {
+        let mut line = Vec::new();
+        try!(self.read_line(&mut line));
+        let size = match line.iter().position(|&b| b == b';') {
+            Some(idx) => &line[..idx],
+            None => &line[..],
+        };
+        let size = match str::from_utf8(size) {
+            Ok(s) => s.trim(),
+            Err(_) => return Err(invalid_data(ChunkedError::InvalidChunkSize)),
+        };
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid_data(ChunkedError::InvalidChunkSize));
+        }
+        u64::from_str_radix(size, 16).map_err(|_| invalid_data(ChunkedError::InvalidChunkSize))
+    }
+
+    fn read_trailers(&mut self) -> io::Result<()> {
+        let mut line = Vec::new();
+        loop {
+            try!(self.read_line(&mut line));
+            if line.is_empty() {
+                return Ok(());
+            }
+            let field = match str::from_utf8(&line) {
+                Ok(s) => s,
+                Err(_) => return Err(invalid_data(ChunkedError::InvalidTrailer)),
+            };
+            let colon = match field.find(':') {
+                Some(idx) if valid_name(&field[..idx]) => idx,
+                _ => return Err(invalid_data(ChunkedError::InvalidTrailer)),
+            };
+            if self.trailers.len() == MAX_TRAILERS {
+                return Err(invalid_data(ChunkedError::TooManyTrailers));
+            }
+            self.trailers.push((field[..colon].to_string(), field[colon + 1..].trim().to_string()));
+        }
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.state {
+                State::Done => return Ok(0),
+                State::Size => {
+                    let size = try!(self.read_size());
+                    if size == 0 {
+                        try!(self.read_trailers());
+                        self.state = State::Done;
+                    } else {
+                        self.state = State::Data(size);
+                    }
+                }
+                State::Data(remaining) => {
+                    if buf.is_empty() {
+                        return Ok(0);
+                    }
+                    let n = {
+                        let available = try!(self.inner.fill_buf());
+                        if available.is_empty() {
+                            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                      "truncated chunked body"));
+                        }
+                        let n = [buf.len() as u64, available.len() as u64, remaining]
+                            .iter().cloned().min().unwrap() as usize;
+                        buf[..n].copy_from_slice(&available[..n]);
+                        n
+                    };
+                    self.inner.consume(n);
+                    self.state = if n as u64 == remaining {
+                        State::DataEnd
+                    } else {
+                        State::Data(remaining - n as u64)
+                    };
+                    return Ok(n);
+                }
+                State::DataEnd => {
+                    let mut line = Vec::new();
+                    try!(self.read_line(&mut line));
+                    if !line.is_empty() {
+                        return Err(invalid_data(ChunkedError::MissingLineEnding));
+                    }
+                    self.state = State::Size;
+                }
+            }
+        }
+    }
+}
+
+// Whether `name` is a valid field name: one or more token characters.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| match b {
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' | b'.' |
+        b'^' | b'_' | b'`' | b'|' | b'~' => true,
+        b => (b as char).is_alphanumeric() && b < 0x80,
+    })
+}
+
+/// A writer producing a chunked body, one chunk per `write` call.
+///
+/// Wrap it in a `BufWriter` to avoid many small chunks. Call `finish` or
+/// `finish_with_trailers` at the end to write the last chunk. If the writer
+/// is dropped instead, the last chunk is written on a best-effort basis
+/// and errors are ignored.
+pub struct ChunkedWriter<W: Write> {
+    inner: Option<W>,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    /// Creates a new writer producing a chunked body in `inner`.
+    pub fn new(inner: W) -> ChunkedWriter<W> {
+        ChunkedWriter { inner: Some(inner) }
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Writes the last chunk and returns the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        self.finish_with_trailers(&[])
+    }
+
+    /// Writes the last chunk followed by the trailer fields `trailers`,
+    /// given as `(name, value)` pairs, and returns the underlying writer.
+    ///
+    /// Fails with an `InvalidInput` error wrapping
+    /// `ChunkedError::InvalidTrailer`, writing nothing, if a name is not a
+    /// valid field name or a value contains a line break.
+    pub fn finish_with_trailers(mut self, trailers: &[(&str, &str)]) -> io::Result<W> {
+        let mut inner = self.inner.take().unwrap();
+        for &(name, value) in trailers {
+            if !valid_name(name) || value.contains(&['\r', '\n'][..]) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          ChunkedError::InvalidTrailer));
+            }
+        }
+        try!(write_final(&mut inner, trailers));
+        Ok(inner)
+    }
+
+    /// Unwraps this writer without writing the last chunk, returning the
+    /// underlying writer.
+    pub fn into_inner(mut self) -> W {
+        self.inner.take().unwrap()
+    }
+}
+
+fn write_final<W: Write>(inner: &mut W, trailers: &[(&str, &str)]) -> io::Result<()> {
+    try!(inner.write_all(b"0\r\n"));
+    for &(name, value) in trailers {
+        try!(write!(inner, "{}: {}\r\n", name, value));
+    }
+    try!(inner.write_all(b"\r\n"));
+    inner.flush()
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // An empty chunk would end the body.
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let inner = self.inner.as_mut().unwrap();
+        try!(write!(inner, "{:x}\r\n", buf.len()));
+        try!(inner.write_all(buf));
+        try!(inner.write_all(b"\r\n"));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for ChunkedWriter<W> {
+    fn drop(&mut self) {
+        if let Some(ref mut inner) = self.inner {
+            let _ = write_final(inner, &[]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::prelude::*;
+    use chunked::{ChunkedError, ChunkedReader, ChunkedWriter, MAX_LINE, MAX_TRAILERS};
+
+    type Trailers = Vec<(String, String)>;
+
+    fn decode(body: &[u8]) -> io::Result<(Vec<u8>, Trailers)> {
+        // A one-byte buffer exercises chunks split across reads.
+        let mut reader = ChunkedReader::new(io::BufReader::with_capacity(1, body));
+        let mut out = Vec::new();
+        try!(reader.read_to_end(&mut out));
+        Ok((out, reader.trailers().to_vec()))
+    }
+
+    fn error(body: &[u8]) -> Option<ChunkedError> {
+        ChunkedError::from_io_error(&decode(body).unwrap_err())
+    }
+
+    #[test]
+    fn test_reader() {
+        let (data, trailers) = decode(b"4\r\nWiki\r\n5;ext=1\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n\
+                                        0\r\nX-A: 1\r\nX-B:  two \n\r\n").unwrap();
+        assert_eq!(data, b"Wikipedia in\r\n\r\nchunks.");
+        assert_eq!(trailers, [("X-A".to_string(), "1".to_string()),
+                              ("X-B".to_string(), "two".to_string())]);
+
+        let mut rest = &b"3\r\nabc\r\n0\r\n\r\nNEXT"[..];
+        let mut out = Vec::new();
+        {
+            let mut reader = ChunkedReader::new(&mut rest);
+            reader.read_to_end(&mut out).unwrap();
+            assert!(reader.is_finished());
+        }
+        assert_eq!((&out[..], rest), (&b"abc"[..], &b"NEXT"[..]));
+
+        assert_eq!(error(b"x\r\n"), Some(ChunkedError::InvalidChunkSize));
+        assert_eq!(error(b"+3\r\nabc\r\n0\r\n\r\n"), Some(ChunkedError::InvalidChunkSize));
+        assert_eq!(error(b"11112222333344445\r\n"), Some(ChunkedError::InvalidChunkSize));
+        assert_eq!(error(b"3\r\nabcd\r\n"), Some(ChunkedError::MissingLineEnding));
+        assert_eq!(error(b"0\r\nbad trailer\r\n\r\n"), Some(ChunkedError::InvalidTrailer));
+        let long = vec![b'0'; MAX_LINE + 1];
+        assert_eq!(error(&long), Some(ChunkedError::LineTooLong));
+        let mut many = b"0\r\n".to_vec();
+        for _ in 0..MAX_TRAILERS + 1 {
+            many.extend_from_slice(b"X: 1\r\n");
+        }
+        many.extend_from_slice(b"\r\n");
+        assert_eq!(error(&many), Some(ChunkedError::TooManyTrailers));
+        for truncated in [&b""[..], b"3\r\nab", b"3\r\nabc", b"0\r\n"].iter() {
+            assert_eq!(decode(truncated).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn test_writer() {
+        let mut writer = ChunkedWriter::new(Vec::new());
+        writer.write_all(&[b'x'; 26]).unwrap();
+        writer.write_all(b"").unwrap();
+        let body = writer.finish().unwrap();
+        let mut expected = b"1a\r\n".to_vec();
+        expected.extend_from_slice(&[b'x'; 26]);
+        expected.extend_from_slice(b"\r\n0\r\n\r\n");
+        assert_eq!(body, expected);
+        assert_eq!(decode(&body).unwrap().0, &[b'x'; 26][..]);
+
+        let mut body = Vec::new();
+        {
+            let mut writer = ChunkedWriter::new(&mut body);
+            writer.write_all(b"ab").unwrap();
+        }
+        assert_eq!(body, b"2\r\nab\r\n0\r\n\r\n");
+
+        for trailers in [&[("Bad Name", "v")][..], &[("Name", "a\r\nb")][..]].iter() {
+            let writer = ChunkedWriter::new(Vec::new());
+            let err = writer.finish_with_trailers(trailers).unwrap_err();
+            assert_eq!(ChunkedError::from_io_error(&err), Some(ChunkedError::InvalidTrailer));
+        }
+    }
+}
@@ -0,0 +1,565 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Streaming byte-transform codecs
+//!
+//! A `Codec` only needs to know how to transform one chunk of bytes at a
+//! time; `CodecReader` and `CodecWriter` take care of adapting it to the
+//! `Read` and `Write` traits.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use std::io::{Read, Write};
+//! use rustc_serialize::codec::{CodecReader, CodecWriter};
+//! use rustc_serialize::hex::HexCodec;
+//!
+//! fn main() {
+//!     let mut writer = CodecWriter::new(Vec::new(), HexCodec::new());
+//!     writer.write_all(b"foo").unwrap();
+//!     writer.write_all(b"bar").unwrap();
+//!     let encoded = writer.finish().unwrap();
+//!     assert_eq!(encoded, b"666f6f626172");
+//!
+//!     let mut decoded = Vec::new();
+//!     let mut reader = CodecReader::new(&encoded[..], HexCodec::new());
+//!     reader.read_to_end(&mut decoded).unwrap();
+//!     assert_eq!(decoded, b"foobar");
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+use progress::Progress;
+
+/// A chunk-at-a-time byte transform.
+///
+/// A value implementing this trait should be used in a single direction:
+/// either a series of `encode_chunk` calls or a series of `decode_chunk`
+/// calls, followed by one call to `finish`. Implementations may buffer
+/// incomplete blocks between calls.
+pub trait Codec {
+    /// The error type for decoding failures.
+    type Error: error::Error + Send + Sync + 'static;
+
+    /// Encodes `input`, appending the result to `output`.
+    fn encode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), Self::Error>;
+
+    /// Decodes `input`, appending the result to `output`.
+    fn decode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), Self::Error>;
+
+    /// Signals the end of the stream, appending anything still buffered
+    /// (such as padding) to `output`.
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Self::Error>;
+
+    /// Creates a pipeline which encodes with `self` and then with `next`,
+    /// and decodes in the reverse order.
+    fn chain<C: Codec>(self, next: C) -> Pipeline<Self, C> where Self: Sized {
+        Pipeline::new(self, next)
+    }
+}
+
+impl<C: ?Sized + Codec> Codec for &mut C {
+    type Error = C::Error;
+
+    fn encode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), C::Error> {
+        (**self).encode_chunk(input, output)
+    }
+
+    fn decode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), C::Error> {
+        (**self).decode_chunk(input, output)
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), C::Error> {
+        (**self).finish(output)
+    }
+}
+
+/// Two codecs applied one after the other, created by `Codec::chain`.
+///
+/// When encoding, data passes through the first codec and then the second;
+/// when decoding it passes through the second and then the first. Longer
+/// pipelines are built by chaining further, e.g. `a.chain(b).chain(c)`.
+/// Each stage shares a single intermediate buffer which is reused across
+/// chunks.
+pub struct Pipeline<A, B> {
+    first: A,
+    second: B,
+    buf: Vec<u8>,
+    direction: Option<Direction>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Encode,
+    Decode,
+}
+
+impl<A: Codec, B: Codec> Pipeline<A, B> {
+    /// Creates a pipeline encoding with `first` and then `second`.
+    pub fn new(first: A, second: B) -> Pipeline<A, B> {
+        Pipeline { first: first, second: second, buf: Vec::new(), direction: None }
+    }
+
+    /// Unwraps this pipeline, returning the two stages.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+/// The error type of a `Pipeline`, identifying the stage which failed.
+#[derive(Debug)]
+pub enum PipelineError<A, B> {
+    /// The first codec in the pipeline failed
+    First(A),
+    /// The second codec in the pipeline failed
+    Second(B),
+}
+
+impl<A: fmt::Display, B: fmt::Display> fmt::Display for PipelineError<A, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PipelineError::First(ref e) => e.fmt(f),
+            PipelineError::Second(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl<A: error::Error, B: error::Error> error::Error for PipelineError<A, B> {
+    fn description(&self) -> &str {
+        match *self {
+            PipelineError::First(ref e) => e.description(),
+            PipelineError::Second(ref e) => e.description(),
+        }
+    }
+}
+
+impl<A: Codec, B: Codec> Codec for Pipeline<A, B> {
+    type Error = PipelineError<A::Error, B::Error>;
+
+    fn encode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), Self::Error> {
+        self.direction = Some(Direction::Encode);
+        self.buf.clear();
+        try!(self.first.encode_chunk(input, &mut self.buf).map_err(PipelineError::First));
+        self.second.encode_chunk(&self.buf, output).map_err(PipelineError::Second)
+    }
+
+    fn decode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), Self::Error> {
+        self.direction = Some(Direction::Decode);
+        self.buf.clear();
+        try!(self.second.decode_chunk(input, &mut self.buf).map_err(PipelineError::Second));
+        self.first.decode_chunk(&self.buf, output).map_err(PipelineError::First)
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.buf.clear();
+        if self.direction == Some(Direction::Decode) {
+            try!(self.second.finish(&mut self.buf).map_err(PipelineError::Second));
+            try!(self.first.decode_chunk(&self.buf, output).map_err(PipelineError::First));
+            self.first.finish(output).map_err(PipelineError::First)
+        } else {
+            try!(self.first.finish(&mut self.buf).map_err(PipelineError::First));
+            try!(self.second.encode_chunk(&self.buf, output).map_err(PipelineError::Second));
+            self.second.finish(output).map_err(PipelineError::Second)
+        }
+    }
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+    where E: error::Error + Send + Sync + 'static
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// A reader decoding the contents of the underlying reader with a `Codec`.
+///
+/// Decoding errors are reported as `io::Error`s of kind `InvalidData`
+/// wrapping the codec's error.
+pub struct CodecReader<C, R> {
+    inner: R,
+    codec: C,
+    in_buf: Vec<u8>,
+    out_buf: Vec<u8>,
+    out_pos: usize,
+    done: bool,
+    consumed: u64,
+    produced: u64,
+    progress: Option<Progress>,
+}
+
+impl<C: Codec, R: Read> CodecReader<C, R> {
+    /// Creates a new reader decoding the output of `inner`.
+    pub fn new(inner: R, codec: C) -> CodecReader<C, R> {
+        CodecReader {
+            inner: inner,
+            codec: codec,
+            in_buf: vec![0; 4096],
+            out_buf: Vec::new(),
+            out_pos: 0,
+            done: false,
+            consumed: 0,
+            produced: 0,
+            progress: None,
+        }
+    }
+
+    /// Returns a reference to the codec.
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// Returns the number of bytes read from the underlying reader so far.
+    pub fn bytes_consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    /// Returns the number of decoded bytes returned by `read` so far.
+    pub fn bytes_produced(&self) -> u64 {
+        self.produced
+    }
+
+    /// Calls `callback` with `bytes_consumed()` each time another
+    /// `every_n_bytes` bytes have been read from the underlying reader.
+    pub fn progress<F>(&mut self, callback: F, every_n_bytes: u64)
+        where F: FnMut(u64) + Send + 'static
+    {
+        self.progress = Some(Progress::new(every_n_bytes, callback));
+    }
+
+    /// Unwraps this reader, returning the underlying reader. Any decoded
+    /// but unread data is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        self.out_buf.clear();
+        self.out_pos = 0;
+        while self.out_buf.is_empty() && !self.done {
+            let n = try!(self.inner.read(&mut self.in_buf));
+            self.consumed += n as u64;
+            if let Some(ref mut progress) = self.progress {
+                progress.update(self.consumed);
+            }
+            if n == 0 {
+                self.done = true;
+                try!(self.codec.finish(&mut self.out_buf).map_err(invalid_data));
+            } else {
+                try!(self.codec.decode_chunk(&self.in_buf[..n], &mut self.out_buf)
+                               .map_err(invalid_data));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<C: Codec, R: Read> Read for CodecReader<C, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.out_pos == self.out_buf.len() {
+            try!(self.fill());
+        }
+        let avail = &self.out_buf[self.out_pos..];
+        let n = if avail.len() < buf.len() { avail.len() } else { buf.len() };
+        buf[..n].copy_from_slice(&avail[..n]);
+        self.out_pos += n;
+        self.produced += n as u64;
+        Ok(n)
+    }
+}
+
+/// A writer encoding everything written to it with a `Codec` before
+/// passing it on.
+///
+/// Call `finish` once all data has been written so the codec can emit its
+/// final block. If the writer is dropped instead, the final block is written
+/// on a best-effort basis and errors are ignored.
+pub struct CodecWriter<C: Codec, W: Write> {
+    inner: Option<W>,
+    codec: C,
+    buf: Vec<u8>,
+    consumed: u64,
+    produced: u64,
+    progress: Option<Progress>,
+}
+
+impl<C: Codec, W: Write> CodecWriter<C, W> {
+    /// Creates a new writer encoding into `inner`.
+    pub fn new(inner: W, codec: C) -> CodecWriter<C, W> {
+        CodecWriter {
+            inner: Some(inner),
+            codec: codec,
+            buf: Vec::new(),
+            consumed: 0,
+            produced: 0,
+            progress: None,
+        }
+    }
+
+    /// Returns a reference to the codec.
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// Returns the number of bytes written to this writer so far.
+    pub fn bytes_consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    /// Returns the number of encoded bytes passed to the underlying writer
+    /// so far.
+    pub fn bytes_produced(&self) -> u64 {
+        self.produced
+    }
+
+    /// Calls `callback` with `bytes_consumed()` each time another
+    /// `every_n_bytes` bytes have been written.
+    pub fn progress<F>(&mut self, callback: F, every_n_bytes: u64)
+        where F: FnMut(u64) + Send + 'static
+    {
+        self.progress = Some(Progress::new(every_n_bytes, callback));
+    }
+
+    /// Writes the codec's final block and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        // Taken first so that a failure is not retried on drop.
+        let mut inner = self.inner.take().unwrap();
+        try!(self.write_final(&mut inner));
+        Ok(inner)
+    }
+
+    /// Unwraps this writer without finishing the codec, returning the
+    /// underlying writer.
+    pub fn into_inner(mut self) -> W {
+        self.inner.take().unwrap()
+    }
+
+    fn write_final(&mut self, inner: &mut W) -> io::Result<()> {
+        self.buf.clear();
+        try!(self.codec.finish(&mut self.buf).map_err(invalid_data));
+        try!(inner.write_all(&self.buf));
+        self.produced += self.buf.len() as u64;
+        inner.flush()
+    }
+}
+
+impl<C: Codec, W: Write> Write for CodecWriter<C, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.clear();
+        try!(self.codec.encode_chunk(buf, &mut self.buf).map_err(invalid_data));
+        try!(self.inner.as_mut().unwrap().write_all(&self.buf));
+        self.consumed += buf.len() as u64;
+        self.produced += self.buf.len() as u64;
+        if let Some(ref mut progress) = self.progress {
+            progress.update(self.consumed);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl<C: Codec, W: Write> Drop for CodecWriter<C, W> {
+    fn drop(&mut self) {
+        if let Some(mut inner) = self.inner.take() {
+            let _ = self.write_final(&mut inner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::prelude::*;
+    use codec::{Codec, CodecReader, CodecWriter};
+    use base64::{Base64Codec, Config, STANDARD, MIME};
+    use base64::{FromBase64, ToBase64};
+    use hex::{HexCodec, ToHex};
+
+    // A reader handing out at most one byte per call.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() { return Ok(0) }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    fn encode_in_pieces(data: &[u8], config: Config, piece: usize) -> String {
+        let mut w = CodecWriter::new(Vec::new(), Base64Codec::new(config));
+        for chunk in data.chunks(piece) {
+            w.write_all(chunk).unwrap();
+        }
+        String::from_utf8(w.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_base64_writer_matches_to_base64() {
+        let data: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        for &config in [STANDARD, MIME, Config { pad: false, ..STANDARD }].iter() {
+            for piece in 1..8 {
+                for len in 0..20 {
+                    assert_eq!(encode_in_pieces(&data[..len], config, piece),
+                               data[..len].to_base64(config));
+                }
+                assert_eq!(encode_in_pieces(&data, config, piece),
+                           data.to_base64(config));
+            }
+        }
+    }
+
+    #[test]
+    fn test_base64_reader_matches_from_base64() {
+        let data: Vec<u8> = (0..200).map(|i| (i * 7) as u8).collect();
+        let encoded = data.to_base64(MIME);
+        let mut v = Vec::new();
+        CodecReader::new(Trickle(encoded.as_bytes()), Base64Codec::new(MIME))
+            .read_to_end(&mut v).unwrap();
+        assert_eq!(v, encoded.from_base64().unwrap());
+    }
+
+    #[test]
+    fn test_base64_reader_errors() {
+        let mut v = Vec::new();
+        let err = CodecReader::new(&b"Zm9v$"[..], Base64Codec::new(STANDARD))
+                      .read_to_end(&mut v).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(CodecReader::new(&b"Z"[..], Base64Codec::new(STANDARD))
+                    .read_to_end(&mut v).is_err());
+    }
+
+    #[test]
+    fn test_hex_roundtrip_trickle() {
+        let encoded = b"foobar".to_hex();
+        let mut v = Vec::new();
+        CodecReader::new(Trickle(encoded.as_bytes()), HexCodec::new())
+            .read_to_end(&mut v).unwrap();
+        assert_eq!(v, b"foobar");
+    }
+
+    #[test]
+    fn test_hex_reader_errors() {
+        let mut v = Vec::new();
+        assert!(CodecReader::new(&b"666"[..], HexCodec::new())
+                    .read_to_end(&mut v).is_err());
+        assert!(CodecReader::new(&b"66y6"[..], HexCodec::new())
+                    .read_to_end(&mut v).is_err());
+    }
+
+    #[test]
+    fn test_writer_finishes_on_drop() {
+        let mut out = Vec::new();
+        {
+            let mut w = CodecWriter::new(&mut out, Base64Codec::new(STANDARD));
+            w.write_all(b"f").unwrap();
+        }
+        assert_eq!(out, b"Zg==");
+    }
+
+    #[test]
+    fn test_failed_finish_is_not_retried() {
+        use std::cell::Cell;
+
+        struct Broken<'a>(&'a Cell<usize>);
+
+        impl<'a> Write for Broken<'a> {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                self.0.set(self.0.get() + 1);
+                Err(io::Error::new(io::ErrorKind::Other, "broken"))
+            }
+            fn flush(&mut self) -> io::Result<()> { Ok(()) }
+        }
+
+        let writes = Cell::new(0);
+        let mut w = CodecWriter::new(Broken(&writes), Base64Codec::new(STANDARD));
+        w.write_all(b"f").unwrap();
+        assert!(w.finish().is_err());
+        assert_eq!(writes.get(), 1);
+    }
+
+    #[test]
+    fn test_counters_and_progress() {
+        use std::sync::{Arc, Mutex};
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let mut writer = CodecWriter::new(Vec::new(), HexCodec::new());
+        let r = reports.clone();
+        writer.progress(move |n| r.lock().unwrap().push(n), 4);
+        for _ in 0..5 {
+            writer.write_all(b"abc").unwrap();
+        }
+        assert_eq!(writer.bytes_consumed(), 15);
+        assert_eq!(writer.bytes_produced(), 30);
+        assert_eq!(*reports.lock().unwrap(), [6, 9, 12]);
+
+        let encoded = writer.finish().unwrap();
+        let mut reader = CodecReader::new(Trickle(&encoded), HexCodec::new());
+        let r = reports.clone();
+        reports.lock().unwrap().clear();
+        reader.progress(move |n| r.lock().unwrap().push(n), 10);
+        let mut decoded = Vec::new();
+        reader.read_to_end(&mut decoded).unwrap();
+        assert_eq!(reader.bytes_consumed(), 30);
+        assert_eq!(reader.bytes_produced(), 15);
+        assert_eq!(*reports.lock().unwrap(), [10, 20, 30]);
+    }
+
+    #[test]
+    fn test_pipeline_roundtrip() {
+        let data: Vec<u8> = (0..100).map(|i| (i * 3) as u8).collect();
+        let mut w = CodecWriter::new(Vec::new(),
+                                     HexCodec::new().chain(Base64Codec::new(MIME)));
+        for chunk in data.chunks(7) {
+            w.write_all(chunk).unwrap();
+        }
+        let encoded = w.finish().unwrap();
+        assert_eq!(encoded, data.to_hex().as_bytes().to_base64(MIME).as_bytes());
+
+        let mut v = Vec::new();
+        CodecReader::new(Trickle(&encoded),
+                         HexCodec::new().chain(Base64Codec::new(MIME)))
+            .read_to_end(&mut v).unwrap();
+        assert_eq!(v, data);
+    }
+
+    #[test]
+    fn test_pipeline_three_stages() {
+        let codec = HexCodec::new().chain(HexCodec::new())
+                                   .chain(Base64Codec::new(STANDARD));
+        let mut w = CodecWriter::new(Vec::new(), codec);
+        w.write_all(b"hi").unwrap();
+        let encoded = w.finish().unwrap();
+        assert_eq!(encoded, b"hi".to_hex().as_bytes().to_hex()
+                                 .as_bytes().to_base64(STANDARD).as_bytes());
+    }
+
+    #[test]
+    fn test_pipeline_error_stage() {
+        let mut v = Vec::new();
+        let codec = HexCodec::new().chain(Base64Codec::new(STANDARD));
+        // Valid base64, but the decoded text is not hex.
+        let input = b"zz".to_base64(STANDARD);
+        assert!(CodecReader::new(input.as_bytes(), codec).read_to_end(&mut v).is_err());
+    }
+}
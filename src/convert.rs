@@ -0,0 +1,458 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! High-level conversion between the formats supported by this crate
+//!
+//! `convert` reads one format and writes another, so command line tools can
+//! be thin wrappers around it (see `examples/convert.rs`).
+//!
+//! Raw bytes, hex and base64 are byte encodings: converting between them
+//! decodes the input to bytes and re-encodes them, streaming. When either
+//! side is JSON, CBOR or MessagePack the content is a JSON document
+//! instead; it is parsed from the (decoded) input and its serialization
+//! becomes the output bytes, so e.g. base64 to JSON expects base64-encoded
+//! JSON text.
+//!
+//! CBOR and MessagePack can only be written, as this crate has no decoder
+//! for either. CBOR is written in its deterministic form (see `cbor`), and
+//! MessagePack with the smallest representation of each integer, floats as
+//! doubles and objects as maps with string keys.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::convert::{convert, Format, Options};
+//!
+//! fn main() {
+//!     let mut out = Vec::new();
+//!     convert(&b"666f6f"[..], Format::Hex, &mut out, Format::Base64,
+//!             &Options::default()).unwrap();
+//!     assert_eq!(out, b"Zm9v");
+//!
+//!     let mut out = Vec::new();
+//!     convert(&b"{ \"a\" : [1, 2] }"[..], Format::Json, &mut out, Format::Json,
+//!             &Options::default()).unwrap();
+//!     assert_eq!(out, b"{\"a\":[1,2]}");
+//!
+//!     let mut out = Vec::new();
+//!     convert(&b"{\"a\": 1}"[..], Format::Json, &mut out, Format::Cbor,
+//!             &Options::default()).unwrap();
+//!     assert_eq!(out, b"\xa1\x61a\x01");
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use base64::{self, Base64Codec, FromBase64Error};
+use byteorder::WriteBytesExt;
+use cbor::{self, CanonicalError};
+use codec::{CodecReader, CodecWriter};
+use hex::{FromHexError, HexCodec};
+use json::{self, Json, ParserError};
+use limit::SizeLimitExceeded;
+
+/// The formats `convert` can read and write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Bytes passed through unchanged
+    Raw,
+    /// Hexadecimal text
+    Hex,
+    /// Base64 text
+    Base64,
+    /// A JSON document
+    Json,
+    /// A JSON document as CBOR, for output only
+    Cbor,
+    /// A JSON document as MessagePack, for output only
+    MsgPack,
+}
+
+impl Format {
+    // Whether the content is a JSON document rather than bytes.
+    fn is_document(self) -> bool {
+        match self {
+            Format::Json | Format::Cbor | Format::MsgPack => true,
+            Format::Raw | Format::Hex | Format::Base64 => false,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "raw" => Ok(Format::Raw),
+            "hex" => Ok(Format::Hex),
+            "base64" => Ok(Format::Base64),
+            "json" => Ok(Format::Json),
+            "cbor" => Ok(Format::Cbor),
+            "msgpack" => Ok(Format::MsgPack),
+            _ => Err(format!("unknown format `{}`, expected one of raw, hex, \
+                              base64, json, cbor, msgpack", s)),
+        }
+    }
+}
+
+/// Contains options for `convert`.
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    /// True to pretty-print JSON output
+    pub pretty: bool,
+    /// Configuration for base64 output
+    pub base64: base64::Config,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { pretty: false, base64: base64::STANDARD }
+    }
+}
+
+/// Errors that can occur during `convert`.
+#[derive(Debug)]
+pub enum ConvertError {
+    /// Reading or writing failed
+    IoError(io::Error),
+    /// The input was not valid hex
+    HexError(FromHexError),
+    /// The input was not valid base64
+    Base64Error(FromBase64Error),
+    /// The input was not valid JSON
+    ParseError(ParserError),
+    /// The input format can only be written
+    UnsupportedInput(Format),
+    /// The document had no deterministic CBOR encoding
+    CborError(CanonicalError),
+    /// The input was read from a `limit::LimitedReader` and exceeded its
+    /// limit (in bytes)
+    SizeLimitExceeded(u64),
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConvertError::IoError(ref e) => fmt::Display::fmt(e, f),
+            ConvertError::HexError(ref e) => fmt::Display::fmt(e, f),
+            ConvertError::Base64Error(ref e) => fmt::Display::fmt(e, f),
+            ConvertError::ParseError(ref e) => fmt::Display::fmt(e, f),
+            ConvertError::UnsupportedInput(format) => {
+                write!(f, "{:?} can be written but not read", format)
+            }
+            ConvertError::CborError(ref e) => fmt::Display::fmt(e, f),
+            ConvertError::SizeLimitExceeded(limit) => {
+                fmt::Display::fmt(&SizeLimitExceeded { limit: limit }, f)
+            }
+        }
+    }
+}
+
+impl error::Error for ConvertError {
+    fn description(&self) -> &str {
+        match *self {
+            ConvertError::IoError(ref e) => e.description(),
+            ConvertError::HexError(ref e) => e.description(),
+            ConvertError::Base64Error(ref e) => e.description(),
+            ConvertError::ParseError(ref e) => e.description(),
+            ConvertError::UnsupportedInput(_) => "unsupported input format",
+            ConvertError::CborError(ref e) => e.description(),
+            ConvertError::SizeLimitExceeded(_) => "size limit exceeded",
+        }
+    }
+}
+
+impl From<io::Error> for ConvertError {
+    // The codec readers report decoding errors as `InvalidData` I/O errors;
+    // unwrap those back into the specific error.
+    fn from(err: io::Error) -> ConvertError {
+        if let Some(e) = err.get_ref().and_then(|e| e.downcast_ref::<FromHexError>()) {
+            return ConvertError::HexError(*e);
+        }
+        if let Some(e) = err.get_ref().and_then(|e| e.downcast_ref::<FromBase64Error>()) {
+            return ConvertError::Base64Error(*e);
+        }
+        if let Some(e) = SizeLimitExceeded::from_io_error(&err) {
+            return ConvertError::SizeLimitExceeded(e.limit);
+        }
+        ConvertError::IoError(err)
+    }
+}
+
+impl From<CanonicalError> for ConvertError {
+    fn from(err: CanonicalError) -> ConvertError {
+        ConvertError::CborError(err)
+    }
+}
+
+impl From<ParserError> for ConvertError {
+    fn from(err: ParserError) -> ConvertError {
+        match err {
+            ParserError::IoError(e) => From::from(e),
+            ParserError::SizeLimitExceeded(limit) => ConvertError::SizeLimitExceeded(limit),
+            e => ConvertError::ParseError(e),
+        }
+    }
+}
+
+/// Reads `input` in the format `from` and writes it to `output` in the
+/// format `to`.
+pub fn convert<R: Read, W: Write>(input: R, from: Format, output: W, to: Format,
+                                  opts: &Options) -> Result<(), ConvertError> {
+    let json = from == Format::Json || to.is_document();
+    match from {
+        Format::Raw | Format::Json => convert_decoded(input, json, output, to, opts),
+        Format::Cbor | Format::MsgPack => Err(ConvertError::UnsupportedInput(from)),
+        Format::Hex => {
+            let input = CodecReader::new(input, HexCodec::new());
+            convert_decoded(input, json, output, to, opts)
+        }
+        Format::Base64 => {
+            let input = CodecReader::new(input, Base64Codec::new(opts.base64));
+            convert_decoded(input, json, output, to, opts)
+        }
+    }
+}
+
+fn convert_decoded<R: Read, W: Write>(mut input: R, json: bool, mut output: W, to: Format,
+                                      opts: &Options) -> Result<(), ConvertError> {
+    if !json {
+        return encode_bytes(input, output, to, opts);
+    }
+    let doc = try!(Json::from_reader(&mut input));
+    let bytes = match to {
+        Format::Cbor => try!(cbor::Value::from_json(&doc).to_canonical()),
+        Format::MsgPack => {
+            let mut bytes = Vec::new();
+            try!(write_msgpack(&doc, &mut bytes));
+            bytes
+        }
+        _ if opts.pretty => json::as_pretty_json(&doc).to_string().into_bytes(),
+        _ => json::as_json(&doc).to_string().into_bytes(),
+    };
+    match to {
+        Format::Raw | Format::Hex | Format::Base64 => encode_bytes(&bytes[..], output, to, opts),
+        Format::Json | Format::Cbor | Format::MsgPack => {
+            output.write_all(&bytes).map_err(From::from)
+        }
+    }
+}
+
+fn encode_bytes<R: Read, W: Write>(mut input: R, mut output: W, to: Format,
+                                   opts: &Options) -> Result<(), ConvertError> {
+    match to {
+        Format::Raw | Format::Json | Format::Cbor | Format::MsgPack => {
+            try!(io::copy(&mut input, &mut output));
+        }
+        Format::Hex => {
+            let mut writer = CodecWriter::new(output, HexCodec::new());
+            try!(io::copy(&mut input, &mut writer));
+            try!(writer.finish());
+        }
+        Format::Base64 => {
+            let mut writer = CodecWriter::new(output, Base64Codec::new(opts.base64));
+            try!(io::copy(&mut input, &mut writer));
+            try!(writer.finish());
+        }
+    }
+    Ok(())
+}
+
+// Writes the MessagePack encoding of `json`.
+fn write_msgpack<W: Write>(json: &Json, out: &mut W) -> io::Result<()> {
+    match *json {
+        Json::Null => out.write_u8(0xc0),
+        Json::Boolean(b) => out.write_u8(if b { 0xc3 } else { 0xc2 }),
+        Json::U64(v) => write_msgpack_uint(v, out),
+        Json::I64(v) if v >= 0 => write_msgpack_uint(v as u64, out),
+        Json::I64(v) if v >= -32 => out.write_i8(v as i8),
+        Json::I64(v) if v >= i8::MIN as i64 => {
+            try!(out.write_u8(0xd0));
+            out.write_i8(v as i8)
+        }
+        Json::I64(v) if v >= i16::MIN as i64 => {
+            try!(out.write_u8(0xd1));
+            out.write_i16_be(v as i16)
+        }
+        Json::I64(v) if v >= i32::MIN as i64 => {
+            try!(out.write_u8(0xd2));
+            out.write_i32_be(v as i32)
+        }
+        Json::I64(v) => {
+            try!(out.write_u8(0xd3));
+            out.write_i64_be(v)
+        }
+        Json::F64(v) => {
+            try!(out.write_u8(0xcb));
+            out.write_f64_be(v)
+        }
+        Json::String(ref s) => write_msgpack_str(s, out),
+        Json::Wtf8(ref s) => write_msgpack_str(&s.to_string_lossy(), out),
+        Json::Array(ref array) => {
+            try!(write_msgpack_len(array.len(), 0x90, 0xdc, out));
+            for elt in array {
+                try!(write_msgpack(elt, out));
+            }
+            Ok(())
+        }
+        Json::Object(ref object) => {
+            try!(write_msgpack_len(object.len(), 0x80, 0xde, out));
+            for (key, value) in object {
+                try!(write_msgpack_str(key, out));
+                try!(write_msgpack(value, out));
+            }
+            Ok(())
+        }
+    }
+}
+
+fn write_msgpack_uint<W: Write>(v: u64, out: &mut W) -> io::Result<()> {
+    if v < 0x80 {
+        out.write_u8(v as u8)
+    } else if v <= 0xff {
+        try!(out.write_u8(0xcc));
+        out.write_u8(v as u8)
+    } else if v <= 0xffff {
+        try!(out.write_u8(0xcd));
+        out.write_u16_be(v as u16)
+    } else if v <= 0xffff_ffff {
+        try!(out.write_u8(0xce));
+        out.write_u32_be(v as u32)
+    } else {
+        try!(out.write_u8(0xcf));
+        out.write_u64_be(v)
+    }
+}
+
+fn write_msgpack_str<W: Write>(s: &str, out: &mut W) -> io::Result<()> {
+    let len = s.len();
+    if len < 32 {
+        try!(out.write_u8(0xa0 | len as u8));
+    } else if len <= 0xff {
+        try!(out.write_u8(0xd9));
+        try!(out.write_u8(len as u8));
+    } else {
+        try!(write_msgpack_len(len, 0, 0xda, out));
+    }
+    out.write_all(s.as_bytes())
+}
+
+// Writes the length of an array, map or string: in the fixed-size form
+// `fix` if it has one and the length fits, or else as a 16- or 32-bit
+// number after `code` or `code + 1`.
+fn write_msgpack_len<W: Write>(len: usize, fix: u8, code: u8, out: &mut W) -> io::Result<()> {
+    if fix != 0 && len < 16 {
+        out.write_u8(fix | len as u8)
+    } else if len <= 0xffff {
+        try!(out.write_u8(code));
+        out.write_u16_be(len as u16)
+    } else if len <= 0xffff_ffff {
+        try!(out.write_u8(code + 1));
+        out.write_u32_be(len as u32)
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidData,
+                           "too long for MessagePack, which limits lengths to 32 bits"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{self, FromBase64Error};
+    use convert::{convert, ConvertError, Format, Options};
+    use hex::{FromHexError, ToHex};
+    use limit::LimitedReader;
+
+    fn run(input: &[u8], from: Format, to: Format, opts: &Options)
+           -> Result<Vec<u8>, ConvertError> {
+        let mut out = Vec::new();
+        try!(convert(input, from, &mut out, to, opts));
+        Ok(out)
+    }
+
+    #[test]
+    fn test_byte_formats() {
+        let opts = Options::default();
+        assert_eq!(run(b"foobar", Format::Raw, Format::Hex, &opts).unwrap(), b"666f6f626172");
+        assert_eq!(run(b"666f6f626172", Format::Hex, Format::Base64, &opts).unwrap(),
+                   b"Zm9vYmFy");
+        assert_eq!(run(b"Zm9vYg==", Format::Base64, Format::Raw, &opts).unwrap(), b"foob");
+
+        let opts = Options { base64: base64::URL_SAFE, ..Options::default() };
+        assert_eq!(run(b"\xfb\xff", Format::Raw, Format::Base64, &opts).unwrap(), b"-_8");
+    }
+
+    #[test]
+    fn test_json() {
+        let opts = Options { pretty: true, ..Options::default() };
+        assert_eq!(run(b"[1,{\"a\":null}]", Format::Json, Format::Json, &opts).unwrap(),
+                   &b"[\n  1,\n  {\n    \"a\": null\n  }\n]"[..]);
+        // Hex-encoded JSON text in, base64-encoded compact JSON text out.
+        let opts = Options::default();
+        assert_eq!(run(b"5b 31 2c 20 32 5d", Format::Hex, Format::Json, &opts).unwrap(),
+                   b"[1,2]");
+        assert_eq!(run(b"[1, 2]", Format::Json, Format::Base64, &opts).unwrap(),
+                   b"WzEsMl0=");
+    }
+
+    #[test]
+    fn test_binary_documents() {
+        let opts = Options::default();
+        let doc = b"{\"b\": [true, null, -1.5], \"a\": 1000}";
+        assert_eq!(run(doc, Format::Json, Format::Cbor, &opts).unwrap().to_hex(),
+                   "a261611903e8616283f5f6f9be00");
+        assert_eq!(run(doc, Format::Json, Format::MsgPack, &opts).unwrap().to_hex(),
+                   "82a161cd03e8a16293c3c0cbbff8000000000000");
+        // Hex-encoded JSON text in, CBOR out.
+        assert_eq!(run(b"5b2d315d", Format::Hex, Format::Cbor, &opts).unwrap(), b"\x81\x20");
+
+        let ints = b"[0, 127, 128, 65536, -32, -33, -129, -32769, -2147483649, \
+                     18446744073709551615]";
+        assert_eq!(run(ints, Format::Json, Format::MsgPack, &opts).unwrap().to_hex(),
+                   "9a007fcc80ce00010000e0d0dfd1ff7fd2ffff7fff\
+                    d3ffffffff7fffffffcfffffffffffffffff");
+        let long = format!("[\"{}\"]", "x".repeat(300));
+        let packed = run(long.as_bytes(), Format::Json, Format::MsgPack, &opts).unwrap();
+        assert_eq!(&packed[..4], b"\x91\xda\x01\x2c");
+        assert_eq!(packed.len(), 304);
+    }
+
+    #[test]
+    fn test_errors() {
+        let opts = Options::default();
+        match run(b"66x", Format::Hex, Format::Raw, &opts) {
+            Err(ConvertError::HexError(FromHexError::InvalidHexCharacter('x', 2))) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match run(b"Zm9v!", Format::Base64, Format::Raw, &opts) {
+            Err(ConvertError::Base64Error(FromBase64Error::InvalidBase64Byte(b'!', 4))) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match run(b"[1,", Format::Json, Format::Hex, &opts) {
+            Err(ConvertError::ParseError(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let input = LimitedReader::new(&b"5b312c20325d"[..], 4);
+        match convert(input, Format::Hex, &mut Vec::new(), Format::Json, &opts) {
+            Err(ConvertError::SizeLimitExceeded(4)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match run(b"\xa0", Format::Cbor, Format::Json, &opts) {
+            Err(ConvertError::UnsupportedInput(Format::Cbor)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!("xml".parse::<Format>().is_err());
+        assert_eq!("base64".parse::<Format>().unwrap(), Format::Base64);
+        assert_eq!("msgpack".parse::<Format>().unwrap(), Format::MsgPack);
+    }
+}
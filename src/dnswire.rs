@@ -0,0 +1,497 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! DNS names in wire format
+//!
+//! In a DNS message a name is a series of labels, each a length byte and
+//! up to 63 bytes, ended by the empty label of the root. A name may instead
+//! end with a pointer to an earlier offset of the message, where the rest
+//! of the name is written (RFC 1035, section 4.1.4). `NameEncoder` writes
+//! names with such pointers to the names it has written before, and
+//! `decode_name` follows them.
+//!
+//! Names are text in the presentation format of RFC 1035: labels separated
+//! by `.`, with `\.`, `\\` and `\DDD` escaping a dot, a backslash and any
+//! other byte in a label. The root is `.`, and a trailing dot is optional.
+//!
+//! Internationalized names are written in DNS as labels of punycode (RFC
+//! 3492) after `xn--`. `to_ascii` and `to_unicode` convert between the two,
+//! label by label, but do not apply the case folding and normalization of
+//! IDNA, which the names passed to `to_ascii` should already have.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::dnswire::{self, NameEncoder};
+//!
+//! fn main() {
+//!     let name = dnswire::to_ascii("bücher.example").unwrap();
+//!     assert_eq!(name, "xn--bcher-kva.example");
+//!
+//!     let mut message = vec![0; 12];
+//!     let mut encoder = NameEncoder::new();
+//!     encoder.encode(&mut message, &name).unwrap();
+//!     encoder.encode(&mut message, "www.example").unwrap();
+//!     assert_eq!(&message[35..], b"\x03www\xc0\x1a");
+//!
+//!     let (www, end) = dnswire::decode_name(&message, 35).unwrap();
+//!     assert_eq!((&www[..], end), ("www.example", 41));
+//!     let (first, _) = dnswire::decode_name(&message, 12).unwrap();
+//!     assert_eq!(dnswire::to_unicode(&first).unwrap(), "bücher.example");
+//! }
+//! ```
+
+use std::char;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+/// The longest label, in bytes.
+pub const MAX_LABEL: usize = 63;
+
+/// The longest name in wire format, in bytes.
+pub const MAX_NAME: usize = 255;
+
+/// Errors from encoding and decoding names
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DnsError {
+    /// A label other than the root was empty
+    EmptyLabel,
+    /// A label was longer than `MAX_LABEL` bytes
+    LabelTooLong,
+    /// A name was longer than `MAX_NAME` bytes in wire format
+    NameTooLong,
+    /// A `\` escape in a name was malformed
+    InvalidEscape,
+    /// A name ran past the end of the message
+    Truncated,
+    /// A pointer did not point before the label holding it
+    InvalidPointer,
+    /// A label had the reserved types 0x40 or 0x80 in its length byte
+    InvalidLabelType,
+    /// A label after `xn--` was not valid punycode, or encoding a label as
+    /// punycode overflowed
+    InvalidPunycode,
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DnsError::EmptyLabel => write!(f, "empty label"),
+            DnsError::LabelTooLong => write!(f, "label longer than {} bytes", MAX_LABEL),
+            DnsError::NameTooLong => write!(f, "name longer than {} bytes", MAX_NAME),
+            DnsError::InvalidEscape => write!(f, "invalid escape in name"),
+            DnsError::Truncated => write!(f, "name runs past the end of the message"),
+            DnsError::InvalidPointer => write!(f, "invalid compression pointer"),
+            DnsError::InvalidLabelType => write!(f, "reserved label type"),
+            DnsError::InvalidPunycode => write!(f, "invalid punycode"),
+        }
+    }
+}
+
+impl error::Error for DnsError {
+    fn description(&self) -> &str { "invalid DNS name" }
+}
+
+/// Encodes `name` in wire format, without compression.
+pub fn encode_name(name: &str) -> Result<Vec<u8>, DnsError> {
+    let labels = try!(parse_name(name));
+    let mut out = Vec::new();
+    for label in &labels {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label);
+    }
+    out.push(0);
+    Ok(out)
+}
+
+/// Writes names into a message, replacing the end of each name with a
+/// pointer to an earlier name of the message ending the same way.
+///
+/// Labels are compared ignoring ASCII case, as DNS does. An encoder should
+/// be used for a single message, as the offsets it keeps are offsets into
+/// that message.
+pub struct NameEncoder {
+    // The offsets of the suffixes already written, keyed by their
+    // lowercased labels.
+    offsets: HashMap<Vec<Vec<u8>>, u16>,
+}
+
+impl NameEncoder {
+    /// Creates an encoder for a message with no names written yet.
+    pub fn new() -> NameEncoder {
+        NameEncoder { offsets: HashMap::new() }
+    }
+
+    /// Appends `name` to `message`, compressed when it ends like a name
+    /// written before.
+    pub fn encode(&mut self, message: &mut Vec<u8>, name: &str) -> Result<(), DnsError> {
+        let labels = try!(parse_name(name));
+        let lower: Vec<Vec<u8>> = labels.iter().map(|l| l.to_ascii_lowercase()).collect();
+        for i in 0..labels.len() {
+            if let Some(&offset) = self.offsets.get(&lower[i..]) {
+                message.push(0xc0 | (offset >> 8) as u8);
+                message.push(offset as u8);
+                return Ok(());
+            }
+            // Pointers only reach the first 16 KiB of a message.
+            if message.len() < 0x4000 {
+                self.offsets.insert(lower[i..].to_vec(), message.len() as u16);
+            }
+            message.push(labels[i].len() as u8);
+            message.extend_from_slice(&labels[i]);
+        }
+        message.push(0);
+        Ok(())
+    }
+}
+
+impl Default for NameEncoder {
+    fn default() -> NameEncoder { NameEncoder::new() }
+}
+
+/// Decodes the name at `offset` in `message`, following pointers. Returns
+/// the name in presentation format, without a trailing dot except for the
+/// root, and the offset after the name where it was first read.
+pub fn decode_name(message: &[u8], offset: usize) -> Result<(String, usize), DnsError> {
+    let mut name = String::new();
+    let mut wire_len = 1;
+    let mut pos = offset;
+    let mut end = None;
+    // Each pointer must point before the label holding it, so that
+    // following them ends.
+    let mut limit = offset;
+    loop {
+        let len = *try!(message.get(pos).ok_or(DnsError::Truncated)) as usize;
+        match len & 0xc0 {
+            0xc0 => {
+                let low = *try!(message.get(pos + 1).ok_or(DnsError::Truncated)) as usize;
+                let target = (len & 0x3f) << 8 | low;
+                if end.is_none() {
+                    end = Some(pos + 2);
+                }
+                if target >= limit {
+                    return Err(DnsError::InvalidPointer);
+                }
+                limit = target;
+                pos = target;
+            }
+            0x00 => {
+                if len == 0 {
+                    break;
+                }
+                let label = try!(message.get(pos + 1..pos + 1 + len).ok_or(DnsError::Truncated));
+                wire_len += len + 1;
+                if wire_len > MAX_NAME {
+                    return Err(DnsError::NameTooLong);
+                }
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                push_label(&mut name, label);
+                pos += len + 1;
+            }
+            _ => return Err(DnsError::InvalidLabelType),
+        }
+    }
+    if name.is_empty() {
+        name.push('.');
+    }
+    Ok((name, end.unwrap_or(pos + 1)))
+}
+
+/// Converts a name to ASCII, encoding each label holding other characters
+/// as `xn--` and its punycode.
+pub fn to_ascii(name: &str) -> Result<String, DnsError> {
+    let mut labels = Vec::new();
+    for label in name.split('.') {
+        if label.is_ascii() {
+            labels.push(label.to_string());
+        } else {
+            labels.push(format!("xn--{}", try!(punycode_encode(label))));
+        }
+    }
+    Ok(labels.join("."))
+}
+
+/// Converts a name to Unicode, decoding each label beginning with `xn--`,
+/// in any case, from punycode.
+pub fn to_unicode(name: &str) -> Result<String, DnsError> {
+    let mut labels = Vec::new();
+    for label in name.split('.') {
+        if label.as_bytes().get(..4).map_or(false, |p| p.eq_ignore_ascii_case(b"xn--")) {
+            labels.push(try!(punycode_decode(&label[4..])));
+        } else {
+            labels.push(label.to_string());
+        }
+    }
+    Ok(labels.join("."))
+}
+
+const BASE: u32 = 36;
+const T_MIN: u32 = 1;
+const T_MAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 128;
+
+fn adapt(delta: u32, points: u32, first: bool) -> u32 {
+    let mut delta = if first { delta / DAMP } else { delta / 2 };
+    delta += delta / points;
+    let mut k = 0;
+    while delta > ((BASE - T_MIN) * T_MAX) / 2 {
+        delta /= BASE - T_MIN;
+        k += BASE;
+    }
+    k + (BASE - T_MIN + 1) * delta / (delta + SKEW)
+}
+
+fn threshold(k: u32, bias: u32) -> u32 {
+    if k <= bias {
+        T_MIN
+    } else if k >= bias + T_MAX {
+        T_MAX
+    } else {
+        k - bias
+    }
+}
+
+fn digit(d: u32) -> char {
+    (if d < 26 { b'a' + d as u8 } else { b'0' + (d - 26) as u8 }) as char
+}
+
+/// Encodes `input` as punycode, without the `xn--` prefix.
+pub fn punycode_encode(input: &str) -> Result<String, DnsError> {
+    let chars: Vec<u32> = input.chars().map(|c| c as u32).collect();
+    let mut out: String = input.chars().filter(|c| c.is_ascii()).collect();
+    let basic = out.len() as u32;
+    if basic > 0 {
+        out.push('-');
+    }
+    let mut n = INITIAL_N;
+    let mut delta = 0u32;
+    let mut bias = INITIAL_BIAS;
+    let mut handled = basic;
+    while (handled as usize) < chars.len() {
+        let m = chars.iter().cloned().filter(|&c| c >= n).min().unwrap();
+        delta = try!((m - n).checked_mul(handled + 1).and_then(|d| d.checked_add(delta))
+                         .ok_or(DnsError::InvalidPunycode));
+        n = m;
+        for &c in &chars {
+            if c < n {
+                delta = try!(delta.checked_add(1).ok_or(DnsError::InvalidPunycode));
+            }
+            if c == n {
+                let mut q = delta;
+                let mut k = BASE;
+                loop {
+                    let t = threshold(k, bias);
+                    if q < t {
+                        break;
+                    }
+                    out.push(digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+                out.push(digit(q));
+                bias = adapt(delta, handled + 1, handled == basic);
+                delta = 0;
+                handled += 1;
+            }
+        }
+        delta = try!(delta.checked_add(1).ok_or(DnsError::InvalidPunycode));
+        n = try!(n.checked_add(1).ok_or(DnsError::InvalidPunycode));
+    }
+    Ok(out)
+}
+
+/// Decodes punycode, given without the `xn--` prefix.
+pub fn punycode_decode(input: &str) -> Result<String, DnsError> {
+    if !input.is_ascii() {
+        return Err(DnsError::InvalidPunycode);
+    }
+    let (basic, rest) = match input.rfind('-') {
+        Some(i) => (&input[..i], &input[i + 1..]),
+        None => ("", input),
+    };
+    let mut out: Vec<char> = basic.chars().collect();
+    let mut n = INITIAL_N;
+    let mut i = 0u32;
+    let mut bias = INITIAL_BIAS;
+    let mut digits = rest.bytes();
+    while digits.len() > 0 {
+        let old = i;
+        let mut w = 1u32;
+        let mut k = BASE;
+        loop {
+            let d = match digits.next() {
+                Some(b @ b'a'...b'z') => (b - b'a') as u32,
+                Some(b @ b'A'...b'Z') => (b - b'A') as u32,
+                Some(b @ b'0'...b'9') => (b - b'0') as u32 + 26,
+                _ => return Err(DnsError::InvalidPunycode),
+            };
+            i = try!(d.checked_mul(w).and_then(|dw| i.checked_add(dw))
+                         .ok_or(DnsError::InvalidPunycode));
+            let t = threshold(k, bias);
+            if d < t {
+                break;
+            }
+            w = try!(w.checked_mul(BASE - t).ok_or(DnsError::InvalidPunycode));
+            k += BASE;
+        }
+        let len = out.len() as u32 + 1;
+        bias = adapt(i - old, len, old == 0);
+        n = try!(n.checked_add(i / len).ok_or(DnsError::InvalidPunycode));
+        i %= len;
+        let c = try!(char::from_u32(n).ok_or(DnsError::InvalidPunycode));
+        out.insert(i as usize, c);
+        i += 1;
+    }
+    Ok(out.into_iter().collect())
+}
+
+// Splits a name in presentation format into its labels, unescaped and
+// without the root.
+fn parse_name(name: &str) -> Result<Vec<Vec<u8>>, DnsError> {
+    let mut labels = Vec::new();
+    if name == "." {
+        return Ok(labels);
+    }
+    let mut label = Vec::new();
+    let mut bytes = name.bytes();
+    let mut wire_len = 1;
+    let mut ended = false;
+    let mut finish = |label: &mut Vec<u8>, labels: &mut Vec<Vec<u8>>| {
+        if label.is_empty() {
+            return Err(DnsError::EmptyLabel);
+        }
+        if label.len() > MAX_LABEL {
+            return Err(DnsError::LabelTooLong);
+        }
+        wire_len += label.len() + 1;
+        if wire_len > MAX_NAME {
+            return Err(DnsError::NameTooLong);
+        }
+        labels.push(label.split_off(0));
+        Ok(())
+    };
+    while let Some(b) = bytes.next() {
+        ended = b == b'.';
+        match b {
+            b'.' => try!(finish(&mut label, &mut labels)),
+            b'\\' => match bytes.next() {
+                Some(d @ b'0'...b'9') => {
+                    let mut value = (d - b'0') as u32;
+                    for _ in 0..2 {
+                        match bytes.next() {
+                            Some(d @ b'0'...b'9') => value = value * 10 + (d - b'0') as u32,
+                            _ => return Err(DnsError::InvalidEscape),
+                        }
+                    }
+                    if value > 255 {
+                        return Err(DnsError::InvalidEscape);
+                    }
+                    label.push(value as u8);
+                }
+                Some(b) => label.push(b),
+                None => return Err(DnsError::InvalidEscape),
+            },
+            b => label.push(b),
+        }
+    }
+    // A trailing dot ends the name at the root already.
+    if !ended {
+        try!(finish(&mut label, &mut labels));
+    }
+    Ok(labels)
+}
+
+// Appends a label in presentation format, escaping dots, backslashes, and
+// bytes other than printable ASCII.
+fn push_label(name: &mut String, label: &[u8]) {
+    for &b in label {
+        match b {
+            b'.' | b'\\' => {
+                name.push('\\');
+                name.push(b as char);
+            }
+            0x21...0x7e => name.push(b as char),
+            b => name.push_str(&format!("\\{:03}", b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dnswire::{self, DnsError, NameEncoder};
+
+    #[test]
+    fn test_names() {
+        assert_eq!(dnswire::encode_name("a.example.").unwrap(), b"\x01a\x07example\x00");
+        assert_eq!(dnswire::encode_name(".").unwrap(), b"\x00");
+        assert_eq!(dnswire::encode_name("a\\.b\\032\\\\").unwrap(), b"\x05a.b \\\x00");
+        assert_eq!(dnswire::encode_name("a..b"), Err(DnsError::EmptyLabel));
+        assert_eq!(dnswire::encode_name(""), Err(DnsError::EmptyLabel));
+        assert_eq!(dnswire::encode_name("a\\25"), Err(DnsError::InvalidEscape));
+        assert_eq!(dnswire::encode_name("a\\256"), Err(DnsError::InvalidEscape));
+        assert_eq!(dnswire::encode_name(&"a".repeat(64)), Err(DnsError::LabelTooLong));
+        let long = vec!["a".repeat(63); 4].join(".");
+        assert_eq!(dnswire::encode_name(&long), Err(DnsError::NameTooLong));
+        assert_eq!(dnswire::encode_name(&long[2..]).unwrap().len(), 255);
+
+        let mut message = Vec::new();
+        let mut encoder = NameEncoder::new();
+        encoder.encode(&mut message, "mail.Example.com").unwrap();
+        encoder.encode(&mut message, "example.COM.").unwrap();
+        encoder.encode(&mut message, "a\\.b.com").unwrap();
+        encoder.encode(&mut message, ".").unwrap();
+        assert_eq!(&message[18..], b"\xc0\x05\x03a.b\xc0\x0d\x00");
+        let decoded = [(0, "mail.Example.com", 18), (18, "Example.com", 20),
+                       (20, "a\\.b.com", 26), (26, ".", 27)];
+        for &(offset, name, end) in &decoded {
+            assert_eq!(dnswire::decode_name(&message, offset).unwrap(), (name.to_string(), end));
+        }
+
+        assert_eq!(dnswire::decode_name(b"\x01a\xc0\x00", 2), Err(DnsError::InvalidPointer));
+        assert_eq!(dnswire::decode_name(b"\xc0\x00", 0), Err(DnsError::InvalidPointer));
+        assert_eq!(dnswire::decode_name(b"\x03ab", 0), Err(DnsError::Truncated));
+        assert_eq!(dnswire::decode_name(b"\xc0", 0), Err(DnsError::Truncated));
+        assert_eq!(dnswire::decode_name(b"\x41", 0), Err(DnsError::InvalidLabelType));
+        assert_eq!(dnswire::decode_name(b"\x02\x00\xff\x00", 0).unwrap().0, "\\000\\255");
+    }
+
+    #[test]
+    fn test_punycode() {
+        // Samples from RFC 3492, section 7.1.
+        let samples = [
+            ("\u{644}\u{64A}\u{647}\u{645}\u{627}\u{628}\u{62A}\u{643}\u{644}\u{645}\u{648}\
+              \u{634}\u{639}\u{631}\u{628}\u{64A}\u{61F}", "egbpdaj6bu4bxfgehfvwxn"),
+            ("\u{4ED6}\u{4EEC}\u{4E3A}\u{4EC0}\u{4E48}\u{4E0D}\u{8BF4}\u{4E2D}\u{6587}",
+             "ihqwcrb4cv8a8dqg056pqjye"),
+            ("3\u{5E74}B\u{7D44}\u{91D1}\u{516B}\u{5148}\u{751F}", "3B-ww4c5e180e575a65lsy2b"),
+            ("-> $1.00 <-", "-> $1.00 <--"),
+        ];
+        for &(unicode, puny) in &samples {
+            assert_eq!(dnswire::punycode_encode(unicode).unwrap(), puny);
+            assert_eq!(dnswire::punycode_decode(puny).unwrap(), unicode);
+        }
+        assert_eq!(dnswire::to_ascii("münchen.de").unwrap(), "xn--mnchen-3ya.de");
+        assert_eq!(dnswire::to_unicode("XN--mnchen-3ya.de").unwrap(), "münchen.de");
+        assert_eq!(dnswire::punycode_decode("a!"), Err(DnsError::InvalidPunycode));
+        assert_eq!(dnswire::punycode_decode("99999999999"), Err(DnsError::InvalidPunycode));
+        assert_eq!(dnswire::to_unicode("xn--é"), Err(DnsError::InvalidPunycode));
+        assert_eq!(dnswire::to_unicode("aé.example").unwrap(), "aé.example");
+        assert_eq!(dnswire::to_unicode("日本語.jp").unwrap(), "日本語.jp");
+        let wide = format!("{}\u{10FFFF}", "a".repeat(4100));
+        assert_eq!(dnswire::punycode_encode(&wide), Err(DnsError::InvalidPunycode));
+    }
+}
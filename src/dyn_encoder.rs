@@ -0,0 +1,730 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An object-safe form of `Encoder`, for choosing output formats at runtime.
+
+use std::error;
+use std::fmt;
+use std::mem;
+use std::path::Path;
+
+use serialize::{Encodable, Encoder};
+use wtf8::Wtf8Buf;
+
+/// An error produced by a `DynEncoder`.
+///
+/// This boxes the error of the underlying `Encoder`, which can be recovered
+/// with `downcast`.
+pub struct DynEncoderError {
+    inner: Box<error::Error + Send>,
+}
+
+impl DynEncoderError {
+    /// Wraps an encoder's error.
+    pub fn new<E: error::Error + Send + 'static>(err: E) -> DynEncoderError {
+        DynEncoderError { inner: Box::new(err) }
+    }
+
+    /// Attempts to recover the error of the underlying encoder.
+    pub fn downcast<E: error::Error + Send + 'static>(self)
+                                                      -> Result<E, DynEncoderError> {
+        match self.inner.downcast::<E>() {
+            Ok(err) => Ok(*err),
+            Err(inner) => Err(DynEncoderError { inner: inner }),
+        }
+    }
+
+    /// Returns the boxed error.
+    pub fn into_inner(self) -> Box<error::Error + Send> {
+        self.inner
+    }
+}
+
+impl fmt::Debug for DynEncoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl fmt::Display for DynEncoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for DynEncoderError {
+    fn description(&self) -> &str { self.inner.description() }
+    fn cause(&self) -> Option<&error::Error> { Some(&*self.inner) }
+}
+
+/// An object-safe counterpart to `Encoder`.
+///
+/// The closure-taking methods of `Encoder` are generic, so `&mut Encoder`
+/// cannot be used as a trait object. `DynEncoder` mirrors each method, taking
+/// closures as `&mut FnMut` trait objects and boxing errors into
+/// `DynEncoderError`. It is implemented for every `Encoder` whose error type
+/// implements `std::error::Error`, and `encode_dyn` encodes any `Encodable`
+/// value into a `&mut DynEncoder`.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::{encode_dyn, DynEncoder};
+/// use rustc_serialize::json;
+///
+/// fn encoder<'a>(out: &'a mut String, pretty: bool) -> Box<DynEncoder + 'a> {
+///     if pretty {
+///         Box::new(json::Encoder::new_pretty(out))
+///     } else {
+///         Box::new(json::Encoder::new(out))
+///     }
+/// }
+///
+/// fn main() {
+///     let mut out = String::new();
+///     encode_dyn(&vec![1, 2], &mut *encoder(&mut out, true)).unwrap();
+///     assert_eq!(out, "[\n  1,\n  2\n]");
+/// }
+/// ```
+pub trait DynEncoder {
+    // Primitive types:
+    /// See `Encoder::emit_nil`.
+    fn dyn_emit_nil(&mut self) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_usize`.
+    fn dyn_emit_usize(&mut self, v: usize) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_u64`.
+    fn dyn_emit_u64(&mut self, v: u64) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_u32`.
+    fn dyn_emit_u32(&mut self, v: u32) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_u16`.
+    fn dyn_emit_u16(&mut self, v: u16) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_u8`.
+    fn dyn_emit_u8(&mut self, v: u8) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_isize`.
+    fn dyn_emit_isize(&mut self, v: isize) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_i64`.
+    fn dyn_emit_i64(&mut self, v: i64) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_i32`.
+    fn dyn_emit_i32(&mut self, v: i32) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_i16`.
+    fn dyn_emit_i16(&mut self, v: i16) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_i8`.
+    fn dyn_emit_i8(&mut self, v: i8) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_u128`.
+    fn dyn_emit_u128(&mut self, v: u128) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_i128`.
+    fn dyn_emit_i128(&mut self, v: i128) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_bool`.
+    fn dyn_emit_bool(&mut self, v: bool) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_f64`.
+    fn dyn_emit_f64(&mut self, v: f64) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_f32`.
+    fn dyn_emit_f32(&mut self, v: f32) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_char`.
+    fn dyn_emit_char(&mut self, v: char) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_str`.
+    fn dyn_emit_str(&mut self, v: &str) -> Result<(), DynEncoderError>;
+
+    // Compound types:
+    /// See `Encoder::emit_enum`.
+    fn dyn_emit_enum(&mut self, name: &str,
+                     f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                     -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_enum_variant`.
+    fn dyn_emit_enum_variant(&mut self, v_name: &str, v_id: usize, len: usize,
+                             f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                             -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_enum_variant_arg`.
+    fn dyn_emit_enum_variant_arg(&mut self, a_idx: usize,
+                                 f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                                 -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_enum_struct_variant`.
+    fn dyn_emit_enum_struct_variant(&mut self, v_name: &str, v_id: usize, len: usize,
+                                    f: &mut FnMut(&mut DynEncoder)
+                                                  -> Result<(), DynEncoderError>)
+                                    -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_enum_struct_variant_field`.
+    fn dyn_emit_enum_struct_variant_field(&mut self, f_name: &str, f_idx: usize,
+                                          f: &mut FnMut(&mut DynEncoder)
+                                                        -> Result<(), DynEncoderError>)
+                                          -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_struct`.
+    fn dyn_emit_struct(&mut self, name: &str, len: usize,
+                       f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                       -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_struct_field`.
+    fn dyn_emit_struct_field(&mut self, f_name: &str, f_idx: usize,
+                             f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                             -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_tuple`.
+    fn dyn_emit_tuple(&mut self, len: usize,
+                      f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                      -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_tuple_arg`.
+    fn dyn_emit_tuple_arg(&mut self, idx: usize,
+                          f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                          -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_tuple_struct`.
+    fn dyn_emit_tuple_struct(&mut self, name: &str, len: usize,
+                             f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                             -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_tuple_struct_arg`.
+    fn dyn_emit_tuple_struct_arg(&mut self, f_idx: usize,
+                                 f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                                 -> Result<(), DynEncoderError>;
+
+    // Specialized types:
+    /// See `Encoder::emit_option`.
+    fn dyn_emit_option(&mut self,
+                       f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                       -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_option_none`.
+    fn dyn_emit_option_none(&mut self) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_option_some`.
+    fn dyn_emit_option_some(&mut self,
+                            f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                            -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_seq`.
+    fn dyn_emit_seq(&mut self, len: usize,
+                    f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                    -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_seq_elt`.
+    fn dyn_emit_seq_elt(&mut self, idx: usize,
+                        f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                        -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_map`.
+    fn dyn_emit_map(&mut self, len: usize,
+                    f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                    -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_map_elt_key`.
+    fn dyn_emit_map_elt_key(&mut self, idx: usize,
+                            f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                            -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_map_elt_val`.
+    fn dyn_emit_map_elt_val(&mut self, idx: usize,
+                            f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                            -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_tagged`.
+    fn dyn_emit_tagged(&mut self, tag: u64,
+                       f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                       -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_wtf8`.
+    fn dyn_emit_wtf8(&mut self, v: &Wtf8Buf) -> Result<(), DynEncoderError>;
+    /// See `Encoder::emit_path`.
+    fn dyn_emit_path(&mut self, v: &Path) -> Result<(), DynEncoderError>;
+
+    /// See `Encoder::format`.
+    fn dyn_format(&self) -> Option<&'static str>;
+}
+
+// Converts an error coming back out of a nested closure into the error type
+// of the encoder that produced it.
+fn unerase<E: error::Error + Send + 'static>(err: DynEncoderError) -> E {
+    match err.downcast() {
+        Ok(err) => err,
+        Err(err) => panic!("DynEncoder callback returned an error from a \
+                            different encoder: {}", err),
+    }
+}
+
+impl<E: Encoder> DynEncoder for E where E::Error: error::Error + Send + 'static {
+    fn dyn_emit_nil(&mut self) -> Result<(), DynEncoderError> {
+        self.emit_nil().map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_usize(&mut self, v: usize) -> Result<(), DynEncoderError> {
+        self.emit_usize(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_u64(&mut self, v: u64) -> Result<(), DynEncoderError> {
+        self.emit_u64(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_u32(&mut self, v: u32) -> Result<(), DynEncoderError> {
+        self.emit_u32(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_u16(&mut self, v: u16) -> Result<(), DynEncoderError> {
+        self.emit_u16(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_u8(&mut self, v: u8) -> Result<(), DynEncoderError> {
+        self.emit_u8(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_isize(&mut self, v: isize) -> Result<(), DynEncoderError> {
+        self.emit_isize(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_i64(&mut self, v: i64) -> Result<(), DynEncoderError> {
+        self.emit_i64(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_i32(&mut self, v: i32) -> Result<(), DynEncoderError> {
+        self.emit_i32(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_i16(&mut self, v: i16) -> Result<(), DynEncoderError> {
+        self.emit_i16(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_i8(&mut self, v: i8) -> Result<(), DynEncoderError> {
+        self.emit_i8(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_u128(&mut self, v: u128) -> Result<(), DynEncoderError> {
+        self.emit_u128(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_i128(&mut self, v: i128) -> Result<(), DynEncoderError> {
+        self.emit_i128(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_bool(&mut self, v: bool) -> Result<(), DynEncoderError> {
+        self.emit_bool(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_f64(&mut self, v: f64) -> Result<(), DynEncoderError> {
+        self.emit_f64(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_f32(&mut self, v: f32) -> Result<(), DynEncoderError> {
+        self.emit_f32(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_char(&mut self, v: char) -> Result<(), DynEncoderError> {
+        self.emit_char(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_str(&mut self, v: &str) -> Result<(), DynEncoderError> {
+        self.emit_str(v).map_err(DynEncoderError::new)
+    }
+
+    fn dyn_emit_enum(&mut self, name: &str,
+                     f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                     -> Result<(), DynEncoderError> {
+        self.emit_enum(name, |e| f(e).map_err(unerase)).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_enum_variant(&mut self, v_name: &str, v_id: usize, len: usize,
+                             f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                             -> Result<(), DynEncoderError> {
+        self.emit_enum_variant(v_name, v_id, len, |e| f(e).map_err(unerase))
+            .map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_enum_variant_arg(&mut self, a_idx: usize,
+                                 f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                                 -> Result<(), DynEncoderError> {
+        self.emit_enum_variant_arg(a_idx, |e| f(e).map_err(unerase))
+            .map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_enum_struct_variant(&mut self, v_name: &str, v_id: usize, len: usize,
+                                    f: &mut FnMut(&mut DynEncoder)
+                                                  -> Result<(), DynEncoderError>)
+                                    -> Result<(), DynEncoderError> {
+        self.emit_enum_struct_variant(v_name, v_id, len, |e| f(e).map_err(unerase))
+            .map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_enum_struct_variant_field(&mut self, f_name: &str, f_idx: usize,
+                                          f: &mut FnMut(&mut DynEncoder)
+                                                        -> Result<(), DynEncoderError>)
+                                          -> Result<(), DynEncoderError> {
+        self.emit_enum_struct_variant_field(f_name, f_idx, |e| f(e).map_err(unerase))
+            .map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_struct(&mut self, name: &str, len: usize,
+                       f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                       -> Result<(), DynEncoderError> {
+        self.emit_struct(name, len, |e| f(e).map_err(unerase))
+            .map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_struct_field(&mut self, f_name: &str, f_idx: usize,
+                             f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                             -> Result<(), DynEncoderError> {
+        self.emit_struct_field(f_name, f_idx, |e| f(e).map_err(unerase))
+            .map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_tuple(&mut self, len: usize,
+                      f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                      -> Result<(), DynEncoderError> {
+        self.emit_tuple(len, |e| f(e).map_err(unerase)).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_tuple_arg(&mut self, idx: usize,
+                          f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                          -> Result<(), DynEncoderError> {
+        self.emit_tuple_arg(idx, |e| f(e).map_err(unerase)).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_tuple_struct(&mut self, name: &str, len: usize,
+                             f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                             -> Result<(), DynEncoderError> {
+        self.emit_tuple_struct(name, len, |e| f(e).map_err(unerase))
+            .map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_tuple_struct_arg(&mut self, f_idx: usize,
+                                 f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                                 -> Result<(), DynEncoderError> {
+        self.emit_tuple_struct_arg(f_idx, |e| f(e).map_err(unerase))
+            .map_err(DynEncoderError::new)
+    }
+
+    fn dyn_emit_option(&mut self,
+                       f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                       -> Result<(), DynEncoderError> {
+        self.emit_option(|e| f(e).map_err(unerase)).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_option_none(&mut self) -> Result<(), DynEncoderError> {
+        self.emit_option_none().map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_option_some(&mut self,
+                            f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                            -> Result<(), DynEncoderError> {
+        self.emit_option_some(|e| f(e).map_err(unerase)).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_seq(&mut self, len: usize,
+                    f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                    -> Result<(), DynEncoderError> {
+        self.emit_seq(len, |e| f(e).map_err(unerase)).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_seq_elt(&mut self, idx: usize,
+                        f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                        -> Result<(), DynEncoderError> {
+        self.emit_seq_elt(idx, |e| f(e).map_err(unerase)).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_map(&mut self, len: usize,
+                    f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                    -> Result<(), DynEncoderError> {
+        self.emit_map(len, |e| f(e).map_err(unerase)).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_map_elt_key(&mut self, idx: usize,
+                            f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                            -> Result<(), DynEncoderError> {
+        self.emit_map_elt_key(idx, |e| f(e).map_err(unerase))
+            .map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_map_elt_val(&mut self, idx: usize,
+                            f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                            -> Result<(), DynEncoderError> {
+        self.emit_map_elt_val(idx, |e| f(e).map_err(unerase))
+            .map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_tagged(&mut self, tag: u64,
+                       f: &mut FnMut(&mut DynEncoder) -> Result<(), DynEncoderError>)
+                       -> Result<(), DynEncoderError> {
+        self.emit_tagged(tag, |e| f(e).map_err(unerase)).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_wtf8(&mut self, v: &Wtf8Buf) -> Result<(), DynEncoderError> {
+        self.emit_wtf8(v).map_err(DynEncoderError::new)
+    }
+    fn dyn_emit_path(&mut self, v: &Path) -> Result<(), DynEncoderError> {
+        self.emit_path(v).map_err(DynEncoderError::new)
+    }
+
+    fn dyn_format(&self) -> Option<&'static str> {
+        self.format()
+    }
+}
+
+/// Encodes `value` into a `DynEncoder`.
+pub fn encode_dyn<T: ?Sized + Encodable>(value: &T, s: &mut DynEncoder)
+                                         -> Result<(), DynEncoderError> {
+    value.encode(&mut Erased { inner: s })
+}
+
+// The sized `Encoder` handed to `Encodable::encode` by `encode_dyn`.
+//
+// It is never exposed by value, so `Encodable` implementations can only
+// reach it through the `&mut` borrows passed to them, for the duration of a
+// single call.
+struct Erased<'a> {
+    inner: &'a mut (DynEncoder + 'a),
+}
+
+// Stretches the lifetimes of the encoder passed to a nested callback to
+// those of the enclosing `Erased`. The reference only lives for the duration
+// of the callback, because `Erased` never escapes one, and the encoder
+// behind it is the one the enclosing `Erased` points to (or one it handed
+// out itself), which outlives `'a`.
+unsafe fn rebind<'a, 'b>(e: &'b mut (DynEncoder + 'b)) -> &'a mut (DynEncoder + 'a) {
+    mem::transmute(e)
+}
+
+// Adapts an `FnOnce(&mut Erased)` into the `FnMut(&mut DynEncoder)` taken by
+// `DynEncoder`'s compound methods.
+macro_rules! erased_fn {
+    ($f:ident) => ({
+        let mut $f = Some($f);
+        move |e: &mut DynEncoder| {
+            let f = $f.take().expect("DynEncoder invoked a callback twice");
+            f(&mut Erased { inner: unsafe { rebind(e) } })
+        }
+    })
+}
+
+impl<'a> Encoder for Erased<'a> {
+    type Error = DynEncoderError;
+
+    fn emit_nil(&mut self) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_nil()
+    }
+    fn emit_usize(&mut self, v: usize) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_usize(v)
+    }
+    fn emit_u64(&mut self, v: u64) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_u64(v)
+    }
+    fn emit_u32(&mut self, v: u32) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_u32(v)
+    }
+    fn emit_u16(&mut self, v: u16) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_u16(v)
+    }
+    fn emit_u8(&mut self, v: u8) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_u8(v)
+    }
+    fn emit_isize(&mut self, v: isize) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_isize(v)
+    }
+    fn emit_i64(&mut self, v: i64) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_i64(v)
+    }
+    fn emit_i32(&mut self, v: i32) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_i32(v)
+    }
+    fn emit_i16(&mut self, v: i16) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_i16(v)
+    }
+    fn emit_i8(&mut self, v: i8) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_i8(v)
+    }
+    fn emit_u128(&mut self, v: u128) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_u128(v)
+    }
+    fn emit_i128(&mut self, v: i128) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_i128(v)
+    }
+    fn emit_bool(&mut self, v: bool) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_bool(v)
+    }
+    fn emit_f64(&mut self, v: f64) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_f64(v)
+    }
+    fn emit_f32(&mut self, v: f32) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_f32(v)
+    }
+    fn emit_char(&mut self, v: char) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_char(v)
+    }
+    fn emit_str(&mut self, v: &str) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_str(v)
+    }
+
+    fn emit_enum<F>(&mut self, name: &str, f: F) -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_enum(name, &mut erased_fn!(f))
+    }
+    fn emit_enum_variant<F>(&mut self, v_name: &str, v_id: usize, len: usize, f: F)
+                            -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_enum_variant(v_name, v_id, len, &mut erased_fn!(f))
+    }
+    fn emit_enum_variant_arg<F>(&mut self, a_idx: usize, f: F)
+                                -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_enum_variant_arg(a_idx, &mut erased_fn!(f))
+    }
+    fn emit_enum_struct_variant<F>(&mut self, v_name: &str, v_id: usize, len: usize,
+                                   f: F) -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_enum_struct_variant(v_name, v_id, len, &mut erased_fn!(f))
+    }
+    fn emit_enum_struct_variant_field<F>(&mut self, f_name: &str, f_idx: usize, f: F)
+                                         -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_enum_struct_variant_field(f_name, f_idx, &mut erased_fn!(f))
+    }
+    fn emit_struct<F>(&mut self, name: &str, len: usize, f: F)
+                      -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_struct(name, len, &mut erased_fn!(f))
+    }
+    fn emit_struct_field<F>(&mut self, f_name: &str, f_idx: usize, f: F)
+                            -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_struct_field(f_name, f_idx, &mut erased_fn!(f))
+    }
+    fn emit_tuple<F>(&mut self, len: usize, f: F) -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_tuple(len, &mut erased_fn!(f))
+    }
+    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_tuple_arg(idx, &mut erased_fn!(f))
+    }
+    fn emit_tuple_struct<F>(&mut self, name: &str, len: usize, f: F)
+                            -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_tuple_struct(name, len, &mut erased_fn!(f))
+    }
+    fn emit_tuple_struct_arg<F>(&mut self, f_idx: usize, f: F)
+                                -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_tuple_struct_arg(f_idx, &mut erased_fn!(f))
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_option(&mut erased_fn!(f))
+    }
+    fn emit_option_none(&mut self) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_option_none()
+    }
+    fn emit_option_some<F>(&mut self, f: F) -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_option_some(&mut erased_fn!(f))
+    }
+    fn emit_seq<F>(&mut self, len: usize, f: F) -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_seq(len, &mut erased_fn!(f))
+    }
+    fn emit_seq_elt<F>(&mut self, idx: usize, f: F) -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_seq_elt(idx, &mut erased_fn!(f))
+    }
+    fn emit_map<F>(&mut self, len: usize, f: F) -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_map(len, &mut erased_fn!(f))
+    }
+    fn emit_map_elt_key<F>(&mut self, idx: usize, f: F) -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_map_elt_key(idx, &mut erased_fn!(f))
+    }
+    fn emit_map_elt_val<F>(&mut self, idx: usize, f: F) -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_map_elt_val(idx, &mut erased_fn!(f))
+    }
+    fn emit_tagged<F>(&mut self, tag: u64, f: F) -> Result<(), DynEncoderError>
+        where F: FnOnce(&mut Self) -> Result<(), DynEncoderError>
+    {
+        self.inner.dyn_emit_tagged(tag, &mut erased_fn!(f))
+    }
+    fn emit_wtf8(&mut self, v: &Wtf8Buf) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_wtf8(v)
+    }
+    fn emit_path(&mut self, v: &Path) -> Result<(), DynEncoderError> {
+        self.inner.dyn_emit_path(v)
+    }
+
+    fn format(&self) -> Option<&'static str> {
+        self.inner.dyn_format()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use std::path::Path;
+
+    use {Encodable, Encoder};
+    use cbor;
+    use dyn_encoder::{encode_dyn, DynEncoder};
+    use hex::ToHex;
+    use json;
+    use wtf8::Wtf8Buf;
+
+    struct Point {
+        x: i32,
+        y: Option<String>,
+    }
+
+    impl Encodable for Point {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_struct("Point", 2, |s| {
+                try!(s.emit_struct_field("x", 0, |s| s.emit_i32(self.x)));
+                s.emit_struct_field("y", 1, |s| self.y.encode(s))
+            })
+        }
+    }
+
+    fn encoder<'a>(out: &'a mut String, pretty: bool) -> Box<DynEncoder + 'a> {
+        if pretty {
+            Box::new(json::Encoder::new_pretty(out))
+        } else {
+            Box::new(json::Encoder::new(out))
+        }
+    }
+
+    fn via_dyn<T: Encodable>(value: &T, pretty: bool) -> String {
+        let mut out = String::new();
+        encode_dyn(value, &mut *encoder(&mut out, pretty)).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_matches_static_dispatch() {
+        let points = vec![Point { x: 1, y: None }, Point { x: -2, y: Some("a".to_string()) }];
+        assert_eq!(via_dyn(&points, false), json::encode(&points).unwrap());
+        assert_eq!(via_dyn(&points, true), json::as_pretty_json(&points).to_string());
+
+        let mut map = BTreeMap::new();
+        map.insert("k".to_string(), (1u8, 'c', 2.5f64, true));
+        assert_eq!(via_dyn(&map, false), json::encode(&map).unwrap());
+    }
+
+    struct Epoch(u64);
+
+    impl Encodable for Epoch {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_u64(self.0)
+        }
+    }
+
+    impl ::ExtensionTag for Epoch {
+        const TAG: u64 = 1;
+    }
+
+    #[test]
+    fn test_tagged() {
+        let mut encoder = cbor::Encoder::new();
+        encode_dyn(&::Tagged(Epoch(5)), &mut encoder).unwrap();
+        assert_eq!(encoder.into_value().to_canonical().unwrap().to_hex(), "c105");
+        let epoch = ::Tagged(Epoch(5));
+        assert_eq!(via_dyn(&epoch, false), json::encode(&epoch).unwrap());
+    }
+
+    #[test]
+    fn test_path_strings() {
+        let path = Path::new("/tmp/a");
+        let mut out = String::new();
+        {
+            let mut encoder = json::Encoder::new(&mut out);
+            encoder.set_path_strings(true);
+            encode_dyn(path, &mut encoder).unwrap();
+        }
+        assert_eq!(out, "\"/tmp/a\"");
+    }
+
+    #[test]
+    fn test_wtf8() {
+        let lone = Wtf8Buf::from_utf16(&[0x76, 0xDFFF]);
+        assert_eq!(via_dyn(&lone, false), "\"v\\udfff\"");
+    }
+
+    #[test]
+    fn test_error_downcast() {
+        let mut map = HashMap::new();
+        map.insert(vec![1u8], 1u8);
+        let mut out = String::new();
+        let mut encoder = json::Encoder::new(&mut out);
+        let err = encode_dyn(&map, &mut encoder).unwrap_err();
+        assert_eq!(err.downcast::<json::EncoderError>().unwrap(),
+                   json::EncoderError::BadHashmapKey);
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Encoding structured log events
+//!
+//! An `EventEncoder` writes an event, given as key-value pairs, as a single
+//! line, either in logfmt (`time=2017-06-01T12:00:00.000Z level=info
+//! msg="user logged in" id=7`) or as a JSON object. Values are given as
+//! `&ToJson`, as `Encodable` cannot be a trait object, so that pairs of
+//! different types can share a slice.
+//!
+//! The time the event was encoded is written first, under the key `time`
+//! by default, in RFC 3339 format with milliseconds, in UTC. The pairs
+//! follow in order. A pair whose key is the time key, or the key of an
+//! earlier pair, is written under the key prefixed with `fields.`, so that
+//! no key of a line is repeated and the time cannot be overwritten.
+//!
+//! In logfmt, strings are written bare unless they are empty or hold
+//! spaces, `=`, `"`, `\` or control characters, in which case they are
+//! quoted with the escapes of JSON. Null is written as an empty value, and
+//! arrays and objects as their JSON text. Characters of keys which logfmt
+//! cannot hold bare are replaced with `_`.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::event::{EventEncoder, Format};
+//!
+//! fn main() {
+//!     let mut encoder = EventEncoder::new(Format::Logfmt);
+//!     encoder.set_time_key(None);
+//!     let line = encoder.encode(&[("level", &"info"), ("msg", &"user logged in"),
+//!                                 ("id", &7), ("roles", &vec!["a", "b"])]);
+//!     assert_eq!(line, r#"level=info msg="user logged in" id=7 roles="[\"a\",\"b\"]""#);
+//!
+//!     let encoder = EventEncoder::new(Format::Json);
+//!     let line = encoder.encode(&[("msg", &"started"), ("time", &"soon")]);
+//!     assert!(line.starts_with(r#"{"time":"20"#));
+//!     assert!(line.ends_with(r#"Z","msg":"started","fields.time":"soon"}"#));
+//! }
+//! ```
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use json::{Json, ToJson};
+
+/// The format of the lines an `EventEncoder` writes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// `key=value` pairs separated by spaces
+    Logfmt,
+    /// A JSON object, its members in the order of the pairs
+    Json,
+}
+
+/// An encoder of events as lines; see the module documentation.
+#[derive(Clone, Debug)]
+pub struct EventEncoder {
+    format: Format,
+    time_key: Option<String>,
+}
+
+impl EventEncoder {
+    /// Creates an encoder of lines in `format`.
+    pub fn new(format: Format) -> EventEncoder {
+        EventEncoder { format: format, time_key: Some("time".to_string()) }
+    }
+
+    /// Set the key the time is written under, or with `None` not to write
+    /// the time. This should be set before encoding.
+    pub fn set_time_key(&mut self, key: Option<&str>) {
+        self.time_key = key.map(|key| key.to_string());
+    }
+
+    /// Encodes the event with the pairs `fields` at the current time, as a
+    /// line without its newline.
+    pub fn encode(&self, fields: &[(&str, &ToJson)]) -> String {
+        self.encode_at(SystemTime::now(), fields)
+    }
+
+    /// Encodes the event with the pairs `fields` at `time`, as a line
+    /// without its newline.
+    pub fn encode_at(&self, time: SystemTime, fields: &[(&str, &ToJson)]) -> String {
+        let mut line = String::new();
+        let mut keys = HashSet::new();
+        if let Some(ref key) = self.time_key {
+            keys.insert(key.clone());
+            self.push(&mut line, key, &Json::String(rfc3339(time).into()));
+        }
+        for &(key, value) in fields {
+            let mut key = key.to_string();
+            while keys.contains(&key) {
+                key.insert_str(0, "fields.");
+            }
+            self.push(&mut line, &key, &value.to_json());
+            keys.insert(key);
+        }
+        if self.format == Format::Json {
+            if line.is_empty() {
+                line.push('{');
+            }
+            line.push('}');
+        }
+        line
+    }
+
+    /// Writes the event with the pairs `fields` at the current time to
+    /// `writer`, as a line with its newline, in a single write.
+    pub fn write<W: Write>(&self, writer: &mut W, fields: &[(&str, &ToJson)])
+                           -> io::Result<()> {
+        let mut line = self.encode(fields);
+        line.push('\n');
+        writer.write_all(line.as_bytes())
+    }
+
+    // Appends the pair to the line.
+    fn push(&self, line: &mut String, key: &str, value: &Json) {
+        match self.format {
+            Format::Logfmt => {
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                if key.is_empty() {
+                    line.push('_');
+                }
+                line.extend(key.chars().map(|c| if bare(c) { c } else { '_' }));
+                line.push('=');
+                match *value {
+                    Json::Null => {}
+                    Json::String(ref s) => push_logfmt_str(line, s),
+                    ref value => push_logfmt_str(line, &value.to_string()),
+                }
+            }
+            Format::Json => {
+                line.push(if line.is_empty() { '{' } else { ',' });
+                line.push_str(&Json::String(key.into()).to_string());
+                line.push(':');
+                line.push_str(&value.to_string());
+            }
+        }
+    }
+}
+
+// Whether `c` can be written in logfmt outside quotes.
+fn bare(c: char) -> bool {
+    !(c == ' ' || c == '=' || c == '"' || c == '\\' || c.is_control())
+}
+
+fn push_logfmt_str(line: &mut String, s: &str) {
+    if !s.is_empty() && s.chars().all(bare) {
+        line.push_str(s);
+    } else {
+        line.push_str(&Json::String(s.into()).to_string());
+    }
+}
+
+// Formats `time` in RFC 3339 format with milliseconds, in UTC.
+fn rfc3339(time: SystemTime) -> String {
+    let (secs, millis) = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => (d.as_secs() as i64, d.subsec_millis()),
+        // Before the epoch, the milliseconds count on from an earlier second.
+        Err(e) => match (e.duration().as_secs() as i64, e.duration().subsec_nanos()) {
+            (secs, 0) => (-secs, 0),
+            (secs, nanos) => (-secs - 1, (1_000_000_000 - nanos) / 1_000_000),
+        },
+    };
+    let mut days = secs / 86400;
+    let mut rem = secs % 86400;
+    if rem < 0 {
+        days -= 1;
+        rem += 86400;
+    }
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year, month, day, rem / 3600, rem / 60 % 60, rem % 60, millis)
+}
+
+// The date of the day `days` after 1970-01-01, in the proleptic Gregorian
+// calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::{Duration, UNIX_EPOCH};
+    use json::Json;
+    use event::{rfc3339, EventEncoder, Format};
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::new(951825845, 678_900_000)),
+                   "2000-02-29T12:04:05.678Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::new(253402300799, 999_999_999)),
+                   "9999-12-31T23:59:59.999Z");
+        assert_eq!(rfc3339(UNIX_EPOCH - Duration::new(0, 1_500_000)),
+                   "1969-12-31T23:59:59.998Z");
+        assert_eq!(rfc3339(UNIX_EPOCH - Duration::new(86400, 0)),
+                   "1969-12-31T00:00:00.000Z");
+    }
+
+    #[test]
+    fn test_encode() {
+        let time = UNIX_EPOCH + Duration::new(1496318400, 0);
+        let mut object = BTreeMap::new();
+        object.insert("k".to_string(), Json::Null);
+        let logfmt = EventEncoder::new(Format::Logfmt);
+        assert_eq!(logfmt.encode_at(time, &[("msg", &"a=b"), ("", &""), ("x y", &"\"\n"),
+                                            ("n", &None::<u8>), ("o", &object), ("f", &1.5),
+                                            ("msg", &true), ("time", &"t"), ("é", &"ü")]),
+                   "time=2017-06-01T12:00:00.000Z msg=\"a=b\" _=\"\" x_y=\"\\\"\\n\" n= \
+                    o=\"{\\\"k\\\":null}\" f=1.5 fields.msg=true fields.time=t é=ü");
+
+        let mut json = EventEncoder::new(Format::Json);
+        json.set_time_key(Some("ts"));
+        let line = json.encode_at(time, &[("ts", &1), ("fields.ts", &2), ("q\"", &"\u{1}")]);
+        assert_eq!(line, "{\"ts\":\"2017-06-01T12:00:00.000Z\",\"fields.ts\":1,\
+                          \"fields.fields.ts\":2,\"q\\\"\":\"\\u0001\"}");
+        assert_eq!(Json::from_str(&line).unwrap()["fields.fields.ts"], Json::U64(2));
+        json.set_time_key(None);
+        assert_eq!(json.encode(&[]), "{}");
+
+        let mut out = Vec::new();
+        logfmt.write(&mut out, &[("a", &1)]).unwrap();
+        assert!(out.ends_with(b"Z a=1\n"));
+    }
+}
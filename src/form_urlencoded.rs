@@ -0,0 +1,330 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `application/x-www-form-urlencoded` query strings and form bodies
+//!
+//! `parse` and `serialize` convert between a query string and its
+//! name/value pairs. `decode_nested` goes further and builds a JSON object
+//! from names written in the bracket notation of PHP and Rails:
+//!
+//! * `a[b]=x` sets the key `b` inside the object `a`.
+//! * `a[0]=x` sets index 0 of the array `a`, filling any indices skipped
+//!   with null. If `a` is already an object, `0` is a key instead.
+//! * `a[]=x` appends to the array `a`; `a[][b]=x` appends an object.
+//!
+//! A name which is not in this notation (such as `a]` or `a[b`) is taken
+//! as it is. `encode_nested` is the inverse, writing arrays with indices.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::form_urlencoded;
+//!
+//! fn main() {
+//!     let obj = form_urlencoded::decode_nested("user[name]=Ann+Lee&user[tags][]=a&user[tags][]=b")
+//!                   .unwrap();
+//!     assert_eq!(obj["user"].to_string(), r#"{"name":"Ann Lee","tags":["a","b"]}"#);
+//!     assert_eq!(form_urlencoded::encode_nested(&obj),
+//!                "user%5Bname%5D=Ann+Lee&user%5Btags%5D%5B0%5D=a&user%5Btags%5D%5B1%5D=b");
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+use json::{Json, Object};
+
+/// How many levels of brackets a name may have.
+pub const MAX_DEPTH: usize = 32;
+
+/// Array indices in names must be less than this.
+pub const MAX_INDEX: usize = 1000;
+
+/// Splits a query string into its name/value pairs, decoding `+` and
+/// percent-encoded bytes. Invalid UTF-8 is replaced with U+FFFD.
+pub fn parse(input: &str) -> Vec<(String, String)> {
+    input.split('&').filter(|s| !s.is_empty()).map(|pair| {
+        let mut parts = pair.splitn(2, '=');
+        let name = parts.next().unwrap();
+        let value = parts.next().unwrap_or("");
+        (decode_component(name), decode_component(value))
+    }).collect()
+}
+
+fn decode_component(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex = |idx: usize| bytes.get(idx).and_then(|&b| (b as char).to_digit(16));
+                match (hex(i + 1), hex(i + 2)) {
+                    (Some(hi), Some(lo)) => {
+                        out.push((hi * 16 + lo) as u8);
+                        i += 2;
+                    }
+                    _ => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Joins name/value pairs into a query string, percent-encoding all but
+/// ASCII letters, digits and `*-._`, and writing spaces as `+`.
+pub fn serialize<I, K, V>(pairs: I) -> String
+    where I: IntoIterator<Item = (K, V)>, K: AsRef<str>, V: AsRef<str>
+{
+    let mut out = String::new();
+    for (name, value) in pairs {
+        if !out.is_empty() {
+            out.push('&');
+        }
+        encode_component(name.as_ref(), &mut out);
+        out.push('=');
+        encode_component(value.as_ref(), &mut out);
+    }
+    out
+}
+
+fn encode_component(s: &str, out: &mut String) {
+    for &b in s.as_bytes() {
+        match b {
+            b'a'...b'z' | b'A'...b'Z' | b'0'...b'9' | b'*' | b'-' | b'.' | b'_' => {
+                out.push(b as char)
+            }
+            b' ' => out.push('+'),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+}
+
+/// Errors that can occur in `decode_nested`, with the name of the pair
+/// at fault.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NestingError {
+    /// The name sets a value where a different kind of value already is,
+    /// as in `a=1&a[b]=2`
+    Conflict(String),
+    /// The name has an array index of `MAX_INDEX` or more
+    IndexTooLarge(String),
+    /// The name has more than `MAX_DEPTH` levels of brackets
+    TooDeep(String),
+}
+
+impl fmt::Display for NestingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NestingError::Conflict(ref name) => {
+                write!(f, "`{}` conflicts with an earlier name", name)
+            }
+            NestingError::IndexTooLarge(ref name) => write!(f, "`{}` has too large an index", name),
+            NestingError::TooDeep(ref name) => write!(f, "`{}` is nested too deeply", name),
+        }
+    }
+}
+
+impl error::Error for NestingError {
+    fn description(&self) -> &str { "invalid nested name" }
+}
+
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+    Append,
+}
+
+// Splits a name into its base and its bracketed segments, or returns no
+// segments if it is not in bracket notation.
+fn split_name(name: &str) -> (&str, Vec<Segment<'_>>) {
+    let open = match name.find('[') {
+        Some(idx) if idx > 0 => idx,
+        _ => return (name, Vec::new()),
+    };
+    let mut segments = Vec::new();
+    let mut rest = &name[open..];
+    while !rest.is_empty() {
+        let close = match rest.find(']') {
+            Some(idx) if rest.starts_with('[') => idx,
+            _ => return (name, Vec::new()),
+        };
+        let inner = &rest[1..close];
+        segments.push(if inner.is_empty() {
+            Segment::Append
+        } else if inner.bytes().all(|b| b.is_ascii_digit()) &&
+                  (inner == "0" || !inner.starts_with('0')) {
+            match inner.parse() {
+                Ok(idx) => Segment::Index(idx),
+                Err(_) => Segment::Index(usize::MAX),
+            }
+        } else {
+            Segment::Key(inner)
+        });
+        rest = &rest[close + 1..];
+    }
+    (&name[..open], segments)
+}
+
+/// Parses a query string into a JSON object, nesting values by the
+/// bracket notation in their names. All values are strings; a name which
+/// appears twice keeps the last value.
+pub fn decode_nested(input: &str) -> Result<Object, NestingError> {
+    let mut obj = BTreeMap::new();
+    for (name, value) in parse(input) {
+        try!(insert(&mut obj, &name, value));
+    }
+    Ok(obj)
+}
+
+fn insert(obj: &mut Object, name: &str, value: String) -> Result<(), NestingError> {
+    let (base, segments) = split_name(name);
+    if segments.len() > MAX_DEPTH {
+        return Err(NestingError::TooDeep(name.to_string()));
+    }
+    // Null marks a slot not yet set.
+    let mut slot = obj.entry(base.to_string()).or_insert(Json::Null);
+    for segment in segments {
+        let current = slot;
+        if *current == Json::Null {
+            *current = match segment {
+                Segment::Key(_) => Json::Object(BTreeMap::new()),
+                _ => Json::Array(Vec::new()),
+            };
+        }
+        slot = match (current, segment) {
+            (&mut Json::Object(ref mut o), Segment::Key(key)) => {
+                o.entry(key.to_string()).or_insert(Json::Null)
+            }
+            (&mut Json::Object(ref mut o), Segment::Index(idx)) => {
+                o.entry(idx.to_string()).or_insert(Json::Null)
+            }
+            (&mut Json::Array(ref mut a), Segment::Index(idx)) => {
+                if idx >= MAX_INDEX {
+                    return Err(NestingError::IndexTooLarge(name.to_string()));
+                }
+                if a.len() <= idx {
+                    a.resize(idx + 1, Json::Null);
+                }
+                &mut a[idx]
+            }
+            (&mut Json::Array(ref mut a), Segment::Append) => {
+                a.push(Json::Null);
+                a.last_mut().unwrap()
+            }
+            _ => return Err(NestingError::Conflict(name.to_string())),
+        };
+    }
+    match *slot {
+        Json::Null | Json::String(_) => {
+            *slot = Json::from(value);
+            Ok(())
+        }
+        _ => Err(NestingError::Conflict(name.to_string())),
+    }
+}
+
+/// Writes a JSON object as a query string, naming nested values with
+/// bracket notation and array items by index. Null is written as an empty
+/// value and other scalars as their JSON text; empty arrays and objects
+/// are left out, having no representation.
+pub fn encode_nested(obj: &Object) -> String {
+    let mut pairs = Vec::new();
+    for (key, value) in obj {
+        flatten(key.clone(), value, &mut pairs);
+    }
+    serialize(pairs)
+}
+
+fn flatten(name: String, value: &Json, out: &mut Vec<(String, String)>) {
+    match *value {
+        Json::Object(ref o) => {
+            for (key, value) in o {
+                flatten(format!("{}[{}]", name, key), value, out);
+            }
+        }
+        Json::Array(ref a) => {
+            for (idx, value) in a.iter().enumerate() {
+                flatten(format!("{}[{}]", name, idx), value, out);
+            }
+        }
+        Json::String(ref s) => out.push((name, s.to_string())),
+        Json::Null => out.push((name, String::new())),
+        ref scalar => out.push((name, scalar.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use form_urlencoded::{decode_nested, encode_nested, parse, serialize, NestingError};
+    use form_urlencoded::{MAX_DEPTH, MAX_INDEX};
+    use json::Json;
+
+    #[test]
+    fn test_pairs() {
+        let pairs = parse("a=1&&b=x+y%3D%e2%82%ac&c&=d&e=%zz%4&f=%FF");
+        let expected = [("a", "1"), ("b", "x y=\u{20ac}"), ("c", ""), ("", "d"), ("e", "%zz%4"),
+                        ("f", "\u{fffd}")];
+        assert_eq!(pairs.len(), expected.len());
+        for (pair, &(name, value)) in pairs.iter().zip(expected.iter()) {
+            assert_eq!((&pair.0[..], &pair.1[..]), (name, value));
+        }
+        assert_eq!(serialize(vec![("a b", "\u{20ac}&="), ("_.-*", "")]),
+                   "a+b=%E2%82%AC%26%3D&_.-*=");
+        assert_eq!(parse(&serialize(pairs.clone())), pairs);
+    }
+
+    #[test]
+    fn test_nested() {
+        let obj = decode_nested("a[b][0]=x&a[b][2]=z&a[c]=1&l[]=p&l[]=q&o[][k]=v&o[0][j]=w\
+                                 &h[x]=1&h[0]=2&n[1]=a&p=1&p=2&q]=1&r[s=1&t[u]v=1").unwrap();
+        let expected = Json::from_str(r#"{"a": {"b": ["x", null, "z"], "c": "1"},
+                                          "l": ["p", "q"], "o": [{"j": "w", "k": "v"}],
+                                          "h": {"0": "2", "x": "1"}, "n": [null, "a"], "p": "2",
+                                          "q]": "1", "r[s": "1", "t[u]v": "1"}"#).unwrap();
+        assert_eq!(Json::Object(obj), expected);
+
+        let deep = format!("a{}=1", "[x]".repeat(MAX_DEPTH));
+        assert!(decode_nested(&deep).is_ok());
+        let errors = [("m[0]=0&m[x]=1", NestingError::Conflict("m[x]".to_string())),
+                      ("a=1&a[b]=2", NestingError::Conflict("a[b]".to_string())),
+                      ("a[b]=1&a=2", NestingError::Conflict("a".to_string())),
+                      ("a[]=1&a[b]=2", NestingError::Conflict("a[b]".to_string())),
+                      ("a[99999999999999999999]=x",
+                       NestingError::IndexTooLarge("a[99999999999999999999]".to_string()))];
+        for &(input, ref err) in errors.iter() {
+            assert_eq!(decode_nested(input).as_ref(), Err(err));
+        }
+        let index = format!("a[{}]=x", MAX_INDEX);
+        assert_eq!(decode_nested(&index),
+                   Err(NestingError::IndexTooLarge(format!("a[{}]", MAX_INDEX))));
+        let name = format!("a{}", "[x]".repeat(MAX_DEPTH + 1));
+        assert_eq!(decode_nested(&format!("{}=1", name)), Err(NestingError::TooDeep(name)));
+    }
+
+    #[test]
+    fn test_encode_nested() {
+        let obj = decode_nested("a[b][0]=x&a[b][1]=y&a[c]=1&l[]=p&l[][k]=v").unwrap();
+        let encoded = encode_nested(&obj);
+        assert_eq!(encoded, "a%5Bb%5D%5B0%5D=x&a%5Bb%5D%5B1%5D=y&a%5Bc%5D=1\
+                             &l%5B0%5D=p&l%5B1%5D%5Bk%5D=v");
+        assert_eq!(decode_nested(&encoded).unwrap(), obj);
+
+        let json = Json::from_str(r#"{"n": 1.5, "t": true, "z": null, "e": [], "s": "a b"}"#)
+                       .unwrap();
+        assert_eq!(encode_nested(json.as_object().unwrap()), "n=1.5&s=a+b&t=true&z=");
+    }
+}
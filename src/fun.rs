@@ -0,0 +1,221 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Toy byte-transform codecs: Caesar/ROT13 and repeating-key XOR
+//!
+//! These transforms offer no security whatsoever. They are handy for
+//! obfuscating test fixtures or for puzzle tooling, and double as small
+//! examples of implementing `codec::Codec`.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use std::io::{Read, Write};
+//! use rustc_serialize::fun::{Caesar, CaesarReader, CaesarWriter};
+//!
+//! fn main() {
+//!     let mut writer = CaesarWriter::new(Vec::new(), Caesar::rot13());
+//!     writer.write_all(b"Hello, World").unwrap();
+//!     let encoded = writer.into_inner();
+//!     assert_eq!(encoded, b"Uryyb, Jbeyq");
+//!
+//!     let mut decoded = String::new();
+//!     let mut reader = CaesarReader::new(&encoded[..], Caesar::rot13());
+//!     reader.read_to_string(&mut decoded).unwrap();
+//!     assert_eq!(decoded, "Hello, World");
+//! }
+//! ```
+
+use std::convert::Infallible;
+
+use codec::{Codec, CodecReader, CodecWriter};
+
+/// A Caesar cipher over the ASCII letters. All other bytes pass through
+/// unchanged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Caesar {
+    shift: u8,
+}
+
+impl Caesar {
+    /// Creates a cipher rotating letters forward by `shift` places.
+    pub fn new(shift: u8) -> Caesar {
+        Caesar { shift: shift % 26 }
+    }
+
+    /// Creates the ROT13 cipher, which is its own inverse.
+    pub fn rot13() -> Caesar {
+        Caesar::new(13)
+    }
+
+    /// Encodes a single byte.
+    pub fn encode_byte(&self, b: u8) -> u8 {
+        rotate(b, self.shift)
+    }
+
+    /// Decodes a single byte.
+    pub fn decode_byte(&self, b: u8) -> u8 {
+        rotate(b, 26 - self.shift)
+    }
+
+    /// Encodes a buffer, returning the owned result.
+    pub fn encode(&self, input: &[u8]) -> Vec<u8> {
+        input.iter().map(|&b| self.encode_byte(b)).collect()
+    }
+
+    /// Decodes a buffer, returning the owned result.
+    pub fn decode(&self, input: &[u8]) -> Vec<u8> {
+        input.iter().map(|&b| self.decode_byte(b)).collect()
+    }
+}
+
+fn rotate(b: u8, shift: u8) -> u8 {
+    match b {
+        b'a'...b'z' => (b - b'a' + shift) % 26 + b'a',
+        b'A'...b'Z' => (b - b'A' + shift) % 26 + b'A',
+        _ => b,
+    }
+}
+
+/// A repeating-key XOR transform. XOR is its own inverse, so the same
+/// value both encodes and decodes.
+///
+/// The position within the key is tracked, so a stream may be transformed
+/// in arbitrarily sized pieces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Xor {
+    key: Vec<u8>,
+    pos: usize,
+}
+
+impl Xor {
+    /// Creates a transform using `key`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is empty.
+    pub fn new(key: &[u8]) -> Xor {
+        assert!(!key.is_empty(), "xor key must not be empty");
+        Xor { key: key.to_vec(), pos: 0 }
+    }
+
+    /// Transforms `buf` in place, advancing the key position.
+    pub fn apply(&mut self, buf: &mut [u8]) {
+        for b in buf.iter_mut() {
+            *b ^= self.key[self.pos];
+            self.pos = (self.pos + 1) % self.key.len();
+        }
+    }
+
+    /// Rewinds to the start of the key.
+    pub fn reset(&mut self) {
+        self.pos = 0;
+    }
+}
+
+impl Codec for Caesar {
+    type Error = Infallible;
+
+    fn encode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), Infallible> {
+        output.extend(input.iter().map(|&b| self.encode_byte(b)));
+        Ok(())
+    }
+
+    fn decode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), Infallible> {
+        output.extend(input.iter().map(|&b| self.decode_byte(b)));
+        Ok(())
+    }
+
+    fn finish(&mut self, _output: &mut Vec<u8>) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl Codec for Xor {
+    type Error = Infallible;
+
+    fn encode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), Infallible> {
+        let start = output.len();
+        output.extend_from_slice(input);
+        self.apply(&mut output[start..]);
+        Ok(())
+    }
+
+    fn decode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), Infallible> {
+        self.encode_chunk(input, output)
+    }
+
+    fn finish(&mut self, _output: &mut Vec<u8>) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// A reader decoding Caesar-shifted data from the underlying reader.
+pub type CaesarReader<R> = CodecReader<Caesar, R>;
+
+/// A writer Caesar-shifting everything written to it before passing it on.
+pub type CaesarWriter<W> = CodecWriter<Caesar, W>;
+
+/// A reader XOR-ing the data read from the underlying reader.
+pub type XorReader<R> = CodecReader<Xor, R>;
+
+/// A writer XOR-ing everything written to it before passing it on.
+pub type XorWriter<W> = CodecWriter<Xor, W>;
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+    use fun::{Caesar, CaesarReader, CaesarWriter, Xor, XorReader, XorWriter};
+
+    #[test]
+    fn test_rot13() {
+        let rot13 = Caesar::rot13();
+        assert_eq!(rot13.encode(b"Hello, World!"), b"Uryyb, Jbeyq!");
+        assert_eq!(rot13.encode(&rot13.encode(b"abcXYZ")), b"abcXYZ");
+    }
+
+    #[test]
+    fn test_caesar_roundtrip() {
+        let c = Caesar::new(3);
+        assert_eq!(c.encode(b"xyz"), b"abc");
+        assert_eq!(c.decode(b"abc"), b"xyz");
+        assert_eq!(Caesar::new(29), c);
+    }
+
+    #[test]
+    fn test_caesar_stream() {
+        let mut w = CaesarWriter::new(Vec::new(), Caesar::new(1));
+        w.write_all(b"HAL").unwrap();
+        let encoded = w.into_inner();
+        assert_eq!(encoded, b"IBM");
+
+        let mut s = String::new();
+        CaesarReader::new(&encoded[..], Caesar::new(1)).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "HAL");
+    }
+
+    #[test]
+    fn test_xor_stream() {
+        let mut w = XorWriter::new(Vec::new(), Xor::new(b"key"));
+        w.write_all(b"sec").unwrap();
+        w.write_all(b"ret!").unwrap();
+        let encoded = w.into_inner();
+        assert!(encoded != b"secret!");
+
+        let mut v = Vec::new();
+        XorReader::new(&encoded[..], Xor::new(b"key")).read_to_end(&mut v).unwrap();
+        assert_eq!(v, b"secret!");
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Byte-level entry points for fuzzers
+//!
+//! Each function feeds arbitrary bytes through a complete decode pipeline,
+//! re-encodes whatever was accepted and checks that the output decodes
+//! again. Rejected input returns as soon as the first stage fails, so the
+//! fuzzer spends its time on inputs which get further in.
+//!
+//! Malformed input is never an error here; an `Err` means the pipeline
+//! panicked or an invariant check failed, which is always a bug. A
+//! cargo-fuzz target is then a one-liner:
+//!
+//! ```rust,ignore
+//! fuzz_target!(|data: &[u8]| rustc_serialize::fuzz_json(data).unwrap());
+//! ```
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::panic;
+use std::str;
+
+use Decodable;
+use base64::{self, Base64Codec, FromBase64, ToBase64};
+use codec::Codec;
+use hex::{FromHex, ToHex};
+use json::{self, Json, JsonEvent, Parser};
+
+/// A panic caught while running a fuzz entry point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Panicked {
+    /// The panic message, if it was a string
+    pub message: String,
+}
+
+impl fmt::Display for Panicked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "panicked: {}", self.message)
+    }
+}
+
+impl error::Error for Panicked {
+    fn description(&self) -> &str { "panicked while decoding" }
+}
+
+fn catch<F: FnOnce() + panic::UnwindSafe>(f: F) -> Result<(), Panicked> {
+    panic::catch_unwind(f).map_err(|payload| Panicked { message: message(payload) })
+}
+
+fn message(payload: Box<Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        return s.to_string();
+    }
+    match payload.downcast::<String>() {
+        Ok(s) => *s,
+        Err(_) => "<non-string panic payload>".to_string(),
+    }
+}
+
+/// Feeds `data` through the streaming parser, the `Json` builder and the
+/// `Decoder`, and checks that accepted documents re-encode to valid JSON.
+#[doc(hidden)]
+pub fn fuzz_json(data: &[u8]) -> Result<(), Panicked> {
+    catch(|| {
+        let s = match str::from_utf8(data) {
+            Ok(s) => s,
+            Err(_) => {
+                assert!(Json::from_reader(&mut &data[..]).is_err(),
+                        "from_reader accepted invalid UTF-8");
+                return
+            }
+        };
+
+        let mut stream_ok = true;
+        for event in Parser::new(s.chars()) {
+            if let JsonEvent::Error(_) = event {
+                stream_ok = false;
+                break
+            }
+        }
+        let json = match Json::from_str(s) {
+            Ok(json) => json,
+            Err(_) => {
+                assert!(!stream_ok, "builder rejected a document the parser accepted");
+                return
+            }
+        };
+        assert!(stream_ok, "builder accepted a document the parser rejected");
+
+        let compact = json.to_string();
+        Json::from_str(&compact).expect("compact output failed to parse");
+        let pretty = json.pretty().to_string();
+        Json::from_str(&pretty).expect("pretty output failed to parse");
+
+        let _ = <Vec<Option<String>> as Decodable>::decode(
+            &mut json::Decoder::new(json.clone()));
+        let _ = <BTreeMap<String, Option<f64>> as Decodable>::decode(
+            &mut json::Decoder::new(json.clone()));
+        let _ = <(u8, i64, String, bool) as Decodable>::decode(
+            &mut json::Decoder::new(json));
+    })
+}
+
+/// Feeds `data` through `FromBase64` and the streaming `Base64Codec`,
+/// checking that both agree and that accepted input round-trips through
+/// every standard configuration.
+#[doc(hidden)]
+pub fn fuzz_base64(data: &[u8]) -> Result<(), Panicked> {
+    catch(|| {
+        let oneshot = data.from_base64();
+
+        let mut codec = Base64Codec::new(base64::STANDARD);
+        let mut streamed = Vec::new();
+        let mid = data.len() / 2;
+        let result = codec.decode_chunk(&data[..mid], &mut streamed)
+            .and_then(|()| codec.decode_chunk(&data[mid..], &mut streamed))
+            .and_then(|()| codec.finish(&mut streamed));
+
+        let bytes = match oneshot {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                assert!(result.is_err(), "codec accepted input FromBase64 rejected");
+                return
+            }
+        };
+        assert!(result.is_ok(), "codec rejected input FromBase64 accepted");
+        assert_eq!(streamed, bytes, "codec and FromBase64 disagree");
+
+        for config in [base64::STANDARD, base64::URL_SAFE, base64::MIME].iter() {
+            let encoded = bytes.to_base64(*config);
+            assert_eq!(encoded.from_base64().expect("encoder output failed to decode"),
+                       bytes);
+        }
+    })
+}
+
+/// Feeds `data` through the crate's text-handling paths: lossy and strict
+/// UTF-8 conversion, JSON string escaping and unescaping, and the text side
+/// of the hex and base64 decoders.
+#[doc(hidden)]
+pub fn fuzz_utf8(data: &[u8]) -> Result<(), Panicked> {
+    catch(|| {
+        let text = String::from_utf8_lossy(data).into_owned();
+        if let Ok(s) = str::from_utf8(data) {
+            assert_eq!(s, text, "lossy conversion altered valid UTF-8");
+        }
+
+        let encoded = Json::from(text.clone()).to_string();
+        match Json::from_str(&encoded) {
+            Ok(Json::String(ref s)) if *s == text => (),
+            other => panic!("string failed to round-trip: {:?}", other),
+        }
+        let encoded = json::encode(&text).expect("failed to encode string");
+        let decoded: String = json::decode(&encoded).expect("failed to decode string");
+        assert_eq!(decoded, text);
+
+        if let Ok(bytes) = text.from_hex() {
+            assert_eq!(bytes.to_hex().from_hex().unwrap(), bytes);
+        }
+        let _ = text.from_base64();
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use json::testing::Generator;
+    use fuzz::{fuzz_base64, fuzz_json, fuzz_utf8};
+
+    #[test]
+    fn test_fuzz_json() {
+        assert!(fuzz_json(b"{\"a\": [1, -2, 3.5, null, true]}").is_ok());
+        assert!(fuzz_json(b"[1, 2").is_ok());
+        assert!(fuzz_json(b"\xff").is_ok());
+        for seed in 0..200 {
+            let mut gen = Generator::new(seed);
+            assert!(fuzz_json(gen.json().to_string().as_bytes()).is_ok());
+            assert!(fuzz_json(&gen.invalid_bytes()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_fuzz_base64() {
+        assert!(fuzz_base64(b"Zm9vYmFy").is_ok());
+        assert!(fuzz_base64(b"Zm9vYg==").is_ok());
+        assert!(fuzz_base64(b"Zm9v\r\nYmFy").is_ok());
+        assert!(fuzz_base64(b"Zm9vY").is_ok());
+        assert!(fuzz_base64(b"Zm=9").is_ok());
+        assert!(fuzz_base64(b"\x00\xff").is_ok());
+    }
+
+    #[test]
+    fn test_fuzz_utf8() {
+        assert!(fuzz_utf8(b"plain").is_ok());
+        assert!(fuzz_utf8("\u{1F600}\"\\\n\u{0}".as_bytes()).is_ok());
+        assert!(fuzz_utf8(b"\xed\xa0\x80\xc3").is_ok());
+        assert!(fuzz_utf8(b"0a 1B").is_ok());
+    }
+}
@@ -0,0 +1,284 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Quantizing floats to 16 bits
+//!
+//! Arrays of floats which need not be exact, such as machine learning
+//! embeddings, can be sent as 16-bit floats, in a quarter of the space of
+//! `f64`s, and widened back on arrival. There are two 16-bit formats:
+//!
+//! * `Quantization::F16`, IEEE 754 binary16, keeps 11 significant bits
+//!   but only reaches 65504, and has subnormals down to about 6e-8;
+//! * `Quantization::BF16`, bfloat16, keeps 8 significant bits but has the
+//!   range of an `f32`.
+//!
+//! Values are rounded to the nearest 16-bit float, ties to even; values
+//! beyond the range become infinite.
+//!
+//! A quantized array is marked with the extension tag of its format (see
+//! `ExtensionTag`). The `json` encoder quantizes sequences of floats when
+//! given a format with `set_quantization`, writing each in the JSON form of
+//! a tagged value holding the base64 encoding of the 16-bit floats, little
+//! endian: `{"$tag": F16_TAG, "value": "ADwAQA=="}`. The `json` decoder
+//! reads such values back wherever a sequence is expected, and
+//! `widen_json` turns them into arrays in a `Json` tree.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::half::Quantization;
+//! use rustc_serialize::json::{self, Encoder};
+//! use rustc_serialize::Encodable;
+//!
+//! fn main() {
+//!     let embedding = vec![0.1f32, -2.5, 1000.0];
+//!     let mut buf = String::new();
+//!     {
+//!         let mut encoder = Encoder::new(&mut buf);
+//!         encoder.set_quantization(Quantization::F16);
+//!         embedding.encode(&mut encoder).unwrap();
+//!     }
+//!     assert_eq!(buf, r#"{"$tag":40202,"value":"Zi4AwdBj"}"#);
+//!
+//!     let widened: Vec<f32> = json::decode(&buf).unwrap();
+//!     assert_eq!(widened, [0.099975586, -2.5, 1000.0]);
+//! }
+//! ```
+
+use std::f64;
+
+use base64::{FromBase64, ToBase64, STANDARD};
+use json::{Json, Object};
+
+/// The extension tag of arrays of binary16 floats.
+pub const F16_TAG: u64 = 40202;
+/// The extension tag of arrays of bfloat16 floats.
+pub const BF16_TAG: u64 = 40203;
+
+/// A 16-bit float format; see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Quantization {
+    /// IEEE 754 binary16: 5 exponent bits and 10 mantissa bits
+    F16,
+    /// bfloat16: 8 exponent bits and 7 mantissa bits
+    BF16,
+}
+
+impl Quantization {
+    /// The extension tag marking arrays in this format.
+    pub fn tag(self) -> u64 {
+        match self {
+            Quantization::F16 => F16_TAG,
+            Quantization::BF16 => BF16_TAG,
+        }
+    }
+
+    /// The format marked by `tag`, if any.
+    pub fn from_tag(tag: u64) -> Option<Quantization> {
+        match tag {
+            F16_TAG => Some(Quantization::F16),
+            BF16_TAG => Some(Quantization::BF16),
+            _ => None,
+        }
+    }
+
+    // The widths of the exponent and mantissa.
+    fn bits(self) -> (u32, u32) {
+        match self {
+            Quantization::F16 => (5, 10),
+            Quantization::BF16 => (8, 7),
+        }
+    }
+
+    /// Rounds `v` to the nearest float in this format, and returns its
+    /// bits. NaNs become quiet NaNs, keeping their sign.
+    pub fn narrow(self, v: f64) -> u16 {
+        let (exp_bits, man_bits) = self.bits();
+        let bits = v.to_bits();
+        let sign = ((bits >> 48) & 0x8000) as u16;
+        let exp = ((bits >> 52) & 0x7ff) as i32;
+        let man = bits & 0xf_ffff_ffff_ffff;
+        let max_exp = (1 << exp_bits) - 1;
+        let inf = (max_exp << man_bits) as u16;
+        if exp == 0x7ff {
+            return sign | inf | if man != 0 { 1 << (man_bits - 1) } else { 0 };
+        }
+        if exp == 0 {
+            // Zero, or an `f64` subnormal, far below the range of either.
+            return sign;
+        }
+        let bias = (1 << (exp_bits - 1)) - 1;
+        let e = exp - 1023 + bias;
+        if e >= max_exp {
+            return sign | inf;
+        }
+        // The significand, and the number of its bits to drop: more below
+        // the smallest normal exponent, as subnormals have fewer.
+        let m = 1 << 52 | man;
+        let shift = (52 - man_bits) as i32 + if e <= 0 { 1 - e } else { 0 };
+        if shift >= 64 {
+            return sign;
+        }
+        let mut r = m >> shift;
+        let rem = m & ((1 << shift) - 1);
+        let half = 1 << (shift - 1);
+        if rem > half || (rem == half && r & 1 == 1) {
+            r += 1;
+        }
+        // Rounding up may carry into the exponent, up to infinity.
+        if e > 0 {
+            r += ((e - 1) as u64) << man_bits;
+        }
+        sign | r as u16
+    }
+
+    /// The value of the float in this format with bits `bits`.
+    pub fn widen(self, bits: u16) -> f64 {
+        let (exp_bits, man_bits) = self.bits();
+        let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let max_exp = (1 << exp_bits) - 1;
+        let e = (bits as i32 >> man_bits) & max_exp;
+        let m = bits as u32 & ((1 << man_bits) - 1);
+        let bias = (1 << (exp_bits - 1)) - 1;
+        if e == max_exp {
+            if m == 0 { sign * f64::INFINITY } else { f64::NAN }
+        } else if e == 0 {
+            sign * m as f64 * 2f64.powi(1 - bias - man_bits as i32)
+        } else {
+            sign * (1 << man_bits | m) as f64 * 2f64.powi(e - bias - man_bits as i32)
+        }
+    }
+
+    /// Rounds `v` to the nearest float in this format.
+    pub fn round(self, v: f64) -> f64 {
+        self.widen(self.narrow(v))
+    }
+
+    /// Quantizes `values` to this format, as the base64 encoding of their
+    /// bits, little endian.
+    pub fn encode(self, values: &[f64]) -> String {
+        let mut bytes = Vec::with_capacity(2 * values.len());
+        for &v in values {
+            let bits = self.narrow(v);
+            bytes.push(bits as u8);
+            bytes.push((bits >> 8) as u8);
+        }
+        bytes.to_base64(STANDARD)
+    }
+
+    /// Widens the values `encode` quantized, or returns `None` if `s` is
+    /// not base64 of an even number of bytes.
+    pub fn decode(self, s: &str) -> Option<Vec<f64>> {
+        let bytes = match s.from_base64() {
+            Ok(bytes) => bytes,
+            Err(_) => return None,
+        };
+        if bytes.len() % 2 != 0 {
+            return None;
+        }
+        Some(bytes.chunks(2).map(|b| self.widen(b[0] as u16 | (b[1] as u16) << 8)).collect())
+    }
+}
+
+/// The values of a quantized array, if `object` is one in the form the
+/// `json` encoder writes.
+pub fn quantized_floats(object: &Object) -> Option<Vec<f64>> {
+    if object.len() != 2 {
+        return None;
+    }
+    let quantization = object.get("$tag").and_then(|t| t.as_u64())
+                             .and_then(Quantization::from_tag);
+    match (quantization, object.get("value").and_then(|v| v.as_string())) {
+        (Some(quantization), Some(s)) => quantization.decode(s),
+        _ => None,
+    }
+}
+
+/// Replaces each quantized array in `json` with an array of its values.
+/// As in `json` output, NaN and infinite values become `null`.
+pub fn widen_json(json: &mut Json) {
+    let widened = match *json {
+        Json::Array(ref mut array) => {
+            for elt in array.iter_mut() {
+                widen_json(elt);
+            }
+            return;
+        }
+        Json::Object(ref mut object) => match quantized_floats(object) {
+            Some(values) => Json::Array(values.into_iter().map(float).collect()),
+            None => {
+                for member in object.values_mut() {
+                    widen_json(member);
+                }
+                return;
+            }
+        },
+        _ => return,
+    };
+    *json = widened;
+}
+
+fn float(v: f64) -> Json {
+    if v.is_finite() { Json::F64(v) } else { Json::Null }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+    use json::Json;
+    use half::{self, Quantization};
+
+    #[test]
+    fn test_narrow() {
+        let f16 = [(0.0, 0x0000), (-0.0, 0x8000), (1.0, 0x3c00), (-2.0, 0xc000),
+                   (65504.0, 0x7bff), (65519.0, 0x7bff), (65520.0, 0x7c00), (1e10, 0x7c00),
+                   (0.1, 0x2e66), (0.000061035156, 0x0400), (5.960464477539063e-8, 0x0001),
+                   (2.9e-8, 0x0000), (3.0e-8, 0x0001), (1e-300, 0x0000),
+                   (f64::INFINITY, 0x7c00), (f64::NEG_INFINITY, 0xfc00), (f64::NAN, 0x7e00),
+                   // Ties go to even: 1 + 2^-11 to 1, 1 + 3 * 2^-11 up to 1 + 2^-9.
+                   (1.00048828125, 0x3c00), (1.00146484375, 0x3c02)];
+        for &(v, bits) in f16.iter() {
+            assert_eq!(Quantization::F16.narrow(v), bits, "{}", v);
+        }
+        let bf16 = [(1.0, 0x3f80), (3.139, 0x4049), (-1e38, 0xfe96), (1e-40, 0x0001),
+                    (3.5e38, 0x7f80), (f64::NAN, 0x7fc0)];
+        for &(v, bits) in bf16.iter() {
+            assert_eq!(Quantization::BF16.narrow(v), bits, "{}", v);
+        }
+
+        for &q in [Quantization::F16, Quantization::BF16].iter() {
+            for bits in 0..0x10000u32 {
+                let v = q.widen(bits as u16);
+                if !v.is_nan() {
+                    assert_eq!(q.narrow(v), bits as u16, "{:?} {:#x}", q, bits);
+                }
+            }
+            assert_eq!(Quantization::from_tag(q.tag()), Some(q));
+        }
+        assert_eq!(Quantization::F16.round(0.1), 0.0999755859375);
+        assert_eq!(Quantization::BF16.widen(0x4049), 3.140625);
+    }
+
+    #[test]
+    fn test_encode() {
+        let values = [1.0, 2.0, -0.5];
+        let encoded = Quantization::F16.encode(&values);
+        assert_eq!(encoded, "ADwAQAC4");
+        assert_eq!(Quantization::F16.decode(&encoded).unwrap(), values);
+        assert_eq!(Quantization::F16.decode("AA=="), None);
+        assert_eq!(Quantization::F16.decode("!"), None);
+
+        let mut json = Json::from_str("{\"e\": {\"$tag\": 40203, \"value\": \"gD+Afw==\"}, \
+                                        \"x\": [{\"$tag\": 40202, \"value\": 1}]}").unwrap();
+        half::widen_json(&mut json);
+        assert_eq!(json.to_string(), "{\"e\":[1.0,null],\"x\":[{\"$tag\":40202,\"value\":1}]}");
+    }
+}
@@ -17,6 +17,8 @@ pub use self::FromHexError::*;
 use std::fmt;
 use std::error;
 
+use codec::Codec;
+
 /// A trait for converting a value to hexadecimal encoding
 pub trait ToHex {
     /// Converts the value of `self` to a hex value, returning the owned
@@ -167,15 +169,129 @@ impl<'a, T: ?Sized + FromHex> FromHex for &'a T {
     }
 }
 
+/// Returns the length of the hex encoding of `len` bytes.
+pub const fn encoded_len(len: usize) -> usize {
+    len * 2
+}
+
+/// Encodes `bytes` as lowercase hex, in a constant expression.
+///
+/// `M` must be `encoded_len(N)`; any other length fails to evaluate, which
+/// in a constant is a compile-time error.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::hex;
+///
+/// const BUILD_ID: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+/// const BUILD_ID_HEX: [u8; hex::encoded_len(4)] = hex::hex_const(&BUILD_ID);
+///
+/// fn main() {
+///     assert_eq!(&BUILD_ID_HEX, b"deadbeef");
+/// }
+/// ```
+pub const fn hex_const<const N: usize, const M: usize>(bytes: &[u8; N]) -> [u8; M] {
+    // `CHARS` is a static, which constant functions cannot read.
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    // Fails if `M` is not twice `N`: `assert!` cannot be used in constant
+    // functions before Rust 1.57.
+    let _ = [0][(M != encoded_len(N)) as usize];
+    let mut out = [0; M];
+    let mut i = 0;
+    while i < N {
+        out[2 * i] = DIGITS[(bytes[i] >> 4) as usize];
+        out[2 * i + 1] = DIGITS[(bytes[i] & 0xf) as usize];
+        i += 1;
+    }
+    out
+}
+
+/// A streaming hex `Codec`, for use with `codec::CodecReader` and
+/// `codec::CodecWriter`.
+///
+/// Decoding follows the rules of `FromHex`: whitespace is ignored and error
+/// positions are byte offsets from the start of the stream.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HexCodec {
+    // The high nibble of a partially decoded byte.
+    pending: Option<u8>,
+    pos: usize,
+}
+
+impl HexCodec {
+    /// Creates a new hex codec.
+    pub fn new() -> HexCodec {
+        HexCodec { pending: None, pos: 0 }
+    }
+}
+
+impl Codec for HexCodec {
+    type Error = FromHexError;
+
+    fn encode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), FromHexError> {
+        output.reserve(input.len() * 2);
+        for &byte in input.iter() {
+            output.push(CHARS[(byte >> 4) as usize]);
+            output.push(CHARS[(byte & 0xf) as usize]);
+        }
+        Ok(())
+    }
+
+    fn decode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), FromHexError> {
+        for (idx, &byte) in input.iter().enumerate() {
+            let nibble = match byte {
+                b'A'...b'F' => byte - b'A' + 10,
+                b'a'...b'f' => byte - b'a' + 10,
+                b'0'...b'9' => byte - b'0',
+                b' '|b'\r'|b'\n'|b'\t' => continue,
+                _ => {
+                    let ch = String::from_utf8_lossy(&input[idx..])
+                                 .chars().next().unwrap();
+                    return Err(InvalidHexCharacter(ch, self.pos + idx))
+                }
+            };
+            match self.pending.take() {
+                Some(high) => output.push(high << 4 | nibble),
+                None => self.pending = Some(nibble),
+            }
+        }
+        self.pos += input.len();
+        Ok(())
+    }
+
+    fn finish(&mut self, _output: &mut Vec<u8>) -> Result<(), FromHexError> {
+        match self.pending.take() {
+            Some(_) => Err(InvalidHexLength),
+            None => Ok(()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use hex::{FromHex, ToHex};
+    use hex::{hex_const, FromHex, ToHex};
 
     #[test]
     pub fn test_to_hex() {
         assert_eq!("foobar".as_bytes().to_hex(), "666f6f626172");
     }
 
+    #[test]
+    pub fn test_hex_const() {
+        const EMPTY: [u8; 0] = hex_const(&[]);
+        const FOOBAR: [u8; 12] = hex_const(b"foobar");
+        assert_eq!(EMPTY, [0u8; 0]);
+        assert_eq!(&FOOBAR, b"666f6f626172");
+        let all: Vec<u8> = (0..256).map(|b| b as u8).collect();
+        let mut bytes = [0; 256];
+        bytes.copy_from_slice(&all);
+        assert_eq!(&hex_const::<256, 512>(&bytes)[..], all.to_hex().as_bytes());
+    }
+
     #[test]
     pub fn test_from_hex_okay() {
         assert_eq!("666f6f626172".from_hex().unwrap(),
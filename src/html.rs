@@ -0,0 +1,289 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! HTML escaping and unescaping
+//!
+//! `escape` makes text safe to embed in HTML, as element content or as a
+//! quoted attribute value. `unescape` replaces character references with
+//! the characters they stand for as an HTML5 parser would in text: all the
+//! named references of HTML5 (including the legacy ones recognized without
+//! a `;`), and decimal and hexadecimal numeric references. Anything which
+//! is not a reference is left as it is.
+//!
+//! `escape_chars` and `unescape_chars` do the same to iterators of
+//! characters, for text which is not all in memory.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::html;
+//!
+//! fn main() {
+//!     assert_eq!(html::escape("<b>\"Tom & Jerry\"</b>"),
+//!                "&lt;b&gt;&quot;Tom &amp; Jerry&quot;&lt;/b&gt;");
+//!     assert_eq!(html::unescape("caf&eacute; &#x2615; &copy 2017 &bogus;"),
+//!                "caf\u{e9} \u{2615} \u{a9} 2017 &bogus;");
+//! }
+//! ```
+
+use std::char;
+use std::collections::VecDeque;
+use std::str;
+
+mod entities;
+
+use self::entities::ENTITIES;
+
+// The longest name in `ENTITIES`, with its `;`.
+const MAX_NAME_LEN: usize = 32;
+
+/// Escapes `&`, `<`, `>`, `"` and `'` in `s`.
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        if let Some(escaped) = escape_char(c) {
+            out.push_str(&s[start..i]);
+            out.push_str(escaped);
+            start = i + 1;
+        }
+    }
+    out.push_str(&s[start..]);
+    out
+}
+
+fn escape_char(c: char) -> Option<&'static str> {
+    match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '"' => Some("&quot;"),
+        '\'' => Some("&#39;"),
+        _ => None,
+    }
+}
+
+/// Replaces the character references in `s`.
+pub fn unescape(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+    unescape_chars(s.chars()).collect()
+}
+
+/// Returns an iterator over the characters of `iter`, escaped as by
+/// `escape`.
+pub fn escape_chars<I: IntoIterator<Item = char>>(iter: I) -> EscapeChars<I::IntoIter> {
+    EscapeChars { iter: iter.into_iter(), pending: "".chars() }
+}
+
+/// Returns an iterator over the characters of `iter` with character
+/// references replaced, as by `unescape`.
+///
+/// At most a reference's length of characters are read ahead.
+pub fn unescape_chars<I: IntoIterator<Item = char>>(iter: I) -> UnescapeChars<I::IntoIter> {
+    UnescapeChars { iter: iter.into_iter(), ahead: VecDeque::new(), pending: None }
+}
+
+/// The iterator returned by `escape_chars`.
+pub struct EscapeChars<I> {
+    iter: I,
+    pending: str::Chars<'static>,
+}
+
+impl<I: Iterator<Item = char>> Iterator for EscapeChars<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if let Some(c) = self.pending.next() {
+            return Some(c);
+        }
+        let c = match self.iter.next() {
+            Some(c) => c,
+            None => return None,
+        };
+        match escape_char(c) {
+            Some(escaped) => {
+                self.pending = escaped[1..].chars();
+                Some('&')
+            }
+            None => Some(c),
+        }
+    }
+}
+
+/// The iterator returned by `unescape_chars`.
+pub struct UnescapeChars<I> {
+    iter: I,
+    // Characters read ahead and not yet consumed.
+    ahead: VecDeque<char>,
+    // The second character of a reference which expands to two.
+    pending: Option<char>,
+}
+
+impl<I: Iterator<Item = char>> UnescapeChars<I> {
+    // Returns the character `i` places ahead, without consuming it.
+    fn peek(&mut self, i: usize) -> Option<char> {
+        while self.ahead.len() <= i {
+            match self.iter.next() {
+                Some(c) => self.ahead.push_back(c),
+                None => return None,
+            }
+        }
+        Some(self.ahead[i])
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        match self.ahead.pop_front() {
+            Some(c) => Some(c),
+            None => self.iter.next(),
+        }
+    }
+
+    // Replaces a numeric reference after its `&#`, returning `None` and
+    // consuming nothing if there are no digits.
+    fn numeric(&mut self) -> Option<char> {
+        let (radix, start) = match self.peek(1) {
+            Some('x') | Some('X') => (16, 2),
+            _ => (10, 1),
+        };
+        let mut len = 0;
+        let mut code: u32 = 0;
+        while let Some(d) = self.peek(start + len).and_then(|c| c.to_digit(radix)) {
+            // Anything this large is out of range; stop it overflowing.
+            code = (code * radix + d).min(0x110000);
+            len += 1;
+        }
+        if len == 0 {
+            return None;
+        }
+        for _ in 0..start + len {
+            self.bump();
+        }
+        if self.peek(0) == Some(';') {
+            self.bump();
+        }
+        Some(match code {
+            0x80...0x9f => WINDOWS_1252[code as usize - 0x80],
+            0 => '\u{fffd}',
+            _ => char::from_u32(code).unwrap_or('\u{fffd}'),
+        })
+    }
+
+    // Replaces the longest named reference after an `&`, returning `None`
+    // and consuming nothing if there is none.
+    fn named(&mut self) -> Option<&'static str> {
+        let mut name = String::new();
+        while name.len() < MAX_NAME_LEN {
+            match self.peek(name.len()) {
+                Some(c) if c.is_ascii_alphanumeric() => name.push(c),
+                Some(';') => {
+                    name.push(';');
+                    break;
+                }
+                _ => break,
+            }
+        }
+        while !name.is_empty() {
+            if let Ok(idx) = ENTITIES.binary_search_by(|e| e.0.cmp(&name)) {
+                for _ in 0..name.len() {
+                    self.bump();
+                }
+                return Some(ENTITIES[idx].1);
+            }
+            name.pop();
+        }
+        None
+    }
+}
+
+impl<I: Iterator<Item = char>> Iterator for UnescapeChars<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if let Some(c) = self.pending.take() {
+            return Some(c);
+        }
+        let c = self.bump();
+        if c != Some('&') {
+            return c;
+        }
+        if self.peek(0) == Some('#') {
+            return Some(self.numeric().unwrap_or('&'));
+        }
+        match self.named() {
+            Some(value) => {
+                let mut chars = value.chars();
+                let first = chars.next();
+                self.pending = chars.next();
+                first
+            }
+            None => Some('&'),
+        }
+    }
+}
+
+// What numeric references to 0x80-0x9F stand for: the windows-1252
+// characters with those codes, where there is one.
+static WINDOWS_1252: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+#[cfg(test)]
+mod tests {
+    use html::entities::ENTITIES;
+    use html::{escape, escape_chars, unescape, unescape_chars, MAX_NAME_LEN};
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape(""), "");
+        assert_eq!(escape("caf\u{e9}"), "caf\u{e9}");
+        assert_eq!(escape("a<'b'>&\"c\""), "a&lt;&#39;b&#39;&gt;&amp;&quot;c&quot;");
+        let s = "x < y && \u{1F600}";
+        assert_eq!(escape_chars(s.chars()).collect::<String>(), escape(s));
+        assert_eq!(unescape(&escape(s)), s);
+    }
+
+    #[test]
+    fn test_unescape() {
+        let cases = [("&amp;&lt;&gt;&quot;&apos;", "&<>\"'"),
+                     ("&AMP;", "&"),
+                     ("&notit;", "\u{ac}it;"),
+                     ("&notin;", "\u{2209}"),
+                     ("&not", "\u{ac}"),
+                     ("&nbsp&nbsp;", "\u{a0}\u{a0}"),
+                     ("&NotEqualTilde;", "\u{2242}\u{338}"),
+                     ("&CounterClockwiseContourIntegral;", "\u{2233}"),
+                     ("&nope; & &; &", "&nope; & &; &"),
+                     ("&#65;&#x42;&#X43&#68", "ABCD"),
+                     ("&#; &#x; &#xg;", "&#; &#x; &#xg;"),
+                     ("&#0; &#x110000; &#xd800; &#99999999999;", "\u{fffd} \u{fffd} \u{fffd} \u{fffd}"),
+                     ("&#128;&#x9f;&#x81;", "\u{20ac}\u{178}\u{81}"),
+                     ("&#x1F600;", "\u{1F600}")];
+        for &(input, expected) in cases.iter() {
+            assert_eq!(unescape(input), expected, "{}", input);
+            assert_eq!(unescape_chars(input.chars()).collect::<String>(), expected);
+        }
+    }
+
+    #[test]
+    fn test_entities() {
+        assert_eq!(ENTITIES.len(), 2231);
+        assert!(ENTITIES.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(ENTITIES.iter().map(|e| e.0.len()).max(), Some(MAX_NAME_LEN));
+        for &(name, value) in ENTITIES {
+            assert_eq!(unescape(&format!("&{}", name)), value);
+        }
+    }
+}
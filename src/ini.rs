@@ -0,0 +1,828 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! INI configuration files
+//!
+//! `Ini` holds the keys and values of a file in order, in sections, and
+//! writes them back out with `Display`. The syntax understood is:
+//!
+//! * `key = value` or `key: value`, with whitespace around both trimmed.
+//!   Keys before the first section header are in the general section,
+//!   whose name is `""`.
+//! * `[section]` headers.
+//! * Comment lines starting with `;` or `#`.
+//! * A `\` at the end of a line, which continues the line on the next one
+//!   with the next line's leading whitespace removed.
+//! * The escapes `\\`, `\;`, `\#`, `\=`, `\:`, `\[`, `\]`, `\t`, `\r`,
+//!   `\n`, `\0` and `\xHHHH`, for the character with that hex code.
+//!
+//! `decode` fills in a `Decodable` struct from a file: its fields are the
+//! keys of the general section, and fields which are structs or maps are
+//! sections. Values are parsed as the field's type, a sequence being a
+//! comma-separated list.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::ini::Ini;
+//!
+//! fn main() {
+//!     let mut ini = Ini::parse("name = demo\n\n[server]\nhost = a.example\n").unwrap();
+//!     assert_eq!(ini.get("server", "host"), Some("a.example"));
+//!     ini.set("server", "port", "8080");
+//!     assert_eq!(ini.to_string(), "name = demo\n\n[server]\nhost = a.example\nport = 8080\n");
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::slice;
+
+use Decodable;
+
+/// A section of an INI file: its keys and values, in order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    name: String,
+    entries: Vec<(String, String)>,
+}
+
+impl Section {
+    /// Creates an empty section.
+    pub fn new(name: &str) -> Section {
+        Section { name: name.to_string(), entries: Vec::new() }
+    }
+
+    /// Returns the name of this section.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.iter().find(|e| e.0 == key).map(|e| &e.1[..])
+    }
+
+    /// Sets the value of `key`, replacing any value it had in place, or
+    /// else adding it at the end.
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.entries.iter_mut().find(|e| e.0 == key) {
+            Some(entry) => entry.1 = value.to_string(),
+            None => self.entries.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// Removes `key`, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        match self.entries.iter().position(|e| e.0 == key) {
+            Some(idx) => Some(self.entries.remove(idx).1),
+            None => None,
+        }
+    }
+
+    /// Returns the number of keys.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether there are no keys.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns an iterator over the keys and values, in order.
+    pub fn iter(&self) -> Entries<'_> {
+        Entries { iter: self.entries.iter() }
+    }
+}
+
+/// An iterator over the keys and values of a `Section`.
+pub struct Entries<'a> {
+    iter: slice::Iter<'a, (String, String)>,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        self.iter.next().map(|e| (&e.0[..], &e.1[..]))
+    }
+}
+
+/// The sections of an INI file, in order, starting with the general
+/// section.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ini {
+    sections: Vec<Section>,
+}
+
+/// Errors that can occur parsing an INI file, with their line numbers
+/// (starting from 1).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IniError {
+    /// A section header had no closing `]`
+    UnterminatedSection(usize),
+    /// A line was neither a section header nor had a `=` or `:`
+    MissingSeparator(usize),
+    /// A line had an empty key
+    EmptyKey(usize),
+    /// A line had an unknown or malformed escape
+    InvalidEscape(usize),
+}
+
+impl fmt::Display for IniError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IniError::UnterminatedSection(line) => write!(f, "line {}: missing `]`", line),
+            IniError::MissingSeparator(line) => write!(f, "line {}: missing `=`", line),
+            IniError::EmptyKey(line) => write!(f, "line {}: empty key", line),
+            IniError::InvalidEscape(line) => write!(f, "line {}: invalid escape", line),
+        }
+    }
+}
+
+impl error::Error for IniError {
+    fn description(&self) -> &str { "invalid INI file" }
+}
+
+impl Ini {
+    /// Creates an INI file with only an empty general section.
+    pub fn new() -> Ini {
+        Ini { sections: vec![Section::new("")] }
+    }
+
+    /// Parses an INI file. Keys which appear twice in a section keep the
+    /// last value, and sections which appear twice are merged.
+    pub fn parse(s: &str) -> Result<Ini, IniError> {
+        let mut ini = Ini::new();
+        let mut current = 0;
+        let mut lines = s.lines().enumerate();
+        while let Some((idx, line)) = lines.next() {
+            let lineno = idx + 1;
+            let mut line = line.to_string();
+            while continues(&line) {
+                line.pop();
+                match lines.next() {
+                    Some((_, next)) => line.push_str(next.trim_start()),
+                    None => break,
+                }
+            }
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                if !line.ends_with(']') || line.len() < 2 {
+                    return Err(IniError::UnterminatedSection(lineno));
+                }
+                let name = try!(unescape(line[1..line.len() - 1].trim(), lineno));
+                current = match ini.sections.iter().position(|s| s.name == name) {
+                    Some(idx) => idx,
+                    None => {
+                        ini.sections.push(Section::new(&name));
+                        ini.sections.len() - 1
+                    }
+                };
+                continue;
+            }
+            let sep = match separator(line) {
+                Some(sep) => sep,
+                None => return Err(IniError::MissingSeparator(lineno)),
+            };
+            let key = try!(unescape(line[..sep].trim(), lineno));
+            let value = try!(unescape(line[sep + 1..].trim(), lineno));
+            if key.is_empty() {
+                return Err(IniError::EmptyKey(lineno));
+            }
+            ini.sections[current].set(&key, &value);
+        }
+        Ok(ini)
+    }
+
+    /// Returns the section `name`.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// Returns the section `name`, adding an empty one at the end if there
+    /// is none.
+    pub fn section_mut(&mut self, name: &str) -> &mut Section {
+        let idx = match self.sections.iter().position(|s| s.name == name) {
+            Some(idx) => idx,
+            None => {
+                self.sections.push(Section::new(name));
+                self.sections.len() - 1
+            }
+        };
+        &mut self.sections[idx]
+    }
+
+    /// Returns the general section.
+    pub fn general(&self) -> &Section {
+        &self.sections[0]
+    }
+
+    /// Returns the value of `key` in section `section`.
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.section(section).and_then(|s| s.get(key))
+    }
+
+    /// Sets the value of `key` in section `section`; see `Section::set`.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        self.section_mut(section).set(key, value)
+    }
+
+    /// Returns an iterator over the sections, in order, starting with the
+    /// general section.
+    pub fn sections(&self) -> slice::Iter<'_, Section> {
+        self.sections.iter()
+    }
+}
+
+impl Default for Ini {
+    fn default() -> Ini { Ini::new() }
+}
+
+impl fmt::Display for Ini {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for section in &self.sections {
+            if !section.name.is_empty() {
+                if !first {
+                    try!(f.write_str("\n"));
+                }
+                try!(write!(f, "[{}]\n", escape(&section.name)));
+            } else if section.is_empty() {
+                continue;
+            }
+            for (key, value) in section.iter() {
+                try!(write!(f, "{} = {}\n", escape(key), escape(value)));
+            }
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+// Whether `line` ends with an unescaped `\`.
+fn continues(line: &str) -> bool {
+    line.bytes().rev().take_while(|&b| b == b'\\').count() % 2 == 1
+}
+
+// Returns the index of the first unescaped `=` or `:` in `line`.
+fn separator(line: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, b) in line.bytes().enumerate() {
+        match b {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            b'=' | b':' => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unescape(s: &str, lineno: usize) -> Result<String, IniError> {
+    if !s.contains('\\') {
+        return Ok(s.to_string());
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let c = match chars.next() {
+            Some(c @ '\\') | Some(c @ ';') | Some(c @ '#') | Some(c @ '=') | Some(c @ ':') |
+            Some(c @ '[') | Some(c @ ']') => c,
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('n') => '\n',
+            Some('0') => '\0',
+            Some('x') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let code = if hex.len() == 4 { u32::from_str_radix(&hex, 16).ok() } else { None };
+                match code.and_then(::std::char::from_u32) {
+                    Some(c) => c,
+                    None => return Err(IniError::InvalidEscape(lineno)),
+                }
+            }
+            _ => return Err(IniError::InvalidEscape(lineno)),
+        };
+        out.push(c);
+    }
+    Ok(out)
+}
+
+fn escape(s: &str) -> String {
+    let last = s.chars().count().saturating_sub(1);
+    let mut out = String::with_capacity(s.len());
+    for (i, c) in s.chars().enumerate() {
+        match c {
+            '\\' | ';' | '#' | '=' | ':' | '[' | ']' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\n' => out.push_str("\\n"),
+            '\0' => out.push_str("\\0"),
+            // Whitespace at either end would be trimmed, and other control
+            // characters have no place in a text file.
+            _ if c.is_control() || (c.is_whitespace() && (i == 0 || i == last)) => {
+                out.push_str(&format!("\\x{:04x}", c as u32))
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Errors that can occur when decoding an INI file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecoderError {
+    ParseError(IniError),
+    ExpectedError(String, String),
+    MissingFieldError(String),
+    UnknownVariantError(String),
+    ApplicationError(String),
+    EOF,
+}
+
+impl fmt::Display for DecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecoderError::ParseError(ref e) => write!(f, "{}", e),
+            DecoderError::ExpectedError(ref expected, ref found) => {
+                write!(f, "expected {}, found {:?}", expected, found)
+            }
+            DecoderError::MissingFieldError(ref name) => write!(f, "missing key `{}`", name),
+            DecoderError::UnknownVariantError(ref name) => {
+                write!(f, "unknown variant `{}`", name)
+            }
+            DecoderError::ApplicationError(ref msg) => write!(f, "{}", msg),
+            DecoderError::EOF => write!(f, "no value left to decode"),
+        }
+    }
+}
+
+impl error::Error for DecoderError {
+    fn description(&self) -> &str { "failed to decode INI file" }
+}
+
+impl From<IniError> for DecoderError {
+    fn from(err: IniError) -> DecoderError { DecoderError::ParseError(err) }
+}
+
+pub type DecodeResult<T> = Result<T, DecoderError>;
+
+/// Decodes a value from an INI file.
+pub fn decode<T: Decodable>(s: &str) -> DecodeResult<T> {
+    let ini = try!(Ini::parse(s));
+    Decodable::decode(&mut Decoder::new(&ini))
+}
+
+enum Item {
+    Value(String),
+    Table(Vec<(String, Item)>),
+}
+
+fn table(section: &Section) -> Item {
+    Item::Table(section.entries.iter().map(|e| (e.0.clone(), Item::Value(e.1.clone()))).collect())
+}
+
+/// A decoder reading from an `Ini`.
+///
+/// The general section's keys and the other sections are one map, so a
+/// struct's fields are looked up among both.
+pub struct Decoder {
+    stack: Vec<Item>,
+}
+
+impl Decoder {
+    /// Creates a decoder for the contents of `ini`.
+    pub fn new(ini: &Ini) -> Decoder {
+        let mut root = match table(&ini.sections[0]) {
+            Item::Table(entries) => entries,
+            Item::Value(_) => unreachable!(),
+        };
+        for section in &ini.sections[1..] {
+            root.push((section.name.clone(), table(section)));
+        }
+        Decoder { stack: vec![Item::Table(root)] }
+    }
+
+    fn pop(&mut self) -> DecodeResult<Item> {
+        self.stack.pop().ok_or(DecoderError::EOF)
+    }
+
+    fn pop_table(&mut self) -> DecodeResult<Vec<(String, Item)>> {
+        match try!(self.pop()) {
+            Item::Table(entries) => Ok(entries),
+            Item::Value(v) => Err(DecoderError::ExpectedError("section".to_string(), v)),
+        }
+    }
+
+    fn pop_value(&mut self) -> DecodeResult<String> {
+        match try!(self.pop()) {
+            Item::Value(v) => Ok(v),
+            Item::Table(_) => {
+                Err(DecoderError::ExpectedError("value".to_string(), "section".to_string()))
+            }
+        }
+    }
+
+    fn parse<T: ::std::str::FromStr>(&mut self, expected: &str) -> DecodeResult<T> {
+        let v = try!(self.pop_value());
+        match v.parse() {
+            Ok(x) => Ok(x),
+            Err(_) => Err(DecoderError::ExpectedError(expected.to_string(), v)),
+        }
+    }
+}
+
+macro_rules! read_primitive {
+    ($name:ident, $ty:ty) => {
+        fn $name(&mut self) -> DecodeResult<$ty> {
+            self.parse(stringify!($ty))
+        }
+    }
+}
+
+impl ::Decoder for Decoder {
+    type Error = DecoderError;
+
+    fn read_nil(&mut self) -> DecodeResult<()> {
+        let v = try!(self.pop_value());
+        if v.is_empty() {
+            Ok(())
+        } else {
+            Err(DecoderError::ExpectedError("empty value".to_string(), v))
+        }
+    }
+
+    read_primitive! { read_usize, usize }
+    read_primitive! { read_u8, u8 }
+    read_primitive! { read_u16, u16 }
+    read_primitive! { read_u32, u32 }
+    read_primitive! { read_u64, u64 }
+    read_primitive! { read_isize, isize }
+    read_primitive! { read_i8, i8 }
+    read_primitive! { read_i16, i16 }
+    read_primitive! { read_i32, i32 }
+    read_primitive! { read_i64, i64 }
+    read_primitive! { read_u128, u128 }
+    read_primitive! { read_i128, i128 }
+    read_primitive! { read_f32, f32 }
+    read_primitive! { read_f64, f64 }
+
+    fn read_bool(&mut self) -> DecodeResult<bool> {
+        let v = try!(self.pop_value());
+        match &v.to_ascii_lowercase()[..] {
+            "true" | "yes" | "on" | "1" => Ok(true),
+            "false" | "no" | "off" | "0" => Ok(false),
+            _ => Err(DecoderError::ExpectedError("boolean".to_string(), v)),
+        }
+    }
+
+    fn read_char(&mut self) -> DecodeResult<char> {
+        let v = try!(self.pop_value());
+        let mut chars = v.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(DecoderError::ExpectedError("single character".to_string(), v)),
+        }
+    }
+
+    fn read_str(&mut self) -> DecodeResult<String> {
+        self.pop_value()
+    }
+
+    fn read_enum<T, F>(&mut self, _name: &str, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_enum_variant<T, F>(&mut self, names: &[&str], mut f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder, usize) -> DecodeResult<T>,
+    {
+        let name = try!(self.pop_value());
+        match names.iter().position(|n| *n == name) {
+            Some(idx) => f(self, idx),
+            None => Err(DecoderError::UnknownVariantError(name)),
+        }
+    }
+
+    fn read_enum_variant_arg<T, F>(&mut self, _idx: usize, _f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        Err(self.error("enum variants with fields are not supported"))
+    }
+
+    fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder, usize) -> DecodeResult<T>,
+    {
+        self.read_enum_variant(names, f)
+    }
+
+    fn read_enum_struct_variant_field<T, F>(&mut self, _name: &str, idx: usize, f: F)
+                                            -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_enum_variant_arg(idx, f)
+    }
+
+    fn read_struct<T, F>(&mut self, _name: &str, _len: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        let entries = try!(self.pop_table());
+        self.stack.push(Item::Table(entries));
+        let value = try!(f(self));
+        try!(self.pop());
+        Ok(value)
+    }
+
+    fn read_struct_field<T, F>(&mut self, name: &str, _idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        let mut entries = try!(self.pop_table());
+        let value = match entries.iter().position(|e| e.0 == name) {
+            Some(idx) => {
+                let (_, item) = entries.remove(idx);
+                self.stack.push(item);
+                try!(f(self))
+            }
+            None => {
+                // A missing key decodes as an `Option`'s None.
+                self.stack.push(Item::Table(Vec::new()));
+                match f(self) {
+                    Ok(x) => x,
+                    Err(_) => return Err(DecoderError::MissingFieldError(name.to_string())),
+                }
+            }
+        };
+        self.stack.push(Item::Table(entries));
+        Ok(value)
+    }
+
+    fn read_tuple<T, F>(&mut self, tuple_len: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_seq(move |d, len| {
+            if len == tuple_len {
+                f(d)
+            } else {
+                Err(DecoderError::ExpectedError(format!("{} values", tuple_len),
+                                                format!("{} values", len)))
+            }
+        })
+    }
+
+    fn read_tuple_arg<T, F>(&mut self, idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_seq_elt(idx, f)
+    }
+
+    fn read_tuple_struct<T, F>(&mut self, _name: &str, len: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_tuple(len, f)
+    }
+
+    fn read_tuple_struct_arg<T, F>(&mut self, idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_tuple_arg(idx, f)
+    }
+
+    fn read_option<T, F>(&mut self, mut f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder, bool) -> DecodeResult<T>,
+    {
+        match try!(self.pop()) {
+            // Pushed by `read_struct_field` for a missing key.
+            Item::Table(ref entries) if entries.is_empty() => f(self, false),
+            item => {
+                self.stack.push(item);
+                f(self, true)
+            }
+        }
+    }
+
+    fn read_seq<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder, usize) -> DecodeResult<T>,
+    {
+        let v = try!(self.pop_value());
+        let items: Vec<&str> = if v.trim().is_empty() {
+            Vec::new()
+        } else {
+            v.split(',').map(|s| s.trim()).collect()
+        };
+        let len = items.len();
+        for item in items.into_iter().rev() {
+            self.stack.push(Item::Value(item.to_string()));
+        }
+        f(self, len)
+    }
+
+    fn read_seq_elt<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_map<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder, usize) -> DecodeResult<T>,
+    {
+        let entries = try!(self.pop_table());
+        let len = entries.len();
+        for (key, item) in entries.into_iter().rev() {
+            self.stack.push(item);
+            self.stack.push(Item::Value(key));
+        }
+        f(self, len)
+    }
+
+    fn read_map_elt_key<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_map_elt_val<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn error(&mut self, err: &str) -> DecoderError {
+        DecoderError::ApplicationError(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use ini::{decode, DecoderError, Ini, IniError};
+    use {Decodable, Decoder};
+
+    #[test]
+    fn test_parse() {
+        let text = "; comment\ntop = 1\n\n[a]\nx = one \\\n    two\n# comment\ny: \\x0020 \\; \
+                    \\= \\\\\nx = three\n[b]\n[a]\nz=\n";
+        let ini = Ini::parse(text).unwrap();
+        assert_eq!(ini.get("", "top"), Some("1"));
+        assert_eq!(ini.get("a", "x"), Some("three"));
+        assert_eq!(ini.get("a", "y"), Some("  ; = \\"));
+        assert_eq!(ini.get("a", "z"), Some(""));
+        assert_eq!(ini.get("b", "x"), None);
+        assert_eq!(ini.sections().map(|s| s.name()).collect::<Vec<_>>(), ["", "a", "b"]);
+        let a = ini.section("a").unwrap();
+        assert_eq!(a.iter().collect::<Vec<_>>(), [("x", "three"), ("y", "  ; = \\"), ("z", "")]);
+
+        assert_eq!(Ini::parse("k = v \\\n w").unwrap().get("", "k"), Some("v w"));
+        assert_eq!(Ini::parse("k = \\\\\nj = 2").unwrap().get("", "j"), Some("2"));
+
+        assert_eq!(Ini::parse("\n[a"), Err(IniError::UnterminatedSection(2)));
+        assert_eq!(Ini::parse("a"), Err(IniError::MissingSeparator(1)));
+        assert_eq!(Ini::parse(" = a"), Err(IniError::EmptyKey(1)));
+        assert_eq!(Ini::parse("a = \\q"), Err(IniError::InvalidEscape(1)));
+        assert_eq!(Ini::parse("a = \\x12"), Err(IniError::InvalidEscape(1)));
+    }
+
+    #[test]
+    fn test_write() {
+        let mut ini = Ini::new();
+        ini.set("s", "k=1", " [x]; #y\n ");
+        ini.set("s", "tab", "\t\u{7}");
+        ini.set("", "g", "v");
+        let text = ini.to_string();
+        assert_eq!(text, "g = v\n\n[s]\nk\\=1 = \\x0020\\[x\\]\\; \\#y\\n\\x0020\ntab = \\t\\x0007\n");
+        assert_eq!(Ini::parse(&text).unwrap(), ini);
+        assert_eq!(ini.section_mut("s").remove("tab"), Some("\t\u{7}".to_string()));
+        assert_eq!(Ini::new().to_string(), "");
+    }
+
+    #[derive(PartialEq, Debug)]
+    enum Level {
+        Debug,
+        Info,
+    }
+
+    impl Decodable for Level {
+        fn decode<D: Decoder>(d: &mut D) -> Result<Level, D::Error> {
+            d.read_enum("Level", |d| {
+                d.read_enum_variant(&["Debug", "Info"], |_, idx| {
+                    match idx {
+                        0 => Ok(Level::Debug),
+                        1 => Ok(Level::Info),
+                        _ => panic!("Internal error"),
+                    }
+                })
+            })
+        }
+    }
+
+    #[derive(PartialEq, Debug)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    impl Decodable for Server {
+        fn decode<D: Decoder>(d: &mut D) -> Result<Server, D::Error> {
+            d.read_struct("Server", 2, |d| {
+                Ok(Server {
+                    host: try!(d.read_struct_field("host", 0, Decodable::decode)),
+                    port: try!(d.read_struct_field("port", 1, Decodable::decode)),
+                })
+            })
+        }
+    }
+
+    #[derive(PartialEq, Debug)]
+    struct Config {
+        name: String,
+        verbose: bool,
+        level: Level,
+        ratio: Option<f64>,
+        tags: Vec<String>,
+        server: Server,
+        env: BTreeMap<String, String>,
+    }
+
+    impl Decodable for Config {
+        fn decode<D: Decoder>(d: &mut D) -> Result<Config, D::Error> {
+            d.read_struct("Config", 7, |d| {
+                Ok(Config {
+                    name: try!(d.read_struct_field("name", 0, Decodable::decode)),
+                    verbose: try!(d.read_struct_field("verbose", 1, Decodable::decode)),
+                    level: try!(d.read_struct_field("level", 2, Decodable::decode)),
+                    ratio: try!(d.read_struct_field("ratio", 3, Decodable::decode)),
+                    tags: try!(d.read_struct_field("tags", 4, Decodable::decode)),
+                    server: try!(d.read_struct_field("server", 5, Decodable::decode)),
+                    env: try!(d.read_struct_field("env", 6, Decodable::decode)),
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let text = "name = demo\nverbose = yes\nlevel = Info\ntags = a, b\n\
+                    [server]\nhost = localhost\nport = 80\n[env]\nPATH = /bin\n";
+        let mut env = BTreeMap::new();
+        env.insert("PATH".to_string(), "/bin".to_string());
+        assert_eq!(decode::<Config>(text).unwrap(), Config {
+            name: "demo".to_string(),
+            verbose: true,
+            level: Level::Info,
+            ratio: None,
+            tags: vec!["a".to_string(), "b".to_string()],
+            server: Server { host: "localhost".to_string(), port: 80 },
+            env: env,
+        });
+
+        let bad = text.replace("port = 80", "port = http");
+        assert_eq!(decode::<Config>(&bad),
+                   Err(DecoderError::ExpectedError("u16".to_string(), "http".to_string())));
+        let bad = text.replace("level = Info", "level = Trace");
+        assert_eq!(decode::<Config>(&bad),
+                   Err(DecoderError::UnknownVariantError("Trace".to_string())));
+        let bad = text.replace("name = demo", "");
+        assert_eq!(decode::<Config>(&bad),
+                   Err(DecoderError::MissingFieldError("name".to_string())));
+        assert_eq!(decode::<Config>("x"),
+                   Err(DecoderError::ParseError(IniError::MissingSeparator(1))));
+        assert_eq!(decode::<Level>("[a]").unwrap_err(),
+                   DecoderError::ExpectedError("value".to_string(), "section".to_string()));
+
+        #[derive(PartialEq, Debug)]
+        struct Wide { max: u128, min: i128 }
+
+        impl Decodable for Wide {
+            fn decode<D: Decoder>(d: &mut D) -> Result<Wide, D::Error> {
+                d.read_struct("Wide", 2, |d| {
+                    Ok(Wide {
+                        max: try!(d.read_struct_field("max", 0, Decodable::decode)),
+                        min: try!(d.read_struct_field("min", 1, Decodable::decode)),
+                    })
+                })
+            }
+        }
+        let text = format!("max = {}\nmin = {}\n", u128::MAX, i128::MIN);
+        assert_eq!(decode::<Wide>(&text).unwrap(),
+                   Wide { max: u128::MAX, min: i128::MIN });
+    }
+}
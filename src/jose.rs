@@ -0,0 +1,231 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The encoding parts of JOSE: JWS compact tokens (RFC 7515), and so JWTs
+//! (RFC 7519), as JSON
+//!
+//! `Token::parse` splits a `header.payload.signature` token and parses its
+//! header and payload as JSON, so that they can be inspected;
+//! `Token::to_compact` serializes one again. Nothing here signs or verifies
+//! signatures: the signature is left as bytes for the caller to check
+//! against the algorithm it expects.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::jose::Token;
+//!
+//! fn main() {
+//!     let jwt = "eyJhbGciOiJIUzI1NiJ9.eyJhZG1pbiI6dHJ1ZSwic3ViIjoiam9lIn0.c2ln";
+//!     let token = Token::parse(jwt).unwrap();
+//!     assert_eq!(token.alg(), Some("HS256"));
+//!     assert_eq!(token.claim("sub").and_then(|s| s.as_string()), Some("joe"));
+//!     assert_eq!(token.signature, b"sig");
+//!     assert_eq!(token.to_compact(), jwt);
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+
+use Decodable;
+use base64::{self, JwsError};
+use json::{self, Json, Object, ParserError};
+
+/// A JWS compact token with its header and payload parsed as JSON
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    /// The protected header, always a JSON object
+    pub header: Json,
+    /// The payload: for a JWT, an object of claims
+    pub payload: Json,
+    /// The signature, undecoded from base64url but otherwise uninterpreted
+    pub signature: Vec<u8>,
+}
+
+/// Errors that can occur parsing a token
+#[derive(Debug)]
+pub enum JoseError {
+    /// The token was not three parts of base64url
+    Jws(JwsError),
+    /// The header was not JSON
+    InvalidHeader(ParserError),
+    /// The header was JSON, but not an object
+    HeaderNotAnObject,
+    /// The payload was not JSON
+    InvalidPayload(ParserError),
+}
+
+impl fmt::Display for JoseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JoseError::Jws(ref e) => fmt::Display::fmt(e, f),
+            JoseError::InvalidHeader(ref e) => write!(f, "invalid header: {}", e),
+            JoseError::HeaderNotAnObject => write!(f, "header is not a JSON object"),
+            JoseError::InvalidPayload(ref e) => write!(f, "invalid payload: {}", e),
+        }
+    }
+}
+
+impl error::Error for JoseError {
+    fn description(&self) -> &str { "invalid JOSE token" }
+}
+
+impl From<JwsError> for JoseError {
+    fn from(e: JwsError) -> JoseError { JoseError::Jws(e) }
+}
+
+impl Token {
+    /// Creates an unsigned token, with an empty signature.
+    pub fn new(header: Object, payload: Json) -> Token {
+        Token { header: Json::Object(header), payload: payload, signature: Vec::new() }
+    }
+
+    /// Parses a JWS compact token.
+    ///
+    /// A signature must be verified over the token's original signing
+    /// input, `base64::split_jws(token).signing_input`. JSON can be written
+    /// more than one way, so `Token::signing_input` need not reproduce it.
+    pub fn parse(token: &str) -> Result<Token, JoseError> {
+        let parts = try!(base64::split_jws(token));
+        let header = try!(Json::from_reader(&mut &parts.header[..])
+                              .map_err(JoseError::InvalidHeader));
+        if !header.is_object() {
+            return Err(JoseError::HeaderNotAnObject);
+        }
+        let payload = try!(Json::from_reader(&mut &parts.payload[..])
+                               .map_err(JoseError::InvalidPayload));
+        Ok(Token { header: header, payload: payload, signature: parts.signature })
+    }
+
+    /// Returns the header parameter `name`, if there is one.
+    pub fn header_param(&self, name: &str) -> Option<&Json> {
+        self.header.find(name)
+    }
+
+    /// Returns the `alg` header parameter, if it is a string.
+    pub fn alg(&self) -> Option<&str> {
+        self.header_param("alg").and_then(|alg| alg.as_string())
+    }
+
+    /// Returns the claim `name`, if the payload is an object which has it.
+    pub fn claim(&self, name: &str) -> Option<&Json> {
+        self.payload.find(name)
+    }
+
+    /// Decodes the payload, as `json::decode` would.
+    pub fn decode_claims<T: Decodable>(&self) -> json::DecodeResult<T> {
+        let mut decoder = json::Decoder::new(self.payload.clone());
+        Decodable::decode(&mut decoder)
+    }
+
+    /// Serializes the header and payload as `header.payload`, the input to
+    /// sign when creating a token.
+    pub fn signing_input(&self) -> String {
+        base64::jws_signing_input(self.header.to_string().as_bytes(),
+                                  self.payload.to_string().as_bytes())
+    }
+
+    /// Serializes the token as `header.payload.signature`.
+    pub fn to_compact(&self) -> String {
+        base64::join_jws(self.header.to_string().as_bytes(),
+                         self.payload.to_string().as_bytes(),
+                         &self.signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{self, JwsError};
+    use jose::{JoseError, Token};
+    use json::{Json, Object};
+    use {Decodable, Decoder};
+
+    #[derive(Debug, PartialEq)]
+    struct Claims {
+        iss: String,
+        exp: u64,
+        sub: Option<String>,
+    }
+
+    impl Decodable for Claims {
+        fn decode<D: Decoder>(d: &mut D) -> Result<Claims, D::Error> {
+            d.read_struct("Claims", 3, |d| {
+                Ok(Claims {
+                    iss: try!(d.read_struct_field("iss", 0, Decodable::decode)),
+                    exp: try!(d.read_struct_field("exp", 1, Decodable::decode)),
+                    sub: try!(d.read_struct_field("sub", 2, Decodable::decode)),
+                })
+            })
+        }
+    }
+
+    // RFC 7515 appendix A.1
+    const TOKEN: &str = "eyJ0eXAiOiJKV1QiLA0KICJhbGciOiJIUzI1NiJ9\
+                         .eyJpc3MiOiJqb2UiLA0KICJleHAiOjEzMDA4MTkzODAsDQogImh0dHA6Ly9le\
+                         GFtcGxlLmNvbS9pc19yb290Ijp0cnVlfQ\
+                         .dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+
+    #[test]
+    fn test_parse() {
+        let token = Token::parse(TOKEN).unwrap();
+        assert_eq!(token.alg(), Some("HS256"));
+        assert_eq!(token.header_param("typ"), Some(&Json::String("JWT".into())));
+        assert_eq!(token.claim("http://example.com/is_root"), Some(&Json::Boolean(true)));
+        assert_eq!(token.claim("nope"), None);
+        assert_eq!(token.signature.len(), 32);
+        assert_eq!(token.decode_claims::<Claims>().unwrap(),
+                   Claims { iss: "joe".to_string(), exp: 1300819380, sub: None });
+
+        // Re-serializing drops the original whitespace, so the signing
+        // input changes, but the token still parses to the same values.
+        let compact = token.to_compact();
+        assert!(compact != TOKEN);
+        assert_eq!(Token::parse(&compact).unwrap(), token);
+        assert!(compact.starts_with(&token.signing_input()));
+        assert!(base64::split_jws(TOKEN).unwrap().signing_input != token.signing_input());
+    }
+
+    #[test]
+    fn test_new() {
+        let mut header = Object::new();
+        header.insert("alg".to_string(), Json::String("none".into()));
+        let token = Token::new(header, Json::from_str("{\"sub\":\"joe\"}").unwrap());
+        assert_eq!(token.to_compact(), "eyJhbGciOiJub25lIn0.eyJzdWIiOiJqb2UifQ.");
+        assert_eq!(Token::parse("eyJhbGciOiJub25lIn0.eyJzdWIiOiJqb2UifQ.").unwrap(), token);
+    }
+
+    #[test]
+    fn test_errors() {
+        match Token::parse("a.b") {
+            Err(JoseError::Jws(JwsError::WrongPartCount(2))) => {}
+            other => panic!("{:?}", other),
+        }
+        // `[1]` and `{"a":1}`
+        match Token::parse("WzFd.eyJhIjoxfQ.") {
+            Err(JoseError::HeaderNotAnObject) => {}
+            other => panic!("{:?}", other),
+        }
+        // `{}` and `{`
+        match Token::parse("e30.ew.") {
+            Err(JoseError::InvalidPayload(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        // `\xff` and `{}`
+        match Token::parse("_w.e30.") {
+            Err(JoseError::InvalidHeader(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        // A payload need not be an object for `parse`.
+        assert_eq!(Token::parse("e30.WzFd.").unwrap().payload,
+                   Json::Array(vec![Json::U64(1)]));
+    }
+}
@@ -244,17 +244,51 @@ use self::DecoderError::*;
 use self::ParserState::*;
 use self::InternalStackElement::*;
 
+use std::any::Any;
+use std::borrow::Cow;
 use std::collections::{HashMap, BTreeMap};
+use std::collections::btree_map::Entry;
 use std::error::Error as StdError;
 use std::i64;
 use std::io::prelude::*;
-use std::mem::swap;
+use std::mem::{self, swap};
 use std::ops::Index;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::string;
-use std::{char, f64, fmt, io, str};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use std::{char, cmp, f64, fmt, io, str};
 
 use Encodable;
+use case::Case;
+use half::{self, Quantization};
+use limit;
+#[cfg(feature = "mmap")]
+use mmap::Mmap;
+use progress::Progress;
+use utf8::{CharIndicesBuffer, ControlStyle, EscapeControls, ExpandTabs, InvalidUtf8};
+use wtf8::Wtf8Buf;
+
+pub mod arc;
+pub mod codegen;
+pub mod edit;
+pub mod format;
+pub mod include;
+pub mod openapi;
+pub mod overlay;
+pub mod query;
+pub mod recover;
+pub mod refs;
+pub mod schema;
+#[cfg(feature = "small_strings")]
+pub mod small_string;
+pub mod spanned;
+pub mod tape;
+pub mod testing;
+pub mod visit;
 
 /// Represents a json value
 #[derive(Clone, PartialEq, PartialOrd, Debug)]
@@ -267,6 +301,9 @@ pub enum Json {
     Array(self::Array),
     Object(self::Object),
     Null,
+    /// A string holding lone surrogates, as a parser set to
+    /// `SurrogatePolicy::Wtf8` keeps them; other strings are `String`s.
+    Wtf8(Wtf8Buf),
 }
 
 pub type Array = Vec<Json>;
@@ -274,6 +311,20 @@ pub type Object = BTreeMap<string::String, Json>;
 
 pub struct PrettyJson<'a> { inner: &'a Json }
 
+/// A `Json` value with comments attached to some of its nodes, displayed as
+/// pretty-printed JSON with `//` comments (sometimes called JSONC).
+///
+/// Comments are keyed by the JSON Pointer (RFC 6901) of the node they
+/// describe: `""` is the whole document, `"/servers/0/port"` is the `port`
+/// member of the first element of `servers`. Each comment is written on its
+/// own line(s) just before its node; comments whose pointer names no node are
+/// ignored.
+pub struct CommentedJson<'a> {
+    inner: &'a Json,
+    comments: &'a BTreeMap<string::String, string::String>,
+    indent: u32,
+}
+
 pub struct AsJson<'a, T: 'a> { inner: &'a T }
 pub struct AsPrettyJson<'a, T: 'a> { inner: &'a T, indent: Option<u32> }
 
@@ -293,11 +344,14 @@ pub enum ErrorCode {
     InvalidEscape,
     InvalidUnicodeCodePoint,
     LoneLeadingSurrogateInHexEscape,
+    LoneTrailingSurrogateInHexEscape,
     UnexpectedEndOfHexEscape,
     UnrecognizedHex,
     NotFourDigit,
     ControlCharacterInString,
+    NoncharacterInString,
     NotUtf8,
+    Cancelled,
 }
 
 #[derive(Debug)]
@@ -305,6 +359,9 @@ pub enum ParserError {
     /// msg, line, col
     SyntaxError(ErrorCode, usize, usize),
     IoError(io::Error),
+    /// The input was read from a `limit::LimitedReader` and exceeded its
+    /// limit (in bytes)
+    SizeLimitExceeded(u64),
 }
 
 impl PartialEq for ParserError {
@@ -312,8 +369,8 @@ impl PartialEq for ParserError {
         match (self, other) {
             (&SyntaxError(msg0, line0, col0), &SyntaxError(msg1, line1, col1)) =>
                 msg0 == msg1 && line0 == line1 && col0 == col1,
-            (&IoError(_), _) => false,
-            (_, &IoError(_)) => false,
+            (&SizeLimitExceeded(limit0), &SizeLimitExceeded(limit1)) => limit0 == limit1,
+            _ => false,
         }
     }
 }
@@ -326,6 +383,14 @@ pub enum DecoderError {
     ParseError(ParserError),
     ExpectedError(string::String, string::String),
     MissingFieldError(string::String),
+    /// A field was missing, and the object had a member whose key, given
+    /// second, is close enough to the field's name to be a misspelling of
+    /// it, such as `usr_id` for `user_id`
+    MisspeltFieldError(string::String, string::String),
+    /// An object had a member which no field of the struct decoded from it
+    /// has, along with the closest field name if any is close; see
+    /// `Decoder::set_deny_unknown_fields`
+    UnknownFieldError(string::String, Option<string::String>),
     UnknownVariantError(string::String),
     ApplicationError(string::String),
     EOF,
@@ -335,6 +400,12 @@ pub enum DecoderError {
 pub enum EncoderError {
     FmtError(fmt::Error),
     BadHashmapKey,
+    /// The buffer given to `encode_to_slice` was too small; the number of
+    /// bytes needed is given
+    BufferTooSmall(usize),
+    /// An element of a sequence being written in columns was not a struct
+    /// with the fields of the first; see `Encoder::set_columnar`
+    BadColumnarRow,
 }
 
 impl PartialEq for EncoderError {
@@ -342,6 +413,8 @@ impl PartialEq for EncoderError {
         match (*self, *other) {
             (EncoderError::FmtError(_), EncoderError::FmtError(_)) => true,
             (EncoderError::BadHashmapKey, EncoderError::BadHashmapKey) => true,
+            (EncoderError::BufferTooSmall(a), EncoderError::BufferTooSmall(b)) => a == b,
+            (EncoderError::BadColumnarRow, EncoderError::BadColumnarRow) => true,
             _ => false,
         }
     }
@@ -371,7 +444,10 @@ pub fn error_str(error: ErrorCode) -> &'static str {
         NotUtf8 => "contents not utf-8",
         InvalidUnicodeCodePoint => "invalid Unicode code point",
         LoneLeadingSurrogateInHexEscape => "lone leading surrogate in hex escape",
+        LoneTrailingSurrogateInHexEscape => "lone trailing surrogate in hex escape",
         UnexpectedEndOfHexEscape => "unexpected end of hex escape",
+        NoncharacterInString => "noncharacter in string",
+        Cancelled => "parsing cancelled",
     }
 }
 
@@ -386,6 +462,19 @@ pub fn decode<T: ::Decodable>(s: &str) -> DecodeResult<T> {
     ::Decodable::decode(&mut decoder)
 }
 
+/// Shortcut function to decode the JSON file at `path` into an object,
+/// through a memory map; see `Decoder::from_mmap`.
+///
+/// # Safety
+///
+/// The file must not be written to or truncated while it is decoded; see
+/// `mmap::Mmap::open`.
+#[cfg(feature = "mmap")]
+pub unsafe fn from_mmap<T: ::Decodable, P: AsRef<Path>>(path: P) -> DecodeResult<T> {
+    let mut decoder = try!(Decoder::from_mmap(path));
+    ::Decodable::decode(&mut decoder)
+}
+
 /// Shortcut function to encode a `T` into a JSON `String`
 pub fn encode<T: ::Encodable>(object: &T) -> EncodeResult<string::String> {
     let mut s = String::new();
@@ -396,6 +485,57 @@ pub fn encode<T: ::Encodable>(object: &T) -> EncodeResult<string::String> {
     Ok(s)
 }
 
+/// Encodes a `T` as compact JSON into `buf`, without allocating, and
+/// returns the number of bytes written.
+///
+/// If the encoding does not fit, the error is `BufferTooSmall` with the
+/// number of bytes it needs, and the contents of `buf` are unspecified.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::json::{self, EncoderError};
+///
+/// fn main() {
+///     let mut buf = [0; 8];
+///     let len = json::encode_to_slice(&[1, 2], &mut buf).unwrap();
+///     assert_eq!(&buf[..len], b"[1,2]");
+///     assert_eq!(json::encode_to_slice(&"too long", &mut buf),
+///                Err(EncoderError::BufferTooSmall(10)));
+/// }
+/// ```
+pub fn encode_to_slice<T: ::Encodable + ?Sized>(object: &T, buf: &mut [u8])
+                                                -> EncodeResult<usize> {
+    let mut writer = SliceWriter { buf: buf, len: 0 };
+    {
+        let mut encoder = Encoder::new(&mut writer);
+        try!(object.encode(&mut encoder));
+    }
+    if writer.len > writer.buf.len() {
+        return Err(EncoderError::BufferTooSmall(writer.len));
+    }
+    Ok(writer.len)
+}
+
+// Writes into a slice, and once it is full goes on counting what would
+// have been written, so that the size needed can be reported.
+struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> fmt::Write for SliceWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end <= self.buf.len() {
+            self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        }
+        self.len = end;
+        Ok(())
+    }
+}
+
 impl fmt::Debug for ErrorCode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         error_str(*self).fmt(f)
@@ -434,9 +574,91 @@ impl fmt::Display for ParserError {
     }
 }
 
+impl ParserError {
+    /// Returns a value displaying this error for people, with the line of
+    /// `input`, the text parsed, which it is in, and a caret under where in
+    /// the line it is. Long lines are cut to the part around the caret,
+    /// tabs are expanded and control characters shown as pictures of
+    /// them. Errors other than syntax errors are displayed alone.
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// use rustc_serialize::json::Json;
+    ///
+    /// fn main() {
+    ///     let input = "{\n  \"port\": 80 80\n}";
+    ///     let err = Json::from_str(input).unwrap_err();
+    ///     assert_eq!(err.snippet(input).to_string(), "\
+    /// error: invalid syntax
+    ///  --> line 2, column 14
+    ///   |
+    /// 2 |   \"port\": 80 80
+    ///   |              ^");
+    /// }
+    /// ```
+    pub fn snippet<'a>(&'a self, input: &'a str) -> ErrorSnippet<'a> {
+        ErrorSnippet { error: self, input: input }
+    }
+}
+
+/// A `ParserError` displayed with the text it was found in; see
+/// `ParserError::snippet`.
+pub struct ErrorSnippet<'a> {
+    error: &'a ParserError,
+    input: &'a str,
+}
+
+// The characters shown of a long line either side of the caret.
+const SNIPPET_CONTEXT: usize = 40;
+
+impl<'a> fmt::Display for ErrorSnippet<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (code, line, col) = match *self.error {
+            SyntaxError(code, line, col) => (code, line, col),
+            ref err => return write!(f, "error: {}", err),
+        };
+        // The parser counts the line feed ending a line as the first column
+        // of the next, and the next line's characters from the second.
+        let (line, idx) = match (line, col) {
+            (0, _) | (1, _) => (1, col.saturating_sub(1)),
+            (line, 0) | (line, 1) => (line - 1, usize::MAX),
+            (line, col) => (line, col - 2),
+        };
+        let text = self.input.split('\n').nth(line - 1).unwrap_or("");
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        let chars: Vec<char> = text.chars().collect();
+        let idx = idx.min(chars.len());
+        let start = idx.saturating_sub(SNIPPET_CONTEXT);
+        let end = chars.len().min(idx + SNIPPET_CONTEXT);
+        let shown = |chars: &[char]| -> string::String {
+            let expanded = ExpandTabs::new(chars.iter().cloned(), 4);
+            EscapeControls::new(expanded, ControlStyle::Pictures).collect()
+        };
+        let mut caret = shown(&chars[start..idx]).chars().count();
+        let mut shown_text = shown(&chars[start..end]);
+        if start > 0 {
+            shown_text.insert_str(0, "...");
+            caret += 3;
+        }
+        if end < chars.len() {
+            shown_text.push_str("...");
+        }
+
+        let gutter = line.to_string().len();
+        try!(writeln!(f, "error: {}", error_str(code)));
+        try!(writeln!(f, "{:w$}--> line {}, column {}", "", line, idx + 1, w = gutter));
+        try!(writeln!(f, "{:w$} |", "", w = gutter));
+        try!(writeln!(f, "{} | {}", line, shown_text));
+        write!(f, "{:w$} | {:c$}^", "", "", w = gutter, c = caret)
+    }
+}
+
 impl From<io::Error> for ParserError {
     fn from(err: io::Error) -> ParserError {
-        IoError(err)
+        match limit::SizeLimitExceeded::from_io_error(&err) {
+            Some(e) => SizeLimitExceeded(e.limit),
+            None => IoError(err),
+        }
     }
 }
 
@@ -457,58 +679,116 @@ impl From<fmt::Error> for EncoderError {
 pub type EncodeResult<T> = Result<T, EncoderError>;
 pub type DecodeResult<T> = Result<T, DecoderError>;
 
+// Bytes which must be escaped in a JSON string. Everything else, including
+// all non-ASCII bytes, is copied through unchanged.
+static ESCAPE: [bool; 256] = {
+    const T: bool = true;
+    const F: bool = false;
+    [
+    // 0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+    T, T, T, T, T, T, T, T, T, T, T, T, T, T, T, T, // 0x
+    T, T, T, T, T, T, T, T, T, T, T, T, T, T, T, T, // 1x
+    F, F, T, F, F, F, F, F, F, F, F, F, F, F, F, F, // 2x
+    F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, // 3x
+    F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, // 4x
+    F, F, F, F, F, F, F, F, F, F, F, F, T, F, F, F, // 5x
+    F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, // 6x
+    F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, T, // 7x
+    F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, // 8x
+    F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, // 9x
+    F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, // Ax
+    F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, // Bx
+    F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, // Cx
+    F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, // Dx
+    F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, // Ex
+    F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, F, // Fx
+    ]
+};
+
+fn escape_byte(byte: u8) -> &'static str {
+    match byte {
+        b'"' => "\\\"",
+        b'\\' => "\\\\",
+        b'\x00' => "\\u0000",
+        b'\x01' => "\\u0001",
+        b'\x02' => "\\u0002",
+        b'\x03' => "\\u0003",
+        b'\x04' => "\\u0004",
+        b'\x05' => "\\u0005",
+        b'\x06' => "\\u0006",
+        b'\x07' => "\\u0007",
+        b'\x08' => "\\b",
+        b'\t' => "\\t",
+        b'\n' => "\\n",
+        b'\x0b' => "\\u000b",
+        b'\x0c' => "\\f",
+        b'\r' => "\\r",
+        b'\x0e' => "\\u000e",
+        b'\x0f' => "\\u000f",
+        b'\x10' => "\\u0010",
+        b'\x11' => "\\u0011",
+        b'\x12' => "\\u0012",
+        b'\x13' => "\\u0013",
+        b'\x14' => "\\u0014",
+        b'\x15' => "\\u0015",
+        b'\x16' => "\\u0016",
+        b'\x17' => "\\u0017",
+        b'\x18' => "\\u0018",
+        b'\x19' => "\\u0019",
+        b'\x1a' => "\\u001a",
+        b'\x1b' => "\\u001b",
+        b'\x1c' => "\\u001c",
+        b'\x1d' => "\\u001d",
+        b'\x1e' => "\\u001e",
+        b'\x1f' => "\\u001f",
+        b'\x7f' => "\\u007f",
+        _ => unreachable!(),
+    }
+}
+
 fn escape_str(wr: &mut fmt::Write, v: &str) -> EncodeResult<()> {
     try!(wr.write_str("\""));
 
+    // Find each byte needing an escape and copy the runs between them whole.
+    let bytes = v.as_bytes();
     let mut start = 0;
-
-    for (i, byte) in v.bytes().enumerate() {
-        let escaped = match byte {
-            b'"' => "\\\"",
-            b'\\' => "\\\\",
-            b'\x00' => "\\u0000",
-            b'\x01' => "\\u0001",
-            b'\x02' => "\\u0002",
-            b'\x03' => "\\u0003",
-            b'\x04' => "\\u0004",
-            b'\x05' => "\\u0005",
-            b'\x06' => "\\u0006",
-            b'\x07' => "\\u0007",
-            b'\x08' => "\\b",
-            b'\t' => "\\t",
-            b'\n' => "\\n",
-            b'\x0b' => "\\u000b",
-            b'\x0c' => "\\f",
-            b'\r' => "\\r",
-            b'\x0e' => "\\u000e",
-            b'\x0f' => "\\u000f",
-            b'\x10' => "\\u0010",
-            b'\x11' => "\\u0011",
-            b'\x12' => "\\u0012",
-            b'\x13' => "\\u0013",
-            b'\x14' => "\\u0014",
-            b'\x15' => "\\u0015",
-            b'\x16' => "\\u0016",
-            b'\x17' => "\\u0017",
-            b'\x18' => "\\u0018",
-            b'\x19' => "\\u0019",
-            b'\x1a' => "\\u001a",
-            b'\x1b' => "\\u001b",
-            b'\x1c' => "\\u001c",
-            b'\x1d' => "\\u001d",
-            b'\x1e' => "\\u001e",
-            b'\x1f' => "\\u001f",
-            b'\x7f' => "\\u007f",
-            _ => { continue; }
-        };
-
+    while let Some(n) = bytes[start..].iter().position(|&b| ESCAPE[b as usize]) {
+        let i = start + n;
         if start < i {
             try!(wr.write_str(&v[start..i]));
         }
+        try!(wr.write_str(escape_byte(bytes[i])));
+        start = i + 1;
+    }
+
+    if start != v.len() {
+        try!(wr.write_str(&v[start..]));
+    }
+
+    try!(wr.write_str("\""));
+    Ok(())
+}
 
-        try!(wr.write_str(escaped));
+// Writes a string as `escape_str` does, and every non-ASCII character in
+// it as a `\uXXXX` escape, so that the output is ASCII.
+fn escape_str_ascii(wr: &mut fmt::Write, v: &str) -> EncodeResult<()> {
+    try!(wr.write_str("\""));
 
-        start = i + 1;
+    let bytes = v.as_bytes();
+    let mut start = 0;
+    while let Some(n) = bytes[start..].iter().position(|&b| ESCAPE[b as usize] || b >= 0x80) {
+        let i = start + n;
+        if start < i {
+            try!(wr.write_str(&v[start..i]));
+        }
+        if bytes[i] < 0x80 {
+            try!(wr.write_str(escape_byte(bytes[i])));
+            start = i + 1;
+        } else {
+            let c = v[i..].chars().next().unwrap();
+            try!(escape_utf16(wr, c as u32));
+            start = i + c.len_utf8();
+        }
     }
 
     if start != v.len() {
@@ -519,6 +799,70 @@ fn escape_str(wr: &mut fmt::Write, v: &str) -> EncodeResult<()> {
     Ok(())
 }
 
+fn escape_str_as(wr: &mut fmt::Write, v: &str, ascii_only: bool) -> EncodeResult<()> {
+    if ascii_only {
+        escape_str_ascii(wr, v)
+    } else {
+        escape_str(wr, v)
+    }
+}
+
+// Writes a string with lone surrogates, escaping them as `\uXXXX`, and
+// every other non-ASCII character too if `ascii_only`.
+fn escape_wtf8(wr: &mut fmt::Write, v: &Wtf8Buf, ascii_only: bool) -> EncodeResult<()> {
+    try!(wr.write_str("\""));
+    let mut buf = [0; 4];
+    for c in v.code_points() {
+        match char::from_u32(c) {
+            Some(c) if c.is_ascii() && ESCAPE[c as usize] => {
+                try!(wr.write_str(escape_byte(c as u8)))
+            }
+            Some(c) if c.is_ascii() || !ascii_only => try!(wr.write_str(c.encode_utf8(&mut buf))),
+            _ => try!(escape_utf16(wr, c)),
+        }
+    }
+    try!(wr.write_str("\""));
+    Ok(())
+}
+
+// Writes a code point as a `\uXXXX` escape, or two of them, for the halves
+// of its UTF-16 surrogate pair, if it is outside the Basic Multilingual
+// Plane.
+fn escape_utf16(wr: &mut fmt::Write, c: u32) -> fmt::Result {
+    if c >= 0x1_0000 {
+        let c = c - 0x1_0000;
+        write!(wr, "\\u{:04x}\\u{:04x}", 0xD800 + (c >> 10), 0xDC00 + (c & 0x3FF))
+    } else {
+        write!(wr, "\\u{:04x}", c)
+    }
+}
+
+/// Escapes `s` as a quoted string literal, in ASCII alone, as an encoder
+/// set to `set_ascii_only` writes strings: characters outside ASCII are
+/// written as `\uXXXX` escapes, and those outside the Basic Multilingual
+/// Plane as the two escapes of their UTF-16 surrogate pair.
+///
+/// The literal is both JSON and JavaScript, for generating JavaScript
+/// source. Unlike JSON, JavaScript engines predating ES2019 do not allow
+/// U+2028 and U+2029 in string literals, which this escapes with the rest.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::json::escape_js_string;
+///
+/// fn main() {
+///     assert_eq!(escape_js_string("caf\u{e9} \u{1F600}\n"),
+///                r#""caf\u00e9 \ud83d\ude00\n""#);
+/// }
+/// ```
+pub fn escape_js_string(s: &str) -> string::String {
+    let mut out = string::String::with_capacity(s.len() + 2);
+    escape_str_ascii(&mut out, s).unwrap();
+    out
+}
+
 fn escape_char(writer: &mut fmt::Write, v: char) -> EncodeResult<()> {
     let mut buf = [0; 4];
     let _ = write!(&mut &mut buf[..], "{}", v);
@@ -553,9 +897,14 @@ fn fmt_number_or_null(v: f64) -> string::String {
     }
 }
 
+// The largest integer an IEEE double represents exactly, along with all
+// smaller ones.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
 macro_rules! emit_enquoted_if_mapkey {
     ($enc:ident,$e:expr) => {
         if $enc.is_emitting_map_key {
+            try!($enc.check_primitive_key());
             try!(write!($enc.writer, "\"{}\"", $e));
             Ok(())
         } else {
@@ -573,11 +922,177 @@ enum EncodingFormat {
     }
 }
 
+/// How the JSON encoder writes the keys of maps.
+///
+/// JSON object keys are always strings. Stringified numbers are parsed back
+/// by the numeric `read_*` methods whatever the policy; the output of
+/// `StringifyAll` and `Pairs` is read back by a decoder given the same
+/// policy with `Decoder::set_map_key_policy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapKeyPolicy {
+    /// Write maps as objects, stringifying numeric and character keys (so
+    /// `1` becomes `"1"`). Any other key, booleans included, is a
+    /// `BadHashmapKey` error. This is the default.
+    Stringify,
+    /// Write maps as objects, stringifying boolean keys as well as those
+    /// `Stringify` does, as `"true"` and `"false"`.
+    StringifyAll,
+    /// Write maps as objects, allowing only string and character keys (and
+    /// fieldless enum variants, which are written as strings). Any other key
+    /// is a `BadHashmapKey` error.
+    Strict,
+    /// Write maps as arrays of `[key, value]` pairs, so keys of any type,
+    /// tuples and structs included, can be encoded.
+    Pairs,
+}
+
+/// How the JSON encoder writes `Option`s, and the decoder reads them.
+///
+/// A policy is set for every `Option` with `Encoder::set_option_policy`
+/// and `Decoder::set_option_policy`, and for struct fields of given names,
+/// holding `Option`s, with `set_field_option_policy`. The encoder and
+/// decoder of a document should be given the same policies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionPolicy {
+    /// Write `None` as `null` and `Some(x)` as `x`. This is the default.
+    Null,
+    /// Leave out struct fields holding `None`, and write `Some(x)` as `x`.
+    /// `None`s anywhere else, such as in arrays, are written as `null`.
+    Omit,
+    /// Write `None` as `[]` and `Some(x)` as `[x]`, so that `Some(None)`,
+    /// `[null]`, can be told apart from `None`. A missing struct field or
+    /// `null` is also read as `None`.
+    Array,
+}
+
+// The encoder's output. While map entries are being sorted, writes go to the
+// innermost capture buffer instead of the underlying writer.
+struct Output<'a> {
+    inner: &'a mut (fmt::Write+'a),
+    captures: Vec<string::String>,
+}
+
+impl<'a> Output<'a> {
+    // Lets `write!` be used without importing `fmt::Write`, which would
+    // clash with the `io::Write` in scope.
+    fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        fmt::Write::write_fmt(self, args)
+    }
+}
+
+impl<'a> fmt::Write for Output<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self.captures.last_mut() {
+            Some(buf) => { buf.push_str(s); Ok(()) }
+            None => self.inner.write_str(s),
+        }
+    }
+}
+
+// A sequence being written in columns.
+struct Columns {
+    // The names of the fields, as written, and the values of each column so
+    // far, each after its separator.
+    names: Vec<string::String>,
+    values: Vec<string::String>,
+    rows: usize,
+    // The number of fields of the current row written so far.
+    fields: usize,
+    // Whether the sequence is written as an array after all, its first
+    // element not being a struct.
+    plain: bool,
+    // The indentation of the sequence itself.
+    indent: u32,
+}
+
+/// The settings of an `Encoder`, as its `set_*` methods make them; see
+/// those for what each does. `Encoder::with_config` and
+/// `Encoder::set_config` apply a whole set at once, so that one can be kept
+/// and shared between encoders.
+#[derive(Clone, Debug)]
+pub struct EncoderConfig {
+    /// See `Encoder::set_map_key_policy`
+    pub map_key_policy: MapKeyPolicy,
+    /// See `Encoder::set_sort_map_keys`
+    pub sort_map_keys: bool,
+    /// See `Encoder::set_skip_null_fields`
+    pub skip_null_fields: bool,
+    /// See `Encoder::set_skip_empty_arrays`
+    pub skip_empty_arrays: bool,
+    /// See `Encoder::set_skip_empty_objects`
+    pub skip_empty_objects: bool,
+    /// See `Encoder::set_option_policy`
+    pub option_policy: OptionPolicy,
+    /// See `Encoder::set_field_option_policy`
+    pub field_option_policies: HashMap<string::String, OptionPolicy>,
+    /// See `Encoder::set_rename_all`
+    pub rename_all: Option<Case>,
+    /// See `Encoder::set_ascii_only`
+    pub ascii_only: bool,
+    /// See `Encoder::set_columnar`
+    pub columnar: bool,
+    /// See `Encoder::set_quantization`
+    pub quantization: Option<Quantization>,
+    /// See `Encoder::set_path_strings`
+    pub path_strings: bool,
+}
+
+impl Default for EncoderConfig {
+    fn default() -> EncoderConfig {
+        EncoderConfig {
+            map_key_policy: MapKeyPolicy::Stringify,
+            sort_map_keys: false,
+            skip_null_fields: false,
+            skip_empty_arrays: false,
+            skip_empty_objects: false,
+            option_policy: OptionPolicy::Null,
+            field_option_policies: HashMap::new(),
+            rename_all: None,
+            ascii_only: false,
+            columnar: false,
+            quantization: None,
+            path_strings: false,
+        }
+    }
+}
+
 /// A structure for implementing serialization to JSON.
 pub struct Encoder<'a> {
-    writer: &'a mut (fmt::Write+'a),
+    writer: Output<'a>,
     format : EncodingFormat,
     is_emitting_map_key: bool,
+    config: EncoderConfig,
+    // For each map being sorted, its entries so far and the length of the
+    // key part of each.
+    sorted_entries: Vec<Vec<(usize, string::String)>>,
+    // Emptied entry buffers, kept for reuse.
+    spare: Vec<string::String>,
+    // For each struct being written while fields may be skipped, the number
+    // of fields written so far.
+    struct_fields: Vec<usize>,
+    // The policy for the `Option` about to be written, if it is the value
+    // of a struct field, and for the one being written.
+    field_option: Option<OptionPolicy>,
+    option: OptionPolicy,
+    // Whether the value of the struct field being written is a `None` to
+    // leave out.
+    omitted: bool,
+    // A buffer for renamed field names, kept for reuse.
+    field_name: string::String,
+    // The sequences being written in columns, innermost last.
+    columns: Vec<Columns>,
+    // Whether the elements of the innermost sequence are being written in
+    // columns, whether the value about to be written is one of them, and
+    // whether the struct being written is a row of columns.
+    in_columns: bool,
+    row_start: bool,
+    in_row: bool,
+    // For each sequence being written while quantizing, the number of its
+    // elements so far and the values of those which are floats.
+    float_seqs: Vec<(usize, Vec<f64>)>,
+    // Whether the value about to be written is an element of the innermost
+    // of them.
+    float_elt: bool,
 }
 
 impl<'a> Encoder<'a> {
@@ -585,12 +1100,26 @@ impl<'a> Encoder<'a> {
     /// JSON to the specified writer
     pub fn new_pretty(writer: &'a mut fmt::Write) -> Encoder<'a> {
         Encoder {
-            writer: writer,
+            writer: Output { inner: writer, captures: Vec::new() },
             format: EncodingFormat::Pretty {
                 curr_indent: 0,
                 indent: 2,
             },
             is_emitting_map_key: false,
+            config: EncoderConfig::default(),
+            sorted_entries: Vec::new(),
+            spare: Vec::new(),
+            struct_fields: Vec::new(),
+            field_option: None,
+            option: OptionPolicy::Null,
+            omitted: false,
+            field_name: string::String::new(),
+            columns: Vec::new(),
+            in_columns: false,
+            row_start: false,
+            in_row: false,
+            float_seqs: Vec::new(),
+            float_elt: false,
         }
     }
 
@@ -598,10 +1127,91 @@ impl<'a> Encoder<'a> {
     /// JSON to the specified writer
     pub fn new(writer: &'a mut fmt::Write) -> Encoder<'a> {
         Encoder {
-            writer: writer,
+            writer: Output { inner: writer, captures: Vec::new() },
             format: EncodingFormat::Compact,
             is_emitting_map_key: false,
+            config: EncoderConfig::default(),
+            sorted_entries: Vec::new(),
+            spare: Vec::new(),
+            struct_fields: Vec::new(),
+            field_option: None,
+            option: OptionPolicy::Null,
+            omitted: false,
+            field_name: string::String::new(),
+            columns: Vec::new(),
+            in_columns: false,
+            row_start: false,
+            in_row: false,
+            float_seqs: Vec::new(),
+            float_elt: false,
+        }
+    }
+
+    /// Creates a new compact encoder, like `new`, with room in its scratch
+    /// buffers for maps nested `depth` levels deep. The scratch buffers are
+    /// only used when sorting map keys.
+    pub fn with_capacity(writer: &'a mut fmt::Write, depth: usize) -> Encoder<'a> {
+        let mut encoder = Encoder::new(writer);
+        encoder.writer.captures.reserve(depth);
+        encoder.sorted_entries.reserve(depth);
+        encoder
+    }
+
+    /// Creates a new compact encoder, like `new`, with the settings in
+    /// `config`.
+    pub fn with_config(writer: &'a mut fmt::Write, config: EncoderConfig) -> Encoder<'a> {
+        let mut encoder = Encoder::new(writer);
+        encoder.config = config;
+        encoder
+    }
+
+    /// Returns the encoder's settings.
+    pub fn config(&self) -> &EncoderConfig {
+        &self.config
+    }
+
+    /// Replace all of the encoder's settings with `config`. This should be
+    /// set before encoding.
+    pub fn set_config(&mut self, config: EncoderConfig) {
+        self.config = config;
+    }
+
+    /// Resets the encoder's internal state, e.g. after an error, so it can be
+    /// used for the next value. Settings are kept, as are scratch buffers, so
+    /// a single encoder can write many values without allocating for each.
+    ///
+    /// Output already written is not touched: the caller must truncate its
+    /// own buffer. Encoding `[1,2]` after an error partway through `[{`
+    /// leaves `[{[1,2]` in the buffer.
+    pub fn reset(&mut self) {
+        self.is_emitting_map_key = false;
+        if let EncodingFormat::Pretty{ref mut curr_indent, ..} = self.format {
+            *curr_indent = 0;
+        }
+        for mut buf in self.writer.captures.drain(..) {
+            buf.clear();
+            self.spare.push(buf);
+        }
+        for entries in self.sorted_entries.drain(..) {
+            for (_, mut buf) in entries {
+                buf.clear();
+                self.spare.push(buf);
+            }
         }
+        self.struct_fields.clear();
+        self.field_option = None;
+        self.omitted = false;
+        for columns in self.columns.drain(..) {
+            for mut buf in columns.names.into_iter().chain(columns.values) {
+                buf.clear();
+                self.spare.push(buf);
+            }
+        }
+        self.in_columns = false;
+        self.row_start = false;
+        self.in_row = false;
+        self.float_seqs.clear();
+        self.float_elt = false;
     }
 
     /// Set the number of spaces to indent for each level.
@@ -617,884 +1227,2161 @@ impl<'a> Encoder<'a> {
             Err(())
         }
     }
-}
 
-impl<'a> ::Encoder for Encoder<'a> {
-    type Error = EncoderError;
+    /// Set how map keys are written. This should be set before encoding.
+    pub fn set_map_key_policy(&mut self, policy: MapKeyPolicy) {
+        self.config.map_key_policy = policy;
+    }
 
-    fn emit_nil(&mut self) -> EncodeResult<()> {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        try!(write!(self.writer, "null"));
-        Ok(())
+    /// Sort the entries of every map by their encoded key before writing
+    /// them, so the output does not depend on the iteration order of the
+    /// map (as it does for `HashMap`). This should be set before encoding.
+    ///
+    /// Entries are buffered in memory until the end of each map.
+    pub fn set_sort_map_keys(&mut self, sort: bool) {
+        self.config.sort_map_keys = sort;
+    }
+
+    /// Leave out struct fields whose value is `null`, such as those holding
+    /// `None`, rather than writing them. This should be set before
+    /// encoding.
+    ///
+    /// Like the other `set_skip_*` settings, this applies to the fields of
+    /// structs alone: map entries, elements of arrays and the fields of
+    /// enum variants, which are written by position, are kept. The value of
+    /// each field is buffered in memory while any is set.
+    pub fn set_skip_null_fields(&mut self, skip: bool) {
+        self.config.skip_null_fields = skip;
     }
 
-    fn emit_usize(&mut self, v: usize) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_u64(&mut self, v: u64) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_u32(&mut self, v: u32) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_u16(&mut self, v: u16) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_u8(&mut self, v: u8) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    /// Leave out struct fields whose value is an empty array. This should
+    /// be set before encoding.
+    pub fn set_skip_empty_arrays(&mut self, skip: bool) {
+        self.config.skip_empty_arrays = skip;
+    }
 
-    fn emit_isize(&mut self, v: isize) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_i64(&mut self, v: i64) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_i32(&mut self, v: i32) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_i16(&mut self, v: i16) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_i8(&mut self, v: i8) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    /// Leave out struct fields whose value is an empty object, including
+    /// structs all of whose fields were left out. This should be set before
+    /// encoding.
+    pub fn set_skip_empty_objects(&mut self, skip: bool) {
+        self.config.skip_empty_objects = skip;
+    }
 
-    fn emit_bool(&mut self, v: bool) -> EncodeResult<()> {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        if v {
-            try!(write!(self.writer, "true"));
-        } else {
-            try!(write!(self.writer, "false"));
-        }
-        Ok(())
+    /// Set how `Option`s are written; see `OptionPolicy`. This should be set
+    /// before encoding.
+    pub fn set_option_policy(&mut self, policy: OptionPolicy) {
+        self.config.option_policy = policy;
     }
 
-    fn emit_f64(&mut self, v: f64) -> EncodeResult<()> {
-        emit_enquoted_if_mapkey!(self, fmt_number_or_null(v))
+    /// Set how `Option`s held by struct fields named `field` are written,
+    /// instead of as the `set_option_policy` setting says. This should be
+    /// set before encoding.
+    pub fn set_field_option_policy(&mut self, field: &str, policy: OptionPolicy) {
+        self.config.field_option_policies.insert(field.to_string(), policy);
     }
-    fn emit_f32(&mut self, v: f32) -> EncodeResult<()> {
-        self.emit_f64(v as f64)
+
+    /// Write the names of struct fields in `case`, so that `max_retries`
+    /// becomes `maxRetries` with `Case::Camel`. Names given to
+    /// `set_field_option_policy` are the fields' own. This should be set
+    /// before encoding.
+    pub fn set_rename_all(&mut self, case: Case) {
+        self.config.rename_all = Some(case);
     }
 
-    fn emit_char(&mut self, v: char) -> EncodeResult<()> {
-        escape_char(self.writer, v)
+    /// Write the output in ASCII alone, escaping every other character in
+    /// strings and names as `\uXXXX`, as `escape_js_string` does. This is
+    /// safe to set during encoding.
+    pub fn set_ascii_only(&mut self, ascii_only: bool) {
+        self.config.ascii_only = ascii_only;
     }
-    fn emit_str(&mut self, v: &str) -> EncodeResult<()> {
-        escape_str(self.writer, v)
+
+    /// Write sequences of structs, such as `Vec<Point>`, as objects of
+    /// columns, `{"x":[1,2],"y":[3,4]}`, rather than as arrays of objects,
+    /// `[{"x":1,"y":3},{"x":2,"y":4}]`. `Decoder::set_columnar` reads them
+    /// back. This should be set before encoding.
+    ///
+    /// A sequence is written in columns if its first element is a struct
+    /// with fields, and every other element must then be a struct with the
+    /// same fields, or encoding fails with `BadColumnarRow`. Fields in
+    /// columns are never left out, whatever the `set_skip_*` settings and
+    /// `OptionPolicy::Omit` say, so that the columns line up. Tuples and
+    /// empty sequences are written as arrays. Columns are buffered in
+    /// memory until the end of each sequence.
+    pub fn set_columnar(&mut self, columnar: bool) {
+        self.config.columnar = columnar;
     }
 
-    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult<()> where
-        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
-    {
-        f(self)
+    /// Write sequences of floats, such as `Vec<f32>`, quantized to 16-bit
+    /// floats in `quantization`, as `{"$tag":40202,"value":"ADwAQA=="}`;
+    /// see `half`. The decoder widens them back. This should be set before
+    /// encoding.
+    ///
+    /// Every sequence with elements is buffered in memory until its end to
+    /// see whether they are all floats. Tuples are never quantized, nor are
+    /// the columns of `set_columnar`.
+    pub fn set_quantization(&mut self, quantization: Quantization) {
+        self.config.quantization = Some(quantization);
     }
 
-    fn emit_enum_variant<F>(&mut self,
-                            name: &str,
-                            _id: usize,
-                            cnt: usize,
-                            f: F)
-                            -> EncodeResult<()> where
-        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
-    {
-        // enums are encoded as strings or objects
-        // Bunny => "Bunny"
-        // Kangaroo(34,"William") => {"variant": "Kangaroo", "fields": [34,"William"]}
-        if cnt == 0 {
-            escape_str(self.writer, name)
-        } else {
-            if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-            if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
-                try!(write!(self.writer, "{{\n"));
-                *curr_indent += indent;
-                try!(spaces(self.writer, *curr_indent));
-                try!(write!(self.writer, "\"variant\": "));
-                try!(escape_str(self.writer, name));
-                try!(write!(self.writer, ",\n"));
-                try!(spaces(self.writer, *curr_indent));
-                try!(write!(self.writer, "\"fields\": [\n"));
-                *curr_indent += indent;
-            } else {
-                try!(write!(self.writer, "{{\"variant\":"));
-                try!(escape_str(self.writer, name));
-                try!(write!(self.writer, ",\"fields\":["));
-            }
-            try!(f(self));
-            if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
-                *curr_indent -= indent;
-                try!(write!(self.writer, "\n"));
-                try!(spaces(self.writer, *curr_indent));
-                *curr_indent -= indent;
-                try!(write!(self.writer, "]\n"));
-                try!(spaces(self.writer, *curr_indent));
-                try!(write!(self.writer, "}}"));
-            } else {
-                try!(write!(self.writer, "]}}"));
-            }
-            Ok(())
+    /// Write paths which are Unicode as strings, rather than in the
+    /// platform's own form (an array of bytes on Unix, or of UTF-16 code
+    /// units on Windows), so that they can be read on any platform. Other
+    /// paths are still written in the platform's form, which loses nothing.
+    /// The decoder reads either form. This should be set before encoding.
+    pub fn set_path_strings(&mut self, path_strings: bool) {
+        self.config.path_strings = path_strings;
+    }
+
+    // Writes a string or name, escaped as the settings say.
+    fn escape(&mut self, v: &str) -> EncodeResult<()> {
+        escape_str_as(&mut self.writer, v, self.config.ascii_only)
+    }
+
+    fn skips_fields(&self) -> bool {
+        let config = &self.config;
+        config.skip_null_fields || config.skip_empty_arrays || config.skip_empty_objects ||
+            config.option_policy == OptionPolicy::Omit ||
+            (!config.field_option_policies.is_empty() &&
+             config.field_option_policies.values().any(|&p| p == OptionPolicy::Omit))
+    }
+
+    // The policy for an `Option` held by the struct field `name`.
+    fn field_option_policy(&self, name: &str) -> OptionPolicy {
+        if self.config.field_option_policies.is_empty() {
+            return self.config.option_policy;
+        }
+        match self.config.field_option_policies.get(name) {
+            Some(&policy) => policy,
+            None => self.config.option_policy,
         }
     }
 
-    fn emit_enum_variant_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
-        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
-    {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+    // The policy for an `Option` which is not the value of a struct field.
+    fn nested_option_policy(&self) -> OptionPolicy {
+        match self.config.option_policy {
+            OptionPolicy::Omit => OptionPolicy::Null,
+            policy => policy,
+        }
+    }
+
+    // Writes the separator before a struct field and its name.
+    fn emit_field_name(&mut self, name: &str, idx: usize) -> EncodeResult<()> {
         if idx != 0 {
             try!(write!(self.writer, ","));
-            if let EncodingFormat::Pretty{..} = self.format {
-                try!(write!(self.writer, "\n"));
-            }
         }
         if let EncodingFormat::Pretty{curr_indent, ..} = self.format {
-            try!(spaces(self.writer, curr_indent));
+            try!(write!(self.writer, "\n"));
+            try!(spaces(&mut self.writer, curr_indent));
         }
-        f(self)
+        try!(self.write_field_name(name));
+        if let EncodingFormat::Pretty{..} = self.format {
+            try!(write!(self.writer, ": "));
+        } else {
+            try!(write!(self.writer, ":"));
+        }
+        Ok(())
     }
 
-    fn emit_enum_struct_variant<F>(&mut self,
-                                   name: &str,
-                                   id: usize,
-                                   cnt: usize,
-                                   f: F) -> EncodeResult<()> where
+    // Writes a struct field's name, renamed and escaped.
+    fn write_field_name(&mut self, name: &str) -> EncodeResult<()> {
+        match self.config.rename_all {
+            Some(case) => {
+                let mut buf = mem::take(&mut self.field_name);
+                buf.clear();
+                case.convert_into(name, &mut buf);
+                let result = self.escape(&buf);
+                self.field_name = buf;
+                try!(result);
+            }
+            None => try!(self.escape(name)),
+        }
+        Ok(())
+    }
+
+    // Emits a struct field's value into a buffer, then writes the field
+    // unless the value is one to leave out.
+    fn emit_skippable_field<F>(&mut self, name: &str, idx: usize, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        self.emit_enum_variant(name, id, cnt, f)
+        let buf = self.spare.pop().unwrap_or_default();
+        self.writer.captures.push(buf);
+        let outer = mem::replace(&mut self.omitted, false);
+        try!(f(self));
+        let omitted = mem::replace(&mut self.omitted, outer);
+        let mut value = self.writer.captures.pop().unwrap();
+        let skip = omitted || match &value[..] {
+            "null" => self.config.skip_null_fields,
+            "[]" => self.config.skip_empty_arrays,
+            "{}" => self.config.skip_empty_objects,
+            _ => false,
+        };
+        if !skip {
+            let idx = match self.struct_fields.last_mut() {
+                Some(written) => { *written += 1; *written - 1 }
+                None => idx,
+            };
+            try!(self.emit_field_name(name, idx));
+            try!(write!(self.writer, "{}", value));
+        }
+        value.clear();
+        self.spare.push(value);
+        Ok(())
     }
 
-    fn emit_enum_struct_variant_field<F>(&mut self,
-                                         _: &str,
-                                         idx: usize,
-                                         f: F) -> EncodeResult<()> where
+    // Runs `f` to emit the entries of a map, sorting them if requested.
+    fn emit_map_entries<F>(&mut self, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        self.emit_enum_variant_arg(idx, f)
+        if !self.config.sort_map_keys {
+            return f(self);
+        }
+        self.sorted_entries.push(Vec::new());
+        try!(f(self));
+        let mut entries = self.sorted_entries.pop().unwrap();
+        entries.sort_by(|&(a_len, ref a), &(b_len, ref b)| a[..a_len].cmp(&b[..b_len]));
+        for (idx, (_, mut entry)) in entries.into_iter().enumerate() {
+            if idx != 0 {
+                try!(write!(self.writer, ","));
+            }
+            try!(write!(self.writer, "{}", entry));
+            entry.clear();
+            self.spare.push(entry);
+        }
+        Ok(())
+    }
+
+    // The indentation of the current level and of each level below it.
+    fn indents(&self) -> (u32, u32) {
+        match self.format {
+            EncodingFormat::Pretty{curr_indent, indent} => (curr_indent, indent),
+            EncodingFormat::Compact => (0, 0),
+        }
     }
 
+    fn set_curr_indent(&mut self, n: u32) {
+        if let EncodingFormat::Pretty{ref mut curr_indent, ..} = self.format {
+            *curr_indent = n;
+        }
+    }
 
-    fn emit_struct<F>(&mut self, _: &str, len: usize, f: F) -> EncodeResult<()> where
+    // Writes an array, as `emit_seq` does when not writing columns.
+    fn emit_array<F>(&mut self, len: usize, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        self.field_option = None;
+        self.row_start = false;
+        self.float_elt = false;
         if len == 0 {
-            try!(write!(self.writer, "{{}}"));
+            try!(write!(self.writer, "[]"));
         } else {
-            try!(write!(self.writer, "{{"));
+            try!(write!(self.writer, "["));
             if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
                 *curr_indent += indent;
             }
-            try!(f(self));
-            if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
-                *curr_indent -= indent;
-                try!(write!(self.writer, "\n"));
-                try!(spaces(self.writer, *curr_indent));
-            }
-            try!(write!(self.writer, "}}"));
+            let outer = mem::replace(&mut self.in_columns, false);
+            let result = f(self);
+            self.in_columns = outer;
+            try!(result);
+            try!(self.close_array());
         }
         Ok(())
     }
 
-    fn emit_struct_field<F>(&mut self, name: &str, idx: usize, f: F) -> EncodeResult<()> where
+    fn close_array(&mut self) -> EncodeResult<()> {
+        if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
+            *curr_indent -= indent;
+            try!(write!(self.writer, "\n"));
+            try!(spaces(&mut self.writer, *curr_indent));
+        }
+        try!(write!(self.writer, "]"));
+        Ok(())
+    }
+
+    fn emit_array_elt<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
         if idx != 0 {
             try!(write!(self.writer, ","));
         }
-        if let EncodingFormat::Pretty{curr_indent, ..} = self.format {
+        if let EncodingFormat::Pretty{ref mut curr_indent, ..} = self.format {
             try!(write!(self.writer, "\n"));
-            try!(spaces(self.writer, curr_indent));
-        }
-        try!(escape_str(self.writer, name));
-        if let EncodingFormat::Pretty{..} = self.format {
-            try!(write!(self.writer, ": "));
-        } else {
-            try!(write!(self.writer, ":"));
+            try!(spaces(&mut self.writer, *curr_indent));
         }
         f(self)
     }
 
-    fn emit_tuple<F>(&mut self, len: usize, f: F) -> EncodeResult<()> where
+    // Writes a non-empty sequence in columns, or as an array if its first
+    // element is not a struct; see `set_columnar`.
+    fn emit_columns<F>(&mut self, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        self.emit_seq(len, f)
+        let (indent, step) = self.indents();
+        self.columns.push(Columns {
+            names: Vec::new(),
+            values: Vec::new(),
+            rows: 0,
+            fields: 0,
+            plain: false,
+            indent: indent,
+        });
+        self.set_curr_indent(indent + step);
+        let outer = mem::replace(&mut self.in_columns, true);
+        let result = f(self);
+        self.in_columns = outer;
+        let columns = self.columns.pop().unwrap();
+        try!(result);
+        if columns.plain {
+            return self.close_array();
+        }
+        self.set_curr_indent(indent);
+        try!(write!(self.writer, "{{"));
+        let pretty = match self.format {
+            EncodingFormat::Pretty{..} => true,
+            EncodingFormat::Compact => false,
+        };
+        for (i, (mut name, mut values)) in columns.names.into_iter().zip(columns.values).enumerate() {
+            if i != 0 {
+                try!(write!(self.writer, ","));
+            }
+            if pretty {
+                try!(write!(self.writer, "\n"));
+                try!(spaces(&mut self.writer, indent + step));
+                try!(write!(self.writer, "{}: [{}\n", name, values));
+                try!(spaces(&mut self.writer, indent + step));
+                try!(write!(self.writer, "]"));
+            } else {
+                try!(write!(self.writer, "{}:[{}]", name, values));
+            }
+            name.clear();
+            values.clear();
+            self.spare.push(name);
+            self.spare.push(values);
+        }
+        if pretty {
+            try!(write!(self.writer, "\n"));
+            try!(spaces(&mut self.writer, indent));
+        }
+        try!(write!(self.writer, "}}"));
+        Ok(())
     }
-    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
+
+    fn emit_seq_unquantized<F>(&mut self, len: usize, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        self.emit_seq_elt(idx, f)
+        if self.config.columnar && len != 0 && !self.is_emitting_map_key {
+            self.field_option = None;
+            self.row_start = false;
+            self.float_elt = false;
+            return self.emit_columns(f);
+        }
+        self.emit_array(len, f)
     }
 
-    fn emit_tuple_struct<F>(&mut self, _: &str, len: usize, f: F) -> EncodeResult<()> where
+    // Writes a non-empty sequence quantized, if its elements are all
+    // floats, or else as it would be otherwise; see `set_quantization`.
+    fn emit_quantizable<F>(&mut self, quantization: Quantization, len: usize, f: F)
+                           -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        self.emit_seq(len, f)
+        let buf = self.spare.pop().unwrap_or_default();
+        self.writer.captures.push(buf);
+        self.float_seqs.push((0, Vec::new()));
+        let result = self.emit_seq_unquantized(len, f);
+        let (elts, floats) = self.float_seqs.pop().unwrap();
+        let mut text = self.writer.captures.pop().unwrap();
+        try!(result);
+        if floats.len() == elts {
+            let (curr_indent, indent) = self.indents();
+            let value = quantization.encode(&floats);
+            if let EncodingFormat::Pretty{..} = self.format {
+                try!(write!(self.writer, "{{\n"));
+                try!(spaces(&mut self.writer, curr_indent + indent));
+                try!(write!(self.writer, "\"$tag\": {},\n", quantization.tag()));
+                try!(spaces(&mut self.writer, curr_indent + indent));
+                try!(write!(self.writer, "\"value\": \"{}\"\n", value));
+                try!(spaces(&mut self.writer, curr_indent));
+                try!(write!(self.writer, "}}"));
+            } else {
+                try!(write!(self.writer, "{{\"$tag\":{},\"value\":\"{}\"}}",
+                            quantization.tag(), value));
+            }
+        } else {
+            try!(write!(self.writer, "{}", text));
+        }
+        text.clear();
+        self.spare.push(text);
+        Ok(())
     }
-    fn emit_tuple_struct_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
+
+    // Writes an element of a sequence being written in columns.
+    fn emit_column_elt<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        self.emit_seq_elt(idx, f)
+        let (rows, plain) = {
+            let columns = self.columns.last().unwrap();
+            (columns.rows, columns.plain)
+        };
+        if plain {
+            return self.emit_array_elt(idx, f);
+        }
+        self.row_start = true;
+        self.in_columns = false;
+        if rows == 0 {
+            // Write the first element into a buffer, in case it is not a
+            // struct and the sequence is an array after all.
+            let buf = self.spare.pop().unwrap_or_default();
+            self.writer.captures.push(buf);
+            let result = self.emit_array_elt(idx, f);
+            let mut elt = self.writer.captures.pop().unwrap();
+            self.in_columns = true;
+            try!(result);
+            if self.columns.last().unwrap().rows == 0 {
+                self.columns.last_mut().unwrap().plain = true;
+                try!(write!(self.writer, "[{}", elt));
+            }
+            elt.clear();
+            self.spare.push(elt);
+        } else {
+            let result = f(self);
+            self.in_columns = true;
+            try!(result);
+            if self.columns.last().unwrap().rows == rows {
+                return Err(EncoderError::BadColumnarRow);
+            }
+        }
+        Ok(())
     }
 
-    fn emit_option<F>(&mut self, f: F) -> EncodeResult<()> where
+    // Writes a field of a struct which is a row of columns into its column.
+    fn emit_column<F>(&mut self, name: &str, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        f(self)
+        let buf = self.spare.pop().unwrap_or_default();
+        self.writer.captures.push(buf);
+        let result = self.write_field_name(name);
+        let mut written = self.writer.captures.pop().unwrap();
+        try!(result);
+        let (i, rows, indent, mut values) = {
+            let columns = self.columns.last_mut().unwrap();
+            let i = columns.fields;
+            columns.fields += 1;
+            if columns.rows == 0 {
+                columns.names.push(written);
+                columns.values.push(string::String::new());
+            } else if columns.names.get(i) == Some(&written) {
+                written.clear();
+                self.spare.push(written);
+            } else {
+                return Err(EncoderError::BadColumnarRow);
+            }
+            let values = mem::take(&mut columns.values[i]);
+            (i, columns.rows, columns.indent, values)
+        };
+        if rows != 0 {
+            values.push(',');
+        }
+        let (curr_indent, step) = self.indents();
+        if let EncodingFormat::Pretty{..} = self.format {
+            values.push('\n');
+            try!(spaces(&mut values, indent + 2 * step));
+        }
+        self.writer.captures.push(values);
+        self.set_curr_indent(indent + 2 * step);
+        let omitted = mem::replace(&mut self.omitted, false);
+        self.in_row = false;
+        let result = f(self);
+        self.in_row = true;
+        // A `None` to leave out is written as `null` all the same.
+        self.omitted = omitted;
+        self.set_curr_indent(curr_indent);
+        let values = self.writer.captures.pop().unwrap();
+        self.columns.last_mut().unwrap().values[i] = values;
+        result
     }
-    fn emit_option_none(&mut self) -> EncodeResult<()> {
+
+    // Called before writing a non-string primitive as a map key.
+    fn check_primitive_key(&self) -> EncodeResult<()> {
+        match self.config.map_key_policy {
+            MapKeyPolicy::Strict => Err(EncoderError::BadHashmapKey),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'a> ::Encoder for Encoder<'a> {
+    type Error = EncoderError;
+
+    fn emit_nil(&mut self) -> EncodeResult<()> {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        self.emit_nil()
+        try!(write!(self.writer, "null"));
+        Ok(())
     }
-    fn emit_option_some<F>(&mut self, f: F) -> EncodeResult<()> where
+
+    fn emit_usize(&mut self, v: usize) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    fn emit_u8(&mut self, v: u8) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+
+    fn emit_isize(&mut self, v: isize) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    fn emit_i8(&mut self, v: i8) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+
+    // 128-bit integers outside the range a JSON number can carry exactly
+    // (as an IEEE double) are written as strings instead.
+    fn emit_u128(&mut self, v: u128) -> EncodeResult<()> {
+        if v <= MAX_SAFE_INTEGER as u128 {
+            emit_enquoted_if_mapkey!(self, v)
+        } else {
+            if self.is_emitting_map_key { try!(self.check_primitive_key()); }
+            try!(write!(self.writer, "\"{}\"", v));
+            Ok(())
+        }
+    }
+    fn emit_i128(&mut self, v: i128) -> EncodeResult<()> {
+        if v >= -(MAX_SAFE_INTEGER as i128) && v <= MAX_SAFE_INTEGER as i128 {
+            emit_enquoted_if_mapkey!(self, v)
+        } else {
+            if self.is_emitting_map_key { try!(self.check_primitive_key()); }
+            try!(write!(self.writer, "\"{}\"", v));
+            Ok(())
+        }
+    }
+
+    fn emit_bool(&mut self, v: bool) -> EncodeResult<()> {
+        if self.is_emitting_map_key {
+            if self.config.map_key_policy != MapKeyPolicy::StringifyAll {
+                return Err(EncoderError::BadHashmapKey);
+            }
+            try!(write!(self.writer, "\"{}\"", v));
+            return Ok(());
+        }
+        if v {
+            try!(write!(self.writer, "true"));
+        } else {
+            try!(write!(self.writer, "false"));
+        }
+        Ok(())
+    }
+
+    fn emit_f64(&mut self, v: f64) -> EncodeResult<()> {
+        if mem::replace(&mut self.float_elt, false) {
+            self.float_seqs.last_mut().unwrap().1.push(v);
+        }
+        emit_enquoted_if_mapkey!(self, fmt_number_or_null(v))
+    }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult<()> {
+        self.emit_f64(v as f64)
+    }
+
+    fn emit_char(&mut self, v: char) -> EncodeResult<()> {
+        if self.config.ascii_only {
+            self.escape(v.encode_utf8(&mut [0; 4]))
+        } else {
+            escape_char(&mut self.writer, v)
+        }
+    }
+    fn emit_str(&mut self, v: &str) -> EncodeResult<()> {
+        self.escape(v)
+    }
+    fn emit_wtf8(&mut self, v: &Wtf8Buf) -> EncodeResult<()> {
+        match v.as_str() {
+            Some(s) => self.escape(s),
+            None => escape_wtf8(&mut self.writer, v, self.config.ascii_only),
+        }
+    }
+
+    fn emit_path(&mut self, v: &Path) -> EncodeResult<()> {
+        match v.to_str() {
+            Some(s) if self.config.path_strings => self.emit_str(s),
+            _ => ::serialize::encode_native_path(v, self),
+        }
+    }
+
+    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        self.row_start = false;
+        self.float_elt = false;
         f(self)
     }
 
-    fn emit_seq<F>(&mut self, len: usize, f: F) -> EncodeResult<()> where
+    fn emit_enum_variant<F>(&mut self,
+                            name: &str,
+                            _id: usize,
+                            cnt: usize,
+                            f: F)
+                            -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        if len == 0 {
-            try!(write!(self.writer, "[]"));
+        // enums are encoded as strings or objects
+        // Bunny => "Bunny"
+        // Kangaroo(34,"William") => {"variant": "Kangaroo", "fields": [34,"William"]}
+        self.field_option = None;
+        if cnt == 0 {
+            self.escape(name)
         } else {
-            try!(write!(self.writer, "["));
+            if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
             if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
+                try!(write!(self.writer, "{{\n"));
+                *curr_indent += indent;
+                try!(spaces(&mut self.writer, *curr_indent));
+                try!(write!(self.writer, "\"variant\": "));
+                try!(escape_str_as(&mut self.writer, name, self.config.ascii_only));
+                try!(write!(self.writer, ",\n"));
+                try!(spaces(&mut self.writer, *curr_indent));
+                try!(write!(self.writer, "\"fields\": [\n"));
                 *curr_indent += indent;
+            } else {
+                try!(write!(self.writer, "{{\"variant\":"));
+                try!(self.escape(name));
+                try!(write!(self.writer, ",\"fields\":["));
             }
             try!(f(self));
             if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
                 *curr_indent -= indent;
                 try!(write!(self.writer, "\n"));
-                try!(spaces(self.writer, *curr_indent));
+                try!(spaces(&mut self.writer, *curr_indent));
+                *curr_indent -= indent;
+                try!(write!(self.writer, "]\n"));
+                try!(spaces(&mut self.writer, *curr_indent));
+                try!(write!(self.writer, "}}"));
+            } else {
+                try!(write!(self.writer, "]}}"));
             }
-            try!(write!(self.writer, "]"));
+            Ok(())
         }
-        Ok(())
     }
 
-    fn emit_seq_elt<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
+    fn emit_enum_variant_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
         if idx != 0 {
             try!(write!(self.writer, ","));
+            if let EncodingFormat::Pretty{..} = self.format {
+                try!(write!(self.writer, "\n"));
+            }
         }
-        if let EncodingFormat::Pretty{ref mut curr_indent, ..} = self.format {
-            try!(write!(self.writer, "\n"));
-            try!(spaces(self.writer, *curr_indent));
+        if let EncodingFormat::Pretty{curr_indent, ..} = self.format {
+            try!(spaces(&mut self.writer, curr_indent));
         }
         f(self)
     }
 
-    fn emit_map<F>(&mut self, len: usize, f: F) -> EncodeResult<()> where
+    fn emit_enum_struct_variant<F>(&mut self,
+                                   name: &str,
+                                   id: usize,
+                                   cnt: usize,
+                                   f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        if len == 0 {
+        self.emit_enum_variant(name, id, cnt, f)
+    }
+
+    fn emit_enum_struct_variant_field<F>(&mut self,
+                                         _: &str,
+                                         idx: usize,
+                                         f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        self.emit_enum_variant_arg(idx, f)
+    }
+
+
+    fn emit_struct<F>(&mut self, _: &str, len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        self.field_option = None;
+        self.float_elt = false;
+        if mem::replace(&mut self.row_start, false) && len != 0 {
+            self.columns.last_mut().unwrap().fields = 0;
+            let outer = mem::replace(&mut self.in_row, true);
+            let result = f(self);
+            self.in_row = outer;
+            try!(result);
+            let columns = self.columns.last_mut().unwrap();
+            if columns.fields != columns.names.len() {
+                return Err(EncoderError::BadColumnarRow);
+            }
+            columns.rows += 1;
+        } else if len == 0 {
             try!(write!(self.writer, "{{}}"));
         } else {
             try!(write!(self.writer, "{{"));
             if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
                 *curr_indent += indent;
             }
+            let skips = self.skips_fields();
+            if skips {
+                self.struct_fields.push(0);
+            }
             try!(f(self));
+            // With every field left out, the struct is written as `{}`.
+            let empty = skips && self.struct_fields.pop() == Some(0);
             if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
                 *curr_indent -= indent;
-                try!(write!(self.writer, "\n"));
-                try!(spaces(self.writer, *curr_indent));
+                if !empty {
+                    try!(write!(self.writer, "\n"));
+                    try!(spaces(&mut self.writer, *curr_indent));
+                }
             }
             try!(write!(self.writer, "}}"));
         }
         Ok(())
     }
 
-    fn emit_map_elt_key<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
+    fn emit_struct_field<F>(&mut self, name: &str, idx: usize, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        if idx != 0 {
-            try!(write!(self.writer, ","));
-        }
-        if let EncodingFormat::Pretty{curr_indent, ..} = self.format {
-            try!(write!(self.writer, "\n"));
-            try!(spaces(self.writer, curr_indent));
-        }
-        self.is_emitting_map_key = true;
-        try!(f(self));
-        self.is_emitting_map_key = false;
-        Ok(())
+        self.field_option = Some(self.field_option_policy(name));
+        let result = if self.in_row {
+            self.emit_column(name, f)
+        } else if self.skips_fields() {
+            self.emit_skippable_field(name, idx, f)
+        } else {
+            try!(self.emit_field_name(name, idx));
+            f(self)
+        };
+        self.field_option = None;
+        result
     }
 
-    fn emit_map_elt_val<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()> where
+    fn emit_tuple<F>(&mut self, len: usize, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        if let EncodingFormat::Pretty{..} = self.format {
-            try!(write!(self.writer, ": "));
-        } else {
-            try!(write!(self.writer, ":"));
-        }
-        f(self)
+        self.emit_array(len, f)
     }
-}
-
-impl Encodable for Json {
-    fn encode<S: ::Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
-        match *self {
-            Json::I64(v) => v.encode(e),
-            Json::U64(v) => v.encode(e),
-            Json::F64(v) => v.encode(e),
-            Json::String(ref v) => v.encode(e),
-            Json::Boolean(v) => v.encode(e),
-            Json::Array(ref v) => v.encode(e),
-            Json::Object(ref v) => v.encode(e),
-            Json::Null => e.emit_nil(),
-        }
+    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        self.emit_array_elt(idx, f)
     }
-}
-
-/// Create an `AsJson` wrapper which can be used to print a value as JSON
-/// on-the-fly via `write!`
-pub fn as_json<T: Encodable>(t: &T) -> AsJson<T> {
-    AsJson { inner: t }
-}
 
-/// Create an `AsPrettyJson` wrapper which can be used to print a value as JSON
-/// on-the-fly via `write!`
-pub fn as_pretty_json<T: Encodable>(t: &T) -> AsPrettyJson<T> {
-    AsPrettyJson { inner: t, indent: None }
-}
-
-impl Json {
-    /// Decodes a json value from an `&mut io::Read`
-    pub fn from_reader(rdr: &mut io::Read) -> Result<Self, BuilderError> {
-        let contents = {
-            let mut c = Vec::new();
-            try!(rdr.read_to_end(&mut c));
-            c
-        };
-        let s = match str::from_utf8(&contents).ok() {
-            Some(s) => s,
-            _       => return Err(SyntaxError(NotUtf8, 0, 0))
-        };
-        let mut builder = Builder::new(s.chars());
-        builder.build()
+    fn emit_tuple_struct<F>(&mut self, _: &str, len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        self.emit_array(len, f)
     }
-
-    /// Decodes a json value from a string
-    pub fn from_str(s: &str) -> Result<Self, BuilderError> {
-        let mut builder = Builder::new(s.chars());
-        builder.build()
+    fn emit_tuple_struct_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        self.emit_array_elt(idx, f)
     }
 
-    /// Borrow this json object as a pretty object to generate a pretty
-    /// representation for it via `Display`.
-    pub fn pretty(&self) -> PrettyJson {
-        PrettyJson { inner: self }
+    fn emit_option<F>(&mut self, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        self.row_start = false;
+        self.float_elt = false;
+        self.option = match self.field_option.take() {
+            Some(policy) => policy,
+            None => self.nested_option_policy(),
+        };
+        f(self)
     }
-
-     /// If the Json value is an Object, returns the value associated with the provided key.
-    /// Otherwise, returns None.
-    pub fn find<'a>(&'a self, key: &str) -> Option<&'a Json>{
-        match self {
-            &Json::Object(ref map) => map.get(key),
-            _ => None
+    fn emit_option_none(&mut self) -> EncodeResult<()> {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        let nested = self.nested_option_policy();
+        let policy = mem::replace(&mut self.option, nested);
+        match policy {
+            OptionPolicy::Null => self.emit_nil(),
+            OptionPolicy::Omit => {
+                self.omitted = true;
+                self.emit_nil()
+            }
+            OptionPolicy::Array => self.emit_array(0, |_| Ok(())),
+        }
+    }
+    fn emit_option_some<F>(&mut self, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        let nested = self.nested_option_policy();
+        let policy = mem::replace(&mut self.option, nested);
+        match policy {
+            OptionPolicy::Array => self.emit_array(1, |e| e.emit_array_elt(0, f)),
+            _ => f(self),
         }
     }
 
-    /// Attempts to get a nested Json Object for each key in `keys`.
-    /// If any key is found not to exist, find_path will return None.
-    /// Otherwise, it will return the Json value associated with the final key.
-    pub fn find_path<'a>(&'a self, keys: &[&str]) -> Option<&'a Json>{
-        let mut target = self;
-        for key in keys.iter() {
-            match target.find(*key) {
-                Some(t) => { target = t; },
-                None => return None
+    fn emit_seq<F>(&mut self, len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if let Some(quantization) = self.config.quantization {
+            if len != 0 && !self.is_emitting_map_key {
+                return self.emit_quantizable(quantization, len, f);
             }
         }
-        Some(target)
+        self.emit_seq_unquantized(len, f)
     }
 
-    /// If the Json value is an Object, performs a depth-first search until
-    /// a value associated with the provided key is found. If no value is found
-    /// or the Json value is not an Object, returns None.
-    pub fn search<'a>(&'a self, key: &str) -> Option<&'a Json> {
-        match self {
-            &Json::Object(ref map) => {
-                match map.get(key) {
-                    Some(json_value) => Some(json_value),
-                    None => {
-                        for (_, v) in map.iter() {
-                            match v.search(key) {
-                                x if x.is_some() => return x,
-                                _ => ()
-                            }
-                        }
-                        None
-                    }
-                }
-            },
-            _ => None
+    fn emit_seq_elt<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        if self.config.quantization.is_some() {
+            if let Some(seq) = self.float_seqs.last_mut() {
+                seq.0 += 1;
+                self.float_elt = true;
+            }
         }
+        let result = if self.in_columns {
+            self.emit_column_elt(idx, f)
+        } else {
+            self.emit_array_elt(idx, f)
+        };
+        self.float_elt = false;
+        result
     }
 
-    /// Returns true if the Json value is an Object. Returns false otherwise.
-    pub fn is_object<'a>(&'a self) -> bool {
-        self.as_object().is_some()
+    fn emit_map<F>(&mut self, len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        self.field_option = None;
+        self.row_start = false;
+        self.float_elt = false;
+        if self.config.map_key_policy == MapKeyPolicy::Pairs {
+            return self.emit_array(len, |e| e.emit_map_entries(f));
+        }
+        if len == 0 {
+            try!(write!(self.writer, "{{}}"));
+        } else {
+            try!(write!(self.writer, "{{"));
+            if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
+                *curr_indent += indent;
+            }
+            try!(self.emit_map_entries(f));
+            if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
+                *curr_indent -= indent;
+                try!(write!(self.writer, "\n"));
+                try!(spaces(&mut self.writer, *curr_indent));
+            }
+            try!(write!(self.writer, "}}"));
+        }
+        Ok(())
     }
 
-    /// If the Json value is an Object, returns a reference to the associated BTreeMap.
-    /// Returns None otherwise.
-    pub fn as_object<'a>(&'a self) -> Option<&'a Object> {
-        match self {
-            &Json::Object(ref map) => Some(map),
-            _ => None
+    fn emit_map_elt_key<F>(&mut self, mut idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        if self.config.sort_map_keys {
+            // Capture the entry as if it were the first; `emit_map_entries`
+            // adds the separators once the entries are sorted.
+            let buf = self.spare.pop().unwrap_or_default();
+            self.writer.captures.push(buf);
+            idx = 0;
+        }
+        if self.config.map_key_policy == MapKeyPolicy::Pairs {
+            // Open a two-element array, exactly as `emit_seq` would.
+            try!(self.emit_array_elt(idx, |e| {
+                try!(write!(e.writer, "["));
+                if let EncodingFormat::Pretty{ref mut curr_indent, indent} = e.format {
+                    *curr_indent += indent;
+                }
+                Ok(())
+            }));
+            try!(self.emit_array_elt(0, f));
+        } else {
+            if idx != 0 {
+                try!(write!(self.writer, ","));
+            }
+            if let EncodingFormat::Pretty{curr_indent, ..} = self.format {
+                try!(write!(self.writer, "\n"));
+                try!(spaces(&mut self.writer, curr_indent));
+            }
+            self.is_emitting_map_key = true;
+            try!(f(self));
+            self.is_emitting_map_key = false;
         }
+        if self.config.sort_map_keys {
+            let key_len = self.writer.captures.last().unwrap().len();
+            self.sorted_entries.last_mut().unwrap().push((key_len, string::String::new()));
+        }
+        Ok(())
     }
 
-    /// If the Json value is an Object, returns a mutable reference to the associated BTreeMap.
-    /// Returns None otherwise.
-    pub fn as_object_mut<'a>(&'a mut self) -> Option<&'a mut Object> {
-        match self {
-            &mut Json::Object(ref mut map) => Some(map),
-            _ => None
+    fn emit_map_elt_val<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        if self.config.map_key_policy == MapKeyPolicy::Pairs {
+            try!(self.emit_array_elt(1, f));
+            if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
+                *curr_indent -= indent;
+                try!(write!(self.writer, "\n"));
+                try!(spaces(&mut self.writer, *curr_indent));
+            }
+            try!(write!(self.writer, "]"));
+        } else {
+            if let EncodingFormat::Pretty{..} = self.format {
+                try!(write!(self.writer, ": "));
+            } else {
+                try!(write!(self.writer, ":"));
+            }
+            try!(f(self));
         }
+        if self.config.sort_map_keys {
+            let entry = self.writer.captures.pop().unwrap();
+            self.sorted_entries.last_mut().unwrap().last_mut().unwrap().1 = entry;
+        }
+        Ok(())
     }
 
-    /// If the Json value is an Object, returns the associated BTreeMap.
-    /// Returns None otherwise.
-    pub fn into_object(self) -> Option<Object> {
-        match self {
-            Json::Object(map) => Some(map),
-            _ => None
-        }
+    fn format(&self) -> Option<&'static str> {
+        Some("json")
     }
+}
 
-    /// Returns true if the Json value is an Array. Returns false otherwise.
-    pub fn is_array<'a>(&'a self) -> bool {
-        self.as_array().is_some()
+/// An encoder which builds a `Json` tree in memory.
+///
+/// The tree is the one `Encoder` would write, parsed back: enums, options,
+/// maps with stringified keys and 128-bit integers are represented in the
+/// same way, non-negative integers are `U64` and negative ones `I64`, and
+/// NaN and infinite floats are `Null`. See `to_value`.
+pub struct ValueEncoder {
+    // The arrays and objects being built, innermost last.
+    stack: Vec<ValueFrame>,
+    // The last complete value.
+    value: Option<Json>,
+}
+
+enum ValueFrame {
+    Array(Array),
+    // An object, with the key of the entry whose value is being encoded.
+    Object(Object, Option<string::String>),
+}
+
+impl ValueEncoder {
+    /// Creates an encoder with no value yet.
+    pub fn new() -> ValueEncoder {
+        ValueEncoder { stack: Vec::new(), value: None }
     }
 
-    /// If the Json value is an Array, returns a reference to the associated vector.
-    /// Returns None otherwise.
-    pub fn as_array<'a>(&'a self) -> Option<&'a Array> {
-        match self {
-            &Json::Array(ref array) => Some(&*array),
-            _ => None
-        }
+    /// Returns the value encoded, or `Null` if nothing has been.
+    pub fn into_value(self) -> Json {
+        self.value.unwrap_or(Json::Null)
     }
 
-    /// If the Json value is an Array, returns a mutable reference to the associated vector.
-    /// Returns None otherwise.
-    pub fn as_array_mut<'a>(&'a mut self) -> Option<&'a mut Array> {
-        match self {
-            &mut Json::Array(ref mut list) => Some(list),
-            _ => None
-        }
+    fn set(&mut self, value: Json) -> EncodeResult<()> {
+        self.value = Some(value);
+        Ok(())
     }
 
-    /// If the Json value is an Array, returns the associated vector.
-    /// Returns None otherwise.
-    pub fn into_array(self) -> Option<Array> {
-        match self {
-            Json::Array(array) => Some(array),
-            _ => None
-        }
+    fn set_int(&mut self, v: i64) -> EncodeResult<()> {
+        self.set(if v < 0 { Json::I64(v) } else { Json::U64(v as u64) })
     }
 
-    /// Returns true if the Json value is a String. Returns false otherwise.
-    pub fn is_string<'a>(&'a self) -> bool {
-        self.as_string().is_some()
+    fn capture<F>(&mut self, f: F) -> EncodeResult<Json>
+        where F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>
+    {
+        try!(f(self));
+        Ok(self.value.take().unwrap_or(Json::Null))
     }
 
-    /// If the Json value is a String, returns the associated str.
-    /// Returns None otherwise.
-    pub fn as_string<'a>(&'a self) -> Option<&'a str> {
-        match *self {
-            Json::String(ref s) => Some(&s),
-            _ => None
-        }
+    fn build<F>(&mut self, frame: ValueFrame, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>
+    {
+        self.stack.push(frame);
+        let result = f(self);
+        let value = match self.stack.pop() {
+            Some(ValueFrame::Array(items)) => Json::Array(items),
+            Some(ValueFrame::Object(entries, _)) => Json::Object(entries),
+            None => unreachable!(),
+        };
+        try!(result);
+        self.set(value)
     }
 
-    /// Returns true if the Json value is a Number. Returns false otherwise.
-    pub fn is_number(&self) -> bool {
-        match *self {
-            Json::I64(_) | Json::U64(_) | Json::F64(_) => true,
-            _ => false,
+    fn push_item<F>(&mut self, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>
+    {
+        let value = try!(self.capture(f));
+        if let Some(&mut ValueFrame::Array(ref mut items)) = self.stack.last_mut() {
+            items.push(value);
         }
+        Ok(())
     }
 
-    /// Returns true if the Json value is a i64. Returns false otherwise.
-    pub fn is_i64(&self) -> bool {
-        match *self {
-            Json::I64(_) => true,
-            _ => false,
+    fn push_entry<F>(&mut self, key: Option<string::String>, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>
+    {
+        let value = try!(self.capture(f));
+        if let Some(&mut ValueFrame::Object(ref mut entries, ref mut pending)) =
+               self.stack.last_mut() {
+            if let Some(key) = key.or_else(|| pending.take()) {
+                entries.insert(key, value);
+            }
         }
+        Ok(())
     }
+}
 
-    /// Returns true if the Json value is a u64. Returns false otherwise.
-    pub fn is_u64(&self) -> bool {
-        match *self {
-            Json::U64(_) => true,
-            _ => false,
+impl Default for ValueEncoder {
+    fn default() -> ValueEncoder { ValueEncoder::new() }
+}
+
+impl ::Encoder for ValueEncoder {
+    type Error = EncoderError;
+
+    fn emit_nil(&mut self) -> EncodeResult<()> { self.set(Json::Null) }
+
+    fn emit_usize(&mut self, v: usize) -> EncodeResult<()> { self.set(Json::U64(v as u64)) }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult<()> { self.set(Json::U64(v)) }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult<()> { self.set(Json::U64(v as u64)) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult<()> { self.set(Json::U64(v as u64)) }
+    fn emit_u8(&mut self, v: u8) -> EncodeResult<()> { self.set(Json::U64(v as u64)) }
+
+    fn emit_isize(&mut self, v: isize) -> EncodeResult<()> { self.set_int(v as i64) }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult<()> { self.set_int(v) }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult<()> { self.set_int(v as i64) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult<()> { self.set_int(v as i64) }
+    fn emit_i8(&mut self, v: i8) -> EncodeResult<()> { self.set_int(v as i64) }
+
+    // As in `Encoder`, 128-bit integers which a JSON number cannot carry
+    // exactly are strings.
+    fn emit_u128(&mut self, v: u128) -> EncodeResult<()> {
+        if v <= MAX_SAFE_INTEGER as u128 {
+            self.set(Json::U64(v as u64))
+        } else {
+            self.set(Json::from(v.to_string()))
         }
     }
-
-    /// Returns true if the Json value is a f64. Returns false otherwise.
-    pub fn is_f64(&self) -> bool {
-        match *self {
-            Json::F64(_) => true,
-            _ => false,
+    fn emit_i128(&mut self, v: i128) -> EncodeResult<()> {
+        if v >= -(MAX_SAFE_INTEGER as i128) && v <= MAX_SAFE_INTEGER as i128 {
+            self.set_int(v as i64)
+        } else {
+            self.set(Json::from(v.to_string()))
         }
     }
 
-    /// If the Json value is a number, return or cast it to a i64.
-    /// Returns None otherwise.
-    pub fn as_i64(&self) -> Option<i64> {
-        match *self {
-            Json::I64(n) => Some(n),
-            Json::U64(n) if n >= i64::MAX as u64 => None,
-            Json::U64(n) => Some(n as i64),
-            _ => None
+    fn emit_bool(&mut self, v: bool) -> EncodeResult<()> { self.set(Json::Boolean(v)) }
+    fn emit_f64(&mut self, v: f64) -> EncodeResult<()> {
+        self.set(if v.is_finite() { Json::F64(v) } else { Json::Null })
+    }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult<()> { self.emit_f64(v as f64) }
+    fn emit_char(&mut self, v: char) -> EncodeResult<()> { self.set(Json::from(v.to_string())) }
+    fn emit_str(&mut self, v: &str) -> EncodeResult<()> { self.set(Json::from(v)) }
+    fn emit_wtf8(&mut self, v: &Wtf8Buf) -> EncodeResult<()> {
+        match v.as_str() {
+            Some(s) => self.emit_str(s),
+            None => self.set(Json::Wtf8(v.clone())),
         }
     }
 
-    /// If the Json value is a number, return or cast it to a u64.
-    /// Returns None otherwise.
-    pub fn as_u64(&self) -> Option<u64> {
-        match *self {
-            Json::I64(n) if n >= 0 => Some(n as u64),
-            Json::U64(n) => Some(n),
-            _ => None
-        }
+    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        f(self)
     }
 
-    /// If the Json value is a number, return or cast it to a f64.
-    /// Returns None otherwise.
-    pub fn as_f64(&self) -> Option<f64> {
-        match *self {
-            Json::I64(n) => Some(n as f64),
-            Json::U64(n) => Some(n as f64),
-            Json::F64(n) => Some(n),
-            _ => None
+    fn emit_enum_variant<F>(&mut self, name: &str, _id: usize, cnt: usize, f: F)
+                            -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        if cnt == 0 {
+            return self.emit_str(name);
         }
+        try!(self.build(ValueFrame::Array(Vec::new()), f));
+        let fields = self.value.take().unwrap_or(Json::Null);
+        let mut variant = BTreeMap::new();
+        variant.insert("variant".to_string(), Json::from(name));
+        variant.insert("fields".to_string(), fields);
+        self.set(Json::Object(variant))
     }
 
-    /// Returns true if the Json value is a Boolean. Returns false otherwise.
-    pub fn is_boolean(&self) -> bool {
-        self.as_boolean().is_some()
+    fn emit_enum_variant_arg<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        self.push_item(f)
     }
 
-    /// If the Json value is a Boolean, returns the associated bool.
-    /// Returns None otherwise.
-    pub fn as_boolean(&self) -> Option<bool> {
-        match self {
-            &Json::Boolean(b) => Some(b),
-            _ => None
-        }
+    fn emit_enum_struct_variant<F>(&mut self, name: &str, id: usize, cnt: usize, f: F)
+                                   -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_enum_variant(name, id, cnt, f)
     }
 
-    /// Returns true if the Json value is a Null. Returns false otherwise.
-    pub fn is_null(&self) -> bool {
-        self.as_null().is_some()
+    fn emit_enum_struct_variant_field<F>(&mut self, _: &str, idx: usize, f: F)
+                                         -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_enum_variant_arg(idx, f)
     }
 
-    /// If the Json value is a Null, returns ().
-    /// Returns None otherwise.
-    pub fn as_null(&self) -> Option<()> {
-        match self {
-            &Json::Null => Some(()),
-            _ => None
+    fn emit_struct<F>(&mut self, _: &str, len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        // Like `Encoder`, write nothing of a struct without fields.
+        if len == 0 {
+            return self.set(Json::Object(BTreeMap::new()));
         }
+        self.build(ValueFrame::Object(BTreeMap::new(), None), f)
     }
-}
 
-impl<'a> Index<&'a str>  for Json {
-    type Output = Json;
+    fn emit_struct_field<F>(&mut self, name: &str, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        self.push_entry(Some(name.to_string()), f)
+    }
 
-    fn index(&self, idx: &str) -> &Json {
-        self.find(idx).unwrap()
+    fn emit_tuple<F>(&mut self, len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_seq(len, f)
+    }
+    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_tuple_struct<F>(&mut self, _: &str, len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_seq(len, f)
+    }
+    fn emit_tuple_struct_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        f(self)
+    }
+    fn emit_option_none(&mut self) -> EncodeResult<()> {
+        self.emit_nil()
+    }
+    fn emit_option_some<F>(&mut self, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        f(self)
+    }
+
+    fn emit_seq<F>(&mut self, _len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        self.build(ValueFrame::Array(Vec::new()), f)
+    }
+    fn emit_seq_elt<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        self.push_item(f)
+    }
+
+    fn emit_map<F>(&mut self, _len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        self.build(ValueFrame::Object(BTreeMap::new(), None), f)
+    }
+    fn emit_map_elt_key<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        // Keys are stringified as `MapKeyPolicy::Stringify` does.
+        let key = match try!(self.capture(f)) {
+            Json::String(s) => s,
+            Json::Wtf8(s) => s.to_string_lossy().into_owned(),
+            Json::Array(_) | Json::Object(_) | Json::Null | Json::Boolean(_) => {
+                return Err(EncoderError::BadHashmapKey);
+            }
+            scalar => scalar.to_string(),
+        };
+        if let Some(&mut ValueFrame::Object(_, ref mut pending)) = self.stack.last_mut() {
+            *pending = Some(key);
+        }
+        Ok(())
+    }
+    fn emit_map_elt_val<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut ValueEncoder) -> EncodeResult<()>,
+    {
+        self.push_entry(None, f)
+    }
+
+    fn format(&self) -> Option<&'static str> {
+        Some("json")
     }
 }
 
-impl Index<usize> for Json {
-    type Output = Json;
+/// Encodes `value` as a `Json` tree, without writing it out; see
+/// `ValueEncoder`.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::json::{self, Json};
+///
+/// fn main() {
+///     let value = json::to_value(&(1, Some("two"), [3.5])).unwrap();
+///     assert_eq!(value, Json::from_str("[1, \"two\", [3.5]]").unwrap());
+/// }
+/// ```
+pub fn to_value<T: Encodable + ?Sized>(value: &T) -> EncodeResult<Json> {
+    let mut encoder = ValueEncoder::new();
+    try!(value.encode(&mut encoder));
+    Ok(encoder.into_value())
+}
 
-    fn index<'a>(&'a self, idx: usize) -> &'a Json {
-        match self {
-            &Json::Array(ref v) => &v[idx],
-            _ => panic!("can only index Json with usize if it is an array")
+impl Encodable for Json {
+    fn encode<S: ::Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
+        match *self {
+            Json::I64(v) => v.encode(e),
+            Json::U64(v) => v.encode(e),
+            Json::F64(v) => v.encode(e),
+            Json::String(ref v) => v.encode(e),
+            Json::Boolean(v) => v.encode(e),
+            Json::Array(ref v) => v.encode(e),
+            Json::Object(ref v) => v.encode(e),
+            Json::Null => e.emit_nil(),
+            Json::Wtf8(ref v) => v.encode(e),
         }
     }
 }
 
-/// The output of the streaming parser.
-#[derive(PartialEq, Debug)]
-pub enum JsonEvent {
-    ObjectStart,
-    ObjectEnd,
-    ArrayStart,
-    ArrayEnd,
-    BooleanValue(bool),
-    I64Value(i64),
-    U64Value(u64),
-    F64Value(f64),
-    StringValue(string::String),
-    NullValue,
-    Error(ParserError),
+impl ::Decodable for Json {
+    /// Reads the value as it is, with `read_json`, which only JSON's
+    /// `Decoder` can.
+    fn decode<D: ::Decoder>(d: &mut D) -> Result<Json, D::Error> {
+        read_json(d)
+    }
 }
 
-#[derive(PartialEq, Debug)]
-enum ParserState {
-    // Parse a value in an array, true means first element.
-    ParseArray(bool),
-    // Parse ',' or ']' after an element in an array.
-    ParseArrayComma,
-    // Parse a key:value in an object, true means first element.
-    ParseObject(bool),
-    // Parse ',' or ']' after an element in an object.
-    ParseObjectComma,
-    // Initial state.
-    ParseStart,
-    // Expecting the stream to end.
-    ParseBeforeFinish,
-    // Parsing can't continue.
-    ParseFinished,
+/// Reads the next value of `d` as a `Json` tree, as
+/// `Decoder::read_json` does, if `d` is a JSON `Decoder`, and fails
+/// otherwise.
+pub fn read_json<D: ::Decoder>(d: &mut D) -> Result<Json, D::Error> {
+    let read = d.as_any()
+                .and_then(|d| d.downcast_mut::<Decoder>())
+                .map(|decoder| decoder.read_json());
+    match read {
+        Some(Ok(json)) => Ok(json),
+        Some(Err(e)) => Err(d.error(&e.to_string())),
+        None => Err(d.error("this decoder cannot read values as JSON")),
+    }
 }
 
-/// A Stack represents the current position of the parser in the logical
-/// structure of the JSON stream.
-/// For example foo.bar[3].x
-pub struct Stack {
-    stack: Vec<InternalStackElement>,
-    str_buffer: Vec<u8>,
+/// Create an `AsJson` wrapper which can be used to print a value as JSON
+/// on-the-fly via `write!`
+pub fn as_json<T: Encodable>(t: &T) -> AsJson<T> {
+    AsJson { inner: t }
 }
 
-/// StackElements compose a Stack.
-/// For example, Key("foo"), Key("bar"), Index(3) and Key("x") are the
-/// StackElements compositing the stack that represents foo.bar[3].x
-#[derive(PartialEq, Clone, Debug)]
-pub enum StackElement<'l> {
-    Index(u32),
-    Key(&'l str),
+/// Create an `AsPrettyJson` wrapper which can be used to print a value as JSON
+/// on-the-fly via `write!`
+pub fn as_pretty_json<T: Encodable>(t: &T) -> AsPrettyJson<T> {
+    AsPrettyJson { inner: t, indent: None }
 }
 
-// Internally, Key elements are stored as indices in a buffer to avoid
-// allocating a string for every member of an object.
-#[derive(PartialEq, Clone, Debug)]
-enum InternalStackElement {
-    InternalIndex(u32),
-    InternalKey(u16, u16), // start, size
+/// How `Json::deep_merge` merges an array into another.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// The array merged in replaces the other
+    Replace,
+    /// The elements of the array merged in are appended to the other's
+    Concat,
+    /// Each element of the array merged in is merged into the element of
+    /// the other with an equal member with the given key, or appended if
+    /// there is none, or it has no such member
+    UnionBy(string::String),
+    /// Each element of the array merged in is merged into the element of
+    /// the other at the same index, or those past the other's end appended
+    ElementWise,
 }
 
-impl Stack {
-    pub fn new() -> Stack {
-        Stack { stack: Vec::new(), str_buffer: Vec::new() }
+impl Json {
+    /// Decodes a json value from an `&mut io::Read`
+    pub fn from_reader(rdr: &mut io::Read) -> Result<Self, BuilderError> {
+        let contents = {
+            let mut c = Vec::new();
+            try!(rdr.read_to_end(&mut c));
+            c
+        };
+        let s = match str::from_utf8(&contents).ok() {
+            Some(s) => s,
+            _       => return Err(SyntaxError(NotUtf8, 0, 0))
+        };
+        let mut builder = Builder::new(s.chars());
+        builder.build()
     }
 
-    /// Returns The number of elements in the Stack.
-    pub fn len(&self) -> usize { self.stack.len() }
+    /// Decodes a json value from a string
+    pub fn from_str(s: &str) -> Result<Self, BuilderError> {
+        let mut builder = Builder::new(s.chars());
+        builder.build()
+    }
 
-    /// Returns true if the stack is empty.
-    pub fn is_empty(&self) -> bool { self.stack.is_empty() }
+    /// Borrow this json object as a pretty object to generate a pretty
+    /// representation for it via `Display`.
+    pub fn pretty(&self) -> PrettyJson {
+        PrettyJson { inner: self }
+    }
 
-    /// Provides access to the StackElement at a given index.
-    /// lower indices are at the bottom of the stack while higher indices are
-    /// at the top.
-    pub fn get<'l>(&'l self, idx: usize) -> StackElement<'l> {
-        match self.stack[idx] {
-            InternalIndex(i) => StackElement::Index(i),
-            InternalKey(start, size) => {
-                StackElement::Key(str::from_utf8(
-                    &self.str_buffer[start as usize .. start as usize + size as usize]).unwrap())
-            }
-        }
+    /// Borrow this json object along with `comments`, keyed by JSON Pointer,
+    /// to generate a pretty representation with comments via `Display`.
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// use std::collections::BTreeMap;
+    /// use rustc_serialize::json::Json;
+    ///
+    /// fn main() {
+    ///     let json = Json::from_str("{\"port\": 8080}").unwrap();
+    ///     let mut comments = BTreeMap::new();
+    ///     comments.insert("/port".to_string(), "Port to listen on".to_string());
+    ///     assert_eq!(json.commented(&comments).to_string(),
+    ///                "{\n  // Port to listen on\n  \"port\": 8080\n}");
+    /// }
+    /// ```
+    pub fn commented<'a>(&'a self, comments: &'a BTreeMap<string::String, string::String>)
+                         -> CommentedJson<'a> {
+        CommentedJson { inner: self, comments: comments, indent: 2 }
     }
 
-    /// Compares this stack with an array of StackElements.
-    pub fn is_equal_to(&self, rhs: &[StackElement]) -> bool {
-        if self.stack.len() != rhs.len() { return false; }
-        for i in 0..rhs.len() {
-            if self.get(i) != rhs[i] { return false; }
+     /// If the Json value is an Object, returns the value associated with the provided key.
+    /// Otherwise, returns None.
+    pub fn find<'a>(&'a self, key: &str) -> Option<&'a Json>{
+        match self {
+            &Json::Object(ref map) => map.get(key),
+            _ => None
         }
-        return true;
     }
 
-    /// Returns true if the bottom-most elements of this stack are the same as
-    /// the ones passed as parameter.
-    pub fn starts_with(&self, rhs: &[StackElement]) -> bool {
-        if self.stack.len() < rhs.len() { return false; }
-        for i in 0..rhs.len() {
-            if self.get(i) != rhs[i] { return false; }
+    /// Attempts to get a nested Json Object for each key in `keys`.
+    /// If any key is found not to exist, find_path will return None.
+    /// Otherwise, it will return the Json value associated with the final key.
+    pub fn find_path<'a>(&'a self, keys: &[&str]) -> Option<&'a Json>{
+        let mut target = self;
+        for key in keys.iter() {
+            match target.find(*key) {
+                Some(t) => { target = t; },
+                None => return None
+            }
         }
-        return true;
+        Some(target)
     }
 
-    /// Returns true if the top-most elements of this stack are the same as
-    /// the ones passed as parameter.
-    pub fn ends_with(&self, rhs: &[StackElement]) -> bool {
-        if self.stack.len() < rhs.len() { return false; }
-        let offset = self.stack.len() - rhs.len();
-        for i in 0..rhs.len() {
-            if self.get(i + offset) != rhs[i] { return false; }
+    /// If the Json value is an Object, performs a depth-first search until
+    /// a value associated with the provided key is found. If no value is found
+    /// or the Json value is not an Object, returns None.
+    pub fn search<'a>(&'a self, key: &str) -> Option<&'a Json> {
+        match self {
+            &Json::Object(ref map) => {
+                match map.get(key) {
+                    Some(json_value) => Some(json_value),
+                    None => {
+                        for (_, v) in map.iter() {
+                            match v.search(key) {
+                                x if x.is_some() => return x,
+                                _ => ()
+                            }
+                        }
+                        None
+                    }
+                }
+            },
+            _ => None
         }
-        return true;
     }
 
-    /// Returns the top-most element (if any).
-    pub fn top<'l>(&'l self) -> Option<StackElement<'l>> {
-        return match self.stack.last() {
-            None => None,
-            Some(&InternalIndex(i)) => Some(StackElement::Index(i)),
-            Some(&InternalKey(start, size)) => {
-                Some(StackElement::Key(str::from_utf8(
-                    &self.str_buffer[start as usize .. (start+size) as usize]
-                ).unwrap()))
+    /// Calls `visitor.visit_value` with this value, to visit it and every
+    /// value in it; see `visit`.
+    pub fn walk_mut<V: visit::JsonVisitor + ?Sized>(&mut self, visitor: &mut V) {
+        visitor.visit_value(&[], self)
+    }
+
+    /// Sorts every array in this value, and the value itself if it is one,
+    /// into the order of `Json`'s `PartialOrd`, so that documents from
+    /// sources which list sets in no particular order compare, and encode,
+    /// the same. Objects need no sorting, as their keys are always kept in
+    /// order. NaNs, which are unordered, keep their places relative to the
+    /// values they are compared with.
+    pub fn sort_arrays_recursive(&mut self) {
+        match *self {
+            Json::Array(ref mut array) => {
+                for elt in array.iter_mut() {
+                    elt.sort_arrays_recursive();
+                }
+                array.sort_by(|a, b| a.partial_cmp(b).unwrap_or(cmp::Ordering::Equal));
+            }
+            Json::Object(ref mut object) => {
+                for member in object.values_mut() {
+                    member.sort_arrays_recursive();
+                }
             }
+            _ => {}
         }
     }
 
-    // Used by Parser to insert Key elements at the top of the stack.
-    fn push_key(&mut self, key: string::String) {
-        self.stack.push(InternalKey(self.str_buffer.len() as u16, key.len() as u16));
-        for c in key.as_bytes().iter() {
-            self.str_buffer.push(*c);
+    /// Sorts the array at the JSON Pointer (RFC 6901) `pointer` by the
+    /// members with `key` of its elements, keeping the order of elements
+    /// with equal keys, and putting elements without the key last. Returns
+    /// whether there was an array there.
+    pub fn sort_arrays_by(&mut self, pointer: &str, key: &str) -> bool {
+        let tokens = match pointer_tokens(pointer) {
+            Some(tokens) => tokens,
+            None => return false,
+        };
+        let mut target = self;
+        for token in &tokens {
+            let next = match *target {
+                Json::Array(ref mut array) => {
+                    token.parse::<usize>().ok().and_then(move |i| array.get_mut(i))
+                }
+                Json::Object(ref mut object) => object.get_mut(&token[..]),
+                _ => None,
+            };
+            target = match next {
+                Some(next) => next,
+                None => return false,
+            };
+        }
+        match *target {
+            Json::Array(ref mut array) => {
+                array.sort_by(|a, b| match (a.find(key), b.find(key)) {
+                    (Some(a), Some(b)) => a.partial_cmp(b).unwrap_or(cmp::Ordering::Equal),
+                    (Some(_), None) => cmp::Ordering::Less,
+                    (None, Some(_)) => cmp::Ordering::Greater,
+                    (None, None) => cmp::Ordering::Equal,
+                });
+                true
+            }
+            _ => false,
         }
     }
 
-    // Used by Parser to insert Index elements at the top of the stack.
-    fn push_index(&mut self, index: u32) {
-        self.stack.push(InternalIndex(index));
+    /// Merges `other` into this value, as a layer of configuration over
+    /// the one below it: the members of an object in `other` are merged
+    /// into the members with the same keys of an object here, added where
+    /// there are none, arrays are merged by `strategy`, and any other value
+    /// replaces the value here. Unlike in an RFC 7386 merge patch, `null`
+    /// is a value like any other, rather than removing a member.
+    pub fn deep_merge(&mut self, other: Json, strategy: MergeStrategy) {
+        self.merge_with(other, &strategy)
     }
 
-    // Used by Parser to remove the top-most element of the stack.
-    fn pop(&mut self) {
-        assert!(!self.is_empty());
-        match *self.stack.last().unwrap() {
-            InternalKey(_, sz) => {
-                let new_size = self.str_buffer.len() - sz as usize;
-                self.str_buffer.truncate(new_size);
+    fn merge_with(&mut self, other: Json, strategy: &MergeStrategy) {
+        match (self, other) {
+            (&mut Json::Object(ref mut object), Json::Object(other)) => {
+                for (key, value) in other {
+                    match object.entry(key) {
+                        Entry::Occupied(mut entry) => entry.get_mut().merge_with(value, strategy),
+                        Entry::Vacant(entry) => {
+                            entry.insert(value);
+                        }
+                    }
+                }
             }
-            InternalIndex(_) => {}
+            (&mut Json::Array(ref mut array), Json::Array(other)) => match *strategy {
+                MergeStrategy::Replace => *array = other,
+                MergeStrategy::Concat => array.extend(other),
+                MergeStrategy::UnionBy(ref key) => {
+                    for elt in other {
+                        let matching = elt.find(key).and_then(|id| {
+                            array.iter().position(|e| e.find(key) == Some(id))
+                        });
+                        match matching {
+                            Some(i) => array[i].merge_with(elt, strategy),
+                            None => array.push(elt),
+                        }
+                    }
+                }
+                MergeStrategy::ElementWise => {
+                    for (i, elt) in other.into_iter().enumerate() {
+                        if i < array.len() {
+                            array[i].merge_with(elt, strategy);
+                        } else {
+                            array.push(elt);
+                        }
+                    }
+                }
+            },
+            (this, other) => *this = other,
         }
-        self.stack.pop();
     }
 
-    // Used by Parser to test whether the top-most element is an index.
-    fn last_is_index(&self) -> bool {
-        if self.is_empty() { return false; }
-        return match *self.stack.last().unwrap() {
-            InternalIndex(_) => true,
-            _ => false,
-        }
+    /// Returns true if the Json value is an Object. Returns false otherwise.
+    pub fn is_object<'a>(&'a self) -> bool {
+        self.as_object().is_some()
     }
 
-    // Used by Parser to increment the index of the top-most element.
-    fn bump_index(&mut self) {
-        let len = self.stack.len();
-        let idx = match *self.stack.last().unwrap() {
-            InternalIndex(i) => { i + 1 }
-            _ => { panic!(); }
-        };
-        self.stack[len - 1] = InternalIndex(idx);
+    /// If the Json value is an Object, returns a reference to the associated BTreeMap.
+    /// Returns None otherwise.
+    pub fn as_object<'a>(&'a self) -> Option<&'a Object> {
+        match self {
+            &Json::Object(ref map) => Some(map),
+            _ => None
+        }
     }
-}
-
-/// A streaming JSON parser implemented as an iterator of JsonEvent, consuming
-/// an iterator of char.
-pub struct Parser<T> {
-    rdr: T,
-    ch: Option<char>,
-    line: usize,
-    col: usize,
-    // We maintain a stack representing where we are in the logical structure
-    // of the JSON stream.
-    stack: Stack,
-    // A state machine is kept to make it possible to interrupt and resume parsing.
-    state: ParserState,
-}
-
-impl<T: Iterator<Item = char>> Iterator for Parser<T> {
-    type Item = JsonEvent;
 
-    fn next(&mut self) -> Option<JsonEvent> {
-        if self.state == ParseFinished {
-            return None;
+    /// If the Json value is an Object, returns a mutable reference to the associated BTreeMap.
+    /// Returns None otherwise.
+    pub fn as_object_mut<'a>(&'a mut self) -> Option<&'a mut Object> {
+        match self {
+            &mut Json::Object(ref mut map) => Some(map),
+            _ => None
         }
+    }
 
-        if self.state == ParseBeforeFinish {
-            self.parse_whitespace();
-            // Make sure there is no trailing characters.
-            if self.eof() {
-                self.state = ParseFinished;
-                return None;
-            } else {
-                return Some(self.error_event(TrailingCharacters));
-            }
+    /// If the Json value is an Object, returns the associated BTreeMap.
+    /// Returns None otherwise.
+    pub fn into_object(self) -> Option<Object> {
+        match self {
+            Json::Object(map) => Some(map),
+            _ => None
         }
-
-        return Some(self.parse());
     }
-}
 
-impl<T: Iterator<Item = char>> Parser<T> {
-    /// Creates the JSON parser.
-    pub fn new(rdr: T) -> Parser<T> {
-        let mut p = Parser {
-            rdr: rdr,
-            ch: Some('\x00'),
-            line: 1,
-            col: 0,
-            stack: Stack::new(),
-            state: ParseStart,
-        };
-        p.bump();
-        return p;
+    /// Returns true if the Json value is an Array. Returns false otherwise.
+    pub fn is_array<'a>(&'a self) -> bool {
+        self.as_array().is_some()
     }
 
-    /// Provides access to the current position in the logical structure of the
-    /// JSON stream.
-    pub fn stack<'l>(&'l self) -> &'l Stack {
-        return &self.stack;
+    /// If the Json value is an Array, returns a reference to the associated vector.
+    /// Returns None otherwise.
+    pub fn as_array<'a>(&'a self) -> Option<&'a Array> {
+        match self {
+            &Json::Array(ref array) => Some(&*array),
+            _ => None
+        }
     }
 
-    fn eof(&self) -> bool { self.ch.is_none() }
-    fn ch_or_null(&self) -> char { self.ch.unwrap_or('\x00') }
-    fn bump(&mut self) {
-        self.ch = self.rdr.next();
-
-        if self.ch_is('\n') {
-            self.line += 1;
-            self.col = 1;
-        } else {
-            self.col += 1;
+    /// If the Json value is an Array, returns a mutable reference to the associated vector.
+    /// Returns None otherwise.
+    pub fn as_array_mut<'a>(&'a mut self) -> Option<&'a mut Array> {
+        match self {
+            &mut Json::Array(ref mut list) => Some(list),
+            _ => None
         }
     }
 
-    fn next_char(&mut self) -> Option<char> {
-        self.bump();
-        self.ch
-    }
-    fn ch_is(&self, c: char) -> bool {
-        self.ch == Some(c)
+    /// If the Json value is an Array, returns the associated vector.
+    /// Returns None otherwise.
+    pub fn into_array(self) -> Option<Array> {
+        match self {
+            Json::Array(array) => Some(array),
+            _ => None
+        }
     }
 
-    fn error<E>(&self, reason: ErrorCode) -> Result<E, ParserError> {
-        Err(SyntaxError(reason, self.line, self.col))
+    /// Returns true if the Json value is a String. Returns false otherwise.
+    pub fn is_string<'a>(&'a self) -> bool {
+        self.as_string().is_some()
     }
 
-    fn parse_whitespace(&mut self) {
-        while self.ch_is(' ') ||
-              self.ch_is('\n') ||
-              self.ch_is('\t') ||
-              self.ch_is('\r') { self.bump(); }
+    /// If the Json value is a String, returns the associated str.
+    /// Returns None otherwise.
+    pub fn as_string<'a>(&'a self) -> Option<&'a str> {
+        match *self {
+            Json::String(ref s) => Some(&s),
+            _ => None
+        }
     }
 
-    fn parse_number(&mut self) -> JsonEvent {
-        let mut neg = false;
+    /// Returns true if the Json value is a Number. Returns false otherwise.
+    pub fn is_number(&self) -> bool {
+        match *self {
+            Json::I64(_) | Json::U64(_) | Json::F64(_) => true,
+            _ => false,
+        }
+    }
 
-        if self.ch_is('-') {
-            self.bump();
-            neg = true;
+    /// Returns true if the Json value is a i64. Returns false otherwise.
+    pub fn is_i64(&self) -> bool {
+        match *self {
+            Json::I64(_) => true,
+            _ => false,
         }
+    }
 
-        let res = match self.parse_u64() {
-            Ok(res) => res,
-            Err(e) => { return Error(e); }
-        };
+    /// Returns true if the Json value is a u64. Returns false otherwise.
+    pub fn is_u64(&self) -> bool {
+        match *self {
+            Json::U64(_) => true,
+            _ => false,
+        }
+    }
 
-        if self.ch_is('.') || self.ch_is('e') || self.ch_is('E') {
-            let mut res = res as f64;
+    /// Returns true if the Json value is a f64. Returns false otherwise.
+    pub fn is_f64(&self) -> bool {
+        match *self {
+            Json::F64(_) => true,
+            _ => false,
+        }
+    }
+
+    /// If the Json value is a number, return or cast it to a i64.
+    /// Returns None otherwise.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Json::I64(n) => Some(n),
+            Json::U64(n) if n >= i64::MAX as u64 => None,
+            Json::U64(n) => Some(n as i64),
+            _ => None
+        }
+    }
+
+    /// If the Json value is a number, return or cast it to a u64.
+    /// Returns None otherwise.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Json::I64(n) if n >= 0 => Some(n as u64),
+            Json::U64(n) => Some(n),
+            _ => None
+        }
+    }
+
+    /// If the Json value is a number, return or cast it to a f64.
+    /// Returns None otherwise.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::I64(n) => Some(n as f64),
+            Json::U64(n) => Some(n as f64),
+            Json::F64(n) => Some(n),
+            _ => None
+        }
+    }
+
+    /// Returns true if the Json value is a Boolean. Returns false otherwise.
+    pub fn is_boolean(&self) -> bool {
+        self.as_boolean().is_some()
+    }
+
+    /// If the Json value is a Boolean, returns the associated bool.
+    /// Returns None otherwise.
+    pub fn as_boolean(&self) -> Option<bool> {
+        match self {
+            &Json::Boolean(b) => Some(b),
+            _ => None
+        }
+    }
+
+    /// Returns true if the Json value is a Null. Returns false otherwise.
+    pub fn is_null(&self) -> bool {
+        self.as_null().is_some()
+    }
+
+    /// If the Json value is a Null, returns ().
+    /// Returns None otherwise.
+    pub fn as_null(&self) -> Option<()> {
+        match self {
+            &Json::Null => Some(()),
+            _ => None
+        }
+    }
+
+    /// Returns the number of bytes of heap memory owned by this value: the
+    /// capacity of its strings and arrays, and the keys and values of its
+    /// objects, all the way down. Not counted are `size_of::<Json>()` for
+    /// this value itself, and the overhead of the B-tree nodes holding
+    /// object entries, so for objects this is an underestimate.
+    pub fn deep_size_of(&self) -> usize {
+        match *self {
+            Json::String(ref s) => s.capacity(),
+            Json::Array(ref v) => {
+                v.capacity() * mem::size_of::<Json>() +
+                    v.iter().map(Json::deep_size_of).sum::<usize>()
+            }
+            Json::Object(ref o) => {
+                let entry = mem::size_of::<string::String>() + mem::size_of::<Json>();
+                o.iter().map(|(k, v)| entry + k.capacity() + v.deep_size_of()).sum()
+            }
+            _ => 0,
+        }
+    }
+}
+
+impl<'a> Index<&'a str>  for Json {
+    type Output = Json;
+
+    fn index(&self, idx: &str) -> &Json {
+        self.find(idx).unwrap()
+    }
+}
+
+impl Index<usize> for Json {
+    type Output = Json;
+
+    fn index<'a>(&'a self, idx: usize) -> &'a Json {
+        match self {
+            &Json::Array(ref v) => &v[idx],
+            _ => panic!("can only index Json with usize if it is an array")
+        }
+    }
+}
+
+/// The output of the streaming parser.
+#[derive(PartialEq, Debug)]
+pub enum JsonEvent {
+    ObjectStart,
+    ObjectEnd,
+    ArrayStart,
+    ArrayEnd,
+    BooleanValue(bool),
+    I64Value(i64),
+    U64Value(u64),
+    F64Value(f64),
+    StringValue(string::String),
+    NullValue,
+    Error(ParserError),
+}
+
+#[derive(PartialEq, Debug)]
+enum ParserState {
+    // Parse a value in an array, true means first element.
+    ParseArray(bool),
+    // Parse ',' or ']' after an element in an array.
+    ParseArrayComma,
+    // Parse a key:value in an object, true means first element.
+    ParseObject(bool),
+    // Parse ',' or ']' after an element in an object.
+    ParseObjectComma,
+    // Initial state.
+    ParseStart,
+    // Expecting the stream to end.
+    ParseBeforeFinish,
+    // Parsing can't continue.
+    ParseFinished,
+}
+
+/// A Stack represents the current position of the parser in the logical
+/// structure of the JSON stream.
+/// For example foo.bar[3].x
+pub struct Stack {
+    stack: Vec<InternalStackElement>,
+    str_buffer: Vec<u8>,
+}
+
+/// StackElements compose a Stack.
+/// For example, Key("foo"), Key("bar"), Index(3) and Key("x") are the
+/// StackElements compositing the stack that represents foo.bar[3].x
+#[derive(PartialEq, Clone, Debug)]
+pub enum StackElement<'l> {
+    Index(u32),
+    Key(&'l str),
+}
+
+// Internally, Key elements are stored as indices in a buffer to avoid
+// allocating a string for every member of an object.
+#[derive(PartialEq, Clone, Debug)]
+enum InternalStackElement {
+    InternalIndex(u32),
+    InternalKey(u16, u16), // start, size
+}
+
+impl Stack {
+    pub fn new() -> Stack {
+        Stack { stack: Vec::new(), str_buffer: Vec::new() }
+    }
+
+    fn clear(&mut self) {
+        self.stack.clear();
+        self.str_buffer.clear();
+    }
+
+    /// Returns The number of elements in the Stack.
+    pub fn len(&self) -> usize { self.stack.len() }
+
+    /// Returns true if the stack is empty.
+    pub fn is_empty(&self) -> bool { self.stack.is_empty() }
+
+    /// Provides access to the StackElement at a given index.
+    /// lower indices are at the bottom of the stack while higher indices are
+    /// at the top.
+    pub fn get<'l>(&'l self, idx: usize) -> StackElement<'l> {
+        match self.stack[idx] {
+            InternalIndex(i) => StackElement::Index(i),
+            InternalKey(start, size) => {
+                StackElement::Key(str::from_utf8(
+                    &self.str_buffer[start as usize .. start as usize + size as usize]).unwrap())
+            }
+        }
+    }
+
+    /// Compares this stack with an array of StackElements.
+    pub fn is_equal_to(&self, rhs: &[StackElement]) -> bool {
+        if self.stack.len() != rhs.len() { return false; }
+        for i in 0..rhs.len() {
+            if self.get(i) != rhs[i] { return false; }
+        }
+        return true;
+    }
+
+    /// Returns true if the bottom-most elements of this stack are the same as
+    /// the ones passed as parameter.
+    pub fn starts_with(&self, rhs: &[StackElement]) -> bool {
+        if self.stack.len() < rhs.len() { return false; }
+        for i in 0..rhs.len() {
+            if self.get(i) != rhs[i] { return false; }
+        }
+        return true;
+    }
+
+    /// Returns true if the top-most elements of this stack are the same as
+    /// the ones passed as parameter.
+    pub fn ends_with(&self, rhs: &[StackElement]) -> bool {
+        if self.stack.len() < rhs.len() { return false; }
+        let offset = self.stack.len() - rhs.len();
+        for i in 0..rhs.len() {
+            if self.get(i + offset) != rhs[i] { return false; }
+        }
+        return true;
+    }
+
+    /// Returns the top-most element (if any).
+    pub fn top<'l>(&'l self) -> Option<StackElement<'l>> {
+        return match self.stack.last() {
+            None => None,
+            Some(&InternalIndex(i)) => Some(StackElement::Index(i)),
+            Some(&InternalKey(start, size)) => {
+                Some(StackElement::Key(str::from_utf8(
+                    &self.str_buffer[start as usize .. (start+size) as usize]
+                ).unwrap()))
+            }
+        }
+    }
+
+    // Used by Parser to insert Key elements at the top of the stack.
+    fn push_key(&mut self, key: string::String) {
+        self.stack.push(InternalKey(self.str_buffer.len() as u16, key.len() as u16));
+        for c in key.as_bytes().iter() {
+            self.str_buffer.push(*c);
+        }
+    }
+
+    // Used by Parser to insert Index elements at the top of the stack.
+    fn push_index(&mut self, index: u32) {
+        self.stack.push(InternalIndex(index));
+    }
+
+    // Used by Parser to remove the top-most element of the stack.
+    fn pop(&mut self) {
+        assert!(!self.is_empty());
+        match *self.stack.last().unwrap() {
+            InternalKey(_, sz) => {
+                let new_size = self.str_buffer.len() - sz as usize;
+                self.str_buffer.truncate(new_size);
+            }
+            InternalIndex(_) => {}
+        }
+        self.stack.pop();
+    }
+
+    // Used by Parser to test whether the top-most element is an index.
+    fn last_is_index(&self) -> bool {
+        if self.is_empty() { return false; }
+        return match *self.stack.last().unwrap() {
+            InternalIndex(_) => true,
+            _ => false,
+        }
+    }
+
+    // Used by Parser to increment the index of the top-most element.
+    fn bump_index(&mut self) {
+        let len = self.stack.len();
+        let idx = match *self.stack.last().unwrap() {
+            InternalIndex(i) => { i + 1 }
+            _ => { panic!(); }
+        };
+        self.stack[len - 1] = InternalIndex(idx);
+    }
+}
+
+/// How a parser treats escapes of UTF-16 surrogates which are not part of a
+/// pair, such as `"\uD800"`. JavaScript, whose strings are sequences of
+/// UTF-16 code units, can write them, but Rust strings cannot hold them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurrogatePolicy {
+    /// Fail with a `LoneLeadingSurrogateInHexEscape` or
+    /// `LoneTrailingSurrogateInHexEscape` error. This is the default.
+    Reject,
+    /// Replace each lone surrogate with U+FFFD.
+    Replace,
+    /// Keep lone surrogates, so that strings holding them can be written
+    /// back as they were read: `Builder` builds them as `Json::Wtf8`
+    /// values, and `Parser::take_wtf8` gives them after their
+    /// `StringValue` event. Otherwise, as in events and object keys, they
+    /// are replaced as with `Replace`.
+    Wtf8,
+}
+
+// The contents of a string being scanned: the string, if wanted, and the
+// string kept whole once it has a lone surrogate to keep.
+struct ScannedStr<'r> {
+    res: Option<&'r mut string::String>,
+    wtf8: Option<Wtf8Buf>,
+}
+
+impl<'r> ScannedStr<'r> {
+    fn push(&mut self, c: char) {
+        if let Some(ref mut res) = self.res {
+            res.push(c);
+        }
+        if let Some(ref mut wtf8) = self.wtf8 {
+            wtf8.push_char(c);
+        }
+    }
+
+    fn keep(&mut self, surrogate: u32) {
+        if let Some(ref mut res) = self.res {
+            self.wtf8.get_or_insert_with(|| Wtf8Buf::from(&res[..])).push_code_point(surrogate);
+            res.push(char::REPLACEMENT_CHARACTER);
+        }
+    }
+}
+
+/// A streaming JSON parser implemented as an iterator of JsonEvent, consuming
+/// an iterator of char.
+pub struct Parser<T> {
+    rdr: T,
+    ch: Option<char>,
+    line: usize,
+    col: usize,
+    // We maintain a stack representing where we are in the logical structure
+    // of the JSON stream.
+    stack: Stack,
+    // A state machine is kept to make it possible to interrupt and resume parsing.
+    state: ParserState,
+    consumed: u64,
+    progress: Option<Progress>,
+    cancel: Option<Arc<AtomicBool>>,
+    cancel_every: u64,
+    cancel_countdown: u64,
+    cancelled: bool,
+    reject_noncharacters: bool,
+    surrogate_policy: SurrogatePolicy,
+    // The last string scanned, if it held lone surrogates which are kept.
+    wtf8: Option<Wtf8Buf>,
+}
+
+impl<T: Iterator<Item = char>> Iterator for Parser<T> {
+    type Item = JsonEvent;
+
+    fn next(&mut self) -> Option<JsonEvent> {
+        if self.state == ParseFinished {
+            return None;
+        }
+
+        if self.state == ParseBeforeFinish {
+            self.parse_whitespace();
+            // Make sure there is no trailing characters.
+            if self.cancelled {
+                return Some(self.error_event(Cancelled));
+            } else if self.eof() {
+                self.state = ParseFinished;
+                return None;
+            } else {
+                return Some(self.error_event(TrailingCharacters));
+            }
+        }
+
+        let event = self.parse();
+        // The value may have been cut short, so don't report it.
+        if self.cancelled {
+            return Some(self.error_event(Cancelled));
+        }
+        Some(event)
+    }
+}
+
+impl<T: Iterator<Item = char>> Parser<T> {
+    /// Creates the JSON parser.
+    pub fn new(rdr: T) -> Parser<T> {
+        let mut p = Parser {
+            rdr: rdr,
+            ch: Some('\x00'),
+            line: 1,
+            col: 0,
+            stack: Stack::new(),
+            state: ParseStart,
+            consumed: 0,
+            progress: None,
+            cancel: None,
+            cancel_every: 0,
+            cancel_countdown: 0,
+            cancelled: false,
+            reject_noncharacters: false,
+            surrogate_policy: SurrogatePolicy::Reject,
+            wtf8: None,
+        };
+        p.bump();
+        return p;
+    }
+
+    /// Creates the JSON parser with room in its stack for documents nested
+    /// `depth` levels deep.
+    pub fn with_capacity(rdr: T, depth: usize) -> Parser<T> {
+        let mut p = Parser::new(rdr);
+        p.stack.stack.reserve(depth);
+        p
+    }
+
+    /// Restarts the parser on a new input, keeping the memory allocated
+    /// for its stack.
+    pub fn reset(&mut self, rdr: T) {
+        self.rdr = rdr;
+        self.ch = Some('\x00');
+        self.line = 1;
+        self.col = 0;
+        self.stack.clear();
+        self.state = ParseStart;
+        self.consumed = 0;
+        self.wtf8 = None;
+        if let Some(ref mut progress) = self.progress {
+            progress.reset();
+        }
+        self.cancel_countdown = self.cancel_every;
+        self.cancelled = false;
+        self.bump();
+    }
+
+    /// Makes the parser check `flag` once every `check_every` characters
+    /// and stop reading as soon as it is found set. The parser then yields a
+    /// `Cancelled` error and no further events; no value is reported from
+    /// input cut short by the cancellation.
+    ///
+    /// The flag is only checked between characters, so a read blocked in
+    /// the underlying iterator is not interrupted.
+    pub fn set_cancel_flag(&mut self, flag: Arc<AtomicBool>, check_every: u64) {
+        let every = if check_every == 0 { 1 } else { check_every };
+        self.cancel = Some(flag);
+        self.cancel_every = every;
+        self.cancel_countdown = every;
+    }
+
+    /// Reject strings containing a noncharacter, U+FFFE, U+FFFF or any
+    /// other of the 66 code points Unicode reserves for internal use,
+    /// whether written as is or escaped, with a `NoncharacterInString`
+    /// error.
+    ///
+    /// Escapes of UTF-16 surrogates are rejected whatever this setting, as
+    /// strings cannot hold them: a trailing surrogate must follow a leading
+    /// one, as a `LoneTrailingSurrogateInHexEscape` error says, and a
+    /// leading one must be followed by a trailing one, as a
+    /// `LoneLeadingSurrogateInHexEscape` error does.
+    pub fn set_reject_noncharacters(&mut self, reject: bool) {
+        self.reject_noncharacters = reject;
+    }
+
+    /// Set how escapes of lone surrogates are treated; see
+    /// `SurrogatePolicy`.
+    pub fn set_surrogate_policy(&mut self, policy: SurrogatePolicy) {
+        self.surrogate_policy = policy;
+    }
+
+    /// With `SurrogatePolicy::Wtf8`, takes the string of the last
+    /// `StringValue` event if it held lone surrogates, which the event
+    /// holds replaced with U+FFFD.
+    pub fn take_wtf8(&mut self) -> Option<Wtf8Buf> {
+        self.wtf8.take()
+    }
+
+    /// Returns the number of bytes of input consumed so far, counting each
+    /// character as its length in UTF-8.
+    pub fn bytes_consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    /// Calls `callback` with `bytes_consumed()` each time another
+    /// `every_n_bytes` bytes of input have been consumed.
+    pub fn progress<F>(&mut self, callback: F, every_n_bytes: u64)
+        where F: FnMut(u64) + Send + 'static
+    {
+        self.progress = Some(Progress::new(every_n_bytes, callback));
+    }
+
+    /// Provides access to the current position in the logical structure of the
+    /// JSON stream.
+    pub fn stack<'l>(&'l self) -> &'l Stack {
+        return &self.stack;
+    }
+
+    fn eof(&self) -> bool { self.ch.is_none() }
+    fn ch_or_null(&self) -> char { self.ch.unwrap_or('\x00') }
+    fn bump(&mut self) {
+        if let Some(ref flag) = self.cancel {
+            self.cancel_countdown -= 1;
+            if self.cancel_countdown == 0 {
+                self.cancel_countdown = self.cancel_every;
+                if flag.load(Ordering::Relaxed) {
+                    self.cancelled = true;
+                }
+            }
+        }
+        if self.cancelled {
+            self.ch = None;
+            return;
+        }
+
+        self.ch = self.rdr.next();
+
+        if let Some(c) = self.ch {
+            self.consumed += c.len_utf8() as u64;
+            if let Some(ref mut progress) = self.progress {
+                progress.update(self.consumed);
+            }
+        }
+
+        if self.ch_is('\n') {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        self.bump();
+        self.ch
+    }
+    fn ch_is(&self, c: char) -> bool {
+        self.ch == Some(c)
+    }
+
+    fn error<E>(&self, reason: ErrorCode) -> Result<E, ParserError> {
+        let reason = if self.cancelled { Cancelled } else { reason };
+        Err(SyntaxError(reason, self.line, self.col))
+    }
+
+    fn parse_whitespace(&mut self) {
+        while self.ch_is(' ') ||
+              self.ch_is('\n') ||
+              self.ch_is('\t') ||
+              self.ch_is('\r') { self.bump(); }
+    }
+
+    fn parse_number(&mut self) -> JsonEvent {
+        let mut neg = false;
+
+        if self.ch_is('-') {
+            self.bump();
+            neg = true;
+        }
+
+        let res = match self.parse_u64() {
+            Ok(res) => res,
+            Err(e) => { return Error(e); }
+        };
+
+        if self.ch_is('.') || self.ch_is('e') || self.ch_is('E') {
+            let mut res = res as f64;
 
             if self.ch_is('.') {
                 res = match self.parse_decimal(res) {
@@ -1503,549 +3390,2007 @@ impl<T: Iterator<Item = char>> Parser<T> {
                 };
             }
 
-            if self.ch_is('e') || self.ch_is('E') {
-                res = match self.parse_exponent(res) {
-                    Ok(res) => res,
-                    Err(e) => { return Error(e); }
-                };
-            }
+            if self.ch_is('e') || self.ch_is('E') {
+                res = match self.parse_exponent(res) {
+                    Ok(res) => res,
+                    Err(e) => { return Error(e); }
+                };
+            }
+
+            if neg {
+                res *= -1.0;
+            }
+
+            F64Value(res)
+        } else {
+            if neg {
+                // Make sure we don't underflow.
+                if res > (i64::MAX as u64) + 1 {
+                    Error(SyntaxError(InvalidNumber, self.line, self.col))
+                } else if res == 0 {
+                    I64Value(res as i64)
+                } else {
+                    I64Value((!res + 1) as i64)
+                }
+            } else {
+                U64Value(res)
+            }
+        }
+    }
+
+    fn parse_u64(&mut self) -> Result<u64, ParserError> {
+        let mut accum: u64 = 0;
+
+        match self.ch_or_null() {
+            '0' => {
+                self.bump();
+
+                // A leading '0' must be the only digit before the decimal point.
+                match self.ch_or_null() {
+                    '0' ... '9' => return self.error(InvalidNumber),
+                    _ => ()
+                }
+            },
+            '1' ... '9' => {
+                while !self.eof() {
+                    match self.ch_or_null() {
+                        c @ '0' ... '9' => {
+                            macro_rules! try_or_invalid {
+                                ($e: expr) => {
+                                    match $e {
+                                        Some(v) => v,
+                                        None => return self.error(InvalidNumber)
+                                    }
+                                }
+                            }
+                            accum = try_or_invalid!(accum.checked_mul(10));
+                            accum = try_or_invalid!(accum.checked_add((c as u64) - ('0' as u64)));
+
+                            self.bump();
+                        }
+                        _ => break,
+                    }
+                }
+            }
+            _ => return self.error(InvalidNumber),
+        }
+
+        Ok(accum)
+    }
+
+    fn parse_decimal(&mut self, mut res: f64) -> Result<f64, ParserError> {
+        self.bump();
+
+        // Make sure a digit follows the decimal place.
+        match self.ch_or_null() {
+            '0' ... '9' => (),
+             _ => return self.error(InvalidNumber)
+        }
+
+        let mut dec = 1.0;
+        let mut frac = 0.0;
+        while !self.eof() {
+            match self.ch_or_null() {
+                c @ '0' ... '9' => {
+                    dec /= 10.0;
+                    frac += (((c as isize) - ('0' as isize)) as f64) * dec;
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+
+        res += frac;
+
+        Ok(res)
+    }
+
+    fn parse_exponent(&mut self, mut res: f64) -> Result<f64, ParserError> {
+        self.bump();
+
+        let mut exp = 0;
+        let mut neg_exp = false;
+
+        if self.ch_is('+') {
+            self.bump();
+        } else if self.ch_is('-') {
+            self.bump();
+            neg_exp = true;
+        }
+
+        // Make sure a digit follows the exponent place.
+        match self.ch_or_null() {
+            '0' ... '9' => (),
+            _ => return self.error(InvalidNumber)
+        }
+        while !self.eof() {
+            match self.ch_or_null() {
+                c @ '0' ... '9' => {
+                    exp *= 10;
+                    exp += (c as usize) - ('0' as usize);
+
+                    self.bump();
+                }
+                _ => break
+            }
+        }
+
+        let exp = 10_f64.powi(exp as i32);
+        if neg_exp {
+            res /= exp;
+        } else {
+            res *= exp;
+        }
+
+        Ok(res)
+    }
+
+    fn decode_hex_escape(&mut self) -> Result<u16, ParserError> {
+        let mut i = 0;
+        let mut n = 0;
+        while i < 4 {
+            self.bump();
+            n = match self.ch_or_null() {
+                c @ '0' ... '9' => n * 16 + ((c as u16) - ('0' as u16)),
+                c @ 'a' ... 'f' => n * 16 + (10 + (c as u16) - ('a' as u16)),
+                c @ 'A' ... 'F' => n * 16 + (10 + (c as u16) - ('A' as u16)),
+                _ => return self.error(InvalidEscape)
+            };
+
+            i += 1;
+        }
+
+        Ok(n)
+    }
+
+    fn parse_str(&mut self) -> Result<string::String, ParserError> {
+        let mut res = string::String::new();
+        try!(self.scan_str(Some(&mut res)));
+        Ok(res)
+    }
+
+    // Consumes a string, appending its contents to `res` if given. With
+    // `SurrogatePolicy::Wtf8`, a string with lone surrogates is also kept
+    // whole, for `take_wtf8`.
+    fn scan_str(&mut self, res: Option<&mut string::String>) -> Result<(), ParserError> {
+        let mut out = ScannedStr { res: res, wtf8: None };
+        let mut escape = false;
+        // Whether `self.ch` is yet to be looked at, having been read to find
+        // the end of a lone leading surrogate.
+        let mut reread = false;
+
+        loop {
+            if !reread {
+                self.bump();
+            }
+            reread = false;
+            if self.eof() {
+                return self.error(EOFWhileParsingString);
+            }
+
+            if escape {
+                escape = false;
+                let c = match self.ch_or_null() {
+                    '"' => '"',
+                    '\\' => '\\',
+                    '/' => '/',
+                    'b' => '\x08',
+                    'f' => '\x0c',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'u' => {
+                        // Non-BMP characters are encoded as a sequence of
+                        // two hex escapes, representing UTF-16 surrogates.
+                        // What follows a leading surrogate which is not part
+                        // of a pair is then looked at again.
+                        let mut n = try!(self.decode_hex_escape()) as u32;
+                        while (0xD800..=0xDBFF).contains(&n) {
+                            match self.next_char() {
+                                Some('\\') => {}
+                                None => return self.error(UnexpectedEndOfHexEscape),
+                                Some(_) => { reread = true; break; }
+                            }
+                            match self.next_char() {
+                                Some('u') => {}
+                                None => return self.error(UnexpectedEndOfHexEscape),
+                                Some(_) => { reread = true; escape = true; break; }
+                            }
+                            let n2 = try!(self.decode_hex_escape()) as u32;
+                            if (0xDC00..=0xDFFF).contains(&n2) {
+                                n = ((n - 0xD800) << 10 | (n2 - 0xDC00)) + 0x1_0000;
+                            } else {
+                                try!(self.push_lone_surrogate(&mut out, n,
+                                                              LoneLeadingSurrogateInHexEscape));
+                                n = n2;
+                            }
+                        }
+                        match n {
+                            0xD800 ... 0xDBFF => {
+                                try!(self.push_lone_surrogate(&mut out, n,
+                                                              LoneLeadingSurrogateInHexEscape));
+                                continue;
+                            }
+                            0xDC00 ... 0xDFFF => {
+                                try!(self.push_lone_surrogate(&mut out, n,
+                                                              LoneTrailingSurrogateInHexEscape));
+                                continue;
+                            }
+                            n => match char::from_u32(n) {
+                                Some(c) => c,
+                                None => return self.error(InvalidUnicodeCodePoint),
+                            },
+                        }
+                    }
+                    _ => return self.error(InvalidEscape),
+                };
+                try!(self.push_char(&mut out, c));
+            } else if self.ch_is('\\') {
+                escape = true;
+            } else {
+                match self.ch {
+                    Some('"') => {
+                        self.bump();
+                        self.wtf8 = out.wtf8;
+                        return Ok(());
+                    },
+                    Some(c) if c <= '\u{1F}' =>
+                        return self.error(ControlCharacterInString),
+                    Some(c) => try!(self.push_char(&mut out, c)),
+                    None => unreachable!()
+                }
+            }
+        }
+    }
+
+    fn push_char(&self, out: &mut ScannedStr, c: char) -> Result<(), ParserError> {
+        fn is_noncharacter(c: char) -> bool {
+            let c = c as u32;
+            (0xFDD0..=0xFDEF).contains(&c) || c & 0xFFFE == 0xFFFE
+        }
+
+        if self.reject_noncharacters && is_noncharacter(c) {
+            return self.error(NoncharacterInString);
+        }
+        out.push(c);
+        Ok(())
+    }
+
+    fn push_lone_surrogate(&self, out: &mut ScannedStr, n: u32, code: ErrorCode)
+                           -> Result<(), ParserError> {
+        match self.surrogate_policy {
+            SurrogatePolicy::Reject => return self.error(code),
+            SurrogatePolicy::Replace => out.push(char::REPLACEMENT_CHARACTER),
+            SurrogatePolicy::Wtf8 => out.keep(n),
+        }
+        Ok(())
+    }
+
+    // Invoked at each iteration, consumes the stream until it has enough
+    // information to return a JsonEvent.
+    // Manages an internal state so that parsing can be interrupted and resumed.
+    // Also keeps track of the position in the logical structure of the json
+    // stream int the form of a stack that can be queried by the user using the
+    // stack() method.
+    fn parse(&mut self) -> JsonEvent {
+        loop {
+            // The only paths where the loop can spin a new iteration
+            // are in the cases ParseArrayComma and ParseObjectComma if ','
+            // is parsed. In these cases the state is set to (respectively)
+            // ParseArray(false) and ParseObject(false), which always return,
+            // so there is no risk of getting stuck in an infinite loop.
+            // All other paths return before the end of the loop's iteration.
+            self.parse_whitespace();
+
+            match self.state {
+                ParseStart => {
+                    return self.parse_start();
+                }
+                ParseArray(first) => {
+                    return self.parse_array(first);
+                }
+                ParseArrayComma => {
+                    match self.parse_array_comma_or_end() {
+                        Some(evt) => { return evt; }
+                        None => {}
+                    }
+                }
+                ParseObject(first) => {
+                    return self.parse_object(first);
+                }
+                ParseObjectComma => {
+                    self.stack.pop();
+                    if self.ch_is(',') {
+                        self.state = ParseObject(false);
+                        self.bump();
+                    } else {
+                        return self.parse_object_end();
+                    }
+                }
+                _ => {
+                    return self.error_event(InvalidSyntax);
+                }
+            }
+        }
+    }
+
+    /// Consumes the rest of the value the last event started, without
+    /// yielding events for it or building its strings.
+    ///
+    /// After an `ArrayStart` or `ObjectStart`, this skips everything up to
+    /// and including the matching end; before the first event, it skips
+    /// the whole document. After any other event the value is already
+    /// complete, and nothing is consumed. The input skipped is checked as
+    /// thoroughly as the parser checks it otherwise, and the parser
+    /// continues with the events after the value.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// use rustc_serialize::json::{JsonEvent, Parser, StackElement};
+    ///
+    /// fn main() {
+    ///     let mut parser = Parser::new(r#"{"big": [1, {"x": "y"}], "id": 7}"#.chars());
+    ///     assert_eq!(parser.next(), Some(JsonEvent::ObjectStart));
+    ///     assert_eq!(parser.next(), Some(JsonEvent::ArrayStart));
+    ///     assert_eq!(parser.stack().top(), Some(StackElement::Key("big")));
+    ///     parser.skip_value().unwrap();
+    ///     assert_eq!(parser.next(), Some(JsonEvent::U64Value(7)));
+    ///     assert_eq!(parser.stack().top(), Some(StackElement::Key("id")));
+    /// }
+    /// ```
+    pub fn skip_value(&mut self) -> Result<(), ParserError> {
+        // The containers the skipped input is within, `true` for objects.
+        let mut open = Vec::new();
+        match self.state {
+            ParseStart => {}
+            ParseArray(true) => open.push(false),
+            ParseObject(true) => open.push(true),
+            _ => return Ok(()),
+        }
+        match self.skip_values(open) {
+            Ok(()) => {
+                self.state = if self.stack.is_empty() {
+                    ParseBeforeFinish
+                } else if self.stack.last_is_index() {
+                    ParseArrayComma
+                } else {
+                    ParseObjectComma
+                };
+                Ok(())
+            }
+            Err(e) => {
+                self.state = ParseFinished;
+                Err(e)
+            }
+        }
+    }
+
+    // Consumes values until the containers in `open` are all closed.
+    fn skip_values(&mut self, mut open: Vec<bool>) -> Result<(), ParserError> {
+        // Whether the next value would be the first in its container, which
+        // may instead end at once.
+        let mut first = !open.is_empty();
+        loop {
+            self.parse_whitespace();
+            let empty = match open.last() {
+                Some(&in_object) if first => self.ch_is(if in_object { '}' } else { ']' }),
+                _ => false,
+            };
+            if empty {
+                self.bump();
+                open.pop();
+            } else {
+                if open.last() == Some(&true) {
+                    try!(self.skip_key());
+                }
+                if try!(self.skip_value_start(&mut open)) {
+                    first = true;
+                    continue;
+                }
+            }
+            // Then any number of ends, and a comma before the next value.
+            loop {
+                let in_object = match open.last() {
+                    Some(&in_object) => in_object,
+                    None => return Ok(()),
+                };
+                self.parse_whitespace();
+                if self.ch_is(',') {
+                    self.bump();
+                    break;
+                } else if self.ch_is(if in_object { '}' } else { ']' }) {
+                    self.bump();
+                    open.pop();
+                } else if self.eof() {
+                    return self.error(if in_object { EOFWhileParsingObject }
+                                      else { EOFWhileParsingArray });
+                } else {
+                    return self.error(InvalidSyntax);
+                }
+            }
+            first = false;
+        }
+    }
+
+    // Consumes an object key and its colon.
+    fn skip_key(&mut self) -> Result<(), ParserError> {
+        if self.eof() {
+            return self.error(EOFWhileParsingObject);
+        } else if self.ch_is('}') {
+            return self.error(TrailingComma);
+        } else if !self.ch_is('"') {
+            return self.error(KeyMustBeAString);
+        }
+        try!(self.scan_str(None));
+        self.parse_whitespace();
+        if self.eof() {
+            return self.error(EOFWhileParsingObject);
+        } else if !self.ch_is(':') {
+            return self.error(ExpectedColon);
+        }
+        self.bump();
+        self.parse_whitespace();
+        Ok(())
+    }
+
+    // Consumes a scalar value, or the start of an array or object, which is
+    // pushed on `open`; returns whether it was a start.
+    fn skip_value_start(&mut self, open: &mut Vec<bool>) -> Result<bool, ParserError> {
+        match self.ch {
+            None => self.error(EOFWhileParsingValue),
+            Some('[') | Some('{') => {
+                open.push(self.ch_is('{'));
+                self.bump();
+                Ok(true)
+            }
+            Some('"') => self.scan_str(None).map(|()| false),
+            Some(_) => match self.parse_value() {
+                Error(e) => Err(e),
+                _ => Ok(false),
+            },
+        }
+    }
+
+    fn parse_start(&mut self) -> JsonEvent {
+        let val = self.parse_value();
+        self.state = match val {
+            Error(_) => ParseFinished,
+            ArrayStart => ParseArray(true),
+            ObjectStart => ParseObject(true),
+            _ => ParseBeforeFinish,
+        };
+        return val;
+    }
+
+    fn parse_array(&mut self, first: bool) -> JsonEvent {
+        if self.ch_is(']') {
+            if !first {
+                self.error_event(InvalidSyntax)
+            } else {
+                self.state = if self.stack.is_empty() {
+                    ParseBeforeFinish
+                } else if self.stack.last_is_index() {
+                    ParseArrayComma
+                } else {
+                    ParseObjectComma
+                };
+                self.bump();
+                ArrayEnd
+            }
+        } else {
+            if first {
+                self.stack.push_index(0);
+            }
+            let val = self.parse_value();
+            self.state = match val {
+                Error(_) => ParseFinished,
+                ArrayStart => ParseArray(true),
+                ObjectStart => ParseObject(true),
+                _ => ParseArrayComma,
+            };
+            val
+        }
+    }
+
+    fn parse_array_comma_or_end(&mut self) -> Option<JsonEvent> {
+        if self.ch_is(',') {
+            self.stack.bump_index();
+            self.state = ParseArray(false);
+            self.bump();
+            None
+        } else if self.ch_is(']') {
+            self.stack.pop();
+            self.state = if self.stack.is_empty() {
+                ParseBeforeFinish
+            } else if self.stack.last_is_index() {
+                ParseArrayComma
+            } else {
+                ParseObjectComma
+            };
+            self.bump();
+            Some(ArrayEnd)
+        } else if self.eof() {
+            Some(self.error_event(EOFWhileParsingArray))
+        } else {
+            Some(self.error_event(InvalidSyntax))
+        }
+    }
+
+    fn parse_object(&mut self, first: bool) -> JsonEvent {
+        if self.ch_is('}') {
+            if !first {
+                if self.stack.is_empty() {
+                    return self.error_event(TrailingComma);
+                } else {
+                    self.stack.pop();
+                }
+            }
+            self.state = if self.stack.is_empty() {
+                ParseBeforeFinish
+            } else if self.stack.last_is_index() {
+                ParseArrayComma
+            } else {
+                ParseObjectComma
+            };
+            self.bump();
+            return ObjectEnd;
+        }
+        if self.eof() {
+            return self.error_event(EOFWhileParsingObject);
+        }
+        if !self.ch_is('"') {
+            return self.error_event(KeyMustBeAString);
+        }
+        let s = match self.parse_str() {
+            Ok(s) => s,
+            Err(e) => {
+                self.state = ParseFinished;
+                return Error(e);
+            }
+        };
+        self.parse_whitespace();
+        if self.eof() {
+            return self.error_event(EOFWhileParsingObject);
+        } else if self.ch_or_null() != ':' {
+            return self.error_event(ExpectedColon);
+        }
+        self.stack.push_key(s);
+        self.bump();
+        self.parse_whitespace();
+
+        let val = self.parse_value();
+
+        self.state = match val {
+            Error(_) => ParseFinished,
+            ArrayStart => ParseArray(true),
+            ObjectStart => ParseObject(true),
+            _ => ParseObjectComma,
+        };
+        return val;
+    }
 
-            if neg {
-                res *= -1.0;
+    fn parse_object_end(&mut self) -> JsonEvent {
+        if self.ch_is('}') {
+            self.state = if self.stack.is_empty() {
+                ParseBeforeFinish
+            } else if self.stack.last_is_index() {
+                ParseArrayComma
+            } else {
+                ParseObjectComma
+            };
+            self.bump();
+            ObjectEnd
+        } else if self.eof() {
+            self.error_event(EOFWhileParsingObject)
+        } else {
+            self.error_event(InvalidSyntax)
+        }
+    }
+
+    fn parse_value(&mut self) -> JsonEvent {
+        if self.eof() { return self.error_event(EOFWhileParsingValue); }
+        match self.ch_or_null() {
+            'n' => { self.parse_ident("ull", NullValue) }
+            't' => { self.parse_ident("rue", BooleanValue(true)) }
+            'f' => { self.parse_ident("alse", BooleanValue(false)) }
+            '0' ... '9' | '-' => self.parse_number(),
+            '"' => match self.parse_str() {
+                Ok(s) => StringValue(s),
+                Err(e) => Error(e),
+            },
+            '[' => {
+                self.bump();
+                ArrayStart
             }
+            '{' => {
+                self.bump();
+                ObjectStart
+            }
+            _ => { self.error_event(InvalidSyntax) }
+        }
+    }
 
-            F64Value(res)
+    fn parse_ident(&mut self, ident: &str, value: JsonEvent) -> JsonEvent {
+        if ident.chars().all(|c| Some(c) == self.next_char()) {
+            self.bump();
+            value
         } else {
-            if neg {
-                // Make sure we don't underflow.
-                if res > (i64::MAX as u64) + 1 {
-                    Error(SyntaxError(InvalidNumber, self.line, self.col))
-                } else if res == 0 {
-                    I64Value(res as i64)
-                } else {
-                    I64Value((!res + 1) as i64)
-                }
-            } else {
-                U64Value(res)
-            }
+            Error(SyntaxError(InvalidSyntax, self.line, self.col))
         }
     }
 
-    fn parse_u64(&mut self) -> Result<u64, ParserError> {
-        let mut accum: u64 = 0;
+    fn error_event(&mut self, reason: ErrorCode) -> JsonEvent {
+        self.state = ParseFinished;
+        let reason = if self.cancelled { Cancelled } else { reason };
+        Error(SyntaxError(reason, self.line, self.col))
+    }
+}
 
-        match self.ch_or_null() {
-            '0' => {
-                self.bump();
+/// A Builder consumes a json::Parser to create a generic Json structure.
+pub struct Builder<T> {
+    parser: Parser<T>,
+    token: Option<JsonEvent>,
+}
 
-                // A leading '0' must be the only digit before the decimal point.
-                match self.ch_or_null() {
-                    '0' ... '9' => return self.error(InvalidNumber),
-                    _ => ()
-                }
-            },
-            '1' ... '9' => {
-                while !self.eof() {
-                    match self.ch_or_null() {
-                        c @ '0' ... '9' => {
-                            macro_rules! try_or_invalid {
-                                ($e: expr) => {
-                                    match $e {
-                                        Some(v) => v,
-                                        None => return self.error(InvalidNumber)
-                                    }
-                                }
-                            }
-                            accum = try_or_invalid!(accum.checked_mul(10));
-                            accum = try_or_invalid!(accum.checked_add((c as u64) - ('0' as u64)));
+impl<T: Iterator<Item = char>> Builder<T> {
+    /// Create a JSON Builder.
+    pub fn new(src: T) -> Builder<T> {
+        Builder { parser: Parser::new(src), token: None, }
+    }
 
-                            self.bump();
-                        }
-                        _ => break,
-                    }
+    /// Create a JSON Builder with room for documents nested `depth` levels
+    /// deep; see `Parser::with_capacity`.
+    pub fn with_capacity(src: T, depth: usize) -> Builder<T> {
+        Builder { parser: Parser::with_capacity(src, depth), token: None, }
+    }
+
+    /// Restarts the builder on a new input, keeping the memory allocated
+    /// by its parser.
+    pub fn reset(&mut self, src: T) {
+        self.parser.reset(src);
+        self.token = None;
+    }
+
+    /// Reject strings containing noncharacters; see
+    /// `Parser::set_reject_noncharacters`.
+    pub fn set_reject_noncharacters(&mut self, reject: bool) {
+        self.parser.set_reject_noncharacters(reject);
+    }
+
+    /// Set how escapes of lone surrogates are treated; see
+    /// `SurrogatePolicy`.
+    pub fn set_surrogate_policy(&mut self, policy: SurrogatePolicy) {
+        self.parser.set_surrogate_policy(policy);
+    }
+
+    // Decode a Json value from a Parser.
+    pub fn build(&mut self) -> Result<Json, BuilderError> {
+        self.bump();
+        let result = self.build_value();
+        self.bump();
+        match self.token.take() {
+            None => {}
+            Some(Error(e)) => { return Err(e); }
+            _ => { return Err(SyntaxError(InvalidSyntax, self.parser.line, self.parser.col)); }
+        }
+        result
+    }
+
+    fn bump(&mut self) {
+        self.token = self.parser.next();
+    }
+
+    fn build_value(&mut self) -> Result<Json, BuilderError> {
+        return match self.token.take() {
+            Some(NullValue) => Ok(Json::Null),
+            Some(I64Value(n)) => Ok(Json::I64(n)),
+            Some(U64Value(n)) => Ok(Json::U64(n)),
+            Some(F64Value(n)) => Ok(Json::F64(n)),
+            Some(BooleanValue(b)) => Ok(Json::Boolean(b)),
+            Some(StringValue(ref mut s)) => {
+                if let Some(wtf8) = self.parser.take_wtf8() {
+                    return Ok(Json::Wtf8(wtf8));
                 }
+                let mut temp = string::String::new();
+                swap(s, &mut temp);
+                Ok(Json::from(temp))
             }
-            _ => return self.error(InvalidNumber),
+            Some(Error(e)) => Err(e),
+            Some(ArrayStart) => self.build_array(),
+            Some(ObjectStart) => self.build_object(),
+            Some(ObjectEnd) => self.parser.error(InvalidSyntax),
+            Some(ArrayEnd) => self.parser.error(InvalidSyntax),
+            None => self.parser.error(EOFWhileParsingValue),
         }
+    }
 
-        Ok(accum)
+    fn build_array(&mut self) -> Result<Json, BuilderError> {
+        self.bump();
+        let mut values = Vec::new();
+
+        loop {
+            if let Some(ArrayEnd) = self.token {
+                return Ok(Json::Array(values.into_iter().collect()));
+            }
+            match self.build_value() {
+                Ok(v) => values.push(v),
+                Err(e) => { return Err(e) }
+            }
+            self.bump();
+        }
     }
 
-    fn parse_decimal(&mut self, mut res: f64) -> Result<f64, ParserError> {
+    fn build_object(&mut self) -> Result<Json, BuilderError> {
         self.bump();
 
-        // Make sure a digit follows the decimal place.
-        match self.ch_or_null() {
-            '0' ... '9' => (),
-             _ => return self.error(InvalidNumber)
+        let mut values = BTreeMap::new();
+
+        loop {
+            match self.token.take() {
+                Some(ObjectEnd) => { return Ok(Json::Object(values)); }
+                Some(Error(e)) => { return Err(e); }
+                None => { break; }
+                token => { self.token = token; }
+            }
+            let key = match self.parser.stack().top() {
+                Some(StackElement::Key(k)) => { k.to_string() }
+                _ => { panic!("invalid state"); }
+            };
+            match self.build_value() {
+                Ok(value) => { values.insert(key, value); }
+                Err(e) => { return Err(e); }
+            }
+            self.bump();
         }
+        return self.parser.error(EOFWhileParsingObject);
+    }
 
-        let mut dec = 1.0;
-        let mut frac = 0.0;
-        while !self.eof() {
-            match self.ch_or_null() {
-                c @ '0' ... '9' => {
-                    dec /= 10.0;
-                    frac += (((c as isize) - ('0' as isize)) as f64) * dec;
+    // Builds the value at the pointer `tokens` beneath the value whose
+    // first event is in `self.token`, skipping the rest.
+    fn extract_value(&mut self, tokens: &[string::String])
+                     -> Result<Option<Json>, BuilderError> {
+        let (token, rest) = match tokens.split_first() {
+            Some(split) => split,
+            None => return self.build_value().map(Some),
+        };
+        match self.token.take() {
+            Some(ObjectStart) => {
+                loop {
                     self.bump();
+                    match self.token.take() {
+                        Some(ObjectEnd) => return Ok(None),
+                        Some(Error(e)) => return Err(e),
+                        None => return self.parser.error(EOFWhileParsingObject),
+                        token => self.token = token,
+                    }
+                    let found = match self.parser.stack().top() {
+                        Some(StackElement::Key(k)) => k == token,
+                        _ => false,
+                    };
+                    if found {
+                        return self.extract_value(rest);
+                    }
+                    try!(self.skip_value());
                 }
-                _ => break,
             }
+            Some(ArrayStart) => {
+                // Array indices have no leading zeros, and `-` (past the
+                // end) never names a value.
+                let target = match token.parse::<usize>() {
+                    Ok(idx) if !token.starts_with('0') || token == "0" => Some(idx),
+                    _ => None,
+                };
+                let mut idx = 0;
+                loop {
+                    self.bump();
+                    match self.token.take() {
+                        Some(ArrayEnd) => return Ok(None),
+                        Some(Error(e)) => return Err(e),
+                        None => return self.parser.error(EOFWhileParsingArray),
+                        token => self.token = token,
+                    }
+                    if target == Some(idx) {
+                        return self.extract_value(rest);
+                    }
+                    try!(self.skip_value());
+                    idx += 1;
+                }
+            }
+            Some(Error(e)) => Err(e),
+            Some(ObjectEnd) | Some(ArrayEnd) => self.parser.error(InvalidSyntax),
+            None => self.parser.error(EOFWhileParsingValue),
+            Some(_) => Ok(None),
+        }
+    }
+
+    // Consumes the value whose first event is in `self.token`.
+    fn skip_value(&mut self) -> Result<(), BuilderError> {
+        match self.token.take() {
+            Some(ObjectStart) | Some(ArrayStart) => self.parser.skip_value(),
+            Some(Error(e)) => Err(e),
+            None => self.parser.error(EOFWhileParsingValue),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+// Splits a JSON Pointer into its unescaped reference tokens.
+fn pointer_tokens(pointer: &str) -> Option<Vec<string::String>> {
+    if pointer.is_empty() {
+        return Some(Vec::new());
+    }
+    if !pointer.starts_with('/') {
+        return None;
+    }
+    pointer[1..].split('/').map(|token| {
+        let mut unescaped = string::String::with_capacity(token.len());
+        let mut chars = token.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '~' => match chars.next() {
+                    Some('0') => unescaped.push('~'),
+                    Some('1') => unescaped.push('/'),
+                    _ => return None,
+                },
+                c => unescaped.push(c),
+            }
+        }
+        Some(unescaped)
+    }).collect()
+}
+
+/// Decodes the value at the JSON Pointer (RFC 6901) `pointer` in the
+/// document read from `rdr`, returning `None` if there is no value there.
+///
+/// Only the target value is built; everything before it is parsed and
+/// thrown away as it is read. Reading stops at the end of the target, so
+/// the rest of the document is neither read nor checked.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::json;
+///
+/// fn main() {
+///     let doc = r#"{"data": {"items": [{"id": 1}, {"id": 2}], "a/b": "c"}}"#;
+///     let id: Option<u32> = json::extract(doc.as_bytes(), "/data/items/1/id").unwrap();
+///     assert_eq!(id, Some(2));
+///     let c: Option<String> = json::extract(doc.as_bytes(), "/data/a~1b").unwrap();
+///     assert_eq!(c, Some("c".to_string()));
+///     let none: Option<u32> = json::extract(doc.as_bytes(), "/data/items/2/id").unwrap();
+///     assert_eq!(none, None);
+/// }
+/// ```
+pub fn extract<T: ::Decodable, R: Read>(rdr: R, pointer: &str) -> DecodeResult<Option<T>> {
+    match try!(extract_json(rdr, pointer)) {
+        Some(json) => {
+            let mut decoder = Decoder::new(json);
+            ::Decodable::decode(&mut decoder).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Builds the value at `pointer` in the document read from `rdr`, as
+/// `extract` does, without decoding it.
+pub fn extract_json<R: Read>(rdr: R, pointer: &str) -> DecodeResult<Option<Json>> {
+    let tokens = match pointer_tokens(pointer) {
+        Some(tokens) => tokens,
+        None => return Err(ApplicationError(format!("invalid JSON pointer: {}", pointer))),
+    };
+    let mut chars = CharIndicesBuffer::new(rdr);
+    let mut error = None;
+    let (result, line, col) = {
+        let mut builder = Builder::new(ReaderChars { chars: &mut chars, error: &mut error });
+        builder.bump();
+        let result = builder.extract_value(&tokens);
+        (result, builder.parser.line, builder.parser.col)
+    };
+    match error {
+        Some(ref e) if InvalidUtf8::from_io_error(e).is_some() => {
+            return Err(ParseError(SyntaxError(NotUtf8, line, col)));
+        }
+        Some(e) => return Err(ParseError(From::from(e))),
+        None => {}
+    }
+    result.map_err(ParseError)
+}
+
+/// Reads JSON documents one at a time from a reader, without consuming any
+/// input past the end of each.
+///
+/// This is for protocols interleaving JSON with other data on the same
+/// stream: after a document, `into_reader_state` hands back the reader along
+/// with the bytes already read from it but not yet used. Unlike
+/// `Json::from_reader`, the input after a document is not checked, and it
+/// need not be valid UTF-8.
+///
+/// Numbers and literals at the top level are only known to have ended when
+/// the next character is seen, so they should be followed by whitespace or
+/// the end of the input.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::json::{DocumentReader, Json};
+///
+/// fn main() {
+///     let mut docs = DocumentReader::new(&b"{\"len\": 3} [1] \x00\x01\x02"[..]);
+///     assert_eq!(docs.read_document().unwrap(), Json::from_str("{\"len\": 3}").unwrap());
+///     assert_eq!(docs.read_document().unwrap(), Json::from_str("[1]").unwrap());
+///     let (_, rest) = docs.into_reader_state();
+///     assert_eq!(rest, b" \x00\x01\x02");
+/// }
+/// ```
+pub struct DocumentReader<R> {
+    chars: CharIndicesBuffer<R>,
+}
+
+impl<R: Read> DocumentReader<R> {
+    /// Creates a new reader of the documents in `rdr`.
+    pub fn new(rdr: R) -> DocumentReader<R> {
+        DocumentReader { chars: CharIndicesBuffer::new(rdr) }
+    }
+
+    /// Reads the next document, skipping any whitespace before it.
+    ///
+    /// Line and column numbers in errors count from the start of the
+    /// document's leading whitespace.
+    pub fn read_document(&mut self) -> Result<Json, BuilderError> {
+        let mut error = None;
+        let (result, next, line, col) = {
+            let mut builder = Builder::new(ReaderChars { chars: &mut self.chars, error: &mut error });
+            builder.bump();
+            let result = builder.build_value();
+            (result, builder.parser.ch, builder.parser.line, builder.parser.col)
+        };
+        if let Some(c) = next {
+            // The parser always reads one character past the document, and
+            // only that one was taken from the pushback buffer, so there is
+            // room for it.
+            self.chars.unread(c).unwrap();
+        }
+        match error {
+            // Invalid UTF-8 after a complete document is left unread.
+            Some(ref e) if InvalidUtf8::from_io_error(e).is_some() => {
+                return result.map_err(|_| SyntaxError(NotUtf8, line, col));
+            }
+            Some(e) => return Err(From::from(e)),
+            None => {}
+        }
+        result
+    }
+
+    /// Unwraps this reader, returning the underlying reader along with the
+    /// bytes read from it but not yet consumed. The reader continues where
+    /// these bytes end.
+    pub fn into_reader_state(self) -> (R, Vec<u8>) {
+        self.chars.into_reader_state()
+    }
+}
+
+// Feeds the characters of a `DocumentReader` to a parser, stopping at the
+// first error.
+struct ReaderChars<'a, R: 'a> {
+    chars: &'a mut CharIndicesBuffer<R>,
+    error: &'a mut Option<io::Error>,
+}
+
+impl<'a, R: Read> Iterator for ReaderChars<'a, R> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.error.is_some() {
+            return None;
+        }
+        match self.chars.next() {
+            Some(Ok((_, c))) => Some(c),
+            Some(Err(e)) => {
+                *self.error = Some(e);
+                None
+            }
+            None => None,
         }
+    }
+}
 
-        res += frac;
+/// Writes a JSON document piece by piece to an `io::Write`, without
+/// building a `Json` tree or implementing `Encodable`.
+///
+/// Arrays and objects are opened and closed explicitly, object members are
+/// written as a `key` followed by a value, and scalars (or any `Encodable`
+/// value, written as `encode` would) are written with `value`. The writer
+/// checks that the calls nest properly, and reports a misplaced call as an
+/// error without writing anything for it. Output is compact and goes to the
+/// underlying writer as it is produced, so wrapping it in an
+/// `io::BufWriter` is usually worthwhile.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::json::JsonWriter;
+///
+/// fn main() {
+///     let mut w = JsonWriter::new(Vec::new());
+///     w.begin_object().unwrap();
+///     w.key("ids").unwrap();
+///     w.begin_array().unwrap();
+///     for id in 1..4 {
+///         w.value(&id).unwrap();
+///     }
+///     w.end_array().unwrap();
+///     w.key("name").unwrap();
+///     w.value("caf\u{e9} \"x\"").unwrap();
+///     w.end_object().unwrap();
+///     let out = w.finish().unwrap();
+///     assert_eq!(out, "{\"ids\":[1,2,3],\"name\":\"caf\u{e9} \\\"x\\\"\"}".as_bytes());
+/// }
+/// ```
+pub struct JsonWriter<W> {
+    writer: W,
+    // The arrays and objects open, innermost last.
+    open: Vec<WriterFrame>,
+    // Whether the top-level value has been written in full.
+    complete: bool,
+    buf: string::String,
+}
 
-        Ok(res)
-    }
+#[derive(Clone, Copy, PartialEq)]
+enum WriterFrame {
+    // The number of elements written so far.
+    Array(usize),
+    // The number of members started so far, and whether the last one's
+    // key awaits its value.
+    Object(usize, bool),
+}
 
-    fn parse_exponent(&mut self, mut res: f64) -> Result<f64, ParserError> {
-        self.bump();
+/// An error from `JsonWriter`.
+#[derive(Debug)]
+pub enum WriterError {
+    /// The underlying writer failed
+    IoError(io::Error),
+    /// A value passed to `JsonWriter::value` could not be encoded
+    EncoderError(EncoderError),
+    /// A key was written outside an object, or in place of a value
+    UnexpectedKey,
+    /// A value was written in an object without a key before it
+    MissingKey,
+    /// An array or object was ended while not the innermost one open, or
+    /// an object was ended after a key without a value
+    MismatchedEnd,
+    /// Something was written after the top-level value was complete
+    DocumentComplete,
+    /// The writer was finished before the top-level value was complete
+    DocumentIncomplete,
+}
 
-        let mut exp = 0;
-        let mut neg_exp = false;
+impl fmt::Display for WriterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WriterError::IoError(ref e) => fmt::Display::fmt(e, f),
+            WriterError::EncoderError(ref e) => fmt::Display::fmt(e, f),
+            _ => f.write_str(self.description()),
+        }
+    }
+}
 
-        if self.ch_is('+') {
-            self.bump();
-        } else if self.ch_is('-') {
-            self.bump();
-            neg_exp = true;
+impl StdError for WriterError {
+    fn description(&self) -> &str {
+        match *self {
+            WriterError::IoError(ref e) => e.description(),
+            WriterError::EncoderError(ref e) => e.description(),
+            WriterError::UnexpectedKey => "key outside an object",
+            WriterError::MissingKey => "object member without a key",
+            WriterError::MismatchedEnd => "mismatched end of array or object",
+            WriterError::DocumentComplete => "write after the end of the document",
+            WriterError::DocumentIncomplete => "document not complete",
         }
+    }
+}
 
-        // Make sure a digit follows the exponent place.
-        match self.ch_or_null() {
-            '0' ... '9' => (),
-            _ => return self.error(InvalidNumber)
+impl From<io::Error> for WriterError {
+    fn from(err: io::Error) -> WriterError { WriterError::IoError(err) }
+}
+
+impl<W: Write> JsonWriter<W> {
+    /// Creates a writer of one JSON document to `writer`.
+    pub fn new(writer: W) -> JsonWriter<W> {
+        JsonWriter {
+            writer: writer,
+            open: Vec::new(),
+            complete: false,
+            buf: string::String::new(),
         }
-        while !self.eof() {
-            match self.ch_or_null() {
-                c @ '0' ... '9' => {
-                    exp *= 10;
-                    exp += (c as usize) - ('0' as usize);
+    }
 
-                    self.bump();
+    /// Returns the current nesting depth: the number of arrays and objects
+    /// begun but not yet ended.
+    pub fn depth(&self) -> usize {
+        self.open.len()
+    }
+
+    // Checks that a value may be written here, and writes the separator
+    // before it.
+    fn before_value(&mut self) -> Result<(), WriterError> {
+        match self.open.last_mut() {
+            None if self.complete => Err(WriterError::DocumentComplete),
+            None => Ok(()),
+            Some(&mut WriterFrame::Object(_, false)) => Err(WriterError::MissingKey),
+            Some(&mut WriterFrame::Object(_, ref mut pending)) => {
+                *pending = false;
+                Ok(())
+            }
+            Some(&mut WriterFrame::Array(ref mut len)) => {
+                *len += 1;
+                if *len > 1 {
+                    try!(self.writer.write_all(b","));
                 }
-                _ => break
+                Ok(())
             }
         }
+    }
 
-        let exp = 10_f64.powi(exp as i32);
-        if neg_exp {
-            res /= exp;
-        } else {
-            res *= exp;
+    fn after_value(&mut self) {
+        if self.open.is_empty() {
+            self.complete = true;
         }
-
-        Ok(res)
     }
 
-    fn decode_hex_escape(&mut self) -> Result<u16, ParserError> {
-        let mut i = 0;
-        let mut n = 0;
-        while i < 4 {
-            self.bump();
-            n = match self.ch_or_null() {
-                c @ '0' ... '9' => n * 16 + ((c as u16) - ('0' as u16)),
-                c @ 'a' ... 'f' => n * 16 + (10 + (c as u16) - ('a' as u16)),
-                c @ 'A' ... 'F' => n * 16 + (10 + (c as u16) - ('A' as u16)),
-                _ => return self.error(InvalidEscape)
-            };
+    /// Begins an array.
+    pub fn begin_array(&mut self) -> Result<(), WriterError> {
+        try!(self.before_value());
+        try!(self.writer.write_all(b"["));
+        self.open.push(WriterFrame::Array(0));
+        Ok(())
+    }
 
-            i += 1;
+    /// Ends the innermost array.
+    pub fn end_array(&mut self) -> Result<(), WriterError> {
+        match self.open.last() {
+            Some(&WriterFrame::Array(_)) => {}
+            _ => return Err(WriterError::MismatchedEnd),
         }
-
-        Ok(n)
+        try!(self.writer.write_all(b"]"));
+        self.open.pop();
+        self.after_value();
+        Ok(())
     }
 
-    fn parse_str(&mut self) -> Result<string::String, ParserError> {
-        let mut escape = false;
-        let mut res = string::String::new();
-
-        loop {
-            self.bump();
-            if self.eof() {
-                return self.error(EOFWhileParsingString);
-            }
-
-            if escape {
-                match self.ch_or_null() {
-                    '"' => res.push('"'),
-                    '\\' => res.push('\\'),
-                    '/' => res.push('/'),
-                    'b' => res.push('\x08'),
-                    'f' => res.push('\x0c'),
-                    'n' => res.push('\n'),
-                    'r' => res.push('\r'),
-                    't' => res.push('\t'),
-                    'u' => match try!(self.decode_hex_escape()) {
-                        0xDC00 ... 0xDFFF => {
-                            return self.error(LoneLeadingSurrogateInHexEscape)
-                        }
-
-                        // Non-BMP characters are encoded as a sequence of
-                        // two hex escapes, representing UTF-16 surrogates.
-                        n1 @ 0xD800 ... 0xDBFF => {
-                            match (self.next_char(), self.next_char()) {
-                                (Some('\\'), Some('u')) => (),
-                                _ => return self.error(UnexpectedEndOfHexEscape),
-                            }
+    /// Begins an object.
+    pub fn begin_object(&mut self) -> Result<(), WriterError> {
+        try!(self.before_value());
+        try!(self.writer.write_all(b"{"));
+        self.open.push(WriterFrame::Object(0, false));
+        Ok(())
+    }
 
-                            let n2 = try!(self.decode_hex_escape());
-                            if n2 < 0xDC00 || n2 > 0xDFFF {
-                                return self.error(LoneLeadingSurrogateInHexEscape)
-                            }
-                            let c = (((n1 - 0xD800) as u32) << 10 |
-                                     (n2 - 0xDC00) as u32) + 0x1_0000;
-                            res.push(char::from_u32(c).unwrap());
-                        }
+    /// Ends the innermost object.
+    pub fn end_object(&mut self) -> Result<(), WriterError> {
+        match self.open.last() {
+            Some(&WriterFrame::Object(_, false)) => {}
+            _ => return Err(WriterError::MismatchedEnd),
+        }
+        try!(self.writer.write_all(b"}"));
+        self.open.pop();
+        self.after_value();
+        Ok(())
+    }
 
-                        n => match char::from_u32(n as u32) {
-                            Some(c) => res.push(c),
-                            None => return self.error(InvalidUnicodeCodePoint),
-                        },
-                    },
-                    _ => return self.error(InvalidEscape),
-                }
-                escape = false;
-            } else if self.ch_is('\\') {
-                escape = true;
-            } else {
-                match self.ch {
-                    Some('"') => {
-                        self.bump();
-                        return Ok(res);
-                    },
-                    Some(c) if c <= '\u{1F}' =>
-                        return self.error(ControlCharacterInString),
-                    Some(c) => res.push(c),
-                    None => unreachable!()
-                }
+    /// Writes the key of the next member of the innermost object.
+    pub fn key(&mut self, key: &str) -> Result<(), WriterError> {
+        match self.open.last() {
+            Some(&WriterFrame::Object(_, false)) => {}
+            _ => return Err(WriterError::UnexpectedKey),
+        }
+        self.buf.clear();
+        if let Some(&WriterFrame::Object(len, _)) = self.open.last() {
+            if len > 0 {
+                self.buf.push(',');
             }
         }
+        try!(escape_str(&mut self.buf, key).map_err(WriterError::EncoderError));
+        self.buf.push(':');
+        try!(self.writer.write_all(self.buf.as_bytes()));
+        if let Some(&mut WriterFrame::Object(ref mut len, ref mut pending)) =
+               self.open.last_mut() {
+            *len += 1;
+            *pending = true;
+        }
+        Ok(())
     }
 
-    // Invoked at each iteration, consumes the stream until it has enough
-    // information to return a JsonEvent.
-    // Manages an internal state so that parsing can be interrupted and resumed.
-    // Also keeps track of the position in the logical structure of the json
-    // stream int the form of a stack that can be queried by the user using the
-    // stack() method.
-    fn parse(&mut self) -> JsonEvent {
-        loop {
-            // The only paths where the loop can spin a new iteration
-            // are in the cases ParseArrayComma and ParseObjectComma if ','
-            // is parsed. In these cases the state is set to (respectively)
-            // ParseArray(false) and ParseObject(false), which always return,
-            // so there is no risk of getting stuck in an infinite loop.
-            // All other paths return before the end of the loop's iteration.
-            self.parse_whitespace();
-
-            match self.state {
-                ParseStart => {
-                    return self.parse_start();
-                }
-                ParseArray(first) => {
-                    return self.parse_array(first);
-                }
-                ParseArrayComma => {
-                    match self.parse_array_comma_or_end() {
-                        Some(evt) => { return evt; }
-                        None => {}
-                    }
-                }
-                ParseObject(first) => {
-                    return self.parse_object(first);
-                }
-                ParseObjectComma => {
-                    self.stack.pop();
-                    if self.ch_is(',') {
-                        self.state = ParseObject(false);
-                        self.bump();
-                    } else {
-                        return self.parse_object_end();
-                    }
-                }
-                _ => {
-                    return self.error_event(InvalidSyntax);
-                }
-            }
+    /// Writes a value, as `encode` would; `Json` trees, strings, numbers
+    /// and anything else `Encodable` can be written.
+    pub fn value<T: Encodable + ?Sized>(&mut self, value: &T) -> Result<(), WriterError> {
+        // Encode first, so that nothing is written for a value which fails.
+        self.buf.clear();
+        {
+            let mut encoder = Encoder::new(&mut self.buf);
+            try!(value.encode(&mut encoder).map_err(WriterError::EncoderError));
         }
+        try!(self.before_value());
+        try!(self.writer.write_all(self.buf.as_bytes()));
+        self.after_value();
+        Ok(())
     }
 
-    fn parse_start(&mut self) -> JsonEvent {
-        let val = self.parse_value();
-        self.state = match val {
-            Error(_) => ParseFinished,
-            ArrayStart => ParseArray(true),
-            ObjectStart => ParseObject(true),
-            _ => ParseBeforeFinish,
-        };
-        return val;
+    /// Writes a `null`.
+    pub fn null(&mut self) -> Result<(), WriterError> {
+        self.value(&())
     }
 
-    fn parse_array(&mut self, first: bool) -> JsonEvent {
-        if self.ch_is(']') {
-            if !first {
-                self.error_event(InvalidSyntax)
-            } else {
-                self.state = if self.stack.is_empty() {
-                    ParseBeforeFinish
-                } else if self.stack.last_is_index() {
-                    ParseArrayComma
-                } else {
-                    ParseObjectComma
-                };
-                self.bump();
-                ArrayEnd
-            }
-        } else {
-            if first {
-                self.stack.push_index(0);
-            }
-            let val = self.parse_value();
-            self.state = match val {
-                Error(_) => ParseFinished,
-                ArrayStart => ParseArray(true),
-                ObjectStart => ParseObject(true),
-                _ => ParseArrayComma,
-            };
-            val
+    /// Checks that the document is complete, and returns the underlying
+    /// writer after flushing it.
+    pub fn finish(mut self) -> Result<W, WriterError> {
+        if !self.complete {
+            return Err(WriterError::DocumentIncomplete);
         }
+        try!(self.writer.flush());
+        Ok(self.writer)
     }
 
-    fn parse_array_comma_or_end(&mut self) -> Option<JsonEvent> {
-        if self.ch_is(',') {
-            self.stack.bump_index();
-            self.state = ParseArray(false);
-            self.bump();
-            None
-        } else if self.ch_is(']') {
-            self.stack.pop();
-            self.state = if self.stack.is_empty() {
-                ParseBeforeFinish
-            } else if self.stack.last_is_index() {
-                ParseArrayComma
-            } else {
-                ParseObjectComma
-            };
-            self.bump();
-            Some(ArrayEnd)
-        } else if self.eof() {
-            Some(self.error_event(EOFWhileParsingArray))
-        } else {
-            Some(self.error_event(InvalidSyntax))
-        }
+    /// Returns the underlying writer, without checking the document.
+    pub fn into_inner(self) -> W {
+        self.writer
     }
+}
 
-    fn parse_object(&mut self, first: bool) -> JsonEvent {
-        if self.ch_is('}') {
-            if !first {
-                if self.stack.is_empty() {
-                    return self.error_event(TrailingComma);
-                } else {
-                    self.stack.pop();
-                }
-            }
-            self.state = if self.stack.is_empty() {
-                ParseBeforeFinish
-            } else if self.stack.last_is_index() {
-                ParseArrayComma
-            } else {
-                ParseObjectComma
-            };
-            self.bump();
-            return ObjectEnd;
+/// Writes the items of `iter` to `writer` as a JSON array, encoding and
+/// writing one at a time, so that the sequence is never held in memory.
+///
+/// If an item fails to encode, the array written so far is left
+/// unterminated.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::json;
+///
+/// fn main() {
+///     let mut out = Vec::new();
+///     json::encode_seq((1..4).map(|i| (i, i * i)), &mut out).unwrap();
+///     assert_eq!(out, b"[[1,1],[2,4],[3,9]]");
+/// }
+/// ```
+pub fn encode_seq<I, W>(iter: I, writer: W) -> Result<(), WriterError>
+    where I: IntoIterator, I::Item: Encodable, W: Write
+{
+    let mut w = JsonWriter::new(writer);
+    try!(w.begin_array());
+    for item in iter {
+        try!(w.value(&item));
+    }
+    try!(w.end_array());
+    try!(w.finish());
+    Ok(())
+}
+
+/// Writes the items of `iter` to `writer` as newline-delimited JSON: each
+/// is encoded on a line of its own, ended by `\n`. Like `encode_seq`, the
+/// items are encoded and written one at a time.
+///
+/// The lines before an item which fails to encode are left written.
+pub fn encode_lines<I, W>(iter: I, mut writer: W) -> Result<(), WriterError>
+    where I: IntoIterator, I::Item: Encodable, W: Write
+{
+    let mut buf = string::String::new();
+    for item in iter {
+        buf.clear();
+        {
+            let mut encoder = Encoder::new(&mut buf);
+            try!(item.encode(&mut encoder).map_err(WriterError::EncoderError));
+        }
+        buf.push('\n');
+        try!(writer.write_all(buf.as_bytes()));
+    }
+    try!(writer.flush());
+    Ok(())
+}
+
+/// A writer of newline-delimited JSON records which threads can share:
+/// each record is encoded by the thread writing it, then appended whole,
+/// so that the lines of records written at once never interleave, as a
+/// structured logger needs.
+///
+/// By default each record is written to the underlying writer as it is
+/// appended. Records can instead be gathered in a buffer, written when it
+/// fills (`set_buffer_size`), after an interval since the last write
+/// (`set_flush_interval`), or by a thread in the background
+/// (`start_background_flush`), which also writes records left waiting when
+/// none follow them. Writes to the underlying writer hold whole lines.
+/// Dropping the writer writes what is left in the buffer.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use std::sync::Arc;
+/// use std::thread;
+/// use rustc_serialize::json::SharedJsonLinesWriter;
+///
+/// fn main() {
+///     let log = Arc::new(SharedJsonLinesWriter::new(Vec::new()));
+///     let threads: Vec<_> = (0..4).map(|i| {
+///         let log = log.clone();
+///         thread::spawn(move || log.write(&("thread", i)).unwrap())
+///     }).collect();
+///     for thread in threads {
+///         thread.join().unwrap();
+///     }
+///     let out = Arc::try_unwrap(log).ok().unwrap().into_inner().unwrap();
+///     assert_eq!(out.split(|&b| b == b'\n').filter(|line| !line.is_empty()).count(), 4);
+/// }
+/// ```
+pub struct SharedJsonLinesWriter<W: Write> {
+    shared: Arc<SharedLines<W>>,
+    buffer_size: usize,
+    flush_interval: Option<Duration>,
+    flusher: Option<JoinHandle<()>>,
+}
+
+struct SharedLines<W> {
+    state: Mutex<LinesState<W>>,
+    // Signalled when the writer is closed, to stop the background flush.
+    closed: Condvar,
+}
+
+struct LinesState<W> {
+    writer: W,
+    buf: Vec<u8>,
+    last_write: Instant,
+    // An error from a background flush, for the next call to report.
+    error: Option<io::Error>,
+    closed: bool,
+}
+
+impl<W> LinesState<W> where W: Write {
+    fn write_buf(&mut self) -> io::Result<()> {
+        self.last_write = Instant::now();
+        if !self.buf.is_empty() {
+            let result = self.writer.write_all(&self.buf);
+            self.buf.clear();
+            try!(result);
         }
-        if self.eof() {
-            return self.error_event(EOFWhileParsingObject);
+        Ok(())
+    }
+
+    fn take_error(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
         }
-        if !self.ch_is('"') {
-            return self.error_event(KeyMustBeAString);
+    }
+}
+
+impl<W> SharedLines<W> {
+    // A panic while the lock was held cannot have left a record half
+    // appended, so the state is used regardless.
+    fn lock(&self) -> MutexGuard<'_, LinesState<W>> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
         }
-        let s = match self.parse_str() {
-            Ok(s) => s,
-            Err(e) => {
-                self.state = ParseFinished;
-                return Error(e);
-            }
+    }
+}
+
+impl<W: Write> SharedJsonLinesWriter<W> {
+    /// Creates a writer of records to `writer`.
+    pub fn new(writer: W) -> SharedJsonLinesWriter<W> {
+        let state = LinesState {
+            writer: writer,
+            buf: Vec::new(),
+            last_write: Instant::now(),
+            error: None,
+            closed: false,
         };
-        self.parse_whitespace();
-        if self.eof() {
-            return self.error_event(EOFWhileParsingObject);
-        } else if self.ch_or_null() != ':' {
-            return self.error_event(ExpectedColon);
+        SharedJsonLinesWriter {
+            shared: Arc::new(SharedLines { state: Mutex::new(state), closed: Condvar::new() }),
+            buffer_size: 0,
+            flush_interval: None,
+            flusher: None,
         }
-        self.stack.push_key(s);
-        self.bump();
-        self.parse_whitespace();
+    }
 
-        let val = self.parse_value();
+    /// Set how many bytes of records to gather before writing them; with 0,
+    /// the default, each is written as it is appended. This should be set
+    /// before writing.
+    pub fn set_buffer_size(&mut self, bytes: usize) {
+        self.buffer_size = bytes;
+    }
 
-        self.state = match val {
-            Error(_) => ParseFinished,
-            ArrayStart => ParseArray(true),
-            ObjectStart => ParseObject(true),
-            _ => ParseObjectComma,
-        };
-        return val;
+    /// Set an interval after which a record appended writes the buffer, and
+    /// flushes the underlying writer, however full it is. This should be
+    /// set before writing.
+    pub fn set_flush_interval(&mut self, interval: Duration) {
+        self.flush_interval = Some(interval);
     }
 
-    fn parse_object_end(&mut self) -> JsonEvent {
-        if self.ch_is('}') {
-            self.state = if self.stack.is_empty() {
-                ParseBeforeFinish
-            } else if self.stack.last_is_index() {
-                ParseArrayComma
-            } else {
-                ParseObjectComma
-            };
-            self.bump();
-            ObjectEnd
-        } else if self.eof() {
-            self.error_event(EOFWhileParsingObject)
-        } else {
-            self.error_event(InvalidSyntax)
+    /// Encodes `record` as a line, and appends it.
+    pub fn write<T: Encodable + ?Sized>(&self, record: &T) -> Result<(), WriterError> {
+        let mut line = string::String::new();
+        {
+            let mut encoder = Encoder::new(&mut line);
+            try!(record.encode(&mut encoder).map_err(WriterError::EncoderError));
+        }
+        line.push('\n');
+
+        let mut state = self.shared.lock();
+        try!(state.take_error());
+        state.buf.extend_from_slice(line.as_bytes());
+        let due = self.flush_interval.map_or(false, |i| state.last_write.elapsed() >= i);
+        if due {
+            try!(state.write_buf());
+            try!(state.writer.flush());
+        } else if state.buf.len() >= self.buffer_size {
+            try!(state.write_buf());
         }
+        Ok(())
     }
 
-    fn parse_value(&mut self) -> JsonEvent {
-        if self.eof() { return self.error_event(EOFWhileParsingValue); }
-        match self.ch_or_null() {
-            'n' => { self.parse_ident("ull", NullValue) }
-            't' => { self.parse_ident("rue", BooleanValue(true)) }
-            'f' => { self.parse_ident("alse", BooleanValue(false)) }
-            '0' ... '9' | '-' => self.parse_number(),
-            '"' => match self.parse_str() {
-                Ok(s) => StringValue(s),
-                Err(e) => Error(e),
+    /// Writes the records in the buffer, and flushes the underlying writer.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut state = self.shared.lock();
+        try!(state.take_error());
+        try!(state.write_buf());
+        state.writer.flush()
+    }
+
+    /// Writes the records in the buffer, and returns the underlying
+    /// writer.
+    pub fn into_inner(mut self) -> Result<W, WriterError> {
+        self.stop();
+        try!(self.flush());
+        let shared = self.shared.clone();
+        // With the background flush stopped, `shared` is the last handle
+        // once `self` is dropped; dropping it has nothing left to write.
+        drop(self);
+        let state = match Arc::try_unwrap(shared) {
+            Ok(shared) => match shared.state.into_inner() {
+                Ok(state) => state,
+                Err(poisoned) => poisoned.into_inner(),
             },
-            '[' => {
-                self.bump();
-                ArrayStart
-            }
-            '{' => {
-                self.bump();
-                ObjectStart
+            Err(_) => unreachable!(),
+        };
+        Ok(state.writer)
+    }
+
+    // Stops the background flush, if any.
+    fn stop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.closed.notify_all();
+        if let Some(flusher) = self.flusher.take() {
+            let _ = flusher.join();
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> SharedJsonLinesWriter<W> {
+    /// Starts a thread which, every `interval`, writes the records in the
+    /// buffer and flushes the underlying writer, so that records are not
+    /// held for long when none follow them. An error it meets is returned
+    /// by the next call to `write` or `flush`. The thread is stopped when
+    /// the writer is dropped, or by `into_inner`.
+    pub fn start_background_flush(&mut self, interval: Duration) {
+        self.stop();
+        self.shared.lock().closed = false;
+        let shared = self.shared.clone();
+        self.flusher = Some(thread::spawn(move || {
+            let mut state = shared.lock();
+            while !state.closed {
+                state = match shared.closed.wait_timeout(state, interval) {
+                    Ok((state, _)) => state,
+                    Err(poisoned) => poisoned.into_inner().0,
+                };
+                if state.closed || state.error.is_some() {
+                    continue;
+                }
+                let result = state.write_buf().and_then(|()| state.writer.flush());
+                if let Err(err) = result {
+                    state.error = Some(err);
+                }
             }
-            _ => { self.error_event(InvalidSyntax) }
+        }));
+    }
+}
+
+impl<W: Write> Drop for SharedJsonLinesWriter<W> {
+    fn drop(&mut self) {
+        self.stop();
+        let _ = self.flush();
+    }
+}
+
+/// An error from `transcode`.
+#[derive(PartialEq, Debug)]
+pub enum TranscodeError<E> {
+    /// The input was not valid JSON. Nothing has been emitted.
+    ParseError(ParserError),
+    /// The encoder failed.
+    EncodeError(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for TranscodeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl<E: StdError> StdError for TranscodeError<E> {
+    fn description(&self) -> &str { "transcode error" }
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            TranscodeError::ParseError(ref e) => Some(e),
+            TranscodeError::EncodeError(ref e) => Some(e),
         }
     }
+}
 
-    fn parse_ident(&mut self, ident: &str, value: JsonEvent) -> JsonEvent {
-        if ident.chars().all(|c| Some(c) == self.next_char()) {
-            self.bump();
-            value
-        } else {
-            Error(SyntaxError(InvalidSyntax, self.line, self.col))
+/// Streams a JSON document straight into any `Encoder`, without building an
+/// intermediate `Json` tree.
+///
+/// The input is read twice: a first pass validates it and records the
+/// length of every array and object, so the encoder is given exact lengths
+/// and never sees any part of an invalid document. Memory use is one `usize`
+/// per container plus the parser's stack.
+///
+/// Arrays become sequences, objects become maps with string keys, and
+/// scalars are emitted with the matching primitive method.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::json;
+///
+/// fn main() {
+///     let mut out = String::new();
+///     {
+///         let mut encoder = json::Encoder::new_pretty(&mut out);
+///         json::transcode("{\"a\": [1, 2]}".chars(), &mut encoder).unwrap();
+///     }
+///     assert_eq!(out, "{\n  \"a\": [\n    1,\n    2\n  ]\n}");
+/// }
+/// ```
+pub fn transcode<T, E>(src: T, e: &mut E) -> Result<(), TranscodeError<E::Error>>
+    where T: Iterator<Item = char> + Clone, E: ::Encoder
+{
+    transcode_impl(src, e, None)
+}
+
+/// Like `transcode`, but checks `flag` once every `check_every` characters
+/// while validating the input (see `Parser::set_cancel_flag`) and fails with
+/// a `Cancelled` parse error if it is found set. Nothing has been passed to
+/// the encoder at that point.
+pub fn transcode_cancellable<T, E>(src: T, e: &mut E, flag: Arc<AtomicBool>, check_every: u64)
+                                   -> Result<(), TranscodeError<E::Error>>
+    where T: Iterator<Item = char> + Clone, E: ::Encoder
+{
+    transcode_impl(src, e, Some((flag, check_every)))
+}
+
+fn transcode_impl<T, E>(src: T, e: &mut E, cancel: Option<(Arc<AtomicBool>, u64)>)
+                        -> Result<(), TranscodeError<E::Error>>
+    where T: Iterator<Item = char> + Clone, E: ::Encoder
+{
+    let mut lens = Vec::new();
+    let mut open = Vec::new();
+    let mut validator = Parser::new(src.clone());
+    if let Some((flag, check_every)) = cancel {
+        validator.set_cancel_flag(flag, check_every);
+    }
+    for event in validator {
+        match event {
+            Error(err) => return Err(TranscodeError::ParseError(err)),
+            ArrayEnd | ObjectEnd => { open.pop(); }
+            event => {
+                if let Some(&idx) = open.last() {
+                    lens[idx] += 1;
+                }
+                if event == ArrayStart || event == ObjectStart {
+                    open.push(lens.len());
+                    lens.push(0);
+                }
+            }
         }
     }
 
-    fn error_event(&mut self, reason: ErrorCode) -> JsonEvent {
-        self.state = ParseFinished;
-        Error(SyntaxError(reason, self.line, self.col))
+    let mut parser = Parser::new(src);
+    let event = parser.next().unwrap();
+    transcode_value(&mut parser, event, &mut lens.into_iter(), e)
+        .map_err(TranscodeError::EncodeError)
+}
+
+fn transcode_value<T, E, L>(parser: &mut Parser<T>, event: JsonEvent, lens: &mut L,
+                            e: &mut E) -> Result<(), E::Error>
+    where T: Iterator<Item = char>, E: ::Encoder, L: Iterator<Item = usize>
+{
+    match event {
+        NullValue => e.emit_nil(),
+        BooleanValue(v) => e.emit_bool(v),
+        I64Value(v) => e.emit_i64(v),
+        U64Value(v) => e.emit_u64(v),
+        F64Value(v) => e.emit_f64(v),
+        StringValue(v) => e.emit_str(&v),
+        ArrayStart => {
+            let len = lens.next().unwrap();
+            try!(e.emit_seq(len, |e| {
+                for i in 0..len {
+                    let event = parser.next().unwrap();
+                    try!(e.emit_seq_elt(i, |e| transcode_value(parser, event, lens, e)));
+                }
+                Ok(())
+            }));
+            // Encoders may skip the closure for empty containers, so the end
+            // event is consumed out here.
+            parser.next();
+            Ok(())
+        }
+        ObjectStart => {
+            let len = lens.next().unwrap();
+            try!(e.emit_map(len, |e| {
+                for i in 0..len {
+                    let event = parser.next().unwrap();
+                    let key = match parser.stack().top() {
+                        Some(StackElement::Key(k)) => k.to_string(),
+                        _ => panic!("invalid state"),
+                    };
+                    try!(e.emit_map_elt_key(i, |e| e.emit_str(&key)));
+                    try!(e.emit_map_elt_val(i, |e| transcode_value(parser, event, lens, e)));
+                }
+                Ok(())
+            }));
+            parser.next();
+            Ok(())
+        }
+        // The first pass has already validated the document.
+        ArrayEnd | ObjectEnd | Error(_) => panic!("invalid state"),
     }
 }
 
-/// A Builder consumes a json::Parser to create a generic Json structure.
-pub struct Builder<T> {
-    parser: Parser<T>,
-    token: Option<JsonEvent>,
+/// A structure to decode JSON to values in rust.
+///
+/// `Json::Wtf8` values read as strings with their lone surrogates replaced
+/// with U+FFFD, except by `Decoder::read_wtf8`. Sequences are also read
+/// from quantized floats, as `Encoder::set_quantization` writes them.
+pub struct Decoder {
+    stack: Vec<Json>,
+    // For each enum variant being decoded, its name and the height of
+    // `stack` below its fields, so that running out of fields is `EOF`
+    // rather than taking the values after the variant.
+    variants: Vec<(string::String, usize)>,
+    option_policy: OptionPolicy,
+    field_option_policies: HashMap<string::String, OptionPolicy>,
+    // The policy for the `Option` about to be read, if it is the value of a
+    // struct field with a policy of its own.
+    field_option: Option<OptionPolicy>,
+    rename_all: Option<Case>,
+    columnar: bool,
+    deny_unknown_fields: bool,
+    map_key_policy: MapKeyPolicy,
+    // For each struct being decoded, the names of the fields read so far,
+    // kept if unknown fields are denied.
+    struct_fields: Vec<Vec<string::String>>,
 }
 
-impl<T: Iterator<Item = char>> Builder<T> {
-    /// Create a JSON Builder.
-    pub fn new(src: T) -> Builder<T> {
-        Builder { parser: Parser::new(src), token: None, }
+impl Decoder {
+    /// Creates a new decoder instance for decoding the specified JSON value.
+    pub fn new(json: Json) -> Decoder {
+        Decoder {
+            stack: vec![json],
+            variants: Vec::new(),
+            option_policy: OptionPolicy::Null,
+            field_option_policies: HashMap::new(),
+            field_option: None,
+            rename_all: None,
+            columnar: false,
+            deny_unknown_fields: false,
+            map_key_policy: MapKeyPolicy::Stringify,
+            struct_fields: Vec::new(),
+        }
     }
 
-    // Decode a Json value from a Parser.
-    pub fn build(&mut self) -> Result<Json, BuilderError> {
-        self.bump();
-        let result = self.build_value();
-        self.bump();
-        match self.token.take() {
-            None => {}
-            Some(Error(e)) => { return Err(e); }
-            _ => { return Err(SyntaxError(InvalidSyntax, self.parser.line, self.parser.col)); }
+    /// Creates a decoder for the JSON file at `path`, mapping it into memory
+    /// with `mmap::Mmap` and parsing it in place, without reading it into a
+    /// buffer. The text is checked to be UTF-8 where it lies, and each
+    /// string is copied once, into the `Json` value it is parsed to, as the
+    /// decoded values own their strings.
+    ///
+    /// This is available with the `mmap` feature.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be written to or truncated while it is parsed; see
+    /// `mmap::Mmap::open`.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn from_mmap<P: AsRef<Path>>(path: P) -> DecodeResult<Decoder> {
+        let map = try!(Mmap::open(path).map_err(|e| ParseError(From::from(e))));
+        let s = match str::from_utf8(&map) {
+            Ok(s) => s,
+            Err(_) => return Err(ParseError(SyntaxError(NotUtf8, 0, 0))),
+        };
+        let json = try!(Json::from_str(s).map_err(ParseError));
+        Ok(Decoder::new(json))
+    }
+
+    /// Reads the next value as a `Json` tree, as it is, for an override or
+    /// `Json`'s `Decodable` implementation to decode.
+    pub fn read_json(&mut self) -> DecodeResult<Json> {
+        self.pop()
+    }
+
+    /// Set how `Option`s are read; see `OptionPolicy`. `Null` and `Omit`
+    /// read the same.
+    pub fn set_option_policy(&mut self, policy: OptionPolicy) {
+        self.option_policy = policy;
+    }
+
+    /// Set how `Option`s held by struct fields named `field` are read,
+    /// instead of as the `set_option_policy` setting says.
+    pub fn set_field_option_policy(&mut self, field: &str, policy: OptionPolicy) {
+        self.field_option_policies.insert(field.to_string(), policy);
+    }
+
+    /// Read struct fields from members whose names are in `case`, as
+    /// `Encoder::set_rename_all` writes them. A missing field is reported
+    /// by the name of the member.
+    pub fn set_rename_all(&mut self, case: Case) {
+        self.rename_all = Some(case);
+    }
+
+    /// Read sequences from objects of columns, as `Encoder::set_columnar`
+    /// writes sequences of structs, as well as from arrays. The columns
+    /// must be arrays of the same length.
+    pub fn set_columnar(&mut self, columnar: bool) {
+        self.columnar = columnar;
+    }
+
+    /// Fail to read a struct from an object with a member which none of
+    /// its fields are read from, with an `UnknownFieldError`, rather than
+    /// ignoring the member. The error suggests the field the member's key
+    /// is likely a misspelling of, if any.
+    pub fn set_deny_unknown_fields(&mut self, deny: bool) {
+        self.deny_unknown_fields = deny;
+    }
+
+    /// Read maps as an encoder with `policy` writes them: booleans from the
+    /// strings `"true"` and `"false"` with `StringifyAll`, and maps from
+    /// arrays of `[key, value]` pairs as well as objects with `Pairs`.
+    /// This should be set before decoding.
+    pub fn set_map_key_policy(&mut self, policy: MapKeyPolicy) {
+        self.map_key_policy = policy;
+    }
+}
+
+// The number of single character insertions, deletions and substitutions
+// turning `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
         }
-        result
     }
+    row[b.len()]
+}
 
-    fn bump(&mut self) {
-        self.token = self.parser.next();
+// The candidate closest to `name`, ignoring case, if any is close enough to
+// be a misspelling of it: within an edit for every three characters.
+fn closest_name<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+    where I: IntoIterator<Item = &'a str>
+{
+    let lower = name.to_lowercase();
+    let max = name.chars().count() / 3;
+    let mut best = None;
+    for candidate in candidates {
+        let distance = edit_distance(&lower, &candidate.to_lowercase());
+        match best {
+            Some((_, best_distance)) if best_distance <= distance => {}
+            _ if distance <= max => best = Some((candidate, distance)),
+            _ => {}
+        }
     }
+    best.map(|(candidate, _)| candidate)
+}
 
-    fn build_value(&mut self) -> Result<Json, BuilderError> {
-        return match self.token.take() {
-            Some(NullValue) => Ok(Json::Null),
-            Some(I64Value(n)) => Ok(Json::I64(n)),
-            Some(U64Value(n)) => Ok(Json::U64(n)),
-            Some(F64Value(n)) => Ok(Json::F64(n)),
-            Some(BooleanValue(b)) => Ok(Json::Boolean(b)),
-            Some(StringValue(ref mut s)) => {
-                let mut temp = string::String::new();
-                swap(s, &mut temp);
-                Ok(Json::String(temp))
+// Turns an object of columns into the array of objects they are the fields
+// of.
+fn rows_of_columns(columns: Object) -> DecodeResult<Array> {
+    let mut rows = Vec::new();
+    for (i, (name, column)) in columns.into_iter().enumerate() {
+        let column = match column {
+            Json::Array(column) => column,
+            other => return Err(ExpectedError("Array".to_string(), format!("{}", other))),
+        };
+        if i == 0 {
+            rows = column.iter().map(|_| Json::Object(Object::new())).collect();
+        } else if column.len() != rows.len() {
+            return Err(ExpectedError(format!("column of {} values", rows.len()),
+                                     format!("{} in column {:?}", column.len(), name)));
+        }
+        for (row, value) in rows.iter_mut().zip(column) {
+            if let Json::Object(ref mut row) = *row {
+                row.insert(name.clone(), value);
             }
-            Some(Error(e)) => Err(e),
-            Some(ArrayStart) => self.build_array(),
-            Some(ObjectStart) => self.build_object(),
-            Some(ObjectEnd) => self.parser.error(InvalidSyntax),
-            Some(ArrayEnd) => self.parser.error(InvalidSyntax),
-            None => self.parser.error(EOFWhileParsingValue),
         }
     }
+    Ok(rows)
+}
 
-    fn build_array(&mut self) -> Result<Json, BuilderError> {
-        self.bump();
-        let mut values = Vec::new();
+/// Decodes a `T` from `json`, as `decode` would from its text, leaving
+/// `json` as it is.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::json::{self, Json};
+///
+/// fn main() {
+///     let json = Json::from_str(r#"{"size": {"w": 640, "h": [480, null]}}"#).unwrap();
+///     let h: Vec<Option<u32>> = json::from_value(&json["size"]["h"]).unwrap();
+///     assert_eq!(h, [Some(480), None]);
+/// }
+/// ```
+pub fn from_value<T: ::Decodable>(json: &Json) -> DecodeResult<T> {
+    let mut decoder = Decoder::new(json.clone());
+    ::Decodable::decode(&mut decoder)
+}
 
-        loop {
-            if let Some(ArrayEnd) = self.token {
-                return Ok(Json::Array(values.into_iter().collect()));
+/// Describes the structure of the documents in `samples`; see `schema`.
+pub fn infer_schema(samples: &[Json]) -> schema::Schema {
+    schema::infer_schema(samples)
+}
+
+/// Parses `s` to a tree in which each value carries the byte range of its
+/// text; see `spanned`.
+pub fn parse_spanned(s: &str) -> Result<spanned::SpannedJson, BuilderError> {
+    spanned::parse_spanned(s)
+}
+
+/// Copies the document read from `input` to `output`, re-indented or
+/// minified in `style`, without building its value; see `format`.
+pub fn reformat<R: io::Read, W: io::Write>(input: R, output: W, style: format::Style)
+                                           -> Result<(), ParserError> {
+    format::reformat(input, output, style)
+}
+
+/// An error from `interpolate`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum InterpolationError {
+    /// A placeholder named a variable which the lookup did not know, and
+    /// gave no default for it
+    UndefinedVariable(string::String),
+    /// A string held a `${` without a `}` after it
+    UnclosedPlaceholder(string::String),
+}
+
+impl fmt::Display for InterpolationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InterpolationError::UndefinedVariable(ref name) => {
+                write!(f, "undefined variable `{}`", name)
             }
-            match self.build_value() {
-                Ok(v) => values.push(v),
-                Err(e) => { return Err(e) }
+            InterpolationError::UnclosedPlaceholder(ref s) => {
+                write!(f, "unclosed placeholder in {:?}", s)
             }
-            self.bump();
         }
     }
+}
 
-    fn build_object(&mut self) -> Result<Json, BuilderError> {
-        self.bump();
-
-        let mut values = BTreeMap::new();
+impl StdError for InterpolationError {
+    fn description(&self) -> &str { "interpolation error" }
+}
 
-        loop {
-            match self.token.take() {
-                Some(ObjectEnd) => { return Ok(Json::Object(values)); }
-                Some(Error(e)) => { return Err(e); }
-                None => { break; }
-                token => { self.token = token; }
+/// Substitutes `${NAME}` placeholders in the strings within `json`, object
+/// keys aside, with the values `lookup` gives for their names, as for
+/// configuration files which refer to environment variables.
+///
+/// A string which is a single placeholder and nothing else is replaced with
+/// the value itself, so that `"${PORT}"` can become the number `8080`;
+/// elsewhere a string value is inserted as it is, and any other value as
+/// its JSON encoding. `${NAME:-default}` gives a default, used as a string,
+/// for when `lookup` returns `None`, and `$${` stands for a literal `${`.
+/// Any other `$` is left as it is.
+///
+/// On an error the strings before the one failing have been substituted
+/// already.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::json::{self, Json};
+///
+/// fn main() {
+///     let mut config = Json::from_str(r#"{"port": "${PORT}",
+///                                         "url": "http://${HOST:-localhost}:${PORT}/",
+///                                         "note": "costs $5, see $${HOME}"}"#).unwrap();
+///     let lookup = |name: &str| match name {
+///         "PORT" => Some(Json::U64(8080)),
+///         _ => None,
+///     };
+///     json::interpolate(&mut config, &lookup).unwrap();
+///     assert_eq!(config["port"], Json::U64(8080));
+///     assert_eq!(config["url"].as_string(), Some("http://localhost:8080/"));
+///     assert_eq!(config["note"].as_string(), Some("costs $5, see ${HOME}"));
+/// }
+/// ```
+pub fn interpolate(json: &mut Json, lookup: &Fn(&str) -> Option<Json>)
+                   -> Result<(), InterpolationError> {
+    let substituted = match *json {
+        Json::Array(ref mut array) => {
+            for elt in array.iter_mut() {
+                try!(interpolate(elt, lookup));
             }
-            let key = match self.parser.stack().top() {
-                Some(StackElement::Key(k)) => { k.to_string() }
-                _ => { panic!("invalid state"); }
-            };
-            match self.build_value() {
-                Ok(value) => { values.insert(key, value); }
-                Err(e) => { return Err(e); }
+            return Ok(());
+        }
+        Json::Object(ref mut object) => {
+            for member in object.values_mut() {
+                try!(interpolate(member, lookup));
             }
-            self.bump();
+            return Ok(());
         }
-        return self.parser.error(EOFWhileParsingObject);
-    }
+        Json::String(ref s) if s.contains('$') => try!(interpolate_str(s, lookup)),
+        _ => return Ok(()),
+    };
+    *json = substituted;
+    Ok(())
 }
 
-/// A structure to decode JSON to values in rust.
-pub struct Decoder {
-    stack: Vec<Json>,
+fn interpolate_str(s: &str, lookup: &Fn(&str) -> Option<Json>)
+                   -> Result<Json, InterpolationError> {
+    if s.starts_with("${") && s.find('}') == Some(s.len() - 1) {
+        return placeholder(&s[2..s.len() - 1], lookup);
+    }
+    let mut out = string::String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if rest.starts_with("$${") {
+            out.push_str("${");
+            rest = &rest[3..];
+        } else if rest.starts_with("${") {
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => return Err(InterpolationError::UnclosedPlaceholder(s.to_string())),
+            };
+            match try!(placeholder(&rest[2..end], lookup)) {
+                Json::String(ref value) => out.push_str(value),
+                value => out.push_str(&value.to_string()),
+            }
+            rest = &rest[end + 1..];
+        } else {
+            out.push('$');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(Json::String(out))
 }
 
-impl Decoder {
-    /// Creates a new decoder instance for decoding the specified JSON value.
-    pub fn new(json: Json) -> Decoder {
-        Decoder { stack: vec![json] }
+// The value of the placeholder `${expr}`.
+fn placeholder(expr: &str, lookup: &Fn(&str) -> Option<Json>)
+               -> Result<Json, InterpolationError> {
+    let (name, default) = match expr.find(":-") {
+        Some(i) => (&expr[..i], Some(&expr[i + 2..])),
+        None => (expr, None),
+    };
+    match (lookup(name), default) {
+        (Some(value), _) => Ok(value),
+        (None, Some(default)) => Ok(Json::from(default)),
+        (None, None) => Err(InterpolationError::UndefinedVariable(name.to_string())),
     }
 }
 
@@ -2132,6 +5477,8 @@ impl ::Decoder for Decoder {
     read_primitive! { read_i16, i16 }
     read_primitive! { read_i32, i32 }
     read_primitive! { read_i64, i64 }
+    read_primitive! { read_u128, u128 }
+    read_primitive! { read_i128, i128 }
 
     fn read_f32(&mut self) -> DecodeResult<f32> {
         self.read_f64().map(|x| x as f32)
@@ -2156,7 +5503,18 @@ impl ::Decoder for Decoder {
     }
 
     fn read_bool(&mut self) -> DecodeResult<bool> {
-        expect!(self.pop(), Boolean)
+        match try!(self.pop()) {
+            Json::Boolean(b) => Ok(b),
+            // Map keys written by `MapKeyPolicy::StringifyAll`.
+            Json::String(ref s) if self.map_key_policy == MapKeyPolicy::StringifyAll => {
+                match &s[..] {
+                    "true" => Ok(true),
+                    "false" => Ok(false),
+                    _ => Err(ExpectedError("Boolean".to_string(), format!("{:?}", &s[..]))),
+                }
+            }
+            other => Err(ExpectedError("Boolean".to_string(), format!("{}", other))),
+        }
     }
 
     fn read_char(&mut self) -> DecodeResult<char> {
@@ -2173,7 +5531,31 @@ impl ::Decoder for Decoder {
     }
 
     fn read_str(&mut self) -> DecodeResult<string::String> {
-        expect!(self.pop(), String)
+        match try!(self.pop()) {
+            Json::String(s) => Ok(s),
+            Json::Wtf8(s) => Ok(s.to_string_lossy().into_owned()),
+            other => Err(ExpectedError("String".to_string(), format!("{}", other))),
+        }
+    }
+
+    fn read_wtf8(&mut self) -> DecodeResult<Wtf8Buf> {
+        match try!(self.pop()) {
+            Json::String(s) => Ok(Wtf8Buf::from(s)),
+            Json::Wtf8(s) => Ok(s),
+            other => Err(ExpectedError("String".to_string(), format!("{}", other))),
+        }
+    }
+
+    // Paths are read from strings, as `Encoder::set_path_strings` writes
+    // them, as well as in the platform's own form.
+    fn read_path(&mut self) -> DecodeResult<PathBuf> {
+        match try!(self.pop()) {
+            Json::String(s) => Ok(PathBuf::from(s)),
+            json => {
+                self.stack.push(json);
+                ::serialize::decode_native_path(self)
+            }
+        }
     }
 
     fn read_enum<T, F>(&mut self, _name: &str, f: F) -> DecodeResult<T> where
@@ -2186,7 +5568,10 @@ impl ::Decoder for Decoder {
                                mut f: F) -> DecodeResult<T>
         where F: FnMut(&mut Decoder, usize) -> DecodeResult<T>,
     {
-        let name = match try!(self.pop()) {
+        self.field_option = None;
+        let value = try!(self.pop());
+        let floor = self.stack.len();
+        let name = match value {
             Json::String(s) => s,
             Json::Object(mut o) => {
                 let n = match o.remove(&"variant".to_string()) {
@@ -2217,16 +5602,32 @@ impl ::Decoder for Decoder {
                 return Err(ExpectedError("String or Object".to_string(), format!("{}", json)))
             }
         };
-        let idx = match names.iter().position(|n| *n == name) {
+        let idx = match names.iter().position(|n| name == *n) {
             Some(idx) => idx,
             None => return Err(UnknownVariantError(name))
         };
-        f(self, idx)
+        let fields = self.stack.len() - floor;
+        self.variants.push((name, floor));
+        let value = f(self, idx);
+        let (name, _) = self.variants.pop().unwrap();
+        let value = try!(value);
+        // Fields the variant does not have would be taken for the values
+        // after it.
+        if self.stack.len() > floor {
+            let read = fields - (self.stack.len() - floor);
+            return Err(ExpectedError(format!("{} fields for variant {}", read, name),
+                                     format!("{} fields", fields)));
+        }
+        Ok(value)
     }
 
     fn read_enum_variant_arg<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
         F: FnOnce(&mut Decoder) -> DecodeResult<T>,
     {
+        match self.variants.last() {
+            Some(&(_, floor)) if self.stack.len() <= floor => return Err(EOF),
+            _ => {}
+        }
         f(self)
     }
 
@@ -2250,8 +5651,18 @@ impl ::Decoder for Decoder {
     fn read_struct<T, F>(&mut self, _name: &str, _len: usize, f: F) -> DecodeResult<T> where
         F: FnOnce(&mut Decoder) -> DecodeResult<T>,
     {
-        let value = try!(f(self));
-        try!(self.pop());
+        self.field_option = None;
+        self.struct_fields.push(Vec::new());
+        let value = f(self);
+        let fields = self.struct_fields.pop().unwrap_or_default();
+        let value = try!(value);
+        let rest = try!(self.pop());
+        if self.deny_unknown_fields {
+            if let Some(key) = rest.as_object().and_then(|rest| rest.keys().next()) {
+                let suggestion = closest_name(key, fields.iter().map(|f| &f[..]));
+                return Err(UnknownFieldError(key.clone(), suggestion.map(|f| f.to_string())));
+            }
+        }
         Ok(value)
     }
 
@@ -2264,14 +5675,30 @@ impl ::Decoder for Decoder {
     {
         let mut obj = try!(expect!(self.pop(), Object));
 
-        let value = match obj.remove(&name.to_string()) {
+        self.field_option = self.field_option_policies.get(name).cloned();
+        let key = match self.rename_all {
+            Some(case) => case.convert(name),
+            None => Cow::Borrowed(name),
+        };
+        if self.deny_unknown_fields {
+            if let Some(fields) = self.struct_fields.last_mut() {
+                fields.push(key.to_string());
+            }
+        }
+        let value = match obj.remove(&*key) {
             None => {
                 // Add a Null and try to parse it as an Option<_>
                 // to get None as a default value.
                 self.stack.push(Json::Null);
                 match f(self) {
                     Ok(x) => x,
-                    Err(_) => return Err(MissingFieldError(name.to_string())),
+                    Err(_) => {
+                        let close = closest_name(&key, obj.keys().map(|k| &k[..]));
+                        return Err(match close {
+                            Some(close) => MisspeltFieldError(key.into_owned(), close.to_string()),
+                            None => MissingFieldError(key.into_owned()),
+                        });
+                    }
                 }
             },
             Some(json) => {
@@ -2279,6 +5706,7 @@ impl ::Decoder for Decoder {
                 try!(f(self))
             }
         };
+        self.field_option = None;
         self.stack.push(Json::Object(obj));
         Ok(value)
     }
@@ -2323,16 +5751,42 @@ impl ::Decoder for Decoder {
     fn read_option<T, F>(&mut self, mut f: F) -> DecodeResult<T> where
         F: FnMut(&mut Decoder, bool) -> DecodeResult<T>,
     {
+        let policy = self.field_option.take().unwrap_or(self.option_policy);
         match try!(self.pop()) {
             Json::Null => f(self, false),
-            value => { self.stack.push(value); f(self, true) }
+            Json::Array(ref mut a) if policy == OptionPolicy::Array && a.len() <= 1 => {
+                match a.pop() {
+                    Some(value) => { self.stack.push(value); f(self, true) }
+                    None => f(self, false),
+                }
+            }
+            value => {
+                if policy == OptionPolicy::Array {
+                    return Err(ExpectedError("Array of at most one element".to_string(),
+                                             format!("{}", value)));
+                }
+                self.stack.push(value);
+                f(self, true)
+            }
         }
     }
 
     fn read_seq<T, F>(&mut self, f: F) -> DecodeResult<T> where
         F: FnOnce(&mut Decoder, usize) -> DecodeResult<T>,
     {
-        let array = try!(expect!(self.pop(), Array));
+        self.field_option = None;
+        let array = match try!(self.pop()) {
+            Json::Array(array) => array,
+            Json::Object(object) => match half::quantized_floats(&object) {
+                Some(floats) => floats.into_iter().map(Json::F64).collect(),
+                None if self.columnar => try!(rows_of_columns(object)),
+                None => {
+                    let found = Json::Object(object).to_string();
+                    return Err(ExpectedError("Array".to_string(), found));
+                }
+            },
+            other => return Err(ExpectedError("Array".to_string(), format!("{}", other))),
+        };
         let len = array.len();
         for v in array.into_iter().rev() {
             self.stack.push(v);
@@ -2349,12 +5803,38 @@ impl ::Decoder for Decoder {
     fn read_map<T, F>(&mut self, f: F) -> DecodeResult<T> where
         F: FnOnce(&mut Decoder, usize) -> DecodeResult<T>,
     {
-        let obj = try!(expect!(self.pop(), Object));
-        let len = obj.len();
-        for (key, value) in obj.into_iter() {
-            self.stack.push(value);
-            self.stack.push(Json::String(key));
-        }
+        self.field_option = None;
+        let len = match try!(self.pop()) {
+            Json::Object(obj) => {
+                let len = obj.len();
+                for (key, value) in obj.into_iter() {
+                    self.stack.push(value);
+                    self.stack.push(Json::from(key));
+                }
+                len
+            }
+            // Written by `MapKeyPolicy::Pairs`.
+            Json::Array(pairs) if self.map_key_policy == MapKeyPolicy::Pairs => {
+                let len = pairs.len();
+                for pair in pairs.into_iter() {
+                    match pair {
+                        Json::Array(mut kv) if kv.len() == 2 => {
+                            let key = kv.pop().unwrap();
+                            let value = kv.pop().unwrap();
+                            // Popped in reverse: `key` holds the value.
+                            self.stack.push(key);
+                            self.stack.push(value);
+                        }
+                        other => {
+                            return Err(ExpectedError("[key, value] pair".to_string(),
+                                                     format!("{}", other)))
+                        }
+                    }
+                }
+                len
+            }
+            other => return Err(ExpectedError("Object".to_string(), format!("{}", other))),
+        };
         f(self, len)
     }
 
@@ -2370,6 +5850,14 @@ impl ::Decoder for Decoder {
         f(self)
     }
 
+    fn format(&self) -> Option<&'static str> {
+        Some("json")
+    }
+
+    fn as_any(&mut self) -> Option<&mut Any> {
+        Some(self)
+    }
+
     fn error(&mut self, err: &str) -> DecoderError {
         ApplicationError(err.to_string())
     }
@@ -2401,6 +5889,21 @@ macro_rules! to_json_impl_u64 {
 
 to_json_impl_u64! { usize, u8, u16, u32, u64 }
 
+/// Any `Encodable` value can be converted with `as_json(&value).to_json()`,
+/// which encodes it with `ValueEncoder`, so a type need not implement both
+/// `Encodable` and `ToJson`.
+///
+/// # Panics
+///
+/// Panics if the value cannot be encoded, which is only the case for maps
+/// with keys which are not strings or scalars; `to_value` returns the error
+/// instead.
+impl<'a, T: Encodable> ToJson for AsJson<'a, T> {
+    fn to_json(&self) -> Json {
+        to_value(self.inner).expect("value could not be encoded as JSON")
+    }
+}
+
 impl ToJson for Json {
     fn to_json(&self) -> Json { self.clone() }
 }
@@ -2428,12 +5931,24 @@ impl ToJson for bool {
     fn to_json(&self) -> Json { Json::Boolean(*self) }
 }
 
+impl From<string::String> for Json {
+    fn from(s: string::String) -> Json { Json::String(s) }
+}
+
+impl<'a> From<&'a str> for Json {
+    fn from(s: &'a str) -> Json { Json::String(s.to_string()) }
+}
+
 impl ToJson for str {
-    fn to_json(&self) -> Json { Json::String(self.to_string()) }
+    fn to_json(&self) -> Json { Json::from(self) }
 }
 
 impl ToJson for string::String {
-    fn to_json(&self) -> Json { Json::String((*self).clone()) }
+    fn to_json(&self) -> Json { Json::from(self.clone()) }
+}
+
+impl<T: ?Sized + ToJson> ToJson for &T {
+    fn to_json(&self) -> Json { (**self).to_json() }
 }
 
 macro_rules! tuple_impl {
@@ -2578,6 +6093,83 @@ impl<'a, T: Encodable> fmt::Display for AsPrettyJson<'a, T> {
     }
 }
 
+impl<'a> CommentedJson<'a> {
+    /// Set the indentation level for the emitted JSON
+    pub fn indent(mut self, indent: u32) -> CommentedJson<'a> {
+        self.indent = indent;
+        self
+    }
+
+    fn write_comment(&self, wr: &mut fmt::Write, pointer: &str, curr_indent: u32)
+                     -> fmt::Result {
+        if let Some(comment) = self.comments.get(pointer) {
+            for line in comment.lines() {
+                try!(write!(wr, "{:1$}//", "", curr_indent as usize));
+                if !line.is_empty() {
+                    try!(write!(wr, " {}", line));
+                }
+                try!(write!(wr, "\n"));
+            }
+        }
+        Ok(())
+    }
+
+    // Writes `json` at `pointer`; its comment has already been written.
+    fn write_value(&self, wr: &mut fmt::Write, json: &Json, pointer: &mut string::String,
+                   curr_indent: u32) -> fmt::Result {
+        match *json {
+            Json::Array(ref v) if !v.is_empty() => {
+                try!(write!(wr, "["));
+                for (idx, elt) in v.iter().enumerate() {
+                    let len = pointer.len();
+                    pointer.push('/');
+                    pointer.push_str(&idx.to_string());
+                    try!(self.write_entry(wr, idx, pointer, curr_indent + self.indent));
+                    try!(self.write_value(wr, elt, pointer, curr_indent + self.indent));
+                    pointer.truncate(len);
+                }
+                write!(wr, "\n{:1$}]", "", curr_indent as usize)
+            }
+            Json::Object(ref o) if !o.is_empty() => {
+                try!(write!(wr, "{{"));
+                for (idx, (key, elt)) in o.iter().enumerate() {
+                    let len = pointer.len();
+                    pointer.push('/');
+                    pointer.push_str(&key.replace("~", "~0").replace("/", "~1"));
+                    try!(self.write_entry(wr, idx, pointer, curr_indent + self.indent));
+                    try!(escape_str(wr, key).map_err(|_| fmt::Error));
+                    try!(write!(wr, ": "));
+                    try!(self.write_value(wr, elt, pointer, curr_indent + self.indent));
+                    pointer.truncate(len);
+                }
+                write!(wr, "\n{:1$}}}", "", curr_indent as usize)
+            }
+            ref scalar => write!(wr, "{}", scalar),
+        }
+    }
+
+    // Starts the line of an array element or object member.
+    fn write_entry(&self, wr: &mut fmt::Write, idx: usize, pointer: &str, curr_indent: u32)
+                   -> fmt::Result {
+        if idx != 0 {
+            try!(write!(wr, ","));
+        }
+        try!(write!(wr, "\n"));
+        try!(self.write_comment(wr, pointer, curr_indent));
+        write!(wr, "{:1$}", "", curr_indent as usize)
+    }
+}
+
+impl<'a> fmt::Display for CommentedJson<'a> {
+    /// Encodes a json value into a string, with comments
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut shim = FormatShim { inner: f };
+        let mut pointer = string::String::new();
+        try!(self.write_comment(&mut shim, "", 0));
+        self.write_value(&mut shim, self.inner, &mut pointer, 0)
+    }
+}
+
 impl FromStr for Json {
     type Err = ParserError;
     fn from_str(s: &str) -> Result<Json, ParserError> {
@@ -2601,11 +6193,21 @@ mod tests {
     use std::collections::BTreeMap;
     use std::string;
 
-    #[derive(RustcDecodable, Eq, PartialEq, Debug)]
+    #[derive(Eq, PartialEq, Debug)]
     struct OptionData {
         opt: Option<usize>,
     }
 
+    impl Decodable for OptionData {
+        fn decode<D: ::Decoder>(d: &mut D) -> Result<OptionData, D::Error> {
+            d.read_struct("OptionData", 1, |d| {
+                Ok(OptionData {
+                    opt: try!(d.read_struct_field("opt", 0, Decodable::decode)),
+                })
+            })
+        }
+    }
+
     #[test]
     fn test_decode_option_none() {
         let s ="{}";
@@ -2628,24 +6230,95 @@ mod tests {
                                 ExpectedError("Number".to_string(), "false".to_string()));
     }
 
-    #[derive(PartialEq, RustcEncodable, RustcDecodable, Debug)]
+    #[derive(PartialEq, Debug)]
     enum Animal {
         Dog,
         Frog(string::String, isize)
     }
 
-    #[derive(PartialEq, RustcEncodable, RustcDecodable, Debug)]
+    impl Encodable for Animal {
+        fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_enum("Animal", |s| {
+                match *self {
+                    Animal::Dog => s.emit_enum_variant("Dog", 0, 0, |_| Ok(())),
+                    Animal::Frog(ref v0, ref v1) => s.emit_enum_variant("Frog", 1, 2, |s| {
+                        try!(s.emit_enum_variant_arg(0, |s| v0.encode(s)));
+                        s.emit_enum_variant_arg(1, |s| v1.encode(s))
+                    }),
+                }
+            })
+        }
+    }
+
+    impl Decodable for Animal {
+        fn decode<D: ::Decoder>(d: &mut D) -> Result<Animal, D::Error> {
+            d.read_enum("Animal", |d| {
+                d.read_enum_variant(&["Dog", "Frog"], |d, idx| {
+                    match idx {
+                        0 => Ok(Animal::Dog),
+                        1 => Ok(Animal::Frog(
+                            try!(d.read_enum_variant_arg(0, Decodable::decode)),
+                            try!(d.read_enum_variant_arg(1, Decodable::decode))
+                        )),
+                        _ => panic!("Internal error"),
+                    }
+                })
+            })
+        }
+    }
+
+    #[derive(PartialEq, Debug)]
     struct Inner {
         a: (),
         b: usize,
         c: Vec<string::String>,
     }
 
-    #[derive(PartialEq, RustcEncodable, RustcDecodable, Debug)]
+    impl Encodable for Inner {
+        fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_struct("Inner", 3, |s| {
+                try!(s.emit_struct_field("a", 0, |s| self.a.encode(s)));
+                try!(s.emit_struct_field("b", 1, |s| self.b.encode(s)));
+                s.emit_struct_field("c", 2, |s| self.c.encode(s))
+            })
+        }
+    }
+
+    impl Decodable for Inner {
+        fn decode<D: ::Decoder>(d: &mut D) -> Result<Inner, D::Error> {
+            d.read_struct("Inner", 3, |d| {
+                Ok(Inner {
+                    a: try!(d.read_struct_field("a", 0, Decodable::decode)),
+                    b: try!(d.read_struct_field("b", 1, Decodable::decode)),
+                    c: try!(d.read_struct_field("c", 2, Decodable::decode)),
+                })
+            })
+        }
+    }
+
+    #[derive(PartialEq, Debug)]
     struct Outer {
         inner: Vec<Inner>,
     }
 
+    impl Encodable for Outer {
+        fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_struct("Outer", 1, |s| {
+                s.emit_struct_field("inner", 0, |s| self.inner.encode(s))
+            })
+        }
+    }
+
+    impl Decodable for Outer {
+        fn decode<D: ::Decoder>(d: &mut D) -> Result<Outer, D::Error> {
+            d.read_struct("Outer", 1, |d| {
+                Ok(Outer {
+                    inner: try!(d.read_struct_field("inner", 0, Decodable::decode)),
+                })
+            })
+        }
+    }
+
     fn mk_object(items: &[(string::String, Json)]) -> Json {
         let mut d = BTreeMap::new();
 
@@ -2711,11 +6384,21 @@ mod tests {
 
     #[test]
     fn test_write_str() {
-        assert_eq!(String("".to_string()).to_string(), "\"\"");
-        assert_eq!(String("".to_string()).pretty().to_string(), "\"\"");
-
-        assert_eq!(String("homura".to_string()).to_string(), "\"homura\"");
-        assert_eq!(String("madoka".to_string()).pretty().to_string(), "\"madoka\"");
+        assert_eq!(String("".into()).to_string(), "\"\"");
+        assert_eq!(String("".into()).pretty().to_string(), "\"\"");
+
+        assert_eq!(String("homura".into()).to_string(), "\"homura\"");
+        assert_eq!(String("madoka".into()).pretty().to_string(), "\"madoka\"");
+
+        // Escapes at either end and between runs of unescaped text.
+        assert_eq!(String("\"a\\b\u{1f}\u{1f}\u{e9}\u{7f}\n".into()).to_string(),
+                   "\"\\\"a\\\\b\\u001f\\u001f\u{e9}\\u007f\\n\"");
+        for b in 0u8..128 {
+            let s = (b as char).to_string();
+            let encoded = Json::from(s.clone()).to_string();
+            let decoded: string::String = super::decode(&encoded).unwrap();
+            assert_eq!(decoded, s);
+        }
     }
 
     #[test]
@@ -2744,7 +6427,7 @@ mod tests {
         let long_test_array = Array(vec![
             Boolean(false),
             Null,
-            Array(vec![String("foo\nbar".to_string()), F64(3.5)])]);
+            Array(vec![String("foo\nbar".into()), F64(3.5)])]);
 
         assert_eq!(long_test_array.to_string(),
             "[false,null,[\"foo\\nbar\",3.5]]");
@@ -2783,8 +6466,8 @@ mod tests {
 
         let complex_obj = mk_object(&[
                 ("b".to_string(), Array(vec![
-                    mk_object(&[("c".to_string(), String("\x0c\r".to_string()))]),
-                    mk_object(&[("d".to_string(), String("".to_string()))])
+                    mk_object(&[("c".to_string(), String("\x0c\r".into()))]),
+                    mk_object(&[("d".to_string(), String("".into()))])
                 ]))
             ]);
 
@@ -2815,8 +6498,8 @@ mod tests {
         let a = mk_object(&[
             ("a".to_string(), Boolean(true)),
             ("b".to_string(), Array(vec![
-                mk_object(&[("c".to_string(), String("\x0c\r".to_string()))]),
-                mk_object(&[("d".to_string(), String("".to_string()))])
+                mk_object(&[("c".to_string(), String("\x0c\r".into()))]),
+                mk_object(&[("d".to_string(), String("".into()))])
             ]))
         ]);
 
@@ -2956,6 +6639,36 @@ mod tests {
         assert_eq!(Json::from_str("18446744073709551615"), Ok(U64(u64::MAX)));
     }
 
+    #[test]
+    fn test_128_bit_integers() {
+        use std::collections::BTreeMap;
+        use super::{encode, decode};
+
+        assert_eq!(encode(&9007199254740991u128).unwrap(), "9007199254740991");
+        assert_eq!(encode(&9007199254740992u128).unwrap(), "\"9007199254740992\"");
+        assert_eq!(encode(&-9007199254740991i128).unwrap(), "-9007199254740991");
+        assert_eq!(encode(&i128::MIN).unwrap(),
+                   "\"-170141183460469231731687303715884105728\"");
+
+        for &v in [0, 1 << 53, u64::MAX as u128, u128::MAX].iter() {
+            assert_eq!(decode::<u128>(&encode(&v).unwrap()).unwrap(), v);
+        }
+        for &v in [-1, i64::MIN as i128, i128::MIN, i128::MAX].iter() {
+            assert_eq!(decode::<i128>(&encode(&v).unwrap()).unwrap(), v);
+        }
+        // Plain JSON numbers decode too.
+        assert_eq!(decode::<u128>("18446744073709551615").unwrap(), u64::MAX as u128);
+        assert_eq!(decode::<i128>("-9223372036854775808").unwrap(), i64::MIN as i128);
+        assert!(decode::<u128>("-1").is_err());
+        assert!(decode::<u128>("\"1e3\"").is_err());
+
+        let mut map = BTreeMap::new();
+        map.insert(1u128 << 100, 1u128);
+        let json = encode(&map).unwrap();
+        assert_eq!(json, "{\"1267650600228229401496703205376\":1}");
+        assert_eq!(decode::<BTreeMap<u128, u128>>(&json).unwrap(), map);
+    }
+
     #[test]
     fn test_decode_numbers() {
         let v: f64 = super::decode("3").unwrap();
@@ -3016,18 +6729,118 @@ mod tests {
         assert!('\u{80}'.is_control());
         assert!('\u{9F}'.is_control());
         let c1_controls = "\u{7F}\u{80}\u{9F}".to_string();
-        assert_eq!(Json::from_str(&format!("\"{}\"", c1_controls)), Ok(String(c1_controls)));
+        assert_eq!(Json::from_str(&format!("\"{}\"", c1_controls)), Ok(Json::from(c1_controls)));
+
+        assert_eq!(Json::from_str("\"\""), Ok(String("".into())));
+        assert_eq!(Json::from_str("\"foo\""), Ok(String("foo".into())));
+        assert_eq!(Json::from_str("\"\\\"\""), Ok(String("\"".into())));
+        assert_eq!(Json::from_str("\"\\b\""), Ok(String("\x08".into())));
+        assert_eq!(Json::from_str("\"\\n\""), Ok(String("\n".into())));
+        assert_eq!(Json::from_str("\"\\r\""), Ok(String("\r".into())));
+        assert_eq!(Json::from_str("\"\\t\""), Ok(String("\t".into())));
+        assert_eq!(Json::from_str(" \"foo\" "), Ok(String("foo".into())));
+        assert_eq!(Json::from_str("\"\\u12ab\""), Ok(String("\u{12ab}".into())));
+        assert_eq!(Json::from_str("\"\\uAB12\""), Ok(String("\u{AB12}".into())));
+    }
+
+    #[test]
+    fn test_read_str_surrogates() {
+        use super::{Builder, ErrorCode};
+
+        fn code(s: &str, reject_noncharacters: bool) -> Option<ErrorCode> {
+            let mut builder = Builder::new(s.chars());
+            builder.set_reject_noncharacters(reject_noncharacters);
+            match builder.build() {
+                Ok(_) => None,
+                Err(SyntaxError(code, _, _)) => Some(code),
+                Err(e) => panic!("unexpected error: {:?}", e),
+            }
+        }
 
-        assert_eq!(Json::from_str("\"\""), Ok(String("".to_string())));
-        assert_eq!(Json::from_str("\"foo\""), Ok(String("foo".to_string())));
-        assert_eq!(Json::from_str("\"\\\"\""), Ok(String("\"".to_string())));
-        assert_eq!(Json::from_str("\"\\b\""), Ok(String("\x08".to_string())));
-        assert_eq!(Json::from_str("\"\\n\""), Ok(String("\n".to_string())));
-        assert_eq!(Json::from_str("\"\\r\""), Ok(String("\r".to_string())));
-        assert_eq!(Json::from_str("\"\\t\""), Ok(String("\t".to_string())));
-        assert_eq!(Json::from_str(" \"foo\" "), Ok(String("foo".to_string())));
-        assert_eq!(Json::from_str("\"\\u12ab\""), Ok(String("\u{12ab}".to_string())));
-        assert_eq!(Json::from_str("\"\\uAB12\""), Ok(String("\u{AB12}".to_string())));
+        assert_eq!(Json::from_str("\"\\uD834\\uDF06\""), Ok(String("\u{1D306}".into())));
+        assert_eq!(Json::from_str("\"\\uDC00\""),
+                   Err(SyntaxError(LoneTrailingSurrogateInHexEscape, 1, 7)));
+        assert_eq!(Json::from_str("\"a\\uD800\""),
+                   Err(SyntaxError(LoneLeadingSurrogateInHexEscape, 1, 9)));
+        let lone = ["\"\\uD800\\n\"", "\"\\uD800\\uD800\\uDC00\"", "\"\\uDBFF\\u0041\"",
+                    "[\"\\uD800x\"]"];
+        for s in lone.iter() {
+            assert_eq!(code(s, false), Some(LoneLeadingSurrogateInHexEscape), "{}", s);
+        }
+        assert_eq!(code("\"\\uD800", false), Some(UnexpectedEndOfHexEscape));
+        assert_eq!(code("\"\\uDFFF\\uD800\"", false), Some(LoneTrailingSurrogateInHexEscape));
+
+        let noncharacters = ["\"\\uFFFE\"", "\"\\uffff\"", "\"\\uFDD0\"", "\"\\uD83F\\uDFFF\"",
+                             "\"\u{FFFF}\"", "\"a\u{10FFFE}\"", "{\"\u{FDEF}\": 1}"];
+        for s in noncharacters.iter() {
+            assert_eq!(code(s, false), None, "{}", s);
+            assert_eq!(code(s, true), Some(NoncharacterInString), "{}", s);
+        }
+        for s in ["\"\\uFFFD\"", "\"\u{FDCF}\u{FDF0}\u{1FFFD}\""].iter() {
+            assert_eq!(code(s, true), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_read_json() {
+        let json = Json::from_str("[1,{\"a\":null}]").unwrap();
+        assert_eq!(super::decode::<Json>(&json.to_string()).unwrap(), json);
+        let mut decoder = Decoder::new(json.clone());
+        assert_eq!(super::read_json(&mut decoder).unwrap(), json);
+        // Other decoders have no `Json` to give.
+        assert!(::ini::decode::<Json>("a = 1").is_err());
+    }
+
+    #[test]
+    fn test_surrogate_policy() {
+        use super::{Builder, Decoder, SurrogatePolicy, ValueEncoder};
+        use wtf8::Wtf8Buf;
+
+        fn build(s: &str, policy: SurrogatePolicy) -> Json {
+            let mut builder = Builder::new(s.chars());
+            builder.set_surrogate_policy(policy);
+            builder.build().unwrap()
+        }
+
+        let replaced = [("\"a\\uD800b\"", "a\u{FFFD}b"),
+                        ("\"\\uD800\\uD800\\uDC00\"", "\u{FFFD}\u{10000}"),
+                        ("\"\\uDC00\\uD800\"", "\u{FFFD}\u{FFFD}"),
+                        ("\"\\uD800\\n\\uDBFF\\\\\"", "\u{FFFD}\n\u{FFFD}\\")];
+        for &(s, expected) in replaced.iter() {
+            assert_eq!(build(s, SurrogatePolicy::Replace), Json::from(expected), "{}", s);
+        }
+
+        let doc = r#"{"k\uD800": ["v\uDFFF", "\uD834\uDF06"]}"#;
+        let json = build(doc, SurrogatePolicy::Wtf8);
+        let kept = Wtf8Buf::from_utf16(&[0x76, 0xDFFF]);
+        assert_eq!(json["k\u{FFFD}"][0], Json::Wtf8(kept.clone()));
+        assert_eq!(json["k\u{FFFD}"][1], Json::from("\u{1D306}"));
+        assert_eq!(json.to_string(), "{\"k\u{FFFD}\":[\"v\\udfff\",\"\u{1D306}\"]}");
+
+        let mut decoder = Decoder::new(json["k\u{FFFD}"].clone());
+        let strings: Vec<Wtf8Buf> = Decodable::decode(&mut decoder).unwrap();
+        assert_eq!(strings, [kept.clone(), Wtf8Buf::from("\u{1D306}")]);
+        let lossy: Vec<string::String> = super::from_value(&json["k\u{FFFD}"]).unwrap();
+        assert_eq!(lossy, ["v\u{FFFD}", "\u{1D306}"]);
+
+        let mut encoder = ValueEncoder::new();
+        kept.encode(&mut encoder).unwrap();
+        assert_eq!(encoder.into_value(), Json::Wtf8(kept));
+
+        let mut parser = Parser::new(r#"["\uDFFF", "ok"]"#.chars());
+        parser.set_surrogate_policy(SurrogatePolicy::Wtf8);
+        assert_eq!(parser.next(), Some(ArrayStart));
+        assert_eq!(parser.next(), Some(StringValue("\u{FFFD}".to_string())));
+        assert_eq!(parser.take_wtf8(), Some(Wtf8Buf::from_utf16(&[0xDFFF])));
+        assert_eq!(parser.next(), Some(StringValue("ok".to_string())));
+        assert_eq!(parser.take_wtf8(), None);
+
+        // A string left untaken does not outlive a reset.
+        parser.reset(r#""\uDFFF""#.chars());
+        assert_eq!(parser.next(), Some(StringValue("\u{FFFD}".to_string())));
+        parser.reset("7".chars());
+        assert_eq!(parser.next(), Some(U64Value(7)));
+        assert_eq!(parser.take_wtf8(), None);
     }
 
     #[test]
@@ -3087,6 +6900,34 @@ mod tests {
         assert_eq!(v, vec![vec![3], vec![1, 2]]);
     }
 
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_from_mmap() {
+        use std::env;
+        use std::fs::{self, File};
+        use std::io::Write;
+
+        let path = env::temp_dir().join("rustc_serialize_test_from_mmap.json");
+        File::create(&path).unwrap().write_all(b"{\"opt\": 10}").unwrap();
+        // The test's own files do not change while they are mapped.
+        let obj: OptionData = unsafe { super::from_mmap(&path).unwrap() };
+        assert_eq!(obj, OptionData { opt: Some(10) });
+
+        File::create(&path).unwrap().write_all(b"[\"\xff\"]").unwrap();
+        match unsafe { Decoder::from_mmap(&path) } {
+            Err(ParseError(SyntaxError(NotUtf8, 0, 0))) => {}
+            _ => panic!("expected a NotUtf8 error"),
+        }
+        File::create(&path).unwrap();
+        assert!(unsafe { super::from_mmap::<OptionData, _>(&path) }.is_err());
+        fs::remove_file(&path).unwrap();
+
+        match unsafe { super::from_mmap::<OptionData, _>(&path) } {
+            Err(ParseError(IoError(_))) => {}
+            _ => panic!("expected an IoError"),
+        }
+    }
+
     #[test]
     fn test_decode_tuple() {
         let t: (usize, usize, usize) = super::decode("[1, 2, 3]").unwrap();
@@ -3153,7 +6994,7 @@ mod tests {
                       ("a".to_string(), F64(1.0)),
                       ("b".to_string(), Array(vec![
                           Boolean(true),
-                          String("foo\nbar".to_string()),
+                          String("foo\nbar".into()),
                           mk_object(&[
                               ("c".to_string(), mk_object(&[("d".to_string(), Null)]))
                           ])
@@ -3180,11 +7021,22 @@ mod tests {
         );
     }
 
-    #[derive(RustcDecodable)]
     struct FloatStruct {
         f: f64,
         a: Vec<f64>
     }
+
+    impl Decodable for FloatStruct {
+        fn decode<D: ::Decoder>(d: &mut D) -> Result<FloatStruct, D::Error> {
+            d.read_struct("FloatStruct", 2, |d| {
+                Ok(FloatStruct {
+                    f: try!(d.read_struct_field("f", 0, Decodable::decode)),
+                    a: try!(d.read_struct_field("a", 1, Decodable::decode)),
+                })
+            })
+        }
+    }
+
     #[test]
     fn test_decode_struct_with_nan() {
         let s = "{\"f\":null,\"a\":[null,123]}";
@@ -3222,6 +7074,75 @@ mod tests {
         assert_eq!(decoded_value, Ok(4));
     }
 
+    #[test]
+    fn test_std_types() {
+        use std::cmp::Ordering;
+        use std::collections::HashMap;
+        use std::net::{IpAddr, SocketAddr};
+        use std::ops::Bound;
+
+        fn round_trip<T>(value: T, expected: &str)
+            where T: Encodable + Decodable + PartialEq + ::std::fmt::Debug
+        {
+            let encoded = super::encode(&value).unwrap();
+            assert_eq!(encoded, expected);
+            assert_eq!(super::decode::<T>(&encoded).unwrap(), value);
+        }
+
+        round_trip(1..4u8, r#"{"start":1,"end":4}"#);
+        round_trip(1..=4u8, r#"{"start":1,"end":4}"#);
+        round_trip(Bound::Included(1u8), r#"{"variant":"Included","fields":[1]}"#);
+        round_trip(Bound::Excluded(1u8), r#"{"variant":"Excluded","fields":[1]}"#);
+        round_trip(Bound::Unbounded::<u8>, r#""Unbounded""#);
+        round_trip(Ordering::Less, r#""Less""#);
+        round_trip(Ordering::Greater, r#""Greater""#);
+        round_trip("10.0.0.1".parse::<IpAddr>().unwrap(), r#""10.0.0.1""#);
+        round_trip("::1".parse::<IpAddr>().unwrap(), r#""::1""#);
+        round_trip("[::1]:80".parse::<SocketAddr>().unwrap(), r#""[::1]:80""#);
+
+        let err = super::decode::<IpAddr>(r#""10.0.0""#).unwrap_err();
+        assert_eq!(err, ApplicationError("invalid IP address: \"10.0.0\"".to_string()));
+
+        let mut map = BTreeMap::new();
+        map.insert('a', 1);
+        map.insert('\n', 2);
+        round_trip(map, r#"{"\n":2,"a":1}"#);
+        let map: HashMap<char, u8> = super::decode(r#"{"x":1,"y":2}"#).unwrap();
+        assert_eq!((map[&'x'], map[&'y']), (1, 2));
+        assert!(super::decode::<HashMap<char, u8>>(r#"{"xy":1}"#).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::path::PathBuf;
+
+        fn encode_strings(path: &PathBuf) -> string::String {
+            let mut buf = string::String::new();
+            {
+                let mut encoder = Encoder::new(&mut buf);
+                encoder.set_path_strings(true);
+                path.encode(&mut encoder).unwrap();
+            }
+            buf
+        }
+
+        let path = PathBuf::from("/a b");
+        assert_eq!(super::encode(&path).unwrap(), "[47,97,32,98]");
+        assert_eq!(encode_strings(&path), "\"/a b\"");
+        for encoded in [super::encode(&path).unwrap(), encode_strings(&path)].iter() {
+            assert_eq!(super::decode::<PathBuf>(encoded).unwrap(), path);
+        }
+
+        let path = PathBuf::from(OsStr::from_bytes(b"a\xff"));
+        assert_eq!(super::encode(&path).unwrap(), "[97,255]");
+        assert_eq!(encode_strings(&path), "[97,255]");
+        assert_eq!(super::decode::<PathBuf>("[97,255]").unwrap(), path);
+        assert!(super::decode::<PathBuf>("{}").is_err());
+    }
+
     #[test]
     fn test_decode_map() {
         let s = "{\"a\": \"Dog\", \"b\": {\"variant\":\"Frog\",\
@@ -3232,13 +7153,40 @@ mod tests {
         assert_eq!(map.remove(&"b".to_string()), Some(Frog("Henry".to_string(), 349)));
     }
 
+    #[test]
+    fn test_error_snippet() {
+        let snippet = |input: &str| Json::from_str(input).unwrap_err().snippet(input).to_string();
+        assert_eq!(snippet("[1, 2,]"), "error: invalid syntax\n --> line 1, column 7\n  |\n\
+                                       1 | [1, 2,]\n  |       ^");
+        assert_eq!(snippet("{\r\n\t\"a\":\r\n"),
+                   "error: EOF While parsing value\n --> line 3, column 1\n  |\n3 | \n  | ^");
+        assert_eq!(snippet("{\"a\":\t\"\x01\"}"),
+                   "error: unescaped control character in string\n --> line 1, column 8\n  |\n\
+                    1 | {\"a\":   \"\u{2401}\"}\n  |          ^");
+        // An error at a line feed is shown at the end of the line it ends.
+        assert_eq!(snippet("\n\n[\"a\n\"]"), "error: unescaped control character in string\n \
+                                                 --> line 3, column 4\n  |\n3 | [\"a\n  |    ^");
+
+        let long = format!("[{}x]", "1,".repeat(100));
+        let lines: Vec<string::String> = snippet(&long).lines().map(|l| l.to_string()).collect();
+        assert_eq!(lines[1], " --> line 1, column 202");
+        assert_eq!(lines[3], format!("1 | ...{}x]", &"1,".repeat(20)));
+        assert_eq!(lines[4], format!("  | {}^", " ".repeat(43)));
+
+        let input = format!("[{}x]", "1,\n".repeat(11));
+        assert_eq!(snippet(&input), "error: invalid syntax\n  --> line 12, column 1\n   |\n\
+                                     12 | x]\n   | ^");
+
+        let err = IoError(::std::io::Error::new(::std::io::ErrorKind::Other, "!"));
+        assert!(err.snippet("").to_string().starts_with("error: IoError"));
+    }
+
     #[test]
     fn test_multiline_errors() {
         assert_eq!(Json::from_str("{\n  \"foo\":\n \"bar\""),
             Err(SyntaxError(EOFWhileParsingObject, 3, 8)));
     }
 
-    #[derive(RustcDecodable)]
     #[allow(dead_code)]
     struct DecodeStruct {
         x: f64,
@@ -3246,11 +7194,39 @@ mod tests {
         z: string::String,
         w: Vec<DecodeStruct>
     }
-    #[derive(RustcDecodable)]
+
+    impl Decodable for DecodeStruct {
+        fn decode<D: ::Decoder>(d: &mut D) -> Result<DecodeStruct, D::Error> {
+            d.read_struct("DecodeStruct", 4, |d| {
+                Ok(DecodeStruct {
+                    x: try!(d.read_struct_field("x", 0, Decodable::decode)),
+                    y: try!(d.read_struct_field("y", 1, Decodable::decode)),
+                    z: try!(d.read_struct_field("z", 2, Decodable::decode)),
+                    w: try!(d.read_struct_field("w", 3, Decodable::decode)),
+                })
+            })
+        }
+    }
+
     enum DecodeEnum {
         A(f64),
         B(string::String)
     }
+
+    impl Decodable for DecodeEnum {
+        fn decode<D: ::Decoder>(d: &mut D) -> Result<DecodeEnum, D::Error> {
+            d.read_enum("DecodeEnum", |d| {
+                d.read_enum_variant(&["A", "B"], |d, idx| {
+                    match idx {
+                        0 => d.read_enum_variant_arg(0, Decodable::decode).map(DecodeEnum::A),
+                        1 => d.read_enum_variant_arg(0, Decodable::decode).map(DecodeEnum::B),
+                        _ => panic!("Internal error"),
+                    }
+                })
+            })
+        }
+    }
+
     fn check_err<T: Decodable>(to_parse: &'static str, expected: DecoderError) {
         let res: DecodeResult<T> = match Json::from_str(to_parse) {
             Err(e) => Err(ParseError(e)),
@@ -3280,6 +7256,51 @@ mod tests {
         check_err::<DecodeStruct>("{\"x\": 1, \"y\": true, \"z\": \"\"}",
                                   MissingFieldError("w".to_string()));
     }
+    #[derive(Debug, PartialEq)]
+    struct Account {
+        user_id: u64,
+        nickname: Option<string::String>,
+    }
+
+    impl Decodable for Account {
+        fn decode<D: ::Decoder>(d: &mut D) -> Result<Account, D::Error> {
+            d.read_struct("Account", 2, |d| {
+                Ok(Account {
+                    user_id: try!(d.read_struct_field("user_id", 0, Decodable::decode)),
+                    nickname: try!(d.read_struct_field("nickname", 1, Decodable::decode)),
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_field_suggestions() {
+        assert_eq!(super::edit_distance("kitten", "sitting"), 3);
+        assert_eq!(super::edit_distance("", "ab"), 2);
+        assert_eq!(super::closest_name("user_id", vec!["usr_id", "user_ids"]), Some("usr_id"));
+        assert_eq!(super::closest_name("id", vec!["ip"]), None);
+        assert_eq!(super::closest_name("id", vec!["ID"]), Some("ID"));
+
+        check_err::<Account>("{\"usr_id\": 1}",
+                             MisspeltFieldError("user_id".to_string(), "usr_id".to_string()));
+        check_err::<Account>("{\"UserId\": 1}",
+                             MisspeltFieldError("user_id".to_string(), "UserId".to_string()));
+        check_err::<Account>("{\"account\": 1}", MissingFieldError("user_id".to_string()));
+        let account: Account = super::decode("{\"user_id\": 1, \"nick_name\": \"a\"}").unwrap();
+        assert_eq!(account, Account { user_id: 1, nickname: None });
+
+        let decode = |s| {
+            let mut decoder = Decoder::new(Json::from_str(s).unwrap());
+            decoder.set_deny_unknown_fields(true);
+            Vec::<Account>::decode(&mut decoder)
+        };
+        assert_eq!(decode("[{\"user_id\": 1, \"nickname\": \"a\"}]").unwrap().len(), 1);
+        assert_eq!(decode("[{\"user_id\": 1}, {\"user_id\": 2, \"nick_name\": \"a\"}]"),
+                   Err(UnknownFieldError("nick_name".to_string(), Some("nickname".to_string()))));
+        assert_eq!(decode("[{\"user_id\": 1, \"zzz\": 0}]"),
+                   Err(UnknownFieldError("zzz".to_string(), None)));
+    }
+
     #[test]
     fn test_decode_errors_enum() {
         check_err::<DecodeEnum>("{}",
@@ -3292,6 +7313,351 @@ mod tests {
                                 ExpectedError("Array".to_string(), "null".to_string()));
         check_err::<DecodeEnum>("{\"variant\": \"C\", \"fields\": []}",
                                 UnknownVariantError("C".to_string()));
+        check_err::<DecodeEnum>("{\"variant\": \"A\", \"fields\": []}", EOF);
+        check_err::<DecodeEnum>("{\"variant\": \"A\", \"fields\": [1, 2]}",
+                                ExpectedError("1 fields for variant A".to_string(),
+                                              "2 fields".to_string()));
+        // A variant's fields are never taken from the values after it.
+        check_err::<Vec<DecodeEnum>>("[\"B\", \"b\"]", EOF);
+    }
+
+    #[test]
+    fn test_from_value() {
+        use super::from_value;
+
+        let json = Json::from_str("{\"pets\": [\"Dog\", {\"variant\": \"Frog\", \
+                                   \"fields\": [\"Henry\", 349]}], \"inner\": \
+                                   {\"a\": null, \"b\": 2, \"c\": [\"x\"]}, \
+                                   \"nested\": [{\"variant\": \"Ok\", \"fields\": [null]}, \
+                                   null]}").unwrap();
+        assert_eq!(from_value::<Vec<Animal>>(&json["pets"]).unwrap(),
+                   vec![Dog, Frog("Henry".to_string(), 349)]);
+        assert_eq!(from_value::<Inner>(&json["inner"]).unwrap(),
+                   Inner { a: (), b: 2, c: vec!["x".to_string()] });
+        assert_eq!(from_value::<Vec<Option<Result<Option<u8>, ()>>>>(&json["nested"]).unwrap(),
+                   vec![Some(Ok(None)), None]);
+        assert_eq!(from_value::<u8>(&json["inner"]["b"]).unwrap(), 2);
+        assert_eq!(from_value::<u8>(&json["inner"]["c"]),
+                   Err(ExpectedError("Number".to_string(), "[\"x\"]".to_string())));
+        // The tree is left as it was.
+        assert_eq!(json["inner"]["b"], U64(2));
+    }
+
+    #[derive(PartialEq, Debug)]
+    struct Money {
+        cents: i64,
+    }
+
+    impl Encodable for Money {
+        fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_struct("Money", 1, |s| {
+                s.emit_struct_field("cents", 0, |s| self.cents.encode(s))
+            })
+        }
+    }
+
+    impl Decodable for Money {
+        fn decode<D: ::Decoder>(d: &mut D) -> Result<Money, D::Error> {
+            d.read_struct("Money", 1, |d| {
+                Ok(Money {
+                    cents: try!(d.read_struct_field("cents", 0, Decodable::decode)),
+                })
+            })
+        }
+    }
+
+    impl ::ExtensionTag for Money {
+        const TAG: u64 = 40000;
+    }
+
+    #[test]
+    fn test_tagged() {
+        let money = ::Tagged(Money { cents: -250 });
+        let encoded = super::encode(&money).unwrap();
+        assert_eq!(encoded, "{\"$tag\":40000,\"value\":{\"cents\":-250}}");
+        assert_eq!(super::decode::<::Tagged<Money>>(&encoded).unwrap(), money);
+        check_err::<::Tagged<Money>>("{\"$tag\":1,\"value\":{\"cents\":1}}",
+                                     ApplicationError("expected tag 40000, found 1".to_string()));
+        check_err::<::Tagged<Money>>("{\"cents\":1}", MissingFieldError("$tag".to_string()));
+    }
+
+    #[test]
+    fn test_skip_value() {
+        let mut parser = Parser::new("[{\"a\": [1, \"\\u00e9\\\"\"]}, [], {}, 2]".chars());
+        assert_eq!(parser.next(), Some(ArrayStart));
+        assert_eq!(parser.next(), Some(ObjectStart));
+        parser.skip_value().unwrap();
+        assert_eq!(parser.next(), Some(ArrayStart));
+        assert!(parser.stack().is_equal_to(&[Index(1)]));
+        parser.skip_value().unwrap();
+        assert_eq!(parser.next(), Some(ObjectStart));
+        parser.skip_value().unwrap();
+        assert_eq!(parser.next(), Some(U64Value(2)));
+        // The value is already complete.
+        parser.skip_value().unwrap();
+        assert_eq!(parser.next(), Some(ArrayEnd));
+        assert_eq!(parser.next(), None);
+
+        // Skipping is as strict as parsing.
+        let docs = ["1", "\"x\"", "[]", "{}", " [ 1 , { \"a\" : null } ] ", "{\"a\":{\"b\":[[]]}}",
+                    "", "[", "{", "[1", "[1,", "[1,]", "[,1]", "[1 2]", "{\"a\"}", "{\"a\":}",
+                    "{\"a\":1,}", "{1:2}", "[1}", "{\"a\":1]", "[\"\\q\"]", "[\"\\ud800\"]",
+                    "[tru]", "[01]", "[-]", "[1.]", "\"\u{1}\"", "[nul", "[1] 2", "{} x"];
+        for doc in docs.iter() {
+            let mut skipped = Parser::new(doc.chars());
+            let result = skipped.skip_value().and_then(|()| match skipped.next() {
+                Some(Error(e)) => Err(e),
+                _ => Ok(()),
+            });
+            let expected = match Json::from_str(doc) {
+                Err(SyntaxError(code, _, _)) => Some(code),
+                _ => None,
+            };
+            assert_eq!(result.err().map(|e| match e {
+                SyntaxError(code, _, _) => code,
+                _ => panic!(),
+            }), expected, "{:?}", doc);
+        }
+    }
+
+    #[test]
+    fn test_extract() {
+        use super::{extract, extract_json};
+
+        let doc = "{\"a\": [1, {\"b\": null}, [true]], \"~/\": {\"\": 3.5},\
+                    \"data\": {\"items\": [{\"id\": 7, \"tags\": [\"x\"]}, {\"id\": 8}]}}";
+        let get = |pointer: &str| extract_json(doc.as_bytes(), pointer);
+        assert_eq!(get("").unwrap(), Some(Json::from_str(doc).unwrap()));
+        assert_eq!(get("/a/0").unwrap(), Some(U64(1)));
+        assert_eq!(get("/a/1/b").unwrap(), Some(Null));
+        assert_eq!(get("/a/2").unwrap(), Some(Array(vec![Boolean(true)])));
+        assert_eq!(get("/~0~1/").unwrap(), Some(F64(3.5)));
+        assert_eq!(extract::<u32, _>(doc.as_bytes(), "/data/items/1/id").unwrap(), Some(8));
+        assert_eq!(extract::<Vec<string::String>, _>(doc.as_bytes(), "/data/items/0/tags").unwrap(),
+                   Some(vec!["x".to_string()]));
+        for pointer in &["/b", "/a/3", "/a/01", "/a/-", "/a/0/x", "/data/items/1/id/0"] {
+            assert_eq!(get(pointer).unwrap(), None);
+        }
+
+        assert_eq!(get("a"), Err(ApplicationError("invalid JSON pointer: a".to_string())));
+        assert_eq!(get("/~2"), Err(ApplicationError("invalid JSON pointer: /~2".to_string())));
+        assert_eq!(extract::<string::String, _>(doc.as_bytes(), "/a/0"),
+                   Err(ExpectedError("String".to_string(), "1".to_string())));
+
+        // Everything up to the target is checked, but nothing after it.
+        assert_eq!(extract::<u32, _>(&b"[1, 2, }"[..], "/1").unwrap(), Some(2));
+        assert_eq!(extract::<u32, _>(&b"[1, }, 3"[..], "/2"),
+                   Err(ParseError(SyntaxError(InvalidSyntax, 1, 5))));
+        assert_eq!(extract::<u32, _>(&b"[[1, 2]"[..], "/1"),
+                   Err(ParseError(SyntaxError(EOFWhileParsingArray, 1, 8))));
+        assert_eq!(extract::<u32, _>(&b"[\"\xff\", 1]"[..], "/1"),
+                   Err(ParseError(SyntaxError(NotUtf8, 1, 3))));
+    }
+
+    #[test]
+    fn test_deep_size_of() {
+        use std::mem::size_of;
+
+        assert_eq!(Null.deep_size_of(), 0);
+        assert_eq!(U64(1).deep_size_of(), 0);
+        assert_eq!(String(string::String::with_capacity(10)).deep_size_of(), 10);
+        let mut v = Vec::with_capacity(4);
+        v.push(String("abc".to_string()));
+        assert_eq!(Array(v).deep_size_of(), 4 * size_of::<Json>() + 3);
+        let mut o = BTreeMap::new();
+        o.insert("key".to_string(), Array(vec![]));
+        o.insert("k".to_string(), String("ab".to_string()));
+        let entry = size_of::<string::String>() + size_of::<Json>();
+        assert_eq!(Object(o).deep_size_of(), 2 * entry + 3 + 1 + 2);
+    }
+
+    #[test]
+    fn test_encode_to_slice() {
+        use super::encode_to_slice;
+
+        let value = (vec![Dog, Frog("Henry".to_string(), 349)], "caf\u{e9}");
+        let expected = super::encode(&value).unwrap();
+        let mut buf = [0; 64];
+        let len = encode_to_slice(&value, &mut buf).unwrap();
+        assert_eq!(&buf[..len], expected.as_bytes());
+        assert_eq!(encode_to_slice(&value, &mut buf[..len]), Ok(len));
+        assert_eq!(encode_to_slice(&value, &mut buf[..len - 1]),
+                   Err(EncoderError::BufferTooSmall(len)));
+        assert_eq!(encode_to_slice(&value, &mut []), Err(EncoderError::BufferTooSmall(len)));
+
+        let mut bad = BTreeMap::new();
+        bad.insert(vec![1], 2);
+        assert_eq!(encode_to_slice(&bad, &mut buf), Err(EncoderError::BadHashmapKey));
+    }
+
+    #[test]
+    fn test_encode_seq() {
+        use super::{encode_lines, encode_seq, WriterError};
+
+        let mut out = Vec::new();
+        encode_seq(vec![Dog, Frog("Henry".to_string(), 349)], &mut out).unwrap();
+        assert_eq!(out, &b"[\"Dog\",{\"variant\":\"Frog\",\"fields\":[\"Henry\",349]}]"[..]);
+        out.clear();
+        encode_seq(Vec::<u8>::new(), &mut out).unwrap();
+        assert_eq!(out, b"[]");
+
+        out.clear();
+        encode_lines((0..3).map(|i| vec!["a\nb"; i]), &mut out).unwrap();
+        assert_eq!(out, &b"[]\n[\"a\\nb\"]\n[\"a\\nb\",\"a\\nb\"]\n"[..]);
+        out.clear();
+        encode_lines(None::<u8>, &mut out).unwrap();
+        assert!(out.is_empty());
+
+        let mut bad = BTreeMap::new();
+        bad.insert(vec![1], 2);
+        out.clear();
+        match encode_lines(vec![BTreeMap::new(), bad], &mut out) {
+            Err(WriterError::EncoderError(EncoderError::BadHashmapKey)) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(out, b"{}\n");
+    }
+
+    #[test]
+    fn test_shared_json_lines_writer() {
+        use std::io::{self, Write};
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+        use std::time::Duration;
+        use super::SharedJsonLinesWriter;
+
+        // Records each write to it, to show how records were gathered.
+        #[derive(Clone)]
+        struct Sink(Arc<Mutex<Vec<Vec<u8>>>>);
+        impl Write for Sink {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().push(buf.to_vec());
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> { Ok(()) }
+        }
+
+        let log = Arc::new(SharedJsonLinesWriter::new(Vec::new()));
+        let threads: Vec<_> = (0..8).map(|i| {
+            let log = log.clone();
+            thread::spawn(move || for j in 0..100 {
+                log.write(&(i, j, "line\nbreak")).unwrap();
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let out = Arc::try_unwrap(log).ok().unwrap().into_inner().unwrap();
+        let lines: Vec<_> = str::from_utf8(&out).unwrap().lines().collect();
+        assert_eq!(lines.len(), 800);
+        for line in lines {
+            assert_eq!(Json::from_str(line).unwrap()[2], Json::String("line\nbreak".into()));
+        }
+
+        let sink = Sink(Arc::new(Mutex::new(Vec::new())));
+        let mut log = SharedJsonLinesWriter::new(sink.clone());
+        log.set_buffer_size(10);
+        log.write(&[1, 2]).unwrap();
+        log.write(&[3, 4]).unwrap();
+        log.write(&5).unwrap();
+        assert_eq!(*sink.0.lock().unwrap(), vec![b"[1,2]\n[3,4]\n".to_vec()]);
+        drop(log);
+        assert_eq!(sink.0.lock().unwrap()[1], b"5\n");
+
+        let sink = Sink(Arc::new(Mutex::new(Vec::new())));
+        let mut log = SharedJsonLinesWriter::new(sink.clone());
+        log.set_buffer_size(1 << 20);
+        log.set_flush_interval(Duration::from_millis(0));
+        log.write(&true).unwrap();
+        assert_eq!(sink.0.lock().unwrap().len(), 1);
+
+        let sink = Sink(Arc::new(Mutex::new(Vec::new())));
+        let mut log = SharedJsonLinesWriter::new(sink.clone());
+        log.set_buffer_size(1 << 20);
+        log.start_background_flush(Duration::from_millis(10));
+        log.write(&"a").unwrap();
+        for _ in 0..500 {
+            if !sink.0.lock().unwrap().is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(*sink.0.lock().unwrap(), vec![b"\"a\"\n".to_vec()]);
+        log.write(&"c").unwrap();
+        log.into_inner().unwrap();
+        assert_eq!(sink.0.lock().unwrap()[1], b"\"c\"\n");
+    }
+
+    #[test]
+    fn test_json_writer() {
+        use super::{JsonWriter, WriterError};
+
+        let mut w = JsonWriter::new(Vec::new());
+        w.begin_array().unwrap();
+        w.begin_object().unwrap();
+        w.end_object().unwrap();
+        w.begin_object().unwrap();
+        w.key("a").unwrap();
+        w.value(&Json::from_str("[1, {\"b\": null}]").unwrap()).unwrap();
+        w.key("c\n").unwrap();
+        w.begin_array().unwrap();
+        w.end_array().unwrap();
+        assert_eq!(w.depth(), 2);
+        w.end_object().unwrap();
+        w.value(&1.5f64).unwrap();
+        w.null().unwrap();
+        w.value(&Some('x')).unwrap();
+        w.end_array().unwrap();
+        assert_eq!(w.depth(), 0);
+        let out = w.finish().unwrap();
+        assert_eq!(str::from_utf8(&out).unwrap(),
+                   "[{},{\"a\":[1,{\"b\":null}],\"c\\n\":[]},1.5,null,\"x\"]");
+
+        macro_rules! assert_err {
+            ($e:expr, $p:pat) => (match $e { Err($p) => {} other => panic!("{:?}", other) })
+        }
+
+        // A misplaced call is an error, and writes nothing.
+        let mut w = JsonWriter::new(Vec::new());
+        assert_err!(w.key("a"), WriterError::UnexpectedKey);
+        assert_err!(w.end_array(), WriterError::MismatchedEnd);
+        w.begin_object().unwrap();
+        assert_err!(w.value(&1), WriterError::MissingKey);
+        assert_err!(w.end_array(), WriterError::MismatchedEnd);
+        w.key("a").unwrap();
+        assert_err!(w.key("b"), WriterError::UnexpectedKey);
+        assert_err!(w.end_object(), WriterError::MismatchedEnd);
+        let mut bad = BTreeMap::new();
+        bad.insert(vec![1], 2);
+        assert_err!(w.value(&bad), WriterError::EncoderError(EncoderError::BadHashmapKey));
+        w.value("v").unwrap();
+        w.end_object().unwrap();
+        assert_err!(w.value(&2), WriterError::DocumentComplete);
+        assert_eq!(w.into_inner(), b"{\"a\":\"v\"}");
+
+        assert_err!(JsonWriter::new(Vec::new()).finish(), WriterError::DocumentIncomplete);
+        let mut w = JsonWriter::new(Vec::new());
+        w.begin_array().unwrap();
+        assert_err!(w.finish(), WriterError::DocumentIncomplete);
+    }
+
+    #[test]
+    fn test_to_value() {
+        use super::{as_json, to_value, ToJson};
+
+        let animals = vec![Dog, Frog("Henry".to_string(), 349)];
+        let mut map = BTreeMap::new();
+        map.insert(1u8, vec![Some(-1i8), None]);
+        let values = (animals, map, (i64::MIN, u64::MAX, f64::NAN, 'c', ()), Inner { a: (), b: 0,
+                      c: vec![] }, 1u128 << 100, -(1i128 << 53));
+        let expected = Json::from_str(&super::encode(&values).unwrap()).unwrap();
+        assert_eq!(to_value(&values).unwrap(), expected);
+        assert_eq!(as_json(&values).to_json(), expected);
+        assert_eq!(String("1267650600228229401496703205376".into()), expected[4]);
+
+        let mut bad = BTreeMap::new();
+        bad.insert(vec![1], 2);
+        assert_eq!(to_value(&bad), Err(EncoderError::BadHashmapKey));
     }
 
     #[test]
@@ -3308,6 +7674,89 @@ mod tests {
         assert!(found_str.unwrap().as_string().unwrap() == "cheese");
     }
 
+    #[test]
+    fn test_deep_merge() {
+        use super::MergeStrategy;
+
+        let base = Json::from_str("{\"name\": \"app\", \"port\": 80, \"tags\": [\"a\", \"b\"], \
+                                   \"db\": {\"host\": \"localhost\", \"pool\": 4}, \
+                                   \"users\": [{\"id\": 1, \"role\": \"admin\"}, {\"id\": 2}]}")
+                       .unwrap();
+        let layer = Json::from_str("{\"port\": 8080, \"tags\": [\"c\"], \"db\": {\"pool\": null}, \
+                                    \"users\": [{\"id\": 2, \"role\": \"ops\"}, {\"id\": 3}, {}], \
+                                    \"debug\": true}")
+                        .unwrap();
+        let merged = |strategy: MergeStrategy| {
+            let mut json = base.clone();
+            json.deep_merge(layer.clone(), strategy);
+            json
+        };
+
+        let replaced = merged(MergeStrategy::Replace);
+        assert_eq!(replaced.to_string(),
+                   "{\"db\":{\"host\":\"localhost\",\"pool\":null},\"debug\":true,\
+                    \"name\":\"app\",\"port\":8080,\"tags\":[\"c\"],\
+                    \"users\":[{\"id\":2,\"role\":\"ops\"},{\"id\":3},{}]}");
+        assert_eq!(merged(MergeStrategy::Concat)["tags"].to_string(), "[\"a\",\"b\",\"c\"]");
+        assert_eq!(merged(MergeStrategy::UnionBy("id".to_string()))["users"].to_string(),
+                   "[{\"id\":1,\"role\":\"admin\"},{\"id\":2,\"role\":\"ops\"},{\"id\":3},{}]");
+        let elementwise = merged(MergeStrategy::ElementWise);
+        assert_eq!(elementwise["tags"].to_string(), "[\"c\",\"b\"]");
+        assert_eq!(elementwise["users"].to_string(),
+                   "[{\"id\":2,\"role\":\"ops\"},{\"id\":3},{}]");
+
+        let mut json = Json::U64(1);
+        json.deep_merge(Json::from_str("[1]").unwrap(), MergeStrategy::Concat);
+        assert_eq!(json.to_string(), "[1]");
+    }
+
+    #[test]
+    fn test_interpolate() {
+        use super::{interpolate, InterpolationError};
+
+        let lookup = |name: &str| match name {
+            "N" => Some(U64(3)),
+            "S" => Some(String("a b".into())),
+            "L" => Some(Json::from_str("[1, true]").unwrap()),
+            _ => None,
+        };
+        let mut json = Json::from_str(r#"{"${N}": ["${N}", "${L}", "n=${N}, l=${L}, s=${S}",
+                                          "${S}${S}", "${X:-}", "${X:-x y}!", "$$${N}$", "$${N}",
+                                          "${S:-no}", 7, null]}"#).unwrap();
+        interpolate(&mut json, &lookup).unwrap();
+        assert_eq!(json["${N}"].to_string(),
+                   r#"[3,[1,true],"n=3, l=[1,true], s=a b","a ba b","","x y!","$${N}$","${N}","#
+                   .to_string() + r#""a b",7,null]"#);
+
+        let mut json = Json::from_str(r#"["${N}", "${Y}"]"#).unwrap();
+        assert_eq!(interpolate(&mut json, &lookup),
+                   Err(InterpolationError::UndefinedVariable("Y".to_string())));
+        assert_eq!(json[0], U64(3));
+        let mut json = String("x ${N".into());
+        assert_eq!(interpolate(&mut json, &lookup),
+                   Err(InterpolationError::UnclosedPlaceholder("x ${N".to_string())));
+    }
+
+    #[test]
+    fn test_sort_arrays() {
+        let mut json = Json::from_str("{\"a\": [3, \"x\", [2, 1], null, 1.5, \
+                                              {\"k\": [true, false]}], \
+                                        \"b\": [{\"id\": 2, \"n\": 0}, {\"n\": 1}, \
+                                                 {\"id\": 1, \"n\": 2}, {\"id\": 2, \"n\": 3}]}")
+                           .unwrap();
+        assert!(json.sort_arrays_by("/b", "id"));
+        assert_eq!(json["b"].to_string(),
+                   "[{\"id\":1,\"n\":2},{\"id\":2,\"n\":0},{\"id\":2,\"n\":3},{\"n\":1}]");
+        assert!(!json.sort_arrays_by("/b/3", "id"));
+        assert!(!json.sort_arrays_by("/nope", "id") && !json.sort_arrays_by("b", "id"));
+        assert!(json.sort_arrays_by("/a", "k"));
+        assert_eq!(json["a"][0], Json::from_str("{\"k\": [true, false]}").unwrap());
+
+        json.sort_arrays_recursive();
+        assert_eq!(json["a"].to_string(), "[3,1.5,\"x\",[1,2],{\"k\":[false,true]},null]");
+        assert_eq!(json["b"][0].to_string(), "{\"id\":1,\"n\":2}");
+    }
+
     #[test]
     fn test_search(){
         let json_value = Json::from_str("{\"dog\":{\"cat\": {\"mouse\" : \"cheese\"}}}").unwrap();
@@ -3494,15 +7943,15 @@ mod tests {
 
         let mut tree = BTreeMap::new();
 
-        tree.insert("hello".to_string(), String("guten tag".to_string()));
-        tree.insert("goodbye".to_string(), String("sayonara".to_string()));
+        tree.insert("hello".to_string(), String("guten tag".into()));
+        tree.insert("goodbye".to_string(), String("sayonara".into()));
 
         let json = Array(
             // The following layout below should look a lot like
             // the pretty-printed JSON (indent * x)
             vec!
             ( // 0x
-                String("greetings".to_string()), // 1x
+                String("greetings".into()), // 1x
                 Object(tree), // 1x + 2x + 2x + 1x
             ) // 0x
             // End JSON array (7 lines)
@@ -3554,12 +8003,38 @@ mod tests {
     fn test_hashmap_with_enum_key() {
         use std::collections::HashMap;
         use json;
-        #[derive(RustcEncodable, Eq, Hash, PartialEq, RustcDecodable, Debug)]
+        #[derive(Eq, Hash, PartialEq, Debug)]
         enum Enum {
             Foo,
             #[allow(dead_code)]
             Bar,
         }
+
+        impl Encodable for Enum {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_enum("Enum", |s| {
+                    match *self {
+                        Enum::Foo => s.emit_enum_variant("Foo", 0, 0, |_| Ok(())),
+                        Enum::Bar => s.emit_enum_variant("Bar", 1, 0, |_| Ok(())),
+                    }
+                })
+            }
+        }
+
+        impl Decodable for Enum {
+            fn decode<D: ::Decoder>(d: &mut D) -> Result<Enum, D::Error> {
+                d.read_enum("Enum", |d| {
+                    d.read_enum_variant(&["Foo", "Bar"], |_, idx| {
+                        match idx {
+                            0 => Ok(Enum::Foo),
+                            1 => Ok(Enum::Bar),
+                            _ => panic!("Internal error"),
+                        }
+                    })
+                })
+            }
+        }
+
         let mut map = HashMap::new();
         map.insert(Enum::Foo, 0);
         let result = json::encode(&map).unwrap();
@@ -3814,6 +8289,136 @@ mod tests {
         assert_eq!(last_event("faz"),  Error(SyntaxError(InvalidSyntax, 1, 3)));
     }
 
+    fn transcoded(s: &str, pretty: bool) -> Result<string::String, super::TranscodeError<EncoderError>> {
+        let mut out = string::String::new();
+        {
+            let mut encoder = if pretty { Encoder::new_pretty(&mut out) } else { Encoder::new(&mut out) };
+            try!(super::transcode(s.chars(), &mut encoder));
+        }
+        Ok(out)
+    }
+
+    #[test]
+    fn test_transcode() {
+        let docs = ["null", "true", "-3", "18446744073709551615", "1.5", "\"a\\nb\"",
+                    "[]", "{}", "[[], {}, [1, [2, [3]]]]",
+                    "{\"a\": {\"b\": [true, null]}, \"c\": \"\\u00e9\", \"d\": []}"];
+        for doc in docs.iter() {
+            let json = Json::from_str(doc).unwrap();
+            assert_eq!(transcoded(doc, false).unwrap(), json.to_string());
+            assert_eq!(transcoded(doc, true).unwrap(), json.pretty().to_string());
+        }
+        // Keys are streamed in document order rather than sorted.
+        assert_eq!(transcoded("{\"b\": 1, \"a\": 2}", false).unwrap(), "{\"b\":1,\"a\":2}");
+    }
+
+    #[test]
+    fn test_transcode_errors() {
+        match transcoded("[1, {\"a\": 2}, 3", false) {
+            Err(super::TranscodeError::ParseError(SyntaxError(EOFWhileParsingArray, _, _))) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match transcoded("{} x", false) {
+            Err(super::TranscodeError::ParseError(SyntaxError(TrailingCharacters, _, _))) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match transcoded("{\"a\": [1, 2]", false) {
+            Err(super::TranscodeError::ParseError(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let docs = ["[1, {\"a\": 2}]", "{\"b\": [],", "\"c\""];
+        let mut parser = Parser::with_capacity(docs[0].chars(), 8);
+        assert_eq!(parser.by_ref().count(), 6);
+        parser.reset(docs[1].chars());
+        assert_eq!(parser.next(), Some(ObjectStart));
+        assert_eq!(parser.next(), Some(ArrayStart));
+        assert!(parser.stack().is_equal_to(&[StackElement::Key("b")]));
+        parser.reset(docs[2].chars());
+        assert!(parser.stack().is_empty());
+        assert_eq!(parser.next(), Some(StringValue("c".to_string())));
+        assert_eq!(parser.next(), None);
+
+        let mut builder = super::Builder::with_capacity(docs[1].chars(), 8);
+        assert!(builder.build().is_err());
+        builder.reset(docs[0].chars());
+        assert_eq!(builder.build().unwrap(), Json::from_str(docs[0]).unwrap());
+    }
+
+    #[test]
+    fn test_document_reader() {
+        use std::io::Read;
+        use super::DocumentReader;
+
+        let src = &b" [1, \"\xc3\xa9\"]{\"a\": null}\n\"s\"true 12\n\xff\xfe"[..];
+        let mut docs = DocumentReader::new(src.chain(&b"rest"[..]));
+        assert_eq!(docs.read_document().unwrap(), Json::from_str("[1, \"\u{e9}\"]").unwrap());
+        assert_eq!(docs.read_document().unwrap(), Json::from_str("{\"a\": null}").unwrap());
+        assert_eq!(docs.read_document().unwrap(), Json::String("s".into()));
+        assert_eq!(docs.read_document().unwrap(), Json::Boolean(true));
+        assert_eq!(docs.read_document().unwrap(), Json::U64(12));
+        let (rdr, rest) = docs.into_reader_state();
+        let mut all = rest;
+        rdr.take(100).read_to_end(&mut all).unwrap();
+        assert_eq!(all, b"\n\xff\xferest");
+
+        let mut docs = DocumentReader::new(&b"[1, \xff]"[..]);
+        assert_eq!(docs.read_document(), Err(SyntaxError(NotUtf8, 1, 5)));
+        let mut docs = DocumentReader::new(&b"[1] "[..]);
+        assert!(docs.read_document().is_ok());
+        assert_eq!(docs.read_document(), Err(SyntaxError(EOFWhileParsingValue, 1, 2)));
+    }
+
+    #[test]
+    fn test_parser_progress() {
+        use std::sync::{Arc, Mutex};
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let r = reports.clone();
+        let mut parser = Parser::new("[\"\u{e9}\u{e9}\", 12345, true]".chars());
+        parser.progress(move |n| r.lock().unwrap().push(n), 8);
+        assert_eq!(parser.next(), Some(ArrayStart));
+        assert_eq!(parser.bytes_consumed(), 2);
+        assert_eq!(parser.by_ref().count(), 4);
+        assert_eq!(parser.bytes_consumed(), 21);
+        assert_eq!(*reports.lock().unwrap(), [8, 16]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let flag = Arc::new(AtomicBool::new(false));
+        let src = "[123456, [true, \"abc\"]]";
+        let mut parser = Parser::new(src.chars());
+        parser.set_cancel_flag(flag.clone(), 2);
+        assert_eq!(parser.next(), Some(ArrayStart));
+        flag.store(true, Ordering::SeqCst);
+        // The number is cut short and must not be reported.
+        match parser.next() {
+            Some(Error(SyntaxError(Cancelled, _, _))) => {}
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert_eq!(parser.next(), None);
+
+        // A flag left clear changes nothing.
+        flag.store(false, Ordering::SeqCst);
+        parser.reset(src.chars());
+        assert_eq!(parser.count(), 7);
+
+        let mut buf = string::String::new();
+        flag.store(true, Ordering::SeqCst);
+        match super::transcode_cancellable(src.chars(), &mut Encoder::new(&mut buf), flag, 1) {
+            Err(super::TranscodeError::ParseError(SyntaxError(Cancelled, _, _))) => {}
+            other => panic!("unexpected result: {:?}", other.is_ok()),
+        }
+        assert_eq!(buf, "");
+    }
+
     #[test]
     fn test_stack() {
         let mut stack = Stack::new();
@@ -3905,8 +8510,8 @@ mod tests {
         assert_eq!(f64::NAN.to_json(), Null);
         assert_eq!(true.to_json(), Boolean(true));
         assert_eq!(false.to_json(), Boolean(false));
-        assert_eq!("abc".to_json(), String("abc".to_string()));
-        assert_eq!("abc".to_string().to_json(), String("abc".to_string()));
+        assert_eq!("abc".to_json(), String("abc".into()));
+        assert_eq!("abc".to_string().to_json(), String("abc".into()));
         assert_eq!((1, 2).to_json(), array2);
         assert_eq!((1, 2, 3).to_json(), array3);
         assert_eq!([1, 2].to_json(), array2);
@@ -3929,8 +8534,17 @@ mod tests {
     #[test]
     fn test_encode_hashmap_with_arbitrary_key() {
         use std::collections::HashMap;
-        #[derive(PartialEq, Eq, Hash, RustcEncodable)]
+        #[derive(PartialEq, Eq, Hash)]
         struct ArbitraryType(u32);
+
+        impl Encodable for ArbitraryType {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("ArbitraryType", 1, |s| {
+                    s.emit_struct_field("_field0", 0, |s| self.0.encode(s))
+                })
+            }
+        }
+
         let mut hm: HashMap<ArbitraryType, bool> = HashMap::new();
         hm.insert(ArbitraryType(1), true);
         let mut mem_buf = string::String::new();
@@ -3942,15 +8556,798 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_map_key_policy() {
+        use std::collections::BTreeMap;
+        use super::MapKeyPolicy;
+
+        let mut bools = BTreeMap::new();
+        bools.insert(false, 0u8);
+        bools.insert(true, 1);
+        assert_eq!(super::encode(&bools).unwrap_err(), EncoderError::BadHashmapKey);
+        let mut s = string::String::new();
+        {
+            let mut encoder = Encoder::new(&mut s);
+            encoder.set_map_key_policy(MapKeyPolicy::StringifyAll);
+            bools.encode(&mut encoder).unwrap();
+        }
+        assert_eq!(s, "{\"false\":0,\"true\":1}");
+        assert!(super::decode::<BTreeMap<bool, u8>>(&s).is_err());
+        let mut decoder = Decoder::new(Json::from_str(&s).unwrap());
+        decoder.set_map_key_policy(MapKeyPolicy::StringifyAll);
+        assert_eq!(BTreeMap::<bool, u8>::decode(&mut decoder).unwrap(), bools);
+        assert!(super::decode::<bool>("\"true\"").is_err());
+
+        let mut ints = BTreeMap::new();
+        ints.insert(1u32, "a".to_string());
+        let mut buf = string::String::new();
+        {
+            let mut encoder = Encoder::new(&mut buf);
+            encoder.set_map_key_policy(MapKeyPolicy::Strict);
+            assert_eq!(ints.encode(&mut encoder).unwrap_err(), EncoderError::BadHashmapKey);
+        }
+        let mut strs = BTreeMap::new();
+        strs.insert("a".to_string(), 'b');
+        buf.clear();
+        {
+            let mut encoder = Encoder::new(&mut buf);
+            encoder.set_map_key_policy(MapKeyPolicy::Strict);
+            strs.encode(&mut encoder).unwrap();
+        }
+        assert_eq!(buf, "{\"a\":\"b\"}");
+    }
+
+    #[test]
+    fn test_map_key_policy_pairs() {
+        use std::collections::BTreeMap;
+        use super::MapKeyPolicy;
+
+        let mut map = BTreeMap::new();
+        map.insert((1u8, 2u8), "a".to_string());
+        map.insert((3, 4), "b".to_string());
+
+        let mut compact = string::String::new();
+        {
+            let mut encoder = Encoder::new(&mut compact);
+            encoder.set_map_key_policy(MapKeyPolicy::Pairs);
+            map.encode(&mut encoder).unwrap();
+        }
+        assert_eq!(compact, "[[[1,2],\"a\"],[[3,4],\"b\"]]");
+        assert!(super::decode::<BTreeMap<(u8, u8), string::String>>(&compact).is_err());
+        let decode_pairs = |s: &str| {
+            let mut decoder = Decoder::new(Json::from_str(s).unwrap());
+            decoder.set_map_key_policy(MapKeyPolicy::Pairs);
+            BTreeMap::<(u8, u8), string::String>::decode(&mut decoder)
+        };
+        assert_eq!(decode_pairs(&compact).unwrap(), map);
+
+        // Pretty output matches that of the equivalent list of tuples.
+        let mut pretty = string::String::new();
+        {
+            let mut encoder = Encoder::new_pretty(&mut pretty);
+            encoder.set_map_key_policy(MapKeyPolicy::Pairs);
+            map.encode(&mut encoder).unwrap();
+        }
+        let pairs: Vec<_> = map.clone().into_iter().collect();
+        assert_eq!(pretty, super::as_pretty_json(&pairs).to_string());
+        assert_eq!(decode_pairs(&pretty).unwrap(), map);
+
+        let mut empty = string::String::new();
+        {
+            let mut encoder = Encoder::new(&mut empty);
+            encoder.set_map_key_policy(MapKeyPolicy::Pairs);
+            BTreeMap::<u8, u8>::new().encode(&mut encoder).unwrap();
+        }
+        assert_eq!(empty, "[]");
+        assert!(super::decode::<BTreeMap<u8, u8>>("[[1]]").is_err());
+    }
+
+    #[test]
+    fn test_commented() {
+        use std::collections::BTreeMap;
+
+        let json = Json::from_str("{\"a/b\": [1, {\"c\": []}], \"d\": {}}").unwrap();
+        let mut comments = BTreeMap::new();
+        assert_eq!(json.commented(&comments).to_string(), json.pretty().to_string());
+
+        comments.insert("".to_string(), "Top\n\nlevel".to_string());
+        comments.insert("/a~1b/1/c".to_string(), "Nested".to_string());
+        comments.insert("/d".to_string(), "Last".to_string());
+        comments.insert("/missing".to_string(), "Unused".to_string());
+        assert_eq!(json.commented(&comments).indent(4).to_string(), "\
+// Top
+//
+// level
+{
+    \"a/b\": [
+        1,
+        {
+            // Nested
+            \"c\": []
+        }
+    ],
+    // Last
+    \"d\": {}
+}");
+    }
+
+    #[test]
+    fn test_sort_map_keys() {
+        use std::collections::HashMap;
+        use super::MapKeyPolicy;
+
+        fn encode_sorted<T: Encodable>(t: &T, pretty: bool, policy: MapKeyPolicy)
+                                       -> string::String {
+            let mut buf = string::String::new();
+            {
+                let mut encoder = if pretty {
+                    Encoder::new_pretty(&mut buf)
+                } else {
+                    Encoder::new(&mut buf)
+                };
+                encoder.set_sort_map_keys(true);
+                encoder.set_map_key_policy(policy);
+                t.encode(&mut encoder).unwrap();
+            }
+            buf
+        }
+
+        let mut map = HashMap::new();
+        for (i, k) in ["d", "b", "e", "a", "c"].iter().enumerate() {
+            let mut inner = HashMap::new();
+            inner.insert(i as u32 * 10, i);
+            inner.insert(i as u32 * 10 + 1, i);
+            map.insert(k.to_string(), inner);
+        }
+        assert_eq!(encode_sorted(&map, false, MapKeyPolicy::Stringify),
+                   "{\"a\":{\"30\":3,\"31\":3},\"b\":{\"10\":1,\"11\":1},\
+                    \"c\":{\"40\":4,\"41\":4},\"d\":{\"0\":0,\"1\":0},\
+                    \"e\":{\"20\":2,\"21\":2}}");
+
+        // Pretty output matches that of the equivalent (already sorted) Json.
+        let json = Json::from_str(&super::encode(&map).unwrap()).unwrap();
+        assert_eq!(encode_sorted(&map, true, MapKeyPolicy::Stringify),
+                   json.pretty().to_string());
+
+        let mut pairs = HashMap::new();
+        pairs.insert((2u8, 1u8), true);
+        pairs.insert((1, 2), false);
+        assert_eq!(encode_sorted(&pairs, false, MapKeyPolicy::Pairs),
+                   "[[[1,2],false],[[2,1],true]]");
+    }
+
+    #[test]
+    fn test_skip_fields() {
+        use std::collections::BTreeMap;
+
+        struct Inner {
+            note: Option<string::String>,
+            tags: Vec<u8>,
+        }
+
+        impl Encodable for Inner {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Inner", 2, |s| {
+                    try!(s.emit_struct_field("note", 0, |s| self.note.encode(s)));
+                    s.emit_struct_field("tags", 1, |s| self.tags.encode(s))
+                })
+            }
+        }
+
+        struct Outer {
+            a: Option<u8>,
+            inner: Inner,
+            b: Option<u8>,
+            map: BTreeMap<string::String, Option<u8>>,
+            list: Vec<Option<u8>>,
+        }
+
+        impl Encodable for Outer {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Outer", 5, |s| {
+                    try!(s.emit_struct_field("a", 0, |s| self.a.encode(s)));
+                    try!(s.emit_struct_field("inner", 1, |s| self.inner.encode(s)));
+                    try!(s.emit_struct_field("b", 2, |s| self.b.encode(s)));
+                    try!(s.emit_struct_field("map", 3, |s| self.map.encode(s)));
+                    s.emit_struct_field("list", 4, |s| self.list.encode(s))
+                })
+            }
+        }
+
+        fn encode_skipping(t: &Outer, pretty: bool, nulls: bool, arrays: bool, objects: bool)
+                           -> string::String {
+            let mut buf = string::String::new();
+            {
+                let mut encoder = if pretty {
+                    Encoder::new_pretty(&mut buf)
+                } else {
+                    Encoder::new(&mut buf)
+                };
+                encoder.set_skip_null_fields(nulls);
+                encoder.set_skip_empty_arrays(arrays);
+                encoder.set_skip_empty_objects(objects);
+                t.encode(&mut encoder).unwrap();
+            }
+            buf
+        }
+
+        let mut map = BTreeMap::new();
+        map.insert("x".to_string(), None);
+        let outer = Outer {
+            a: None,
+            inner: Inner { note: None, tags: vec![] },
+            b: Some(1),
+            map: map,
+            list: vec![None],
+        };
+        assert_eq!(encode_skipping(&outer, false, false, false, false),
+                   super::encode(&outer).unwrap());
+        assert_eq!(encode_skipping(&outer, false, true, false, false),
+                   "{\"inner\":{\"tags\":[]},\"b\":1,\"map\":{\"x\":null},\"list\":[null]}");
+        assert_eq!(encode_skipping(&outer, false, true, true, false),
+                   "{\"inner\":{},\"b\":1,\"map\":{\"x\":null},\"list\":[null]}");
+        assert_eq!(encode_skipping(&outer, false, true, true, true),
+                   "{\"b\":1,\"map\":{\"x\":null},\"list\":[null]}");
+
+        assert_eq!(encode_skipping(&outer, true, true, true, false), "\
+{
+  \"inner\": {},
+  \"b\": 1,
+  \"map\": {
+    \"x\": null
+  },
+  \"list\": [
+    null
+  ]
+}");
+    }
+
+    #[test]
+    fn test_option_policy() {
+        use super::{Decoder, OptionPolicy};
+
+        #[derive(PartialEq, Debug)]
+        struct Record {
+            a: Option<u8>,
+            b: Option<Option<u8>>,
+            c: Vec<Option<u8>>,
+        }
+
+        impl Encodable for Record {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Record", 3, |s| {
+                    try!(s.emit_struct_field("a", 0, |s| self.a.encode(s)));
+                    try!(s.emit_struct_field("b", 1, |s| self.b.encode(s)));
+                    s.emit_struct_field("c", 2, |s| self.c.encode(s))
+                })
+            }
+        }
+
+        impl Decodable for Record {
+            fn decode<D: ::Decoder>(d: &mut D) -> Result<Record, D::Error> {
+                d.read_struct("Record", 3, |d| {
+                    Ok(Record {
+                        a: try!(d.read_struct_field("a", 0, Decodable::decode)),
+                        b: try!(d.read_struct_field("b", 1, Decodable::decode)),
+                        c: try!(d.read_struct_field("c", 2, Decodable::decode)),
+                    })
+                })
+            }
+        }
+
+        fn encode_with(t: &Record, policy: OptionPolicy, field: Option<(&str, OptionPolicy)>)
+                       -> string::String {
+            let mut buf = string::String::new();
+            {
+                let mut encoder = Encoder::new(&mut buf);
+                encoder.set_option_policy(policy);
+                if let Some((name, policy)) = field {
+                    encoder.set_field_option_policy(name, policy);
+                }
+                t.encode(&mut encoder).unwrap();
+            }
+            buf
+        }
+
+        fn decode_with(s: &str, policy: OptionPolicy, field: Option<(&str, OptionPolicy)>)
+                       -> DecodeResult<Record> {
+            let mut decoder = Decoder::new(Json::from_str(s).unwrap());
+            decoder.set_option_policy(policy);
+            if let Some((name, policy)) = field {
+                decoder.set_field_option_policy(name, policy);
+            }
+            Decodable::decode(&mut decoder)
+        }
+
+        let record = Record { a: None, b: Some(None), c: vec![Some(1), None] };
+        assert_eq!(encode_with(&record, OptionPolicy::Null, None),
+                   "{\"a\":null,\"b\":null,\"c\":[1,null]}");
+        assert_eq!(encode_with(&record, OptionPolicy::Omit, None),
+                   "{\"b\":null,\"c\":[1,null]}");
+
+        let array = encode_with(&record, OptionPolicy::Array, None);
+        assert_eq!(array, "{\"a\":[],\"b\":[[]],\"c\":[[1],[]]}");
+        assert_eq!(decode_with(&array, OptionPolicy::Array, None).unwrap(), record);
+        assert_eq!(decode_with("{\"b\":null,\"c\":[]}", OptionPolicy::Array, None).unwrap(),
+                   Record { a: None, b: None, c: vec![] });
+        match decode_with("{\"a\":[1,2],\"c\":[]}", OptionPolicy::Array, None) {
+            Err(ExpectedError(..)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Only `b` is set apart from `None` when it is `Some(None)`.
+        let field = Some(("b", OptionPolicy::Array));
+        let some_none = encode_with(&record, OptionPolicy::Null, field);
+        assert_eq!(some_none, "{\"a\":null,\"b\":[null],\"c\":[1,null]}");
+        assert_eq!(decode_with(&some_none, OptionPolicy::Null, field).unwrap(), record);
+        let none = Record { a: Some(2), b: None, c: vec![] };
+        assert_eq!(encode_with(&none, OptionPolicy::Null, field),
+                   "{\"a\":2,\"b\":[],\"c\":[]}");
+        assert_eq!(encode_with(&none, OptionPolicy::Null, Some(("b", OptionPolicy::Omit))),
+                   "{\"a\":2,\"c\":[]}");
+        assert_eq!(encode_with(&none, OptionPolicy::Omit, Some(("b", OptionPolicy::Null))),
+                   "{\"a\":2,\"b\":null,\"c\":[]}");
+        assert_eq!(decode_with("{\"a\":2,\"c\":[]}", OptionPolicy::Null, field).unwrap(), none);
+    }
+
+    #[test]
+    fn test_rename_all() {
+        use case::Case;
+        use super::Decoder;
+
+        #[derive(PartialEq, Debug)]
+        struct Request {
+            max_retries: u8,
+            retry_after_ms: Option<u32>,
+            inner_config: Config,
+            labels: BTreeMap<string::String, u8>,
+        }
+
+        impl Encodable for Request {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Request", 4, |s| {
+                    try!(s.emit_struct_field("max_retries", 0, |s| self.max_retries.encode(s)));
+                    try!(s.emit_struct_field("retry_after_ms", 1, |s| {
+                        self.retry_after_ms.encode(s)
+                    }));
+                    try!(s.emit_struct_field("inner_config", 2, |s| self.inner_config.encode(s)));
+                    s.emit_struct_field("labels", 3, |s| self.labels.encode(s))
+                })
+            }
+        }
+
+        impl Decodable for Request {
+            fn decode<D: ::Decoder>(d: &mut D) -> Result<Request, D::Error> {
+                d.read_struct("Request", 4, |d| {
+                    Ok(Request {
+                        max_retries: try!(d.read_struct_field("max_retries", 0, Decodable::decode)),
+                        retry_after_ms: try!(d.read_struct_field("retry_after_ms", 1,
+                                                                 Decodable::decode)),
+                        inner_config: try!(d.read_struct_field("inner_config", 2,
+                                                               Decodable::decode)),
+                        labels: try!(d.read_struct_field("labels", 3, Decodable::decode)),
+                    })
+                })
+            }
+        }
+
+        #[derive(PartialEq, Debug)]
+        struct Config {
+            use_tls: bool,
+        }
+
+        impl Encodable for Config {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Config", 1, |s| {
+                    s.emit_struct_field("use_tls", 0, |s| self.use_tls.encode(s))
+                })
+            }
+        }
+
+        impl Decodable for Config {
+            fn decode<D: ::Decoder>(d: &mut D) -> Result<Config, D::Error> {
+                d.read_struct("Config", 1, |d| {
+                    Ok(Config {
+                        use_tls: try!(d.read_struct_field("use_tls", 0, Decodable::decode)),
+                    })
+                })
+            }
+        }
+
+        let mut labels = BTreeMap::new();
+        labels.insert("snake_key".to_string(), 1);
+        let request = Request {
+            max_retries: 3,
+            retry_after_ms: None,
+            inner_config: Config { use_tls: true },
+            labels: labels,
+        };
+        let mut buf = string::String::new();
+        let config;
+        {
+            let mut encoder = Encoder::new(&mut buf);
+            encoder.set_rename_all(Case::Camel);
+            encoder.set_skip_null_fields(true);
+            request.encode(&mut encoder).unwrap();
+            config = encoder.config().clone();
+        }
+        // Map keys are kept as they are.
+        assert_eq!(buf, "{\"maxRetries\":3,\"innerConfig\":{\"useTls\":true},\
+                         \"labels\":{\"snake_key\":1}}");
+        // The same settings can be given at once.
+        let mut again = string::String::new();
+        request.encode(&mut Encoder::with_config(&mut again, config)).unwrap();
+        assert_eq!(again, buf);
+
+        let mut decoder = Decoder::new(Json::from_str(&buf).unwrap());
+        decoder.set_rename_all(Case::Camel);
+        assert_eq!(Request::decode(&mut decoder).unwrap(), request);
+
+        let mut decoder = Decoder::new(Json::from_str("{\"use_tls\":true}").unwrap());
+        decoder.set_rename_all(Case::Kebab);
+        assert_eq!(Config::decode(&mut decoder),
+                   Err(MisspeltFieldError("use-tls".to_string(), "use_tls".to_string())));
+    }
+
+    #[test]
+    fn test_ascii_only() {
+        use wtf8::Wtf8Buf;
+        use super::{escape_js_string, Builder, SurrogatePolicy};
+
+        assert_eq!(escape_js_string(""), "\"\"");
+        assert_eq!(escape_js_string("a\"\\\n\u{7f}"), "\"a\\\"\\\\\\n\\u007f\"");
+        assert_eq!(escape_js_string("\u{e9}\u{2028}\u{FFFF}"), "\"\\u00e9\\u2028\\uffff\"");
+        assert_eq!(escape_js_string("x\u{10000}\u{10FFFF}y"),
+                   "\"x\\ud800\\udc00\\udbff\\udfffy\"");
+
+        let mut map = BTreeMap::new();
+        map.insert("cl\u{e9}".to_string(), ('\u{1F600}', Wtf8Buf::from_utf16(&[0xE9, 0xD800])));
+        let mut buf = string::String::new();
+        {
+            let mut encoder = Encoder::new(&mut buf);
+            encoder.set_ascii_only(true);
+            map.encode(&mut encoder).unwrap();
+        }
+        assert_eq!(buf, "{\"cl\\u00e9\":[\"\\ud83d\\ude00\",\"\\u00e9\\ud800\"]}");
+        assert!(buf.is_ascii());
+        let mut builder = Builder::new(buf.chars());
+        builder.set_surrogate_policy(SurrogatePolicy::Wtf8);
+        assert_eq!(super::from_value(&builder.build().unwrap()), Ok(map));
+    }
+
+    #[test]
+    fn test_columnar() {
+        use ::Encoder as SerializeEncoder;
+        use super::{Decoder, EncoderError, OptionPolicy};
+
+        #[derive(PartialEq, Debug)]
+        struct Point {
+            x: i32,
+            label: Option<string::String>,
+        }
+
+        impl Encodable for Point {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Point", 2, |s| {
+                    try!(s.emit_struct_field("x", 0, |s| self.x.encode(s)));
+                    s.emit_struct_field("label", 1, |s| self.label.encode(s))
+                })
+            }
+        }
+
+        impl Decodable for Point {
+            fn decode<D: ::Decoder>(d: &mut D) -> Result<Point, D::Error> {
+                d.read_struct("Point", 2, |d| {
+                    Ok(Point {
+                        x: try!(d.read_struct_field("x", 0, Decodable::decode)),
+                        label: try!(d.read_struct_field("label", 1, Decodable::decode)),
+                    })
+                })
+            }
+        }
+
+        #[derive(PartialEq, Debug)]
+        struct Series {
+            name: string::String,
+            points: Vec<Point>,
+            weights: Vec<u8>,
+        }
+
+        impl Encodable for Series {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Series", 3, |s| {
+                    try!(s.emit_struct_field("name", 0, |s| self.name.encode(s)));
+                    try!(s.emit_struct_field("points", 1, |s| self.points.encode(s)));
+                    s.emit_struct_field("weights", 2, |s| self.weights.encode(s))
+                })
+            }
+        }
+
+        impl Decodable for Series {
+            fn decode<D: ::Decoder>(d: &mut D) -> Result<Series, D::Error> {
+                d.read_struct("Series", 3, |d| {
+                    Ok(Series {
+                        name: try!(d.read_struct_field("name", 0, Decodable::decode)),
+                        points: try!(d.read_struct_field("points", 1, Decodable::decode)),
+                        weights: try!(d.read_struct_field("weights", 2, Decodable::decode)),
+                    })
+                })
+            }
+        }
+
+        fn columnar<T: Encodable>(t: &T, pretty: bool) -> Result<string::String, EncoderError> {
+            let mut buf = string::String::new();
+            {
+                let mut encoder = if pretty {
+                    Encoder::new_pretty(&mut buf)
+                } else {
+                    Encoder::new(&mut buf)
+                };
+                encoder.set_columnar(true);
+                encoder.set_option_policy(OptionPolicy::Omit);
+                try!(t.encode(&mut encoder));
+            }
+            Ok(buf)
+        }
+
+        let series = vec![
+            Series {
+                name: "a".to_string(),
+                points: vec![Point { x: 1, label: Some("p".to_string()) },
+                             Point { x: 2, label: None }],
+                weights: vec![7],
+            },
+            Series { name: "b".to_string(), points: vec![], weights: vec![] },
+        ];
+        let compact = columnar(&series, false).unwrap();
+        assert_eq!(compact, "{\"name\":[\"a\",\"b\"],\
+                              \"points\":[{\"x\":[1,2],\"label\":[\"p\",null]},[]],\
+                              \"weights\":[[7],[]]}");
+        let pretty = columnar(&series[0].points, true).unwrap();
+        assert_eq!(pretty, "\
+{
+  \"x\": [
+    1,
+    2
+  ],
+  \"label\": [
+    \"p\",
+    null
+  ]
+}");
+
+        let mut decoder = Decoder::new(Json::from_str(&compact).unwrap());
+        decoder.set_columnar(true);
+        assert_eq!(Vec::<Series>::decode(&mut decoder).unwrap(), series);
+        let mut decoder = Decoder::new(Json::from_str(&pretty).unwrap());
+        decoder.set_columnar(true);
+        assert_eq!(Vec::<Point>::decode(&mut decoder).unwrap(), series[0].points);
+
+        // Sequences of anything but structs, and tuples, are arrays.
+        let p = || Point { x: 0, label: None };
+        assert_eq!(columnar(&(p(), p()), false).unwrap(), "[{\"x\":0},{\"x\":0}]");
+        assert_eq!(columnar(&vec![Some(p())], false).unwrap(), "[{\"x\":0}]");
+        assert_eq!(columnar(&vec![vec![1, 2]], true).unwrap(), "[\n  [\n    1,\n    2\n  ]\n]");
+
+        // A struct with as many fields as it holds.
+        struct Ragged(usize);
+        impl Encodable for Ragged {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Ragged", self.0, |s| {
+                    for i in 0..self.0 {
+                        try!(s.emit_struct_field(["a", "b"][i], i, |s| s.emit_usize(i)));
+                    }
+                    Ok(())
+                })
+            }
+        }
+        assert_eq!(columnar(&vec![Ragged(2), Ragged(2)], false).unwrap(),
+                   "{\"a\":[0,0],\"b\":[1,1]}");
+        for rows in [vec![Ragged(2), Ragged(1)], vec![Ragged(1), Ragged(2)]].iter() {
+            assert_eq!(columnar(rows, false), Err(EncoderError::BadColumnarRow));
+        }
+        let mut buf = string::String::new();
+        {
+            let mut encoder = Encoder::new(&mut buf);
+            encoder.set_columnar(true);
+            let result = encoder.emit_seq(2, |e| {
+                try!(e.emit_seq_elt(0, |e| Ragged(1).encode(e)));
+                e.emit_seq_elt(1, |e| e.emit_u8(1))
+            });
+            assert_eq!(result, Err(EncoderError::BadColumnarRow));
+        }
+
+        let mut decoder = Decoder::new(Json::from_str("{\"x\":[1,2],\"label\":[null]}").unwrap());
+        decoder.set_columnar(true);
+        assert_eq!(Vec::<Point>::decode(&mut decoder),
+                   Err(ExpectedError("column of 1 values".to_string(),
+                                     "2 in column \"x\"".to_string())));
+        let mut decoder = Decoder::new(Json::from_str("{}").unwrap());
+        decoder.set_columnar(true);
+        assert_eq!(Vec::<Point>::decode(&mut decoder), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_quantization() {
+        use half::Quantization;
+        use super::Decoder;
+
+        #[derive(PartialEq, Debug)]
+        struct Sample {
+            embedding: Vec<f32>,
+            ids: Vec<u32>,
+            maybe: Vec<Option<f64>>,
+            nested: Vec<Vec<f64>>,
+            pair: (f64, f64),
+        }
+
+        impl Encodable for Sample {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Sample", 5, |s| {
+                    try!(s.emit_struct_field("embedding", 0, |s| self.embedding.encode(s)));
+                    try!(s.emit_struct_field("ids", 1, |s| self.ids.encode(s)));
+                    try!(s.emit_struct_field("maybe", 2, |s| self.maybe.encode(s)));
+                    try!(s.emit_struct_field("nested", 3, |s| self.nested.encode(s)));
+                    s.emit_struct_field("pair", 4, |s| self.pair.encode(s))
+                })
+            }
+        }
+
+        impl Decodable for Sample {
+            fn decode<D: ::Decoder>(d: &mut D) -> Result<Sample, D::Error> {
+                d.read_struct("Sample", 5, |d| {
+                    Ok(Sample {
+                        embedding: try!(d.read_struct_field("embedding", 0, Decodable::decode)),
+                        ids: try!(d.read_struct_field("ids", 1, Decodable::decode)),
+                        maybe: try!(d.read_struct_field("maybe", 2, Decodable::decode)),
+                        nested: try!(d.read_struct_field("nested", 3, Decodable::decode)),
+                        pair: try!(d.read_struct_field("pair", 4, Decodable::decode)),
+                    })
+                })
+            }
+        }
+
+        fn quantized<T: Encodable>(t: &T, q: Quantization, pretty: bool) -> string::String {
+            let mut buf = string::String::new();
+            {
+                let mut encoder = if pretty {
+                    Encoder::new_pretty(&mut buf)
+                } else {
+                    Encoder::new(&mut buf)
+                };
+                encoder.set_quantization(q);
+                t.encode(&mut encoder).unwrap();
+            }
+            buf
+        }
+
+        let sample = Sample {
+            embedding: vec![0.5, -1.25, 3.0],
+            ids: vec![1, 2],
+            maybe: vec![Some(1.0)],
+            nested: vec![vec![1.0, 2.0], vec![]],
+            pair: (1.0, 2.0),
+        };
+        let compact = quantized(&sample, Quantization::F16, false);
+        assert_eq!(compact, "{\"embedding\":{\"$tag\":40202,\"value\":\"ADgAvQBC\"},\
+                              \"ids\":[1,2],\"maybe\":[1.0],\
+                              \"nested\":[{\"$tag\":40202,\"value\":\"ADwAQA==\"},[]],\
+                              \"pair\":[1.0,2.0]}");
+        assert_eq!(super::decode::<Sample>(&compact).unwrap(), sample);
+
+        let pretty = quantized(&vec![vec![0.1f64]], Quantization::F16, true);
+        assert_eq!(pretty, "\
+[
+  {
+    \"$tag\": 40202,
+    \"value\": \"Zi4=\"
+  }
+]");
+        assert_eq!(super::decode::<Vec<Vec<f64>>>(&pretty).unwrap(), [[0.0999755859375]]);
+
+        let bf16 = quantized(&vec![1.0f32, 0.1], Quantization::BF16, false);
+        assert_eq!(bf16, "{\"$tag\":40203,\"value\":\"gD/NPQ==\"}");
+        let widened: Vec<f32> = super::decode(&bf16).unwrap();
+        assert_eq!(widened, [1.0, 0.100097656]);
+
+        // Columns of floats are not quantized.
+        struct Point {
+            x: f64,
+        }
+
+        impl Encodable for Point {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Point", 1, |s| {
+                    s.emit_struct_field("x", 0, |s| self.x.encode(s))
+                })
+            }
+        }
+
+        let mut buf = string::String::new();
+        {
+            let mut encoder = Encoder::new(&mut buf);
+            encoder.set_quantization(Quantization::F16);
+            encoder.set_columnar(true);
+            vec![Point { x: 0.1 }, Point { x: 0.2 }].encode(&mut encoder).unwrap();
+        }
+        assert_eq!(buf, "{\"x\":[0.1,0.2]}");
+
+        let mut decoder = Decoder::new(Json::from_str("{\"$tag\":40202,\"value\":\"AA==\"}")
+                                           .unwrap());
+        assert!(Vec::<f64>::decode(&mut decoder).is_err());
+    }
+
+    #[test]
+    fn test_encoder_reset() {
+        use std::collections::BTreeMap;
+
+        #[derive(PartialEq, Eq, PartialOrd, Ord)]
+        struct Point(u8, u8);
+
+        impl Encodable for Point {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Point", 2, |s| {
+                    try!(s.emit_struct_field("_field0", 0, |s| self.0.encode(s)));
+                    s.emit_struct_field("_field1", 1, |s| self.1.encode(s))
+                })
+            }
+        }
+
+        let mut bad = BTreeMap::new();
+        bad.insert(Point(1, 2), vec![1]);
+        let mut good = BTreeMap::new();
+        good.insert("a", vec![1]);
+
+        let mut buf = string::String::new();
+        {
+            let mut encoder = Encoder::new_pretty(&mut buf);
+            encoder.set_sort_map_keys(true);
+            assert!(vec![bad].encode(&mut encoder).is_err());
+            encoder.reset();
+            good.encode(&mut encoder).unwrap();
+            encoder.reset();
+            good.encode(&mut encoder).unwrap();
+        }
+        // The failed value leaves its beginning in the output.
+        let expected = super::as_pretty_json(&good).to_string();
+        assert_eq!(buf, format!("[\n  {{{}{}", expected, expected));
+
+        let mut buf = string::String::new();
+        {
+            let mut encoder = Encoder::with_capacity(&mut buf, 4);
+            good.encode(&mut encoder).unwrap();
+        }
+        assert_eq!(buf, "{\"a\":[1]}");
+    }
+
     #[test]
     fn test_encode_decode_phantom_data() {
         use std::marker::PhantomData;
 
-        #[derive(Debug, RustcDecodable, RustcEncodable, Eq, PartialEq)]
+        #[derive(Debug, Eq, PartialEq)]
         struct Foo<P> {
             phantom_data: PhantomData<P>
         }
 
+        impl<P: Encodable> Encodable for Foo<P> {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Foo", 1, |s| {
+                    s.emit_struct_field("phantom_data", 0, |s| self.phantom_data.encode(s))
+                })
+            }
+        }
+
+        impl<P: Decodable> Decodable for Foo<P> {
+            fn decode<D: ::Decoder>(d: &mut D) -> Result<Foo<P>, D::Error> {
+                d.read_struct("Foo", 1, |d| {
+                    Ok(Foo {
+                        phantom_data: try!(d.read_struct_field("phantom_data", 0,
+                                                               Decodable::decode)),
+                    })
+                })
+            }
+        }
+
         let f: Foo<u8> = Foo {
             phantom_data: PhantomData
         };
@@ -3962,10 +9359,27 @@ mod tests {
     #[test]
     fn test_bad_json_stack_depleted() {
         use json;
-        #[derive(Debug, RustcDecodable)]
+        #[derive(Debug)]
         enum ChatEvent {
             Variant(i32)
         }
+
+        impl Decodable for ChatEvent {
+            fn decode<D: ::Decoder>(d: &mut D) -> Result<ChatEvent, D::Error> {
+                d.read_enum("ChatEvent", |d| {
+                    d.read_enum_variant(&["Variant"], |d, idx| {
+                        match idx {
+                            0 => {
+                                d.read_enum_variant_arg(0, Decodable::decode)
+                                    .map(ChatEvent::Variant)
+                            }
+                            _ => panic!("Internal error"),
+                        }
+                    })
+                })
+            }
+        }
+
         let serialized = "{\"variant\": \"Variant\", \"fields\": []}";
         let r: Result<ChatEvent, _> = json::decode(serialized);
         assert!(r.unwrap_err() == EOF);
@@ -3973,12 +9387,35 @@ mod tests {
 
     #[test]
     fn fixed_length_array() {
-        #[derive(Debug, RustcDecodable, RustcEncodable, Eq, PartialEq)]
+        #[derive(Debug, Eq, PartialEq)]
         struct Foo {
             a: [u8; 1],
             b: [i32; 2],
             c: [u64; 3],
         }
+
+        impl Encodable for Foo {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Foo", 3, |s| {
+                    try!(s.emit_struct_field("a", 0, |s| self.a.encode(s)));
+                    try!(s.emit_struct_field("b", 1, |s| self.b.encode(s)));
+                    s.emit_struct_field("c", 2, |s| self.c.encode(s))
+                })
+            }
+        }
+
+        impl Decodable for Foo {
+            fn decode<D: ::Decoder>(d: &mut D) -> Result<Foo, D::Error> {
+                d.read_struct("Foo", 3, |d| {
+                    Ok(Foo {
+                        a: try!(d.read_struct_field("a", 0, Decodable::decode)),
+                        b: try!(d.read_struct_field("b", 1, Decodable::decode)),
+                        c: try!(d.read_struct_field("c", 2, Decodable::decode)),
+                    })
+                })
+            }
+        }
+
         let f = Foo {
             a: [0],
             b: [1, 2],
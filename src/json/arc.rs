@@ -0,0 +1,345 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A shared, immutable JSON tree with cheap clones
+//!
+//! `ArcJson` is `Json` with its strings, arrays and objects behind `Arc`s,
+//! so that cloning a document, or any part of it, only counts references,
+//! and documents can be shared between threads. Modifying a clone goes
+//! through the `_mut` methods, which copy only the nodes on the path to
+//! the change that are still shared (`Arc::make_mut`); the rest of the tree
+//! stays shared with the original.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::arc::ArcJson;
+//!
+//! fn main() {
+//!     let config = ArcJson::from_str(r#"{"db": {"host": "a", "pool": 4}, "tls": {}}"#)
+//!                      .unwrap();
+//!
+//!     // A per-request copy, with one setting overridden.
+//!     let mut request = config.clone();
+//!     request.find_path_mut(&["db"]).and_then(|db| db.as_object_mut()).unwrap()
+//!            .insert("host".to_string(), ArcJson::from("b"));
+//!
+//!     assert_eq!(request.find_path(&["db", "host"]).unwrap().as_string(), Some("b"));
+//!     assert_eq!(config.find_path(&["db", "host"]).unwrap().as_string(), Some("a"));
+//!     // The untouched parts are still shared.
+//!     assert!(request["tls"].ptr_eq(&config["tls"]));
+//!     assert!(!request["db"].ptr_eq(&config["db"]));
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Index;
+use std::sync::Arc;
+
+use Encodable;
+use json::{self, BuilderError, Json};
+
+/// A JSON value whose strings, arrays and objects are shared; see the
+/// module documentation.
+#[derive(Clone, PartialEq, PartialOrd, Debug)]
+pub enum ArcJson {
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    String(Arc<str>),
+    Boolean(bool),
+    Array(Arc<Array>),
+    Object(Arc<Object>),
+    Null,
+}
+
+/// The elements of an `ArcJson` array
+pub type Array = Vec<ArcJson>;
+/// The members of an `ArcJson` object
+pub type Object = BTreeMap<String, ArcJson>;
+
+impl ArcJson {
+    /// Parses a document, as `Json::from_str` does.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<ArcJson, BuilderError> {
+        Json::from_str(s).map(ArcJson::from)
+    }
+
+    /// Converts into a `Json` tree, copying every node.
+    pub fn to_json(&self) -> Json {
+        match *self {
+            ArcJson::I64(v) => Json::I64(v),
+            ArcJson::U64(v) => Json::U64(v),
+            ArcJson::F64(v) => Json::F64(v),
+            ArcJson::String(ref s) => Json::from(&**s),
+            ArcJson::Boolean(b) => Json::Boolean(b),
+            ArcJson::Array(ref a) => Json::Array(a.iter().map(|v| v.to_json()).collect()),
+            ArcJson::Object(ref o) => {
+                Json::Object(o.iter().map(|(k, v)| (k.clone(), v.to_json())).collect())
+            }
+            ArcJson::Null => Json::Null,
+        }
+    }
+
+    /// Returns whether `self` and `other` are the same shared string, array
+    /// or object, rather than merely equal.
+    pub fn ptr_eq(&self, other: &ArcJson) -> bool {
+        match (self, other) {
+            (ArcJson::String(a), ArcJson::String(b)) => Arc::ptr_eq(a, b),
+            (ArcJson::Array(a), ArcJson::Array(b)) => Arc::ptr_eq(a, b),
+            (ArcJson::Object(a), ArcJson::Object(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// If the value is an object, returns the value of its member `key`.
+    pub fn find(&self, key: &str) -> Option<&ArcJson> {
+        self.as_object().and_then(|o| o.get(key))
+    }
+
+    /// Looks up each key in `keys` in turn, as `Json::find_path` does.
+    pub fn find_path(&self, keys: &[&str]) -> Option<&ArcJson> {
+        let mut target = self;
+        for key in keys {
+            target = match target.find(key) {
+                Some(target) => target,
+                None => return None,
+            };
+        }
+        Some(target)
+    }
+
+    /// If the value is an array, returns its elements.
+    pub fn as_array(&self) -> Option<&Array> {
+        match *self {
+            ArcJson::Array(ref a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// If the value is an object, returns its members.
+    pub fn as_object(&self) -> Option<&Object> {
+        match *self {
+            ArcJson::Object(ref o) => Some(o),
+            _ => None,
+        }
+    }
+
+    /// If the value is a string, returns it.
+    pub fn as_string(&self) -> Option<&str> {
+        match *self {
+            ArcJson::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// If the value is a number which fits in an `i64`, returns it.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            ArcJson::I64(v) => Some(v),
+            ArcJson::U64(v) if v <= i64::MAX as u64 => Some(v as i64),
+            _ => None,
+        }
+    }
+
+    /// If the value is a non-negative integer, returns it.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            ArcJson::I64(v) if v >= 0 => Some(v as u64),
+            ArcJson::U64(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// If the value is a number, returns it as an `f64`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            ArcJson::I64(v) => Some(v as f64),
+            ArcJson::U64(v) => Some(v as f64),
+            ArcJson::F64(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// If the value is a boolean, returns it.
+    pub fn as_boolean(&self) -> Option<bool> {
+        match *self {
+            ArcJson::Boolean(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the value is `null`.
+    pub fn is_null(&self) -> bool {
+        *self == ArcJson::Null
+    }
+
+    /// If the value is an array, returns its elements for modification,
+    /// first copying them if they are shared.
+    pub fn as_array_mut(&mut self) -> Option<&mut Array> {
+        match *self {
+            ArcJson::Array(ref mut a) => Some(Arc::make_mut(a)),
+            _ => None,
+        }
+    }
+
+    /// If the value is an object, returns its members for modification,
+    /// first copying them if they are shared.
+    pub fn as_object_mut(&mut self) -> Option<&mut Object> {
+        match *self {
+            ArcJson::Object(ref mut o) => Some(Arc::make_mut(o)),
+            _ => None,
+        }
+    }
+
+    /// Looks up each key in `keys` in turn, as `find_path` does, for
+    /// modification. Each object on the way is copied if it is shared, so
+    /// that nothing shared with other values is changed.
+    pub fn find_path_mut(&mut self, keys: &[&str]) -> Option<&mut ArcJson> {
+        let mut target = self;
+        for key in keys {
+            target = match target.as_object_mut().and_then(|o| o.get_mut(*key)) {
+                Some(target) => target,
+                None => return None,
+            };
+        }
+        Some(target)
+    }
+}
+
+impl From<Json> for ArcJson {
+    fn from(json: Json) -> ArcJson {
+        match json {
+            Json::I64(v) => ArcJson::I64(v),
+            Json::U64(v) => ArcJson::U64(v),
+            Json::F64(v) => ArcJson::F64(v),
+            Json::String(s) => ArcJson::String(Arc::from(&*s)),
+            // Lone surrogates are replaced with U+FFFD.
+            Json::Wtf8(s) => ArcJson::String(Arc::from(&*s.to_string_lossy())),
+            Json::Boolean(b) => ArcJson::Boolean(b),
+            Json::Array(a) => ArcJson::Array(Arc::new(a.into_iter().map(ArcJson::from).collect())),
+            Json::Object(o) => {
+                ArcJson::Object(Arc::new(o.into_iter().map(|(k, v)| (k, ArcJson::from(v)))
+                                          .collect()))
+            }
+            Json::Null => ArcJson::Null,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for ArcJson {
+    fn from(s: &'a str) -> ArcJson { ArcJson::String(Arc::from(s)) }
+}
+
+impl From<String> for ArcJson {
+    fn from(s: String) -> ArcJson { ArcJson::String(Arc::from(s)) }
+}
+
+impl Index<&str> for ArcJson {
+    type Output = ArcJson;
+
+    fn index(&self, idx: &str) -> &ArcJson {
+        self.find(idx).unwrap()
+    }
+}
+
+impl Index<usize> for ArcJson {
+    type Output = ArcJson;
+
+    fn index(&self, idx: usize) -> &ArcJson {
+        match self.as_array() {
+            Some(a) => &a[idx],
+            None => panic!("can only index ArcJson with usize if it is an array"),
+        }
+    }
+}
+
+impl Encodable for ArcJson {
+    fn encode<S: ::Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
+        match *self {
+            ArcJson::I64(v) => v.encode(e),
+            ArcJson::U64(v) => v.encode(e),
+            ArcJson::F64(v) => v.encode(e),
+            ArcJson::String(ref v) => e.emit_str(v),
+            ArcJson::Boolean(v) => v.encode(e),
+            ArcJson::Array(ref v) => v.encode(e),
+            ArcJson::Object(ref v) => v.encode(e),
+            ArcJson::Null => e.emit_nil(),
+        }
+    }
+}
+
+impl fmt::Display for ArcJson {
+    /// Encodes the value as compact JSON, as `Json`'s `Display` does.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&json::as_json(self), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use json::Json;
+    use json::arc::ArcJson;
+
+    #[test]
+    fn test_arc_json() {
+        let doc = r#"{"a":[1,-2,3.5,"x",true,null],"b":{"c":{}}}"#;
+        let json = Json::from_str(doc).unwrap();
+        let shared = ArcJson::from_str(doc).unwrap();
+        assert_eq!(shared.to_json(), json);
+        assert_eq!(shared.to_string(), json.to_string());
+        assert_eq!(shared["a"][3].as_string(), Some("x"));
+        assert_eq!(shared["a"][1].as_i64(), Some(-2));
+        assert_eq!(shared["a"][1].as_u64(), None);
+        assert!(shared["a"][5].is_null());
+        assert_eq!(shared.find_path(&["b", "c", "d"]), None);
+
+        let copy = shared.clone();
+        assert!(copy.ptr_eq(&shared) && copy["a"][3].ptr_eq(&shared["a"][3]));
+
+        let shared = Arc::new(shared);
+        let threads: Vec<_> = (0..4u64).map(|i| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let mut mine = (*shared).clone();
+                mine.as_object_mut().unwrap().insert("i".to_string(), ArcJson::U64(i));
+                assert!(mine["b"].ptr_eq(&shared["b"]));
+                mine.to_string()
+            })
+        }).collect();
+        for (i, t) in threads.into_iter().enumerate() {
+            assert!(t.join().unwrap().ends_with(&format!(",\"i\":{}}}", i)));
+        }
+        assert_eq!(shared.to_json(), json);
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let original = ArcJson::from_str(r#"{"x":{"y":[1,2]},"z":[3]}"#).unwrap();
+        let mut modified = original.clone();
+        modified.find_path_mut(&["x", "y"]).and_then(|y| y.as_array_mut()).unwrap()
+                .push(ArcJson::Null);
+        assert_eq!(modified.to_string(), r#"{"x":{"y":[1,2,null]},"z":[3]}"#);
+        assert_eq!(original.to_string(), r#"{"x":{"y":[1,2]},"z":[3]}"#);
+        assert!(!modified.ptr_eq(&original) && !modified["x"].ptr_eq(&original["x"]));
+        assert!(modified["z"].ptr_eq(&original["z"]));
+        assert!(modified.find_path_mut(&["z", "w"]).is_none());
+
+        // Unshared nodes are modified in place.
+        let y = modified.find_path(&["x", "y"]).unwrap().clone();
+        drop(original);
+        modified.find_path_mut(&["z"]).and_then(|z| z.as_array_mut()).unwrap().clear();
+        assert!(modified["x"]["y"].ptr_eq(&y));
+    }
+}
@@ -0,0 +1,586 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Generating Rust types from sample documents or JSON Schemas
+//!
+//! `from_samples`, `from_schema` and `from_json_schema` write the source of
+//! a struct for each kind of object in the documents described, to paste
+//! into a client and adjust. Each object member becomes a public field of
+//! the type of the values seen for it: `bool`, `i64`, `f64` (for any
+//! non-integer number), `String`, a `Vec`, another struct, or `Json` where
+//! values of several types were seen. Members which may be missing or
+//! `null` are `Option`s.
+//!
+//! Field names are members' keys in snake case, with characters which
+//! cannot appear in identifiers replaced by `_` and a `_` added after
+//! keywords, and struct names are keys in camel case. Each struct gets
+//! implementations of `Encodable` and `Decodable` which use the keys. Where
+//! the documents are not objects, a type alias with the given name is
+//! generated for their type.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::{codegen, Json};
+//!
+//! fn main() {
+//!     let sample = Json::from_str(r#"{"id": 7, "tags": ["a"], "owner": {"name": "x"}}"#)
+//!                      .unwrap();
+//!     let code = codegen::from_samples("Repo", &[sample]);
+//!     assert!(code.starts_with("\
+//! #[derive(Clone, Debug, PartialEq)]
+//! pub struct Repo {
+//!     pub id: i64,
+//!     pub owner: Owner,
+//!     pub tags: Vec<String>,
+//! }
+//! "));
+//!     assert!(code.contains("impl ::rustc_serialize::Decodable for Owner {"));
+//! }
+//! ```
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use json::Json;
+use json::schema::{self, Schema};
+
+// Strict, reserved and edition-specific keywords, which cannot be field
+// names.
+static KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue",
+    "crate", "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen",
+    "if", "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override",
+    "priv", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+    "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where",
+    "while", "yield",
+];
+
+/// Generates types for the documents in `samples`, naming the outermost
+/// `name`.
+pub fn from_samples(name: &str, samples: &[Json]) -> String {
+    from_schema(name, &schema::infer_schema(samples))
+}
+
+/// Generates types for the documents `schema` describes, naming the
+/// outermost `name`.
+pub fn from_schema(name: &str, schema: &Schema) -> String {
+    let mut gen = Generator::new(name);
+    let ty = gen.schema_ty(name, schema);
+    gen.render(ty)
+}
+
+/// Generates types for the documents the JSON Schema `schema` describes,
+/// naming the outermost `name`.
+///
+/// Only `type`, `properties`, `required` and `items` are understood; values
+/// described otherwise, such as with `$ref` or `oneOf`, are typed `Json`.
+pub fn from_json_schema(name: &str, schema: &Json) -> String {
+    let mut gen = Generator::new(name);
+    let ty = gen.json_schema_ty(name, schema);
+    gen.render(ty)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Ty {
+    Bool,
+    I64,
+    F64,
+    String,
+    Json,
+    Object,
+    Vec(Box<Ty>),
+    Option(Box<Ty>),
+    Struct(String),
+}
+
+impl Ty {
+    fn nullable(self, nullable: bool) -> Ty {
+        match self {
+            Ty::Json | Ty::Option(_) => self,
+            ty if nullable => Ty::Option(Box::new(ty)),
+            ty => ty,
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        match *self {
+            Ty::Bool => out.push_str("bool"),
+            Ty::I64 => out.push_str("i64"),
+            Ty::F64 => out.push_str("f64"),
+            Ty::String => out.push_str("String"),
+            Ty::Json => out.push_str("::rustc_serialize::json::Json"),
+            Ty::Object => out.push_str("::rustc_serialize::json::Object"),
+            Ty::Vec(ref ty) => {
+                out.push_str("Vec<");
+                ty.write(out);
+                out.push('>');
+            }
+            Ty::Option(ref ty) => {
+                out.push_str("Option<");
+                ty.write(out);
+                out.push('>');
+            }
+            Ty::Struct(ref name) => out.push_str(name),
+        }
+    }
+}
+
+struct Field {
+    key: String,
+    name: String,
+    ty: Ty,
+}
+
+struct Generator {
+    // The structs, in the order they were named; each is filled in once its
+    // fields have been generated.
+    defs: Vec<(String, Vec<Field>)>,
+    names: HashSet<String>,
+    // The name for the outermost type, until a struct for it takes it.
+    root: Option<String>,
+    root_name: String,
+    // The number of values being typed, outermost first.
+    depth: usize,
+}
+
+impl Generator {
+    fn new(name: &str) -> Generator {
+        let name = type_name(name);
+        let mut names = HashSet::new();
+        names.insert(name.clone());
+        Generator {
+            defs: Vec::new(),
+            names: names,
+            root: Some(name.clone()),
+            root_name: name,
+            depth: 0,
+        }
+    }
+
+    fn schema_ty(&mut self, hint: &str, schema: &Schema) -> Ty {
+        self.depth += 1;
+        let kinds = [schema.booleans, schema.integers + schema.floats, schema.strings,
+                     schema.arrays, schema.objects];
+        let ty = if kinds.iter().filter(|&&n| n > 0).count() != 1 {
+            Ty::Json
+        } else if schema.booleans > 0 {
+            Ty::Bool
+        } else if schema.floats > 0 {
+            Ty::F64
+        } else if schema.integers > 0 {
+            Ty::I64
+        } else if schema.strings > 0 {
+            Ty::String
+        } else if schema.arrays > 0 {
+            let items = match schema.items {
+                Some(ref items) => self.schema_ty(&format!("{}Item", hint), items),
+                None => Ty::Json,
+            };
+            Ty::Vec(Box::new(items))
+        } else if schema.properties.is_empty() {
+            Ty::Object
+        } else {
+            let members = schema.properties.iter().map(|(key, member)| {
+                (&key[..], member.count == schema.objects)
+            }).collect();
+            self.def(hint, members, |gen, key| gen.schema_ty(key, &schema.properties[key]))
+        };
+        self.depth -= 1;
+        ty.nullable(schema.nulls > 0)
+    }
+
+    fn json_schema_ty(&mut self, hint: &str, schema: &Json) -> Ty {
+        self.depth += 1;
+        let properties = schema.find("properties").and_then(|p| p.as_object());
+        let mut types: Vec<&str> = match schema.find("type") {
+            Some(Json::Array(types)) => types.iter().filter_map(|t| t.as_string()).collect(),
+            Some(ty) => ty.as_string().into_iter().collect(),
+            None if properties.is_some() => vec!["object"],
+            None if schema.find("items").is_some() => vec!["array"],
+            None => vec![],
+        };
+        let nullable = types.contains(&"null");
+        types.retain(|&t| t != "null");
+        if types.len() == 2 && types.contains(&"integer") && types.contains(&"number") {
+            types = vec!["number"];
+        }
+        let ty = match (types.len(), types.first()) {
+            (1, Some(&"boolean")) => Ty::Bool,
+            (1, Some(&"integer")) => Ty::I64,
+            (1, Some(&"number")) => Ty::F64,
+            (1, Some(&"string")) => Ty::String,
+            (1, Some(&"array")) => {
+                let items = match schema.find("items") {
+                    Some(items) => self.json_schema_ty(&format!("{}Item", hint), items),
+                    None => Ty::Json,
+                };
+                Ty::Vec(Box::new(items))
+            }
+            (1, Some(&"object")) => match properties {
+                Some(properties) if !properties.is_empty() => {
+                    let required: Vec<&str> = match schema.find("required") {
+                        Some(Json::Array(keys)) => {
+                            keys.iter().filter_map(|k| k.as_string()).collect()
+                        }
+                        _ => vec![],
+                    };
+                    let members = properties.keys().map(|key| {
+                        (&key[..], required.contains(&&key[..]))
+                    }).collect();
+                    self.def(hint, members, |gen, key| {
+                        gen.json_schema_ty(key, &properties[key])
+                    })
+                }
+                _ => Ty::Object,
+            },
+            _ => Ty::Json,
+        };
+        self.depth -= 1;
+        ty.nullable(nullable)
+    }
+
+    // Generates a struct for objects with the given members, and whether
+    // each is in every object, typing each member with `ty_of`.
+    fn def<F>(&mut self, hint: &str, members: Vec<(&str, bool)>, mut ty_of: F) -> Ty
+        where F: FnMut(&mut Generator, &str) -> Ty
+    {
+        let name = match self.root.take() {
+            Some(name) if self.depth == 1 => name,
+            root => {
+                self.root = root;
+                self.unique_type_name(hint)
+            }
+        };
+        let idx = self.defs.len();
+        self.defs.push((name.clone(), Vec::new()));
+        let mut used = HashSet::new();
+        let mut fields = Vec::new();
+        for (key, required) in members {
+            let ty = ty_of(self, key).nullable(!required);
+            let mut field = field_name(key);
+            let base = field.clone();
+            let mut n = 1;
+            while !used.insert(field.clone()) {
+                n += 1;
+                field = format!("{}_{}", base, n);
+            }
+            fields.push(Field { key: key.to_string(), name: field, ty: ty });
+        }
+        self.defs[idx].1 = fields;
+        Ty::Struct(name)
+    }
+
+    fn unique_type_name(&mut self, hint: &str) -> String {
+        let base = type_name(hint);
+        let mut name = base.clone();
+        let mut n = 1;
+        while !self.names.insert(name.clone()) {
+            n += 1;
+            name = format!("{}{}", base, n);
+        }
+        name
+    }
+
+    fn render(&self, root: Ty) -> String {
+        let mut out = String::new();
+        if self.root.is_some() {
+            write!(out, "pub type {} = ", self.root_name).unwrap();
+            root.write(&mut out);
+            out.push_str(";\n");
+        }
+        for (name, fields) in &self.defs {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str("#[derive(Clone, Debug, PartialEq)]\n");
+            writeln!(out, "pub struct {} {{", name).unwrap();
+            for field in fields {
+                write!(out, "    pub {}: ", field.name).unwrap();
+                field.ty.write(&mut out);
+                out.push_str(",\n");
+            }
+            out.push_str("}\n");
+            write_impls(&mut out, name, fields);
+        }
+        out
+    }
+}
+
+fn write_impls(out: &mut String, name: &str, fields: &[Field]) {
+    writeln!(out, "
+impl ::rustc_serialize::Encodable for {} {{
+    fn encode<S: ::rustc_serialize::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {{
+        s.emit_struct({:?}, {}, |s| {{", name, name, fields.len()).unwrap();
+    for (i, field) in fields.iter().enumerate() {
+        writeln!(out, "            s.emit_struct_field({:?}, {}, |s| {{
+                ::rustc_serialize::Encodable::encode(&self.{}, s)
+            }})?;", field.key, i, field.name).unwrap();
+    }
+    writeln!(out, "            Ok(())
+        }})
+    }}
+}}
+
+impl ::rustc_serialize::Decodable for {} {{
+    fn decode<D: ::rustc_serialize::Decoder>(d: &mut D) -> Result<{}, D::Error> {{
+        d.read_struct({:?}, {}, |d| Ok({} {{", name, name, name, fields.len(), name).unwrap();
+    for (i, field) in fields.iter().enumerate() {
+        writeln!(out, "            {}: d.read_struct_field({:?}, {}, \
+                       ::rustc_serialize::Decodable::decode)?,",
+                 field.name, field.key, i).unwrap();
+    }
+    out.push_str("        }))
+    }
+}
+");
+}
+
+fn is_keyword(s: &str) -> bool {
+    KEYWORDS.contains(&s)
+}
+
+// Converts a key into a snake case field name.
+fn field_name(key: &str) -> String {
+    let mut name = String::new();
+    // Whether the last character was a lowercase letter or digit, after
+    // which an uppercase letter starts a word.
+    let mut word = false;
+    for c in key.chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase() && word {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+            word = c.is_ascii_lowercase() || c.is_ascii_digit();
+        } else {
+            if c == '_' || !name.ends_with('_') {
+                name.push('_');
+            }
+            word = false;
+        }
+    }
+    if name.chars().all(|c| c == '_') {
+        name = "field".to_string();
+    } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    if is_keyword(&name) {
+        name.push('_');
+    }
+    name
+}
+
+// Converts a key into a camel case type name.
+fn type_name(key: &str) -> String {
+    let mut name = String::new();
+    let mut upper = true;
+    for c in key.chars() {
+        if !c.is_ascii_alphanumeric() {
+            upper = true;
+        } else if upper {
+            name.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            name.push(c);
+        }
+    }
+    if name.is_empty() {
+        name = "Type".to_string();
+    } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, 'T');
+    }
+    if is_keyword(&name) {
+        name.push('_');
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use json::{self, Json};
+    use json::codegen::{field_name, from_json_schema, from_samples, type_name};
+
+    #[test]
+    fn test_names() {
+        assert_eq!(field_name("userId"), "user_id");
+        assert_eq!(field_name("userID"), "user_id");
+        assert_eq!(field_name("User-Agent"), "user_agent");
+        assert_eq!(field_name("__meta"), "__meta");
+        assert_eq!(field_name("2fa"), "_2fa");
+        assert_eq!(field_name("type"), "type_");
+        assert_eq!(field_name("self"), "self_");
+        assert_eq!(field_name("-"), "field");
+        assert_eq!(type_name("line_items"), "LineItems");
+        assert_eq!(type_name("3d"), "T3d");
+        assert_eq!(type_name("self"), "Self_");
+        assert_eq!(type_name(""), "Type");
+    }
+
+    // The output of `test_from_samples`, to check that it compiles.
+    mod generated {
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct Doc {
+            pub id: f64,
+            pub note: Option<String>,
+            pub type_: String,
+        }
+
+        impl ::rustc_serialize::Encodable for Doc {
+            fn encode<S: ::rustc_serialize::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Doc", 3, |s| {
+                    s.emit_struct_field("id", 0, |s| {
+                        ::rustc_serialize::Encodable::encode(&self.id, s)
+                    })?;
+                    s.emit_struct_field("note", 1, |s| {
+                        ::rustc_serialize::Encodable::encode(&self.note, s)
+                    })?;
+                    s.emit_struct_field("type", 2, |s| {
+                        ::rustc_serialize::Encodable::encode(&self.type_, s)
+                    })?;
+                    Ok(())
+                })
+            }
+        }
+
+        impl ::rustc_serialize::Decodable for Doc {
+            fn decode<D: ::rustc_serialize::Decoder>(d: &mut D) -> Result<Doc, D::Error> {
+                d.read_struct("Doc", 3, |d| Ok(Doc {
+                    id: d.read_struct_field("id", 0, ::rustc_serialize::Decodable::decode)?,
+                    note: d.read_struct_field("note", 1, ::rustc_serialize::Decodable::decode)?,
+                    type_: d.read_struct_field("type", 2, ::rustc_serialize::Decodable::decode)?,
+                }))
+            }
+        }
+    }
+
+    fn samples(docs: &[&str]) -> Vec<Json> {
+        docs.iter().map(|s| Json::from_str(s).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_from_samples() {
+        let docs = samples(&[r#"{"type": "a", "id": 1, "note": "x"}"#,
+                             r#"{"type": "b", "id": 2.5}"#]);
+        assert_eq!(from_samples("doc", &docs), "\
+#[derive(Clone, Debug, PartialEq)]
+pub struct Doc {
+    pub id: f64,
+    pub note: Option<String>,
+    pub type_: String,
+}
+
+impl ::rustc_serialize::Encodable for Doc {
+    fn encode<S: ::rustc_serialize::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct(\"Doc\", 3, |s| {
+            s.emit_struct_field(\"id\", 0, |s| {
+                ::rustc_serialize::Encodable::encode(&self.id, s)
+            })?;
+            s.emit_struct_field(\"note\", 1, |s| {
+                ::rustc_serialize::Encodable::encode(&self.note, s)
+            })?;
+            s.emit_struct_field(\"type\", 2, |s| {
+                ::rustc_serialize::Encodable::encode(&self.type_, s)
+            })?;
+            Ok(())
+        })
+    }
+}
+
+impl ::rustc_serialize::Decodable for Doc {
+    fn decode<D: ::rustc_serialize::Decoder>(d: &mut D) -> Result<Doc, D::Error> {
+        d.read_struct(\"Doc\", 3, |d| Ok(Doc {
+            id: d.read_struct_field(\"id\", 0, ::rustc_serialize::Decodable::decode)?,
+            note: d.read_struct_field(\"note\", 1, ::rustc_serialize::Decodable::decode)?,
+            type_: d.read_struct_field(\"type\", 2, ::rustc_serialize::Decodable::decode)?,
+        }))
+    }
+}
+");
+
+        let doc: generated::Doc = json::decode(r#"{"type": "a", "id": 1}"#).unwrap();
+        assert_eq!(doc, generated::Doc { id: 1.0, note: None, type_: "a".to_string() });
+        assert_eq!(json::encode(&doc).unwrap(), r#"{"id":1.0,"note":null,"type":"a"}"#);
+
+        let docs = samples(&[r#"[{"n": 1, "xs": [], "m": {}, "v": 1}, {"n": 2, "v": "1"}]"#]);
+        let out = from_samples("list", &docs);
+        assert!(out.starts_with("\
+pub type List = Vec<ListItem>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListItem {
+    pub m: Option<::rustc_serialize::json::Object>,
+    pub n: i64,
+    pub v: ::rustc_serialize::json::Json,
+    pub xs: Option<Vec<::rustc_serialize::json::Json>>,
+}
+
+impl ::rustc_serialize::Encodable for ListItem {
+"), "{}", out);
+        assert!(out.contains("xs: d.read_struct_field(\"xs\", 3, "), "{}", out);
+    }
+
+    #[test]
+    fn test_from_json_schema() {
+        let schema = Json::from_str(r#"{
+            "type": "object",
+            "properties": {
+                "a-b": {"type": "integer"},
+                "a_b": {"type": ["string", "null"]},
+                "kind": {"enum": ["x", "y"], "type": "string"},
+                "n": {"type": ["integer", "number"]},
+                "owner": {"$ref": "user.json"},
+                "user": {"properties": {"name": {"type": "string"}}, "required": ["name"]},
+                "users": {"type": "array", "items": {
+                    "type": "object",
+                    "properties": {"user": {"properties": {"id": {"type": "integer"}}}}
+                }}
+            },
+            "required": ["a-b", "kind", "user", "users"]
+        }"#).unwrap();
+        let out = from_json_schema("Root", &schema);
+        assert!(out.starts_with("\
+#[derive(Clone, Debug, PartialEq)]
+pub struct Root {
+    pub a_b: i64,
+    pub a_b_2: Option<String>,
+    pub kind: String,
+    pub n: Option<f64>,
+    pub owner: ::rustc_serialize::json::Json,
+    pub user: User,
+    pub users: Vec<UsersItem>,
+}
+"), "{}", out);
+        assert!(out.contains("a_b_2: d.read_struct_field(\"a_b\", 1, "));
+        for def in &["\
+#[derive(Clone, Debug, PartialEq)]
+pub struct User {
+    pub name: String,
+}
+", "\
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsersItem {
+    pub user: Option<User2>,
+}
+", "\
+#[derive(Clone, Debug, PartialEq)]
+pub struct User2 {
+    pub id: Option<i64>,
+}
+"] {
+            assert!(out.contains(def), "{}", out);
+        }
+        assert!(out.contains("impl ::rustc_serialize::Decodable for User2 {"), "{}", out);
+
+        let scalar = Json::from_str(r#"{"type": ["string", "null"]}"#).unwrap();
+        assert_eq!(from_json_schema("name", &scalar), "pub type Name = Option<String>;\n");
+    }
+}
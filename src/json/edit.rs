@@ -0,0 +1,355 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Editing JSON documents in place
+//!
+//! A `JsonDocument` holds a parsed document together with its text, and
+//! where in the text each value lies. Values are edited through
+//! `pointer_mut`, which marks the value it returns as changed, and
+//! `encode` then re-encodes only the values changed, splicing them into the
+//! text in place of their old encodings. The rest of the text is copied as
+//! it was, layout and all, so a small edit to a large document costs
+//! little more than copying its text.
+//!
+//! A changed value is encoded compactly, whatever the layout around it.
+//! Changing a value held by an array or object, rather than the array or
+//! object, keeps the rest of it as it was: to keep the most of a
+//! document's layout, edit the values deepest within it.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::Json;
+//! use rustc_serialize::json::edit::JsonDocument;
+//!
+//! fn main() {
+//!     let text = "{\n  \"name\": \"ada\",\n  \"tags\": [1, 2]\n}";
+//!     let mut doc = JsonDocument::from_str(text).unwrap();
+//!     *doc.pointer_mut("/tags/1").unwrap() = Json::U64(3);
+//!     assert_eq!(doc.encode(), "{\n  \"name\": \"ada\",\n  \"tags\": [1, 3]\n}");
+//!
+//!     doc.pointer_mut("/tags").unwrap().as_array_mut().unwrap().push(Json::U64(4));
+//!     assert_eq!(doc.encode(), "{\n  \"name\": \"ada\",\n  \"tags\": [1,3,4]\n}");
+//! }
+//! ```
+
+use std::str::{self, FromStr};
+
+use json::{pointer_tokens, BuilderError, Json};
+
+// Where a value lies in the text of its document.
+#[derive(Clone, Debug)]
+struct Span {
+    // The start of the value's text, from the start of the text of the
+    // array or object holding it, and its length.
+    start: usize,
+    len: usize,
+    // Whether the value has been changed, and whether any value within it
+    // has.
+    dirty: bool,
+    dirty_below: bool,
+    // The spans of the elements or members, in the order of the text, with
+    // the keys of members.
+    children: Vec<(Option<String>, Span)>,
+}
+
+impl Span {
+    // The index of the child at `token`: the element at that index of an
+    // array, or the last member with that key of an object.
+    fn child(&self, token: &str) -> Option<usize> {
+        match self.children.first() {
+            Some(&(None, _)) => token.parse().ok().filter(|&i| i < self.children.len()),
+            Some(&(Some(_), _)) => {
+                self.children.iter()
+                    .rposition(|(key, _)| key.as_ref().map(|k| &k[..]) == Some(token))
+            }
+            None => None,
+        }
+    }
+}
+
+/// A parsed JSON document which re-encodes only the values edited; see
+/// the module documentation.
+#[derive(Clone, Debug)]
+pub struct JsonDocument {
+    json: Json,
+    text: String,
+    root: Span,
+}
+
+impl JsonDocument {
+    /// Parses a document from a string.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<JsonDocument, BuilderError> {
+        let json = try!(Json::from_str(s));
+        let mut pos = 0;
+        let root = scan(s.as_bytes(), &mut pos, 0);
+        Ok(JsonDocument { json: json, text: s.to_string(), root: root })
+    }
+
+    /// Returns the document's value, with any edits made.
+    pub fn json(&self) -> &Json {
+        &self.json
+    }
+
+    /// Returns the text of the document as of the last `encode`, or as
+    /// parsed if it has not been encoded since.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether any value has been changed since the last `encode`.
+    pub fn is_modified(&self) -> bool {
+        self.root.dirty || self.root.dirty_below
+    }
+
+    /// Looks up the value at the JSON Pointer (RFC 6901) `pointer`, and
+    /// marks it as changed, to be re-encoded by the next `encode`. Returns
+    /// `None`, marking nothing, if the pointer is invalid or there is no
+    /// value there.
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Json> {
+        let tokens = match pointer_tokens(pointer) {
+            Some(tokens) => tokens,
+            None => return None,
+        };
+        let mut json = &mut self.json;
+        for token in &tokens {
+            json = match step_mut(json, token) {
+                Some(json) => json,
+                None => return None,
+            };
+        }
+
+        let mut span = &mut self.root;
+        for token in &tokens {
+            if span.dirty {
+                return Some(json);
+            }
+            span.dirty_below = true;
+            span = match span.child(token) {
+                Some(i) => &mut span.children[i].1,
+                None => {
+                    // The value's text no longer matches it; re-encode it all.
+                    span.dirty = true;
+                    return Some(json);
+                }
+            };
+        }
+        span.dirty = true;
+        Some(json)
+    }
+
+    /// Re-encodes the values changed since the last `encode` into the
+    /// document's text, and returns the text.
+    pub fn encode(&mut self) -> &str {
+        if !self.is_modified() {
+            return &self.text;
+        }
+        let start = self.root.start;
+        let end = start + self.root.len;
+        let mut out = String::with_capacity(self.text.len());
+        out.push_str(&self.text[..start]);
+        splice(&self.json, &mut self.root, &self.text[start..end], &mut out);
+        out.push_str(&self.text[end..]);
+        self.text = out;
+        &self.text
+    }
+
+    /// Returns the document's value, discarding its text.
+    pub fn into_json(self) -> Json {
+        self.json
+    }
+}
+
+impl FromStr for JsonDocument {
+    type Err = BuilderError;
+
+    fn from_str(s: &str) -> Result<JsonDocument, BuilderError> {
+        JsonDocument::from_str(s)
+    }
+}
+
+fn step_mut<'a>(json: &'a mut Json, token: &str) -> Option<&'a mut Json> {
+    match *json {
+        Json::Array(ref mut array) => token.parse::<usize>().ok()
+                                           .and_then(move |i| array.get_mut(i)),
+        Json::Object(ref mut object) => object.get_mut(token),
+        _ => None,
+    }
+}
+
+// Writes the text of `json`, whose text was `old` as of `span`, to `out`,
+// and updates `span` to match, apart from its start.
+fn splice(json: &Json, span: &mut Span, old: &str, out: &mut String) {
+    if span.dirty {
+        let new = json.to_string();
+        let mut pos = 0;
+        let start = span.start;
+        *span = scan(new.as_bytes(), &mut pos, 0);
+        span.start = start;
+        out.push_str(&new);
+        return;
+    }
+    if !span.dirty_below {
+        out.push_str(old);
+        return;
+    }
+    let node_start = out.len();
+    let mut prev = 0;
+    for (i, &mut (ref key, ref mut child)) in span.children.iter_mut().enumerate() {
+        let elt = match *key {
+            Some(ref key) => json.find(key),
+            None => json.as_array().and_then(|array| array.get(i)),
+        };
+        let child_old = &old[child.start..child.start + child.len];
+        out.push_str(&old[prev..child.start]);
+        prev = child.start + child.len;
+        child.start = out.len() - node_start;
+        match elt {
+            Some(elt) => splice(elt, child, child_old, out),
+            None => out.push_str(child_old),
+        }
+    }
+    out.push_str(&old[prev..]);
+    span.len = out.len() - node_start;
+    span.dirty_below = false;
+}
+
+fn skip_whitespace(b: &[u8], pos: &mut usize) {
+    while *pos < b.len() && (b[*pos] == b' ' || b[*pos] == b'\t' ||
+                             b[*pos] == b'\n' || b[*pos] == b'\r') {
+        *pos += 1;
+    }
+}
+
+fn skip_string(b: &[u8], pos: &mut usize) {
+    *pos += 1;
+    while *pos < b.len() {
+        match b[*pos] {
+            b'\\' => *pos += 2,
+            b'"' => {
+                *pos += 1;
+                return;
+            }
+            _ => *pos += 1,
+        }
+    }
+}
+
+// Finds the spans of the value at `pos` in `b`, which has been parsed, and
+// so is known to be valid, and of the values within it, leaving `pos` after
+// it. Its start is counted from `origin`.
+fn scan(b: &[u8], pos: &mut usize, origin: usize) -> Span {
+    skip_whitespace(b, pos);
+    let start = *pos;
+    let mut children = Vec::new();
+    match b[start] {
+        b'[' | b'{' => {
+            let object = b[start] == b'{';
+            *pos += 1;
+            skip_whitespace(b, pos);
+            if b[*pos] == b']' || b[*pos] == b'}' {
+                *pos += 1;
+            } else {
+                loop {
+                    let key = if object {
+                        skip_whitespace(b, pos);
+                        let key_start = *pos;
+                        skip_string(b, pos);
+                        let key = str::from_utf8(&b[key_start..*pos]).ok()
+                                      .and_then(|k| Json::from_str(k).ok())
+                                      .and_then(|k| k.as_string().map(|k| k.to_string()));
+                        skip_whitespace(b, pos);
+                        // The colon.
+                        *pos += 1;
+                        Some(key.unwrap_or_default())
+                    } else {
+                        None
+                    };
+                    let span = scan(b, pos, start);
+                    children.push((key, span));
+                    skip_whitespace(b, pos);
+                    let c = b[*pos];
+                    *pos += 1;
+                    if c != b',' {
+                        break;
+                    }
+                }
+            }
+        }
+        b'"' => skip_string(b, pos),
+        _ => {
+            while *pos < b.len() && !b",]} \t\n\r".contains(&b[*pos]) {
+                *pos += 1;
+            }
+        }
+    }
+    Span {
+        start: start - origin,
+        len: *pos - start,
+        dirty: false,
+        dirty_below: false,
+        children: children,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::Json;
+    use json::edit::JsonDocument;
+
+    #[test]
+    fn test_encode_edits() {
+        let text = " {\"a\" : [1, {\"b\": true},\t\"x\"],\n \"c~/\": {}, \"d\\u0065\": null } \n";
+        let mut doc = JsonDocument::from_str(text).unwrap();
+        assert!(!doc.is_modified());
+        assert_eq!(doc.encode(), text);
+
+        *doc.pointer_mut("/a/1/b").unwrap() = Json::Boolean(false);
+        *doc.pointer_mut("/de").unwrap() = Json::from_str("[ 2 ]").unwrap();
+        assert!(doc.is_modified());
+        assert_eq!(doc.encode(),
+                   " {\"a\" : [1, {\"b\": false},\t\"x\"],\n \"c~/\": {}, \"d\\u0065\": [2] } \n");
+        assert!(!doc.is_modified());
+
+        // The spans of the values after those re-encoded moved with them.
+        *doc.pointer_mut("/a/2").unwrap() = Json::String("yz".into());
+        doc.pointer_mut("/c~0~1").unwrap().as_object_mut().unwrap()
+           .insert("k".to_string(), Json::U64(1));
+        *doc.pointer_mut("/de/0").unwrap() = Json::U64(30);
+        assert_eq!(doc.encode(),
+                   " {\"a\" : [1, {\"b\": false},\t\"yz\"],\n \
+                    \"c~/\": {\"k\":1}, \"d\\u0065\": [30] } \n");
+        *doc.pointer_mut("/c~0~1/k").unwrap() = Json::U64(2);
+        doc.pointer_mut("/a/1").unwrap();
+        assert_eq!(doc.encode(),
+                   " {\"a\" : [1, {\"b\":false},\t\"yz\"],\n \
+                    \"c~/\": {\"k\":2}, \"d\\u0065\": [30] } \n");
+        assert_eq!(doc.json(), &Json::from_str(doc.text()).unwrap());
+
+        assert!(doc.pointer_mut("/a/3").is_none());
+        assert!(doc.pointer_mut("/nope").is_none());
+        assert!(doc.pointer_mut("a").is_none());
+        assert!(!doc.is_modified());
+
+        *doc.pointer_mut("").unwrap() = Json::U64(7);
+        assert_eq!(doc.encode(), " 7 \n");
+        assert_eq!(doc.into_json(), Json::U64(7));
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let mut doc: JsonDocument = "{\"a\": 1, \"a\": 2, \"b\": []}".parse().unwrap();
+        *doc.pointer_mut("/a").unwrap() = Json::U64(3);
+        assert_eq!(doc.encode(), "{\"a\": 1, \"a\": 3, \"b\": []}");
+        assert!(JsonDocument::from_str("[1,").is_err());
+    }
+}
@@ -0,0 +1,449 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Re-indenting and minifying JSON text
+//!
+//! `reformat` copies a document from a reader to a writer, changing only
+//! the whitespace between its tokens: strings and numbers are copied as
+//! they were written, escapes, exponents and all, and members stay in the
+//! order of the text, duplicate keys included. No value is built, so a
+//! document of any size is reformatted in the memory of its deepest
+//! nesting.
+//!
+//! The document is checked as it is copied, as strictly as by the parser,
+//! and an error stops the copy; what was written before it is left in the
+//! writer.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json;
+//! use rustc_serialize::json::format::Style;
+//!
+//! fn main() {
+//!     let text = "{\"n\": 1.50e3, \"a\": [\"\\u00e9\", {}]}";
+//!     let mut out = Vec::new();
+//!     json::reformat(text.as_bytes(), &mut out, Style::Pretty(2)).unwrap();
+//!     assert_eq!(String::from_utf8(out).unwrap(),
+//!                "{\n  \"n\": 1.50e3,\n  \"a\": [\n    \"\\u00e9\",\n    {}\n  ]\n}");
+//!
+//!     let mut out = Vec::new();
+//!     json::reformat(text.as_bytes(), &mut out, Style::Compact).unwrap();
+//!     assert_eq!(out, b"{\"n\":1.50e3,\"a\":[\"\\u00e9\",{}]}");
+//! }
+//! ```
+
+use std::io::{Read, Write};
+use std::str;
+
+use json::{ErrorCode, ParserError};
+use json::ErrorCode::*;
+use json::ParserError::SyntaxError;
+
+/// How `reformat` lays out a document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    /// No whitespace at all
+    Compact,
+    /// Each element and member on a line of its own, indented by the given
+    /// number of spaces a level, as by `PrettyEncoder`
+    Pretty(u32),
+}
+
+/// Copies the document read from `input` to `output`, laid out in
+/// `style`; see the module documentation.
+pub fn reformat<R: Read, W: Write>(input: R, output: W, style: Style)
+                                   -> Result<(), ParserError> {
+    let mut reformatter = Reformatter {
+        rdr: input,
+        inbuf: vec![0; 8192],
+        pos: 0,
+        filled: 0,
+        line: 1,
+        col: 0,
+        wr: output,
+        out: Vec::with_capacity(8192),
+        style: style,
+        stack: Vec::new(),
+    };
+    try!(reformatter.document());
+    reformatter.flush()
+}
+
+struct Reformatter<R, W> {
+    rdr: R,
+    inbuf: Vec<u8>,
+    pos: usize,
+    filled: usize,
+    // The line and column of the last character read, counted as the
+    // parser counts them.
+    line: usize,
+    col: usize,
+    wr: W,
+    out: Vec<u8>,
+    style: Style,
+    // Whether each array or object open is an object.
+    stack: Vec<bool>,
+}
+
+impl<R: Read, W: Write> Reformatter<R, W> {
+    fn peek(&mut self) -> Result<Option<u8>, ParserError> {
+        if self.pos == self.filled {
+            self.pos = 0;
+            self.filled = try!(self.rdr.read(&mut self.inbuf));
+            if self.filled == 0 {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.inbuf[self.pos]))
+    }
+
+    fn bump(&mut self) {
+        let b = self.inbuf[self.pos];
+        self.pos += 1;
+        if b == b'\n' {
+            self.line += 1;
+            self.col = 1;
+        } else if b & 0xc0 != 0x80 {
+            self.col += 1;
+        }
+    }
+
+    // Reads the next byte, failing with `eof` at the end of the input.
+    fn next(&mut self, eof: ErrorCode) -> Result<u8, ParserError> {
+        match try!(self.peek()) {
+            Some(b) => {
+                self.bump();
+                Ok(b)
+            }
+            None => Err(self.error(eof)),
+        }
+    }
+
+    // An error at the character after the last read.
+    fn error(&self, code: ErrorCode) -> ParserError {
+        SyntaxError(code, self.line, self.col + 1)
+    }
+
+    fn skip_whitespace(&mut self) -> Result<Option<u8>, ParserError> {
+        loop {
+            match try!(self.peek()) {
+                Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') => self.bump(),
+                b => return Ok(b),
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), ParserError> {
+        try!(self.wr.write_all(&self.out));
+        self.out.clear();
+        Ok(())
+    }
+
+    // Starts a line at the indent of the innermost array or object open.
+    fn newline(&mut self) {
+        if let Style::Pretty(indent) = self.style {
+            self.out.push(b'\n');
+            let n = self.stack.len() * indent as usize;
+            self.out.extend((0..n).map(|_| b' '));
+        }
+    }
+
+    fn document(&mut self) -> Result<(), ParserError> {
+        'value: loop {
+            if try!(self.value()) {
+                continue;
+            }
+            if self.out.len() >= 8192 {
+                try!(self.flush());
+            }
+            // Close arrays and objects until the next element or member.
+            while let Some(&object) = self.stack.last() {
+                let close = if object { b'}' } else { b']' };
+                match try!(self.skip_whitespace()) {
+                    Some(b',') => {
+                        self.bump();
+                        self.out.push(b',');
+                        self.newline();
+                        if object {
+                            try!(self.key());
+                        }
+                        continue 'value;
+                    }
+                    Some(b) if b == close => {
+                        self.bump();
+                        self.stack.pop();
+                        self.newline();
+                        self.out.push(close);
+                    }
+                    Some(_) => return Err(self.error(InvalidSyntax)),
+                    None if object => return Err(self.error(EOFWhileParsingObject)),
+                    None => return Err(self.error(EOFWhileParsingArray)),
+                }
+            }
+            break;
+        }
+        match try!(self.skip_whitespace()) {
+            Some(_) => Err(self.error(TrailingCharacters)),
+            None => Ok(()),
+        }
+    }
+
+    // Copies a value, or the start of an array or object and its first
+    // member's key, returning whether an array or object was left open.
+    fn value(&mut self) -> Result<bool, ParserError> {
+        let b = match try!(self.skip_whitespace()) {
+            Some(b) => b,
+            None => return Err(self.error(match self.stack.last() {
+                Some(&true) => EOFWhileParsingObject,
+                Some(&false) => EOFWhileParsingArray,
+                None => EOFWhileParsingValue,
+            })),
+        };
+        match b {
+            b'[' | b'{' => {
+                self.bump();
+                self.out.push(b);
+                let object = b == b'{';
+                let close = if object { b'}' } else { b']' };
+                if try!(self.skip_whitespace()) == Some(close) {
+                    self.bump();
+                    self.out.push(close);
+                    return Ok(false);
+                }
+                self.stack.push(object);
+                self.newline();
+                if object {
+                    try!(self.key());
+                }
+                return Ok(true);
+            }
+            b'"' => try!(self.string()),
+            b'-' | b'0'...b'9' => try!(self.number()),
+            b't' => try!(self.literal(b"true")),
+            b'f' => try!(self.literal(b"false")),
+            b'n' => try!(self.literal(b"null")),
+            _ => return Err(self.error(InvalidSyntax)),
+        }
+        Ok(false)
+    }
+
+    // Copies a key and its colon.
+    fn key(&mut self) -> Result<(), ParserError> {
+        match try!(self.skip_whitespace()) {
+            Some(b'"') => try!(self.string()),
+            Some(_) => return Err(self.error(KeyMustBeAString)),
+            None => return Err(self.error(EOFWhileParsingObject)),
+        }
+        match try!(self.skip_whitespace()) {
+            Some(b':') => self.bump(),
+            Some(_) => return Err(self.error(ExpectedColon)),
+            None => return Err(self.error(EOFWhileParsingObject)),
+        }
+        self.out.push(b':');
+        if let Style::Pretty(_) = self.style {
+            self.out.push(b' ');
+        }
+        Ok(())
+    }
+
+    fn literal(&mut self, text: &[u8]) -> Result<(), ParserError> {
+        for &expected in text {
+            if try!(self.peek()) != Some(expected) {
+                return Err(self.error(InvalidSyntax));
+            }
+            self.bump();
+        }
+        self.out.extend_from_slice(text);
+        Ok(())
+    }
+
+    // Copies digits, returning how many.
+    fn digits(&mut self) -> Result<usize, ParserError> {
+        let mut n = 0;
+        while let Some(b @ b'0'...b'9') = try!(self.peek()) {
+            self.bump();
+            self.out.push(b);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    fn number(&mut self) -> Result<(), ParserError> {
+        if try!(self.peek()) == Some(b'-') {
+            self.bump();
+            self.out.push(b'-');
+        }
+        match try!(self.peek()) {
+            Some(b'0') => {
+                self.bump();
+                self.out.push(b'0');
+            }
+            Some(b'1'...b'9') => {
+                try!(self.digits());
+            }
+            _ => return Err(self.error(InvalidNumber)),
+        }
+        if try!(self.peek()) == Some(b'.') {
+            self.bump();
+            self.out.push(b'.');
+            if try!(self.digits()) == 0 {
+                return Err(self.error(InvalidNumber));
+            }
+        }
+        if let Some(e @ b'e') | Some(e @ b'E') = try!(self.peek()) {
+            self.bump();
+            self.out.push(e);
+            if let Some(sign @ b'+') | Some(sign @ b'-') = try!(self.peek()) {
+                self.bump();
+                self.out.push(sign);
+            }
+            if try!(self.digits()) == 0 {
+                return Err(self.error(InvalidNumber));
+            }
+        }
+        Ok(())
+    }
+
+    // Reads the four digits of a `\u` escape, copying them.
+    fn hex_escape(&mut self) -> Result<u16, ParserError> {
+        let mut n = 0;
+        for _ in 0..4 {
+            let b = try!(self.next(EOFWhileParsingString));
+            let digit = match (b as char).to_digit(16) {
+                Some(digit) => digit as u16,
+                None => return Err(SyntaxError(InvalidEscape, self.line, self.col)),
+            };
+            self.out.push(b);
+            n = n << 4 | digit;
+        }
+        Ok(n)
+    }
+
+    fn string(&mut self) -> Result<(), ParserError> {
+        let start = self.out.len();
+        self.bump();
+        self.out.push(b'"');
+        loop {
+            match try!(self.next(EOFWhileParsingString)) {
+                b'"' => break,
+                b'\\' => {
+                    self.out.push(b'\\');
+                    let b = try!(self.next(EOFWhileParsingString));
+                    self.out.push(b);
+                    match b {
+                        b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => {}
+                        b'u' => match try!(self.hex_escape()) {
+                            0xd800...0xdbff => {
+                                if try!(self.next(EOFWhileParsingString)) != b'\\' ||
+                                   try!(self.next(EOFWhileParsingString)) != b'u' {
+                                    return Err(self.error(UnexpectedEndOfHexEscape));
+                                }
+                                self.out.extend_from_slice(b"\\u");
+                                match try!(self.hex_escape()) {
+                                    0xdc00...0xdfff => {}
+                                    _ => return Err(self.error(LoneLeadingSurrogateInHexEscape)),
+                                }
+                            }
+                            0xdc00...0xdfff => {
+                                return Err(self.error(LoneTrailingSurrogateInHexEscape))
+                            }
+                            _ => {}
+                        },
+                        _ => return Err(SyntaxError(InvalidEscape, self.line, self.col)),
+                    }
+                }
+                b if b < 0x20 => {
+                    return Err(SyntaxError(ControlCharacterInString, self.line, self.col))
+                }
+                b => self.out.push(b),
+            }
+        }
+        self.out.push(b'"');
+        if str::from_utf8(&self.out[start..]).is_err() {
+            return Err(SyntaxError(NotUtf8, self.line, self.col));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str;
+    use json::{self, Json};
+    use json::ErrorCode::*;
+    use json::ParserError::SyntaxError;
+    use json::format::Style;
+
+    fn reformat(text: &str, style: Style) -> String {
+        let mut out = Vec::new();
+        json::reformat(text.as_bytes(), &mut out, style).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_reformat() {
+        let text = " {\"b\": [1, -0.5E+10, 2e-3, true, null],\
+                    \"a\\n\": {\"x\": \"\\ud83d\\ude00\u{e9}\"},\
+                    \r\n\t\"b\": [], \"c\": {}, \"d\": [[\"\"]] } ";
+        let compact = reformat(text, Style::Compact);
+        assert_eq!(compact, "{\"b\":[1,-0.5E+10,2e-3,true,null],\
+                             \"a\\n\":{\"x\":\"\\ud83d\\ude00\u{e9}\"},\
+                             \"b\":[],\"c\":{},\"d\":[[\"\"]]}");
+        assert_eq!(reformat(&compact, Style::Compact), compact);
+
+        // Without duplicate keys or number lexemes the encoder would change,
+        // the layout is the encoder's.
+        let json = Json::from_str("{\"a\": [1, {\"b\": \"x\", \"c\": []}], \"d\": {}}").unwrap();
+        assert_eq!(reformat(&json.to_string(), Style::Pretty(2)), json.pretty().to_string());
+        assert_eq!(reformat(&json.pretty().to_string(), Style::Compact), json.to_string());
+        assert_eq!(reformat("[1,[2]]", Style::Pretty(0)), "[\n1,\n[\n2\n]\n]");
+        assert_eq!(reformat(" \"s\" ", Style::Pretty(4)), "\"s\"");
+
+        // A document larger than the buffers, and one nested deeply.
+        let big = format!("[{}1]", "[\"0123456789\", 1.0], ".repeat(2000));
+        assert_eq!(Json::from_str(&reformat(&big, Style::Pretty(1))).unwrap(),
+                   Json::from_str(&big).unwrap());
+        let deep = format!("{}{}", "[".repeat(100000), "]".repeat(100000));
+        assert_eq!(reformat(&deep, Style::Compact), deep);
+    }
+
+    #[test]
+    fn test_reformat_errors() {
+        let errors = [("", EOFWhileParsingValue, 1, 1),
+                      ("[1,]", InvalidSyntax, 1, 4),
+                      ("{\"a\" 1}", ExpectedColon, 1, 6),
+                      ("{1: 2}", KeyMustBeAString, 1, 2),
+                      ("[1, 2", EOFWhileParsingArray, 1, 6),
+                      ("{\"a\": 1", EOFWhileParsingObject, 1, 8),
+                      ("[01]", InvalidSyntax, 1, 3),
+                      ("[1.]", InvalidNumber, 1, 4),
+                      ("[1e]", InvalidNumber, 1, 4),
+                      ("-", InvalidNumber, 1, 2),
+                      ("tru", InvalidSyntax, 1, 4),
+                      ("[1]\n 2", TrailingCharacters, 2, 3),
+                      ("\"a\\x\"", InvalidEscape, 1, 4),
+                      ("\"\\u12g4\"", InvalidEscape, 1, 6),
+                      ("\"\\ud800\"", UnexpectedEndOfHexEscape, 1, 9),
+                      ("\"\\udc00\"", LoneTrailingSurrogateInHexEscape, 1, 8),
+                      ("\"a\tb\"", ControlCharacterInString, 1, 3),
+                      ("\"abc", EOFWhileParsingString, 1, 5)];
+        for &(text, code, line, col) in errors.iter() {
+            let mut out = Vec::new();
+            assert_eq!(json::reformat(text.as_bytes(), &mut out, Style::Compact),
+                       Err(SyntaxError(code, line, col)), "{}", text);
+        }
+        let mut out = Vec::new();
+        assert_eq!(json::reformat(&b"[\"\xff\"]"[..], &mut out, Style::Compact),
+                   Err(SyntaxError(NotUtf8, 1, 4)));
+        assert!(str::from_utf8(&out).is_ok());
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Including files in JSON documents
+//!
+//! A configuration can be split over files by writing, in place of a
+//! value, an object whose only member is `$include`, holding the path of
+//! the file with the value: `{"db": {"$include": "db.json"}}`. `resolve`
+//! replaces each such object with the document the path names, read with
+//! a loader given, and with the files it includes resolved in turn. The
+//! path is relative to the directory of the file including it.
+//!
+//! A file which includes itself, directly or through others, is an error,
+//! as its document would have no end. Including one file in several
+//! places is not: each is replaced with its own copy.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use std::io;
+//! use std::path::Path;
+//! use rustc_serialize::json::{Json, ParserError};
+//! use rustc_serialize::json::include;
+//!
+//! fn main() {
+//!     // A loader which reads files from memory, rather than disk.
+//!     let mut loader = |path: &Path| -> Result<Json, ParserError> {
+//!         match path.to_str() {
+//!             Some("conf/db.json") => Json::from_str(r#"{"host": "localhost"}"#),
+//!             _ => Err(ParserError::IoError(io::Error::new(io::ErrorKind::NotFound, "no file"))),
+//!         }
+//!     };
+//!     let mut config = Json::from_str(r#"{"db": {"$include": "db.json"}}"#).unwrap();
+//!     include::resolve(&mut config, Path::new("conf/app.json"), &mut loader).unwrap();
+//!     assert_eq!(config.to_string(), r#"{"db":{"host":"localhost"}}"#);
+//! }
+//! ```
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs::File;
+use std::path::{Component, Path, PathBuf};
+
+use json::{Json, ParserError};
+
+/// An error from `resolve`.
+#[derive(Debug, PartialEq)]
+pub enum IncludeError {
+    /// The file at the path could not be loaded
+    LoadError(PathBuf, ParserError),
+    /// The file at the last path includes the first, through the others
+    /// between
+    Cycle(Vec<PathBuf>),
+    /// The file at the path has an object with an `$include` member which
+    /// is not a string, or alongside other members
+    InvalidInclude(PathBuf),
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IncludeError::LoadError(ref path, ref err) => {
+                write!(f, "{}: {}", path.display(), err)
+            }
+            IncludeError::Cycle(ref paths) => {
+                try!(write!(f, "include cycle: "));
+                for path in paths {
+                    try!(write!(f, "{} -> ", path.display()));
+                }
+                write!(f, "{}", paths[0].display())
+            }
+            IncludeError::InvalidInclude(ref path) => {
+                write!(f, "{}: invalid $include", path.display())
+            }
+        }
+    }
+}
+
+impl StdError for IncludeError {
+    fn description(&self) -> &str { "include error" }
+}
+
+/// Reads the JSON document in the file at `path`; this is the loader of
+/// `load`.
+pub fn read_file(path: &Path) -> Result<Json, ParserError> {
+    let mut file = try!(File::open(path));
+    Json::from_reader(&mut file)
+}
+
+/// Reads the document in the file at `path`, and resolves the files it
+/// includes from disk.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Json, IncludeError> {
+    let path = normalize(path.as_ref());
+    let mut json = try!(read_file(&path).map_err(|e| IncludeError::LoadError(path.clone(), e)));
+    try!(resolve(&mut json, &path, &mut read_file));
+    Ok(json)
+}
+
+/// Replaces each `$include` object within `json`, which was read from the
+/// file at `path`, with the document `loader` returns for the path it
+/// names; see the module documentation.
+pub fn resolve<F>(json: &mut Json, path: &Path, loader: &mut F) -> Result<(), IncludeError>
+    where F: FnMut(&Path) -> Result<Json, ParserError>
+{
+    let mut stack = vec![normalize(path)];
+    resolve_in(json, &mut stack, loader)
+}
+
+// Resolves the includes in `json`, from the file last in `stack`, which
+// holds the files being resolved.
+fn resolve_in<F>(json: &mut Json, stack: &mut Vec<PathBuf>, loader: &mut F)
+                 -> Result<(), IncludeError>
+    where F: FnMut(&Path) -> Result<Json, ParserError>
+{
+    let target = match *json {
+        Json::Array(ref mut array) => {
+            for elt in array.iter_mut() {
+                try!(resolve_in(elt, stack, loader));
+            }
+            return Ok(());
+        }
+        Json::Object(ref mut object) => match object.get("$include") {
+            Some(Json::String(target)) if object.len() == 1 => {
+                let current = &stack[stack.len() - 1];
+                normalize(&current.parent().unwrap_or(Path::new("")).join(&target[..]))
+            }
+            Some(_) => return Err(IncludeError::InvalidInclude(stack[stack.len() - 1].clone())),
+            None => {
+                for member in object.values_mut() {
+                    try!(resolve_in(member, stack, loader));
+                }
+                return Ok(());
+            }
+        },
+        _ => return Ok(()),
+    };
+    if let Some(i) = stack.iter().position(|p| *p == target) {
+        return Err(IncludeError::Cycle(stack[i..].to_vec()));
+    }
+    let mut included = match loader(&target) {
+        Ok(included) => included,
+        Err(err) => return Err(IncludeError::LoadError(target, err)),
+    };
+    stack.push(target);
+    try!(resolve_in(&mut included, stack, loader));
+    stack.pop();
+    *json = included;
+    Ok(())
+}
+
+// Removes the `.` components of `path`, and the `..` components with the
+// ones before them, so that paths to the same file compare equal.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normal.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normal.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normal.push(".."),
+            },
+            component => normal.push(component.as_os_str()),
+        }
+    }
+    normal
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{self, Write};
+    use std::path::{Path, PathBuf};
+    use json::{Json, ParserError};
+    use json::include::{self, IncludeError};
+
+    fn files(files: &[(&str, &str)]) -> HashMap<PathBuf, String> {
+        files.iter().map(|&(path, text)| (PathBuf::from(path), text.to_string())).collect()
+    }
+
+    fn resolve(files: &HashMap<PathBuf, String>, text: &str) -> Result<Json, IncludeError> {
+        let mut loader = |path: &Path| match files.get(path) {
+            Some(text) => Json::from_str(text),
+            None => Err(ParserError::IoError(io::Error::new(io::ErrorKind::NotFound, "no file"))),
+        };
+        let mut json = Json::from_str(text).unwrap();
+        try!(include::resolve(&mut json, Path::new("conf/main.json"), &mut loader));
+        Ok(json)
+    }
+
+    #[test]
+    fn test_resolve() {
+        let files = files(&[("conf/a.json", "[{\"$include\": \"sub/b.json\"}, 1]"),
+                            ("conf/sub/b.json", "{\"b\": {\"$include\": \"../c.json\"}}"),
+                            ("conf/c.json", "\"c\""),
+                            ("conf/loop.json", "{\"x\": {\"$include\": \"./sub/../loop2.json\"}}"),
+                            ("conf/loop2.json", "[{\"$include\": \"loop.json\"}]"),
+                            ("conf/self.json", "{\"$include\": \"main.json\"}")]);
+        assert_eq!(resolve(&files, "{\"a\": {\"$include\": \"a.json\"}, \
+                                     \"c\": [{\"$include\": \"c.json\"}]}")
+                       .unwrap().to_string(),
+                   "{\"a\":[{\"b\":\"c\"},1],\"c\":[\"c\"]}");
+
+        assert_eq!(resolve(&files, "[{\"$include\": \"loop.json\"}]"),
+                   Err(IncludeError::Cycle(vec![PathBuf::from("conf/loop.json"),
+                                                PathBuf::from("conf/loop2.json")])));
+        assert_eq!(resolve(&files, "{\"$include\": \"self.json\"}"),
+                   Err(IncludeError::Cycle(vec![PathBuf::from("conf/main.json"),
+                                                PathBuf::from("conf/self.json")])));
+        assert_eq!(resolve(&files, "{\"a\": {\"$include\": 1}}"),
+                   Err(IncludeError::InvalidInclude(PathBuf::from("conf/main.json"))));
+        assert_eq!(resolve(&files, "{\"$include\": \"c.json\", \"x\": 2}"),
+                   Err(IncludeError::InvalidInclude(PathBuf::from("conf/main.json"))));
+        match resolve(&files, "{\"$include\": \"sub/none.json\"}") {
+            Err(IncludeError::LoadError(ref path, ParserError::IoError(_))) => {
+                assert_eq!(path, Path::new("conf/sub/none.json"))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = env::temp_dir().join("rustc_serialize_test_include");
+        fs::create_dir_all(&dir).unwrap();
+        File::create(dir.join("main.json")).unwrap()
+            .write_all(b"{\"part\": {\"$include\": \"part.json\"}}").unwrap();
+        File::create(dir.join("part.json")).unwrap().write_all(b"[true]").unwrap();
+        assert_eq!(include::load(dir.join("main.json")).unwrap().to_string(),
+                   "{\"part\":[true]}");
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(include::load(dir.join("main.json")).is_err());
+    }
+}
@@ -0,0 +1,669 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Validating requests and responses against an OpenAPI document
+//!
+//! `OpenApi::new` compiles the `paths` of an OpenAPI 3 document into an
+//! `Endpoint` for each operation, holding the JSON schemas of its request
+//! body and of its responses by status. `find` looks up the endpoint for
+//! a method and a request path, matching templated segments such as
+//! `{id}`, and the endpoint's `validate_request` and `validate_response`
+//! check JSON bodies against their schemas, returning every problem found,
+//! each at the JSON Pointer of the value in the body at fault.
+//!
+//! Schemas may refer to `components`, or anywhere else in the document,
+//! with `$ref`; every reference is checked when the document is compiled.
+//! The keywords checked are `type` (and OpenAPI 3.0's `nullable`), `enum`,
+//! `const`, `properties`, `required`, `additionalProperties`,
+//! `min`/`maxProperties`, `items`, `min`/`maxItems`, `uniqueItems`,
+//! `min`/`maxLength`, `minimum`, `maximum`, `exclusiveMinimum`,
+//! `exclusiveMaximum` (as numbers, or as OpenAPI 3.0's booleans),
+//! `multipleOf`, `allOf`, `anyOf`, `oneOf` and `not`. Others, such as
+//! `pattern` and `format`, are ignored, as are all but JSON media types.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::Json;
+//! use rustc_serialize::json::openapi::OpenApi;
+//!
+//! fn main() {
+//!     let doc = Json::from_str(r##"{
+//!         "openapi": "3.0.3",
+//!         "paths": {"/pets/{id}": {"put": {
+//!             "requestBody": {"required": true, "content": {"application/json": {
+//!                 "schema": {"$ref": "#/components/schemas/Pet"}}}},
+//!             "responses": {"204": {}}}}},
+//!         "components": {"schemas": {"Pet": {
+//!             "type": "object", "required": ["name"],
+//!             "properties": {"name": {"type": "string"},
+//!                            "age": {"type": "integer", "minimum": 0}}}}}
+//!     }"##).unwrap();
+//!     let api = OpenApi::new(doc).unwrap();
+//!
+//!     let (endpoint, params) = api.find("PUT", "/pets/7").unwrap();
+//!     assert_eq!(params["id"], "7");
+//!     let pet = Json::from_str(r#"{"name": "Rex", "age": 3}"#).unwrap();
+//!     assert!(endpoint.validate_request(Some(&pet)).is_ok());
+//!
+//!     let errors = endpoint.validate_request(Some(&Json::from_str(r#"{"age": -1}"#).unwrap()))
+//!                          .unwrap_err();
+//!     let errors: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+//!     assert_eq!(errors, ["missing required member \"name\"", "/age: less than the minimum 0"]);
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::Arc;
+
+use json::{Json, Object};
+use json::refs::{self, RefError};
+
+/// The methods of the operations of a path item.
+pub const METHODS: [&str; 8] =
+    ["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+
+// The deepest schemas are checked, counting references followed, lest a
+// schema referring only to itself never end.
+const MAX_DEPTH: usize = 256;
+
+/// An error compiling an OpenAPI document.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OpenApiError {
+    /// The document has no `paths` object
+    NoPaths,
+    /// A reference in the document has no target in it
+    RefError(RefError),
+}
+
+impl fmt::Display for OpenApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OpenApiError::NoPaths => write!(f, "document has no paths"),
+            OpenApiError::RefError(ref err) => fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+impl StdError for OpenApiError {
+    fn description(&self) -> &str { "OpenAPI error" }
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            OpenApiError::RefError(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A problem with a body, found by an `Endpoint`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationError {
+    /// The JSON Pointer of the value in the body at fault
+    pub pointer: String,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.pointer.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.pointer, self.message)
+        }
+    }
+}
+
+impl StdError for ValidationError {
+    fn description(&self) -> &str { "validation error" }
+}
+
+/// A compiled OpenAPI document; see the module documentation.
+#[derive(Clone, Debug)]
+pub struct OpenApi {
+    endpoints: Vec<Endpoint>,
+}
+
+/// An operation of an OpenAPI document, with the schemas of its bodies.
+#[derive(Clone, Debug)]
+pub struct Endpoint {
+    /// The method, in lower case
+    pub method: String,
+    /// The path template, as written in the document
+    pub path: String,
+    doc: Arc<Json>,
+    // The schema of the request body, if it has one, and whether it is
+    // required.
+    request: Option<(Option<Json>, bool)>,
+    // The schemas of the responses, by status or status range.
+    responses: BTreeMap<String, Option<Json>>,
+}
+
+impl OpenApi {
+    /// Compiles the operations of `doc`.
+    pub fn new(doc: Json) -> Result<OpenApi, OpenApiError> {
+        try!(check_refs(&doc, &doc).map_err(OpenApiError::RefError));
+        let doc = Arc::new(doc);
+        let paths = match doc.find("paths").and_then(|p| p.as_object()) {
+            Some(paths) => paths,
+            None => return Err(OpenApiError::NoPaths),
+        };
+        let mut endpoints = Vec::new();
+        for (path, item) in paths {
+            let item = deref(&doc, item);
+            for &method in METHODS.iter() {
+                let operation = match item.find(method) {
+                    Some(operation) => deref(&doc, operation),
+                    None => continue,
+                };
+                let request = operation.find("requestBody").map(|body| {
+                    let body = deref(&doc, body);
+                    let required = body.find("required").and_then(|r| r.as_boolean());
+                    (json_schema(&doc, body), required == Some(true))
+                });
+                let mut responses = BTreeMap::new();
+                if let Some(statuses) = operation.find("responses").and_then(|r| r.as_object()) {
+                    for (status, response) in statuses {
+                        let schema = json_schema(&doc, deref(&doc, response));
+                        responses.insert(status.to_uppercase(), schema);
+                    }
+                }
+                endpoints.push(Endpoint {
+                    method: method.to_string(),
+                    path: path.clone(),
+                    doc: doc.clone(),
+                    request: request,
+                    responses: responses,
+                });
+            }
+        }
+        Ok(OpenApi { endpoints: endpoints })
+    }
+
+    /// Returns the endpoints, in the order of their paths and then methods.
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    /// Finds the endpoint for `method` on the request path `path`, along
+    /// with the values of the templated segments of its path by name. A
+    /// query string is ignored. Where several templates match, the one with
+    /// the fewest templated segments is found.
+    pub fn find(&self, method: &str, path: &str) -> Option<(&Endpoint, BTreeMap<String, String>)> {
+        let path = path.split('?').next().unwrap_or("");
+        let method = method.to_lowercase();
+        self.endpoints.iter().filter(|e| e.method == method).filter_map(|endpoint| {
+            endpoint.match_path(path).map(|params| (endpoint, params))
+        }).min_by_key(|(_, params)| params.len())
+    }
+}
+
+impl Endpoint {
+    // The values of the templated segments of this endpoint's path, if it
+    // matches `path`.
+    fn match_path(&self, path: &str) -> Option<BTreeMap<String, String>> {
+        let mut params = BTreeMap::new();
+        let mut segments = path.split('/');
+        for template in self.path.split('/') {
+            let segment = match segments.next() {
+                Some(segment) => segment,
+                None => return None,
+            };
+            if template.starts_with('{') && template.ends_with('}') && template.len() > 2 {
+                if segment.is_empty() {
+                    return None;
+                }
+                params.insert(template[1..template.len() - 1].to_string(), segment.to_string());
+            } else if template != segment {
+                return None;
+            }
+        }
+        if segments.next().is_some() { None } else { Some(params) }
+    }
+
+    /// Checks a request's body, or its absence, against the endpoint's.
+    pub fn validate_request(&self, body: Option<&Json>) -> Result<(), Vec<ValidationError>> {
+        match (&self.request, body) {
+            (&Some((_, true)), None) => {
+                Err(vec![error("", "missing required request body".to_string())])
+            }
+            (&Some((Some(ref schema), _)), Some(body)) => validate(&self.doc, schema, body),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the body of a response with `status` against the schema of
+    /// the endpoint's response with that status, with the range of statuses
+    /// holding it (such as `4XX`), or else its default response. A status
+    /// without any of these is an error.
+    pub fn validate_response(&self, status: u16, body: &Json) -> Result<(), Vec<ValidationError>> {
+        let exact = status.to_string();
+        let range = format!("{}XX", status / 100);
+        let response = self.responses.get(&exact).or_else(|| self.responses.get(&range))
+                           .or_else(|| self.responses.get("DEFAULT"));
+        match response {
+            Some(Some(schema)) => validate(&self.doc, schema, body),
+            Some(&None) => Ok(()),
+            None => Err(vec![error("", format!("undocumented response status {}", status))]),
+        }
+    }
+}
+
+// Follows `node`, if it is a reference, to its target, and so on.
+fn deref<'a>(doc: &'a Json, mut node: &'a Json) -> &'a Json {
+    for _ in 0..MAX_DEPTH {
+        match node.find("$ref").and_then(|r| r.as_string()) {
+            Some(reference) => match refs::target(doc, reference) {
+                Ok(target) => node = target,
+                Err(_) => break,
+            },
+            None => break,
+        }
+    }
+    node
+}
+
+// The schema of the JSON content of a request body or response.
+fn json_schema(doc: &Json, body: &Json) -> Option<Json> {
+    let content = match body.find("content").and_then(|c| c.as_object()) {
+        Some(content) => content,
+        None => return None,
+    };
+    let media = content.get("application/json").or_else(|| {
+        content.iter().find(|&(k, _)| k.contains("json")).map(|(_, v)| v)
+    });
+    media.and_then(|media| deref(doc, media).find("schema")).cloned()
+}
+
+// Checks that every reference within `node` has a target in `doc`.
+fn check_refs(doc: &Json, node: &Json) -> Result<(), RefError> {
+    match *node {
+        Json::Array(ref array) => {
+            for elt in array {
+                try!(check_refs(doc, elt));
+            }
+        }
+        Json::Object(ref object) => {
+            if let Some(reference) = object.get("$ref").and_then(|r| r.as_string()) {
+                try!(refs::target(doc, reference));
+            }
+            for member in object.values() {
+                try!(check_refs(doc, member));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn error(pointer: &str, message: String) -> ValidationError {
+    ValidationError { pointer: pointer.to_string(), message: message }
+}
+
+fn validate(doc: &Json, schema: &Json, value: &Json) -> Result<(), Vec<ValidationError>> {
+    let mut validation = Validation { doc: doc, errors: Vec::new() };
+    validation.check(schema, value, &mut String::new(), 0);
+    if validation.errors.is_empty() { Ok(()) } else { Err(validation.errors) }
+}
+
+struct Validation<'a> {
+    doc: &'a Json,
+    errors: Vec<ValidationError>,
+}
+
+fn type_name(value: &Json) -> &'static str {
+    match *value {
+        Json::I64(_) | Json::U64(_) => "integer",
+        Json::F64(_) => "number",
+        Json::String(_) | Json::Wtf8(_) => "string",
+        Json::Boolean(_) => "boolean",
+        Json::Array(_) => "array",
+        Json::Object(_) => "object",
+        Json::Null => "null",
+    }
+}
+
+fn has_type(value: &Json, ty: &str) -> bool {
+    match (ty, value) {
+        ("integer", &Json::F64(f)) => f.fract() == 0.0,
+        ("number", _) => value.is_number(),
+        (ty, value) => type_name(value) == ty,
+    }
+}
+
+// Whether `a` and `b` are equal, numbers by their values.
+fn same(a: &Json, b: &Json) -> bool {
+    match (a, b) {
+        (Json::Array(a), Json::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Json::Object(a), Json::Object(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|((ka, a), (kb, b))| ka == kb && same(a, b))
+        }
+        _ if a.is_number() && b.is_number() => a.as_f64() == b.as_f64(),
+        _ => a == b,
+    }
+}
+
+fn escape_token(token: &str, pointer: &mut String) {
+    pointer.push('/');
+    pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+}
+
+impl<'a> Validation<'a> {
+    fn fail(&mut self, pointer: &str, message: String) {
+        self.errors.push(error(pointer, message));
+    }
+
+    // Whether `value` matches `schema`, without recording why not.
+    fn passes(&self, schema: &Json, value: &Json, depth: usize) -> bool {
+        let mut validation = Validation { doc: self.doc, errors: Vec::new() };
+        validation.check(schema, value, &mut String::new(), depth);
+        validation.errors.is_empty()
+    }
+
+    fn check(&mut self, schema: &Json, value: &Json, pointer: &mut String, depth: usize) {
+        if depth > MAX_DEPTH {
+            return self.fail(pointer, "schema nested too deeply".to_string());
+        }
+        let object = match *schema {
+            Json::Boolean(false) => return self.fail(pointer, "no value is allowed".to_string()),
+            Json::Object(ref object) => object,
+            _ => return,
+        };
+        let doc = self.doc;
+        if let Some(reference) = object.get("$ref").and_then(|r| r.as_string()) {
+            if let Ok(target) = refs::target(doc, reference) {
+                self.check(target, value, pointer, depth + 1);
+            }
+        }
+        let get = |key: &str| object.get(key);
+        if value.is_null() && get("nullable").and_then(|n| n.as_boolean()) == Some(true) {
+            return;
+        }
+        if let Some(ty) = get("type") {
+            let types: Vec<&str> = match *ty {
+                Json::Array(ref types) => types.iter().filter_map(|t| t.as_string()).collect(),
+                _ => ty.as_string().into_iter().collect(),
+            };
+            if !types.iter().any(|ty| has_type(value, ty)) {
+                let message = format!("expected {}, found {}", types.join(" or "),
+                                      type_name(value));
+                return self.fail(pointer, message);
+            }
+        }
+        if let Some(values) = get("enum").and_then(|e| e.as_array()) {
+            if !values.iter().any(|v| same(v, value)) {
+                self.fail(pointer, "not one of the allowed values".to_string());
+            }
+        }
+        if let Some(constant) = get("const") {
+            if !same(constant, value) {
+                self.fail(pointer, format!("expected {}", constant));
+            }
+        }
+
+        match *value {
+            Json::Object(ref members) => self.check_object(object, members, pointer, depth),
+            Json::Array(ref elements) => {
+                self.check_count(object, elements.len(), "minItems", "maxItems", "elements",
+                                 pointer);
+                if get("uniqueItems").and_then(|u| u.as_boolean()) == Some(true) {
+                    let duplicate = (0..elements.len()).any(|i| {
+                        elements[i + 1..].iter().any(|e| same(e, &elements[i]))
+                    });
+                    if duplicate {
+                        self.fail(pointer, "elements are not unique".to_string());
+                    }
+                }
+                if let Some(items) = get("items") {
+                    let len = pointer.len();
+                    for (i, elt) in elements.iter().enumerate() {
+                        escape_token(&i.to_string(), pointer);
+                        self.check(items, elt, pointer, depth + 1);
+                        pointer.truncate(len);
+                    }
+                }
+            }
+            Json::String(ref s) => {
+                let len = s.chars().count();
+                self.check_count(object, len, "minLength", "maxLength", "characters", pointer);
+            }
+            _ => {}
+        }
+        if let Some(n) = value.as_f64() {
+            self.check_number(object, n, pointer);
+        }
+
+        if let Some(schemas) = get("allOf").and_then(|s| s.as_array()) {
+            for schema in schemas {
+                self.check(schema, value, pointer, depth + 1);
+            }
+        }
+        if let Some(schemas) = get("anyOf").and_then(|s| s.as_array()) {
+            if !schemas.iter().any(|schema| self.passes(schema, value, depth + 1)) {
+                self.fail(pointer, "matches none of the schemas of anyOf".to_string());
+            }
+        }
+        if let Some(schemas) = get("oneOf").and_then(|s| s.as_array()) {
+            let matching = schemas.iter().filter(|s| self.passes(s, value, depth + 1)).count();
+            if matching != 1 {
+                let message = format!("matches {} of the schemas of oneOf, not one", matching);
+                self.fail(pointer, message);
+            }
+        }
+        if let Some(schema) = get("not") {
+            if self.passes(schema, value, depth + 1) {
+                self.fail(pointer, "matches the schema of not".to_string());
+            }
+        }
+    }
+
+    fn check_object(&mut self, schema: &Object, members: &Object, pointer: &mut String,
+                    depth: usize) {
+        self.check_count(schema, members.len(), "minProperties", "maxProperties", "members",
+                         pointer);
+        if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+            for key in required.iter().filter_map(|k| k.as_string()) {
+                if !members.contains_key(key) {
+                    self.fail(pointer, format!("missing required member {:?}", key));
+                }
+            }
+        }
+        let properties = schema.get("properties").and_then(|p| p.as_object());
+        let additional = schema.get("additionalProperties");
+        let len = pointer.len();
+        for (key, member) in members {
+            escape_token(key, pointer);
+            match properties.and_then(|p| p.get(key)).or(additional) {
+                Some(&Json::Boolean(false)) => {
+                    let message = format!("unexpected member {:?}", key);
+                    self.fail(&pointer[..len], message);
+                }
+                Some(property) => self.check(property, member, pointer, depth + 1),
+                None => {}
+            }
+            pointer.truncate(len);
+        }
+    }
+
+    fn check_count(&mut self, schema: &Object, n: usize, min: &str, max: &str, what: &str,
+                   pointer: &str) {
+        if let Some(min) = schema.get(min).and_then(|m| m.as_u64()) {
+            if (n as u64) < min {
+                self.fail(pointer, format!("fewer than {} {}", min, what));
+            }
+        }
+        if let Some(max) = schema.get(max).and_then(|m| m.as_u64()) {
+            if n as u64 > max {
+                self.fail(pointer, format!("more than {} {}", max, what));
+            }
+        }
+    }
+
+    fn check_number(&mut self, schema: &Object, n: f64, pointer: &str) {
+        let bound = |key: &str| schema.get(key).and_then(|b| b.as_f64());
+        // OpenAPI 3.0 makes the bounds exclusive with booleans.
+        let exclusive = |key: &str| schema.get(key).and_then(|b| b.as_boolean()) == Some(true);
+        if let Some(min) = bound("minimum") {
+            if n < min || (n == min && exclusive("exclusiveMinimum")) {
+                self.fail(pointer, format!("less than the minimum {}", min));
+            }
+        }
+        if let Some(max) = bound("maximum") {
+            if n > max || (n == max && exclusive("exclusiveMaximum")) {
+                self.fail(pointer, format!("more than the maximum {}", max));
+            }
+        }
+        if let Some(min) = bound("exclusiveMinimum") {
+            if n <= min {
+                self.fail(pointer, format!("not more than {}", min));
+            }
+        }
+        if let Some(max) = bound("exclusiveMaximum") {
+            if n >= max {
+                self.fail(pointer, format!("not less than {}", max));
+            }
+        }
+        if let Some(m) = bound("multipleOf") {
+            if m > 0.0 && (n / m).fract() != 0.0 {
+                self.fail(pointer, format!("not a multiple of {}", m));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::Json;
+    use json::openapi::{OpenApi, OpenApiError, ValidationError};
+    use json::refs::RefError;
+
+    fn api() -> OpenApi {
+        OpenApi::new(Json::from_str(r##"{
+            "openapi": "3.1.0",
+            "paths": {
+                "/items": {
+                    "get": {"responses": {
+                        "200": {"content": {"application/json": {"schema": {
+                            "type": "array", "items": {"$ref": "#/components/schemas/Item"},
+                            "maxItems": 2, "uniqueItems": true}}}},
+                        "4xx": {"$ref": "#/components/responses/Error"}}},
+                    "post": {"requestBody": {"content": {"application/merge-patch+json": {
+                        "schema": {"$ref": "#/components/schemas/Item"}}}},
+                             "responses": {"default": {}}}
+                },
+                "/items/{id}": {"get": {"responses": {}}},
+                "/items/new": {"get": {"responses": {}}}
+            },
+            "components": {
+                "schemas": {
+                    "Item": {
+                        "type": "object", "required": ["id", "kind"],
+                        "additionalProperties": false,
+                        "properties": {
+                            "id": {"type": "integer", "exclusiveMinimum": 0},
+                            "kind": {"enum": ["a", "b"]},
+                            "name": {"type": ["string", "null"], "minLength": 1,
+                                     "maxLength": 3},
+                            "price": {"type": "number", "maximum": 10, "multipleOf": 0.5},
+                            "tags": {"type": "array", "items": {"type": "string"},
+                                     "minItems": 1},
+                            "child": {"$ref": "#/components/schemas/Item"},
+                            "code": {"oneOf": [{"type": "integer"}, {"minimum": 5}]},
+                            "alt": {"anyOf": [{"type": "string"}, {"type": "boolean"}],
+                                    "not": {"const": "x"}}
+                        }
+                    },
+                    "Error": {"type": "object", "properties": {"message": {"type": "string"}},
+                              "required": ["message"]}
+                },
+                "responses": {"Error": {"content": {"application/json": {
+                    "schema": {"$ref": "#/components/schemas/Error"}}}}}
+            }
+        }"##).unwrap()).unwrap()
+    }
+
+    fn errors(result: Result<(), Vec<ValidationError>>) -> Vec<String> {
+        result.err().unwrap_or_default().iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_find() {
+        let api = api();
+        assert_eq!(api.endpoints().len(), 4);
+        let (endpoint, params) = api.find("get", "/items/12?full=1").unwrap();
+        assert_eq!((&endpoint.path[..], &params["id"][..]), ("/items/{id}", "12"));
+        let (endpoint, params) = api.find("GET", "/items/new").unwrap();
+        assert_eq!((&endpoint.path[..], params.len()), ("/items/new", 0));
+        assert_eq!(api.find("post", "/items").unwrap().0.method, "post");
+        assert!(api.find("get", "/items/").is_none());
+        assert!(api.find("delete", "/items").is_none());
+        assert!(api.find("get", "/items/1/x").is_none());
+    }
+
+    #[test]
+    fn test_validate() {
+        let api = api();
+        let post = api.find("post", "/items").unwrap().0;
+        let item = |s: &str| Json::from_str(s).unwrap();
+        assert!(post.validate_request(None).is_ok());
+        assert!(post.validate_request(Some(&item(r#"{"id": 1, "kind": "a", "name": null,
+            "price": 9.5, "tags": ["t"], "code": 2.0, "alt": true,
+            "child": {"id": 2, "kind": "b"}}"#))).is_ok());
+        let bad = item(r#"{"id": 0, "kind": "c", "name": "long", "price": 10.25, "tags": [],
+                           "extra": 1, "child": {"id": 1.5, "kind": "a", "tags": [3]},
+                           "code": 7, "alt": "x"}"#);
+        assert_eq!(errors(post.validate_request(Some(&bad))),
+                   ["/alt: matches the schema of not",
+                    "/child/id: expected integer, found number",
+                    "/child/tags/0: expected string, found integer",
+                    "/code: matches 2 of the schemas of oneOf, not one",
+                    "unexpected member \"extra\"",
+                    "/id: not more than 0",
+                    "/kind: not one of the allowed values",
+                    "/name: more than 3 characters",
+                    "/price: more than the maximum 10",
+                    "/price: not a multiple of 0.5",
+                    "/tags: fewer than 1 elements"]);
+
+        let get = api.find("get", "/items").unwrap().0;
+        assert!(get.validate_response(200, &item(r#"[{"id": 1, "kind": "a"}]"#)).is_ok());
+        assert_eq!(errors(get.validate_response(200, &item(r#"[{"id": 1, "kind": "a"},
+                                                             {"id": 1, "kind": "a"}, 3]"#))),
+                   ["more than 2 elements", "elements are not unique",
+                    "/2: expected object, found integer"]);
+        assert_eq!(errors(get.validate_response(404, &item("{}"))),
+                   ["missing required member \"message\""]);
+        assert_eq!(errors(get.validate_response(500, &item("{}"))),
+                   ["undocumented response status 500"]);
+        assert!(post.validate_response(500, &item("{}")).is_ok());
+        assert!(api.find("get", "/items/1").unwrap().0.validate_request(Some(&bad)).is_ok());
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let doc = |s: &str| Json::from_str(s).unwrap();
+        assert_eq!(OpenApi::new(doc("{}")).err(), Some(OpenApiError::NoPaths));
+        assert_eq!(OpenApi::new(doc(r##"{"paths": {"/": {"$ref": "#/nope"}}}"##)).err(),
+                   Some(OpenApiError::RefError(RefError::Unresolved("#/nope".to_string()))));
+
+        let api = OpenApi::new(doc(r##"{"paths": {"/": {"post": {"requestBody": {
+            "required": true, "content": {"application/json": {"schema": {"$ref": "#/loop"}}}}}}},
+            "loop": {"$ref": "#/loop"}}"##)).unwrap();
+        let endpoint = &api.endpoints()[0];
+        assert_eq!(errors(endpoint.validate_request(None)), ["missing required request body"]);
+        assert_eq!(errors(endpoint.validate_request(Some(&Json::Null))),
+                   ["schema nested too deeply"]);
+    }
+}
@@ -0,0 +1,257 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Decoding configuration from layers of sources
+//!
+//! An `Overlay` holds layers of configuration, added in order of priority,
+//! highest first: JSON documents and files, environment variables, and
+//! defaults given by a value. `decode` merges them, each over the ones
+//! added after it, with `Json::deep_merge`, and decodes the result with any
+//! `Decodable` type. A field is thus read from the first layer which has
+//! it, and a struct within the configuration is merged field by field.
+//!
+//! An environment variable named with the layer's prefix sets the field
+//! named by the rest of its name, lowercased, with `__` between the names
+//! of nested fields: with the prefix `APP_`, `APP_DB__HOST` sets the field
+//! `host` of the field `db`. Its value is read as a string, which the
+//! decoder also reads as a number or boolean where one is expected, unless
+//! it is a JSON array or object.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::Json;
+//! use rustc_serialize::json::overlay::Overlay;
+//! # use rustc_serialize::{Decodable, Decoder};
+//!
+//! struct Config {
+//!     name: String,
+//!     port: u16,
+//!     pool: u32,
+//! }
+//! # impl Decodable for Config {
+//! #     fn decode<D: Decoder>(d: &mut D) -> Result<Config, D::Error> {
+//! #         d.read_struct("Config", 3, |d| Ok(Config {
+//! #             name: try!(d.read_struct_field("name", 0, Decodable::decode)),
+//! #             port: try!(d.read_struct_field("port", 1, Decodable::decode)),
+//! #             pool: try!(d.read_struct_field("pool", 2, Decodable::decode)),
+//! #         }))
+//! #     }
+//! # }
+//!
+//! fn main() {
+//!     let mut overlay = Overlay::new();
+//!     overlay.add_json(Json::from_str(r#"{"port": 8080}"#).unwrap());
+//!     overlay.add_vars("APP_", vec![("APP_POOL".to_string(), "16".to_string()),
+//!                                   ("HOME".to_string(), "/root".to_string())]);
+//!     overlay.add_json(Json::from_str(r#"{"name": "app", "port": 80, "pool": 4}"#).unwrap());
+//!
+//!     let config: Config = overlay.decode().unwrap();
+//!     assert_eq!(config.name, "app");
+//!     assert_eq!((config.port, config.pool), (8080, 16));
+//! }
+//! ```
+
+use std::env;
+use std::fs::File;
+use std::path::Path;
+
+use Decodable;
+use Encodable;
+use json::{self, DecodeResult, Decoder, EncodeResult, Json, MapKeyPolicy, MergeStrategy,
+           Object};
+
+/// Layers of configuration, merged and decoded by `decode`; see the module
+/// documentation.
+#[derive(Clone, Debug, Default)]
+pub struct Overlay {
+    // The layers, highest priority first.
+    layers: Vec<Json>,
+    strategy: Option<MergeStrategy>,
+}
+
+impl Overlay {
+    /// Creates an overlay without layers.
+    pub fn new() -> Overlay {
+        Overlay { layers: Vec::new(), strategy: None }
+    }
+
+    /// Set how an array in a layer merges with one in the layers after it;
+    /// by default it replaces it. This should be set before decoding.
+    pub fn set_merge_strategy(&mut self, strategy: MergeStrategy) {
+        self.strategy = Some(strategy);
+    }
+
+    /// Adds a layer holding `json`.
+    pub fn add_json(&mut self, json: Json) {
+        self.layers.push(json);
+    }
+
+    /// Adds a layer holding the JSON document in the file at `path`.
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P) -> DecodeResult<()> {
+        let mut file = try!(File::open(path).map_err(|e| json::ParseError(From::from(e))));
+        let json = try!(Json::from_reader(&mut file));
+        self.add_json(json);
+        Ok(())
+    }
+
+    /// Adds a layer holding the values of the environment variables whose
+    /// names start with `prefix`.
+    pub fn add_env(&mut self, prefix: &str) {
+        self.add_vars(prefix, env::vars())
+    }
+
+    /// Adds a layer holding the values of the variables in `vars` whose
+    /// names start with `prefix`, as `add_env` does those of the
+    /// environment.
+    pub fn add_vars<I>(&mut self, prefix: &str, vars: I)
+        where I: IntoIterator<Item = (String, String)>
+    {
+        let mut vars: Vec<_> = vars.into_iter().filter(|(name, _)| {
+            name.len() > prefix.len() && name.starts_with(prefix)
+        }).collect();
+        vars.sort();
+        let mut layer = Json::Object(Object::new());
+        for (name, value) in vars {
+            let value = match Json::from_str(&value) {
+                Ok(json @ Json::Array(_)) | Ok(json @ Json::Object(_)) => json,
+                _ => Json::String(value.into()),
+            };
+            let path = name[prefix.len()..].to_lowercase();
+            let var = path.rsplit("__").fold(value, |value, key| {
+                let mut object = Object::new();
+                object.insert(key.to_string(), value);
+                Json::Object(object)
+            });
+            layer.deep_merge(var, MergeStrategy::Replace);
+        }
+        self.add_json(layer);
+    }
+
+    /// Adds a layer holding the encoding of `defaults`.
+    pub fn add_defaults<T: Encodable>(&mut self, defaults: &T) -> EncodeResult<()> {
+        let json = try!(json::to_value(defaults));
+        self.add_json(json);
+        Ok(())
+    }
+
+    /// Returns the layers merged.
+    pub fn json(&self) -> Json {
+        let strategy = self.strategy.clone().unwrap_or(MergeStrategy::Replace);
+        let mut merged = Json::Null;
+        for layer in self.layers.iter().rev() {
+            merged.deep_merge(layer.clone(), strategy.clone());
+        }
+        merged
+    }
+
+    /// Decodes a `T` from the layers merged.
+    ///
+    /// Variables hold strings, which numbers are read from, and booleans
+    /// too, read as a decoder with `MapKeyPolicy::StringifyAll` reads them.
+    pub fn decode<T: Decodable>(&self) -> DecodeResult<T> {
+        let mut decoder = Decoder::new(self.json());
+        decoder.set_map_key_policy(MapKeyPolicy::StringifyAll);
+        Decodable::decode(&mut decoder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use json::{DecoderError, Json, MergeStrategy};
+    use json::overlay::Overlay;
+    use {Decodable, Decoder};
+
+    #[derive(PartialEq, Debug)]
+    struct Server {
+        host: String,
+        port: u16,
+        tls: bool,
+        tags: Vec<String>,
+        limit: Option<f64>,
+    }
+
+    impl Decodable for Server {
+        fn decode<D: Decoder>(d: &mut D) -> Result<Server, D::Error> {
+            d.read_struct("Server", 5, |d| {
+                Ok(Server {
+                    host: try!(d.read_struct_field("host", 0, Decodable::decode)),
+                    port: try!(d.read_struct_field("port", 1, Decodable::decode)),
+                    tls: try!(d.read_struct_field("tls", 2, Decodable::decode)),
+                    tags: try!(d.read_struct_field("tags", 3, Decodable::decode)),
+                    limit: try!(d.read_struct_field("limit", 4, Decodable::decode)),
+                })
+            })
+        }
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_overlay() {
+        let mut overlay = Overlay::new();
+        overlay.add_vars("SRV_", vars(&[("SRV_PORT", "9000"), ("SRV_TLS", "true"),
+                                        ("SRV_TAGS", "[\"b\"]"), ("SRV_", "x"), ("PORT", "1")]));
+        overlay.add_json(Json::from_str("{\"host\": \"example.com\", \"port\": 8080}").unwrap());
+        overlay.add_json(Json::from_str("{\"host\": \"localhost\", \"tls\": false, \
+                                          \"tags\": [\"a\"], \"limit\": null}").unwrap());
+        assert_eq!(overlay.decode::<Server>().unwrap(), Server {
+            host: "example.com".to_string(),
+            port: 9000,
+            tls: true,
+            tags: vec!["b".to_string()],
+            limit: None,
+        });
+
+        overlay.set_merge_strategy(MergeStrategy::Concat);
+        assert_eq!(overlay.json()["tags"].to_string(), "[\"a\",\"b\"]");
+
+        let mut nested = Overlay::new();
+        nested.add_vars("A_", vars(&[("A_SRV__HOST", "h"), ("A_SRV__LIMIT", "1.5")]));
+        nested.add_json(Json::from_str("{\"srv\": {\"host\": \"x\", \"port\": 1}}").unwrap());
+        assert_eq!(nested.json().to_string(),
+                   "{\"srv\":{\"host\":\"h\",\"limit\":\"1.5\",\"port\":1}}");
+
+        let mut missing = Overlay::new();
+        missing.add_vars("SRV_", vars(&[("SRV_HOST", "h"), ("SRV_PORT", "high")]));
+        match missing.decode::<Server>() {
+            Err(DecoderError::ExpectedError(ref expected, ref found)) => {
+                assert_eq!((&expected[..], &found[..]), ("Number", "high"))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_overlay_sources() {
+        let path = env::temp_dir().join("rustc_serialize_test_overlay.json");
+        File::create(&path).unwrap().write_all(b"{\"host\": \"file\", \"port\": 1}").unwrap();
+        env::set_var("RUSTC_SERIALIZE_TEST_OVERLAY_PORT", "2");
+
+        let mut overlay = Overlay::new();
+        overlay.add_env("RUSTC_SERIALIZE_TEST_OVERLAY_");
+        overlay.add_file(&path).unwrap();
+        let mut defaults = BTreeMap::new();
+        defaults.insert("port", 3);
+        defaults.insert("workers", 4);
+        overlay.add_defaults(&defaults).unwrap();
+        assert_eq!(overlay.json().to_string(),
+                   "{\"host\":\"file\",\"port\":\"2\",\"workers\":4}");
+        fs::remove_file(&path).unwrap();
+        assert!(overlay.add_file(&path).is_err());
+    }
+}
@@ -0,0 +1,1055 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Selecting nodes of a `Json` tree with JSONPath queries
+//!
+//! A `Query` is a subset of JSONPath (RFC 9535). A query starts with `$`,
+//! the root, followed by any number of segments, each selecting nodes
+//! beneath those selected by the segments before it:
+//!
+//! * `.name` or `['name']` selects an object member, and `.*` or `[*]`
+//!   every member or element.
+//! * `[1]` selects an array element, counting from the end if negative,
+//!   and `[start:end:step]` a slice of elements, as Python slices do.
+//! * `[?expr]` or `[?(expr)]` selects the members or elements for which
+//!   `expr` holds. In `expr`, `@` is the member or element, and `$` the
+//!   root, each followed by names and indices (`@.price`, `@['a b'][0]`).
+//!   A path on its own tests that it exists; paths and literals (numbers,
+//!   strings, `true`, `false` and `null`) compare with `==`, `!=`, `<`,
+//!   `<=`, `>` and `>=`; and tests combine with `!`, `&&`, `||` and
+//!   parentheses. Numbers compare with numbers and strings with strings;
+//!   other orderings are false, as are comparisons of a path which does not
+//!   exist, except that two such paths are equal.
+//! * Several selectors in one bracket, such as `[0,2]` or `['a','b']`,
+//!   select the nodes each of them selects, in turn.
+//! * `..` before any of those applies it to the node and all of its
+//!   descendants, as in `$..author`.
+//!
+//! Each matched node comes with its path, as a JSON Pointer (RFC 6901).
+//!
+//! A `StreamQuery` applies a query to a document as it is read, without
+//! building it: each match is built and returned in turn, in document
+//! order, and everything else is parsed and thrown away. Queries for which
+//! this needs more than the one value being read, those with `..`, negative
+//! indices or steps, or `$` in a filter, are not supported. A filter's
+//! candidate is built to test it, and the matches within it are returned
+//! before reading on.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::Json;
+//! use rustc_serialize::json::query::{self, StreamQuery};
+//!
+//! fn main() {
+//!     let store = Json::from_str(r#"{"store": {"book": [
+//!         {"author": "Rees", "price": 8.95},
+//!         {"author": "Waugh", "price": 12.99},
+//!         {"author": "Melville", "price": 8.99, "isbn": "0-553-21311-3"}
+//!     ]}}"#).unwrap();
+//!
+//!     let authors: Vec<_> = query::select(&store, "$.store.book[*].author").unwrap()
+//!                               .into_iter().map(|m| m.value.as_string().unwrap())
+//!                               .collect();
+//!     assert_eq!(authors, ["Rees", "Waugh", "Melville"]);
+//!
+//!     let cheap = query::select(&store, "$..book[?(@.price < 10 && !@.isbn)]").unwrap();
+//!     assert_eq!(cheap.len(), 1);
+//!     assert_eq!(cheap[0].path, "/store/book/0");
+//!
+//!     let doc = r#"{"count": 2, "items": [{"id": 1}, {"id": 2}]}"#;
+//!     let query = StreamQuery::new("$.items[*].id").unwrap();
+//!     let ids: Vec<u32> = query.matches(doc.as_bytes()).map(|m| m.unwrap().decode().unwrap())
+//!                              .collect();
+//!     assert_eq!(ids, [1, 2]);
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::error;
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+use Decodable;
+use json::{Builder, BuilderError, DecodeResult, Decoder, Json, JsonEvent, StackElement};
+use json::ErrorCode::NotUtf8;
+use json::ParserError::SyntaxError;
+use utf8::{CharIndicesBuffer, InvalidUtf8};
+
+/// A parsed query; see the module documentation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    segments: Vec<Segment>,
+}
+
+/// A node selected by a query
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match<'a> {
+    /// The JSON Pointer of the node
+    pub path: String,
+    /// The node
+    pub value: &'a Json,
+}
+
+/// Errors that can occur parsing a query, with their byte offsets in it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryError {
+    /// A character was not valid where it appeared
+    UnexpectedChar(usize),
+    /// The query ended in the middle of a segment or filter
+    UnexpectedEnd,
+    /// A number or string was malformed, or a number too large
+    InvalidLiteral(usize),
+    /// The query cannot be applied to a stream; see `StreamQuery`
+    NotStreamable,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueryError::UnexpectedChar(pos) => write!(f, "unexpected character at {}", pos),
+            QueryError::UnexpectedEnd => write!(f, "unexpected end of query"),
+            QueryError::InvalidLiteral(pos) => write!(f, "invalid literal at {}", pos),
+            QueryError::NotStreamable => write!(f, "query cannot be applied to a stream"),
+        }
+    }
+}
+
+impl error::Error for QueryError {
+    fn description(&self) -> &str { "invalid JSONPath query" }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Segment {
+    descendants: bool,
+    selectors: Vec<Selector>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Selector {
+    Name(String),
+    Wildcard,
+    Index(i64),
+    Slice(Option<i64>, Option<i64>, i64),
+    Filter(Expr),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Key {
+    Name(String),
+    Index(i64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    // Whether the path starts from the root rather than the current node.
+    Path(bool, Vec<Key>),
+    Literal(Json),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Exists(Operand),
+    Compare(Operand, Op, Operand),
+}
+
+impl Query {
+    /// Parses a query.
+    pub fn parse(s: &str) -> Result<Query, QueryError> {
+        QueryParser { src: s, pos: 0 }.query()
+    }
+
+    /// Returns the nodes of `root` which the query selects, in order.
+    pub fn select<'a>(&self, root: &'a Json) -> Vec<Match<'a>> {
+        select_segments(&self.segments, root, Match { path: String::new(), value: root })
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+    fn from_str(s: &str) -> Result<Query, QueryError> { Query::parse(s) }
+}
+
+/// Parses `query` and returns the nodes of `root` which it selects.
+pub fn select<'a>(root: &'a Json, query: &str) -> Result<Vec<Match<'a>>, QueryError> {
+    Query::parse(query).map(|q| q.select(root))
+}
+
+// Applies `segments` to `start`, a node of `root`.
+fn select_segments<'a>(segments: &[Segment], root: &'a Json, start: Match<'a>)
+                       -> Vec<Match<'a>> {
+    let mut nodes = vec![start];
+    for segment in segments {
+        let mut next = Vec::new();
+        for node in nodes {
+            if segment.descendants {
+                let mut stack = vec![node];
+                while let Some(node) = stack.pop() {
+                    select_children(&segment.selectors, root, &node, &mut next);
+                    let len = stack.len();
+                    push_children(&node, &mut stack);
+                    stack[len..].reverse();
+                }
+            } else {
+                select_children(&segment.selectors, root, &node, &mut next);
+            }
+        }
+        nodes = next;
+    }
+    nodes
+}
+
+fn child<'a>(parent: &Match<'a>, key: &str, value: &'a Json) -> Match<'a> {
+    let mut path = parent.path.clone();
+    path.push('/');
+    path.push_str(&key.replace("~", "~0").replace("/", "~1"));
+    Match { path: path, value: value }
+}
+
+fn element<'a>(parent: &Match<'a>, idx: usize, value: &'a Json) -> Match<'a> {
+    Match { path: format!("{}/{}", parent.path, idx), value: value }
+}
+
+fn push_children<'a>(node: &Match<'a>, out: &mut Vec<Match<'a>>) {
+    match *node.value {
+        Json::Array(ref a) => out.extend(a.iter().enumerate().map(|(i, v)| element(node, i, v))),
+        Json::Object(ref o) => out.extend(o.iter().map(|(k, v)| child(node, k, v))),
+        _ => {}
+    }
+}
+
+// Resolves a possibly negative index into an array of `len` elements.
+fn index(idx: i64, len: usize) -> Option<usize> {
+    let idx = if idx < 0 { idx + len as i64 } else { idx };
+    if idx >= 0 && (idx as u64) < len as u64 { Some(idx as usize) } else { None }
+}
+
+fn slice(start: Option<i64>, end: Option<i64>, step: i64, len: usize) -> Vec<usize> {
+    let len = len as i64;
+    let norm = |i: i64| if i < 0 { i + len } else { i };
+    let mut idxs = Vec::new();
+    if step > 0 {
+        let mut i = norm(start.unwrap_or(0)).max(0).min(len);
+        let upper = norm(end.unwrap_or(len)).max(0).min(len);
+        while i < upper {
+            idxs.push(i as usize);
+            i += step;
+        }
+    } else if step < 0 {
+        let mut i = start.map_or(len - 1, &norm).max(-1).min(len - 1);
+        let lower = end.map_or(-1, &norm).max(-1).min(len - 1);
+        while i > lower {
+            idxs.push(i as usize);
+            i += step;
+        }
+    }
+    idxs
+}
+
+fn select_children<'a>(selectors: &[Selector], root: &'a Json, node: &Match<'a>,
+                       out: &mut Vec<Match<'a>>) {
+    for selector in selectors {
+        match (selector, node.value) {
+            (Selector::Name(name), Json::Object(o)) => {
+                if let Some(v) = o.get(name) {
+                    out.push(child(node, name, v));
+                }
+            }
+            (&Selector::Wildcard, _) => push_children(node, out),
+            (&Selector::Index(idx), Json::Array(a)) => {
+                if let Some(i) = index(idx, a.len()) {
+                    out.push(element(node, i, &a[i]));
+                }
+            }
+            (&Selector::Slice(start, end, step), Json::Array(a)) => {
+                for i in slice(start, end, step, a.len()) {
+                    out.push(element(node, i, &a[i]));
+                }
+            }
+            (Selector::Filter(expr), _) => {
+                let mut children = Vec::new();
+                push_children(node, &mut children);
+                out.extend(children.into_iter().filter(|c| expr.holds(root, c.value)));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Operand {
+    fn resolve<'a>(&'a self, root: &'a Json, current: &'a Json) -> Option<&'a Json> {
+        let (from_root, keys) = match *self {
+            Operand::Path(from_root, ref keys) => (from_root, keys),
+            Operand::Literal(ref json) => return Some(json),
+        };
+        let mut target = if from_root { root } else { current };
+        for key in keys {
+            target = match (key, target) {
+                (Key::Name(name), Json::Object(o)) => o.get(name)?,
+                (&Key::Index(idx), Json::Array(a)) => match index(idx, a.len()) {
+                    Some(i) => &a[i],
+                    None => return None,
+                },
+                _ => return None,
+            };
+        }
+        Some(target)
+    }
+}
+
+fn equal(a: &Json, b: &Json) -> bool {
+    match (a.is_number() && b.is_number(), a.as_f64(), b.as_f64()) {
+        (true, Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn less(a: &Json, b: &Json) -> bool {
+    match (a, b) {
+        (Json::String(a), Json::String(b)) => a < b,
+        _ => match (a.is_number() && b.is_number(), a.as_f64(), b.as_f64()) {
+            (true, Some(a), Some(b)) => a < b,
+            _ => false,
+        },
+    }
+}
+
+impl Expr {
+    fn holds(&self, root: &Json, current: &Json) -> bool {
+        match *self {
+            Expr::Or(ref a, ref b) => a.holds(root, current) || b.holds(root, current),
+            Expr::And(ref a, ref b) => a.holds(root, current) && b.holds(root, current),
+            Expr::Not(ref a) => !a.holds(root, current),
+            Expr::Exists(ref a) => a.resolve(root, current).is_some(),
+            Expr::Compare(ref a, op, ref b) => {
+                match (a.resolve(root, current), b.resolve(root, current)) {
+                    (Some(a), Some(b)) => match op {
+                        Op::Eq => equal(a, b),
+                        Op::Ne => !equal(a, b),
+                        Op::Lt => less(a, b),
+                        Op::Le => less(a, b) || (equal(a, b) && less_or_equal_typed(a, b)),
+                        Op::Gt => less(b, a),
+                        Op::Ge => less(b, a) || (equal(a, b) && less_or_equal_typed(a, b)),
+                    },
+                    (None, None) => op == Op::Eq,
+                    _ => op == Op::Ne,
+                }
+            }
+        }
+    }
+}
+
+// Whether equal values `a` and `b` are of a type with an ordering, for
+// `<=` and `>=`.
+fn less_or_equal_typed(a: &Json, b: &Json) -> bool {
+    (a.is_number() && b.is_number()) || (a.is_string() && b.is_string())
+}
+
+/// A query applied to documents as they are read; see the module
+/// documentation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamQuery {
+    segments: Vec<Segment>,
+}
+
+/// A node selected by a `StreamQuery`
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamMatch {
+    /// The JSON Pointer of the node
+    pub path: String,
+    /// The node
+    pub value: Json,
+}
+
+impl StreamMatch {
+    /// Decodes the node.
+    pub fn decode<T: Decodable>(self) -> DecodeResult<T> {
+        Decodable::decode(&mut Decoder::new(self.value))
+    }
+}
+
+impl StreamQuery {
+    /// Parses a query, checking that it can be applied to a stream.
+    pub fn new(s: &str) -> Result<StreamQuery, QueryError> {
+        StreamQuery::from_query(try!(Query::parse(s)))
+    }
+
+    /// Checks that `query` can be applied to a stream.
+    pub fn from_query(query: Query) -> Result<StreamQuery, QueryError> {
+        for segment in &query.segments {
+            if segment.descendants || !segment.selectors.iter().all(Selector::streamable) {
+                return Err(QueryError::NotStreamable);
+            }
+        }
+        Ok(StreamQuery { segments: query.segments })
+    }
+
+    /// Returns the nodes the query selects in the document read from `rdr`,
+    /// as they are read.
+    ///
+    /// Each is built when its last character has been read, and an error
+    /// in the document ends the matches, so those found before it are
+    /// returned first.
+    pub fn matches<R: Read>(&self, rdr: R) -> StreamMatches<'_, R> {
+        let chars = ReadChars { chars: CharIndicesBuffer::new(rdr), error: None };
+        StreamMatches {
+            segments: &self.segments,
+            builder: Builder::new(chars),
+            frames: Vec::new(),
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+}
+
+impl Selector {
+    fn streamable(&self) -> bool {
+        match *self {
+            Selector::Index(idx) => idx >= 0,
+            Selector::Slice(start, end, step) => {
+                start.unwrap_or(0) >= 0 && end.unwrap_or(0) >= 0 && step >= 0
+            }
+            Selector::Filter(ref expr) => !expr.uses_root(),
+            Selector::Name(_) | Selector::Wildcard => true,
+        }
+    }
+
+    // How many times this selects the child `key` or `idx` of a node.
+    fn count(&self, key: &StackElement) -> usize {
+        let selected = match (self, key) {
+            (Selector::Name(name), &StackElement::Key(key)) => name == key,
+            (&Selector::Wildcard, _) => true,
+            (&Selector::Index(i), &StackElement::Index(idx)) => i == idx as i64,
+            (&Selector::Slice(start, end, step), &StackElement::Index(idx)) => {
+                let (idx, start) = (idx as i64, start.unwrap_or(0));
+                step > 0 && idx >= start && end.map_or(true, |end| idx < end)
+                    && (idx - start) % step == 0
+            }
+            _ => false,
+        };
+        selected as usize
+    }
+}
+
+impl Expr {
+    fn uses_root(&self) -> bool {
+        let is_root = |a: &Operand| match *a {
+            Operand::Path(from_root, _) => from_root,
+            Operand::Literal(_) => false,
+        };
+        match *self {
+            Expr::Or(ref a, ref b) | Expr::And(ref a, ref b) => a.uses_root() || b.uses_root(),
+            Expr::Not(ref a) => a.uses_root(),
+            Expr::Exists(ref a) => is_root(a),
+            Expr::Compare(ref a, _, ref b) => is_root(a) || is_root(b),
+        }
+    }
+}
+
+// An array or object being read which the segments before its depth have
+// selected, `count` times in all.
+struct Frame {
+    path: String,
+    count: usize,
+}
+
+/// An iterator over the nodes a `StreamQuery` selects in a document; see
+/// `StreamQuery::matches`.
+pub struct StreamMatches<'a, R> {
+    segments: &'a [Segment],
+    builder: Builder<ReadChars<R>>,
+    frames: Vec<Frame>,
+    pending: VecDeque<StreamMatch>,
+    done: bool,
+}
+
+impl<'a, R: Read> StreamMatches<'a, R> {
+    // Ends the matches with `e`, or with the read error which caused it.
+    fn error(&mut self, e: BuilderError) -> BuilderError {
+        self.done = true;
+        self.read_error().unwrap_or(e)
+    }
+
+    fn read_error(&mut self) -> Option<BuilderError> {
+        match self.builder.parser.rdr.error.take() {
+            Some(ref e) if InvalidUtf8::from_io_error(e).is_some() => {
+                Some(SyntaxError(NotUtf8, self.builder.parser.line, self.builder.parser.col))
+            }
+            Some(e) => Some(From::from(e)),
+            None => None,
+        }
+    }
+
+    // Handles the first event of a value, which is in the builder.
+    fn value(&mut self) -> Result<(), BuilderError> {
+        let depth = self.frames.len();
+        let container = matches!(self.builder.token,
+                                 Some(JsonEvent::ArrayStart) | Some(JsonEvent::ObjectStart));
+        let (mut path, mut count, mut filtered) = (String::new(), 1, false);
+        if let Some(parent) = self.frames.last() {
+            let key = self.builder.parser.stack().top().unwrap();
+            let selectors = &self.segments[depth - 1].selectors;
+            count = parent.count * selectors.iter().map(|s| s.count(&key)).sum::<usize>();
+            filtered = selectors.iter().any(|s| matches!(*s, Selector::Filter(_)));
+            if count > 0 || filtered {
+                path.push_str(&parent.path);
+                match key {
+                    StackElement::Key(key) => {
+                        path.push('/');
+                        path.push_str(&key.replace("~", "~0").replace("/", "~1"));
+                    }
+                    StackElement::Index(idx) => path.push_str(&format!("/{}", idx)),
+                }
+            }
+        }
+        if filtered {
+            self.filter(path, count)
+        } else if count == 0 {
+            if container {
+                try!(self.builder.parser.skip_value());
+            }
+            Ok(())
+        } else if depth == self.segments.len() {
+            let value = try!(self.builder.build_value());
+            self.push(count, StreamMatch { path: path, value: value });
+            Ok(())
+        } else {
+            if container {
+                self.frames.push(Frame { path: path, count: count });
+            }
+            Ok(())
+        }
+    }
+
+    // Handles a value which the filters in its segment may select, `count`
+    // times plus once for each that holds, by building it.
+    fn filter(&mut self, path: String, count: usize) -> Result<(), BuilderError> {
+        let depth = self.frames.len();
+        let parent = self.frames[depth - 1].count;
+        let value = try!(self.builder.build_value());
+        let count = self.segments[depth - 1].selectors.iter().filter(|s| match **s {
+            Selector::Filter(ref expr) => expr.holds(&value, &value),
+            _ => false,
+        }).count() * parent + count;
+        if count == 0 {
+            return Ok(());
+        }
+        let start = Match { path: String::new(), value: &value };
+        for m in select_segments(&self.segments[depth..], &value, start) {
+            let path = path.clone() + &m.path;
+            self.push(count, StreamMatch { path: path, value: m.value.clone() });
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, count: usize, m: StreamMatch) {
+        for _ in 1..count {
+            self.pending.push_back(m.clone());
+        }
+        self.pending.push_back(m);
+    }
+}
+
+impl<'a, R: Read> Iterator for StreamMatches<'a, R> {
+    type Item = Result<StreamMatch, BuilderError>;
+
+    fn next(&mut self) -> Option<Result<StreamMatch, BuilderError>> {
+        loop {
+            if let Some(m) = self.pending.pop_front() {
+                return Some(Ok(m));
+            }
+            if self.done {
+                return None;
+            }
+            self.builder.bump();
+            match self.builder.token.take() {
+                Some(JsonEvent::ArrayEnd) | Some(JsonEvent::ObjectEnd) => {
+                    self.frames.pop();
+                    continue;
+                }
+                Some(JsonEvent::Error(e)) => return Some(Err(self.error(e))),
+                None => {
+                    self.done = true;
+                    return self.read_error().map(Err);
+                }
+                token => self.builder.token = token,
+            }
+            if let Err(e) = self.value() {
+                return Some(Err(self.error(e)));
+            }
+        }
+    }
+}
+
+// Feeds the characters read from a reader to a parser, stopping at the
+// first error.
+struct ReadChars<R> {
+    chars: CharIndicesBuffer<R>,
+    error: Option<io::Error>,
+}
+
+impl<R: Read> Iterator for ReadChars<R> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.error.is_some() {
+            return None;
+        }
+        match self.chars.next() {
+            Some(Ok((_, c))) => Some(c),
+            Some(Err(e)) => {
+                self.error = Some(e);
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+struct QueryParser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> QueryParser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.src[self.pos..].starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), QueryError> {
+        if self.eat(c.encode_utf8(&mut [0; 4])) { Ok(()) } else { Err(self.unexpected()) }
+    }
+
+    fn unexpected(&self) -> QueryError {
+        if self.pos == self.src.len() {
+            QueryError::UnexpectedEnd
+        } else {
+            QueryError::UnexpectedChar(self.pos)
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn query(&mut self) -> Result<Query, QueryError> {
+        try!(self.expect('$'));
+        let mut segments = Vec::new();
+        while self.pos < self.src.len() {
+            let descendants = self.eat("..");
+            let selectors = if self.peek() == Some('[') {
+                try!(self.bracket())
+            } else if !descendants && !self.eat(".") {
+                return Err(self.unexpected());
+            } else if self.eat("*") {
+                vec![Selector::Wildcard]
+            } else {
+                vec![Selector::Name(try!(self.name()))]
+            };
+            segments.push(Segment { descendants: descendants, selectors: selectors });
+        }
+        Ok(Query { segments: segments })
+    }
+
+    fn name(&mut self) -> Result<String, QueryError> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !(c.is_alphanumeric() || c == '_') {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        if self.pos == start {
+            return Err(self.unexpected());
+        }
+        Ok(self.src[start..self.pos].to_string())
+    }
+
+    fn bracket(&mut self) -> Result<Vec<Selector>, QueryError> {
+        try!(self.expect('['));
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_whitespace();
+            selectors.push(try!(self.selector()));
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Ok(selectors);
+            }
+            try!(self.expect(','));
+        }
+    }
+
+    fn selector(&mut self) -> Result<Selector, QueryError> {
+        match self.peek() {
+            Some('\'') | Some('"') => self.string().map(Selector::Name),
+            Some('*') => {
+                self.pos += 1;
+                Ok(Selector::Wildcard)
+            }
+            Some('?') => {
+                self.pos += 1;
+                self.skip_whitespace();
+                self.or().map(Selector::Filter)
+            }
+            _ => {
+                let start = try!(self.opt_int());
+                self.skip_whitespace();
+                if !self.eat(":") {
+                    return match start {
+                        Some(idx) => Ok(Selector::Index(idx)),
+                        None => Err(self.unexpected()),
+                    };
+                }
+                self.skip_whitespace();
+                let end = try!(self.opt_int());
+                self.skip_whitespace();
+                let mut step = None;
+                if self.eat(":") {
+                    self.skip_whitespace();
+                    step = try!(self.opt_int());
+                }
+                Ok(Selector::Slice(start, end, step.unwrap_or(1)))
+            }
+        }
+    }
+
+    fn opt_int(&mut self) -> Result<Option<i64>, QueryError> {
+        let start = self.pos;
+        self.eat("-");
+        while self.peek().map_or(false, |c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        match &self.src[start..self.pos] {
+            "" => Ok(None),
+            s => s.parse().map(Some).map_err(|_| QueryError::InvalidLiteral(start)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, QueryError> {
+        let start = self.pos;
+        let quote = self.peek().unwrap();
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return Err(QueryError::UnexpectedEnd),
+            };
+            self.pos += c.len_utf8();
+            if c == quote {
+                return Ok(s);
+            }
+            if c != '\\' {
+                s.push(c);
+                continue;
+            }
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return Err(QueryError::UnexpectedEnd),
+            };
+            self.pos += c.len_utf8();
+            s.push(match c {
+                '\\' | '/' | '\'' | '"' => c,
+                'b' => '\x08',
+                'f' => '\x0c',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                _ => return Err(QueryError::InvalidLiteral(start)),
+            });
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = try!(self.and());
+        while self.eat("||") {
+            self.skip_whitespace();
+            expr = Expr::Or(Box::new(expr), Box::new(try!(self.and())));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = try!(self.unary());
+        while self.eat("&&") {
+            self.skip_whitespace();
+            expr = Expr::And(Box::new(expr), Box::new(try!(self.unary())));
+        }
+        Ok(expr)
+    }
+
+    // Parses a test, and any whitespace after it.
+    fn unary(&mut self) -> Result<Expr, QueryError> {
+        if self.eat("!") {
+            self.skip_whitespace();
+            return Ok(Expr::Not(Box::new(try!(self.unary()))));
+        }
+        if self.eat("(") {
+            self.skip_whitespace();
+            let expr = try!(self.or());
+            try!(self.expect(')'));
+            self.skip_whitespace();
+            return Ok(expr);
+        }
+        let start = self.pos;
+        let a = try!(self.operand());
+        self.skip_whitespace();
+        let op = if self.eat("==") {
+            Op::Eq
+        } else if self.eat("!=") {
+            Op::Ne
+        } else if self.eat("<=") {
+            Op::Le
+        } else if self.eat("<") {
+            Op::Lt
+        } else if self.eat(">=") {
+            Op::Ge
+        } else if self.eat(">") {
+            Op::Gt
+        } else {
+            return match a {
+                Operand::Path(..) => Ok(Expr::Exists(a)),
+                Operand::Literal(_) => Err(QueryError::UnexpectedChar(start)),
+            };
+        };
+        self.skip_whitespace();
+        let b = try!(self.operand());
+        self.skip_whitespace();
+        Ok(Expr::Compare(a, op, b))
+    }
+
+    fn operand(&mut self) -> Result<Operand, QueryError> {
+        let from_root = match self.peek() {
+            Some('@') => false,
+            Some('$') => true,
+            Some('\'') | Some('"') => {
+                return self.string().map(|s| Operand::Literal(Json::from(s)));
+            }
+            _ => return self.literal().map(Operand::Literal),
+        };
+        self.pos += 1;
+        let mut keys = Vec::new();
+        loop {
+            if self.eat(".") {
+                keys.push(Key::Name(try!(self.name())));
+            } else if self.eat("[") {
+                self.skip_whitespace();
+                match self.peek() {
+                    Some('\'') | Some('"') => keys.push(Key::Name(try!(self.string()))),
+                    _ => match try!(self.opt_int()) {
+                        Some(idx) => keys.push(Key::Index(idx)),
+                        None => return Err(self.unexpected()),
+                    },
+                }
+                self.skip_whitespace();
+                try!(self.expect(']'));
+            } else {
+                return Ok(Operand::Path(from_root, keys));
+            }
+        }
+    }
+
+    fn literal(&mut self) -> Result<Json, QueryError> {
+        for &(word, ref json) in [("true", Json::Boolean(true)),
+                                  ("false", Json::Boolean(false)),
+                                  ("null", Json::Null)].iter() {
+            if self.eat(word) {
+                return Ok(json.clone());
+            }
+        }
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E') {
+                break;
+            }
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(self.unexpected());
+        }
+        match Json::from_str(&self.src[start..self.pos]) {
+            Ok(ref json) if json.is_number() => Ok(json.clone()),
+            _ => Err(QueryError::InvalidLiteral(start)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read};
+    use json::{ErrorCode, Json, ParserError};
+    use json::query::{select, Query, QueryError, StreamMatch, StreamQuery};
+
+    fn paths(json: &Json, query: &str) -> Vec<String> {
+        select(json, query).unwrap().into_iter().map(|m| m.path).collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let json = Json::from_str(r#"{"a": [10, 11, 12, 13], "b": {"c": 1, "d/~": {"c": 2}},
+                                      "e f": null}"#).unwrap();
+        assert_eq!(paths(&json, "$"), [""]);
+        assert_eq!(paths(&json, "$.a[1]"), ["/a/1"]);
+        assert_eq!(paths(&json, "$['a'][-1]"), ["/a/3"]);
+        assert_eq!(paths(&json, "$.a[4]"), Vec::<String>::new());
+        assert_eq!(paths(&json, "$.a[1:3]"), ["/a/1", "/a/2"]);
+        assert_eq!(paths(&json, "$.a[::2]"), ["/a/0", "/a/2"]);
+        assert_eq!(paths(&json, "$.a[-2:]"), ["/a/2", "/a/3"]);
+        assert_eq!(paths(&json, "$.a[::-1]"), ["/a/3", "/a/2", "/a/1", "/a/0"]);
+        assert_eq!(paths(&json, "$.a[0, 3, 0]"), ["/a/0", "/a/3", "/a/0"]);
+        assert_eq!(paths(&json, "$.b.*"), ["/b/c", "/b/d~1~0"]);
+        assert_eq!(paths(&json, "$[\"e f\"]"), ["/e f"]);
+        assert_eq!(paths(&json, "$..c"), ["/b/c", "/b/d~1~0/c"]);
+        assert_eq!(paths(&json, "$..[0]"), ["/a/0"]);
+        assert_eq!(select(&json, "$..c").unwrap()[1].value, &Json::U64(2));
+        assert_eq!(paths(&json, "$.a.*").len(), 4);
+        assert_eq!(paths(&json, "$.b[0]"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_filters() {
+        let json = Json::from_str(r#"{"min": 2, "items": [
+            {"n": 1, "tag": "x"}, {"n": 2.0, "tag": "y"}, {"n": 3}, {"n": "3"}]}"#).unwrap();
+        let q = |query| paths(&json, query);
+        assert_eq!(q("$.items[?(@.n == 2)]"), ["/items/1"]);
+        assert_eq!(q("$.items[?@.n >= $.min]"), ["/items/1", "/items/2"]);
+        assert_eq!(q("$.items[?(@.n < 3)]"), ["/items/0", "/items/1"]);
+        assert_eq!(q("$.items[?(@.n <= '3')]"), ["/items/3"]);
+        assert_eq!(q("$.items[?(@.tag)]"), ["/items/0", "/items/1"]);
+        assert_eq!(q("$.items[?(!@.tag)]"), ["/items/2", "/items/3"]);
+        assert_eq!(q("$.items[?(@.tag != 'x')]"), ["/items/1", "/items/2", "/items/3"]);
+        assert_eq!(q("$.items[?(@.n > 1 && @.tag || @.n == '3')]"), ["/items/1", "/items/3"]);
+        assert_eq!(q("$.items[?(@.n > 1 && (@.tag || @.n == '3'))]"), ["/items/1"]);
+        assert_eq!(q("$.items[?(@.missing == @['other'])].n"),
+                   ["/items/0/n", "/items/1/n", "/items/2/n", "/items/3/n"]);
+        assert_eq!(q("$[?(@ == 2)]"), ["/min"]);
+        assert_eq!(q("$.items[?(@.n == 1e0)].tag"), ["/items/0/tag"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Query::parse(""), Err(QueryError::UnexpectedEnd));
+        assert_eq!(Query::parse("a"), Err(QueryError::UnexpectedChar(0)));
+        assert_eq!(Query::parse("$a"), Err(QueryError::UnexpectedChar(1)));
+        assert_eq!(Query::parse("$."), Err(QueryError::UnexpectedEnd));
+        assert_eq!(Query::parse("$[1"), Err(QueryError::UnexpectedEnd));
+        assert_eq!(Query::parse("$['a]"), Err(QueryError::UnexpectedEnd));
+        assert_eq!(Query::parse("$['\\q']"), Err(QueryError::InvalidLiteral(2)));
+        assert_eq!(Query::parse("$[99999999999999999999]"), Err(QueryError::InvalidLiteral(2)));
+        assert_eq!(Query::parse("$[?(1)]"), Err(QueryError::UnexpectedChar(4)));
+        assert_eq!(Query::parse("$[?(@.a == 1-)]"), Err(QueryError::InvalidLiteral(11)));
+        assert_eq!(Query::parse("$[?(@.a == 1]"), Err(QueryError::UnexpectedChar(12)));
+        assert!("$..a[?(@.b)][0:1]".parse::<Query>().is_ok());
+    }
+
+    fn stream(doc: &str, query: &str) -> Vec<Result<StreamMatch, ParserError>> {
+        StreamQuery::new(query).unwrap().matches(doc.as_bytes()).collect()
+    }
+
+    #[test]
+    fn test_stream_query() {
+        let doc = r#"{"skip": [{"a": 1}], "items": [{"id": 1, "t": ["x", "y"]}, 7,
+                      {"id": 2, "t/~": {"a": "b"}}, {"id": 3, "t": []}], "id": 0}"#;
+        let json = Json::from_str(doc).unwrap();
+        for query in ["$", "$.items", "$.items[*].id", "$.items[1:]", "$.items[0::2].t",
+                      "$.items[0].t[1]", "$.*[*][*]", "$.items[?(@.id > 1)].id",
+                      "$.items[?(@.id < 3)].t[*]", "$.items[?@.t][0]", "$.missing[*]"].iter() {
+            // Members are selected in document order rather than by key.
+            let mut expected: Vec<_> = select(&json, query).unwrap().into_iter()
+                                           .map(|m| (m.path, m.value.clone())).collect();
+            let mut found: Vec<_> = stream(doc, query).into_iter()
+                                        .map(|m| m.map(|m| (m.path, m.value)).unwrap())
+                                        .collect();
+            expected.sort_by(|a, b| a.0.cmp(&b.0));
+            found.sort_by(|a, b| a.0.cmp(&b.0));
+            assert_eq!(found, expected, "{}", query);
+        }
+
+        // Matches are in document order, once per selector selecting them.
+        let paths: Vec<_> = stream(doc, "$.items[2, 0, 0].id").into_iter()
+                                .map(|m| m.unwrap().path).collect();
+        assert_eq!(paths, ["/items/0/id", "/items/0/id", "/items/2/id"]);
+        let paths: Vec<_> = stream(doc, "$.items[*]['t/~', 't/~']").into_iter()
+                                .map(|m| m.unwrap().path).collect();
+        assert_eq!(paths, ["/items/2/t~1~0", "/items/2/t~1~0"]);
+
+        let ids = StreamQuery::new("$.items[*].id").unwrap().matches(doc.as_bytes())
+                      .map(|m| m.unwrap().decode::<u32>().unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids, [1, 2, 3]);
+
+        for query in ["$..id", "$.items[-1]", "$[::-1]", "$[-2:]", "$[?(@ == $.id)]"].iter() {
+            assert_eq!(StreamQuery::new(query), Err(QueryError::NotStreamable));
+        }
+        assert!(StreamQuery::new("$[?(@[-1] == 1)]").is_ok());
+    }
+
+    #[test]
+    fn test_stream_query_errors() {
+        // Matches before an error are returned first.
+        let found = stream("[1, 2, }", "$[*]");
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[1].as_ref().unwrap().value, Json::U64(2));
+        match found[2] {
+            Err(ParserError::SyntaxError(ErrorCode::InvalidSyntax, 1, 8)) => {}
+            ref other => panic!("{:?}", other),
+        }
+        match stream("[1] 2", "$[0]").pop() {
+            Some(Err(ParserError::SyntaxError(ErrorCode::TrailingCharacters, ..))) => {}
+            other => panic!("{:?}", other),
+        }
+
+        let found: Vec<_> = StreamQuery::new("$[*]").unwrap()
+                                .matches(&b"[\"a\", \"\xff\"]"[..]).collect();
+        match found[..] {
+            [Ok(_), Err(ParserError::SyntaxError(ErrorCode::NotUtf8, ..))] => {}
+            ref other => panic!("{:?}", other),
+        }
+
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::Other, "broken"))
+            }
+        }
+        match StreamQuery::new("$").unwrap().matches(Broken).collect::<Vec<_>>()[..] {
+            [Err(ParserError::IoError(_))] => {}
+            ref other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,516 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Parsing broken JSON documents as well as possible
+//!
+//! `parse_lenient` parses a document which may have errors in it, as a
+//! document being edited does, patching over each error it can and noting
+//! it in a `ParseDiagnostic`, so that editors and linters can still show
+//! the document's tree along with its errors. Among the patches:
+//!
+//! * a missing comma or colon is taken as written;
+//! * a trailing or doubled comma is dropped;
+//! * arrays, objects and strings left open at the end of the input, or
+//!   closed by the wrong bracket, are closed, and a string left open at
+//!   the end of a line is closed there;
+//! * a missing value, or one which cannot be parsed, is taken as `null`;
+//! * an object key which is not a string is taken as the text it is;
+//! * anything after the document is ignored.
+//!
+//! A document with no errors parses to the same value as with
+//! `Json::from_str`, and no diagnostics. The lines and columns of
+//! diagnostics are counted as in `ParserError::SyntaxError`, so that
+//! `to_error` converts one for display with `ParserError::snippet`.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::{ErrorCode, Json};
+//! use rustc_serialize::json::recover::{self, Repair};
+//!
+//! fn main() {
+//!     let (json, diagnostics) = recover::parse_lenient("{\"a\": [1 2,], \"b\": tru");
+//!     assert_eq!(json, Json::from_str("{\"a\": [1, 2], \"b\": null}").unwrap());
+//!     let repairs: Vec<Repair> = diagnostics.iter().map(|d| d.repair).collect();
+//!     assert_eq!(repairs, [Repair::InsertedComma, Repair::DroppedComma,
+//!                          Repair::Skipped, Repair::Closed]);
+//!     assert_eq!(diagnostics[3].code, ErrorCode::EOFWhileParsingObject);
+//! }
+//! ```
+
+use json::{Array, ErrorCode, Json, Object, ParserError};
+use json::ErrorCode::*;
+
+/// The deepest arrays and objects are nested before they are skipped.
+pub const MAX_DEPTH: usize = 512;
+
+/// How `parse_lenient` patched over an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repair {
+    /// A comma was missing between two elements or members.
+    InsertedComma,
+    /// A colon was missing between a key and its value.
+    InsertedColon,
+    /// A value was missing, and `null` was taken in its place.
+    InsertedNull,
+    /// A comma with no element or member after it was dropped.
+    DroppedComma,
+    /// An array or object was closed at the end of the input, or at a
+    /// bracket closing the array or object holding it.
+    Closed,
+    /// A string was closed at the end of its line, or of the input.
+    ClosedString,
+    /// A key which was not a string was taken as the text it is.
+    QuotedKey,
+    /// Text which is no value, or nested too deeply, was skipped, and the
+    /// value it was in place of taken as `null`; a string with invalid
+    /// escapes was taken as it is written.
+    Skipped,
+    /// Text after the end of the document was ignored.
+    IgnoredTrailing,
+}
+
+/// An error `parse_lenient` patched over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseDiagnostic {
+    /// The error, as `Parser` would report it.
+    pub code: ErrorCode,
+    /// Where the error is, counted as for `ParserError::SyntaxError`.
+    pub line: usize,
+    pub col: usize,
+    /// How the error was patched over.
+    pub repair: Repair,
+}
+
+impl ParseDiagnostic {
+    /// Returns the syntax error this diagnostic is for.
+    pub fn to_error(&self) -> ParserError {
+        ParserError::SyntaxError(self.code, self.line, self.col)
+    }
+}
+
+/// Parses `s`, patching over the errors in it; see the module
+/// documentation.
+pub fn parse_lenient(s: &str) -> (Json, Vec<ParseDiagnostic>) {
+    let mut p = Recover {
+        s: s,
+        pos: 0,
+        ch: None,
+        line: 1,
+        col: 0,
+        depth: 0,
+        diagnostics: Vec::new(),
+    };
+    p.bump();
+    let json = p.parse_value();
+    p.skip_whitespace();
+    if p.ch.is_some() {
+        p.diagnose(TrailingCharacters, Repair::IgnoredTrailing);
+    }
+    (json, p.diagnostics)
+}
+
+struct Recover<'a> {
+    s: &'a str,
+    // The offset of `ch`.
+    pos: usize,
+    ch: Option<char>,
+    line: usize,
+    col: usize,
+    depth: usize,
+    diagnostics: Vec<ParseDiagnostic>,
+}
+
+impl<'a> Recover<'a> {
+    // Moves to the next character, counting lines and columns as `Parser`
+    // does.
+    fn bump(&mut self) {
+        if let Some(c) = self.ch {
+            self.pos += c.len_utf8();
+        }
+        self.ch = self.s[self.pos..].chars().next();
+        if self.ch == Some('\n') {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+    }
+
+    fn diagnose(&mut self, code: ErrorCode, repair: Repair) {
+        self.diagnostics.push(ParseDiagnostic {
+            code: code,
+            line: self.line,
+            col: self.col,
+            repair: repair,
+        });
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.ch {
+            self.bump();
+        }
+    }
+
+    // Consumes a run of characters which could be part of a number or a
+    // literal, returning its text.
+    fn word(&mut self) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self.ch {
+            if !(c.is_alphanumeric() || "+-._$".contains(c)) {
+                break;
+            }
+            self.bump();
+        }
+        &self.s[start..self.pos]
+    }
+
+    // Parses a value, leaving a closing bracket or a comma, where a value is
+    // missing, to the array or object holding it.
+    fn parse_value(&mut self) -> Json {
+        self.skip_whitespace();
+        let c = match self.ch {
+            Some(c) => c,
+            None => {
+                self.diagnose(EOFWhileParsingValue, Repair::InsertedNull);
+                return Json::Null;
+            }
+        };
+        match c {
+            '[' | '{' if self.depth == MAX_DEPTH => {
+                self.diagnose(InvalidSyntax, Repair::Skipped);
+                self.skip_nested();
+                Json::Null
+            }
+            '[' => {
+                self.depth += 1;
+                let array = self.parse_array();
+                self.depth -= 1;
+                Json::Array(array)
+            }
+            '{' => {
+                self.depth += 1;
+                let object = self.parse_object();
+                self.depth -= 1;
+                Json::Object(object)
+            }
+            '"' => self.parse_string(),
+            ']' | '}' | ',' => {
+                self.diagnose(InvalidSyntax, Repair::InsertedNull);
+                Json::Null
+            }
+            _ => {
+                let (line, col) = (self.line, self.col);
+                let word = self.word();
+                if word.is_empty() {
+                    self.bump();
+                }
+                match Json::from_str(word) {
+                    Ok(json) => json,
+                    Err(_) => {
+                        let code = match c {
+                            '-' | '0'...'9' => InvalidNumber,
+                            _ => InvalidSyntax,
+                        };
+                        self.diagnostics.push(ParseDiagnostic {
+                            code: code,
+                            line: line,
+                            col: col,
+                            repair: Repair::Skipped,
+                        });
+                        Json::Null
+                    }
+                }
+            }
+        }
+    }
+
+    // Skips an array or object, counting brackets only, as it is nested too
+    // deeply to parse.
+    fn skip_nested(&mut self) {
+        let mut depth = 0;
+        while let Some(c) = self.ch {
+            self.bump();
+            match c {
+                '[' | '{' => depth += 1,
+                ']' | '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                '"' => {
+                    while let Some(c) = self.ch {
+                        self.bump();
+                        match c {
+                            '"' => break,
+                            '\\' => self.bump(),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Json {
+        let start = self.pos;
+        let (line, col) = (self.line, self.col);
+        self.bump();
+        loop {
+            match self.ch {
+                Some('"') => {
+                    self.bump();
+                    break;
+                }
+                Some('\\') => {
+                    self.bump();
+                    if self.ch.is_some() {
+                        self.bump();
+                    }
+                }
+                Some('\n') | None => {
+                    let code = if self.ch.is_none() {
+                        EOFWhileParsingString
+                    } else {
+                        ControlCharacterInString
+                    };
+                    self.diagnose(code, Repair::ClosedString);
+                    let mut raw = &self.s[start + 1..self.pos];
+                    if raw.ends_with('\r') {
+                        raw = &raw[..raw.len() - 1];
+                    }
+                    return Json::String(raw.into());
+                }
+                Some(_) => self.bump(),
+            }
+        }
+        let text = &self.s[start..self.pos];
+        match Json::from_str(text) {
+            Ok(json) => json,
+            Err(err) => {
+                let code = match err {
+                    ParserError::SyntaxError(code, _, _) => code,
+                    _ => InvalidSyntax,
+                };
+                self.diagnostics.push(ParseDiagnostic {
+                    code: code,
+                    line: line,
+                    col: col,
+                    repair: Repair::Skipped,
+                });
+                Json::String(text[1..text.len() - 1].into())
+            }
+        }
+    }
+
+    // Handles the end of an element or member, returning whether the array
+    // or object goes on.
+    fn parse_separator(&mut self, close: char, eof: ErrorCode) -> bool {
+        self.skip_whitespace();
+        match self.ch {
+            Some(',') => {
+                self.bump();
+                self.skip_whitespace();
+                while self.ch == Some(',') {
+                    self.diagnose(InvalidSyntax, Repair::DroppedComma);
+                    self.bump();
+                    self.skip_whitespace();
+                }
+                if self.ch == Some(close) {
+                    self.diagnose(TrailingComma, Repair::DroppedComma);
+                    self.bump();
+                    return false;
+                }
+                true
+            }
+            Some(c) if c == close => {
+                self.bump();
+                false
+            }
+            Some(']') | Some('}') => {
+                self.diagnose(InvalidSyntax, Repair::Closed);
+                false
+            }
+            None => {
+                self.diagnose(eof, Repair::Closed);
+                false
+            }
+            Some(_) => {
+                self.diagnose(InvalidSyntax, Repair::InsertedComma);
+                true
+            }
+        }
+    }
+
+    // Handles the start of an element or member, returning whether there
+    // is one.
+    fn parse_start(&mut self, close: char, eof: ErrorCode) -> bool {
+        loop {
+            self.skip_whitespace();
+            match self.ch {
+                Some(c) if c == close => {
+                    self.bump();
+                    return false;
+                }
+                Some(']') | Some('}') => {
+                    self.diagnose(InvalidSyntax, Repair::Closed);
+                    return false;
+                }
+                Some(',') => {
+                    self.diagnose(InvalidSyntax, Repair::DroppedComma);
+                    self.bump();
+                }
+                None => {
+                    self.diagnose(eof, Repair::Closed);
+                    return false;
+                }
+                Some(_) => return true,
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Array {
+        let mut array = Vec::new();
+        self.bump();
+        if !self.parse_start(']', EOFWhileParsingArray) {
+            return array;
+        }
+        loop {
+            array.push(self.parse_value());
+            if !self.parse_separator(']', EOFWhileParsingArray) {
+                return array;
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Object {
+        let mut object = Object::new();
+        self.bump();
+        if !self.parse_start('}', EOFWhileParsingObject) {
+            return object;
+        }
+        loop {
+            let key = match self.ch {
+                Some('"') => match self.parse_string() {
+                    Json::String(key) => key.to_string(),
+                    _ => unreachable!(),
+                },
+                Some(':') => {
+                    self.diagnose(KeyMustBeAString, Repair::QuotedKey);
+                    String::new()
+                }
+                _ => {
+                    self.diagnose(KeyMustBeAString, Repair::QuotedKey);
+                    let word = self.word();
+                    if word.is_empty() {
+                        // A value where a key should be.
+                        self.parse_value().to_string()
+                    } else {
+                        word.to_string()
+                    }
+                }
+            };
+            self.skip_whitespace();
+            let value = match self.ch {
+                Some(':') => {
+                    self.bump();
+                    self.parse_value()
+                }
+                Some(',') | Some('}') | Some(']') | None => {
+                    self.diagnose(ExpectedColon, Repair::InsertedNull);
+                    Json::Null
+                }
+                Some(_) => {
+                    self.diagnose(ExpectedColon, Repair::InsertedColon);
+                    self.parse_value()
+                }
+            };
+            object.insert(key, value);
+            if !self.parse_separator('}', EOFWhileParsingObject) {
+                return object;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::{ErrorCode, Json};
+    use json::ErrorCode::*;
+    use json::recover::{parse_lenient, Repair, MAX_DEPTH};
+    use json::recover::Repair::*;
+
+    fn repairs(s: &str) -> (Json, Vec<(ErrorCode, usize, usize, Repair)>) {
+        let (json, diagnostics) = parse_lenient(s);
+        (json, diagnostics.iter().map(|d| (d.code, d.line, d.col, d.repair)).collect())
+    }
+
+    fn json(s: &str) -> Json {
+        Json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_valid() {
+        for s in ["null", " [1, -2.5e3, \"a\\u00e9\\n\", true, {}] ", "{\"a\": {\"b\": [[]]}}",
+                  "\"\\ud83d\\ude00\"", "18446744073709551615"].iter() {
+            assert_eq!(parse_lenient(s), (json(s), vec![]));
+        }
+    }
+
+    #[test]
+    fn test_repairs() {
+        assert_eq!(repairs("[1 2 ,, 3,]"),
+                   (json("[1, 2, 3]"), vec![(InvalidSyntax, 1, 4, InsertedComma),
+                                           (InvalidSyntax, 1, 7, DroppedComma),
+                                           (TrailingComma, 1, 11, DroppedComma)]));
+        assert_eq!(repairs("{\"a\" 1, b: 2, \"c\": , \"d\"}"),
+                   (json("{\"a\": 1, \"b\": 2, \"c\": null, \"d\": null}"),
+                    vec![(ExpectedColon, 1, 6, InsertedColon),
+                         (KeyMustBeAString, 1, 9, QuotedKey),
+                         (InvalidSyntax, 1, 20, InsertedNull),
+                         (ExpectedColon, 1, 25, InsertedNull)]));
+        assert_eq!(repairs("{\"a\": [1, {\"b\": \"x"),
+                   (json("{\"a\": [1, {\"b\": \"x\"}]}"),
+                    vec![(EOFWhileParsingString, 1, 19, ClosedString),
+                         (EOFWhileParsingObject, 1, 19, Closed),
+                         (EOFWhileParsingArray, 1, 19, Closed),
+                         (EOFWhileParsingObject, 1, 19, Closed)]));
+        assert_eq!(repairs("[{\"a\": 1]\n"),
+                   (json("[{\"a\": 1}]"), vec![(InvalidSyntax, 1, 9, Closed)]));
+        assert_eq!(repairs("[\"ab\r\n, 01, \"\\x\", nul, @]"),
+                   (json("[\"ab\", null, \"\\\\x\", null, null]"),
+                    vec![(ControlCharacterInString, 2, 1, ClosedString),
+                         (InvalidNumber, 2, 4, Skipped),
+                         (InvalidEscape, 2, 8, Skipped),
+                         (InvalidSyntax, 2, 14, Skipped),
+                         (InvalidSyntax, 2, 19, Skipped)]));
+        assert_eq!(repairs("{} x"),
+                   (json("{}"), vec![(TrailingCharacters, 1, 4, IgnoredTrailing)]));
+        assert_eq!(repairs(""), (Json::Null, vec![(EOFWhileParsingValue, 1, 1, InsertedNull)]));
+        assert_eq!(repairs("{[1]: 2}").0, json("{\"[1]\": 2}"));
+    }
+
+    #[test]
+    fn test_depth() {
+        let deep = format!("{}{}", "[".repeat(MAX_DEPTH + 10), "]".repeat(MAX_DEPTH + 10));
+        let (_, diagnostics) = parse_lenient(&format!("[{}, 1, \"]\"]", deep));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].repair, Skipped);
+        let (json, _) = parse_lenient(&format!("[{}, 1, \"]\"]", deep));
+        assert_eq!(json[1], Json::U64(1));
+
+        let (json, diagnostics) = parse_lenient(&"[".repeat(100000));
+        assert_eq!(diagnostics.len(), MAX_DEPTH + 1);
+        assert!(json.is_array());
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Resolving `$ref` references within a document
+//!
+//! OpenAPI and JSON Schema documents refer from one part to another with
+//! objects whose only member is `$ref`, holding a URI fragment with a JSON
+//! Pointer (RFC 6901) to the target: `{"$ref": "#/definitions/pet"}`.
+//! `resolve_refs` replaces each such object with a copy of its target, and
+//! `resolve_refs_shared` builds an `ArcJson` tree in which every reference
+//! to a target shares the one node for it. References to other documents,
+//! which do not start with `#`, are left as they are.
+//!
+//! A target which holds a reference to itself, directly or through other
+//! targets, as recursive schemas do, cannot be copied in full:
+//! `resolve_refs` fails with `RefError::Cycle`, and `resolve_refs_shared`
+//! leaves the reference which would close the cycle as it is.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::Json;
+//! use rustc_serialize::json::refs;
+//!
+//! fn main() {
+//!     let doc = Json::from_str(r##"{"definitions": {"id": {"type": "integer"}},
+//!                                  "properties": {"a": {"$ref": "#/definitions/id"},
+//!                                                 "b": {"$ref": "#/definitions/id"}}}"##)
+//!                   .unwrap();
+//!     let mut copied = doc.clone();
+//!     refs::resolve_refs(&mut copied).unwrap();
+//!     assert_eq!(copied["properties"]["b"].to_string(), r#"{"type":"integer"}"#);
+//!
+//!     let shared = refs::resolve_refs_shared(&doc).unwrap();
+//!     assert!(shared["properties"]["a"].ptr_eq(&shared["properties"]["b"]));
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::Arc;
+
+use json::{pointer_tokens, Json, Object};
+use json::arc::{self, ArcJson};
+
+/// An error from resolving references.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RefError {
+    /// A reference, given, has no target in the document
+    Unresolved(String),
+    /// The references given each refer to a target holding the next, and
+    /// the last to one holding the first
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for RefError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RefError::Unresolved(ref reference) => write!(f, "unresolved $ref {:?}", reference),
+            RefError::Cycle(ref references) => {
+                write!(f, "$ref cycle: {}", references.join(" -> "))
+            }
+        }
+    }
+}
+
+impl StdError for RefError {
+    fn description(&self) -> &str { "reference error" }
+}
+
+/// Replaces each reference within `json` with a copy of its target, with
+/// the references in the target resolved in turn; see the module
+/// documentation. On an error `json` is left as it was.
+pub fn resolve_refs(json: &mut Json) -> Result<(), RefError> {
+    let resolved = try!(resolve(json, json, &mut Vec::new()));
+    *json = resolved;
+    Ok(())
+}
+
+/// Converts `json` to an `ArcJson` tree with each reference replaced by
+/// its target, the same node for each reference to it; see the module
+/// documentation.
+pub fn resolve_refs_shared(json: &Json) -> Result<ArcJson, RefError> {
+    resolve_shared(json, json, &mut Vec::new(), &mut HashMap::new())
+}
+
+// The reference `node` is, if it is one to a place in its own document.
+fn local_ref(node: &Json) -> Option<&str> {
+    match *node {
+        Json::Object(ref object) if object.len() == 1 => {
+            object.get("$ref").and_then(|r| r.as_string()).filter(|r| r.starts_with('#'))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the target in `root` of the local reference `reference`, a
+/// `#` and a JSON Pointer with any `%` escapes of a URI fragment.
+pub fn target<'a>(root: &'a Json, reference: &str) -> Result<&'a Json, RefError> {
+    if !reference.starts_with('#') {
+        return Err(RefError::Unresolved(reference.to_string()));
+    }
+    let unresolved = || RefError::Unresolved(reference.to_string());
+    let pointer = try!(percent_decode(&reference[1..]).ok_or_else(&unresolved));
+    let tokens = try!(pointer_tokens(&pointer).ok_or_else(&unresolved));
+    let mut target = root;
+    for token in &tokens {
+        let next = match *target {
+            Json::Array(ref array) => token.parse::<usize>().ok().and_then(|i| array.get(i)),
+            Json::Object(ref object) => object.get(&token[..]),
+            _ => None,
+        };
+        target = try!(next.ok_or_else(&unresolved));
+    }
+    Ok(target)
+}
+
+// Decodes the `%XX` escapes of a URI fragment.
+fn percent_decode(s: &str) -> Option<String> {
+    if !s.contains('%') {
+        return Some(s.to_string());
+    }
+    let b = s.as_bytes();
+    let mut bytes = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] == b'%' {
+            match s.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                Some(byte) => bytes.push(byte),
+                None => return None,
+            }
+            i += 3;
+        } else {
+            bytes.push(b[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+// A copy of `node`, within `root`, with its references resolved, while
+// expanding the targets of the references in `stack`.
+fn resolve(root: &Json, node: &Json, stack: &mut Vec<String>) -> Result<Json, RefError> {
+    if let Some(reference) = local_ref(node) {
+        if let Some(i) = stack.iter().position(|r| r == reference) {
+            return Err(RefError::Cycle(stack[i..].to_vec()));
+        }
+        let target = try!(target(root, reference));
+        stack.push(reference.to_string());
+        let resolved = try!(resolve(root, target, stack));
+        stack.pop();
+        return Ok(resolved);
+    }
+    Ok(match *node {
+        Json::Array(ref array) => {
+            let mut resolved = Vec::with_capacity(array.len());
+            for elt in array {
+                resolved.push(try!(resolve(root, elt, stack)));
+            }
+            Json::Array(resolved)
+        }
+        Json::Object(ref object) => {
+            let mut resolved = Object::new();
+            for (key, member) in object {
+                resolved.insert(key.clone(), try!(resolve(root, member, stack)));
+            }
+            Json::Object(resolved)
+        }
+        ref scalar => scalar.clone(),
+    })
+}
+
+// As `resolve`, to an `ArcJson`, with the nodes for the targets resolved
+// so far in `done`.
+fn resolve_shared(root: &Json, node: &Json, stack: &mut Vec<String>,
+                  done: &mut HashMap<String, ArcJson>) -> Result<ArcJson, RefError> {
+    if let Some(reference) = local_ref(node) {
+        if let Some(resolved) = done.get(reference) {
+            return Ok(resolved.clone());
+        }
+        if stack.iter().any(|r| r == reference) {
+            return Ok(ArcJson::from(node.clone()));
+        }
+        let target = try!(target(root, reference));
+        stack.push(reference.to_string());
+        let resolved = try!(resolve_shared(root, target, stack, done));
+        stack.pop();
+        done.insert(reference.to_string(), resolved.clone());
+        return Ok(resolved);
+    }
+    Ok(match *node {
+        Json::Array(ref array) => {
+            let mut resolved = Vec::with_capacity(array.len());
+            for elt in array {
+                resolved.push(try!(resolve_shared(root, elt, stack, done)));
+            }
+            ArcJson::Array(Arc::new(resolved))
+        }
+        Json::Object(ref object) => {
+            let mut resolved = arc::Object::new();
+            for (key, member) in object {
+                resolved.insert(key.clone(), try!(resolve_shared(root, member, stack, done)));
+            }
+            ArcJson::Object(Arc::new(resolved))
+        }
+        ref scalar => ArcJson::from(scalar.clone()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use json::Json;
+    use json::refs::{self, RefError};
+
+    #[test]
+    fn test_resolve_refs() {
+        let text = r##"{"defs": {"a b": {"$ref": "#/defs/list/1"}, "list": [0, {"x": true}],
+                                "c~d": 3},
+                       "use": [{"$ref": "#/defs/a%20b"}, {"$ref": "#/defs/c~0d"},
+                               {"$ref": "other.json#/x"}, {"$ref": "#"}],
+                       "skip": {"$ref": "#/defs/c~0d", "note": "not a reference"}}"##;
+        let doc = Json::from_str(&text.replace(", {\"$ref\": \"#\"}", "")).unwrap();
+        let mut copied = doc.clone();
+        refs::resolve_refs(&mut copied).unwrap();
+        assert_eq!(copied["use"].to_string(),
+                   r#"[{"x":true},3,{"$ref":"other.json#/x"}]"#);
+        assert_eq!(copied["defs"]["a b"], copied["defs"]["list"][1]);
+        assert_eq!(copied["skip"], doc["skip"]);
+
+        let shared = refs::resolve_refs_shared(&doc).unwrap();
+        assert_eq!(shared.to_json(), copied);
+        assert!(shared["use"][0].ptr_eq(&shared["defs"]["a b"]));
+
+        let mut whole = Json::from_str(text).unwrap();
+        assert_eq!(refs::resolve_refs(&mut whole), Err(RefError::Cycle(vec!["#".to_string()])));
+        assert_eq!(whole, Json::from_str(text).unwrap());
+    }
+
+    #[test]
+    fn test_cycles() {
+        let doc = Json::from_str(r##"{"node": {"next": {"$ref": "#/link"}, "v": 1},
+                                     "link": {"$ref": "#/node"},
+                                     "root": {"$ref": "#/node"}}"##).unwrap();
+        let mut copied = doc.clone();
+        assert_eq!(refs::resolve_refs(&mut copied),
+                   Err(RefError::Cycle(vec!["#/node".to_string(), "#/link".to_string()])));
+
+        let shared = refs::resolve_refs_shared(&doc).unwrap();
+        assert_eq!(shared["root"].to_json().to_string(),
+                   r##"{"next":{"$ref":"#/node"},"v":1}"##);
+        assert!(shared["root"].ptr_eq(&shared["link"]));
+
+        for &reference in ["#/none", "#/node/v/0", "#/link/x", "#/%zz", "#x"].iter() {
+            let mut doc = Json::from_str(&format!("[{{\"$ref\": \"{}\"}}]", reference)).unwrap();
+            assert_eq!(refs::resolve_refs(&mut doc),
+                       Err(RefError::Unresolved(reference.to_string())));
+        }
+    }
+}
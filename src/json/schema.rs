@@ -0,0 +1,336 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Inferring the structure of documents from samples
+//!
+//! A `Schema` describes the values seen at one place in a set of sample
+//! documents: how many there were of each type, the distinct strings among
+//! them while there are few, the merged description of the elements of
+//! their arrays, and of the members of their objects by key. Members found
+//! in fewer objects than were seen are optional.
+//!
+//! `infer_schema` describes a set of samples, and `to_json_schema` exports
+//! a description as a JSON Schema (draft 2020-12), to start from when
+//! writing types for an undocumented API.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::{self, Json};
+//!
+//! fn main() {
+//!     let samples: Vec<Json> = [
+//!         r#"{"id": 1, "state": "open", "tags": ["a"]}"#,
+//!         r#"{"id": 2, "state": "closed", "tags": [], "note": null}"#,
+//!         r#"{"id": 3, "state": "open", "tags": ["b", "c"], "note": "late"}"#,
+//!     ].iter().map(|s| Json::from_str(s).unwrap()).collect();
+//!     let schema = json::infer_schema(&samples);
+//!     assert_eq!(schema.required(), ["id", "state", "tags"]);
+//!     assert_eq!(schema.properties["state"].enum_candidates().unwrap().len(), 2);
+//!
+//!     let paths: Vec<_> = schema.paths().into_iter().map(|(path, _)| path).collect();
+//!     assert_eq!(paths, ["$", "$.id", "$.note", "$.state", "$.tags", "$.tags[*]"]);
+//!
+//!     assert_eq!(schema.to_json_schema()["properties"]["note"],
+//!                Json::from_str(r#"{"type": ["null", "string"]}"#).unwrap());
+//! }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use json::{Json, Object};
+
+/// The most distinct strings a `Schema` keeps track of at one place
+pub const MAX_ENUM_CANDIDATES: usize = 16;
+
+/// A description of the values seen at one place in a set of documents;
+/// see the module documentation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    /// The number of values seen
+    pub count: usize,
+    /// The number of them which were `null`
+    pub nulls: usize,
+    /// The number of them which were booleans
+    pub booleans: usize,
+    /// The number of them which were integers (`I64` or `U64`)
+    pub integers: usize,
+    /// The number of them which were other numbers (`F64`)
+    pub floats: usize,
+    /// The number of them which were strings
+    pub strings: usize,
+    /// The number of them which were arrays
+    pub arrays: usize,
+    /// The number of them which were objects
+    pub objects: usize,
+    /// The distinct strings seen, or `None` once there have been more than
+    /// `MAX_ENUM_CANDIDATES`
+    pub string_values: Option<BTreeSet<String>>,
+    /// The elements of all the arrays seen, or `None` if none had any
+    pub items: Option<Box<Schema>>,
+    /// The members of all the objects seen, by key
+    pub properties: BTreeMap<String, Schema>,
+}
+
+/// Describes the values in `samples`.
+pub fn infer_schema(samples: &[Json]) -> Schema {
+    let mut schema = Schema::new();
+    for sample in samples {
+        schema.add(sample);
+    }
+    schema
+}
+
+impl Schema {
+    /// Creates a description of no values.
+    pub fn new() -> Schema {
+        Schema {
+            count: 0,
+            nulls: 0,
+            booleans: 0,
+            integers: 0,
+            floats: 0,
+            strings: 0,
+            arrays: 0,
+            objects: 0,
+            string_values: Some(BTreeSet::new()),
+            items: None,
+            properties: BTreeMap::new(),
+        }
+    }
+
+    /// Adds `value` to the values described.
+    pub fn add(&mut self, value: &Json) {
+        self.count += 1;
+        match *value {
+            Json::Null => self.nulls += 1,
+            Json::Boolean(_) => self.booleans += 1,
+            Json::I64(_) | Json::U64(_) => self.integers += 1,
+            Json::F64(_) => self.floats += 1,
+            Json::String(ref s) => self.add_string(s),
+            Json::Wtf8(ref s) => self.add_string(&s.to_string_lossy()),
+            Json::Array(ref a) => {
+                self.arrays += 1;
+                for elt in a {
+                    self.items.get_or_insert_with(|| Box::new(Schema::new())).add(elt);
+                }
+            }
+            Json::Object(ref o) => {
+                self.objects += 1;
+                for (key, member) in o {
+                    self.properties.entry(key.clone()).or_default().add(member);
+                }
+            }
+        }
+    }
+
+    fn add_string(&mut self, s: &str) {
+        self.strings += 1;
+        let full = match self.string_values {
+            Some(ref mut values) => {
+                values.insert(s.to_string());
+                values.len() > MAX_ENUM_CANDIDATES
+            }
+            None => false,
+        };
+        if full {
+            self.string_values = None;
+        }
+    }
+
+    /// Returns the keys of the members found in every object seen.
+    pub fn required(&self) -> Vec<&str> {
+        self.properties.iter()
+            .filter(|&(_, member)| member.count == self.objects)
+            .map(|(key, _)| &key[..])
+            .collect()
+    }
+
+    /// Returns the distinct strings seen, if every value other than `null`
+    /// was one of few strings, with at least one seen more than once.
+    pub fn enum_candidates(&self) -> Option<&BTreeSet<String>> {
+        match self.string_values {
+            Some(ref values) if self.strings > values.len() &&
+                                self.strings + self.nulls == self.count => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Returns the description of each place beneath and including this
+    /// one, in order, with its path as a JSONPath query for it: `$` for
+    /// this one, `.key` or `['key']` for object members and `[*]` for
+    /// array elements.
+    pub fn paths(&self) -> Vec<(String, &Schema)> {
+        let mut paths = Vec::new();
+        self.push_paths("$".to_string(), &mut paths);
+        paths
+    }
+
+    fn push_paths<'a>(&'a self, path: String, out: &mut Vec<(String, &'a Schema)>) {
+        out.push((path.clone(), self));
+        for (key, member) in &self.properties {
+            let shorthand = !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_');
+            let member_path = if shorthand {
+                format!("{}.{}", path, key)
+            } else {
+                format!("{}[{:?}]", path, key)
+            };
+            member.push_paths(member_path, out);
+        }
+        if let Some(ref items) = self.items {
+            items.push_paths(format!("{}[*]", path), out);
+        }
+    }
+
+    /// Exports the description as a JSON Schema (draft 2020-12).
+    ///
+    /// Each place gets the types seen there, `enum` if there are
+    /// `enum_candidates`, `items` if arrays were seen and `properties` and
+    /// `required` if objects were. Integers are typed `integer` unless
+    /// other numbers were seen in the same place, and places where nothing
+    /// was seen accept anything.
+    pub fn to_json_schema(&self) -> Json {
+        match self.json_schema() {
+            Json::Object(mut o) => {
+                let draft = "https://json-schema.org/draft/2020-12/schema";
+                o.insert("$schema".to_string(), Json::from(draft));
+                Json::Object(o)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn json_schema(&self) -> Json {
+        let mut o = Object::new();
+        let mut types = Vec::new();
+        for &(n, ty) in [(self.nulls, "null"), (self.booleans, "boolean"),
+                         (if self.floats > 0 { 0 } else { self.integers }, "integer"),
+                         (self.floats, "number"), (self.strings, "string"),
+                         (self.arrays, "array"), (self.objects, "object")].iter() {
+            if n > 0 {
+                types.push(Json::from(ty));
+            }
+        }
+        match types.len() {
+            0 => return Json::Object(o),
+            1 => o.insert("type".to_string(), types.pop().unwrap()),
+            _ => o.insert("type".to_string(), Json::Array(types)),
+        };
+        if let Some(values) = self.enum_candidates() {
+            let mut values: Vec<Json> = values.iter().map(|v| Json::from(&v[..])).collect();
+            if self.nulls > 0 {
+                values.push(Json::Null);
+            }
+            o.insert("enum".to_string(), Json::Array(values));
+        }
+        if self.arrays > 0 {
+            let items = match self.items {
+                Some(ref items) => items.json_schema(),
+                None => Json::Object(Object::new()),
+            };
+            o.insert("items".to_string(), items);
+        }
+        if self.objects > 0 {
+            let properties = self.properties.iter()
+                                 .map(|(key, member)| (key.clone(), member.json_schema()))
+                                 .collect();
+            o.insert("properties".to_string(), Json::Object(properties));
+            let required = self.required().into_iter().map(Json::from).collect();
+            o.insert("required".to_string(), Json::Array(required));
+        }
+        Json::Object(o)
+    }
+}
+
+impl Default for Schema {
+    fn default() -> Schema { Schema::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::{self, Json};
+    use json::schema::{infer_schema, Schema, MAX_ENUM_CANDIDATES};
+
+    fn samples(docs: &[&str]) -> Vec<Json> {
+        docs.iter().map(|s| Json::from_str(s).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_infer_schema() {
+        let schema = infer_schema(&samples(&[
+            r#"{"n": 1, "xs": [{"a": true}, {"a": false, "b": "x"}], "e f": "x"}"#,
+            r#"{"n": 2.5, "xs": [], "e f": "y"}"#,
+            r#"{"n": null, "xs": [{"a": true, "b": 3}]}"#,
+        ]));
+        assert_eq!((schema.count, schema.objects), (3, 3));
+        assert_eq!(schema.required(), ["n", "xs"]);
+        let n = &schema.properties["n"];
+        assert_eq!((n.integers, n.floats, n.nulls, n.count), (1, 1, 1, 3));
+        let items = schema.properties["xs"].items.as_ref().unwrap();
+        assert_eq!((items.count, items.required()), (3, vec!["a"]));
+        assert_eq!(items.properties["b"].strings + items.properties["b"].integers, 2);
+        // Two distinct strings, neither repeated, are not an enum.
+        assert_eq!(schema.properties["e f"].enum_candidates(), None);
+
+        let paths: Vec<_> = schema.paths().into_iter().map(|(p, s)| (p, s.count)).collect();
+        assert_eq!(paths, [("$".to_string(), 3), ("$[\"e f\"]".to_string(), 2),
+                           ("$.n".to_string(), 3), ("$.xs".to_string(), 3),
+                           ("$.xs[*]".to_string(), 3), ("$.xs[*].a".to_string(), 3),
+                           ("$.xs[*].b".to_string(), 2)]);
+        for (path, _) in schema.paths() {
+            assert!(json::query::Query::parse(&path).is_ok(), "{}", path);
+        }
+
+        assert_eq!(schema.to_json_schema(), Json::from_str(r#"{
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "properties": {
+                "e f": {"type": "string"},
+                "n": {"type": ["null", "number"]},
+                "xs": {"type": "array", "items": {
+                    "type": "object",
+                    "properties": {"a": {"type": "boolean"},
+                                   "b": {"type": ["integer", "string"]}},
+                    "required": ["a"]
+                }}
+            },
+            "required": ["n", "xs"]
+        }"#).unwrap());
+    }
+
+    #[test]
+    fn test_enum_candidates() {
+        let mut schema = Schema::new();
+        assert_eq!(schema, Schema::default());
+        assert!(schema.to_json_schema()["$schema"].as_string().is_some());
+        for s in ["a", "b", "a"].iter() {
+            schema.add(&Json::from(*s));
+        }
+        schema.add(&Json::Null);
+        assert_eq!(schema.enum_candidates().unwrap().len(), 2);
+        assert_eq!(schema.to_json_schema()["enum"],
+                   Json::from_str(r#"["a", "b", null]"#).unwrap());
+
+        schema.add(&Json::U64(1));
+        assert_eq!(schema.enum_candidates(), None);
+
+        let mut schema = Schema::new();
+        for i in 0..MAX_ENUM_CANDIDATES + 2 {
+            schema.add(&Json::from(format!("{}", i % (MAX_ENUM_CANDIDATES + 1))));
+        }
+        assert_eq!(schema.string_values, None);
+        assert_eq!(schema.enum_candidates(), None);
+
+        let empty = infer_schema(&samples(&["[]"]));
+        assert_eq!(empty.items, None);
+        assert_eq!(empty.to_json_schema()["items"], Json::from_str("{}").unwrap());
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A string which stores short contents inline
+//!
+//! Most strings in typical JSON documents, keys aside, are short: names,
+//! identifiers, enum-like values. `SmallString` keeps up to `INLINE_CAP`
+//! bytes inline, without a heap allocation, and longer contents in a
+//! `String`.
+//!
+//! `SmallString` is a separate type, available with the `small_strings`
+//! feature; `Json::String` always holds a `String`. It derefs to `str`,
+//! converts to and from `String` and `&str`, and encodes and decodes as a
+//! string, so it can stand in for `String` in decoded structs.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::Deref;
+use std::str;
+
+use {Decodable, Decoder, Encodable, Encoder};
+
+/// The most bytes a `SmallString` stores inline
+pub const INLINE_CAP: usize = 22;
+
+/// A string which stores up to `INLINE_CAP` bytes inline; see the module
+/// documentation.
+#[derive(Clone)]
+pub struct SmallString(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    // The length, and the contents followed by unused bytes.
+    Inline(u8, [u8; INLINE_CAP]),
+    Heap(String),
+}
+
+impl SmallString {
+    /// Creates an empty string.
+    pub fn new() -> SmallString {
+        SmallString(Repr::Inline(0, [0; INLINE_CAP]))
+    }
+
+    /// Returns the contents.
+    pub fn as_str(&self) -> &str {
+        match self.0 {
+            // Only ever copied from a `str`, up to its length.
+            Repr::Inline(len, ref bytes) => unsafe {
+                str::from_utf8_unchecked(&bytes[..len as usize])
+            },
+            Repr::Heap(ref s) => s,
+        }
+    }
+
+    /// Returns whether the contents are stored inline.
+    pub fn is_inline(&self) -> bool {
+        match self.0 {
+            Repr::Inline(..) => true,
+            Repr::Heap(_) => false,
+        }
+    }
+
+    /// Returns the number of bytes allocated on the heap, as
+    /// `String::capacity` does: zero for inline contents.
+    pub fn capacity(&self) -> usize {
+        match self.0 {
+            Repr::Inline(..) => 0,
+            Repr::Heap(ref s) => s.capacity(),
+        }
+    }
+
+    /// Converts into a `String`, allocating unless the contents were on
+    /// the heap already.
+    pub fn into_string(self) -> String {
+        match self.0 {
+            Repr::Inline(..) => self.as_str().to_string(),
+            Repr::Heap(s) => s,
+        }
+    }
+}
+
+impl Default for SmallString {
+    fn default() -> SmallString { SmallString::new() }
+}
+
+impl<'a> From<&'a str> for SmallString {
+    fn from(s: &'a str) -> SmallString {
+        if s.len() > INLINE_CAP {
+            return SmallString(Repr::Heap(s.to_string()));
+        }
+        let mut bytes = [0; INLINE_CAP];
+        bytes[..s.len()].copy_from_slice(s.as_bytes());
+        SmallString(Repr::Inline(s.len() as u8, bytes))
+    }
+}
+
+impl From<String> for SmallString {
+    /// Moves short contents inline, freeing the `String`'s buffer, and
+    /// keeps longer contents where they are.
+    fn from(s: String) -> SmallString {
+        if s.len() > INLINE_CAP {
+            SmallString(Repr::Heap(s))
+        } else {
+            SmallString::from(&s[..])
+        }
+    }
+}
+
+impl From<SmallString> for String {
+    fn from(s: SmallString) -> String { s.into_string() }
+}
+
+impl FromIterator<char> for SmallString {
+    fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> SmallString {
+        SmallString::from(iter.into_iter().collect::<String>())
+    }
+}
+
+impl<'a> FromIterator<&'a str> for SmallString {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(iter: I) -> SmallString {
+        SmallString::from(iter.into_iter().collect::<String>())
+    }
+}
+
+impl Deref for SmallString {
+    type Target = str;
+    fn deref(&self) -> &str { self.as_str() }
+}
+
+impl AsRef<str> for SmallString {
+    fn as_ref(&self) -> &str { self.as_str() }
+}
+
+impl Borrow<str> for SmallString {
+    fn borrow(&self) -> &str { self.as_str() }
+}
+
+impl PartialEq for SmallString {
+    fn eq(&self, other: &SmallString) -> bool { self.as_str() == other.as_str() }
+}
+
+impl Eq for SmallString {}
+
+impl PartialEq<str> for SmallString {
+    fn eq(&self, other: &str) -> bool { self.as_str() == other }
+}
+
+impl<'a> PartialEq<&'a str> for SmallString {
+    fn eq(&self, other: &&'a str) -> bool { self.as_str() == *other }
+}
+
+impl PartialEq<String> for SmallString {
+    fn eq(&self, other: &String) -> bool { self.as_str() == &other[..] }
+}
+
+impl PartialOrd for SmallString {
+    fn partial_cmp(&self, other: &SmallString) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for SmallString {
+    fn cmp(&self, other: &SmallString) -> Ordering { self.as_str().cmp(other.as_str()) }
+}
+
+impl Hash for SmallString {
+    // As `str` hashes, so that `Borrow<str>` lookups find the string.
+    fn hash<H: Hasher>(&self, state: &mut H) { self.as_str().hash(state) }
+}
+
+impl fmt::Debug for SmallString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::Debug::fmt(self.as_str(), f) }
+}
+
+impl fmt::Display for SmallString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::Display::fmt(self.as_str(), f) }
+}
+
+impl Encodable for SmallString {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_str(self.as_str())
+    }
+}
+
+impl Decodable for SmallString {
+    fn decode<D: Decoder>(d: &mut D) -> Result<SmallString, D::Error> {
+        d.read_str().map(SmallString::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use json;
+    use json::small_string::{SmallString, INLINE_CAP};
+
+    #[test]
+    fn test_small_string() {
+        let short = "0123456789abcdefghij\u{e9}";
+        assert_eq!(short.len(), INLINE_CAP);
+        let long = "caf\u{e9} au lait, s'il vous pla\u{ee}t";
+        for &s in [short, long, ""].iter() {
+            let small = SmallString::from(s);
+            assert_eq!(small.is_inline(), s.len() <= INLINE_CAP);
+            assert_eq!(small, s);
+            assert_eq!(SmallString::from(s.to_string()), small);
+            assert_eq!(small.clone().into_string(), s);
+            assert_eq!(format!("{:?}", small), format!("{:?}", s));
+            assert_eq!(json::encode(&small).unwrap(), json::encode(&s).unwrap());
+            assert_eq!(json::decode::<SmallString>(&json::encode(&s).unwrap()).unwrap(), s);
+        }
+        let (inline, heap) = (SmallString::from("a"), SmallString::from(long));
+        assert!(inline < heap);
+
+        let set: HashSet<SmallString> = vec!["a".into(), long.into()].into_iter().collect();
+        assert!(set.contains("a") && set.contains(long) && !set.contains("b"));
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Parsing JSON with the place of each value in the source
+//!
+//! `parse_spanned` parses a document to a `SpannedJson` tree, in which
+//! every value, and every object key, carries the byte range of its text in
+//! the source, for tools which report problems at their place in the text
+//! or edit it there. Members are kept in the order of the text, duplicate
+//! keys and all.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::{self, Json};
+//! use rustc_serialize::json::spanned::SpannedValue;
+//!
+//! fn main() {
+//!     let source = "{\"name\": \"ada\", \"tags\": [1, 22]}";
+//!     let doc = json::parse_spanned(source).unwrap();
+//!     let tag = doc.pointer("/tags/1").unwrap();
+//!     assert_eq!(tag.value, SpannedValue::U64(22));
+//!     assert_eq!(&source[tag.span.clone()], "22");
+//!
+//!     // Replace the tag in the text, leaving the rest as it is.
+//!     let edited = format!("{}33{}", &source[..tag.span.start], &source[tag.span.end..]);
+//!     assert_eq!(edited, "{\"name\": \"ada\", \"tags\": [1, 33]}");
+//!     assert_eq!(doc.node_at(12).unwrap().to_json(), Json::String("ada".into()));
+//! }
+//! ```
+
+use std::ops::Range;
+
+use json::{pointer_tokens, BuilderError, Json, JsonEvent, Parser};
+
+/// A value, with the byte range of its text in the source.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpannedJson {
+    /// The range of the value's text, from its first character to its
+    /// last.
+    pub span: Range<usize>,
+    /// The value.
+    pub value: SpannedValue,
+}
+
+/// The value of a `SpannedJson`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpannedValue {
+    Null,
+    Boolean(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    String(String),
+    Array(Vec<SpannedJson>),
+    /// The members, in the order of the text.
+    Object(Vec<SpannedMember>),
+}
+
+/// A member of an object, with the byte ranges of its key and value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpannedMember {
+    /// The key, unescaped.
+    pub key: String,
+    /// The range of the key's text, quotes included.
+    pub key_span: Range<usize>,
+    /// The value.
+    pub value: SpannedJson,
+}
+
+/// Parses a document, with the byte range of each value and key in `s`;
+/// see the module documentation.
+pub fn parse_spanned(s: &str) -> Result<SpannedJson, BuilderError> {
+    // Check the document first, so that the text can be scanned knowing it
+    // is valid.
+    for event in Parser::new(s.chars()) {
+        if let JsonEvent::Error(err) = event {
+            return Err(err);
+        }
+    }
+    let mut pos = 0;
+    Ok(scan(s, &mut pos))
+}
+
+impl SpannedJson {
+    /// If this is an object, returns the value of the last member with
+    /// `key`.
+    pub fn get(&self, key: &str) -> Option<&SpannedJson> {
+        match self.value {
+            SpannedValue::Object(ref members) => {
+                members.iter().rev().find(|m| m.key == key).map(|m| &m.value)
+            }
+            _ => None,
+        }
+    }
+
+    /// If this is an array, returns its element at `idx`.
+    pub fn at(&self, idx: usize) -> Option<&SpannedJson> {
+        match self.value {
+            SpannedValue::Array(ref elements) => elements.get(idx),
+            _ => None,
+        }
+    }
+
+    /// Returns the value at the JSON Pointer (RFC 6901) `pointer`.
+    pub fn pointer(&self, pointer: &str) -> Option<&SpannedJson> {
+        let tokens = match pointer_tokens(pointer) {
+            Some(tokens) => tokens,
+            None => return None,
+        };
+        let mut target = self;
+        for token in &tokens {
+            let next = match target.value {
+                SpannedValue::Array(_) => token.parse().ok().and_then(|i| target.at(i)),
+                _ => target.get(token),
+            };
+            target = match next {
+                Some(next) => next,
+                None => return None,
+            };
+        }
+        Some(target)
+    }
+
+    /// Returns the innermost value whose text holds the byte at `offset`.
+    pub fn node_at(&self, offset: usize) -> Option<&SpannedJson> {
+        if offset < self.span.start || offset >= self.span.end {
+            return None;
+        }
+        let inner = match self.value {
+            SpannedValue::Array(ref elements) => {
+                elements.iter().filter_map(|elt| elt.node_at(offset)).next()
+            }
+            SpannedValue::Object(ref members) => {
+                members.iter().filter_map(|m| m.value.node_at(offset)).next()
+            }
+            _ => None,
+        };
+        Some(inner.unwrap_or(self))
+    }
+
+    /// Converts this value to a `Json`, without its spans. Of members with
+    /// the same key, the last is kept, as when parsing to a `Json`.
+    pub fn to_json(&self) -> Json {
+        match self.value {
+            SpannedValue::Null => Json::Null,
+            SpannedValue::Boolean(b) => Json::Boolean(b),
+            SpannedValue::I64(n) => Json::I64(n),
+            SpannedValue::U64(n) => Json::U64(n),
+            SpannedValue::F64(n) => Json::F64(n),
+            SpannedValue::String(ref s) => Json::String(s[..].into()),
+            SpannedValue::Array(ref elements) => {
+                Json::Array(elements.iter().map(|elt| elt.to_json()).collect())
+            }
+            SpannedValue::Object(ref members) => {
+                Json::Object(members.iter().map(|m| (m.key.clone(), m.value.to_json())).collect())
+            }
+        }
+    }
+}
+
+fn skip_whitespace(b: &[u8], pos: &mut usize) {
+    while *pos < b.len() && (b[*pos] == b' ' || b[*pos] == b'\t' ||
+                             b[*pos] == b'\n' || b[*pos] == b'\r') {
+        *pos += 1;
+    }
+}
+
+fn skip_string(b: &[u8], pos: &mut usize) {
+    *pos += 1;
+    while *pos < b.len() {
+        match b[*pos] {
+            b'\\' => *pos += 2,
+            b'"' => {
+                *pos += 1;
+                return;
+            }
+            _ => *pos += 1,
+        }
+    }
+}
+
+// The value of the scalar whose text is `text`.
+fn scalar(text: &str) -> SpannedValue {
+    // The text has been parsed already, so parses again.
+    match Json::from_str(text).unwrap() {
+        Json::Boolean(b) => SpannedValue::Boolean(b),
+        Json::I64(n) => SpannedValue::I64(n),
+        Json::U64(n) => SpannedValue::U64(n),
+        Json::F64(n) => SpannedValue::F64(n),
+        Json::String(s) => SpannedValue::String(s.to_string()),
+        _ => SpannedValue::Null,
+    }
+}
+
+// Scans the value at `pos` in `s`, which is known to be valid, leaving
+// `pos` after it.
+fn scan(s: &str, pos: &mut usize) -> SpannedJson {
+    let b = s.as_bytes();
+    skip_whitespace(b, pos);
+    let start = *pos;
+    let value = match b[start] {
+        b'[' => {
+            let mut elements = Vec::new();
+            *pos += 1;
+            skip_whitespace(b, pos);
+            if b[*pos] == b']' {
+                *pos += 1;
+            } else {
+                loop {
+                    elements.push(scan(s, pos));
+                    skip_whitespace(b, pos);
+                    *pos += 1;
+                    if b[*pos - 1] != b',' {
+                        break;
+                    }
+                }
+            }
+            SpannedValue::Array(elements)
+        }
+        b'{' => {
+            let mut members = Vec::new();
+            *pos += 1;
+            skip_whitespace(b, pos);
+            if b[*pos] == b'}' {
+                *pos += 1;
+            } else {
+                loop {
+                    skip_whitespace(b, pos);
+                    let key_start = *pos;
+                    skip_string(b, pos);
+                    let key_span = key_start..*pos;
+                    let key = match scalar(&s[key_span.clone()]) {
+                        SpannedValue::String(key) => key,
+                        _ => String::new(),
+                    };
+                    skip_whitespace(b, pos);
+                    // The colon.
+                    *pos += 1;
+                    let value = scan(s, pos);
+                    members.push(SpannedMember { key: key, key_span: key_span, value: value });
+                    skip_whitespace(b, pos);
+                    *pos += 1;
+                    if b[*pos - 1] != b',' {
+                        break;
+                    }
+                }
+            }
+            SpannedValue::Object(members)
+        }
+        b'"' => {
+            skip_string(b, pos);
+            scalar(&s[start..*pos])
+        }
+        _ => {
+            while *pos < b.len() && !b",]} \t\n\r".contains(&b[*pos]) {
+                *pos += 1;
+            }
+            scalar(&s[start..*pos])
+        }
+    };
+    SpannedJson { span: start..*pos, value: value }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::{self, Json};
+    use json::spanned::{SpannedJson, SpannedValue};
+
+    // Checks that each value's span holds its text, in `source`.
+    fn check_spans(source: &str, node: &SpannedJson) {
+        let text = &source[node.span.clone()];
+        assert_eq!(Json::from_str(text).unwrap(), node.to_json(), "{}", text);
+        match node.value {
+            SpannedValue::Array(ref elements) => {
+                for elt in elements {
+                    check_spans(source, elt);
+                }
+            }
+            SpannedValue::Object(ref members) => {
+                for m in members {
+                    assert_eq!(Json::from_str(&source[m.key_span.clone()]).unwrap(),
+                               Json::String(m.key[..].into()));
+                    check_spans(source, &m.value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_parse_spanned() {
+        let source = " {\"a\\u0062\" :[1, -2.5e3,\t\"x\\\"\", {}, []],\n\
+                      \"c\": {\"d\": null, \"d\": true},\"\u{e9}\": false } ";
+        let doc = json::parse_spanned(source).unwrap();
+        assert_eq!(doc.span, 1..source.len() - 1);
+        assert_eq!(doc.to_json(), Json::from_str(source).unwrap());
+        check_spans(source, &doc);
+
+        assert_eq!(doc.pointer("/ab/2").unwrap().value, SpannedValue::String("x\"".to_string()));
+        assert_eq!(doc.pointer("/c/d").unwrap().value, SpannedValue::Boolean(true));
+        assert_eq!(doc.pointer("").unwrap().span, doc.span);
+        assert!(doc.pointer("/ab/5").is_none() && doc.pointer("/x").is_none());
+        match doc.get("c").unwrap().value {
+            SpannedValue::Object(ref members) => assert_eq!(members.len(), 2),
+            _ => panic!(),
+        }
+
+        let one = source.find('1').unwrap();
+        assert_eq!(doc.node_at(one).unwrap().value, SpannedValue::U64(1));
+        assert_eq!(doc.node_at(one - 1).unwrap().span, doc.get("ab").unwrap().span);
+        assert_eq!(doc.node_at(2).unwrap().span, doc.span);
+        assert!(doc.node_at(0).is_none());
+
+        assert_eq!(json::parse_spanned("7").unwrap().span, 0..1);
+        assert!(json::parse_spanned("[1,]").is_err());
+        assert!(json::parse_spanned("[1] 2").is_err());
+    }
+}
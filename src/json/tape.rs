@@ -0,0 +1,425 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A compact, read-only representation of parsed JSON
+//!
+//! A `JsonTape` holds a document as one flat vector of nodes in document
+//! order, with the text of all its strings and keys in a single arena.
+//! Building one makes two allocations that grow as needed, rather than one
+//! or more per node as `Json` does, and the whole document stays together
+//! in memory, which suits scanning many documents for a few values.
+//!
+//! Values are navigated with `TapeValue`, a cheap reference into the tape.
+//! Looking up an object member searches the object's members in order, so
+//! objects with many members are better iterated over than searched.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::Json;
+//! use rustc_serialize::json::tape::JsonTape;
+//!
+//! fn main() {
+//!     let doc = r#"{"items": [{"id": 1, "tags": ["a"]}, {"id": 2, "tags": []}]}"#;
+//!     let tape = JsonTape::from_str(doc).unwrap();
+//!     let items = tape.root().get("items").unwrap();
+//!     let ids: Vec<u64> = items.elements()
+//!                              .filter_map(|item| item.get("id"))
+//!                              .filter_map(|id| id.as_u64())
+//!                              .collect();
+//!     assert_eq!(ids, [1, 2]);
+//!     assert_eq!(items.at(0).unwrap().get("tags").unwrap().len(), Some(1));
+//!     assert_eq!(tape.to_json(), Json::from_str(doc).unwrap());
+//! }
+//! ```
+
+use std::io::prelude::*;
+use std::mem;
+use std::str::{self, FromStr};
+
+use json::{BuilderError, Json, JsonEvent, Parser, StackElement};
+use json::ErrorCode::NotUtf8;
+use json::ParserError::SyntaxError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Node {
+    Null,
+    Boolean(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    // The start and end of the text in the arena.
+    String(usize, usize),
+    // The number of elements, and the index of the node after the last.
+    // The elements follow.
+    Array(usize, usize),
+    // The number of members, and the index of the node after the last.
+    // Each member follows as a `String` node for the key, then the value.
+    Object(usize, usize),
+}
+
+/// A parsed JSON document, stored flat
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonTape {
+    nodes: Vec<Node>,
+    strings: String,
+}
+
+impl JsonTape {
+    /// Parses a document from a string.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<JsonTape, BuilderError> {
+        let mut tape = JsonTape { nodes: Vec::new(), strings: String::new() };
+        try!(tape.build(Parser::new(s.chars())));
+        Ok(tape)
+    }
+
+    /// Parses a document from a reader.
+    pub fn from_reader(rdr: &mut Read) -> Result<JsonTape, BuilderError> {
+        let mut contents = Vec::new();
+        try!(rdr.read_to_end(&mut contents));
+        match str::from_utf8(&contents) {
+            Ok(s) => JsonTape::from_str(s),
+            Err(_) => Err(SyntaxError(NotUtf8, 0, 0)),
+        }
+    }
+
+    fn build<T: Iterator<Item = char>>(&mut self, mut parser: Parser<T>)
+                                       -> Result<(), BuilderError> {
+        // The indices of the arrays and objects not yet ended, with their
+        // numbers of children so far.
+        let mut open: Vec<(usize, usize)> = Vec::new();
+        loop {
+            let event = match parser.next() {
+                Some(JsonEvent::Error(e)) => return Err(e),
+                Some(event) => event,
+                None => return Ok(()),
+            };
+            match event {
+                JsonEvent::ArrayEnd | JsonEvent::ObjectEnd => {
+                    // The parser only yields ends matching their starts.
+                    let (idx, len) = open.pop().unwrap();
+                    let end = self.nodes.len();
+                    self.nodes[idx] = match self.nodes[idx] {
+                        Node::Array(..) => Node::Array(len, end),
+                        _ => Node::Object(len, end),
+                    };
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(&mut (idx, ref mut len)) = open.last_mut() {
+                *len += 1;
+                if let Node::Object(..) = self.nodes[idx] {
+                    if let Some(StackElement::Key(key)) = parser.stack().top() {
+                        let node = self.push_str(key);
+                        self.nodes.push(node);
+                    }
+                }
+            }
+            let node = match event {
+                JsonEvent::NullValue => Node::Null,
+                JsonEvent::BooleanValue(b) => Node::Boolean(b),
+                JsonEvent::I64Value(n) => Node::I64(n),
+                JsonEvent::U64Value(n) => Node::U64(n),
+                JsonEvent::F64Value(n) => Node::F64(n),
+                JsonEvent::StringValue(ref s) => self.push_str(s),
+                JsonEvent::ArrayStart => {
+                    open.push((self.nodes.len(), 0));
+                    Node::Array(0, 0)
+                }
+                JsonEvent::ObjectStart => {
+                    open.push((self.nodes.len(), 0));
+                    Node::Object(0, 0)
+                }
+                _ => unreachable!(),
+            };
+            self.nodes.push(node);
+        }
+    }
+
+    fn push_str(&mut self, s: &str) -> Node {
+        let start = self.strings.len();
+        self.strings.push_str(s);
+        Node::String(start, self.strings.len())
+    }
+
+    /// Returns the top-level value of the document.
+    pub fn root(&self) -> TapeValue<'_> {
+        TapeValue { tape: self, idx: 0 }
+    }
+
+    /// Converts the document to a `Json` tree.
+    pub fn to_json(&self) -> Json {
+        self.root().to_json()
+    }
+
+    /// Returns the number of bytes of heap memory owned by the tape; this
+    /// is exact, unlike `Json::deep_size_of`.
+    pub fn deep_size_of(&self) -> usize {
+        self.nodes.capacity() * mem::size_of::<Node>() + self.strings.capacity()
+    }
+}
+
+impl FromStr for JsonTape {
+    type Err = BuilderError;
+    fn from_str(s: &str) -> Result<JsonTape, BuilderError> {
+        JsonTape::from_str(s)
+    }
+}
+
+/// A value within a `JsonTape`
+#[derive(Clone, Copy)]
+pub struct TapeValue<'a> {
+    tape: &'a JsonTape,
+    idx: usize,
+}
+
+impl<'a> TapeValue<'a> {
+    fn node(&self) -> Node {
+        self.tape.nodes[self.idx]
+    }
+
+    // The index of the node after this value.
+    fn end(&self) -> usize {
+        match self.node() {
+            Node::Array(_, end) | Node::Object(_, end) => end,
+            _ => self.idx + 1,
+        }
+    }
+
+    fn at_idx(&self, idx: usize) -> TapeValue<'a> {
+        TapeValue { tape: self.tape, idx: idx }
+    }
+
+    /// Returns true if the value is an array.
+    pub fn is_array(&self) -> bool {
+        matches!(self.node(), Node::Array(..))
+    }
+
+    /// Returns true if the value is an object.
+    pub fn is_object(&self) -> bool {
+        matches!(self.node(), Node::Object(..))
+    }
+
+    /// Returns true if the value is null.
+    pub fn is_null(&self) -> bool {
+        self.node() == Node::Null
+    }
+
+    /// If the value is a string, returns it.
+    pub fn as_string(&self) -> Option<&'a str> {
+        match self.node() {
+            Node::String(start, end) => Some(&self.tape.strings[start..end]),
+            _ => None,
+        }
+    }
+
+    /// If the value is a boolean, returns it.
+    pub fn as_boolean(&self) -> Option<bool> {
+        match self.node() { Node::Boolean(b) => Some(b), _ => None }
+    }
+
+    /// If the value is a number, returns or casts it to an i64, as
+    /// `Json::as_i64` does.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.number().and_then(|n| n.as_i64())
+    }
+
+    /// If the value is a number, returns or casts it to a u64, as
+    /// `Json::as_u64` does.
+    pub fn as_u64(&self) -> Option<u64> {
+        self.number().and_then(|n| n.as_u64())
+    }
+
+    /// If the value is a number, returns or casts it to an f64.
+    pub fn as_f64(&self) -> Option<f64> {
+        self.number().and_then(|n| n.as_f64())
+    }
+
+    fn number(&self) -> Option<Json> {
+        match self.node() {
+            Node::I64(n) => Some(Json::I64(n)),
+            Node::U64(n) => Some(Json::U64(n)),
+            Node::F64(n) => Some(Json::F64(n)),
+            _ => None,
+        }
+    }
+
+    /// If the value is an array or object, returns its number of elements
+    /// or members.
+    pub fn len(&self) -> Option<usize> {
+        match self.node() {
+            Node::Array(len, _) | Node::Object(len, _) => Some(len),
+            _ => None,
+        }
+    }
+
+    /// If the value is an array or object, returns whether it has no
+    /// elements or members.
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// If the value is an array, returns its element at `idx`, if any.
+    pub fn at(&self, idx: usize) -> Option<TapeValue<'a>> {
+        if !self.is_array() {
+            return None;
+        }
+        self.elements().nth(idx)
+    }
+
+    /// If the value is an object, returns its member `key`, if any. With
+    /// more than one such member, the last is returned, as it is the one
+    /// `Json` keeps.
+    pub fn get(&self, key: &str) -> Option<TapeValue<'a>> {
+        self.members().filter(|&(k, _)| k == key).last().map(|(_, v)| v)
+    }
+
+    /// Returns an iterator over the elements of an array, which is empty
+    /// for any other value.
+    pub fn elements(&self) -> Elements<'a> {
+        match self.node() {
+            Node::Array(_, end) => Elements { next: self.at_idx(self.idx + 1), end: end },
+            _ => Elements { next: *self, end: self.idx },
+        }
+    }
+
+    /// Returns an iterator over the members of an object, in document
+    /// order, which is empty for any other value.
+    pub fn members(&self) -> Members<'a> {
+        match self.node() {
+            Node::Object(_, end) => Members { next: self.at_idx(self.idx + 1), end: end },
+            _ => Members { next: *self, end: self.idx },
+        }
+    }
+
+    /// Converts the value to a `Json` tree.
+    pub fn to_json(&self) -> Json {
+        match self.node() {
+            Node::Null => Json::Null,
+            Node::Boolean(b) => Json::Boolean(b),
+            Node::I64(n) => Json::I64(n),
+            Node::U64(n) => Json::U64(n),
+            Node::F64(n) => Json::F64(n),
+            Node::String(..) => Json::from(self.as_string().unwrap()),
+            Node::Array(..) => Json::Array(self.elements().map(|v| v.to_json()).collect()),
+            Node::Object(..) => {
+                Json::Object(self.members().map(|(k, v)| (k.to_string(), v.to_json())).collect())
+            }
+        }
+    }
+}
+
+/// An iterator over the elements of an array in a `JsonTape`
+pub struct Elements<'a> {
+    next: TapeValue<'a>,
+    end: usize,
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = TapeValue<'a>;
+
+    fn next(&mut self) -> Option<TapeValue<'a>> {
+        if self.next.idx >= self.end {
+            return None;
+        }
+        let value = self.next;
+        self.next = value.at_idx(value.end());
+        Some(value)
+    }
+}
+
+/// An iterator over the members of an object in a `JsonTape`
+pub struct Members<'a> {
+    next: TapeValue<'a>,
+    end: usize,
+}
+
+impl<'a> Iterator for Members<'a> {
+    type Item = (&'a str, TapeValue<'a>);
+
+    fn next(&mut self) -> Option<(&'a str, TapeValue<'a>)> {
+        if self.next.idx >= self.end {
+            return None;
+        }
+        let key = self.next.as_string().unwrap();
+        let value = self.next.at_idx(self.next.idx + 1);
+        self.next = value.at_idx(value.end());
+        Some((key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::{Json, ParserError};
+    use json::ErrorCode::*;
+    use json::tape::JsonTape;
+    use json::testing::Generator;
+
+    #[test]
+    fn test_navigation() {
+        let tape = JsonTape::from_str(r#"{"a": [1, -2, 3.5, "x", true, null, [], {}],
+                                         "b": {"c": "d"}, "a": "again"}"#).unwrap();
+        let root = tape.root();
+        assert!(root.is_object());
+        assert_eq!(root.len(), Some(3));
+        let keys: Vec<&str> = root.members().map(|(k, _)| k).collect();
+        assert_eq!(keys, ["a", "b", "a"]);
+        assert_eq!(root.get("a").unwrap().as_string(), Some("again"));
+        assert_eq!(root.get("b").unwrap().get("c").unwrap().as_string(), Some("d"));
+        assert!(root.get("z").is_none());
+        assert!(root.at(0).is_none());
+
+        let a = root.members().next().unwrap().1;
+        assert_eq!(a.len(), Some(8));
+        assert_eq!(a.at(0).unwrap().as_u64(), Some(1));
+        assert_eq!(a.at(1).unwrap().as_i64(), Some(-2));
+        assert_eq!(a.at(2).unwrap().as_f64(), Some(3.5));
+        assert_eq!(a.at(3).unwrap().as_string(), Some("x"));
+        assert_eq!(a.at(4).unwrap().as_boolean(), Some(true));
+        assert!(a.at(5).unwrap().is_null());
+        assert_eq!(a.at(6).unwrap().len(), Some(0));
+        assert_eq!(a.at(6).unwrap().is_empty(), Some(true));
+        assert!(a.at(7).unwrap().is_object());
+        assert!(a.at(8).is_none());
+        assert_eq!(a.at(3).unwrap().elements().count(), 0);
+        assert_eq!(a.at(3).unwrap().members().count(), 0);
+    }
+
+    #[test]
+    fn test_to_json() {
+        for seed in 0..200 {
+            let json = Generator::new(seed).json();
+            let text = json.to_string();
+            let tape = JsonTape::from_str(&text).unwrap();
+            assert_eq!(tape.to_json(), json);
+            assert_eq!(JsonTape::from_reader(&mut text.as_bytes()).unwrap(), tape);
+        }
+        assert_eq!(JsonTape::from_str("7").unwrap().to_json(), Json::U64(7));
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases = [("", EOFWhileParsingValue), ("[1,", EOFWhileParsingValue),
+                     ("{\"a\" 1}", ExpectedColon), ("[] []", TrailingCharacters)];
+        for &(doc, code) in cases.iter() {
+            match JsonTape::from_str(doc) {
+                Err(ParserError::SyntaxError(c, _, _)) => assert_eq!(c, code, "{}", doc),
+                other => panic!("{}: {:?}", doc, other),
+            }
+        }
+        match JsonTape::from_reader(&mut &b"\"\xff\""[..]) {
+            Err(ParserError::SyntaxError(NotUtf8, _, _)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+}
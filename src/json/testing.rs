@@ -0,0 +1,328 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Random generators for property tests and fuzzing
+//!
+//! A `Generator` produces random, valid `Json` trees and random byte streams
+//! which are *not* valid JSON. Generation is deterministic for a given seed,
+//! so failing cases can be reproduced.
+//!
+//! Generated trees are normalized the way the parser would produce them
+//! (non-negative integers are `U64`, negative ones `I64`, and floats are
+//! exactly representable in decimal), so encoding one and parsing the output
+//! gives back an equal tree.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::Json;
+//! use rustc_serialize::json::testing::Generator;
+//!
+//! fn main() {
+//!     for seed in 0..100 {
+//!         let mut gen = Generator::new(seed);
+//!         let json = gen.json();
+//!         assert_eq!(Json::from_str(&json.to_string()).unwrap(), json);
+//!         assert!(Json::from_str(&json.pretty().to_string()).is_ok());
+//!
+//!         let garbage = gen.invalid_bytes();
+//!         assert!(Json::from_reader(&mut &garbage[..]).is_err());
+//!     }
+//! }
+//! ```
+
+use std::char;
+use std::collections::BTreeMap;
+use std::str;
+
+use json::Json;
+
+/// The characters used for generated strings and object keys.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Charset {
+    /// Alphanumeric ASCII characters only
+    Alphanumeric,
+    /// Any ASCII character, including control characters and characters
+    /// requiring escapes
+    Ascii,
+    /// Any Unicode scalar value
+    Unicode,
+    /// Characters drawn from the given set, which must not be empty
+    Custom(Vec<char>),
+}
+
+/// Contains configuration parameters for a `Generator`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Maximum nesting depth of arrays and objects; `0` only generates
+    /// scalars
+    pub max_depth: usize,
+    /// Maximum number of elements in a generated array or object
+    pub max_len: usize,
+    /// Maximum length of generated strings and keys, in characters
+    pub max_string_len: usize,
+    /// Characters to use for strings and keys
+    pub charset: Charset,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            max_depth: 4,
+            max_len: 8,
+            max_string_len: 16,
+            charset: Charset::Unicode,
+        }
+    }
+}
+
+/// A deterministic generator of random JSON data.
+pub struct Generator {
+    state: u64,
+    config: Config,
+}
+
+impl Generator {
+    /// Creates a generator with the default configuration.
+    pub fn new(seed: u64) -> Generator {
+        Generator::with_config(seed, Config::default())
+    }
+
+    /// Creates a generator with the given configuration.
+    pub fn with_config(seed: u64, config: Config) -> Generator {
+        // xorshift must not start from zero; mix the seed so nearby seeds
+        // diverge immediately.
+        let state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+        Generator { state: state, config: config }
+    }
+
+    /// Returns this generator's configuration.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the next random 64-bit value.
+    pub fn next_u64(&mut self) -> u64 {
+        // xorshift64*
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a random value in `0..n`, or `0` if `n` is `0`.
+    pub fn below(&mut self, n: usize) -> usize {
+        if n == 0 { 0 } else { (self.next_u64() % n as u64) as usize }
+    }
+
+    /// Generates a random JSON value.
+    pub fn json(&mut self) -> Json {
+        let depth = self.config.max_depth;
+        self.value(depth)
+    }
+
+    /// Generates a random JSON value whose top level is an array or object.
+    pub fn document(&mut self) -> Json {
+        let depth = if self.config.max_depth == 0 { 1 } else { self.config.max_depth };
+        if self.below(2) == 0 { self.array(depth) } else { self.object(depth) }
+    }
+
+    /// Generates a random scalar (null, boolean, number or string).
+    pub fn scalar(&mut self) -> Json {
+        match self.below(6) {
+            0 => Json::Null,
+            1 => Json::Boolean(self.below(2) == 0),
+            2 => Json::U64(self.next_u64() >> self.below(64)),
+            3 => {
+                let n = (self.next_u64() >> (self.below(63) + 1)) as i64;
+                Json::I64(-1 - n)
+            }
+            4 => {
+                // Halves of small integers print and parse back exactly.
+                let n = (self.next_u64() >> 44) as i64 - (1 << 19);
+                Json::F64(n as f64 / 2.0)
+            }
+            _ => Json::from(self.string()),
+        }
+    }
+
+    /// Generates a random string using the configured character set.
+    pub fn string(&mut self) -> String {
+        let len = self.below(self.config.max_string_len + 1);
+        (0..len).map(|_| self.char()).collect()
+    }
+
+    fn char(&mut self) -> char {
+        const ALNUM: &[u8] = b"abcdefghijklmnopqrstuvwxyz\
+                               ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+                               0123456789";
+        let r = self.next_u64();
+        match self.config.charset {
+            Charset::Alphanumeric => ALNUM[(r % ALNUM.len() as u64) as usize] as char,
+            Charset::Ascii => (r % 0x80) as u8 as char,
+            Charset::Unicode => {
+                // Favour the BMP, which is where most real text lives.
+                let max = if r & 3 == 0 { 0x11_0000 } else { 0x1_0000 };
+                loop {
+                    if let Some(c) = char::from_u32(self.below(max) as u32) {
+                        return c;
+                    }
+                }
+            }
+            Charset::Custom(ref chars) => chars[(r % chars.len() as u64) as usize],
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Json {
+        if depth == 0 {
+            return self.scalar();
+        }
+        match self.below(4) {
+            0 => self.array(depth),
+            1 => self.object(depth),
+            _ => self.scalar(),
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Json {
+        let len = self.below(self.config.max_len + 1);
+        Json::Array((0..len).map(|_| self.value(depth - 1)).collect())
+    }
+
+    fn object(&mut self, depth: usize) -> Json {
+        let len = self.below(self.config.max_len + 1);
+        let mut map = BTreeMap::new();
+        for _ in 0..len {
+            let key = self.string();
+            let value = self.value(depth - 1);
+            map.insert(key, value);
+        }
+        Json::Object(map)
+    }
+
+    /// Generates a random byte stream which is not a valid JSON document.
+    ///
+    /// The stream is derived from a valid document by truncating it,
+    /// inserting or deleting bytes, or corrupting its UTF-8 encoding, so it
+    /// usually looks plausibly like JSON.
+    pub fn invalid_bytes(&mut self) -> Vec<u8> {
+        let mut doc = self.json().to_string().into_bytes();
+        for _ in 0..16 {
+            let mut bytes = doc.clone();
+            self.mutate(&mut bytes);
+            if !is_valid(&bytes) {
+                return bytes;
+            }
+            doc = bytes;
+        }
+        // An unmatched closing bracket is always trailing garbage.
+        doc.push(b']');
+        doc
+    }
+
+    fn mutate(&mut self, bytes: &mut Vec<u8>) {
+        const INTERESTING: &[u8] = b"{}[]\",:\\-.eE0tfn \xff\xc3\x80\x00";
+        let len = bytes.len();
+        match self.below(5) {
+            0 => bytes.truncate(self.below(len)),
+            1 => {
+                let b = INTERESTING[self.below(INTERESTING.len())];
+                bytes.insert(self.below(len + 1), b);
+            }
+            2 if len > 0 => { bytes.remove(self.below(len)); }
+            3 if len > 0 => {
+                let idx = self.below(len);
+                bytes[idx] = self.next_u64() as u8;
+            }
+            _ => {
+                // Splice in an invalid UTF-8 sequence.
+                let idx = self.below(len + 1);
+                let garbage: &[u8] = match self.below(3) {
+                    0 => b"\x80",
+                    1 => b"\xed\xa0\x80",
+                    _ => b"\xf8\x88\x80\x80\x80",
+                };
+                for (i, &b) in garbage.iter().enumerate() {
+                    bytes.insert(idx + i, b);
+                }
+            }
+        }
+    }
+}
+
+fn is_valid(bytes: &[u8]) -> bool {
+    match str::from_utf8(bytes) {
+        Ok(s) => Json::from_str(s).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::Json;
+    use super::{Charset, Config, Generator};
+
+    fn depth(json: &Json) -> usize {
+        match *json {
+            Json::Array(ref v) => 1 + v.iter().map(depth).max().unwrap_or(0),
+            Json::Object(ref o) => 1 + o.values().map(depth).max().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for seed in 0..500 {
+            let json = Generator::new(seed).json();
+            assert_eq!(Json::from_str(&json.to_string()).unwrap(), json);
+            assert_eq!(Json::from_str(&json.pretty().to_string()).unwrap(), json);
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        assert_eq!(Generator::new(42).json(), Generator::new(42).json());
+        assert_eq!(Generator::new(7).invalid_bytes(), Generator::new(7).invalid_bytes());
+    }
+
+    #[test]
+    fn test_config_limits() {
+        let config = Config {
+            max_depth: 2,
+            max_len: 3,
+            max_string_len: 5,
+            charset: Charset::Custom(vec!['x', 'y']),
+        };
+        for seed in 0..200 {
+            let json = Generator::with_config(seed, config.clone()).document();
+            assert!(depth(&json) >= 1 && depth(&json) <= 2);
+            match json {
+                Json::Array(ref v) => assert!(v.len() <= 3),
+                Json::Object(ref o) => {
+                    assert!(o.len() <= 3);
+                    for key in o.keys() {
+                        assert!(key.len() <= 5);
+                        assert!(key.chars().all(|c| c == 'x' || c == 'y'));
+                    }
+                }
+                _ => panic!("expected a container"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        for seed in 0..500 {
+            let bytes = Generator::new(seed).invalid_bytes();
+            assert!(Json::from_reader(&mut &bytes[..]).is_err());
+        }
+    }
+}
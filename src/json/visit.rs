@@ -0,0 +1,183 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Transforming `Json` trees in place
+//!
+//! A `JsonVisitor` has a method for each type of value, which
+//! `Json::walk_mut` calls for every value in a tree, depth first, with the
+//! path to it and a mutable reference to it. Each method does nothing by
+//! default, apart from `visit_value`, `visit_array` and `visit_object`,
+//! which carry on into the value's elements or members by calling
+//! `walk_value`, `walk_array` and `walk_object`; a visitor overriding them
+//! calls those itself to carry on, before or after changing the value, or
+//! not at all to skip it.
+//!
+//! `visit_value` can replace a value with one of another type, and
+//! `visit_array` and `visit_object` can add and remove elements and
+//! members.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::{Json, Object, StackElement};
+//! use rustc_serialize::json::visit::{walk_object, JsonVisitor};
+//!
+//! // Trims strings and removes members which are null.
+//! struct Tidy;
+//!
+//! impl JsonVisitor for Tidy {
+//!     fn visit_string(&mut self, _path: &[StackElement], s: &mut String) {
+//!         *s = s.trim().into();
+//!     }
+//!
+//!     fn visit_object(&mut self, path: &[StackElement], o: &mut Object) {
+//!         walk_object(self, path, o);
+//!         o.retain(|_, v| !v.is_null());
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut json = Json::from_str(r#"{"name": " ada ", "tags": [{"x": null}]}"#).unwrap();
+//!     json.walk_mut(&mut Tidy);
+//!     assert_eq!(json.to_string(), r#"{"name":"ada","tags":[{}]}"#);
+//! }
+//! ```
+
+use json::{Array, Json, Object, StackElement};
+use wtf8::Wtf8Buf;
+
+/// A transformation of the values in a `Json` tree; see the module
+/// documentation.
+///
+/// `path` is the path from the root of the tree to the value visited.
+pub trait JsonVisitor {
+    /// Visits any value, by calling `walk_value`.
+    fn visit_value(&mut self, path: &[StackElement], value: &mut Json) {
+        walk_value(self, path, value)
+    }
+
+    /// Visits `null`.
+    fn visit_null(&mut self, _path: &[StackElement]) {}
+
+    /// Visits a boolean.
+    fn visit_boolean(&mut self, _path: &[StackElement], _value: &mut bool) {}
+
+    /// Visits a negative integer, or any held as an `I64`.
+    fn visit_i64(&mut self, _path: &[StackElement], _value: &mut i64) {}
+
+    /// Visits an integer held as a `U64`.
+    fn visit_u64(&mut self, _path: &[StackElement], _value: &mut u64) {}
+
+    /// Visits a floating point number.
+    fn visit_f64(&mut self, _path: &[StackElement], _value: &mut f64) {}
+
+    /// Visits a string.
+    fn visit_string(&mut self, _path: &[StackElement], _value: &mut String) {}
+
+    /// Visits a string holding lone surrogates.
+    fn visit_wtf8(&mut self, _path: &[StackElement], _value: &mut Wtf8Buf) {}
+
+    /// Visits an array, by calling `walk_array`.
+    fn visit_array(&mut self, path: &[StackElement], array: &mut Array) {
+        walk_array(self, path, array)
+    }
+
+    /// Visits an object, by calling `walk_object`.
+    fn visit_object(&mut self, path: &[StackElement], object: &mut Object) {
+        walk_object(self, path, object)
+    }
+}
+
+/// Calls the method of `visitor` for the type of `value`.
+pub fn walk_value<V>(visitor: &mut V, path: &[StackElement], value: &mut Json)
+    where V: JsonVisitor + ?Sized
+{
+    match *value {
+        Json::I64(ref mut n) => visitor.visit_i64(path, n),
+        Json::U64(ref mut n) => visitor.visit_u64(path, n),
+        Json::F64(ref mut n) => visitor.visit_f64(path, n),
+        Json::String(ref mut s) => visitor.visit_string(path, s),
+        Json::Wtf8(ref mut s) => visitor.visit_wtf8(path, s),
+        Json::Boolean(ref mut b) => visitor.visit_boolean(path, b),
+        Json::Array(ref mut a) => visitor.visit_array(path, a),
+        Json::Object(ref mut o) => visitor.visit_object(path, o),
+        Json::Null => visitor.visit_null(path),
+    }
+}
+
+/// Calls `visitor.visit_value` for each element of `array`, in order.
+pub fn walk_array<V>(visitor: &mut V, path: &[StackElement], array: &mut Array)
+    where V: JsonVisitor + ?Sized
+{
+    let mut elt_path = path.to_vec();
+    elt_path.push(StackElement::Index(0));
+    for (i, elt) in array.iter_mut().enumerate() {
+        elt_path[path.len()] = StackElement::Index(i as u32);
+        visitor.visit_value(&elt_path, elt);
+    }
+}
+
+/// Calls `visitor.visit_value` for each member of `object`, in order of
+/// their keys.
+pub fn walk_object<V>(visitor: &mut V, path: &[StackElement], object: &mut Object)
+    where V: JsonVisitor + ?Sized
+{
+    for (key, member) in object.iter_mut() {
+        let mut member_path = path.to_vec();
+        member_path.push(StackElement::Key(key));
+        visitor.visit_value(&member_path, member);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::{Json, StackElement};
+    use json::visit::{walk_value, JsonVisitor};
+
+    // Holds whole numbers as `I64`s where they fit, and records the paths of
+    // the strings it sees.
+    struct Normalize {
+        strings: Vec<String>,
+    }
+
+    impl JsonVisitor for Normalize {
+        fn visit_value(&mut self, path: &[StackElement], value: &mut Json) {
+            match *value {
+                Json::F64(f) if f.fract() == 0.0 && f.abs() < 1e15 => *value = Json::I64(f as i64),
+                Json::U64(n) if n <= i64::MAX as u64 => *value = Json::I64(n as i64),
+                _ => walk_value(self, path, value),
+            }
+        }
+
+        fn visit_string(&mut self, path: &[StackElement], _value: &mut String) {
+            let path: Vec<String> = path.iter().map(|elt| match *elt {
+                StackElement::Key(k) => k.to_string(),
+                StackElement::Index(i) => i.to_string(),
+            }).collect();
+            self.strings.push(path.join("/"));
+        }
+    }
+
+    #[test]
+    fn test_walk_mut() {
+        let mut json = Json::from_str(r#"{"a": [1.0, 2.5, 3, "x"], "b": {"c": "y"}, "d": 4.0}"#)
+                           .unwrap();
+        let mut normalize = Normalize { strings: vec![] };
+        json.walk_mut(&mut normalize);
+        assert_eq!(json.to_string(), r#"{"a":[1,2.5,3,"x"],"b":{"c":"y"},"d":4}"#);
+        assert_eq!((&json["a"][2], &json["d"]), (&Json::I64(3), &Json::I64(4)));
+        assert_eq!(normalize.strings, ["a/3", "b/c"]);
+
+        let mut scalar = Json::F64(1.0);
+        scalar.walk_mut(&mut normalize);
+        assert_eq!(scalar, Json::I64(1));
+    }
+}
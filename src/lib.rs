@@ -49,9 +49,13 @@
                      reason = "use the crates.io `rustc-serialize` library instead"))]
 
 #[cfg(test)] extern crate rand;
+#[cfg(all(feature = "mmap", unix))] extern crate libc;
 
 pub use self::serialize::{Decoder, Encoder, Decodable, Encodable,
-                          DecoderHelpers, EncoderHelpers};
+                          DecoderHelpers, EncoderHelpers, ExtensionTag, Tagged};
+pub use self::dyn_encoder::{encode_dyn, DynEncoder, DynEncoderError};
+#[doc(hidden)]
+pub use self::fuzz::{fuzz_base64, fuzz_json, fuzz_utf8};
 
 
 // Limit collections from allocating more than
@@ -69,10 +73,61 @@ fn cap_capacity<T>(given_len: usize) -> usize {
 
 mod serialize;
 mod collection_impls;
+mod dyn_encoder;
+mod progress;
 
+pub mod avro;
+pub mod base32;
 pub mod base64;
+pub mod bits;
+pub mod bson;
+pub mod byteorder;
+pub mod bytes;
+pub mod case;
+pub mod cbor;
+pub mod chunked;
+pub mod codec;
+pub mod convert;
+pub mod dnswire;
+pub mod event;
+pub mod form_urlencoded;
+pub mod half;
 pub mod hex;
+pub mod html;
+pub mod ini;
+pub mod jose;
 pub mod json;
+pub mod limit;
+pub mod metrics;
+pub mod mime;
+pub mod multipart;
+pub mod netaddr;
+pub mod netstring;
+pub mod overrides;
+pub mod packed;
+pub mod promtext;
+pub mod protowire;
+pub mod quoted_printable;
+pub mod redact;
+pub mod rfc2047;
+pub mod semver_lite;
+pub mod sse;
+pub mod stats;
+pub mod tarhdr;
+pub mod textprop;
+pub mod utf8;
+pub mod versioned;
+pub mod wtf8;
+pub mod xml;
+
+#[doc(hidden)]
+pub mod fuzz;
+
+#[cfg(feature = "fun")]
+pub mod fun;
+
+#[cfg(feature = "mmap")]
+pub mod mmap;
 
 mod rustc_serialize {
     pub use serialize::*;
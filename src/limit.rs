@@ -0,0 +1,159 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Limiting the size of untrusted input
+//!
+//! A `LimitedReader` fails with a `SizeLimitExceeded` error once its input
+//! turns out to be larger than the limit. The parsers in this crate
+//! recognize that error and report it as such (for example as
+//! `json::ParserError::SizeLimitExceeded`) rather than as a generic I/O
+//! error, so callers can tell "too large" apart from "malformed".
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::{Json, ParserError};
+//! use rustc_serialize::limit::LimitedReader;
+//!
+//! fn main() {
+//!     let mut reader = LimitedReader::new(&b"[1, 2, 3]"[..], 4);
+//!     match Json::from_reader(&mut reader) {
+//!         Err(ParserError::SizeLimitExceeded(4)) => {}
+//!         other => panic!("unexpected result: {:?}", other),
+//!     }
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+/// The error returned once a `LimitedReader`'s input exceeds its limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SizeLimitExceeded {
+    /// The limit, in bytes
+    pub limit: u64,
+}
+
+impl SizeLimitExceeded {
+    /// Returns the `SizeLimitExceeded` error wrapped by `err`, if any.
+    pub fn from_io_error(err: &io::Error) -> Option<SizeLimitExceeded> {
+        err.get_ref().and_then(|e| e.downcast_ref::<SizeLimitExceeded>()).cloned()
+    }
+}
+
+impl fmt::Display for SizeLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "input exceeds the size limit of {} bytes", self.limit)
+    }
+}
+
+impl error::Error for SizeLimitExceeded {
+    fn description(&self) -> &str { "size limit exceeded" }
+}
+
+/// A reader passing on at most `limit` bytes of the underlying reader.
+///
+/// Input of exactly `limit` bytes is read successfully. If there is more,
+/// the read which would go past the limit fails with an `io::Error` of kind
+/// `InvalidData` wrapping `SizeLimitExceeded`, and so does every read after
+/// it.
+pub struct LimitedReader<R> {
+    inner: R,
+    limit: u64,
+    remaining: u64,
+    exceeded: bool,
+}
+
+impl<R: Read> LimitedReader<R> {
+    /// Creates a new reader passing on at most `limit` bytes of `inner`.
+    pub fn new(inner: R, limit: u64) -> LimitedReader<R> {
+        LimitedReader { inner: inner, limit: limit, remaining: limit, exceeded: false }
+    }
+
+    /// Returns the limit, in bytes.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwraps this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn limit_error(&self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, SizeLimitExceeded { limit: self.limit })
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.exceeded {
+            return Err(self.limit_error());
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            // The limit may fall exactly at the end of the input.
+            let mut probe = [0];
+            if try!(self.inner.read(&mut probe)) == 0 {
+                return Ok(0);
+            }
+            self.exceeded = true;
+            return Err(self.limit_error());
+        }
+        let max = if (buf.len() as u64) < self.remaining {
+            buf.len()
+        } else {
+            self.remaining as usize
+        };
+        let n = try!(self.inner.read(&mut buf[..max]));
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+    use hex::HexCodec;
+    use codec::CodecReader;
+    use limit::{LimitedReader, SizeLimitExceeded};
+
+    #[test]
+    fn test_limit() {
+        let mut out = Vec::new();
+        LimitedReader::new(&b"abcd"[..], 4).read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"abcd");
+
+        out.clear();
+        let mut reader = LimitedReader::new(&b"abcde"[..], 4);
+        let err = reader.read_to_end(&mut out).unwrap_err();
+        assert_eq!(SizeLimitExceeded::from_io_error(&err), Some(SizeLimitExceeded { limit: 4 }));
+        assert_eq!(out, b"abcd");
+        assert!(reader.read(&mut [0; 4]).is_err());
+    }
+
+    #[test]
+    fn test_limit_through_codec() {
+        let mut out = Vec::new();
+        let reader = LimitedReader::new(&b"666f6f626172"[..], 6);
+        let err = CodecReader::new(reader, HexCodec::new()).read_to_end(&mut out).unwrap_err();
+        assert_eq!(SizeLimitExceeded::from_io_error(&err), Some(SizeLimitExceeded { limit: 6 }));
+    }
+}
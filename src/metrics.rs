@@ -0,0 +1,314 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Encoding metrics as statsd datagrams and InfluxDB line protocol
+//!
+//! `statsd` encodes a sample of a metric as a statsd datagram,
+//! `name:value|type`, with a sample rate and DogStatsD tags
+//! (`|@0.5|#region:eu,canary`) where given. Statsd has no escapes, so the
+//! characters which separate the parts of a datagram, and newlines, are
+//! replaced with `_` in names and tags. A gauge cannot be set to a negative
+//! value directly, as a signed value changes it instead, so `statsd`
+//! first sets it to 0 on a line of its own.
+//!
+//! `influx_line` encodes a point as a line of InfluxDB line protocol:
+//! `measurement,tag=value field=value timestamp`. Commas and spaces in the
+//! measurement, and commas, spaces and `=` in tag keys, tag values and
+//! field keys, are escaped with `\`, as are `"` and `\` in string field
+//! values, which are quoted. Newlines cannot be written, and are an error.
+//! Tags are written sorted by key, as InfluxDB stores them, and tags with
+//! empty values are left out.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::metrics::{self, Field, Kind};
+//!
+//! fn main() {
+//!     assert_eq!(metrics::statsd("api.requests", 1.0, Kind::Counter, 0.1,
+//!                                &[("route", "/users"), ("canary", "")]).unwrap(),
+//!                "api.requests:1|c|@0.1|#route:/users,canary");
+//!
+//!     let line = metrics::influx_line("cpu load", &[("host", "a,b"), ("dc", "eu")],
+//!                                     &[("idle", Field::from(0.5)), ("procs", Field::from(7))],
+//!                                     Some(1496318400000000000)).unwrap();
+//!     assert_eq!(line, r"cpu\ load,dc=eu,host=a\,b idle=0.5,procs=7i 1496318400000000000");
+//! }
+//! ```
+
+use std::error::Error as StdError;
+use std::fmt;
+
+/// An error from encoding a metric.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetricsError {
+    /// The name of the metric or measurement is empty
+    EmptyName,
+    /// A point has no fields, which line protocol requires
+    NoFields,
+    /// The value, of the field named, is infinite or NaN
+    NonFinite(String),
+    /// The name, key or value given has a newline, which line protocol
+    /// cannot hold
+    Newline(String),
+}
+
+impl fmt::Display for MetricsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MetricsError::EmptyName => write!(f, "empty metric name"),
+            MetricsError::NoFields => write!(f, "point without fields"),
+            MetricsError::NonFinite(ref name) => write!(f, "non-finite value for {:?}", name),
+            MetricsError::Newline(ref s) => write!(f, "newline in {:?}", s),
+        }
+    }
+}
+
+impl StdError for MetricsError {
+    fn description(&self) -> &str { "metrics encoding error" }
+}
+
+/// The type of a statsd metric
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// A count of events, `c`
+    Counter,
+    /// A value which is kept until the next is sent, `g`
+    Gauge,
+    /// A change to a gauge, `g` with the sign always written
+    GaugeDelta,
+    /// A duration in milliseconds, `ms`
+    Timer,
+    /// A value whose distribution is measured, `h`
+    Histogram,
+    /// A value whose distribution is measured across hosts, `d`
+    Distribution,
+}
+
+impl Kind {
+    fn code(&self) -> &'static str {
+        match *self {
+            Kind::Counter => "c",
+            Kind::Gauge | Kind::GaugeDelta => "g",
+            Kind::Timer => "ms",
+            Kind::Histogram => "h",
+            Kind::Distribution => "d",
+        }
+    }
+}
+
+/// Encodes a sample `value` of the metric `name` as a statsd datagram,
+/// sampled at `sample_rate`, which is left out when it is 1 or more, with
+/// the DogStatsD tags `tags`, a tag with an empty value being written as
+/// its key alone.
+pub fn statsd(name: &str, value: f64, kind: Kind, sample_rate: f64, tags: &[(&str, &str)])
+              -> Result<String, MetricsError> {
+    if !value.is_finite() {
+        return Err(MetricsError::NonFinite(name.to_string()));
+    }
+    let value = match kind {
+        Kind::GaugeDelta if value >= 0.0 => format!("+{}", value),
+        _ => value.to_string(),
+    };
+    let mut datagram = String::new();
+    if kind == Kind::Gauge && value.starts_with('-') {
+        try!(push_statsd(&mut datagram, name, "0", kind.code(), 1.0, tags));
+        datagram.push('\n');
+    }
+    try!(push_statsd(&mut datagram, name, &value, kind.code(), sample_rate, tags));
+    Ok(datagram)
+}
+
+/// Encodes `member` as a statsd datagram counting it in the set of unique
+/// values the metric `name` holds, with the DogStatsD tags `tags`.
+pub fn statsd_set(name: &str, member: &str, tags: &[(&str, &str)])
+                  -> Result<String, MetricsError> {
+    let mut datagram = String::new();
+    try!(push_statsd(&mut datagram, name, &sanitize(member, ":|@#"), "s", 1.0, tags));
+    Ok(datagram)
+}
+
+fn push_statsd(datagram: &mut String, name: &str, value: &str, code: &str, sample_rate: f64,
+               tags: &[(&str, &str)]) -> Result<(), MetricsError> {
+    if name.is_empty() {
+        return Err(MetricsError::EmptyName);
+    }
+    datagram.push_str(&sanitize(name, ":|@#"));
+    datagram.push(':');
+    datagram.push_str(value);
+    datagram.push('|');
+    datagram.push_str(code);
+    if sample_rate < 1.0 {
+        datagram.push_str(&format!("|@{}", sample_rate));
+    }
+    for (i, &(key, value)) in tags.iter().enumerate() {
+        datagram.push_str(if i == 0 { "|#" } else { "," });
+        datagram.push_str(&sanitize(key, ":|@#,"));
+        if !value.is_empty() {
+            datagram.push(':');
+            datagram.push_str(&sanitize(value, "|@#,"));
+        }
+    }
+    Ok(())
+}
+
+// `s` with the characters in `reserved`, and newlines, replaced with `_`.
+fn sanitize(s: &str, reserved: &str) -> String {
+    s.chars().map(|c| if c == '\n' || c == '\r' || reserved.contains(c) { '_' } else { c })
+             .collect()
+}
+
+/// The value of a field of an InfluxDB point
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field<'a> {
+    /// A float, written as it is
+    Float(f64),
+    /// A signed integer, written with the suffix `i`
+    Integer(i64),
+    /// An unsigned integer, written with the suffix `u`
+    UInteger(u64),
+    /// A string, written quoted
+    String(&'a str),
+    /// A boolean, written as `true` or `false`
+    Boolean(bool),
+}
+
+impl<'a> From<f64> for Field<'a> {
+    fn from(v: f64) -> Field<'a> { Field::Float(v) }
+}
+
+impl<'a> From<i64> for Field<'a> {
+    fn from(v: i64) -> Field<'a> { Field::Integer(v) }
+}
+
+impl<'a> From<i32> for Field<'a> {
+    fn from(v: i32) -> Field<'a> { Field::Integer(v as i64) }
+}
+
+impl<'a> From<u64> for Field<'a> {
+    fn from(v: u64) -> Field<'a> { Field::UInteger(v) }
+}
+
+impl<'a> From<&'a str> for Field<'a> {
+    fn from(v: &'a str) -> Field<'a> { Field::String(v) }
+}
+
+impl<'a> From<bool> for Field<'a> {
+    fn from(v: bool) -> Field<'a> { Field::Boolean(v) }
+}
+
+/// Encodes a point of `measurement` as a line of InfluxDB line protocol,
+/// without its newline, with the tags `tags`, the fields `fields`, and the
+/// `timestamp`, in the precision the line is to be written with, if any.
+pub fn influx_line(measurement: &str, tags: &[(&str, &str)], fields: &[(&str, Field)],
+                   timestamp: Option<i64>) -> Result<String, MetricsError> {
+    if measurement.is_empty() {
+        return Err(MetricsError::EmptyName);
+    }
+    if fields.is_empty() {
+        return Err(MetricsError::NoFields);
+    }
+    let mut line = String::new();
+    try!(push_escaped(&mut line, measurement, ", "));
+    let mut tags: Vec<_> = tags.iter().filter(|&&(_, value)| !value.is_empty()).collect();
+    tags.sort_by_key(|&&(key, _)| key);
+    for &&(key, value) in &tags {
+        line.push(',');
+        try!(push_escaped(&mut line, key, ", ="));
+        line.push('=');
+        try!(push_escaped(&mut line, value, ", ="));
+    }
+    for (i, &(key, value)) in fields.iter().enumerate() {
+        line.push(if i == 0 { ' ' } else { ',' });
+        try!(push_escaped(&mut line, key, ", ="));
+        line.push('=');
+        match value {
+            Field::Float(v) if !v.is_finite() => {
+                return Err(MetricsError::NonFinite(key.to_string()))
+            }
+            Field::Float(v) => line.push_str(&v.to_string()),
+            Field::Integer(v) => line.push_str(&format!("{}i", v)),
+            Field::UInteger(v) => line.push_str(&format!("{}u", v)),
+            Field::String(v) => {
+                line.push('"');
+                try!(push_escaped(&mut line, v, "\"\\"));
+                line.push('"');
+            }
+            Field::Boolean(v) => line.push_str(if v { "true" } else { "false" }),
+        }
+    }
+    if let Some(timestamp) = timestamp {
+        line.push(' ');
+        line.push_str(&timestamp.to_string());
+    }
+    Ok(line)
+}
+
+// Appends `s` to the line with the characters in `special` escaped.
+fn push_escaped(line: &mut String, s: &str, special: &str) -> Result<(), MetricsError> {
+    for c in s.chars() {
+        if c == '\n' {
+            return Err(MetricsError::Newline(s.to_string()));
+        }
+        if special.contains(c) {
+            line.push('\\');
+        }
+        line.push(c);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+    use metrics::{self, Field, Kind, MetricsError};
+
+    #[test]
+    fn test_statsd() {
+        assert_eq!(metrics::statsd("a", 2.5, Kind::Timer, 1.0, &[]).unwrap(), "a:2.5|ms");
+        assert_eq!(metrics::statsd("a:b|c@d", 3.0, Kind::Histogram, 1.5,
+                                   &[("k:x", "v:|y"), ("#", "\n")]).unwrap(),
+                   "a_b_c_d:3|h|#k_x:v:_y,_:_");
+        assert_eq!(metrics::statsd("g", -4.0, Kind::Gauge, 0.5, &[("t", "1")]).unwrap(),
+                   "g:0|g|#t:1\ng:-4|g|@0.5|#t:1");
+        assert_eq!(metrics::statsd("g", 4.0, Kind::GaugeDelta, 1.0, &[]).unwrap(), "g:+4|g");
+        assert_eq!(metrics::statsd("g", -4.0, Kind::GaugeDelta, 1.0, &[]).unwrap(), "g:-4|g");
+        assert_eq!(metrics::statsd("d", 0.25, Kind::Distribution, 1.0, &[]).unwrap(),
+                   "d:0.25|d");
+        assert_eq!(metrics::statsd_set("users", "id|7", &[("c", "")]).unwrap(),
+                   "users:id_7|s|#c");
+        assert_eq!(metrics::statsd("", 1.0, Kind::Counter, 1.0, &[]),
+                   Err(MetricsError::EmptyName));
+        assert_eq!(metrics::statsd("n", f64::NAN, Kind::Counter, 1.0, &[]),
+                   Err(MetricsError::NonFinite("n".to_string())));
+    }
+
+    #[test]
+    fn test_influx_line() {
+        let line = metrics::influx_line("m,x", &[("b k", "v=1"), ("a", "2"), ("e", "")],
+                                        &[("s", Field::from("say \"hi\" \\o/")),
+                                          ("u", Field::from(3u64)), ("f", Field::from(2.0)),
+                                          ("n=1", Field::from(-1i64)),
+                                          ("t", Field::from(false))],
+                                        None).unwrap();
+        assert_eq!(line, "m\\,x,a=2,b\\ k=v\\=1 s=\"say \\\"hi\\\" \\\\o/\",u=3u,f=2,n\\=1=-1i,\
+                          t=false");
+        assert_eq!(metrics::influx_line("m", &[], &[("f", Field::from(1))], Some(-5)).unwrap(),
+                   "m f=1i -5");
+        assert_eq!(metrics::influx_line("m", &[], &[], None), Err(MetricsError::NoFields));
+        assert_eq!(metrics::influx_line("", &[], &[("f", Field::from(1))], None),
+                   Err(MetricsError::EmptyName));
+        assert_eq!(metrics::influx_line("m", &[], &[("f", Field::from(f64::INFINITY))], None),
+                   Err(MetricsError::NonFinite("f".to_string())));
+        assert_eq!(metrics::influx_line("m", &[], &[("f", Field::from("a\nb"))], None),
+                   Err(MetricsError::Newline("a\nb".to_string())));
+    }
+}
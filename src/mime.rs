@@ -0,0 +1,192 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Mapping between MIME types and the formats of the `convert` module
+//!
+//! `format_for` picks the format for a `Content-Type` header, and
+//! `negotiate` the best format for an `Accept` header, so HTTP services can
+//! choose a codec in one call.
+//!
+//! JSON (`application/json` and `+json` types such as
+//! `application/ld+json`), CBOR (`application/cbor` and `+cbor` types),
+//! MessagePack (`application/msgpack`) and raw bytes
+//! (`application/octet-stream`) have MIME types of their own. Hex and
+//! base64 are plain text, written as `text/plain`, which is too general to
+//! be mapped back.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::convert::Format;
+//! use rustc_serialize::mime;
+//!
+//! fn main() {
+//!     assert_eq!(mime::format_for("application/json; charset=utf-8"), Some(Format::Json));
+//!     let accept = "text/html, application/octet-stream;q=0.5, */*;q=0.1";
+//!     assert_eq!(mime::negotiate(accept), Some(Format::Raw));
+//!     assert_eq!(mime::content_type(Format::Raw), "application/octet-stream");
+//! }
+//! ```
+
+use convert::Format;
+
+// The formats `negotiate` can choose, most preferred first, with their MIME
+// types.
+static NEGOTIABLE: &[(Format, &str, &str)] = &[
+    (Format::Json, "application", "json"),
+    (Format::Raw, "application", "octet-stream"),
+    (Format::Cbor, "application", "cbor"),
+    (Format::MsgPack, "application", "msgpack"),
+];
+
+/// Returns the format of content with the MIME type `mime`, e.g. the value
+/// of a `Content-Type` header. Parameters and case are ignored.
+pub fn format_for(mime: &str) -> Option<Format> {
+    let (ty, subty) = match split_type(mime) {
+        Some(split) => split,
+        None => return None,
+    };
+    if ty != "application" {
+        return None;
+    }
+    if subty == "json" || subty.ends_with("+json") {
+        Some(Format::Json)
+    } else if subty == "cbor" || subty.ends_with("+cbor") {
+        Some(Format::Cbor)
+    } else if subty == "msgpack" {
+        Some(Format::MsgPack)
+    } else if subty == "octet-stream" {
+        Some(Format::Raw)
+    } else {
+        None
+    }
+}
+
+/// Returns the MIME type to send content of the given format with.
+pub fn content_type(format: Format) -> &'static str {
+    match format {
+        Format::Raw => "application/octet-stream",
+        Format::Hex | Format::Base64 => "text/plain",
+        Format::Json => "application/json",
+        Format::Cbor => "application/cbor",
+        Format::MsgPack => "application/msgpack",
+    }
+}
+
+/// Returns the format best matching `accept`, the value of an `Accept`
+/// header, or None if none is acceptable.
+///
+/// Each format gets the quality (`q` parameter) of the most specific media
+/// range matching its MIME type; the highest quality wins, with JSON, then
+/// raw bytes, then CBOR preferred on ties. Hex and base64 are never chosen.
+pub fn negotiate(accept: &str) -> Option<Format> {
+    let mut best = None;
+    let mut best_q = 0.0;
+    for &(format, ty, subty) in NEGOTIABLE {
+        let q = quality(accept, ty, subty);
+        if q > best_q {
+            best = Some(format);
+            best_q = q;
+        }
+    }
+    best
+}
+
+// The quality `accept` gives to `ty/subty`.
+fn quality(accept: &str, ty: &str, subty: &str) -> f64 {
+    // (specificity, quality) of the best match so far
+    let mut best = (0, 0.0);
+    for range in accept.split(',') {
+        let (rty, rsubty) = match split_type(range) {
+            Some(t) => t,
+            None => continue,
+        };
+        let specificity = if rty == ty && rsubty == subty {
+            3
+        } else if rty == ty && rsubty == "*" {
+            2
+        } else if rty == "*" && rsubty == "*" {
+            1
+        } else {
+            continue;
+        };
+        if specificity > best.0 {
+            best = (specificity, range_quality(range));
+        }
+    }
+    best.1
+}
+
+// The `q` parameter of a media range, 1 if missing or invalid.
+fn range_quality(range: &str) -> f64 {
+    for param in range.split(';').skip(1) {
+        let mut kv = param.splitn(2, '=');
+        let key = kv.next().unwrap().trim();
+        if key.eq_ignore_ascii_case("q") {
+            return match kv.next().and_then(|v| v.trim().parse::<f64>().ok()) {
+                Some(q) if (0.0..=1.0).contains(&q) => q,
+                _ => 1.0,
+            };
+        }
+    }
+    1.0
+}
+
+// Splits the `type/subtype` at the start of `mime` into its lowercased
+// parts, ignoring any parameters.
+fn split_type(mime: &str) -> Option<(String, String)> {
+    let essence = mime.split(';').next().unwrap().trim();
+    let mut parts = essence.splitn(2, '/');
+    match (parts.next(), parts.next()) {
+        (Some(ty), Some(subty)) if !ty.trim().is_empty() && !subty.trim().is_empty() => {
+            Some((ty.trim().to_ascii_lowercase(), subty.trim().to_ascii_lowercase()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use convert::Format;
+    use mime::{content_type, format_for, negotiate};
+
+    #[test]
+    fn test_format_for() {
+        assert_eq!(format_for("application/json"), Some(Format::Json));
+        assert_eq!(format_for(" Application/JSON ; charset=UTF-8"), Some(Format::Json));
+        assert_eq!(format_for("application/problem+json"), Some(Format::Json));
+        assert_eq!(format_for("application/octet-stream"), Some(Format::Raw));
+        assert_eq!(format_for("application/msgpack"), Some(Format::MsgPack));
+        assert_eq!(format_for("application/cose+cbor"), Some(Format::Cbor));
+        assert_eq!(format_for("application/x-protobuf"), None);
+        assert_eq!(format_for("text/json"), None);
+        assert_eq!(format_for("json"), None);
+        for &format in &[Format::Raw, Format::Json, Format::Cbor, Format::MsgPack] {
+            assert_eq!(format_for(content_type(format)), Some(format));
+        }
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("application/json"), Some(Format::Json));
+        assert_eq!(negotiate("*/*"), Some(Format::Json));
+        assert_eq!(negotiate("application/*;q=0.2, application/octet-stream"), Some(Format::Raw));
+        assert_eq!(negotiate("application/json;q=0, */*"), Some(Format::Raw));
+        assert_eq!(negotiate("application/octet-stream;q=0.3, application/json;q=0.4"),
+                   Some(Format::Json));
+        assert_eq!(negotiate("application/json; q=bad"), Some(Format::Json));
+        assert_eq!(negotiate("application/msgpack, application/cbor;q=0.9"),
+                   Some(Format::MsgPack));
+        assert_eq!(negotiate("application/cbor, application/msgpack"), Some(Format::Cbor));
+        assert_eq!(negotiate("text/html, text/*"), None);
+        assert_eq!(negotiate(""), None);
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reading files through memory maps
+//!
+//! An `Mmap` maps a whole file into memory, read only, and derefs to its
+//! bytes, so that a large file can be parsed where it lies in the page
+//! cache rather than copied into a buffer first; a file read again soon
+//! after, by the same or another process, costs little more than the
+//! parsing. `json::from_mmap` and `json::Decoder::from_mmap` decode JSON
+//! files this way.
+//!
+//! On platforms other than Unix, and for empty files, which cannot be
+//! mapped, the file is read into a buffer instead.
+//!
+//! A mapped file which is written to, or truncated, while it is mapped
+//! changes under the `Mmap`, and reading past a truncated end may crash the
+//! process, so only files which do not change while in use, such as
+//! reference data, should be mapped. That cannot be checked, so mapping a
+//! file is `unsafe`.
+//!
+//! This module is available with the `mmap` feature.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use std::fs::File;
+//! use std::io::Write;
+//! use rustc_serialize::mmap::Mmap;
+//!
+//! fn main() {
+//!     let path = std::env::temp_dir().join("rustc_serialize_mmap_doc.txt");
+//!     File::create(&path).unwrap().write_all(b"mapped").unwrap();
+//!     // Nothing else writes to the file while it is mapped.
+//!     let map = unsafe { Mmap::open(&path).unwrap() };
+//!     assert_eq!(&map[..], b"mapped");
+//! }
+//! ```
+
+use std::fs::File;
+use std::io;
+#[cfg(not(unix))]
+use std::io::Read;
+use std::ops::Deref;
+use std::path::Path;
+#[cfg(unix)]
+use std::{ptr, slice};
+
+#[cfg(unix)]
+use libc;
+
+/// A file mapped into memory, read only; see the module documentation.
+pub struct Mmap {
+    inner: Inner,
+}
+
+enum Inner {
+    // The address and length of the mapping.
+    #[cfg(unix)]
+    Mapped(*mut libc::c_void, usize),
+    Read(Vec<u8>),
+}
+
+// The mapping is never written through, so sharing it is as safe as
+// sharing a `Vec<u8>`.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Maps the file at `path`.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be written to or truncated, by this or any other
+    /// process, while the `Mmap` is alive; otherwise the bytes it derefs to
+    /// change under it, and reading them is undefined behavior.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<Mmap> {
+        let file = try!(File::open(path));
+        let len = try!(file.metadata()).len();
+        if len == 0 {
+            return Ok(Mmap { inner: Inner::Read(Vec::new()) });
+        }
+        Mmap::map(file, len)
+    }
+
+    #[cfg(unix)]
+    fn map(file: File, len: u64) -> io::Result<Mmap> {
+        use std::os::unix::io::AsRawFd;
+
+        if len > usize::MAX as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "file too large to map"));
+        }
+        let len = len as usize;
+        let addr = unsafe {
+            libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE,
+                       file.as_raw_fd(), 0)
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mmap { inner: Inner::Mapped(addr, len) })
+    }
+
+    #[cfg(not(unix))]
+    fn map(mut file: File, len: u64) -> io::Result<Mmap> {
+        let mut buf = Vec::with_capacity(len as usize);
+        try!(file.read_to_end(&mut buf));
+        Ok(Mmap { inner: Inner::Read(buf) })
+    }
+
+    /// Whether the file is mapped, rather than read into a buffer.
+    pub fn is_mapped(&self) -> bool {
+        match self.inner {
+            #[cfg(unix)]
+            Inner::Mapped(..) => true,
+            Inner::Read(_) => false,
+        }
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self.inner {
+            #[cfg(unix)]
+            Inner::Mapped(addr, len) => unsafe { slice::from_raw_parts(addr as *const u8, len) },
+            Inner::Read(ref buf) => buf,
+        }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            if let Inner::Mapped(addr, len) = self.inner {
+                unsafe {
+                    libc::munmap(addr, len);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use super::Mmap;
+
+    #[test]
+    fn test_mmap() {
+        let dir = env::temp_dir();
+        let path = dir.join("rustc_serialize_test_mmap.bin");
+        let bytes: Vec<u8> = (0..10000).map(|i| (i % 251) as u8).collect();
+        File::create(&path).unwrap().write_all(&bytes).unwrap();
+        let map = unsafe { Mmap::open(&path).unwrap() };
+        assert_eq!(&map[..], &bytes[..]);
+        assert_eq!(map.is_mapped(), cfg!(unix));
+        drop(map);
+
+        File::create(&path).unwrap();
+        let map = unsafe { Mmap::open(&path).unwrap() };
+        assert!(map.is_empty() && !map.is_mapped());
+        fs::remove_file(&path).unwrap();
+
+        assert!(unsafe { Mmap::open(dir.join("rustc_serialize_test_mmap.missing")) }.is_err());
+    }
+}
@@ -0,0 +1,634 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `multipart/form-data` bodies
+//!
+//! `MultipartWriter` writes a body part by part, with a boundary of its
+//! own choosing or one given, streaming the contents of file parts from
+//! any `Read`. `MultipartReader` reads a body part by part, without
+//! holding more than a buffer of it: `next_part` returns each part, with
+//! its header fields, as a `Read` of its contents. The preamble before the
+//! first part and the epilogue after the last are ignored.
+//!
+//! As HTML form submission does, `"`, CR and LF in the field names and
+//! file names written are percent-encoded. Those read are left as they
+//! are.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use std::io::Read;
+//! use rustc_serialize::multipart::{MultipartReader, MultipartWriter};
+//!
+//! fn main() {
+//!     let mut writer = MultipartWriter::with_boundary(Vec::new(), "XyZ").unwrap();
+//!     writer.add_field("title", "notes").unwrap();
+//!     writer.add_file("doc", "a.txt", "text/plain", &mut &b"hello"[..]).unwrap();
+//!     assert_eq!(writer.content_type(), "multipart/form-data; boundary=XyZ");
+//!     let body = writer.finish().unwrap();
+//!
+//!     let mut reader = MultipartReader::new(&body[..], "XyZ").unwrap();
+//!     let mut parts = Vec::new();
+//!     while let Some(mut part) = reader.next_part().unwrap() {
+//!         let mut contents = String::new();
+//!         part.read_to_string(&mut contents).unwrap();
+//!         parts.push((part.name().unwrap(), part.filename(), contents));
+//!     }
+//!     assert_eq!(parts, [("title".to_string(), None, "notes".to_string()),
+//!                        ("doc".to_string(), Some("a.txt".to_string()), "hello".to_string())]);
+//! }
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::error;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::io::prelude::*;
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The longest header line accepted, in bytes, excluding the line ending.
+pub const MAX_LINE: usize = 4096;
+
+/// The most header fields accepted for a part.
+pub const MAX_HEADERS: usize = 64;
+
+/// Errors from reading or writing a multipart body. They are reported as
+/// `io::Error`s wrapping a `MultipartError`, of kind `InvalidData` when
+/// reading and `InvalidInput` when writing, except that a truncated body is
+/// an `UnexpectedEof` error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MultipartError {
+    /// A boundary was empty, longer than 70 characters, ended with a space,
+    /// or held a character boundaries cannot
+    InvalidBoundary,
+    /// A header field was malformed, or would be written malformed
+    InvalidHeader,
+    /// A line was longer than `MAX_LINE`
+    LineTooLong,
+    /// A part had more than `MAX_HEADERS` header fields
+    TooManyHeaders,
+    /// A boundary delimiter was followed by something other than a line
+    /// ending or `--`
+    InvalidDelimiter,
+}
+
+impl MultipartError {
+    /// Returns the `MultipartError` wrapped by `err`, if any.
+    pub fn from_io_error(err: &io::Error) -> Option<MultipartError> {
+        err.get_ref().and_then(|e| e.downcast_ref::<MultipartError>()).cloned()
+    }
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MultipartError::InvalidBoundary => write!(f, "invalid boundary"),
+            MultipartError::InvalidHeader => write!(f, "invalid header field"),
+            MultipartError::LineTooLong => write!(f, "line longer than {} bytes", MAX_LINE),
+            MultipartError::TooManyHeaders => {
+                write!(f, "more than {} header fields", MAX_HEADERS)
+            }
+            MultipartError::InvalidDelimiter => write!(f, "invalid boundary delimiter"),
+        }
+    }
+}
+
+impl error::Error for MultipartError {
+    fn description(&self) -> &str { "invalid multipart body" }
+}
+
+fn invalid_data(err: MultipartError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn invalid_input(err: MultipartError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated multipart body")
+}
+
+// Whether `boundary` is a valid boundary (RFC 2046, section 5.1.1).
+fn valid_boundary(boundary: &str) -> bool {
+    !boundary.is_empty() && boundary.len() <= 70 && !boundary.ends_with(' ') &&
+    boundary.bytes().all(|b| match b {
+        b'\'' | b'(' | b')' | b'+' | b'_' | b',' | b'-' | b'.' | b'/' | b':' | b'=' | b'?' |
+        b' ' => true,
+        b => (b as char).is_alphanumeric() && b < 0x80,
+    })
+}
+
+// Whether `name` is a valid field name: one or more token characters.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| match b {
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' | b'.' |
+        b'^' | b'_' | b'`' | b'|' | b'~' => true,
+        b => (b as char).is_alphanumeric() && b < 0x80,
+    })
+}
+
+/// Returns a new boundary, unlikely to appear in any part.
+pub fn generate_boundary() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos())
+                                 .unwrap_or(0);
+    let mut boundary = "rustc-serialize-".to_string();
+    for _ in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(count);
+        hasher.write_u32(nanos);
+        boundary.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    boundary
+}
+
+/// A writer of a `multipart/form-data` body to `W`.
+///
+/// Call `finish` at the end to write the closing delimiter.
+pub struct MultipartWriter<W: Write> {
+    inner: W,
+    boundary: String,
+}
+
+impl<W: Write> MultipartWriter<W> {
+    /// Creates a writer of a body to `inner`, with a boundary from
+    /// `generate_boundary`.
+    pub fn new(inner: W) -> MultipartWriter<W> {
+        MultipartWriter { inner: inner, boundary: generate_boundary() }
+    }
+
+    /// Creates a writer of a body to `inner`, with the boundary `boundary`.
+    ///
+    /// Fails with an `InvalidInput` error wrapping
+    /// `MultipartError::InvalidBoundary` if it is not a valid boundary.
+    pub fn with_boundary(inner: W, boundary: &str) -> io::Result<MultipartWriter<W>> {
+        if !valid_boundary(boundary) {
+            return Err(invalid_input(MultipartError::InvalidBoundary));
+        }
+        Ok(MultipartWriter { inner: inner, boundary: boundary.to_string() })
+    }
+
+    /// Returns the boundary of the body.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Returns the value of the `Content-Type` header for the body.
+    pub fn content_type(&self) -> String {
+        if self.boundary.contains(&[' ', ':', '=', '?', '(', ')', ',', '/'][..]) {
+            format!("multipart/form-data; boundary=\"{}\"", self.boundary)
+        } else {
+            format!("multipart/form-data; boundary={}", self.boundary)
+        }
+    }
+
+    /// Writes a part holding the form field `name` with the value `value`.
+    pub fn add_field(&mut self, name: &str, value: &str) -> io::Result<()> {
+        let disposition = format!("form-data; name=\"{}\"", escape(name));
+        self.add_part(&[("Content-Disposition", &disposition)], &mut value.as_bytes())
+    }
+
+    /// Writes a part holding the file `filename` of the type
+    /// `content_type`, for the form field `name`, with the contents read
+    /// from `reader` to its end.
+    pub fn add_file<R: Read + ?Sized>(&mut self, name: &str, filename: &str, content_type: &str,
+                                      reader: &mut R) -> io::Result<()> {
+        let disposition = format!("form-data; name=\"{}\"; filename=\"{}\"",
+                                  escape(name), escape(filename));
+        self.add_part(&[("Content-Disposition", &disposition), ("Content-Type", content_type)],
+                      reader)
+    }
+
+    /// Writes a part with the header fields `headers`, given as `(name,
+    /// value)` pairs, and the contents read from `reader` to its end.
+    ///
+    /// Fails with an `InvalidInput` error wrapping
+    /// `MultipartError::InvalidHeader`, writing nothing, if a name is not a
+    /// valid field name or a value contains a line break.
+    pub fn add_part<R: Read + ?Sized>(&mut self, headers: &[(&str, &str)], reader: &mut R)
+                                      -> io::Result<()> {
+        let mut head = format!("--{}\r\n", self.boundary);
+        for &(name, value) in headers {
+            if !valid_name(name) || value.contains(&['\r', '\n'][..]) {
+                return Err(invalid_input(MultipartError::InvalidHeader));
+            }
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        try!(self.inner.write_all(head.as_bytes()));
+        try!(io::copy(reader, &mut self.inner));
+        self.inner.write_all(b"\r\n")
+    }
+
+    /// Writes the closing delimiter and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        try!(write!(self.inner, "--{}--\r\n", self.boundary));
+        try!(self.inner.flush());
+        Ok(self.inner)
+    }
+}
+
+// Percent-encodes `"`, CR and LF in a quoted parameter value.
+fn escape(s: &str) -> String {
+    s.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    // Before the first delimiter.
+    Preamble,
+    // Just after a delimiter.
+    Delimiter,
+    // Within the contents of a part.
+    Body,
+    // After the closing delimiter.
+    Done,
+}
+
+/// A reader of the parts of a `multipart/form-data` body read from `R`.
+///
+/// Delimiters must end with CRLF; header lines may end with CRLF or LF.
+pub struct MultipartReader<R: Read> {
+    inner: R,
+    // CRLF, `--` and the boundary.
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    pos: usize,
+    eof: bool,
+    state: State,
+    headers: Vec<(String, String)>,
+}
+
+impl<R: Read> MultipartReader<R> {
+    /// Creates a reader of the parts of the body read from `inner` with
+    /// the boundary `boundary`, as from its `Content-Type`.
+    ///
+    /// Fails with an `InvalidInput` error wrapping
+    /// `MultipartError::InvalidBoundary` if it is not a valid boundary.
+    pub fn new(inner: R, boundary: &str) -> io::Result<MultipartReader<R>> {
+        if !valid_boundary(boundary) {
+            return Err(invalid_input(MultipartError::InvalidBoundary));
+        }
+        Ok(MultipartReader {
+            inner: inner,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            // The first delimiter need not follow a line ending.
+            buf: b"\r\n".to_vec(),
+            pos: 0,
+            eof: false,
+            state: State::Preamble,
+            headers: Vec::new(),
+        })
+    }
+
+    /// Returns the next part of the body, or `None` after the last one.
+    /// The rest of the part before, if any, is skipped.
+    pub fn next_part(&mut self) -> io::Result<Option<Part<'_, R>>> {
+        let mut skip = [0; 4096];
+        while self.state == State::Preamble || self.state == State::Body {
+            while try!(self.read_body(&mut skip)) > 0 {}
+        }
+        if self.state == State::Done {
+            return Ok(None);
+        }
+        try!(self.fill(2));
+        if self.buf[self.pos..].starts_with(b"--") {
+            self.state = State::Done;
+            return Ok(None);
+        }
+        // Transport padding, then the line ending.
+        let line = try!(self.read_line());
+        if line.iter().any(|&b| b != b' ' && b != b'\t') {
+            return Err(invalid_data(MultipartError::InvalidDelimiter));
+        }
+        self.headers.clear();
+        loop {
+            let line = try!(self.read_line());
+            if line.is_empty() {
+                break;
+            }
+            if self.headers.len() == MAX_HEADERS {
+                return Err(invalid_data(MultipartError::TooManyHeaders));
+            }
+            let field = try!(str::from_utf8(&line)
+                                 .map_err(|_| invalid_data(MultipartError::InvalidHeader)));
+            match field.find(':') {
+                Some(i) if valid_name(&field[..i]) => {
+                    self.headers.push((field[..i].to_string(), field[i + 1..].trim().to_string()))
+                }
+                _ => return Err(invalid_data(MultipartError::InvalidHeader)),
+            }
+        }
+        self.state = State::Body;
+        Ok(Some(Part { reader: self }))
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // Reads until at least `n` bytes are buffered, or the end of the
+    // stream.
+    fn fill(&mut self, n: usize) -> io::Result<()> {
+        while self.buf.len() - self.pos < n && !self.eof {
+            if self.pos > 0 {
+                self.buf.drain(..self.pos);
+                self.pos = 0;
+            }
+            let len = self.buf.len();
+            self.buf.resize(len + 8192, 0);
+            let result = self.inner.read(&mut self.buf[len..]);
+            let read = match result {
+                Ok(read) => read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => 0,
+                Err(e) => {
+                    self.buf.truncate(len);
+                    return Err(e);
+                }
+            };
+            self.buf.truncate(len + read);
+            self.eof = read == 0 && result.is_ok();
+        }
+        Ok(())
+    }
+
+    // Reads a line, without its line ending.
+    fn read_line(&mut self) -> io::Result<Vec<u8>> {
+        let mut searched = 0;
+        loop {
+            let end = self.buf[self.pos + searched..].iter().position(|&b| b == b'\n');
+            if let Some(i) = end {
+                let end = self.pos + searched + i;
+                let mut line = self.buf[self.pos..end].to_vec();
+                self.pos = end + 1;
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                if line.len() > MAX_LINE {
+                    return Err(invalid_data(MultipartError::LineTooLong));
+                }
+                return Ok(line);
+            }
+            searched = self.buf.len() - self.pos;
+            if searched > MAX_LINE + 1 {
+                return Err(invalid_data(MultipartError::LineTooLong));
+            }
+            if self.eof {
+                return Err(truncated());
+            }
+            try!(self.fill(searched + 1));
+        }
+    }
+
+    // Reads contents up to the next delimiter, returning 0 once it is
+    // reached.
+    fn read_body(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        let n = self.delimiter.len();
+        try!(self.fill(n + 1));
+        let available = &self.buf[self.pos..];
+        let found = available.windows(n).position(|w| w == &self.delimiter[..]);
+        let len = match found {
+            // The boundary followed by other characters is not a delimiter,
+            // but contents.
+            Some(0) => match available.get(n) {
+                Some(&b'-') | Some(&b' ') | Some(&b'\t') | Some(&b'\r') | Some(&b'\n') => {
+                    self.pos += n;
+                    self.state = State::Delimiter;
+                    return Ok(0);
+                }
+                Some(_) => n,
+                None => return Err(truncated()),
+            },
+            Some(i) => i,
+            None if available.len() < n => return Err(truncated()),
+            // The last bytes may start a delimiter.
+            None => available.len() - (n - 1),
+        };
+        let len = len.min(out.len());
+        out[..len].copy_from_slice(&available[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// A part of a multipart body, reading its contents.
+pub struct Part<'a, R: Read + 'a> {
+    reader: &'a mut MultipartReader<R>,
+}
+
+impl<'a, R: Read> Part<'a, R> {
+    /// Returns the header fields of the part as `(name, value)` pairs, in
+    /// the order read.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.reader.headers
+    }
+
+    /// Returns the value of the first header field named `name`, ignoring
+    /// case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.reader.headers.iter().find(|h| h.0.eq_ignore_ascii_case(name)).map(|h| &h.1[..])
+    }
+
+    /// Returns the form field name of the part, from its
+    /// `Content-Disposition`.
+    pub fn name(&self) -> Option<String> {
+        self.header("Content-Disposition").and_then(|d| parameter(d, "name"))
+    }
+
+    /// Returns the file name of the part, from its `Content-Disposition`.
+    pub fn filename(&self) -> Option<String> {
+        self.header("Content-Disposition").and_then(|d| parameter(d, "filename"))
+    }
+
+    /// Returns the `Content-Type` of the part, if it has one.
+    pub fn content_type(&self) -> Option<&str> {
+        self.header("Content-Type")
+    }
+}
+
+impl<'a, R: Read> Read for Part<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.reader.state != State::Body {
+            return Ok(0);
+        }
+        self.reader.read_body(buf)
+    }
+}
+
+// The value of the parameter `name` of a header value such as
+// `form-data; name="a"`, unquoted.
+fn parameter(value: &str, name: &str) -> Option<String> {
+    let mut rest = value;
+    while let Some(i) = rest.find(';') {
+        rest = rest[i + 1..].trim_start();
+        let eq = match rest.find('=') {
+            Some(eq) => eq,
+            None => return None,
+        };
+        let key = rest[..eq].trim();
+        rest = rest[eq + 1..].trim_start();
+        let value = if rest.starts_with('"') {
+            let mut value = String::new();
+            let mut chars = rest[1..].char_indices();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => value.extend(chars.next().map(|(_, c)| c)),
+                    Some((j, '"')) => {
+                        rest = &rest[j + 2..];
+                        break;
+                    }
+                    Some((_, c)) => value.push(c),
+                    None => {
+                        rest = "";
+                        break;
+                    }
+                }
+            }
+            value
+        } else {
+            let end = rest.find(';').unwrap_or(rest.len());
+            let value = rest[..end].trim().to_string();
+            rest = &rest[end..];
+            value
+        };
+        if key.eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read};
+    use multipart::{self, MultipartError, MultipartReader, MultipartWriter};
+
+    type Headers = Vec<(String, String)>;
+
+    fn read_all(body: &[u8], boundary: &str) -> io::Result<Vec<(Headers, Vec<u8>)>> {
+        let mut reader = try!(MultipartReader::new(body, boundary));
+        let mut parts = Vec::new();
+        while let Some(mut part) = try!(reader.next_part()) {
+            let mut contents = Vec::new();
+            try!(part.read_to_end(&mut contents));
+            parts.push((part.headers().to_vec(), contents));
+        }
+        Ok(parts)
+    }
+
+    #[test]
+    fn test_write() {
+        let mut writer = MultipartWriter::new(Vec::new());
+        writer.add_field("a\"b\r\n", "").unwrap();
+        let file: Vec<u8> = (0..20000).map(|i| (i % 251) as u8).collect();
+        writer.add_file("f", "x.bin", "application/octet-stream", &mut &file[..]).unwrap();
+        let err = writer.add_part(&[("Bad Name", "v")], &mut io::empty()).unwrap_err();
+        assert_eq!(MultipartError::from_io_error(&err), Some(MultipartError::InvalidHeader));
+        let boundary = writer.boundary().to_string();
+        assert!(boundary.starts_with("rustc-serialize-") && boundary.len() == 48);
+        assert!(boundary != multipart::generate_boundary());
+        let body = writer.finish().unwrap();
+        assert!(body.starts_with(format!("--{}\r\nContent-Disposition: form-data; \
+                                          name=\"a%22b%0D%0A\"\r\n\r\n\r\n--", boundary)
+                                     .as_bytes()));
+        assert!(body.ends_with(format!("\r\n--{}--\r\n", boundary).as_bytes()));
+
+        let parts = read_all(&body, &boundary).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[1].1, file);
+        assert_eq!(parts[1].0[1], ("Content-Type".to_string(),
+                                   "application/octet-stream".to_string()));
+
+        assert_eq!(MultipartWriter::with_boundary(Vec::new(), "a b").unwrap().content_type(),
+                   "multipart/form-data; boundary=\"a b\"");
+        for boundary in ["", "a ", "a\"", &"x".repeat(71)].iter() {
+            let err = MultipartWriter::with_boundary(Vec::new(), boundary).err().unwrap();
+            assert_eq!(MultipartError::from_io_error(&err), Some(MultipartError::InvalidBoundary));
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        let body = b"preamble\r\n--b  \r\n\
+                     content-disposition: form-data; filename=\"q\\\"\"; Name=n\n\
+                     X-Empty:\r\n\r\n--b\r\n\r\n\r\n--b\r\n\r\n\r\n--b--\r\nignored epilogue";
+        let parts = read_all(body, "b").unwrap();
+        assert_eq!(parts[0].0, [("content-disposition".to_string(),
+                                 "form-data; filename=\"q\\\"\"; Name=n".to_string()),
+                                ("X-Empty".to_string(), String::new())]);
+        assert_eq!(parts[0].1, b"--b\r\n\r\n");
+        assert_eq!(parts[1], (vec![], vec![]));
+        assert_eq!(parts.len(), 2);
+        assert_eq!(read_all(b"--bad\r\n\r\n\r\n--b--", "b").unwrap(), vec![]);
+
+        let body = b"--b\r\nContent-Disposition: form-data; filename=\"q\\\"\"; Name=n\r\n\r\n\
+                     one\r\n--bx\r\n--b \t\r\n\r\ntwo\r\n--b--";
+        let mut reader = MultipartReader::new(&body[..], "b").unwrap();
+        {
+            let part = reader.next_part().unwrap().unwrap();
+            assert_eq!((part.name(), part.filename()), (Some("n".to_string()),
+                                                        Some("q\"".to_string())));
+            assert_eq!(part.content_type(), None);
+        }
+        let mut contents = String::new();
+        reader.next_part().unwrap().unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "two");
+        assert!(reader.next_part().unwrap().is_none());
+        assert!(reader.next_part().unwrap().is_none());
+
+        // Reads of one byte each, so that delimiters are split across them.
+        struct Bytes<'a>(&'a [u8]);
+        impl<'a> Read for Bytes<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.0.is_empty() || buf.is_empty() {
+                    return Ok(0);
+                }
+                buf[0] = self.0[0];
+                self.0 = &self.0[1..];
+                Ok(1)
+            }
+        }
+        let mut reader = MultipartReader::new(Bytes(&body[..]), "b").unwrap();
+        let mut contents = Vec::new();
+        while let Some(mut part) = reader.next_part().unwrap() {
+            part.read_to_end(&mut contents).unwrap();
+        }
+        assert_eq!(contents, b"one\r\n--bxtwo");
+
+        for &(body, kind, err) in &[(&b"--b\r\nA: 1\r\n\r\nshort"[..], io::ErrorKind::UnexpectedEof,
+                                     None),
+                                    (b"--b\r\nno colon\r\n\r\n\r\n--b--",
+                                     io::ErrorKind::InvalidData,
+                                     Some(MultipartError::InvalidHeader)),
+                                    (b"--b\r\n\r\n\r\n--b", io::ErrorKind::UnexpectedEof,
+                                     None),
+                                    (b"--b x\r\n\r\n\r\n--b--", io::ErrorKind::InvalidData,
+                                     Some(MultipartError::InvalidDelimiter))] {
+            let e = read_all(body, "b").unwrap_err();
+            assert_eq!((e.kind(), MultipartError::from_io_error(&e)), (kind, err));
+        }
+        let long = format!("--b\r\nA: {}\r\n\r\n\r\n--b--", "x".repeat(5000));
+        let e = read_all(long.as_bytes(), "b").unwrap_err();
+        assert_eq!(MultipartError::from_io_error(&e), Some(MultipartError::LineTooLong));
+        let many = format!("--b\r\n{}\r\n\r\n--b--", "A: 1\r\n".repeat(65));
+        let e = read_all(many.as_bytes(), "b").unwrap_err();
+        assert_eq!(MultipartError::from_io_error(&e), Some(MultipartError::TooManyHeaders));
+    }
+}
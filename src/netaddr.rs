@@ -0,0 +1,449 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Network addresses as text
+//!
+//! `MacAddr` is a 48-bit MAC address, read with its bytes separated by
+//! colons or hyphens (`00:1a:2b:3c:4d:5e`, `00-1A-2B-3C-4D-5E`, allowing
+//! single digits as in `0:1a:2b:3c:4d:5e`), in three dotted groups
+//! (`001a.2b3c.4d5e`), or as twelve bare digits. `ZonedAddr` is an IP
+//! address with an optional IPv6 zone, as in `fe80::1%eth0` (RFC 4007),
+//! also read in brackets with the zone escaped (`[fe80::1%25eth0]`, RFC
+//! 6874). `Cidr` is an address and a prefix length, as in `10.0.0.0/8` or
+//! `2001:db8::/32`; for IPv4 the length may be given as a netmask.
+//!
+//! Each is encoded as a string of its `Display` form, which is the first
+//! form given above, and decoded with `FromStr`, like the address types of
+//! `std::net`.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json;
+//! use rustc_serialize::netaddr::{Cidr, MacAddr, MacFormat};
+//!
+//! fn main() {
+//!     let mac: MacAddr = "001A.2B3C.4D5E".parse().unwrap();
+//!     assert_eq!(mac.to_string(), "00:1a:2b:3c:4d:5e");
+//!     assert_eq!(mac.format(MacFormat::Hyphen), "00-1a-2b-3c-4d-5e");
+//!
+//!     let net: Cidr = json::decode("\"192.168.1.20/255.255.255.0\"").unwrap();
+//!     assert_eq!(net.to_string(), "192.168.1.20/24");
+//!     assert_eq!(net.network().to_string(), "192.168.1.0/24");
+//!     assert!(net.contains(&"192.168.1.200".parse().unwrap()));
+//!     assert_eq!(json::encode(&net.network()).unwrap(), "\"192.168.1.0/24\"");
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use {Decodable, Decoder, Encodable, Encoder};
+
+/// Errors from parsing addresses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetAddrError {
+    /// The text was not a MAC address in one of the forms read
+    InvalidMac,
+    /// The text was not an IPv4 or IPv6 address
+    InvalidIp,
+    /// A zone was empty or held `%`, `/` or whitespace, or followed an
+    /// IPv4 address
+    InvalidZone,
+    /// A CIDR block had no `/` and prefix length
+    MissingPrefix,
+    /// A prefix length was not a decimal number up to the length of the
+    /// address, or a netmask was not contiguous
+    InvalidPrefix,
+}
+
+impl fmt::Display for NetAddrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NetAddrError::InvalidMac => write!(f, "invalid MAC address"),
+            NetAddrError::InvalidIp => write!(f, "invalid IP address"),
+            NetAddrError::InvalidZone => write!(f, "invalid zone"),
+            NetAddrError::MissingPrefix => write!(f, "missing prefix length"),
+            NetAddrError::InvalidPrefix => write!(f, "invalid prefix length"),
+        }
+    }
+}
+
+impl error::Error for NetAddrError {
+    fn description(&self) -> &str { "invalid network address" }
+}
+
+/// A 48-bit MAC address
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MacAddr(pub [u8; 6]);
+
+/// The forms a `MacAddr` is written in, all with lowercase digits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MacFormat {
+    /// `00:1a:2b:3c:4d:5e`
+    Colon,
+    /// `00-1a-2b-3c-4d-5e`
+    Hyphen,
+    /// `001a.2b3c.4d5e`
+    Dot,
+    /// `001a2b3c4d5e`
+    Bare,
+}
+
+impl MacAddr {
+    /// Returns the bytes of the address.
+    pub fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+
+    /// Returns whether the address is a group address, with the least
+    /// significant bit of its first byte set.
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 1 != 0
+    }
+
+    /// Returns whether the address is locally administered rather than
+    /// assigned by its manufacturer.
+    pub fn is_local(&self) -> bool {
+        self.0[0] & 2 != 0
+    }
+
+    /// Writes the address in `format`.
+    pub fn format(&self, format: MacFormat) -> String {
+        let b = self.0;
+        match format {
+            MacFormat::Colon | MacFormat::Hyphen => {
+                let sep = if format == MacFormat::Colon { ":" } else { "-" };
+                let bytes: Vec<String> = b.iter().map(|b| format!("{:02x}", b)).collect();
+                bytes.join(sep)
+            }
+            MacFormat::Dot => {
+                format!("{:02x}{:02x}.{:02x}{:02x}.{:02x}{:02x}",
+                        b[0], b[1], b[2], b[3], b[4], b[5])
+            }
+            MacFormat::Bare => b.iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.format(MacFormat::Colon))
+    }
+}
+
+impl FromStr for MacAddr {
+    type Err = NetAddrError;
+
+    fn from_str(s: &str) -> Result<MacAddr, NetAddrError> {
+        let hex = |group: &str, min: usize, max: usize| {
+            if group.len() < min || group.len() > max ||
+               !group.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(NetAddrError::InvalidMac);
+            }
+            u64::from_str_radix(group, 16).map_err(|_| NetAddrError::InvalidMac)
+        };
+        let mut bytes = [0; 6];
+        let groups: Vec<&str> = s.split(&[':', '-', '.'][..]).collect();
+        match groups.len() {
+            1 => {
+                let n = try!(hex(s, 12, 12));
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = (n >> (40 - 8 * i)) as u8;
+                }
+            }
+            3 if !s.contains(':') && !s.contains('-') => {
+                for (i, group) in groups.iter().enumerate() {
+                    let n = try!(hex(group, 4, 4));
+                    bytes[2 * i] = (n >> 8) as u8;
+                    bytes[2 * i + 1] = n as u8;
+                }
+            }
+            6 if !(s.contains('.') || (s.contains(':') && s.contains('-'))) => {
+                for (i, group) in groups.iter().enumerate() {
+                    bytes[i] = try!(hex(group, 1, 2)) as u8;
+                }
+            }
+            _ => return Err(NetAddrError::InvalidMac),
+        }
+        Ok(MacAddr(bytes))
+    }
+}
+
+/// An IP address with the zone of an IPv6 link-local address, if any
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ZonedAddr {
+    /// The address
+    pub addr: IpAddr,
+    /// The zone, an interface name or index
+    pub zone: Option<String>,
+}
+
+impl ZonedAddr {
+    /// Creates an address with no zone.
+    pub fn new(addr: IpAddr) -> ZonedAddr {
+        ZonedAddr { addr: addr, zone: None }
+    }
+
+    /// Creates an IPv6 address in `zone`.
+    pub fn with_zone(addr: Ipv6Addr, zone: &str) -> Result<ZonedAddr, NetAddrError> {
+        if !valid_zone(zone) {
+            return Err(NetAddrError::InvalidZone);
+        }
+        Ok(ZonedAddr { addr: IpAddr::V6(addr), zone: Some(zone.to_string()) })
+    }
+}
+
+fn valid_zone(zone: &str) -> bool {
+    !zone.is_empty() && !zone.chars().any(|c| c == '%' || c == '/' || c.is_whitespace())
+}
+
+impl fmt::Display for ZonedAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.zone {
+            Some(ref zone) => write!(f, "{}%{}", self.addr, zone),
+            None => write!(f, "{}", self.addr),
+        }
+    }
+}
+
+impl FromStr for ZonedAddr {
+    type Err = NetAddrError;
+
+    fn from_str(s: &str) -> Result<ZonedAddr, NetAddrError> {
+        let (s, escape) = if s.starts_with('[') && s.ends_with(']') && s.len() > 1 {
+            (&s[1..s.len() - 1], "%25")
+        } else {
+            (s, "%")
+        };
+        let (addr, zone) = match s.find('%') {
+            Some(i) if s[i..].starts_with(escape) => (&s[..i], Some(&s[i + escape.len()..])),
+            Some(_) => return Err(NetAddrError::InvalidZone),
+            None => (s, None),
+        };
+        let addr = try!(addr.parse::<IpAddr>().map_err(|_| NetAddrError::InvalidIp));
+        match (addr, zone) {
+            (IpAddr::V6(addr), Some(zone)) => ZonedAddr::with_zone(addr, zone),
+            (IpAddr::V4(_), Some(_)) => Err(NetAddrError::InvalidZone),
+            (addr, None) => Ok(ZonedAddr::new(addr)),
+        }
+    }
+}
+
+/// A block of IP addresses: an address and the length of the prefix the
+/// block shares.
+///
+/// The address need not be the first of the block, so that an interface
+/// address such as `192.168.1.20/24` keeps its host bits; `network`
+/// clears them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Creates a block, failing if `prefix` is longer than the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Cidr, NetAddrError> {
+        if prefix > max_prefix(&addr) {
+            return Err(NetAddrError::InvalidPrefix);
+        }
+        Ok(Cidr { addr: addr, prefix: prefix })
+    }
+
+    /// Returns the address.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Returns the prefix length.
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Returns the netmask of the prefix, as in `255.255.255.0` for `/24`.
+    pub fn netmask(&self) -> IpAddr {
+        match self.addr {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(mask(self.prefix, 32) as u32)),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(mask(self.prefix, 128))),
+        }
+    }
+
+    /// Returns the block with the bits of the address after the prefix
+    /// cleared.
+    pub fn network(&self) -> Cidr {
+        let addr = match self.addr {
+            IpAddr::V4(a) => {
+                IpAddr::V4(Ipv4Addr::from(u32::from(a) & mask(self.prefix, 32) as u32))
+            }
+            IpAddr::V6(a) => IpAddr::V6(Ipv6Addr::from(u128::from(a) & mask(self.prefix, 128))),
+        };
+        Cidr { addr: addr, prefix: self.prefix }
+    }
+
+    /// Returns whether `addr` is in the block. IPv4 addresses are never in
+    /// IPv6 blocks, nor the reverse.
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.addr, *addr) {
+            (IpAddr::V4(a), IpAddr::V4(b)) => {
+                let m = mask(self.prefix, 32) as u32;
+                u32::from(a) & m == u32::from(b) & m
+            }
+            (IpAddr::V6(a), IpAddr::V6(b)) => {
+                let m = mask(self.prefix, 128);
+                u128::from(a) & m == u128::from(b) & m
+            }
+            _ => false,
+        }
+    }
+}
+
+fn max_prefix(addr: &IpAddr) -> u8 {
+    if addr.is_ipv4() { 32 } else { 128 }
+}
+
+// The mask of the first `prefix` bits of a `bits`-bit address.
+fn mask(prefix: u8, bits: u32) -> u128 {
+    if prefix == 0 { 0 } else { !0u128 << (128 - prefix as u32) >> (128 - bits) }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = NetAddrError;
+
+    fn from_str(s: &str) -> Result<Cidr, NetAddrError> {
+        let slash = try!(s.find('/').ok_or(NetAddrError::MissingPrefix));
+        let addr = try!(s[..slash].parse::<IpAddr>().map_err(|_| NetAddrError::InvalidIp));
+        let len = &s[slash + 1..];
+        let prefix = if len.contains('.') && addr.is_ipv4() {
+            let netmask = try!(len.parse::<Ipv4Addr>().map_err(|_| NetAddrError::InvalidPrefix));
+            let m = u32::from(netmask);
+            // The bits after the mask must be a run of ones at the end.
+            if (!m).wrapping_add(1) & !m != 0 {
+                return Err(NetAddrError::InvalidPrefix);
+            }
+            m.count_ones() as u8
+        } else {
+            if len.is_empty() || len.len() > 3 || !len.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(NetAddrError::InvalidPrefix);
+            }
+            try!(len.parse::<u8>().map_err(|_| NetAddrError::InvalidPrefix))
+        };
+        Cidr::new(addr, prefix)
+    }
+}
+
+// Written as strings like the address types of `std::net`.
+macro_rules! display_from_str {
+    ($($ty:ident, $what:expr;)*) => ($(
+        impl Encodable for $ty {
+            fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_str(&self.to_string())
+            }
+        }
+
+        impl Decodable for $ty {
+            fn decode<D: Decoder>(d: &mut D) -> Result<$ty, D::Error> {
+                let s = try!(d.read_str());
+                s.parse().map_err(|_| d.error(&format!("invalid {}: {:?}", $what, s)))
+            }
+        }
+    )*)
+}
+
+display_from_str! {
+    MacAddr, "MAC address";
+    ZonedAddr, "IP address";
+    Cidr, "CIDR block";
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use json;
+    use netaddr::{Cidr, MacAddr, MacFormat, NetAddrError, ZonedAddr};
+
+    #[test]
+    fn test_mac() {
+        let mac = MacAddr([0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]);
+        for s in &["00:1a:2b:3c:4d:5e", "00-1A-2B-3C-4D-5E", "0:1a:2b:3c:4d:5e",
+                   "001a.2b3c.4d5e", "001A2B3C4D5E"] {
+            assert_eq!(s.parse(), Ok(mac));
+        }
+        for s in &["00:1a:2b:3c:4d", "00:1a-2b:3c:4d:5e", "001:a:2b:3c:4d:5e", "001a.2b3c4d5e",
+                   "001a:2b3c:4d5e", "+1:1a:2b:3c:4d:5e", "00:1a:2b:3c:4d:5g", "001a2b3c4d5", ""] {
+            assert_eq!(s.parse::<MacAddr>(), Err(NetAddrError::InvalidMac));
+        }
+        assert_eq!(mac.format(MacFormat::Dot), "001a.2b3c.4d5e");
+        assert_eq!(mac.format(MacFormat::Bare), "001a2b3c4d5e");
+        assert!(!mac.is_multicast() && !mac.is_local());
+        assert!(MacAddr([3, 0, 0, 0, 0, 0]).is_multicast());
+        assert_eq!(json::encode(&mac).unwrap(), "\"00:1a:2b:3c:4d:5e\"");
+        assert_eq!(json::decode::<MacAddr>("\"00-1a-2b-3c-4d-5e\"").unwrap(), mac);
+        assert!(json::decode::<MacAddr>("\"00-1a\"").is_err());
+    }
+
+    #[test]
+    fn test_zoned() {
+        let addr: ZonedAddr = "fe80::1%eth0".parse().unwrap();
+        assert_eq!(addr.zone, Some("eth0".to_string()));
+        assert_eq!(addr.to_string(), "fe80::1%eth0");
+        assert_eq!("[fe80::1%25eth0]".parse(), Ok(addr.clone()));
+        assert_eq!("[::1]".parse::<ZonedAddr>().unwrap().to_string(), "::1");
+        assert_eq!("10.0.0.1".parse::<ZonedAddr>().unwrap().zone, None);
+        assert_eq!("[fe80::1%eth0]".parse::<ZonedAddr>(), Err(NetAddrError::InvalidZone));
+        assert_eq!("fe80::1%".parse::<ZonedAddr>(), Err(NetAddrError::InvalidZone));
+        assert_eq!("fe80::1%a b".parse::<ZonedAddr>(), Err(NetAddrError::InvalidZone));
+        assert_eq!("10.0.0.1%eth0".parse::<ZonedAddr>(), Err(NetAddrError::InvalidZone));
+        assert_eq!("10.0.0.256".parse::<ZonedAddr>(), Err(NetAddrError::InvalidIp));
+        assert_eq!(json::decode::<ZonedAddr>("\"fe80::1%eth0\"").unwrap(), addr);
+        assert_eq!(json::encode(&addr).unwrap(), "\"fe80::1%eth0\"");
+    }
+
+    #[test]
+    fn test_cidr() {
+        let net: Cidr = "2001:db8::1/32".parse().unwrap();
+        assert_eq!(net.prefix(), 32);
+        assert_eq!(net.network().to_string(), "2001:db8::/32");
+        assert_eq!(net.netmask().to_string(), "ffff:ffff::");
+        assert!(net.contains(&"2001:db8:ffff::".parse().unwrap()));
+        assert!(!net.contains(&"2001:db9::".parse().unwrap()));
+        assert!(!net.contains(&"10.0.0.1".parse().unwrap()));
+
+        let net: Cidr = "10.1.2.3/8".parse().unwrap();
+        assert_eq!(net.netmask().to_string(), "255.0.0.0");
+        assert_eq!("10.1.2.3/255.0.0.0".parse(), Ok(net));
+        assert_eq!("0.0.0.0/0.0.0.0".parse::<Cidr>().unwrap().to_string(), "0.0.0.0/0");
+        let all: Cidr = "::/0".parse().unwrap();
+        assert!(all.contains(&"2001:db8::1".parse().unwrap()));
+        let host: Cidr = "10.0.0.1/32".parse().unwrap();
+        assert!(host.contains(&"10.0.0.1".parse().unwrap()));
+        assert!(!host.contains(&"10.0.0.2".parse().unwrap()));
+
+        assert_eq!("10.0.0.0".parse::<Cidr>(), Err(NetAddrError::MissingPrefix));
+        for s in &["10.0.0.0/33", "10.0.0.0/", "10.0.0.0/+8", "10.0.0.0/255.0.255.0",
+                   "::/129", "::/ffff::", "10.0.0.0/0008"] {
+            assert_eq!(s.parse::<Cidr>(), Err(NetAddrError::InvalidPrefix));
+        }
+        assert_eq!("10.0.0/8".parse::<Cidr>(), Err(NetAddrError::InvalidIp));
+        let addr: IpAddr = "10.0.0.0".parse().unwrap();
+        assert_eq!(Cidr::new(addr, 33), Err(NetAddrError::InvalidPrefix));
+        assert_eq!(json::decode::<Cidr>("\"10.1.2.3/8\"").unwrap(), net);
+        assert!(json::decode::<Cidr>("\"10.1.2.3\"").is_err());
+    }
+}
@@ -0,0 +1,493 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Netstrings and tagged netstrings
+//!
+//! A netstring frames a payload of bytes with its length, in decimal
+//! without leading zeros, and a comma: `5:hello,`. `encode` and `decode`
+//! frame one payload in memory, `decode` reporting a frame not yet
+//! complete so that a buffer can be filled and tried again.
+//! `NetstringWriter` and `NetstringReader` write and read a stream of
+//! them, as for messages between processes.
+//!
+//! A tagged netstring (tnetstring) ends with a tag giving the type of its
+//! payload in place of the comma: `,` a string, `#` an integer, `^` a
+//! float, `!` a boolean, `~` null, `]` a list of tagged netstrings and `}`
+//! a dictionary of them, keys and values in turn. `encode_tagged` and
+//! `decode_tagged` convert between them and `Json`.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::Json;
+//! use rustc_serialize::netstring::{self, NetstringReader, NetstringWriter};
+//!
+//! fn main() {
+//!     assert_eq!(netstring::encode(b"hello"), b"5:hello,");
+//!     assert_eq!(netstring::decode(b"5:hello,0:,").unwrap(), Some((&b"hello"[..], 8)));
+//!     assert_eq!(netstring::decode(b"5:hel").unwrap(), None);
+//!
+//!     let mut writer = NetstringWriter::new(Vec::new());
+//!     writer.write_frame(b"one").unwrap();
+//!     writer.write_frame(b"").unwrap();
+//!     let stream = writer.into_inner();
+//!     let mut reader = NetstringReader::new(&stream[..]);
+//!     assert_eq!(reader.read_frame().unwrap(), Some(b"one".to_vec()));
+//!     assert_eq!(reader.read_frame().unwrap(), Some(Vec::new()));
+//!     assert_eq!(reader.read_frame().unwrap(), None);
+//!
+//!     let json = Json::from_str(r#"{"id": 7, "tags": ["a"]}"#).unwrap();
+//!     let tagged = netstring::encode_tagged(&json);
+//!     assert_eq!(tagged, &b"23:2:id,1:7#4:tags,4:1:a,]}"[..]);
+//!     assert_eq!(netstring::decode_tagged(&tagged).unwrap(), Some((json, 27)));
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::str;
+
+use json::{Json, Object};
+use limit::SizeLimitExceeded;
+
+/// The longest payload `NetstringReader` accepts by default, in bytes.
+pub const DEFAULT_MAX_LENGTH: usize = 16 << 20;
+
+/// The deepest nesting of lists and dictionaries `decode_tagged` accepts.
+pub const MAX_DEPTH: usize = 256;
+
+/// Errors from decoding netstrings. `NetstringReader` reports them as
+/// `io::Error`s of kind `InvalidData` wrapping a `NetstringError`, except
+/// that a truncated netstring is an `UnexpectedEof` error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetstringError {
+    /// The length was not a decimal number without leading zeros, or too
+    /// large to hold
+    InvalidLength,
+    /// The payload was not followed by a comma
+    MissingComma,
+    /// A tagged netstring ended with a tag of no type, given
+    InvalidTag(u8),
+    /// The payload of a tagged netstring was not one of its type: a
+    /// number, boolean or null malformed, a list or dictionary whose
+    /// payload does not divide into tagged netstrings, or a dictionary
+    /// with a key which is not a string or without a value
+    InvalidValue,
+    /// A string of a tagged netstring was not UTF-8
+    InvalidUtf8,
+    /// Lists and dictionaries were nested deeper than `MAX_DEPTH`
+    TooDeep,
+}
+
+impl NetstringError {
+    /// Returns the `NetstringError` wrapped by `err`, if any.
+    pub fn from_io_error(err: &io::Error) -> Option<NetstringError> {
+        err.get_ref().and_then(|e| e.downcast_ref::<NetstringError>()).cloned()
+    }
+}
+
+impl fmt::Display for NetstringError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NetstringError::InvalidLength => write!(f, "invalid netstring length"),
+            NetstringError::MissingComma => write!(f, "missing comma after netstring"),
+            NetstringError::InvalidTag(tag) => {
+                write!(f, "invalid tnetstring tag {:?}", tag as char)
+            }
+            NetstringError::InvalidValue => write!(f, "invalid tnetstring value"),
+            NetstringError::InvalidUtf8 => write!(f, "tnetstring string is not UTF-8"),
+            NetstringError::TooDeep => write!(f, "tnetstring nested deeper than {}", MAX_DEPTH),
+        }
+    }
+}
+
+impl error::Error for NetstringError {
+    fn description(&self) -> &str { "invalid netstring" }
+}
+
+/// Returns `payload` framed as a netstring.
+pub fn encode(payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 12);
+    push_frame(&mut out, payload, b',');
+    out
+}
+
+fn push_frame(out: &mut Vec<u8>, payload: &[u8], end: u8) {
+    out.extend_from_slice(payload.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend_from_slice(payload);
+    out.push(end);
+}
+
+/// Decodes the netstring at the start of `input`, returning its payload
+/// and the number of bytes it takes, or `None` if `input` holds only the
+/// start of one.
+pub fn decode(input: &[u8]) -> Result<Option<(&[u8], usize)>, NetstringError> {
+    match try!(frame(input)) {
+        Some((payload, b',', len)) => Ok(Some((payload, len))),
+        Some(_) => Err(NetstringError::MissingComma),
+        None => Ok(None),
+    }
+}
+
+// A frame's payload, the byte after it, and its length in all.
+type Frame<'a> = (&'a [u8], u8, usize);
+
+// Splits the frame at the start of `input`, if it is all there.
+fn frame(input: &[u8]) -> Result<Option<Frame<'_>>, NetstringError> {
+    let (len, digits) = match try!(length(input)) {
+        Some(length) => length,
+        None => return Ok(None),
+    };
+    let start = digits + 1;
+    match start.checked_add(len) {
+        Some(end) if end < input.len() => Ok(Some((&input[start..end], input[end], end + 1))),
+        Some(_) => Ok(None),
+        None => Err(NetstringError::InvalidLength),
+    }
+}
+
+// Parses the length at the start of `input`, returning it and the number
+// of its digits, or `None` if `input` ends before its colon.
+fn length(input: &[u8]) -> Result<Option<(usize, usize)>, NetstringError> {
+    let mut len = 0usize;
+    for (i, &b) in input.iter().enumerate() {
+        match b {
+            b':' if i > 0 => return Ok(Some((len, i))),
+            b'0'...b'9' if !(i == 1 && input[0] == b'0') => {
+                len = try!(len.checked_mul(10).and_then(|l| l.checked_add((b - b'0') as usize))
+                              .ok_or(NetstringError::InvalidLength));
+            }
+            _ => return Err(NetstringError::InvalidLength),
+        }
+    }
+    Ok(None)
+}
+
+/// A writer of netstrings to `W`.
+pub struct NetstringWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> NetstringWriter<W> {
+    /// Creates a writer of netstrings to `inner`.
+    pub fn new(inner: W) -> NetstringWriter<W> {
+        NetstringWriter { inner: inner }
+    }
+
+    /// Writes `payload` as a netstring.
+    pub fn write_frame(&mut self, payload: &[u8]) -> io::Result<()> {
+        try!(write!(self.inner, "{}:", payload.len()));
+        try!(self.inner.write_all(payload));
+        self.inner.write_all(b",")
+    }
+
+    /// Writes `json` as a tagged netstring.
+    pub fn write_tagged(&mut self, json: &Json) -> io::Result<()> {
+        self.inner.write_all(&encode_tagged(json))
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// A reader of netstrings from `R`.
+///
+/// A payload longer than the maximum length is an `InvalidData` error
+/// wrapping `limit::SizeLimitExceeded`, met before it is read.
+pub struct NetstringReader<R: BufRead> {
+    inner: R,
+    max_length: usize,
+}
+
+impl<R: BufRead> NetstringReader<R> {
+    /// Creates a reader of netstrings from `inner`.
+    pub fn new(inner: R) -> NetstringReader<R> {
+        NetstringReader { inner: inner, max_length: DEFAULT_MAX_LENGTH }
+    }
+
+    /// Set the longest payload accepted, in bytes; by default
+    /// `DEFAULT_MAX_LENGTH`. This should be set before reading.
+    pub fn set_max_length(&mut self, bytes: usize) {
+        self.max_length = bytes;
+    }
+
+    /// Reads the payload of the next netstring, or returns `None` at the
+    /// end of the stream.
+    pub fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match try!(self.read()) {
+            Some((payload, b',')) => Ok(Some(payload)),
+            Some(_) => Err(invalid_data(NetstringError::MissingComma)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the next tagged netstring, or returns `None` at the end of the
+    /// stream.
+    pub fn read_tagged(&mut self) -> io::Result<Option<Json>> {
+        let (payload, tag) = match try!(self.read()) {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        value(&payload, tag, 0).map(Some).map_err(invalid_data)
+    }
+
+    // Reads the payload of the next frame and the byte after it.
+    fn read(&mut self) -> io::Result<Option<(Vec<u8>, u8)>> {
+        let mut head = Vec::new();
+        // A length of `usize` takes at most 20 digits.
+        try!((&mut self.inner).take(22).read_until(b':', &mut head));
+        if head.is_empty() {
+            return Ok(None);
+        }
+        let len = match length(&head) {
+            Ok(Some((len, _))) => len,
+            Ok(None) if head.len() < 22 => return Err(truncated()),
+            Ok(None) | Err(_) => return Err(invalid_data(NetstringError::InvalidLength)),
+        };
+        if len > self.max_length {
+            let limit = SizeLimitExceeded { limit: self.max_length as u64 };
+            return Err(io::Error::new(io::ErrorKind::InvalidData, limit));
+        }
+        let mut payload = Vec::new();
+        let read = try!((&mut self.inner).take(len as u64 + 1).read_to_end(&mut payload));
+        if read < len + 1 {
+            return Err(truncated());
+        }
+        let end = payload.pop().unwrap();
+        Ok(Some((payload, end)))
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns the underlying reader, positioned after the last netstring
+    /// read.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+fn invalid_data(err: NetstringError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated netstring")
+}
+
+/// Returns `json` encoded as a tagged netstring. Strings, numbers and
+/// booleans are tagged with their own types, objects as dictionaries and
+/// arrays as lists.
+pub fn encode_tagged(json: &Json) -> Vec<u8> {
+    let mut out = Vec::new();
+    push_tagged(&mut out, json);
+    out
+}
+
+fn push_tagged(out: &mut Vec<u8>, json: &Json) {
+    match *json {
+        Json::Null => push_frame(out, b"", b'~'),
+        Json::Boolean(b) => push_frame(out, if b { b"true" } else { b"false" }, b'!'),
+        Json::I64(n) => push_frame(out, n.to_string().as_bytes(), b'#'),
+        Json::U64(n) => push_frame(out, n.to_string().as_bytes(), b'#'),
+        Json::F64(f) => push_frame(out, f.to_string().as_bytes(), b'^'),
+        Json::String(ref s) => push_frame(out, s.as_bytes(), b','),
+        Json::Wtf8(ref s) => push_frame(out, s.to_string_lossy().as_bytes(), b','),
+        Json::Array(ref array) => {
+            let mut payload = Vec::new();
+            for elt in array {
+                push_tagged(&mut payload, elt);
+            }
+            push_frame(out, &payload, b']');
+        }
+        Json::Object(ref object) => {
+            let mut payload = Vec::new();
+            for (key, value) in object {
+                push_frame(&mut payload, key.as_bytes(), b',');
+                push_tagged(&mut payload, value);
+            }
+            push_frame(out, &payload, b'}');
+        }
+    }
+}
+
+/// Decodes the tagged netstring at the start of `input`, returning its
+/// value and the number of bytes it takes, or `None` if `input` holds only
+/// the start of one.
+pub fn decode_tagged(input: &[u8]) -> Result<Option<(Json, usize)>, NetstringError> {
+    match try!(frame(input)) {
+        Some((payload, tag, len)) => Ok(Some((try!(value(payload, tag, 0)), len))),
+        None => Ok(None),
+    }
+}
+
+// The value of the tagged netstring with the payload and tag, within
+// `depth` lists and dictionaries.
+fn value(payload: &[u8], tag: u8, depth: usize) -> Result<Json, NetstringError> {
+    let text = || str::from_utf8(payload).map_err(|_| NetstringError::InvalidValue);
+    Ok(match tag {
+        b',' => {
+            let s = try!(str::from_utf8(payload).map_err(|_| NetstringError::InvalidUtf8));
+            Json::String(s.into())
+        }
+        b'#' => {
+            // As the JSON parser does, non-negative integers are `U64`s.
+            let s = try!(text());
+            let n = if s.starts_with('-') {
+                s.parse().map(Json::I64)
+            } else {
+                s.parse().map(Json::U64)
+            };
+            try!(n.map_err(|_| NetstringError::InvalidValue))
+        }
+        b'^' => Json::F64(try!(try!(text()).parse().map_err(|_| NetstringError::InvalidValue))),
+        b'!' => match payload {
+            b"true" => Json::Boolean(true),
+            b"false" => Json::Boolean(false),
+            _ => return Err(NetstringError::InvalidValue),
+        },
+        b'~' if payload.is_empty() => Json::Null,
+        b'~' => return Err(NetstringError::InvalidValue),
+        b']' | b'}' => {
+            if depth == MAX_DEPTH {
+                return Err(NetstringError::TooDeep);
+            }
+            let mut elts = Vec::new();
+            let mut rest = payload;
+            while !rest.is_empty() {
+                let (payload, tag, len) = match frame(rest) {
+                    Ok(Some(frame)) => frame,
+                    _ => return Err(NetstringError::InvalidValue),
+                };
+                elts.push((payload, tag));
+                rest = &rest[len..];
+            }
+            if tag == b']' {
+                let mut array = Vec::with_capacity(elts.len());
+                for (payload, tag) in elts {
+                    array.push(try!(value(payload, tag, depth + 1)));
+                }
+                Json::Array(array)
+            } else {
+                if elts.len() % 2 == 1 {
+                    return Err(NetstringError::InvalidValue);
+                }
+                let mut object = Object::new();
+                for pair in elts.chunks(2) {
+                    let key = match try!(value(pair[0].0, pair[0].1, depth + 1)) {
+                        Json::String(key) => key,
+                        _ => return Err(NetstringError::InvalidValue),
+                    };
+                    object.insert(key.into(), try!(value(pair[1].0, pair[1].1, depth + 1)));
+                }
+                Json::Object(object)
+            }
+        }
+        tag => return Err(NetstringError::InvalidTag(tag)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use json::Json;
+    use limit::SizeLimitExceeded;
+    use netstring::{self, NetstringError, NetstringReader, NetstringWriter};
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(netstring::encode(b""), b"0:,");
+        assert_eq!(netstring::decode(b"3:a,b,").unwrap(), Some((&b"a,b"[..], 6)));
+        for partial in [&b""[..], b"1", b"12:", b"3:abc"].iter() {
+            assert_eq!(netstring::decode(partial), Ok(None));
+        }
+        for &(input, err) in &[(&b":a,"[..], NetstringError::InvalidLength),
+                               (b"01:a,", NetstringError::InvalidLength),
+                               (b"-1:a,", NetstringError::InvalidLength),
+                               (b"99999999999999999999999:", NetstringError::InvalidLength),
+                               (b"1:ab", NetstringError::MissingComma)] {
+            assert_eq!(netstring::decode(input), Err(err));
+        }
+    }
+
+    #[test]
+    fn test_reader() {
+        let mut writer = NetstringWriter::new(Vec::new());
+        writer.write_frame(b"\x00\xff").unwrap();
+        writer.write_tagged(&Json::from_str("[1.5, null, false]").unwrap()).unwrap();
+        let mut stream = writer.into_inner();
+        assert_eq!(stream, &b"2:\x00\xff,17:3:1.5^0:~5:false!]"[..]);
+        stream.extend_from_slice(b"3:abc");
+
+        let mut reader = NetstringReader::new(&stream[..]);
+        assert_eq!(reader.read_frame().unwrap(), Some(b"\x00\xff".to_vec()));
+        assert_eq!(reader.read_tagged().unwrap().unwrap().to_string(), "[1.5,null,false]");
+        assert_eq!(reader.read_frame().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut reader = NetstringReader::new(&b"4:abcd,"[..]);
+        reader.set_max_length(3);
+        let err = reader.read_frame().unwrap_err();
+        assert_eq!(SizeLimitExceeded::from_io_error(&err), Some(SizeLimitExceeded { limit: 3 }));
+        for &(input, err) in &[(&b"1:ab"[..], NetstringError::MissingComma),
+                               (b"x:", NetstringError::InvalidLength),
+                               (b"0000000000000000000000000:", NetstringError::InvalidLength)] {
+            let e = NetstringReader::new(input).read_frame().unwrap_err();
+            assert_eq!(NetstringError::from_io_error(&e), Some(err));
+        }
+        let e = NetstringReader::new(&b"12"[..]).read_frame().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_tagged() {
+        let json = Json::from_str("{\"a\": [-1, 18446744073709551615, \"\", {}], \"b\": true}")
+            .unwrap();
+        let tagged = netstring::encode_tagged(&json);
+        assert_eq!(netstring::decode_tagged(&tagged), Ok(Some((json, tagged.len()))));
+        assert_eq!(netstring::decode_tagged(&tagged[..tagged.len() - 1]), Ok(None));
+
+        for &(input, err) in &[(&b"1:x?"[..], NetstringError::InvalidTag(b'?')),
+                               (b"1:x#", NetstringError::InvalidValue),
+                               (b"3:yes!", NetstringError::InvalidValue),
+                               (b"1:x~", NetstringError::InvalidValue),
+                               (b"1:\xff,", NetstringError::InvalidUtf8),
+                               (b"3:0:~}", NetstringError::InvalidValue),
+                               (b"4:1:a,}", NetstringError::InvalidValue),
+                               (b"3:1:a]", NetstringError::InvalidValue),
+                               (b"1:x]", NetstringError::InvalidValue)] {
+            assert_eq!(netstring::decode_tagged(input), Err(err));
+        }
+        let mut deep = netstring::encode_tagged(&Json::Null);
+        for _ in 0..257 {
+            deep = netstring::encode(&deep);
+            let end = deep.len() - 1;
+            deep[end] = b']';
+        }
+        assert_eq!(netstring::decode_tagged(&deep), Err(NetstringError::TooDeep));
+    }
+}
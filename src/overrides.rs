@@ -0,0 +1,236 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Per-format representations of types, chosen at runtime
+//!
+//! An application can register, for a type and a format name such as
+//! `"json"`, a function converting values of the type to the `Json` to
+//! encode in their place, and one converting such `Json` back. A type opts
+//! in by wrapping its `Encodable` and `Decodable` implementations in
+//! `encode_with` and `decode_with`, which use the override registered for
+//! the format of the encoder or decoder, if there is one, and otherwise the
+//! type's own representation.
+//!
+//! Formats are named by `Encoder::format` and `Decoder::format`. The `json`
+//! encoders and decoder are `"json"`, and the `cbor` encoder is `"cbor"`.
+//! An override's `Json` is encoded as any other `Json` tree would be, so it
+//! can be used with any encoder; decoding needs `json::read_json`, which
+//! only the `json` decoder supports.
+//!
+//! The registry is global, and registering replaces any override for the
+//! same type and format.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::{overrides, Decodable, Decoder, Encodable, Encoder};
+//! use rustc_serialize::json::{self, Json};
+//!
+//! // Seconds since the epoch, encoded as a number by default.
+//! #[derive(Debug, PartialEq)]
+//! struct Timestamp(i64);
+//!
+//! impl Encodable for Timestamp {
+//!     fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+//!         overrides::encode_with(self, s, |s| s.emit_i64(self.0))
+//!     }
+//! }
+//!
+//! impl Decodable for Timestamp {
+//!     fn decode<D: Decoder>(d: &mut D) -> Result<Timestamp, D::Error> {
+//!         overrides::decode_with(d, |d| d.read_i64().map(Timestamp))
+//!     }
+//! }
+//!
+//! fn main() {
+//!     assert_eq!(json::encode(&Timestamp(90)).unwrap(), "90");
+//!
+//!     // This application's JSON clients expect `"@90"`.
+//!     fn to_json(t: &Timestamp) -> Json {
+//!         Json::from(format!("@{}", t.0))
+//!     }
+//!     fn from_json(json: Json) -> Result<Timestamp, String> {
+//!         json.as_string()
+//!             .and_then(|s| if s.starts_with('@') { s[1..].parse().ok() } else { None })
+//!             .map(Timestamp)
+//!             .ok_or(format!("not a timestamp: {}", json))
+//!     }
+//!     overrides::register_encode("json", to_json);
+//!     overrides::register_decode("json", from_json);
+//!
+//!     assert_eq!(json::encode(&Timestamp(90)).unwrap(), "\"@90\"");
+//!     assert_eq!(json::decode::<Timestamp>("\"@90\"").unwrap(), Timestamp(90));
+//! }
+//! ```
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::ptr;
+use std::sync::{Once, RwLock};
+
+use json::{self, Json};
+use serialize::{Decoder, Encodable, Encoder};
+
+// The registered functions, boxed, by the type they apply to and format.
+type Registry = RwLock<HashMap<(TypeId, &'static str), Box<Any + Send + Sync>>>;
+
+// The encoding and decoding registries, created on first use: before Rust
+// 1.63 a `RwLock` cannot be created in a static.
+fn registries() -> &'static (Registry, Registry) {
+    static INIT: Once = Once::new();
+    static mut REGISTRIES: *const (Registry, Registry) = ptr::null();
+    unsafe {
+        INIT.call_once(|| {
+            let registries = (RwLock::new(HashMap::new()), RwLock::new(HashMap::new()));
+            REGISTRIES = Box::into_raw(Box::new(registries));
+        });
+        &*REGISTRIES
+    }
+}
+
+fn register<T: Any, F: Any + Send + Sync>(registry: &Registry, format: &'static str, f: F) {
+    let mut registry = registry.write().unwrap_or_else(|e| e.into_inner());
+    registry.insert((TypeId::of::<T>(), format), Box::new(f));
+}
+
+fn unregister<T: Any>(registry: &Registry, format: &'static str) {
+    let mut registry = registry.write().unwrap_or_else(|e| e.into_inner());
+    registry.remove(&(TypeId::of::<T>(), format));
+}
+
+fn lookup<T: Any, F: Any + Copy>(registry: &Registry, format: Option<&'static str>)
+                                  -> Option<F> {
+    let format = match format {
+        Some(format) => format,
+        None => return None,
+    };
+    let registry = registry.read().unwrap_or_else(|e| e.into_inner());
+    registry.get(&(TypeId::of::<T>(), format))
+            .and_then(|f| f.downcast_ref::<F>())
+            .cloned()
+}
+
+/// Registers `f` to convert values of type `T` to the `Json` to encode in
+/// their place, in `format`.
+pub fn register_encode<T: Any>(format: &'static str, f: fn(&T) -> Json) {
+    register::<T, _>(&registries().0, format, f)
+}
+
+/// Registers `f` to convert `Json` decoded in place of a `T`, in `format`,
+/// to the `T`; an error is reported through `Decoder::error`.
+pub fn register_decode<T: Any>(format: &'static str, f: fn(Json) -> Result<T, String>) {
+    register::<T, _>(&registries().1, format, f)
+}
+
+/// Removes the overrides for encoding and decoding `T` in `format`.
+pub fn unregister_all<T: Any>(format: &'static str) {
+    unregister::<T>(&registries().0, format);
+    unregister::<T>(&registries().1, format);
+}
+
+/// Encodes `value` with the override registered for `T` in the format of
+/// `s`, or with `default` if there is none.
+///
+/// This is for `T`'s `Encodable` implementation, with `default` encoding
+/// its own representation.
+pub fn encode_with<T, S, F>(value: &T, s: &mut S, default: F) -> Result<(), S::Error>
+    where T: Any, S: Encoder, F: FnOnce(&mut S) -> Result<(), S::Error>
+{
+    match lookup::<T, fn(&T) -> Json>(&registries().0, s.format()) {
+        Some(f) => f(value).encode(s),
+        None => default(s),
+    }
+}
+
+/// Decodes a `T` with the override registered for it in the format of
+/// `d`, or with `default` if there is none.
+///
+/// This is for `T`'s `Decodable` implementation, with `default` decoding
+/// its own representation.
+pub fn decode_with<T, D, F>(d: &mut D, default: F) -> Result<T, D::Error>
+    where T: Any, D: Decoder, F: FnOnce(&mut D) -> Result<T, D::Error>
+{
+    match lookup::<T, fn(Json) -> Result<T, String>>(&registries().1, d.format()) {
+        Some(f) => {
+            let json = try!(json::read_json(d));
+            f(json).map_err(|msg| d.error(&msg))
+        }
+        None => default(d),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cbor;
+    use json::{self, Json};
+    use overrides::{decode_with, encode_with, register_decode, register_encode,
+                    unregister_all};
+    use serialize::{Decodable, Decoder, Encodable, Encoder};
+
+    // Tests run concurrently against the one registry, so overrides are
+    // only registered for types no other test uses.
+    macro_rules! celsius {
+        ($name:ident) => (
+            #[derive(Debug, PartialEq)]
+            struct $name(f64);
+
+            impl Encodable for $name {
+                fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                    encode_with(self, s, |s| s.emit_f64(self.0))
+                }
+            }
+
+            impl Decodable for $name {
+                fn decode<D: Decoder>(d: &mut D) -> Result<$name, D::Error> {
+                    decode_with(d, |d| d.read_f64().map($name))
+                }
+            }
+        )
+    }
+
+    celsius!(Celsius);
+    celsius!(Unregistered);
+
+    fn encode_celsius(c: &Celsius) -> Json {
+        let mut obj = json::Object::new();
+        obj.insert("C".to_string(), Json::F64(c.0));
+        Json::Object(obj)
+    }
+
+    fn decode_celsius(json: Json) -> Result<Celsius, String> {
+        json.find("C").and_then(|c| c.as_f64()).map(Celsius).ok_or("no C".to_string())
+    }
+
+    #[test]
+    fn test_overrides() {
+        let readings = vec![Some(Celsius(21.5)), None];
+        assert_eq!(json::encode(&readings).unwrap(), "[21.5,null]");
+        register_encode("json", encode_celsius);
+        register_decode("json", decode_celsius);
+        assert_eq!(json::encode(&readings).unwrap(), "[{\"C\":21.5},null]");
+        assert_eq!(json::to_value(&readings).unwrap(),
+                   Json::from_str("[{\"C\":21.5},null]").unwrap());
+        assert_eq!(json::decode::<Vec<Option<Celsius>>>("[{\"C\":21.5},null]").unwrap(),
+                   readings);
+        assert_eq!(json::decode::<Celsius>("{\"F\":70}"),
+                   Err(json::DecoderError::ApplicationError("no C".to_string())));
+
+        // Other formats, and other types, are unaffected.
+        let mut encoder = cbor::Encoder::new();
+        Celsius(21.5).encode(&mut encoder).unwrap();
+        assert_eq!(encoder.into_value(), cbor::Value::Float(21.5));
+        assert_eq!(json::encode(&Unregistered(21.5)).unwrap(), "21.5");
+
+        unregister_all::<Celsius>("json");
+        assert_eq!(json::encode(&readings).unwrap(), "[21.5,null]");
+        assert_eq!(json::decode::<Celsius>("21.5").unwrap(), Celsius(21.5));
+    }
+}
@@ -0,0 +1,390 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Delta and run-length packing of integer arrays
+//!
+//! Time series and other long arrays of integers tend to be monotone, like
+//! timestamps, or repetitive, like status codes, and this module packs
+//! them into fewer, smaller numbers:
+//!
+//! * `Packing::Delta` keeps the first value and then the difference of
+//!   each value from the one before, so `[1000, 1001, 1003]` becomes
+//!   `[1000, 1, 2]`;
+//! * `Packing::RunLength` keeps each run of equal values as the value and
+//!   the length of the run, so `[7, 7, 7, 0]` becomes `[7, 3, 0, 1]`.
+//!
+//! A packed array is marked with the extension tag of its packing (see
+//! `ExtensionTag`). `pack_json` replaces the arrays of a `Json` tree that
+//! pack well with objects in the JSON form of tagged values, `{"$tag":
+//! DELTA_TAG, "value": [1000, 1, 2]}`, and `unpack_json` restores them.
+//! The `cbor` encoder packs arrays when set to with `set_pack_arrays`,
+//! writing each as a tag holding a byte string of the packed numbers as
+//! zigzag varints (see `write_varints`), and converts the JSON form of
+//! packed arrays likewise.
+//!
+//! Only arrays of at least `MIN_LEN` integers, all in the range of `i64`,
+//! are packed, and only when packing shrinks them.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::Json;
+//! use rustc_serialize::packed::{self, Packing};
+//!
+//! fn main() {
+//!     let times = [1500000000, 1500000010, 1500000020, 1500000031];
+//!     assert_eq!(Packing::Delta.pack(&times), [1500000000, 10, 10, 11]);
+//!
+//!     let mut json = Json::from_str("{\"status\": [200, 200, 200, 200, 200, 200, 200, 404]}")
+//!                        .unwrap();
+//!     let original = json.clone();
+//!     packed::pack_json(&mut json);
+//!     assert_eq!(json.to_string(), "{\"status\":{\"$tag\":40201,\"value\":[200,7,404,1]}}");
+//!     packed::unpack_json(&mut json);
+//!     assert_eq!(json, original);
+//! }
+//! ```
+
+use std::mem;
+
+use json::{Json, Object};
+
+/// The extension tag of delta packed arrays.
+pub const DELTA_TAG: u64 = 40200;
+/// The extension tag of run-length packed arrays.
+pub const RLE_TAG: u64 = 40201;
+/// The length of the shortest arrays `pack_json` and the `cbor` encoder
+/// pack.
+pub const MIN_LEN: usize = 8;
+
+/// A way of packing an array of integers; see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Packing {
+    /// The first value, then the difference of each from the one before
+    Delta,
+    /// Each run of equal values as the value and the length of the run
+    RunLength,
+}
+
+impl Packing {
+    /// The extension tag marking arrays packed this way.
+    pub fn tag(self) -> u64 {
+        match self {
+            Packing::Delta => DELTA_TAG,
+            Packing::RunLength => RLE_TAG,
+        }
+    }
+
+    /// The packing marked by `tag`, if any.
+    pub fn from_tag(tag: u64) -> Option<Packing> {
+        match tag {
+            DELTA_TAG => Some(Packing::Delta),
+            RLE_TAG => Some(Packing::RunLength),
+            _ => None,
+        }
+    }
+
+    /// The packing which shrinks `values` the most, measured in the bytes
+    /// of their zigzag varints, if any does and there are at least
+    /// `MIN_LEN` of them.
+    pub fn choose(values: &[i64]) -> Option<Packing> {
+        if values.len() < MIN_LEN {
+            return None;
+        }
+        let plain = varints_len(values);
+        let delta = varints_len(&Packing::Delta.pack(values));
+        let rle = varints_len(&Packing::RunLength.pack(values));
+        if delta <= rle && delta < plain {
+            Some(Packing::Delta)
+        } else if rle < plain {
+            Some(Packing::RunLength)
+        } else {
+            None
+        }
+    }
+
+    /// Packs `values` this way. Differences wrap around, so that every
+    /// array of `i64`s can be delta packed.
+    pub fn pack(self, values: &[i64]) -> Vec<i64> {
+        let mut packed = Vec::new();
+        match self {
+            Packing::Delta => {
+                let mut prev = 0i64;
+                for &v in values {
+                    packed.push(v.wrapping_sub(prev));
+                    prev = v;
+                }
+            }
+            Packing::RunLength => {
+                let mut i = 0;
+                while i < values.len() {
+                    let run = values[i..].iter().take_while(|&&v| v == values[i]).count();
+                    packed.push(values[i]);
+                    packed.push(run as i64);
+                    i += run;
+                }
+            }
+        }
+        packed
+    }
+
+    /// Unpacks numbers packed this way, or returns `None` if they are not
+    /// the packing of any array: for run-length packing, if there are an
+    /// odd number of them, a run length is not positive or the runs total
+    /// more values than an array can hold.
+    ///
+    /// Run lengths are not otherwise limited, so unpacking untrusted input
+    /// can take any amount of memory.
+    pub fn unpack(self, packed: &[i64]) -> Option<Vec<i64>> {
+        let mut values = Vec::new();
+        match self {
+            Packing::Delta => {
+                values.reserve(packed.len());
+                let mut prev = 0i64;
+                for &d in packed {
+                    prev = prev.wrapping_add(d);
+                    values.push(prev);
+                }
+            }
+            Packing::RunLength => {
+                if packed.len() % 2 == 1 {
+                    return None;
+                }
+                let max = (isize::MAX as usize / mem::size_of::<i64>()) as u64;
+                let mut len = 0u64;
+                for pair in packed.chunks(2) {
+                    if pair[1] <= 0 {
+                        return None;
+                    }
+                    len = match len.checked_add(pair[1] as u64) {
+                        Some(len) if len <= max => len,
+                        _ => return None,
+                    };
+                }
+                values.reserve(len as usize);
+                for pair in packed.chunks(2) {
+                    values.extend((0..pair[1]).map(|_| pair[0]));
+                }
+            }
+        }
+        Some(values)
+    }
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn varint_len(mut v: u64) -> usize {
+    let mut len = 1;
+    while v >= 0x80 {
+        v >>= 7;
+        len += 1;
+    }
+    len
+}
+
+fn varints_len(values: &[i64]) -> usize {
+    values.iter().map(|&v| varint_len(zigzag(v))).sum()
+}
+
+/// Appends `values` to `out` as zigzag varints: each value `v` is mapped
+/// to `(v << 1) ^ (v >> 63)`, so that numbers near zero, negative or not,
+/// are small, and written seven bits at a time, least significant first,
+/// the top bit of each byte but the last set.
+pub fn write_varints(values: &[i64], out: &mut Vec<u8>) {
+    for &v in values {
+        let mut v = zigzag(v);
+        while v >= 0x80 {
+            out.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+    }
+}
+
+/// Reads the values `write_varints` wrote, or returns `None` if `bytes`
+/// ends within a varint or holds one longer than ten bytes.
+pub fn read_varints(bytes: &[u8]) -> Option<Vec<i64>> {
+    let mut values = Vec::new();
+    let mut v = 0u64;
+    let mut shift = 0;
+    for &b in bytes {
+        if shift > 63 {
+            return None;
+        }
+        v |= ((b & 0x7f) as u64) << shift;
+        shift += 7;
+        if b & 0x80 == 0 {
+            values.push((v >> 1) as i64 ^ -((v & 1) as i64));
+            v = 0;
+            shift = 0;
+        }
+    }
+    if shift != 0 {
+        return None;
+    }
+    Some(values)
+}
+
+/// The values of `array`, if they are all integers in the range of `i64`.
+pub fn integers(array: &[Json]) -> Option<Vec<i64>> {
+    array.iter().map(|v| match *v {
+        Json::I64(v) => Some(v),
+        Json::U64(v) if v <= i64::MAX as u64 => Some(v as i64),
+        _ => None,
+    }).collect()
+}
+
+/// Replaces each array in `json` which `Packing::choose` packs with the
+/// JSON form of a tagged value holding the packed numbers.
+pub fn pack_json(json: &mut Json) {
+    let packed = match *json {
+        Json::Array(ref mut array) => {
+            let values = integers(array);
+            match values.as_ref().and_then(|values| Packing::choose(values)) {
+                Some(packing) => {
+                    let packed = packing.pack(&values.unwrap());
+                    tagged(packing.tag(), Json::Array(packed.into_iter().map(json_int).collect()))
+                }
+                None => {
+                    for elt in array.iter_mut() {
+                        pack_json(elt);
+                    }
+                    return;
+                }
+            }
+        }
+        Json::Object(ref mut object) => {
+            for member in object.values_mut() {
+                pack_json(member);
+            }
+            return;
+        }
+        _ => return,
+    };
+    *json = packed;
+}
+
+/// Replaces each packed array in `json`, in the form `pack_json` writes,
+/// with the array it packs. A tagged value with a packing's tag which is
+/// not a packed array is left as it is.
+pub fn unpack_json(json: &mut Json) {
+    let unpacked = match *json {
+        Json::Array(ref mut array) => {
+            for elt in array.iter_mut() {
+                unpack_json(elt);
+            }
+            return;
+        }
+        Json::Object(ref mut object) => match packed_array(object) {
+            Some(values) => Json::Array(values.into_iter().map(json_int).collect()),
+            None => {
+                for member in object.values_mut() {
+                    unpack_json(member);
+                }
+                return;
+            }
+        },
+        _ => return,
+    };
+    *json = unpacked;
+}
+
+// The array an object in the JSON form of a packed array packs.
+fn packed_array(object: &Object) -> Option<Vec<i64>> {
+    if object.len() != 2 {
+        return None;
+    }
+    let packing = object.get("$tag").and_then(|t| t.as_u64()).and_then(Packing::from_tag);
+    let packed = match object.get("value") {
+        Some(Json::Array(packed)) => integers(packed),
+        _ => None,
+    };
+    match (packing, packed) {
+        (Some(packing), Some(packed)) => packing.unpack(&packed),
+        _ => None,
+    }
+}
+
+fn tagged(tag: u64, value: Json) -> Json {
+    let mut object = Object::new();
+    object.insert("$tag".to_string(), Json::U64(tag));
+    object.insert("value".to_string(), value);
+    Json::Object(object)
+}
+
+// An integer as the parser reads it.
+fn json_int(v: i64) -> Json {
+    if v < 0 { Json::I64(v) } else { Json::U64(v as u64) }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::Json;
+    use packed::{self, read_varints, write_varints, Packing};
+
+    #[test]
+    fn test_packing() {
+        let values = [i64::MIN, -1, 0, 5, 5, 5, i64::MAX, 3];
+        for &packing in [Packing::Delta, Packing::RunLength].iter() {
+            let packed = packing.pack(&values);
+            assert_eq!(packing.unpack(&packed).unwrap(), values);
+            assert_eq!(Packing::from_tag(packing.tag()), Some(packing));
+        }
+        assert_eq!(Packing::RunLength.pack(&values), [i64::MIN, 1, -1, 1, 0, 1, 5, 3,
+                                                      i64::MAX, 1, 3, 1]);
+        assert_eq!(Packing::RunLength.unpack(&[1, 2, 3]), None);
+        assert_eq!(Packing::RunLength.unpack(&[1, 0]), None);
+        assert_eq!(Packing::RunLength.unpack(&[1, 9_000_000_000_000_000_000]), None);
+        assert_eq!(Packing::RunLength.unpack(&[1, i64::MAX, 2, i64::MAX, 3, 2]), None);
+        assert_eq!(Packing::Delta.unpack(&[]), Some(vec![]));
+
+        let ramp: Vec<i64> = (0..10).map(|i| 1_000_000 + 3 * i).collect();
+        assert_eq!(Packing::choose(&ramp), Some(Packing::Delta));
+        assert_eq!(Packing::choose(&[9; 10]), Some(Packing::RunLength));
+        assert_eq!(Packing::choose(&[9; 7]), None);
+        assert_eq!(Packing::choose(&[1, -1, 1, -1, 1, -1, 1, -1]), None);
+
+        let mut bytes = Vec::new();
+        write_varints(&[0, -1, 1, 300, i64::MIN], &mut bytes);
+        assert_eq!(&bytes[..5], &[0, 1, 2, 0xd8, 0x04]);
+        assert_eq!(bytes.len(), 15);
+        assert_eq!(read_varints(&bytes).unwrap(), [0, -1, 1, 300, i64::MIN]);
+        assert_eq!(read_varints(&bytes[..14]), None);
+        assert_eq!(read_varints(&[0x80; 11]), None);
+    }
+
+    #[test]
+    fn test_pack_json() {
+        let text = "{\"t\": [1000000, 1000001, 1000002, 1000003, 1000004, 1000005, \
+                    1000006, 1000008], \"nested\": [[0, 0, 0, 0, 0, 0, 0, 0], \"x\"], \
+                    \"short\": [5, 5], \"floats\": [1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5], \
+                    \"$tag\": 40200}";
+        let original = Json::from_str(text).unwrap();
+        let mut json = original.clone();
+        packed::pack_json(&mut json);
+        assert_eq!(json.to_string(), "{\"$tag\":40200,\
+                                      \"floats\":[1.5,1.5,1.5,1.5,1.5,1.5,1.5,1.5],\
+                                      \"nested\":[{\"$tag\":40201,\"value\":[0,8]},\"x\"],\
+                                      \"short\":[5,5],\
+                                      \"t\":{\"$tag\":40200,\"value\":[1000000,1,1,1,1,1,1,2]}}");
+        packed::unpack_json(&mut json);
+        assert_eq!(json, original);
+
+        // Not packed arrays, though tagged as such.
+        let text = "[{\"$tag\":40201,\"value\":[1]},{\"$tag\":40200,\"value\":\"x\"},\
+                    {\"$tag\":40201,\"value\":[1,9000000000000000000]}]";
+        let mut json = Json::from_str(text).unwrap();
+        packed::unpack_json(&mut json);
+        assert_eq!(json.to_string(), text);
+    }
+}
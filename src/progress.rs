@@ -0,0 +1,40 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Progress callbacks shared by the streaming readers, writers and parser.
+
+/// Calls a callback each time a byte count crosses a multiple of `every`.
+pub struct Progress {
+    every: u64,
+    next: u64,
+    callback: Box<FnMut(u64) + Send>,
+}
+
+impl Progress {
+    pub fn new<F>(every: u64, callback: F) -> Progress
+        where F: FnMut(u64) + Send + 'static
+    {
+        let every = if every == 0 { 1 } else { every };
+        Progress { every: every, next: every, callback: Box::new(callback) }
+    }
+
+    /// Reports `total` bytes; the callback runs at most once per call.
+    pub fn update(&mut self, total: u64) {
+        if total >= self.next {
+            (self.callback)(total);
+            self.next = (total / self.every + 1) * self.every;
+        }
+    }
+
+    /// Starts counting from zero again.
+    pub fn reset(&mut self) {
+        self.next = self.every;
+    }
+}
@@ -0,0 +1,384 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Encoding metrics in the Prometheus text exposition format
+//!
+//! A `Family` holds the series of one metric, each with its own labels:
+//! the values of a counter, gauge or untyped metric, or the buckets, sum
+//! and count of a histogram. `encode` writes families in the text format
+//! (version 0.0.4) scrapers read, each with its `# HELP` and `# TYPE`
+//! lines.
+//!
+//! As the Go client library does, families are written sorted by name,
+//! the labels of a series sorted by name, and the series of a family
+//! sorted by their labels. The buckets of a histogram are written in order
+//! of their upper bounds, with their counts cumulative, its `le` label
+//! last, and a `+Inf` bucket holding the count added if it is not given.
+//! Label values are escaped with `\\`, `\"` and `\n`, and help text with
+//! `\\` and `\n`. Names are checked, as are the buckets of histograms, and
+//! an error returned rather than a page scrapers would reject.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::promtext::{self, Family, Type};
+//!
+//! fn main() {
+//!     let mut requests = Family::new("http_requests_total", Type::Counter);
+//!     requests.set_help("Requests handled.");
+//!     requests.add(&[("method", "post"), ("code", "200")], 3.0);
+//!     requests.add(&[("method", "get"), ("code", "200")], 1027.0);
+//!
+//!     let mut latency = Family::new("http_latency_seconds", Type::Histogram);
+//!     latency.add_histogram(&[], &[(0.1, 20), (0.5, 24)], 3.5, 25);
+//!
+//!     assert_eq!(promtext::encode(&[requests, latency]).unwrap(), "\
+//! ## TYPE http_latency_seconds histogram
+//! http_latency_seconds_bucket{le=\"0.1\"} 20
+//! http_latency_seconds_bucket{le=\"0.5\"} 24
+//! http_latency_seconds_bucket{le=\"+Inf\"} 25
+//! http_latency_seconds_sum 3.5
+//! http_latency_seconds_count 25
+//! ## HELP http_requests_total Requests handled.
+//! ## TYPE http_requests_total counter
+//! http_requests_total{code=\"200\",method=\"get\"} 1027
+//! http_requests_total{code=\"200\",method=\"post\"} 3
+//! ");
+//! }
+//! ```
+
+use std::error::Error as StdError;
+use std::f64;
+use std::fmt;
+
+/// An error from encoding metrics, naming the family it was met in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PromError {
+    /// A metric name is not `[a-zA-Z_:][a-zA-Z0-9_:]*`
+    InvalidMetricName(String),
+    /// A label name, given after the family's, is not `[a-zA-Z_][a-zA-Z0-9_]*`,
+    /// starts with `__`, or is `le` in a histogram
+    InvalidLabelName(String, String),
+    /// A label name is given twice for a series
+    DuplicateLabel(String, String),
+    /// Several families have the name
+    DuplicateFamily(String),
+    /// Several series of the family have the same labels
+    DuplicateSeries(String),
+    /// A value was added with `add` to a histogram, or a histogram with
+    /// `add_histogram` to a family of another type
+    TypeMismatch(String),
+    /// A histogram has a bucket whose upper bound is NaN, whose count is
+    /// less than that of a bucket below it, or more than the histogram's
+    /// count, or a `+Inf` bucket without the histogram's count
+    InvalidBuckets(String),
+}
+
+impl fmt::Display for PromError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PromError::InvalidMetricName(ref name) => write!(f, "invalid metric name {:?}", name),
+            PromError::InvalidLabelName(ref name, ref label) => {
+                write!(f, "{}: invalid label name {:?}", name, label)
+            }
+            PromError::DuplicateLabel(ref name, ref label) => {
+                write!(f, "{}: duplicate label {:?}", name, label)
+            }
+            PromError::DuplicateFamily(ref name) => write!(f, "{}: duplicate family", name),
+            PromError::DuplicateSeries(ref name) => write!(f, "{}: duplicate series", name),
+            PromError::TypeMismatch(ref name) => write!(f, "{}: sample of the wrong type", name),
+            PromError::InvalidBuckets(ref name) => write!(f, "{}: invalid buckets", name),
+        }
+    }
+}
+
+impl StdError for PromError {
+    fn description(&self) -> &str { "metrics exposition error" }
+}
+
+/// The type of a metric family
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Type {
+    /// A value which only increases, but for resets
+    Counter,
+    /// A value which goes up and down
+    Gauge,
+    /// Counts of observations in buckets, with their sum
+    Histogram,
+    /// A value of no type given
+    Untyped,
+}
+
+impl Type {
+    fn name(&self) -> &'static str {
+        match *self {
+            Type::Counter => "counter",
+            Type::Gauge => "gauge",
+            Type::Histogram => "histogram",
+            Type::Untyped => "untyped",
+        }
+    }
+}
+
+/// The series of a metric; see the module documentation.
+#[derive(Clone, Debug)]
+pub struct Family {
+    name: String,
+    help: Option<String>,
+    kind: Type,
+    series: Vec<Series>,
+}
+
+#[derive(Clone, Debug)]
+struct Series {
+    labels: Vec<(String, String)>,
+    sample: Sample,
+}
+
+#[derive(Clone, Debug)]
+enum Sample {
+    Value(f64),
+    Histogram(Vec<(f64, u64)>, f64, u64),
+}
+
+impl Family {
+    /// Creates a family, without series, of the metric `name` of type
+    /// `kind`.
+    pub fn new(name: &str, kind: Type) -> Family {
+        Family { name: name.to_string(), help: None, kind: kind, series: Vec::new() }
+    }
+
+    /// Set the help text of the metric, written with its `# HELP` line.
+    pub fn set_help(&mut self, help: &str) {
+        self.help = Some(help.to_string());
+    }
+
+    /// Adds a series with the labels `labels` and the value `value`, to a
+    /// family which is not a histogram.
+    pub fn add(&mut self, labels: &[(&str, &str)], value: f64) {
+        self.push(labels, Sample::Value(value));
+    }
+
+    /// Adds a series of a histogram with the labels `labels`, the buckets
+    /// `buckets`, each an upper bound and the count of observations at or
+    /// below it, the sum of the observations `sum`, and their number
+    /// `count`.
+    pub fn add_histogram(&mut self, labels: &[(&str, &str)], buckets: &[(f64, u64)], sum: f64,
+                         count: u64) {
+        self.push(labels, Sample::Histogram(buckets.to_vec(), sum, count));
+    }
+
+    fn push(&mut self, labels: &[(&str, &str)], sample: Sample) {
+        let labels = labels.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect();
+        self.series.push(Series { labels: labels, sample: sample });
+    }
+
+    fn encode(&self, out: &mut String) -> Result<(), PromError> {
+        let name = &self.name[..];
+        if !valid_name(name, true) {
+            return Err(PromError::InvalidMetricName(name.to_string()));
+        }
+        let mut series = Vec::with_capacity(self.series.len());
+        for s in &self.series {
+            let mut labels = s.labels.clone();
+            labels.sort();
+            for (i, (label, _)) in labels.iter().enumerate() {
+                if !valid_name(label, false) || label.starts_with("__") ||
+                   self.kind == Type::Histogram && label == "le" {
+                    return Err(PromError::InvalidLabelName(name.to_string(), label.clone()));
+                }
+                if i > 0 && labels[i - 1].0 == *label {
+                    return Err(PromError::DuplicateLabel(name.to_string(), label.clone()));
+                }
+            }
+            match (self.kind, &s.sample) {
+                (Type::Histogram, &Sample::Histogram(..)) => {}
+                (Type::Histogram, _) | (_, &Sample::Histogram(..)) => {
+                    return Err(PromError::TypeMismatch(name.to_string()))
+                }
+                _ => {}
+            }
+            series.push((labels, &s.sample));
+        }
+        series.sort_by(|a, b| a.0.cmp(&b.0));
+        if series.windows(2).any(|w| w[0].0 == w[1].0) {
+            return Err(PromError::DuplicateSeries(name.to_string()));
+        }
+
+        if let Some(ref help) = self.help {
+            out.push_str(&format!("# HELP {} {}\n",
+                                  name, help.replace('\\', "\\\\").replace('\n', "\\n")));
+        }
+        out.push_str(&format!("# TYPE {} {}\n", name, self.kind.name()));
+        for (labels, sample) in series {
+            match *sample {
+                Sample::Value(v) => push_sample(out, name, "", &labels, None, v),
+                Sample::Histogram(ref buckets, sum, count) => {
+                    let mut buckets = buckets.clone();
+                    if buckets.iter().any(|b| b.0.is_nan()) {
+                        return Err(PromError::InvalidBuckets(name.to_string()));
+                    }
+                    buckets.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+                    if buckets.last().map_or(true, |b| b.0 != f64::INFINITY) {
+                        buckets.push((f64::INFINITY, count));
+                    }
+                    let cumulative = buckets.windows(2).all(|w| w[0].1 <= w[1].1);
+                    if !cumulative || buckets[buckets.len() - 1].1 != count {
+                        return Err(PromError::InvalidBuckets(name.to_string()));
+                    }
+                    for &(le, n) in &buckets {
+                        push_sample(out, name, "_bucket", &labels, Some(le), n as f64);
+                    }
+                    push_sample(out, name, "_sum", &labels, None, sum);
+                    push_sample(out, name, "_count", &labels, None, count as f64);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Encodes the families `families` in the text exposition format.
+pub fn encode(families: &[Family]) -> Result<String, PromError> {
+    let mut families: Vec<_> = families.iter().collect();
+    families.sort_by(|a, b| a.name.cmp(&b.name));
+    if let Some(w) = families.windows(2).find(|w| w[0].name == w[1].name) {
+        return Err(PromError::DuplicateFamily(w[0].name.clone()));
+    }
+    let mut out = String::new();
+    for family in families {
+        try!(family.encode(&mut out));
+    }
+    Ok(out)
+}
+
+// Whether `name` is a valid metric name, or label name, which cannot hold
+// `:`.
+fn valid_name(name: &str, metric: bool) -> bool {
+    name.chars().enumerate().all(|(i, c)| {
+        c.is_ascii_alphabetic() || c == '_' || c == ':' && metric || i > 0 && c.is_ascii_digit()
+    }) && !name.is_empty()
+}
+
+fn push_sample(out: &mut String, name: &str, suffix: &str, labels: &[(String, String)],
+               le: Option<f64>, value: f64) {
+    out.push_str(name);
+    out.push_str(suffix);
+    let le = le.map(|le| ("le".to_string(), float(le)));
+    for (i, (label, value)) in labels.iter().chain(le.as_ref()).enumerate() {
+        out.push(if i == 0 { '{' } else { ',' });
+        out.push_str(label);
+        out.push_str("=\"");
+        for c in value.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '"' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+    if !labels.is_empty() || le.is_some() {
+        out.push('}');
+    }
+    out.push(' ');
+    out.push_str(&float(value));
+    out.push('\n');
+}
+
+// Formats `v` as the format reads floats.
+fn float(v: f64) -> String {
+    if v.is_nan() {
+        "NaN".to_string()
+    } else if v == f64::INFINITY {
+        "+Inf".to_string()
+    } else if v == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+    use promtext::{self, Family, PromError, Type};
+
+    #[test]
+    fn test_encode() {
+        let mut gauge = Family::new("temp:celsius", Type::Gauge);
+        gauge.set_help("Line one\nback\\slash");
+        gauge.add(&[("room", "a \"b\"\n\\")], -1.5);
+        gauge.add(&[], f64::NAN);
+        let mut untyped = Family::new("x", Type::Untyped);
+        untyped.add(&[("b", "1"), ("a", "2")], f64::NEG_INFINITY);
+        let mut hist = Family::new("h", Type::Histogram);
+        hist.add_histogram(&[("z", "1")], &[(f64::INFINITY, 4), (1.0, 1), (2.5, 3)], 6.0, 4);
+        assert_eq!(promtext::encode(&[untyped, gauge, hist]).unwrap(), "\
+# TYPE h histogram
+h_bucket{z=\"1\",le=\"1\"} 1
+h_bucket{z=\"1\",le=\"2.5\"} 3
+h_bucket{z=\"1\",le=\"+Inf\"} 4
+h_sum{z=\"1\"} 6
+h_count{z=\"1\"} 4
+# HELP temp:celsius Line one\\nback\\\\slash
+# TYPE temp:celsius gauge
+temp:celsius NaN
+temp:celsius{room=\"a \\\"b\\\"\\n\\\\\"} -1.5
+# TYPE x untyped
+x{a=\"2\",b=\"1\"} -Inf
+");
+        assert_eq!(promtext::encode(&[]).unwrap(), "");
+    }
+
+    #[test]
+    fn test_errors() {
+        fn encode(name: &str, kind: Type, f: &Fn(&mut Family)) -> Result<String, PromError> {
+            let mut family = Family::new(name, kind);
+            f(&mut family);
+            promtext::encode(&[family])
+        }
+        let err = |e: fn(String) -> PromError| Err(e("m".to_string()));
+        let label = |e: fn(String, String) -> PromError, l: &str| {
+            Err(e("m".to_string(), l.to_string()))
+        };
+
+        assert_eq!(encode("1m", Type::Gauge, &|_| {}),
+                   Err(PromError::InvalidMetricName("1m".into())));
+        assert_eq!(encode("", Type::Gauge, &|_| {}), Err(PromError::InvalidMetricName("".into())));
+        assert_eq!(encode("m", Type::Gauge, &|f| f.add(&[("a:b", "")], 0.0)),
+                   label(PromError::InvalidLabelName, "a:b"));
+        assert_eq!(encode("m", Type::Gauge, &|f| f.add(&[("__name__", "")], 0.0)),
+                   label(PromError::InvalidLabelName, "__name__"));
+        assert_eq!(encode("m", Type::Histogram, &|f| f.add_histogram(&[("le", "")], &[], 0.0, 0)),
+                   label(PromError::InvalidLabelName, "le"));
+        assert_eq!(encode("m", Type::Gauge, &|f| f.add(&[("a", "1"), ("a", "2")], 0.0)),
+                   label(PromError::DuplicateLabel, "a"));
+        assert_eq!(encode("m", Type::Gauge, &|f| {
+                       f.add(&[("a", "1"), ("b", "2")], 0.0);
+                       f.add(&[("b", "2"), ("a", "1")], 1.0);
+                   }),
+                   err(PromError::DuplicateSeries));
+        assert_eq!(encode("m", Type::Histogram, &|f| f.add(&[], 0.0)),
+                   err(PromError::TypeMismatch));
+        assert_eq!(encode("m", Type::Counter, &|f| f.add_histogram(&[], &[], 0.0, 0)),
+                   err(PromError::TypeMismatch));
+        for buckets in [&[(1.0, 2), (2.0, 1)][..], &[(f64::NAN, 0)], &[(1.0, 3)],
+                        &[(f64::INFINITY, 1)]].iter() {
+            assert_eq!(encode("m", Type::Histogram, &|f| f.add_histogram(&[], buckets, 0.0, 2)),
+                       err(PromError::InvalidBuckets));
+        }
+        assert_eq!(promtext::encode(&[Family::new("m", Type::Gauge),
+                                      Family::new("m", Type::Counter)]),
+                   err(PromError::DuplicateFamily));
+    }
+}
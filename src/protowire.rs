@@ -0,0 +1,365 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Schema-less protocol buffers wire format
+//!
+//! `decode` reads a serialized message into a tree of `Field`s holding
+//! only what the wire format itself says: field numbers, wire types and
+//! raw values. Without a `.proto` schema a length-delimited value may be a
+//! string, bytes, a packed array or a nested message; `Value::as_message`
+//! tries the latter. `encode` writes a tree back out, and the `ToJson`
+//! impls give a readable dump for debugging.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::protowire::{self, Field, Value};
+//!
+//! fn main() {
+//!     // Field 1 = 150, field 2 = "hi"
+//!     let msg = b"\x08\x96\x01\x12\x02hi";
+//!     let fields = protowire::decode(msg).unwrap();
+//!     assert_eq!(fields, [Field { number: 1, value: Value::Varint(150) },
+//!                         Field { number: 2, value: Value::Bytes(b"hi".to_vec()) }]);
+//!     assert_eq!(protowire::encode(&fields), msg);
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+
+use base64::{ToBase64, STANDARD};
+use json::{Json, ToJson};
+
+/// The largest valid field number.
+pub const MAX_FIELD_NUMBER: u32 = (1 << 29) - 1;
+
+/// How deeply `decode` lets groups nest.
+pub const MAX_DEPTH: usize = 100;
+
+/// A field of a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    /// The field number, from 1 to `MAX_FIELD_NUMBER`
+    pub number: u32,
+    /// The value, whose variant gives the wire type
+    pub value: Value,
+}
+
+/// A raw field value, one variant per wire type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    /// Wire type 0: an `int32`, `int64`, `uint32`, `uint64`, `sint32`,
+    /// `sint64`, `bool` or `enum`
+    Varint(u64),
+    /// Wire type 1: a `fixed64`, `sfixed64` or `double`
+    Fixed64(u64),
+    /// Wire type 2: a string, bytes, nested message or packed array
+    Bytes(Vec<u8>),
+    /// Wire types 3 and 4: the fields of a (deprecated) group
+    Group(Vec<Field>),
+    /// Wire type 5: a `fixed32`, `sfixed32` or `float`
+    Fixed32(u32),
+}
+
+impl Value {
+    /// Returns the wire type of this value; 3 (start group) for a group.
+    pub fn wire_type(&self) -> u8 {
+        match *self {
+            Value::Varint(_) => 0,
+            Value::Fixed64(_) => 1,
+            Value::Bytes(_) => 2,
+            Value::Group(_) => 3,
+            Value::Fixed32(_) => 5,
+        }
+    }
+
+    /// Decodes a length-delimited value as a nested message, returning None
+    /// if it is not one or does not parse as one. Strings and bytes may
+    /// happen to parse as well.
+    pub fn as_message(&self) -> Option<Vec<Field>> {
+        match *self {
+            Value::Bytes(ref bytes) => decode(bytes).ok(),
+            _ => None,
+        }
+    }
+
+    /// Decodes a `sint32` or `sint64` varint, undoing the zigzag encoding.
+    pub fn as_sint(&self) -> Option<i64> {
+        match *self {
+            Value::Varint(v) => Some((v >> 1) as i64 ^ -((v & 1) as i64)),
+            _ => None,
+        }
+    }
+}
+
+/// Errors that can occur when decoding the wire format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WireError {
+    /// The input ended in the middle of a field
+    Truncated,
+    /// A varint was longer than 10 bytes
+    InvalidVarint,
+    /// A wire type of 6 or 7 was found at the given offset
+    InvalidWireType(u8, usize),
+    /// A field number of 0 or above `MAX_FIELD_NUMBER` was found at the
+    /// given offset
+    InvalidFieldNumber(usize),
+    /// An end group tag did not match the open group, at the given offset
+    UnexpectedEndGroup(usize),
+    /// The input ended inside a group
+    UnterminatedGroup,
+    /// Groups were nested more than `MAX_DEPTH` deep
+    RecursionLimitExceeded,
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WireError::Truncated => write!(f, "truncated message"),
+            WireError::InvalidVarint => write!(f, "varint longer than 10 bytes"),
+            WireError::InvalidWireType(t, pos) => {
+                write!(f, "invalid wire type {} at byte {}", t, pos)
+            }
+            WireError::InvalidFieldNumber(pos) => write!(f, "invalid field number at byte {}", pos),
+            WireError::UnexpectedEndGroup(pos) => write!(f, "unexpected end group at byte {}", pos),
+            WireError::UnterminatedGroup => write!(f, "unterminated group"),
+            WireError::RecursionLimitExceeded => {
+                write!(f, "groups nested more than {} deep", MAX_DEPTH)
+            }
+        }
+    }
+}
+
+impl error::Error for WireError {
+    fn description(&self) -> &str { "invalid protobuf wire format" }
+}
+
+/// Decodes the fields of a serialized message, in the order they appear.
+pub fn decode(bytes: &[u8]) -> Result<Vec<Field>, WireError> {
+    let mut reader = Reader { bytes: bytes, pos: 0 };
+    let (fields, end_group) = try!(reader.fields(None, 0));
+    debug_assert!(end_group.is_none());
+    Ok(fields)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    // Reads fields until the end of the input or the end of the group
+    // `group`.
+    fn fields(&mut self, group: Option<u32>, depth: usize)
+              -> Result<(Vec<Field>, Option<u32>), WireError> {
+        let mut fields = Vec::new();
+        while self.pos < self.bytes.len() {
+            let start = self.pos;
+            let tag = try!(self.varint());
+            let wire_type = (tag & 7) as u8;
+            let number = tag >> 3;
+            if number == 0 || number > MAX_FIELD_NUMBER as u64 {
+                return Err(WireError::InvalidFieldNumber(start));
+            }
+            let number = number as u32;
+            let value = match wire_type {
+                0 => Value::Varint(try!(self.varint())),
+                1 => Value::Fixed64(try!(self.fixed(8))),
+                2 => {
+                    let len = try!(self.varint());
+                    if len > (self.bytes.len() - self.pos) as u64 {
+                        return Err(WireError::Truncated);
+                    }
+                    let end = self.pos + len as usize;
+                    let bytes = self.bytes[self.pos..end].to_vec();
+                    self.pos = end;
+                    Value::Bytes(bytes)
+                }
+                3 => {
+                    if depth == MAX_DEPTH {
+                        return Err(WireError::RecursionLimitExceeded);
+                    }
+                    match try!(self.fields(Some(number), depth + 1)) {
+                        (fields, Some(_)) => Value::Group(fields),
+                        (_, None) => return Err(WireError::UnterminatedGroup),
+                    }
+                }
+                4 => {
+                    if group != Some(number) {
+                        return Err(WireError::UnexpectedEndGroup(start));
+                    }
+                    return Ok((fields, group));
+                }
+                5 => Value::Fixed32(try!(self.fixed(4)) as u32),
+                t => return Err(WireError::InvalidWireType(t, start)),
+            };
+            fields.push(Field { number: number, value: value });
+        }
+        Ok((fields, None))
+    }
+
+    fn varint(&mut self) -> Result<u64, WireError> {
+        let mut value = 0;
+        for i in 0..10 {
+            let b = match self.bytes.get(self.pos) {
+                Some(&b) => b,
+                None => return Err(WireError::Truncated),
+            };
+            self.pos += 1;
+            value |= ((b & 0x7f) as u64) << (7 * i);
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(WireError::InvalidVarint)
+    }
+
+    // Reads a little-endian integer of `n` bytes.
+    fn fixed(&mut self, n: usize) -> Result<u64, WireError> {
+        if self.bytes.len() - self.pos < n {
+            return Err(WireError::Truncated);
+        }
+        let mut value = 0;
+        for (i, &b) in self.bytes[self.pos..self.pos + n].iter().enumerate() {
+            value |= (b as u64) << (8 * i);
+        }
+        self.pos += n;
+        Ok(value)
+    }
+}
+
+/// Encodes `fields` as a serialized message.
+///
+/// # Panics
+///
+/// Panics if a field number is 0 or above `MAX_FIELD_NUMBER`.
+pub fn encode(fields: &[Field]) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_into(fields, &mut out);
+    out
+}
+
+fn encode_into(fields: &[Field], out: &mut Vec<u8>) {
+    for field in fields {
+        assert!(field.number != 0 && field.number <= MAX_FIELD_NUMBER,
+                "invalid field number {}", field.number);
+        let tag = (field.number as u64) << 3;
+        write_varint(tag | field.value.wire_type() as u64, out);
+        match field.value {
+            Value::Varint(v) => write_varint(v, out),
+            Value::Fixed64(v) => write_fixed(v, 8, out),
+            Value::Bytes(ref bytes) => {
+                write_varint(bytes.len() as u64, out);
+                out.extend_from_slice(bytes);
+            }
+            Value::Group(ref fields) => {
+                encode_into(fields, out);
+                write_varint(tag | 4, out);
+            }
+            Value::Fixed32(v) => write_fixed(v as u64, 4, out),
+        }
+    }
+}
+
+fn write_varint(mut v: u64, out: &mut Vec<u8>) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn write_fixed(v: u64, n: usize, out: &mut Vec<u8>) {
+    for i in 0..n {
+        out.push((v >> (8 * i)) as u8);
+    }
+}
+
+/// A field is shown as an object with `number`, `wire_type` and `value`
+/// members.
+impl ToJson for Field {
+    fn to_json(&self) -> Json {
+        let mut obj = ::std::collections::BTreeMap::new();
+        obj.insert("number".to_string(), self.number.to_json());
+        obj.insert("wire_type".to_string(), self.value.wire_type().to_json());
+        obj.insert("value".to_string(), self.value.to_json());
+        Json::Object(obj)
+    }
+}
+
+/// Numbers are shown unsigned, bytes as a base64 string and a group as the
+/// array of its fields.
+impl ToJson for Value {
+    fn to_json(&self) -> Json {
+        match *self {
+            Value::Varint(v) | Value::Fixed64(v) => Json::U64(v),
+            Value::Bytes(ref bytes) => Json::from(bytes.to_base64(STANDARD)),
+            Value::Group(ref fields) => fields.to_json(),
+            Value::Fixed32(v) => Json::U64(v as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::{Json, ToJson};
+    use protowire::{decode, encode, Field, Value, WireError, MAX_DEPTH};
+
+    fn field(number: u32, value: Value) -> Field {
+        Field { number: number, value: value }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let fields = vec![
+            field(1, Value::Varint(u64::MAX)),
+            field(2, Value::Fixed64(0x0102030405060708)),
+            field(3, Value::Bytes(encode(&[field(1, Value::Varint(3))]))),
+            field(4, Value::Group(vec![field(5, Value::Fixed32(7)),
+                                       field(4, Value::Group(vec![]))])),
+            field((1 << 29) - 1, Value::Bytes(vec![])),
+        ];
+        let bytes = encode(&fields);
+        assert_eq!(decode(&bytes).unwrap(), fields);
+        assert_eq!(fields[2].value.as_message().unwrap(), [field(1, Value::Varint(3))]);
+        assert_eq!(Value::Varint(3).as_sint(), Some(-2));
+        assert_eq!(Value::Varint(4).as_sint(), Some(2));
+        assert_eq!(Value::Varint(u64::MAX).as_sint(), Some(i64::MIN));
+        assert_eq!(decode(b"").unwrap(), []);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(decode(b"\x08"), Err(WireError::Truncated));
+        assert_eq!(decode(b"\x08\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01"),
+                   Err(WireError::InvalidVarint));
+        assert_eq!(decode(b"\x12\x03ab"), Err(WireError::Truncated));
+        assert_eq!(decode(b"\x0d\x01\x02\x03"), Err(WireError::Truncated));
+        assert_eq!(decode(b"\x08\x00\x0e"), Err(WireError::InvalidWireType(6, 2)));
+        assert_eq!(decode(b"\x00"), Err(WireError::InvalidFieldNumber(0)));
+        assert_eq!(decode(b"\x0c"), Err(WireError::UnexpectedEndGroup(0)));
+        assert_eq!(decode(b"\x0b\x14"), Err(WireError::UnexpectedEndGroup(1)));
+        assert_eq!(decode(b"\x0b\x08\x01"), Err(WireError::UnterminatedGroup));
+        let nested = vec![0x0b; MAX_DEPTH + 1];
+        assert_eq!(decode(&nested), Err(WireError::RecursionLimitExceeded));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let fields = decode(b"\x08\x96\x01\x12\x02hi\x1b\x25\x01\x00\x00\x00\x1c").unwrap();
+        assert_eq!(fields.to_json(), Json::from_str(
+            "[{\"number\": 1, \"wire_type\": 0, \"value\": 150},
+              {\"number\": 2, \"wire_type\": 2, \"value\": \"aGk=\"},
+              {\"number\": 3, \"wire_type\": 3, \"value\": [
+                  {\"number\": 4, \"wire_type\": 5, \"value\": 1}]}]").unwrap());
+    }
+}
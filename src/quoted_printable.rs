@@ -0,0 +1,325 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Quoted-printable encoding (RFC 2045 section 6.7)
+//!
+//! `QuotedPrintableCodec` is a streaming `codec::Codec`. Encoding keeps
+//! printable ASCII other than `=` as it is and writes every other byte as
+//! `=` and two upper case hex digits. A CRLF in the input is a line break
+//! and is kept; a lone CR or LF is encoded. Spaces and tabs are encoded at
+//! the end of a line, and lines longer than 76 characters are split with
+//! soft line breaks (`=` at the end of the line).
+//!
+//! Decoding removes soft line breaks, which may also end in a bare LF, and
+//! the spaces and tabs at the end of each line, which transports may add.
+//! Escapes may use either case.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use std::io::{Read, Write};
+//! use rustc_serialize::codec::{CodecReader, CodecWriter};
+//! use rustc_serialize::quoted_printable::QuotedPrintableCodec;
+//!
+//! fn main() {
+//!     let mut writer = CodecWriter::new(Vec::new(), QuotedPrintableCodec::new());
+//!     writer.write_all("caf\u{e9} = 1 \r\n".as_bytes()).unwrap();
+//!     let encoded = writer.finish().unwrap();
+//!     assert_eq!(encoded, b"caf=C3=A9 =3D 1=20\r\n");
+//!
+//!     let mut decoded = Vec::new();
+//!     let mut reader = CodecReader::new(&encoded[..], QuotedPrintableCodec::new());
+//!     reader.read_to_end(&mut decoded).unwrap();
+//!     assert_eq!(decoded, "caf\u{e9} = 1 \r\n".as_bytes());
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+
+use codec::Codec;
+
+static CHARS: &'static [u8] = b"0123456789ABCDEF";
+
+// The longest encoded line, not counting the `=` of a soft line break.
+const MAX_LINE_LEN: usize = 75;
+
+/// Errors that can occur when decoding quoted-printable text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuotedPrintableError {
+    /// The `=` at the given byte offset was not followed by two hex digits
+    /// or a line break
+    InvalidEscape(usize),
+    /// The input ended within an escape
+    UnexpectedEnd,
+}
+
+impl fmt::Display for QuotedPrintableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QuotedPrintableError::InvalidEscape(pos) => {
+                write!(f, "invalid escape at position {}", pos)
+            }
+            QuotedPrintableError::UnexpectedEnd => write!(f, "unexpected end of input"),
+        }
+    }
+}
+
+impl error::Error for QuotedPrintableError {
+    fn description(&self) -> &str {
+        match *self {
+            QuotedPrintableError::InvalidEscape(_) => "invalid escape",
+            QuotedPrintableError::UnexpectedEnd => "unexpected end of input",
+        }
+    }
+}
+
+// Where the decoder is within an escape; each state but `Text` holds the
+// offset of the `=`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Text,
+    Equals(usize),
+    // Spaces or tabs after `=`, which may only be followed by a line break.
+    EqualsSpace(usize),
+    // `=` and then CR, to be followed by LF.
+    SoftBreak(usize),
+    // `=` and then one hex digit, whose value is given.
+    Hex(usize, u8),
+}
+
+/// A streaming quoted-printable `Codec`, for use with `codec::CodecReader`
+/// and `codec::CodecWriter`.
+#[derive(Clone, Debug)]
+pub struct QuotedPrintableCodec {
+    // Encoding: the length of the current line, and a space, tab or CR
+    // whose encoding depends on the byte after it.
+    line_len: usize,
+    pending: Option<u8>,
+    // Decoding: the spaces and tabs read since the last other byte, which
+    // are dropped at the end of a line.
+    space: Vec<u8>,
+    state: State,
+    pos: usize,
+}
+
+impl QuotedPrintableCodec {
+    /// Creates a new quoted-printable codec.
+    pub fn new() -> QuotedPrintableCodec {
+        QuotedPrintableCodec {
+            line_len: 0,
+            pending: None,
+            space: Vec::new(),
+            state: State::Text,
+            pos: 0,
+        }
+    }
+
+    fn push_literal(&mut self, byte: u8, output: &mut Vec<u8>) {
+        self.soft_break(1, output);
+        output.push(byte);
+        self.line_len += 1;
+    }
+
+    fn push_escaped(&mut self, byte: u8, output: &mut Vec<u8>) {
+        self.soft_break(3, output);
+        output.push(b'=');
+        output.push(CHARS[(byte >> 4) as usize]);
+        output.push(CHARS[(byte & 0xf) as usize]);
+        self.line_len += 3;
+    }
+
+    // Ends the line with a soft line break unless `len` more characters
+    // fit on it.
+    fn soft_break(&mut self, len: usize, output: &mut Vec<u8>) {
+        if self.line_len + len > MAX_LINE_LEN {
+            output.extend_from_slice(b"=\r\n");
+            self.line_len = 0;
+        }
+    }
+}
+
+impl Default for QuotedPrintableCodec {
+    fn default() -> QuotedPrintableCodec {
+        QuotedPrintableCodec::new()
+    }
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'...b'9' => Some(byte - b'0'),
+        b'A'...b'F' => Some(byte - b'A' + 10),
+        b'a'...b'f' => Some(byte - b'a' + 10),
+        _ => None,
+    }
+}
+
+impl Codec for QuotedPrintableCodec {
+    type Error = QuotedPrintableError;
+
+    fn encode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), QuotedPrintableError> {
+        for &byte in input.iter() {
+            match self.pending.take() {
+                Some(b'\r') if byte == b'\n' => {
+                    output.extend_from_slice(b"\r\n");
+                    self.line_len = 0;
+                    continue;
+                }
+                // Whitespace before a CR may end a line.
+                Some(p) if p == b'\r' || byte == b'\r' => self.push_escaped(p, output),
+                Some(p) => self.push_literal(p, output),
+                None => {}
+            }
+            match byte {
+                b' ' | b'\t' | b'\r' => self.pending = Some(byte),
+                b'!'...b'<' | b'>'...b'~' => self.push_literal(byte, output),
+                _ => self.push_escaped(byte, output),
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_chunk(&mut self, input: &[u8], output: &mut Vec<u8>)
+                    -> Result<(), QuotedPrintableError> {
+        for (idx, &byte) in input.iter().enumerate() {
+            self.state = match self.state {
+                State::Text => match byte {
+                    b' ' | b'\t' => {
+                        self.space.push(byte);
+                        State::Text
+                    }
+                    b'\r' | b'\n' => {
+                        self.space.clear();
+                        output.push(byte);
+                        State::Text
+                    }
+                    b'=' => {
+                        output.extend_from_slice(&self.space);
+                        self.space.clear();
+                        State::Equals(self.pos + idx)
+                    }
+                    _ => {
+                        output.extend_from_slice(&self.space);
+                        self.space.clear();
+                        output.push(byte);
+                        State::Text
+                    }
+                },
+                State::Equals(start) | State::EqualsSpace(start) if byte == b'\r' => {
+                    State::SoftBreak(start)
+                }
+                State::Equals(_) | State::EqualsSpace(_) if byte == b'\n' => State::Text,
+                State::Equals(start) | State::EqualsSpace(start)
+                    if byte == b' ' || byte == b'\t' => State::EqualsSpace(start),
+                State::Equals(start) => match hex_value(byte) {
+                    Some(high) => State::Hex(start, high),
+                    None => return Err(QuotedPrintableError::InvalidEscape(start)),
+                },
+                State::Hex(start, high) => match hex_value(byte) {
+                    Some(low) => {
+                        output.push(high << 4 | low);
+                        State::Text
+                    }
+                    None => return Err(QuotedPrintableError::InvalidEscape(start)),
+                },
+                State::SoftBreak(_) if byte == b'\n' => State::Text,
+                State::EqualsSpace(start) | State::SoftBreak(start) => {
+                    return Err(QuotedPrintableError::InvalidEscape(start))
+                }
+            };
+        }
+        self.pos += input.len();
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), QuotedPrintableError> {
+        // Encoding: a space or tab ending the input ends its last line.
+        if let Some(p) = self.pending.take() {
+            self.push_escaped(p, output);
+        }
+
+        // Decoding: an `=` ending the input is a soft line break, and any
+        // spaces after it are dropped.
+        self.space.clear();
+        match self.state {
+            State::Text | State::Equals(_) | State::EqualsSpace(_) => {
+                self.state = State::Text;
+                Ok(())
+            }
+            State::SoftBreak(_) | State::Hex(..) => Err(QuotedPrintableError::UnexpectedEnd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+    use codec::{CodecReader, CodecWriter};
+    use quoted_printable::{QuotedPrintableCodec, QuotedPrintableError};
+
+    fn encode(data: &[u8], piece: usize) -> String {
+        let mut w = CodecWriter::new(Vec::new(), QuotedPrintableCodec::new());
+        for chunk in data.chunks(piece) {
+            w.write_all(chunk).unwrap();
+        }
+        String::from_utf8(w.finish().unwrap()).unwrap()
+    }
+
+    fn decode(text: &[u8]) -> Result<Vec<u8>, QuotedPrintableError> {
+        let mut decoded = Vec::new();
+        let mut r = CodecReader::new(text, QuotedPrintableCodec::new());
+        match r.read_to_end(&mut decoded) {
+            Ok(_) => Ok(decoded),
+            Err(e) => Err(*e.get_ref().unwrap().downcast_ref::<QuotedPrintableError>().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        for piece in 1..4 {
+            assert_eq!(encode(b"a=b \tc \r\nd\t\r\n \r", piece),
+                       "a=3Db \tc=20\r\nd=09\r\n=20=0D");
+            assert_eq!(encode(b"a\nb\rc \r", piece), "a=0Ab=0Dc=20=0D");
+            assert_eq!(encode(b"end ", piece), "end=20");
+        }
+        let long = [b'x'; 160];
+        assert_eq!(encode(&long, 7),
+                   format!("{}=\r\n{}=\r\n{}", "x".repeat(75), "x".repeat(75), "x".repeat(10)));
+        let mut escaped = vec![b'x'; 73];
+        escaped.push(0xff);
+        assert_eq!(encode(&escaped, 5), format!("{}=\r\n=FF", "x".repeat(73)));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut data: Vec<u8> = (0..256).map(|i| i as u8).collect();
+        data.extend_from_slice(b"line \r\n\tnext\t\r\n\r\n  ");
+        for piece in 1..5 {
+            let encoded = encode(&data, piece);
+            assert!(encoded.split("\r\n").all(|line| line.len() <= 76), "{}", encoded);
+            assert_eq!(decode(encoded.as_bytes()).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"a=3db=\r\nc=  \r\nd=\ne").unwrap(), b"a=bcde");
+        assert_eq!(decode(b"trailing  \r\nspace\t\nend  ").unwrap(), b"trailing\r\nspace\nend");
+        assert_eq!(decode(b"soft=").unwrap(), b"soft");
+        assert_eq!(decode(b"a=4"), Err(QuotedPrintableError::UnexpectedEnd));
+        assert_eq!(decode(b"a=\r"), Err(QuotedPrintableError::UnexpectedEnd));
+        assert_eq!(decode(b"ab=4x"), Err(QuotedPrintableError::InvalidEscape(2)));
+        assert_eq!(decode(b"a==41"), Err(QuotedPrintableError::InvalidEscape(1)));
+        assert_eq!(decode(b"a= 41"), Err(QuotedPrintableError::InvalidEscape(1)));
+        assert_eq!(decode(b"a=\rb"), Err(QuotedPrintableError::InvalidEscape(1)));
+    }
+}
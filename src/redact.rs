@@ -0,0 +1,690 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Scrubbing sensitive values while encoding
+//!
+//! A `Redaction` names the values to keep out of encoded output, such as
+//! logs: struct fields and map entries with given names wherever they
+//! appear, and values at given JSON Pointers (RFC 6901). `Redaction::encode`
+//! encodes any `Encodable` value into any `Encoder`, replacing those values
+//! with a string, `"[REDACTED]"` by default, or with a hash of them. The
+//! value being replaced is never passed to the encoder.
+//!
+//! Paths follow the layout the `json` encoder gives values: struct fields
+//! and map values are named by their field name or key, and the elements
+//! of sequences and tuples, and the arguments of enum variants, by their
+//! index. Derived `RustcEncodable` impls emit the fields of struct-like
+//! enum variants as positional arguments, without their names, so those
+//! fields are only matched by pointer; matching them by name needs a
+//! hand-written impl that calls `emit_enum_struct_variant_field`.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::{Encodable, Encoder};
+//! use rustc_serialize::redact::Redaction;
+//!
+//! struct Login {
+//!     user: String,
+//!     password: String,
+//!     headers: Vec<(String, String)>,
+//! }
+//!
+//! impl Encodable for Login {
+//!     fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+//!         s.emit_struct("Login", 3, |s| {
+//!             try!(s.emit_struct_field("user", 0, |s| self.user.encode(s)));
+//!             try!(s.emit_struct_field("password", 1, |s| self.password.encode(s)));
+//!             s.emit_struct_field("headers", 2, |s| self.headers.encode(s))
+//!         })
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let login = Login {
+//!         user: "ada".to_string(),
+//!         password: "hunter2".to_string(),
+//!         headers: vec![("Cookie".to_string(), "id=1".to_string())],
+//!     };
+//!     let mut redaction = Redaction::new(&["password"]);
+//!     redaction.pointers.insert("/headers/0/1".to_string());
+//!     assert_eq!(redaction.to_json(&login).unwrap(),
+//!                r#"{"user":"ada","password":"[REDACTED]","headers":[["Cookie","[REDACTED]"]]}"#);
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::mem;
+use std::path::Path;
+
+use json;
+use serialize::{encode_native_path, Encodable, Encoder};
+use wtf8::Wtf8Buf;
+
+/// The default replacement for redacted values
+pub const REDACTED: &str = "[REDACTED]";
+
+/// What redacted values are replaced with
+#[derive(Clone, Debug)]
+pub enum Replacement {
+    /// The given string
+    Text(String),
+    /// The string the function returns for a canonical encoding of the
+    /// value, so that equal values can be matched up without being
+    /// revealed. The function should be a keyed hash, such as an HMAC.
+    Hash(fn(&[u8]) -> String),
+}
+
+/// The values to redact; see the module documentation.
+#[derive(Clone, Debug)]
+pub struct Redaction {
+    /// The names of struct fields and map keys whose values are redacted,
+    /// at any depth
+    pub fields: HashSet<String>,
+    /// The JSON Pointers of values which are redacted
+    pub pointers: HashSet<String>,
+    /// What redacted values are replaced with
+    pub replacement: Replacement,
+}
+
+impl Default for Redaction {
+    fn default() -> Redaction {
+        Redaction {
+            fields: HashSet::new(),
+            pointers: HashSet::new(),
+            replacement: Replacement::Text(REDACTED.to_string()),
+        }
+    }
+}
+
+impl Redaction {
+    /// Creates a redaction of the given fields and map keys, replacing
+    /// their values with `REDACTED`.
+    pub fn new(fields: &[&str]) -> Redaction {
+        Redaction {
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+            ..Redaction::default()
+        }
+    }
+
+    /// Encodes `value` into `e`, with redacted values replaced.
+    pub fn encode<T, E>(&self, value: &T, e: &mut E) -> Result<(), E::Error>
+        where T: Encodable + ?Sized, E: Encoder
+    {
+        let state = RefCell::new(State { path: Vec::new(), key: None, map_key: None, hash: None });
+        let root = self.pointers.contains("");
+        Redactor { inner: e, redaction: self, state: &state }.value(root, |r| value.encode(r))
+    }
+
+    /// Encodes `value` as JSON, with redacted values replaced.
+    pub fn to_json<T: Encodable + ?Sized>(&self, value: &T) -> json::EncodeResult<String> {
+        let mut s = String::new();
+        {
+            let mut encoder = json::Encoder::new(&mut s);
+            try!(self.encode(value, &mut encoder));
+        }
+        Ok(s)
+    }
+}
+
+struct State {
+    // The tokens of the path to the value being encoded.
+    path: Vec<String>,
+    // The text of the map key being encoded, if one is.
+    key: Option<String>,
+    // The last map key encoded, for its value.
+    map_key: Option<String>,
+    // The canonical encoding of the value being hashed, if one is. Nothing
+    // is passed to the inner encoder meanwhile.
+    hash: Option<String>,
+}
+
+// The `Encoder` handed to `Encodable::encode` by `Redaction::encode`.
+//
+// Like `Erased` in `dyn_encoder`, it is never exposed by value, so
+// `Encodable` implementations can only reach it through the `&mut` borrows
+// passed to them, for the duration of a single call.
+struct Redactor<'a, E: 'a> {
+    inner: &'a mut E,
+    redaction: &'a Redaction,
+    state: &'a RefCell<State>,
+}
+
+// Stretches the lifetime of the encoder passed to a nested callback to that
+// of the enclosing `Redactor`. The reference only lives for the duration of
+// the callback, because `Redactor` never escapes one, and the encoder behind
+// it is the one the enclosing `Redactor` points to (or one it handed out
+// itself), which outlives `'a`.
+unsafe fn rebind<'a, E>(e: &mut E) -> &'a mut E {
+    &mut *(e as *mut E)
+}
+
+// Adapts an `FnOnce(&mut Redactor)` into the `FnOnce(&mut E)` taken by the
+// inner encoder's compound methods.
+macro_rules! nested {
+    ($r:ident, $f:expr) => ({
+        let (redaction, state, f) = ($r.redaction, $r.state, $f);
+        move |e: &mut E| {
+            f(&mut Redactor { inner: unsafe { rebind(e) }, redaction: redaction, state: state })
+        }
+    })
+}
+
+macro_rules! scalar {
+    ($name:ident, $ty:ty) => (
+        fn $name(&mut self, v: $ty) -> Result<(), E::Error> {
+            if self.capturing() && self.scalar(&v.to_string(), false) {
+                return Ok(());
+            }
+            self.inner.$name(v)
+        }
+    )
+}
+
+impl<'a, E: Encoder> Redactor<'a, E> {
+    fn hashing(&self) -> bool {
+        self.state.borrow().hash.is_some()
+    }
+
+    fn hash(&self, s: &str) {
+        if let Some(ref mut hash) = self.state.borrow_mut().hash {
+            hash.push_str(s);
+        }
+    }
+
+    fn capturing(&self) -> bool {
+        let state = self.state.borrow();
+        state.key.is_some() || state.hash.is_some()
+    }
+
+    // Records the text of a scalar in the map key or hash being built,
+    // returning whether it is hashed, and so not to be encoded.
+    fn scalar(&self, text: &str, quoted: bool) -> bool {
+        let mut state = self.state.borrow_mut();
+        if let Some(ref mut key) = state.key {
+            key.push_str(text);
+        }
+        match state.hash {
+            Some(ref mut hash) if quoted => {
+                write!(hash, "{:?}", text).unwrap();
+                true
+            }
+            Some(ref mut hash) => {
+                hash.push_str(text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Appends `token` to the path, returning whether the value there is
+    // redacted. `named` is whether it is a field name or map key.
+    fn enter(&self, token: String, named: bool) -> bool {
+        let mut state = self.state.borrow_mut();
+        let redacted = named && self.redaction.fields.contains(&token);
+        state.path.push(token);
+        if redacted || self.redaction.pointers.is_empty() {
+            return redacted;
+        }
+        let mut pointer = String::new();
+        for token in &state.path {
+            pointer.push('/');
+            pointer.push_str(&token.replace("~", "~0").replace("/", "~1"));
+        }
+        self.redaction.pointers.contains(&pointer)
+    }
+
+    fn leave(&self) {
+        self.state.borrow_mut().path.pop();
+    }
+
+    // Encodes the value `f` encodes, or its replacement if `redacted`.
+    fn value<F>(&mut self, redacted: bool, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if !redacted {
+            return f(self);
+        }
+        match self.redaction.replacement {
+            Replacement::Text(ref text) => self.inner.emit_str(text),
+            Replacement::Hash(hash) => {
+                self.state.borrow_mut().hash = Some(String::new());
+                let result = f(self);
+                let encoded = self.state.borrow_mut().hash.take().unwrap();
+                try!(result);
+                self.inner.emit_str(&hash(encoded.as_bytes()))
+            }
+        }
+    }
+}
+
+// Encodes a member of a compound value at the path token `$token`, which
+// is a field name or map key if `$named`, through the inner encoder's
+// `$method`.
+macro_rules! member {
+    ($r:ident, $token:expr, $named:expr, $f:ident, $method:ident($($arg:expr),*)) => ({
+        let redacted = $r.enter($token, $named);
+        let (redaction, state) = ($r.redaction, $r.state);
+        let result = $r.inner.$method($($arg,)* move |e: &mut E| {
+            let inner = unsafe { rebind(e) };
+            Redactor { inner: inner, redaction: redaction, state: state }.value(redacted, $f)
+        });
+        $r.leave();
+        result
+    })
+}
+
+impl<'a, E: Encoder> Encoder for Redactor<'a, E> {
+    type Error = E::Error;
+
+    fn emit_nil(&mut self) -> Result<(), E::Error> {
+        if self.capturing() && self.scalar("null", false) {
+            return Ok(());
+        }
+        self.inner.emit_nil()
+    }
+    scalar!(emit_usize, usize);
+    scalar!(emit_u64, u64);
+    scalar!(emit_u32, u32);
+    scalar!(emit_u16, u16);
+    scalar!(emit_u8, u8);
+    scalar!(emit_isize, isize);
+    scalar!(emit_i64, i64);
+    scalar!(emit_i32, i32);
+    scalar!(emit_i16, i16);
+    scalar!(emit_i8, i8);
+    scalar!(emit_u128, u128);
+    scalar!(emit_i128, i128);
+    scalar!(emit_bool, bool);
+    scalar!(emit_f64, f64);
+    scalar!(emit_f32, f32);
+    fn emit_char(&mut self, v: char) -> Result<(), E::Error> {
+        if self.capturing() && self.scalar(v.encode_utf8(&mut [0; 4]), true) {
+            return Ok(());
+        }
+        self.inner.emit_char(v)
+    }
+    fn emit_str(&mut self, v: &str) -> Result<(), E::Error> {
+        if self.capturing() && self.scalar(v, true) {
+            return Ok(());
+        }
+        self.inner.emit_str(v)
+    }
+    fn emit_wtf8(&mut self, v: &Wtf8Buf) -> Result<(), E::Error> {
+        if self.capturing() && self.scalar(&v.to_string_lossy(), true) {
+            return Ok(());
+        }
+        self.inner.emit_wtf8(v)
+    }
+    fn emit_path(&mut self, v: &Path) -> Result<(), E::Error> {
+        // Hashed in the platform's own form, which loses nothing.
+        if self.hashing() {
+            return encode_native_path(v, self);
+        }
+        if self.capturing() {
+            self.scalar(&v.to_string_lossy(), true);
+        }
+        self.inner.emit_path(v)
+    }
+
+    fn emit_enum<F>(&mut self, name: &str, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            return f(self);
+        }
+        self.inner.emit_enum(name, nested!(self, f))
+    }
+    fn emit_enum_variant<F>(&mut self, v_name: &str, v_id: usize, len: usize, f: F)
+                            -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash(&format!("{:?}", v_name));
+            if len == 0 {
+                return f(self);
+            }
+            self.hash("(");
+            try!(f(self));
+            self.hash(")");
+            return Ok(());
+        }
+        self.inner.emit_enum_variant(v_name, v_id, len, nested!(self, f))
+    }
+    fn emit_enum_variant_arg<F>(&mut self, a_idx: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash(if a_idx > 0 { "," } else { "" });
+            return f(self);
+        }
+        member!(self, a_idx.to_string(), false, f, emit_enum_variant_arg(a_idx))
+    }
+    fn emit_enum_struct_variant<F>(&mut self, v_name: &str, v_id: usize, len: usize, f: F)
+                                   -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash(&format!("{:?}{{", v_name));
+            try!(f(self));
+            self.hash("}");
+            return Ok(());
+        }
+        self.inner.emit_enum_struct_variant(v_name, v_id, len, nested!(self, f))
+    }
+    fn emit_enum_struct_variant_field<F>(&mut self, f_name: &str, f_idx: usize, f: F)
+                                         -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash(&format!("{}{:?}:", if f_idx > 0 { "," } else { "" }, f_name));
+            return f(self);
+        }
+        member!(self, f_name.to_string(), true, f,
+                emit_enum_struct_variant_field(f_name, f_idx))
+    }
+
+    fn emit_struct<F>(&mut self, name: &str, len: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash("{");
+            try!(f(self));
+            self.hash("}");
+            return Ok(());
+        }
+        self.inner.emit_struct(name, len, nested!(self, f))
+    }
+    fn emit_struct_field<F>(&mut self, f_name: &str, f_idx: usize, f: F)
+                            -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash(&format!("{}{:?}:", if f_idx > 0 { "," } else { "" }, f_name));
+            return f(self);
+        }
+        member!(self, f_name.to_string(), true, f, emit_struct_field(f_name, f_idx))
+    }
+
+    fn emit_tuple<F>(&mut self, len: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash("[");
+            try!(f(self));
+            self.hash("]");
+            return Ok(());
+        }
+        self.inner.emit_tuple(len, nested!(self, f))
+    }
+    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash(if idx > 0 { "," } else { "" });
+            return f(self);
+        }
+        member!(self, idx.to_string(), false, f, emit_tuple_arg(idx))
+    }
+    fn emit_tuple_struct<F>(&mut self, name: &str, len: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash("[");
+            try!(f(self));
+            self.hash("]");
+            return Ok(());
+        }
+        self.inner.emit_tuple_struct(name, len, nested!(self, f))
+    }
+    fn emit_tuple_struct_arg<F>(&mut self, f_idx: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash(if f_idx > 0 { "," } else { "" });
+            return f(self);
+        }
+        member!(self, f_idx.to_string(), false, f, emit_tuple_struct_arg(f_idx))
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            return f(self);
+        }
+        self.inner.emit_option(nested!(self, f))
+    }
+    fn emit_option_none(&mut self) -> Result<(), E::Error> {
+        if self.hashing() {
+            self.hash("null");
+            return Ok(());
+        }
+        self.inner.emit_option_none()
+    }
+    fn emit_option_some<F>(&mut self, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            return f(self);
+        }
+        self.inner.emit_option_some(nested!(self, f))
+    }
+
+    fn emit_seq<F>(&mut self, len: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash("[");
+            try!(f(self));
+            self.hash("]");
+            return Ok(());
+        }
+        self.inner.emit_seq(len, nested!(self, f))
+    }
+    fn emit_seq_elt<F>(&mut self, idx: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash(if idx > 0 { "," } else { "" });
+            return f(self);
+        }
+        member!(self, idx.to_string(), false, f, emit_seq_elt(idx))
+    }
+
+    fn emit_map<F>(&mut self, len: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash("{");
+            try!(f(self));
+            self.hash("}");
+            return Ok(());
+        }
+        self.inner.emit_map(len, nested!(self, f))
+    }
+    fn emit_map_elt_key<F>(&mut self, idx: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash(if idx > 0 { "," } else { "" });
+            return f(self);
+        }
+        let outer = self.state.borrow_mut().key.replace(String::new());
+        let result = self.inner.emit_map_elt_key(idx, nested!(self, f));
+        let mut state = self.state.borrow_mut();
+        state.map_key = mem::replace(&mut state.key, outer);
+        result
+    }
+    fn emit_map_elt_val<F>(&mut self, idx: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash(":");
+            return f(self);
+        }
+        let key = self.state.borrow_mut().map_key.take().unwrap_or_default();
+        member!(self, key, true, f, emit_map_elt_val(idx))
+    }
+
+    fn emit_tagged<F>(&mut self, tag: u64, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        if self.hashing() {
+            self.hash(&format!("{}:", tag));
+            return f(self);
+        }
+        self.inner.emit_tagged(tag, nested!(self, f))
+    }
+
+    fn format(&self) -> Option<&'static str> {
+        self.inner.format()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use cbor;
+    use json;
+    use redact::{Redaction, Replacement};
+    use wtf8::Wtf8Buf;
+    use {Encodable, Encoder};
+
+    struct Account {
+        name: String,
+        token: Option<String>,
+        settings: BTreeMap<String, u32>,
+        sessions: Vec<Session>,
+    }
+
+    impl Encodable for Account {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_struct("Account", 4, |s| {
+                try!(s.emit_struct_field("name", 0, |s| self.name.encode(s)));
+                try!(s.emit_struct_field("token", 1, |s| self.token.encode(s)));
+                try!(s.emit_struct_field("settings", 2, |s| self.settings.encode(s)));
+                s.emit_struct_field("sessions", 3, |s| self.sessions.encode(s))
+            })
+        }
+    }
+
+    enum Session {
+        Anonymous,
+        User { id: u32, password: String },
+    }
+
+    impl Encodable for Session {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_enum("Session", |s| {
+                match *self {
+                    Session::Anonymous => s.emit_enum_variant("Anonymous", 0, 0, |_| Ok(())),
+                    Session::User { ref id, ref password } => {
+                        s.emit_enum_variant("User", 1, 2, |s| {
+                            try!(s.emit_enum_variant_arg(0, |s| id.encode(s)));
+                            s.emit_enum_variant_arg(1, |s| password.encode(s))
+                        })
+                    }
+                }
+            })
+        }
+    }
+
+    fn account() -> Account {
+        let mut settings = BTreeMap::new();
+        settings.insert("pin".to_string(), 1234);
+        settings.insert("volume".to_string(), 7);
+        Account {
+            name: "ada".to_string(),
+            token: Some("abc".to_string()),
+            settings: settings,
+            sessions: vec![Session::Anonymous,
+                           Session::User { id: 1, password: "hunter2".to_string() }],
+        }
+    }
+
+    #[test]
+    fn test_redaction() {
+        // `Session::User` encodes its fields as positional arguments, as a
+        // derived impl would, so `password` there is redacted by pointer
+        // rather than by name.
+        let mut redaction = Redaction::new(&["token", "password", "pin"]);
+        assert_eq!(redaction.to_json(&account()).unwrap(),
+                   "{\"name\":\"ada\",\"token\":\"[REDACTED]\",\
+                    \"settings\":{\"pin\":\"[REDACTED]\",\"volume\":7},\
+                    \"sessions\":[\"Anonymous\",{\"variant\":\"User\",\
+                    \"fields\":[1,\"hunter2\"]}]}");
+        redaction.pointers.insert("/sessions/1/1".to_string());
+        assert_eq!(redaction.to_json(&account()).unwrap(),
+                   "{\"name\":\"ada\",\"token\":\"[REDACTED]\",\
+                    \"settings\":{\"pin\":\"[REDACTED]\",\"volume\":7},\
+                    \"sessions\":[\"Anonymous\",{\"variant\":\"User\",\
+                    \"fields\":[1,\"[REDACTED]\"]}]}");
+
+        redaction.fields.clear();
+        redaction.pointers.clear();
+        redaction.pointers.insert("/sessions/1".to_string());
+        redaction.pointers.insert("/settings/volume".to_string());
+        redaction.replacement = Replacement::Text("***".to_string());
+        assert_eq!(redaction.to_json(&account()).unwrap(),
+                   "{\"name\":\"ada\",\"token\":\"abc\",\"settings\":{\"pin\":1234,\
+                    \"volume\":\"***\"},\"sessions\":[\"Anonymous\",\"***\"]}");
+
+        redaction.pointers.insert("".to_string());
+        assert_eq!(redaction.to_json(&account()).unwrap(), "\"***\"");
+
+        // Other formats are redacted the same way.
+        let mut encoder = cbor::Encoder::new();
+        Redaction::new(&["name"]).encode(&account(), &mut encoder).unwrap();
+        match encoder.into_value() {
+            cbor::Value::Map(ref entries) => {
+                assert_eq!(entries[0], (cbor::Value::Text("name".to_string()),
+                                        cbor::Value::Text("[REDACTED]".to_string())));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_path_strings() {
+        let path = Path::new("/tmp/a");
+        let mut out = String::new();
+        {
+            let mut encoder = json::Encoder::new(&mut out);
+            encoder.set_path_strings(true);
+            Redaction::new(&[]).encode(path, &mut encoder).unwrap();
+        }
+        assert_eq!(out, "\"/tmp/a\"");
+    }
+
+    #[test]
+    fn test_wtf8() {
+        let lone = Wtf8Buf::from_utf16(&[0x76, 0xDFFF]);
+        assert_eq!(Redaction::new(&[]).to_json(&lone).unwrap(), "\"v\\udfff\"");
+    }
+
+    #[test]
+    fn test_hash_replacement() {
+        fn show(encoded: &[u8]) -> String {
+            format!("<{}>", String::from_utf8(encoded.to_vec()).unwrap())
+        }
+        let mut redaction = Redaction::new(&["token", "sessions"]);
+        redaction.replacement = Replacement::Hash(show);
+        assert_eq!(redaction.to_json(&account()).unwrap(),
+                   "{\"name\":\"ada\",\"token\":\"<\\\"abc\\\">\",\
+                    \"settings\":{\"pin\":1234,\"volume\":7},\
+                    \"sessions\":\"<[\\\"Anonymous\\\",\\\"User\\\"(1,\
+                    \\\"hunter2\\\")]>\"}");
+    }
+}
@@ -0,0 +1,247 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! RFC 2047 encoded words, for non-ASCII text in mail headers
+//!
+//! `decode` replaces the encoded words (`=?UTF-8?B?...?=` or
+//! `=?UTF-8?Q?...?=`) in a header value with the text they encode, and
+//! `encode` turns text into encoded words, folding them onto several lines
+//! to keep each word within the 75 characters allowed.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::rfc2047::{self, Encoding};
+//!
+//! fn main() {
+//!     assert_eq!(rfc2047::decode("=?UTF-8?Q?caf=C3=A9?= au =?utf-8?B?bGFpdA==?="),
+//!                "caf\u{e9} au lait");
+//!     assert_eq!(rfc2047::encode("caf\u{e9}", Encoding::Q), "=?UTF-8?Q?caf=C3=A9?=");
+//! }
+//! ```
+
+use base64::{FromBase64, ToBase64, STANDARD};
+use utf8::split_at_char_boundary;
+
+/// The longest encoded word allowed, in characters.
+pub const MAX_WORD_LEN: usize = 75;
+
+/// The encodings for the text of an encoded word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Base64, best for mostly non-ASCII text
+    B,
+    /// A variant of quoted-printable which keeps ASCII letters and digits
+    /// readable
+    Q,
+}
+
+// "=?UTF-8?X?" and "?="
+const OVERHEAD: usize = 12;
+
+/// Decodes the encoded words in the header value `header`.
+///
+/// Whitespace between two adjacent encoded words is removed. Words in the
+/// UTF-8, US-ASCII and ISO-8859-1 charsets are decoded, invalid UTF-8
+/// being replaced by U+FFFD; malformed words and words in other charsets are
+/// left as they are. Line breaks from folding should be removed first.
+pub fn decode(header: &str) -> String {
+    let mut out = String::with_capacity(header.len());
+    let mut rest = header;
+    let mut after_word = false;
+    while let Some(idx) = rest.find("=?") {
+        let (before, word) = rest.split_at(idx);
+        match decode_word(word) {
+            Some((text, len)) => {
+                if !(after_word && before.chars().all(|c| c == ' ' || c == '\t')) {
+                    out.push_str(before);
+                }
+                out.push_str(&text);
+                rest = &word[len..];
+                after_word = true;
+            }
+            None => {
+                out.push_str(before);
+                out.push_str("=?");
+                rest = &word[2..];
+                after_word = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Decodes the encoded word at the start of `s`, returning its text and its
+// length in `s`.
+fn decode_word(s: &str) -> Option<(String, usize)> {
+    let mut parts = s[2..].splitn(4, '?');
+    let (charset, encoding, text, rest) =
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(c), Some(e), Some(t), Some(r)) => (c, e, t, r),
+            _ => return None,
+        };
+    if !rest.starts_with('=') || text.contains(|c: char| c.is_whitespace()) {
+        return None;
+    }
+    let len = 2 + charset.len() + encoding.len() + text.len() + 4;
+    // Drop an RFC 2231 language tag.
+    let charset = charset.split('*').next().unwrap().to_ascii_lowercase();
+    let bytes = match encoding {
+        "B" | "b" => match text.from_base64() {
+            Ok(bytes) => bytes,
+            Err(_) => return None,
+        },
+        "Q" | "q" => match decode_q(text) {
+            Some(bytes) => bytes,
+            None => return None,
+        },
+        _ => return None,
+    };
+    let text = match &charset[..] {
+        "utf-8" | "utf8" => String::from_utf8_lossy(&bytes).into_owned(),
+        "us-ascii" | "iso-8859-1" | "latin1" => bytes.iter().map(|&b| b as char).collect(),
+        _ => return None,
+    };
+    Some((text, len))
+}
+
+fn decode_q(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut bytes = text.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'_' => out.push(b' '),
+            b'=' => {
+                let hi = bytes.next().and_then(|b| (b as char).to_digit(16));
+                let lo = bytes.next().and_then(|b| (b as char).to_digit(16));
+                match (hi, lo) {
+                    (Some(hi), Some(lo)) => out.push((hi * 16 + lo) as u8),
+                    _ => return None,
+                }
+            }
+            b => out.push(b),
+        }
+    }
+    Some(out)
+}
+
+/// Encodes `text` as UTF-8 encoded words using `encoding`.
+///
+/// Text too long for one word is split between characters into several,
+/// separated by a folding line break (`"\r\n "`), which `decode` removes
+/// again once the line breaks are unfolded.
+pub fn encode(text: &str, encoding: Encoding) -> String {
+    let mut out = String::new();
+    let mut rest = text.as_bytes();
+    loop {
+        if !out.is_empty() {
+            out.push_str("\r\n ");
+        }
+        rest = match encoding {
+            Encoding::B => {
+                // Each 3 bytes take 4 characters.
+                let (head, tail) = split_at_char_boundary(rest, (MAX_WORD_LEN - OVERHEAD) / 4 * 3);
+                out.push_str("=?UTF-8?B?");
+                out.push_str(&head.to_base64(STANDARD));
+                tail
+            }
+            Encoding::Q => {
+                out.push_str("=?UTF-8?Q?");
+                encode_q(rest, MAX_WORD_LEN - OVERHEAD, &mut out)
+            }
+        };
+        out.push_str("?=");
+        if rest.is_empty() {
+            return out;
+        }
+    }
+}
+
+// Q-encodes as many whole characters of `bytes` as fit in `max_len`
+// characters, returning the rest.
+fn encode_q<'a>(bytes: &'a [u8], max_len: usize, out: &mut String) -> &'a [u8] {
+    let mut len = 0;
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let width = match rest[0] {
+            0x00...0x7f => 1,
+            0xc0...0xdf => 2,
+            0xe0...0xef => 3,
+            _ => 4,
+        };
+        let (c, tail) = rest.split_at(width);
+        let encoded_len = c.iter().map(|&b| if q_literal(b) { 1 } else { 3 }).sum::<usize>();
+        if len + encoded_len > max_len {
+            break;
+        }
+        for &b in c {
+            if b == b' ' {
+                out.push('_');
+            } else if q_literal(b) {
+                out.push(b as char);
+            } else {
+                out.push_str(&format!("={:02X}", b));
+            }
+        }
+        len += encoded_len;
+        rest = tail;
+    }
+    rest
+}
+
+// Whether `b` is written as itself in Q-encoded text in a phrase; a space
+// is written as `_`.
+fn q_literal(b: u8) -> bool {
+    matches!(b, b'a'...b'z' | b'A'...b'Z' | b'0'...b'9' |
+                b'!' | b'*' | b'+' | b'-' | b'/' | b' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use rfc2047::{decode, encode, Encoding, MAX_WORD_LEN};
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("plain text"), "plain text");
+        assert_eq!(decode("=?ISO-8859-1?Q?a?="), "a");
+        assert_eq!(decode("=?ISO-8859-1?Q?a?= b"), "a b");
+        assert_eq!(decode("=?ISO-8859-1?Q?a?= =?ISO-8859-1?Q?b?="), "ab");
+        assert_eq!(decode("=?ISO-8859-1?Q?a?=  \t =?ISO-8859-1?Q?b?="), "ab");
+        assert_eq!(decode("=?ISO-8859-1?Q?a_b?="), "a b");
+        assert_eq!(decode("=?ISO-8859-1?Q?a?= =?ISO-8859-2?Q?_b?="), "a =?ISO-8859-2?Q?_b?=");
+        assert_eq!(decode("(=?iso-8859-1?q?Andr=E9?= Pirard)"), "(Andr\u{e9} Pirard)");
+        assert_eq!(decode("=?UTF-8*en?B?8J+YgA==?="), "\u{1F600}");
+        assert_eq!(decode("=?UTF-8?Q?=FF?="), "\u{fffd}");
+        for bad in ["=?UTF-8?Q?a b?=", "=?UTF-8?X?a?=", "=?UTF-8?Q?=4?=", "=?UTF-8?B?@@?=",
+                    "=?UTF-8?Q?a?", "=?UTF-8?Q?a", "x =? y"].iter() {
+            assert_eq!(decode(bad), *bad);
+        }
+        assert_eq!(decode("=?=?UTF-8?Q?x?="), "=?x");
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode("a b=?_", Encoding::Q), "=?UTF-8?Q?a_b=3D=3F=5F?=");
+        assert_eq!(encode("caf\u{e9}", Encoding::B), "=?UTF-8?B?Y2Fmw6k=?=");
+        assert_eq!(encode("", Encoding::B), "=?UTF-8?B??=");
+
+        let text = "\u{e9}t\u{e9} \u{1F600} ".repeat(20);
+        for &encoding in &[Encoding::B, Encoding::Q] {
+            let encoded = encode(&text, encoding);
+            let lines: Vec<_> = encoded.split("\r\n ").collect();
+            assert!(lines.len() > 1);
+            assert!(lines.iter().all(|l| l.len() <= MAX_WORD_LEN));
+            assert_eq!(decode(&lines.join(" ")), text);
+        }
+        assert!(encode(&text, Encoding::Q).split("\r\n ").any(|l| l.len() > MAX_WORD_LEN - 4));
+    }
+}
@@ -0,0 +1,604 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Semantic versions and version requirements
+//!
+//! `Version` is a version of Semantic Versioning 2.0.0, as in
+//! `1.4.0-beta.2+build.7`, ordered by its precedence. `VersionReq` is a
+//! requirement in the syntax of Cargo: comparators separated by commas,
+//! each an operator (`=`, `>`, `>=`, `<`, `<=`, `~` or `^`) and a version
+//! of which the minor and patch numbers may be left out, or a wildcard as
+//! in `1.*` or `*`. A comparator without an operator is `^`, and a
+//! prerelease version only meets a requirement naming a prerelease of the
+//! same major, minor and patch numbers, as in Cargo.
+//!
+//! Both are encoded as strings of their `Display` form and decoded with
+//! `FromStr`.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json;
+//! use rustc_serialize::semver_lite::{Version, VersionReq};
+//!
+//! fn main() {
+//!     let version: Version = json::decode("\"1.4.0-beta.2\"").unwrap();
+//!     let later: Version = "1.4.0-beta.10".parse().unwrap();
+//!     assert!(version < later && later < Version::new(1, 4, 0));
+//!
+//!     let req: VersionReq = ">=1.2, <2".parse().unwrap();
+//!     assert!(req.matches(&"1.9.3".parse().unwrap()));
+//!     assert!(!req.matches(&version));
+//!     assert_eq!(json::encode(&req).unwrap(), "\">=1.2, <2\"");
+//!     assert_eq!("1.3".parse::<VersionReq>().unwrap().to_string(), "^1.3");
+//! }
+//! ```
+
+use std::cmp::Ordering;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+use {Decodable, Decoder, Encodable, Encoder};
+
+/// Errors from parsing versions and requirements
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SemverError {
+    /// A version or comparator was empty
+    Empty,
+    /// A part was not a decimal number, or too large
+    InvalidNumber,
+    /// A number had a leading zero
+    LeadingZero,
+    /// A prerelease or build identifier was empty or held characters other
+    /// than ASCII letters, digits and `-`
+    InvalidIdentifier,
+    /// A version had more or fewer than three numbers, or a comparator
+    /// more than three
+    WrongParts,
+    /// A wildcard followed an operator other than `=`, or came before a
+    /// number or a prerelease
+    InvalidWildcard,
+    /// A comparator had a prerelease without all three numbers, or build
+    /// metadata
+    InvalidComparator,
+}
+
+impl fmt::Display for SemverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SemverError::Empty => write!(f, "empty version"),
+            SemverError::InvalidNumber => write!(f, "invalid version number"),
+            SemverError::LeadingZero => write!(f, "leading zero in version number"),
+            SemverError::InvalidIdentifier => write!(f, "invalid identifier"),
+            SemverError::WrongParts => write!(f, "wrong number of version numbers"),
+            SemverError::InvalidWildcard => write!(f, "invalid wildcard"),
+            SemverError::InvalidComparator => write!(f, "invalid comparator"),
+        }
+    }
+}
+
+impl error::Error for SemverError {
+    fn description(&self) -> &str { "invalid version" }
+}
+
+/// An identifier of a prerelease. Numeric identifiers have lower
+/// precedence than alphanumeric ones, and are compared by value.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Identifier {
+    /// Digits only, as in `2`
+    Numeric(u64),
+    /// Any other identifier, as in `beta` or `0a`
+    AlphaNumeric(String),
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Identifier::Numeric(n) => write!(f, "{}", n),
+            Identifier::AlphaNumeric(ref s) => f.write_str(s),
+        }
+    }
+}
+
+/// A semantic version
+///
+/// Versions are ordered by precedence, then, since build metadata has
+/// none, by their build identifiers as strings, so that the order agrees
+/// with equality.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Version {
+    /// The major version, incremented for incompatible changes
+    pub major: u64,
+    /// The minor version, incremented for compatible additions
+    pub minor: u64,
+    /// The patch version, incremented for fixes
+    pub patch: u64,
+    /// The prerelease identifiers after `-`, if any
+    pub pre: Vec<Identifier>,
+    /// The build identifiers after `+`, if any
+    pub build: Vec<String>,
+}
+
+impl Version {
+    /// Creates a release version.
+    pub fn new(major: u64, minor: u64, patch: u64) -> Version {
+        Version { major: major, minor: minor, patch: patch, pre: Vec::new(), build: Vec::new() }
+    }
+
+    /// Returns whether the version is a prerelease.
+    pub fn is_prerelease(&self) -> bool {
+        !self.pre.is_empty()
+    }
+
+    /// Compares versions by precedence, ignoring build metadata.
+    pub fn cmp_precedence(&self, other: &Version) -> Ordering {
+        (self.major, self.minor, self.patch).cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => self.pre.cmp(&other.pre),
+            })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Version) -> Ordering {
+        self.cmp_precedence(other).then_with(|| self.build.cmp(&other.build))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}.{}.{}", self.major, self.minor, self.patch));
+        try!(write_pre(f, &self.pre));
+        if !self.build.is_empty() {
+            try!(write!(f, "+{}", self.build.join(".")));
+        }
+        Ok(())
+    }
+}
+
+fn write_pre(f: &mut fmt::Formatter, pre: &[Identifier]) -> fmt::Result {
+    for (i, id) in pre.iter().enumerate() {
+        try!(write!(f, "{}{}", if i == 0 { "-" } else { "." }, id));
+    }
+    Ok(())
+}
+
+impl FromStr for Version {
+    type Err = SemverError;
+
+    fn from_str(s: &str) -> Result<Version, SemverError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(SemverError::Empty);
+        }
+        let (rest, build) = match s.find('+') {
+            Some(i) => (&s[..i], try!(parse_build(&s[i + 1..]))),
+            None => (s, Vec::new()),
+        };
+        let (core, pre) = try!(split_pre(rest));
+        let numbers: Vec<&str> = core.split('.').collect();
+        if numbers.len() != 3 {
+            return Err(SemverError::WrongParts);
+        }
+        Ok(Version {
+            major: try!(parse_number(numbers[0])),
+            minor: try!(parse_number(numbers[1])),
+            patch: try!(parse_number(numbers[2])),
+            pre: pre,
+            build: build,
+        })
+    }
+}
+
+// A number without a sign or leading zeros.
+fn parse_number(s: &str) -> Result<u64, SemverError> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(SemverError::InvalidNumber);
+    }
+    if s.len() > 1 && s.starts_with('0') {
+        return Err(SemverError::LeadingZero);
+    }
+    s.parse().map_err(|_| SemverError::InvalidNumber)
+}
+
+fn valid_identifier(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b == b'-' || b.is_ascii_alphanumeric())
+}
+
+// Splits the prerelease identifiers, after the first `-`, off a version.
+fn split_pre(s: &str) -> Result<(&str, Vec<Identifier>), SemverError> {
+    let i = match s.find('-') {
+        Some(i) => i,
+        None => return Ok((s, Vec::new())),
+    };
+    let mut pre = Vec::new();
+    for id in s[i + 1..].split('.') {
+        if !valid_identifier(id) {
+            return Err(SemverError::InvalidIdentifier);
+        }
+        if id.bytes().all(|b| b.is_ascii_digit()) {
+            pre.push(Identifier::Numeric(try!(parse_number(id))));
+        } else {
+            pre.push(Identifier::AlphaNumeric(id.to_string()));
+        }
+    }
+    Ok((&s[..i], pre))
+}
+
+fn parse_build(s: &str) -> Result<Vec<String>, SemverError> {
+    let mut build = Vec::new();
+    for id in s.split('.') {
+        if !valid_identifier(id) {
+            return Err(SemverError::InvalidIdentifier);
+        }
+        build.push(id.to_string());
+    }
+    Ok(build)
+}
+
+/// The operator of a comparator
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Op {
+    /// `=`: the version, or any with the numbers given
+    Exact,
+    /// `>`
+    Greater,
+    /// `>=`
+    GreaterEq,
+    /// `<`
+    Less,
+    /// `<=`
+    LessEq,
+    /// `~`: at least the version, with the same major and minor numbers,
+    /// or the same major number if the minor is left out
+    Tilde,
+    /// `^`: at least the version, with the same first number of the
+    /// major, minor and patch that is not zero
+    Caret,
+    /// `1.*` or `1.2.*`: any version with the numbers given
+    Wildcard,
+}
+
+/// A comparator of a requirement
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Comparator {
+    /// The operator
+    pub op: Op,
+    /// The major number
+    pub major: u64,
+    /// The minor number, if given
+    pub minor: Option<u64>,
+    /// The patch number, if given with the minor
+    pub patch: Option<u64>,
+    /// The prerelease identifiers, only given with all three numbers
+    pub pre: Vec<Identifier>,
+}
+
+impl Comparator {
+    /// Returns whether `version` meets the comparator, without the rule
+    /// for prereleases of `VersionReq::matches`.
+    pub fn matches(&self, version: &Version) -> bool {
+        let v = version;
+        // The order of the version named to `v`, where all numbers are given.
+        let full = || {
+            let named = Version {
+                major: self.major,
+                minor: self.minor.unwrap_or(0),
+                patch: self.patch.unwrap_or(0),
+                pre: self.pre.clone(),
+                build: Vec::new(),
+            };
+            named.cmp_precedence(v)
+        };
+        match (self.op, self.minor, self.patch) {
+            (Op::Exact, Some(_), Some(_)) | (Op::Wildcard, Some(_), Some(_)) => {
+                full() == Ordering::Equal
+            }
+            (Op::Exact, Some(minor), None) | (Op::Wildcard, Some(minor), None) |
+            (Op::Tilde, Some(minor), None) => v.major == self.major && v.minor == minor,
+            (Op::Exact, None, _) | (Op::Wildcard, None, _) |
+            (Op::Tilde, None, _) | (Op::Caret, None, _) => v.major == self.major,
+
+            (Op::Greater, Some(_), Some(_)) => full() == Ordering::Less,
+            (Op::Greater, Some(minor), None) => {
+                v.major > self.major || v.major == self.major && v.minor > minor
+            }
+            (Op::Greater, None, _) => v.major > self.major,
+            (Op::GreaterEq, Some(_), Some(_)) => full() != Ordering::Greater,
+            (Op::GreaterEq, Some(minor), None) => {
+                v.major > self.major || v.major == self.major && v.minor >= minor
+            }
+            (Op::GreaterEq, None, _) => v.major >= self.major,
+            (Op::Less, Some(_), Some(_)) => full() == Ordering::Greater,
+            (Op::Less, Some(minor), None) => {
+                v.major < self.major || v.major == self.major && v.minor < minor
+            }
+            (Op::Less, None, _) => v.major < self.major,
+            (Op::LessEq, Some(_), Some(_)) => full() != Ordering::Less,
+            (Op::LessEq, Some(minor), None) => {
+                v.major < self.major || v.major == self.major && v.minor <= minor
+            }
+            (Op::LessEq, None, _) => v.major <= self.major,
+
+            (Op::Tilde, Some(minor), Some(_)) => {
+                v.major == self.major && v.minor == minor && full() != Ordering::Greater
+            }
+            (Op::Caret, Some(minor), None) => {
+                let minor_ok = if self.major > 0 { v.minor >= minor } else { v.minor == minor };
+                v.major == self.major && minor_ok
+            }
+            (Op::Caret, Some(minor), Some(_)) => {
+                if self.major > 0 {
+                    v.major == self.major && full() != Ordering::Greater
+                } else if minor > 0 {
+                    v.major == 0 && v.minor == minor && full() != Ordering::Greater
+                } else {
+                    full() == Ordering::Equal
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Comparator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self.op {
+            Op::Exact => "=",
+            Op::Greater => ">",
+            Op::GreaterEq => ">=",
+            Op::Less => "<",
+            Op::LessEq => "<=",
+            Op::Tilde => "~",
+            Op::Caret => "^",
+            Op::Wildcard => "",
+        };
+        try!(write!(f, "{}{}", op, self.major));
+        if let Some(minor) = self.minor {
+            try!(write!(f, ".{}", minor));
+        }
+        if let Some(patch) = self.patch {
+            try!(write!(f, ".{}", patch));
+        }
+        try!(write_pre(f, &self.pre));
+        if self.op == Op::Wildcard {
+            try!(f.write_str(".*"));
+        }
+        Ok(())
+    }
+}
+
+// Parses a comparator, or returns `None` for a lone wildcard, which every
+// version meets.
+fn parse_comparator(s: &str) -> Result<Option<Comparator>, SemverError> {
+    let s = s.trim();
+    let ops = [(">=", Op::GreaterEq), ("<=", Op::LessEq), (">", Op::Greater),
+               ("<", Op::Less), ("=", Op::Exact), ("~", Op::Tilde), ("^", Op::Caret)];
+    let (op, rest) = match ops.iter().find(|&&(prefix, _)| s.starts_with(prefix)) {
+        Some(&(prefix, op)) => (Some(op), s[prefix.len()..].trim()),
+        None => (None, s),
+    };
+    if rest.is_empty() {
+        return Err(SemverError::Empty);
+    }
+    if rest.contains('+') {
+        return Err(SemverError::InvalidComparator);
+    }
+    let (core, pre) = try!(split_pre(rest));
+    let parts: Vec<&str> = core.split('.').collect();
+    if parts.len() > 3 {
+        return Err(SemverError::WrongParts);
+    }
+    let mut numbers = Vec::new();
+    let mut wildcard = false;
+    for part in &parts {
+        if *part == "*" || *part == "x" || *part == "X" {
+            wildcard = true;
+        } else if wildcard {
+            return Err(SemverError::InvalidWildcard);
+        } else {
+            numbers.push(try!(parse_number(part)));
+        }
+    }
+    if wildcard && (!pre.is_empty() || op.is_some() && op != Some(Op::Exact)) {
+        return Err(SemverError::InvalidWildcard);
+    }
+    if !pre.is_empty() && numbers.len() < 3 {
+        return Err(SemverError::InvalidComparator);
+    }
+    if numbers.is_empty() {
+        return Ok(None);
+    }
+    let op = if wildcard && numbers.len() < 3 { Op::Wildcard } else { op.unwrap_or(Op::Caret) };
+    Ok(Some(Comparator {
+        op: op,
+        major: numbers[0],
+        minor: numbers.get(1).cloned(),
+        patch: numbers.get(2).cloned(),
+        pre: pre,
+    }))
+}
+
+/// A version requirement: comparators which a version must all meet
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VersionReq {
+    /// The comparators; none for `*`
+    pub comparators: Vec<Comparator>,
+}
+
+impl VersionReq {
+    /// Returns whether `version` meets every comparator. A prerelease
+    /// version must also have the numbers of a comparator with a
+    /// prerelease.
+    pub fn matches(&self, version: &Version) -> bool {
+        self.comparators.iter().all(|c| c.matches(version)) &&
+        (version.pre.is_empty() || self.comparators.iter().any(|c| {
+            !c.pre.is_empty() && c.major == version.major && c.minor == Some(version.minor) &&
+            c.patch == Some(version.patch)
+        }))
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.comparators.is_empty() {
+            return f.write_str("*");
+        }
+        for (i, c) in self.comparators.iter().enumerate() {
+            try!(write!(f, "{}{}", if i == 0 { "" } else { ", " }, c));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = SemverError;
+
+    fn from_str(s: &str) -> Result<VersionReq, SemverError> {
+        let mut comparators = Vec::new();
+        for part in s.split(',') {
+            if let Some(c) = try!(parse_comparator(part)) {
+                comparators.push(c);
+            }
+        }
+        Ok(VersionReq { comparators: comparators })
+    }
+}
+
+// Written as strings like the address types of `std::net`.
+macro_rules! display_from_str {
+    ($($ty:ident, $what:expr;)*) => ($(
+        impl Encodable for $ty {
+            fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_str(&self.to_string())
+            }
+        }
+
+        impl Decodable for $ty {
+            fn decode<D: Decoder>(d: &mut D) -> Result<$ty, D::Error> {
+                let s = try!(d.read_str());
+                s.parse().map_err(|e| d.error(&format!("invalid {} {:?}: {}", $what, s, e)))
+            }
+        }
+    )*)
+}
+
+display_from_str! {
+    Version, "version";
+    VersionReq, "version requirement";
+}
+
+#[cfg(test)]
+mod tests {
+    use json;
+    use semver_lite::{Identifier, SemverError, Version, VersionReq};
+
+    fn v(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_version() {
+        let version = v("1.4.0-beta.2+build.007");
+        assert_eq!(version.pre, vec![Identifier::AlphaNumeric("beta".to_string()),
+                                     Identifier::Numeric(2)]);
+        assert_eq!(version.build, vec!["build".to_string(), "007".to_string()]);
+        assert_eq!(version.to_string(), "1.4.0-beta.2+build.007");
+        assert_eq!(v("0.0.0-x-y.0a"), Version { pre: vec![Identifier::AlphaNumeric("x-y".into()),
+                                                          Identifier::AlphaNumeric("0a".into())],
+                                                ..Version::new(0, 0, 0) });
+
+        // The order of the example in section 11 of the specification.
+        let ordered = ["1.0.0-alpha", "1.0.0-alpha.1", "1.0.0-alpha.beta", "1.0.0-beta",
+                       "1.0.0-beta.2", "1.0.0-beta.11", "1.0.0-rc.1", "1.0.0", "1.0.1", "1.1.0",
+                       "2.0.0"];
+        for pair in ordered.windows(2) {
+            assert!(v(pair[0]) < v(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+        assert!(v("1.0.0+a") < v("1.0.0+b"));
+        assert_eq!(v("1.0.0+a").cmp_precedence(&v("1.0.0+b")), ::std::cmp::Ordering::Equal);
+
+        let errors = [("", SemverError::Empty), ("1.2", SemverError::WrongParts),
+                      ("1.2.3.4", SemverError::WrongParts), ("01.2.3", SemverError::LeadingZero),
+                      ("1.2.3-01", SemverError::LeadingZero),
+                      ("1.2.-3", SemverError::InvalidNumber),
+                      ("1.2.3-", SemverError::InvalidIdentifier),
+                      ("1.2.3-a..b", SemverError::InvalidIdentifier),
+                      ("1.2.3+é", SemverError::InvalidIdentifier),
+                      ("v1.2.3", SemverError::InvalidNumber),
+                      ("1.2.99999999999999999999", SemverError::InvalidNumber)];
+        for &(s, err) in &errors {
+            assert_eq!(s.parse::<Version>(), Err(err), "{:?}", s);
+        }
+        assert_eq!(json::encode(&version).unwrap(), "\"1.4.0-beta.2+build.007\"");
+        assert_eq!(json::decode::<Version>("\"1.4.0-beta.2+build.007\"").unwrap(), version);
+        assert!(json::decode::<Version>("\"1.4\"").is_err());
+    }
+
+    #[test]
+    fn test_req() {
+        let cases = [
+            ("=1.2.3", &["1.2.3"][..], &["1.2.4", "1.2.3-pre"][..]),
+            ("=1.2", &["1.2.0", "1.2.9"], &["1.3.0"]),
+            (">1.2.3", &["1.2.4", "2.0.0"], &["1.2.3", "1.3.0-pre"]),
+            (">1.2", &["1.3.0"], &["1.2.9"]),
+            (">=1.2.3-beta", &["1.2.3-beta", "1.2.3-rc", "1.2.3"], &["1.2.3-alpha", "1.2.4-rc"]),
+            ("<2", &["1.99.99"], &["2.0.0", "2.0.0-pre"]),
+            ("<=1.2", &["1.2.9", "0.1.0"], &["1.3.0"]),
+            ("~1.2.3", &["1.2.3", "1.2.9"], &["1.2.2", "1.3.0"]),
+            ("~1", &["1.0.0", "1.9.9"], &["2.0.0"]),
+            ("1.2.3", &["1.2.3", "1.9.0"], &["1.2.2", "2.0.0"]),
+            ("^0.2.3", &["0.2.3", "0.2.9"], &["0.3.0", "0.2.2"]),
+            ("^0.0.3", &["0.0.3"], &["0.0.4"]),
+            ("^0.2", &["0.2.0", "0.2.9"], &["0.3.0"]),
+            ("1.*", &["1.0.0", "1.9.0"], &["2.0.0"]),
+            ("1.2.x", &["1.2.0"], &["1.3.0"]),
+            ("*", &["0.0.1", "9.9.9"], &["1.0.0-pre"]),
+            (">= 1.2, < 1.5", &["1.4.9"], &["1.5.0", "1.1.0"]),
+        ];
+        for &(req, yes, no) in &cases {
+            let parsed: VersionReq = req.parse().unwrap();
+            for s in yes {
+                assert!(parsed.matches(&v(s)), "{} matches {}", req, s);
+            }
+            for s in no {
+                assert!(!parsed.matches(&v(s)), "{} does not match {}", req, s);
+            }
+        }
+
+        let shown = [("1.2.3", "^1.2.3"), ("=1.2.3-rc.1", "=1.2.3-rc.1"), ("1.X", "1.*"),
+                     ("=1.2.*", "1.2.*"), ("x", "*"), (">= 1.2 , <1.5", ">=1.2, <1.5")];
+        for &(req, s) in &shown {
+            assert_eq!(req.parse::<VersionReq>().unwrap().to_string(), s);
+        }
+        let errors = [("", SemverError::Empty), (">=", SemverError::Empty),
+                      ("1.*.3", SemverError::InvalidWildcard),
+                      (">1.*", SemverError::InvalidWildcard),
+                      ("1.2-pre", SemverError::InvalidComparator),
+                      ("1.2.3+b", SemverError::InvalidComparator),
+                      ("1.2.3.4", SemverError::WrongParts), ("=>1", SemverError::InvalidNumber),
+                      ("1.2,", SemverError::Empty)];
+        for &(s, err) in &errors {
+            assert_eq!(s.parse::<VersionReq>(), Err(err), "{:?}", s);
+        }
+        let req: VersionReq = json::decode("\"~1.2\"").unwrap();
+        assert_eq!(json::encode(&req).unwrap(), "\"~1.2\"");
+    }
+}
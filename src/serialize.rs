@@ -22,14 +22,19 @@ Core encoding and decoding interfaces.
 */
 
 use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
 use std::ffi::OsString;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::ops::{Bound, Range, RangeInclusive};
 use std::path;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::marker::PhantomData;
+use std::any::Any;
 use std::borrow::Cow;
 
 use cap_capacity;
+use wtf8::Wtf8Buf;
 
 /// Trait for writing out an encoding when serializing.
 ///
@@ -84,6 +89,30 @@ pub trait Encoder {
     /// Emit a i8 value.
     fn emit_i8(&mut self, v: i8) -> Result<(), Self::Error>;
 
+    /// Emit a u128 value.
+    ///
+    /// The default implementation uses `emit_u64` for values which fit, and
+    /// otherwise emits the value as a decimal string with `emit_str`.
+    fn emit_u128(&mut self, v: u128) -> Result<(), Self::Error> {
+        if v <= u64::MAX as u128 {
+            self.emit_u64(v as u64)
+        } else {
+            self.emit_str(&v.to_string())
+        }
+    }
+
+    /// Emit a i128 value.
+    ///
+    /// The default implementation uses `emit_i64` for values which fit, and
+    /// otherwise emits the value as a decimal string with `emit_str`.
+    fn emit_i128(&mut self, v: i128) -> Result<(), Self::Error> {
+        if v >= i64::MIN as i128 && v <= i64::MAX as i128 {
+            self.emit_i64(v as i64)
+        } else {
+            self.emit_str(&v.to_string())
+        }
+    }
+
     /// Emit a bool value.
     ///
     /// For example, this might be stored as the true and false keywords in
@@ -486,6 +515,50 @@ pub trait Encoder {
     /// emitted followed immediately by the value.
     fn emit_map_elt_val<F>(&mut self, idx: usize, f: F) -> Result<(), Self::Error>
         where F: FnOnce(&mut Self) -> Result<(), Self::Error>;
+
+    /// Emit a value marked with an application-defined extension tag.
+    ///
+    /// * `tag` identifies the type of the value; see `ExtensionTag`
+    /// * `f` is a function that will call the appropriate emit method to encode
+    ///   the value.
+    ///
+    /// The default implementation emits a struct with two fields, `$tag` and
+    /// `value`, so that in JSON the value is `{"$tag": tag, "value": ...}`.
+    /// Formats with tags of their own, such as CBOR, should use them instead.
+    fn emit_tagged<F>(&mut self, tag: u64, f: F) -> Result<(), Self::Error>
+        where F: FnOnce(&mut Self) -> Result<(), Self::Error>
+    {
+        self.emit_struct("$tagged", 2, |s| {
+            try!(s.emit_struct_field("$tag", 0, |s| s.emit_u64(tag)));
+            s.emit_struct_field("value", 1, f)
+        })
+    }
+
+    /// Emit a string which may hold lone surrogates; see `wtf8`.
+    ///
+    /// The default implementation emits the string with `emit_str`, lone
+    /// surrogates replaced with U+FFFD. The `json` encoders keep them.
+    fn emit_wtf8(&mut self, v: &Wtf8Buf) -> Result<(), Self::Error> {
+        self.emit_str(&v.to_string_lossy())
+    }
+
+    /// Emit a path.
+    ///
+    /// The default implementation emits the platform's own form of the
+    /// path, which loses nothing: its bytes on Unix and its UTF-16 code
+    /// units on Windows. Encoders may offer to write paths which are
+    /// Unicode as strings instead, so that they can be read on any
+    /// platform.
+    fn emit_path(&mut self, v: &path::Path) -> Result<(), Self::Error> where Self: Sized {
+        encode_native_path(v, self)
+    }
+
+    /// The name of the format being encoded, such as `"json"`, under which
+    /// `overrides` are looked up. The default implementation returns
+    /// `None`, so that no overrides apply.
+    fn format(&self) -> Option<&'static str> {
+        None
+    }
 }
 
 /// Trait for reading in an encoding for deserialization.
@@ -540,6 +613,44 @@ pub trait Decoder {
     /// Read a i8 value.
     fn read_i8(&mut self) -> Result<i8, Self::Error>;
 
+    /// Read a u128 value.
+    ///
+    /// The default implementation mirrors `Encoder::emit_u128`: it reads a
+    /// `u64`, and if that fails reads a decimal string with `read_str`. The
+    /// fallback only works for decoders which leave the value in place when
+    /// `read_u64` fails; other decoders should override this method.
+    fn read_u128(&mut self) -> Result<u128, Self::Error> {
+        match self.read_u64() {
+            Ok(v) => Ok(v as u128),
+            Err(e) => {
+                let s = match self.read_str() {
+                    Ok(s) => s,
+                    Err(_) => return Err(e),
+                };
+                s.parse().map_err(|_| self.error(&format!("invalid u128 `{}`", s)))
+            }
+        }
+    }
+
+    /// Read a i128 value.
+    ///
+    /// The default implementation mirrors `Encoder::emit_i128`: it reads an
+    /// `i64`, and if that fails reads a decimal string with `read_str`. The
+    /// fallback only works for decoders which leave the value in place when
+    /// `read_i64` fails; other decoders should override this method.
+    fn read_i128(&mut self) -> Result<i128, Self::Error> {
+        match self.read_i64() {
+            Ok(v) => Ok(v as i128),
+            Err(e) => {
+                let s = match self.read_str() {
+                    Ok(s) => s,
+                    Err(_) => return Err(e),
+                };
+                s.parse().map_err(|_| self.error(&format!("invalid i128 `{}`", s)))
+            }
+        }
+    }
+
     /// Read a bool value.
     fn read_bool(&mut self) -> Result<bool, Self::Error>;
 
@@ -788,6 +899,55 @@ pub trait Decoder {
                               -> Result<T, Self::Error>
         where F: FnOnce(&mut Self) -> Result<T, Self::Error>;
 
+    /// Read a value marked with an extension tag, as emitted by
+    /// `Encoder::emit_tagged`.
+    ///
+    /// * `f` is a function that will be passed the decoder and the tag, and
+    ///   will call the appropriate read method to decode the value.
+    ///
+    /// The default implementation reads the struct that the default
+    /// `Encoder::emit_tagged` emits.
+    fn read_tagged<T, F>(&mut self, f: F) -> Result<T, Self::Error>
+        where F: FnOnce(&mut Self, u64) -> Result<T, Self::Error>
+    {
+        self.read_struct("$tagged", 2, |d| {
+            let tag = try!(d.read_struct_field("$tag", 0, |d| d.read_u64()));
+            d.read_struct_field("value", 1, |d| f(d, tag))
+        })
+    }
+
+    /// The name of the format being decoded, under which `overrides` are
+    /// looked up. The default implementation returns `None`, so that no
+    /// overrides apply.
+    fn format(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Read a string which may hold lone surrogates; see `wtf8`.
+    ///
+    /// The default implementation reads a string with `read_str`. The
+    /// `json` decoder also reads the `Json::Wtf8` values its parser keeps.
+    fn read_wtf8(&mut self) -> Result<Wtf8Buf, Self::Error> {
+        self.read_str().map(Wtf8Buf::from)
+    }
+
+    /// Read a path.
+    ///
+    /// The default implementation reads the platform's own form, as
+    /// `Encoder::emit_path` writes it by default. Decoders for encoders
+    /// which can write paths as strings should read strings too.
+    fn read_path(&mut self) -> Result<path::PathBuf, Self::Error> where Self: Sized {
+        decode_native_path(self)
+    }
+
+    /// Returns the decoder as `Any`, so that `Decodable` implementations
+    /// made for a particular decoder can reach it when given that one.
+    ///
+    /// The default implementation returns `None`.
+    fn as_any(&mut self) -> Option<&mut Any> {
+        None
+    }
+
     // Failure
     /// Record a decoding error.
     ///
@@ -1024,6 +1184,30 @@ impl Decodable for i64 {
     }
 }
 
+impl Encodable for u128 {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_u128(*self)
+    }
+}
+
+impl Decodable for u128 {
+    fn decode<D: Decoder>(d: &mut D) -> Result<u128, D::Error> {
+        d.read_u128()
+    }
+}
+
+impl Encodable for i128 {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_i128(*self)
+    }
+}
+
+impl Decodable for i128 {
+    fn decode<D: Decoder>(d: &mut D) -> Result<i128, D::Error> {
+        d.read_i128()
+    }
+}
+
 impl Encodable for str {
     fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
         s.emit_str(self)
@@ -1152,7 +1336,7 @@ impl<'a, T: ?Sized> Decodable for Cow<'a, T>
     where T: ToOwned, T::Owned: Decodable
 {
     #[inline]
-    fn decode<D: Decoder>(d: &mut D) -> Result<Cow<'static, T>, D::Error> {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Cow<'a, T>, D::Error> {
         Ok(Cow::Owned(try!(Decodable::decode(d))))
     }
 }
@@ -1261,6 +1445,134 @@ impl<T: Decodable, E: Decodable> Decodable for Result<T, E> {
     }
 }
 
+// Ranges are written as structs with `start` and `end` fields, `end` being
+// included in a `RangeInclusive`.
+impl<T: Encodable> Encodable for Range<T> {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("Range", 2, |s| {
+            try!(s.emit_struct_field("start", 0, |s| self.start.encode(s)));
+            s.emit_struct_field("end", 1, |s| self.end.encode(s))
+        })
+    }
+}
+
+impl<T: Decodable> Decodable for Range<T> {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Range<T>, D::Error> {
+        d.read_struct("Range", 2, |d| {
+            let start = try!(d.read_struct_field("start", 0, Decodable::decode));
+            let end = try!(d.read_struct_field("end", 1, Decodable::decode));
+            Ok(start..end)
+        })
+    }
+}
+
+impl<T: Encodable> Encodable for RangeInclusive<T> {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("RangeInclusive", 2, |s| {
+            try!(s.emit_struct_field("start", 0, |s| self.start().encode(s)));
+            s.emit_struct_field("end", 1, |s| self.end().encode(s))
+        })
+    }
+}
+
+impl<T: Decodable> Decodable for RangeInclusive<T> {
+    fn decode<D: Decoder>(d: &mut D) -> Result<RangeInclusive<T>, D::Error> {
+        d.read_struct("RangeInclusive", 2, |d| {
+            let start = try!(d.read_struct_field("start", 0, Decodable::decode));
+            let end = try!(d.read_struct_field("end", 1, Decodable::decode));
+            Ok(RangeInclusive::new(start, end))
+        })
+    }
+}
+
+impl<T: Encodable> Encodable for Bound<T> {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_enum("Bound", |s| {
+            match *self {
+                Bound::Included(ref v) => s.emit_enum_variant("Included", 0, 1, |s| {
+                    s.emit_enum_variant_arg(0, |s| v.encode(s))
+                }),
+                Bound::Excluded(ref v) => s.emit_enum_variant("Excluded", 1, 1, |s| {
+                    s.emit_enum_variant_arg(0, |s| v.encode(s))
+                }),
+                Bound::Unbounded => s.emit_enum_variant("Unbounded", 2, 0, |_| Ok(())),
+            }
+        })
+    }
+}
+
+impl<T: Decodable> Decodable for Bound<T> {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Bound<T>, D::Error> {
+        d.read_enum("Bound", |d| {
+            d.read_enum_variant(&["Included", "Excluded", "Unbounded"], |d, idx| {
+                match idx {
+                    0 => d.read_enum_variant_arg(0, Decodable::decode).map(Bound::Included),
+                    1 => d.read_enum_variant_arg(0, Decodable::decode).map(Bound::Excluded),
+                    2 => Ok(Bound::Unbounded),
+                    _ => panic!("Internal error"),
+                }
+            })
+        })
+    }
+}
+
+// Orderings are written as the fieldless variants `Less`, `Equal` and
+// `Greater`.
+impl Encodable for Ordering {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_enum("Ordering", |s| {
+            match *self {
+                Ordering::Less => s.emit_enum_variant("Less", 0, 0, |_| Ok(())),
+                Ordering::Equal => s.emit_enum_variant("Equal", 1, 0, |_| Ok(())),
+                Ordering::Greater => s.emit_enum_variant("Greater", 2, 0, |_| Ok(())),
+            }
+        })
+    }
+}
+
+impl Decodable for Ordering {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Ordering, D::Error> {
+        d.read_enum("Ordering", |d| {
+            d.read_enum_variant(&["Less", "Equal", "Greater"], |_, idx| {
+                match idx {
+                    0 => Ok(Ordering::Less),
+                    1 => Ok(Ordering::Equal),
+                    2 => Ok(Ordering::Greater),
+                    _ => panic!("Internal error"),
+                }
+            })
+        })
+    }
+}
+
+// Types written as strings in their `Display` form and read back with
+// `FromStr`: addresses as in `"127.0.0.1"`, `"::1"` and `"[::1]:80"`.
+macro_rules! display_from_str {
+    ($($ty:ident, $what:expr;)*) => ($(
+        impl Encodable for $ty {
+            fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_str(&self.to_string())
+            }
+        }
+
+        impl Decodable for $ty {
+            fn decode<D: Decoder>(d: &mut D) -> Result<$ty, D::Error> {
+                let s = try!(d.read_str());
+                s.parse().map_err(|_| d.error(&format!("invalid {}: {:?}", $what, s)))
+            }
+        }
+    )*)
+}
+
+display_from_str! {
+    IpAddr, "IP address";
+    Ipv4Addr, "IPv4 address";
+    Ipv6Addr, "IPv6 address";
+    SocketAddr, "socket address";
+    SocketAddrV4, "IPv4 socket address";
+    SocketAddrV6, "IPv6 socket address";
+}
+
 impl<T> Encodable for PhantomData<T> {
     fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
         s.emit_nil()
@@ -1273,6 +1585,72 @@ impl<T> Decodable for PhantomData<T> {
     }
 }
 
+/// A type which is encoded marked with an extension tag.
+///
+/// Wrapping a value of such a type in `Tagged` encodes it with
+/// `Encoder::emit_tagged`, so that it keeps its identity through formats
+/// which have no type of their own for it: as a CBOR tag, or in JSON as
+/// `{"$tag": TAG, "value": ...}`. Decoding a `Tagged` checks the tag.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::{json, ExtensionTag, Tagged};
+///
+/// // A point in time, as seconds since the Unix epoch (CBOR tag 1).
+/// #[derive(Debug, PartialEq)]
+/// struct Epoch(u64);
+///
+/// impl rustc_serialize::Encodable for Epoch {
+///     fn encode<S: rustc_serialize::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+///         s.emit_u64(self.0)
+///     }
+/// }
+///
+/// impl rustc_serialize::Decodable for Epoch {
+///     fn decode<D: rustc_serialize::Decoder>(d: &mut D) -> Result<Epoch, D::Error> {
+///         d.read_u64().map(Epoch)
+///     }
+/// }
+///
+/// impl ExtensionTag for Epoch {
+///     const TAG: u64 = 1;
+/// }
+///
+/// fn main() {
+///     let encoded = json::encode(&Tagged(Epoch(1363896240))).unwrap();
+///     assert_eq!(encoded, r#"{"$tag":1,"value":1363896240}"#);
+///     let decoded: Tagged<Epoch> = json::decode(&encoded).unwrap();
+///     assert_eq!(decoded.0, Epoch(1363896240));
+/// }
+/// ```
+pub trait ExtensionTag {
+    /// The tag, which must be unique among the application's tagged types
+    const TAG: u64;
+}
+
+/// A value encoded with its type's extension tag; see `ExtensionTag`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tagged<T>(pub T);
+
+impl<T: ExtensionTag + Encodable> Encodable for Tagged<T> {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_tagged(T::TAG, |s| self.0.encode(s))
+    }
+}
+
+impl<T: ExtensionTag + Decodable> Decodable for Tagged<T> {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Tagged<T>, D::Error> {
+        d.read_tagged(|d, tag| {
+            if tag != T::TAG {
+                return Err(d.error(&format!("expected tag {}, found {}", T::TAG, tag)));
+            }
+            Decodable::decode(d).map(Tagged)
+        })
+    }
+}
+
 macro_rules! peel {
     ($name:ident, $($other:ident,)*) => (tuple! { $($other,)* })
 }
@@ -1355,21 +1733,54 @@ array! {
     15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
 }
 
+// The platform's own form of paths, the default of `Encoder::emit_path`
+// and `Decoder::read_path`.
+#[cfg(target_os = "redox")]
+pub fn encode_native_path<S: Encoder>(p: &path::Path, e: &mut S) -> Result<(), S::Error> {
+    p.as_os_str().to_str().unwrap().encode(e)
+}
+#[cfg(unix)]
+pub fn encode_native_path<S: Encoder>(p: &path::Path, e: &mut S) -> Result<(), S::Error> {
+    use std::os::unix::prelude::*;
+    p.as_os_str().as_bytes().encode(e)
+}
+#[cfg(windows)]
+pub fn encode_native_path<S: Encoder>(p: &path::Path, e: &mut S) -> Result<(), S::Error> {
+    use std::os::windows::prelude::*;
+    let v = p.as_os_str().encode_wide().collect::<Vec<_>>();
+    v.encode(e)
+}
+
+#[cfg(target_os = "redox")]
+pub fn decode_native_path<D: Decoder>(d: &mut D) -> Result<path::PathBuf, D::Error> {
+    let string: String = try!(Decodable::decode(d));
+    let s: OsString = OsString::from(string);
+    let mut p = path::PathBuf::new();
+    p.push(s);
+    Ok(p)
+}
+#[cfg(unix)]
+pub fn decode_native_path<D: Decoder>(d: &mut D) -> Result<path::PathBuf, D::Error> {
+    use std::os::unix::prelude::*;
+    let bytes: Vec<u8> = try!(Decodable::decode(d));
+    let s: OsString = OsStringExt::from_vec(bytes);
+    let mut p = path::PathBuf::new();
+    p.push(s);
+    Ok(p)
+}
+#[cfg(windows)]
+pub fn decode_native_path<D: Decoder>(d: &mut D) -> Result<path::PathBuf, D::Error> {
+    use std::os::windows::prelude::*;
+    let bytes: Vec<u16> = try!(Decodable::decode(d));
+    let s: OsString = OsStringExt::from_wide(&bytes);
+    let mut p = path::PathBuf::new();
+    p.push(s);
+    Ok(p)
+}
+
 impl Encodable for path::Path {
-    #[cfg(target_os = "redox")]
     fn encode<S: Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
-        self.as_os_str().to_str().unwrap().encode(e)
-    }
-    #[cfg(unix)]
-    fn encode<S: Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
-        use std::os::unix::prelude::*;
-        self.as_os_str().as_bytes().encode(e)
-    }
-    #[cfg(windows)]
-    fn encode<S: Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
-        use std::os::windows::prelude::*;
-        let v = self.as_os_str().encode_wide().collect::<Vec<_>>();
-        v.encode(e)
+        e.emit_path(self)
     }
 }
 
@@ -1380,31 +1791,8 @@ impl Encodable for path::PathBuf {
 }
 
 impl Decodable for path::PathBuf {
-    #[cfg(target_os = "redox")]
-    fn decode<D: Decoder>(d: &mut D) -> Result<path::PathBuf, D::Error> {
-        let string: String = try!(Decodable::decode(d));
-        let s: OsString = OsString::from(string);
-        let mut p = path::PathBuf::new();
-        p.push(s);
-        Ok(p)
-    }
-    #[cfg(unix)]
     fn decode<D: Decoder>(d: &mut D) -> Result<path::PathBuf, D::Error> {
-        use std::os::unix::prelude::*;
-        let bytes: Vec<u8> = try!(Decodable::decode(d));
-        let s: OsString = OsStringExt::from_vec(bytes);
-        let mut p = path::PathBuf::new();
-        p.push(s);
-        Ok(p)
-    }
-    #[cfg(windows)]
-    fn decode<D: Decoder>(d: &mut D) -> Result<path::PathBuf, D::Error> {
-        use std::os::windows::prelude::*;
-        let bytes: Vec<u16> = try!(Decodable::decode(d));
-        let s: OsString = OsStringExt::from_wide(&bytes);
-        let mut p = path::PathBuf::new();
-        p.push(s);
-        Ok(p)
+        d.read_path()
     }
 }
 
@@ -1669,3 +2057,104 @@ fn capacity_rules() {
     let set_result: Result<HashSet<u8>, ()> = Decodable::decode(&mut dummy);
     assert!(set_result.is_err());
 }
+
+#[test]
+#[allow(unused_variables)]
+fn default_read_128() {
+    // Holds a single value as text, as a decoder for a text format might.
+    struct TextDecoder(String);
+    impl Decoder for TextDecoder {
+        type Error = String;
+
+        // Primitive types:
+        fn read_nil(&mut self) -> Result<(), String> { Err(self.0.clone()) }
+        fn read_usize(&mut self) -> Result<usize, String> { Err(self.0.clone()) }
+        fn read_u64(&mut self) -> Result<u64, String> {
+            self.0.parse().map_err(|_| self.0.clone())
+        }
+        fn read_u32(&mut self) -> Result<u32, String> { Err(self.0.clone()) }
+        fn read_u16(&mut self) -> Result<u16, String> { Err(self.0.clone()) }
+        fn read_u8(&mut self) -> Result<u8, String> { Err(self.0.clone()) }
+        fn read_isize(&mut self) -> Result<isize, String> { Err(self.0.clone()) }
+        fn read_i64(&mut self) -> Result<i64, String> {
+            self.0.parse().map_err(|_| self.0.clone())
+        }
+        fn read_i32(&mut self) -> Result<i32, String> { Err(self.0.clone()) }
+        fn read_i16(&mut self) -> Result<i16, String> { Err(self.0.clone()) }
+        fn read_i8(&mut self) -> Result<i8, String> { Err(self.0.clone()) }
+        fn read_bool(&mut self) -> Result<bool, String> { Err(self.0.clone()) }
+        fn read_f64(&mut self) -> Result<f64, String> { Err(self.0.clone()) }
+        fn read_f32(&mut self) -> Result<f32, String> { Err(self.0.clone()) }
+        fn read_char(&mut self) -> Result<char, String> { Err(self.0.clone()) }
+        fn read_str(&mut self) -> Result<String, String> { Ok(self.0.clone()) }
+
+        // Compound types:
+        fn read_enum<T, F>(&mut self, name: &str, f: F) -> Result<T, String>
+            where F: FnOnce(&mut Self) -> Result<T, String> { Err(self.0.clone()) }
+
+        fn read_enum_variant<T, F>(&mut self, names: &[&str], f: F) -> Result<T, String>
+            where F: FnMut(&mut Self, usize) -> Result<T, String> { Err(self.0.clone()) }
+        fn read_enum_variant_arg<T, F>(&mut self, a_idx: usize, f: F) -> Result<T, String>
+            where F: FnOnce(&mut Self) -> Result<T, String> { Err(self.0.clone()) }
+
+        fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F)
+                                          -> Result<T, String>
+            where F: FnMut(&mut Self, usize) -> Result<T, String> { Err(self.0.clone()) }
+        fn read_enum_struct_variant_field<T, F>(&mut self,
+                                                f_name: &str,
+                                                f_idx: usize,
+                                                f: F)
+                                                -> Result<T, String>
+            where F: FnOnce(&mut Self) -> Result<T, String> { Err(self.0.clone()) }
+
+        fn read_struct<T, F>(&mut self, s_name: &str, len: usize, f: F) -> Result<T, String>
+            where F: FnOnce(&mut Self) -> Result<T, String> { Err(self.0.clone()) }
+        fn read_struct_field<T, F>(&mut self,
+                                   f_name: &str,
+                                   f_idx: usize,
+                                   f: F)
+                                   -> Result<T, String>
+            where F: FnOnce(&mut Self) -> Result<T, String> { Err(self.0.clone()) }
+
+        fn read_tuple<T, F>(&mut self, len: usize, f: F) -> Result<T, String>
+            where F: FnOnce(&mut Self) -> Result<T, String> { Err(self.0.clone()) }
+        fn read_tuple_arg<T, F>(&mut self, a_idx: usize, f: F) -> Result<T, String>
+            where F: FnOnce(&mut Self) -> Result<T, String> { Err(self.0.clone()) }
+
+        fn read_tuple_struct<T, F>(&mut self, s_name: &str, len: usize, f: F)
+                                   -> Result<T, String>
+            where F: FnOnce(&mut Self) -> Result<T, String> { Err(self.0.clone()) }
+        fn read_tuple_struct_arg<T, F>(&mut self, a_idx: usize, f: F) -> Result<T, String>
+            where F: FnOnce(&mut Self) -> Result<T, String> { Err(self.0.clone()) }
+
+        // Specialized types:
+        fn read_option<T, F>(&mut self, f: F) -> Result<T, String>
+            where F: FnMut(&mut Self, bool) -> Result<T, String> { Err(self.0.clone()) }
+
+        fn read_seq<T, F>(&mut self, f: F) -> Result<T, String>
+            where F: FnOnce(&mut Self, usize) -> Result<T, String> { Err(self.0.clone()) }
+        fn read_seq_elt<T, F>(&mut self, idx: usize, f: F) -> Result<T, String>
+            where F: FnOnce(&mut Self) -> Result<T, String> { Err(self.0.clone()) }
+
+        fn read_map<T, F>(&mut self, f: F) -> Result<T, String>
+            where F: FnOnce(&mut Self, usize) -> Result<T, String> { Err(self.0.clone()) }
+        fn read_map_elt_key<T, F>(&mut self, idx: usize, f: F) -> Result<T, String>
+            where F: FnOnce(&mut Self) -> Result<T, String> { Err(self.0.clone()) }
+        fn read_map_elt_val<T, F>(&mut self, idx: usize, f: F) -> Result<T, String>
+            where F: FnOnce(&mut Self) -> Result<T, String> { Err(self.0.clone()) }
+
+        // Failure
+        fn error(&mut self, err: &str) -> String { err.to_string() }
+    }
+
+    fn read<T: Decodable>(s: &str) -> Result<T, String> {
+        Decodable::decode(&mut TextDecoder(s.to_string()))
+    }
+
+    assert_eq!(read::<u128>("42"), Ok(42));
+    assert_eq!(read::<u128>("340282366920938463463374607431768211455"), Ok(u128::MAX));
+    assert_eq!(read::<i128>("-7"), Ok(-7));
+    assert_eq!(read::<i128>("-170141183460469231731687303715884105728"), Ok(i128::MIN));
+    assert_eq!(read::<u128>("-1"), Err("invalid u128 `-1`".to_string()));
+    assert_eq!(read::<i128>("x"), Err("invalid i128 `x`".to_string()));
+}
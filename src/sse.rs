@@ -0,0 +1,407 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Server-sent events
+//!
+//! `Event::write` writes an event as a frame of a `text/event-stream`
+//! body, with its `event:`, `id:` and `retry:` fields and a `data:` field
+//! for each line of its data, and `EventReader` decodes such a body, read
+//! from any `Read`, into an iterator of events, as the HTML standard
+//! describes: lines may end with CR, LF or CRLF, comments and unknown
+//! fields are ignored, the last event ID carries over from one event to
+//! the next, and an event left unfinished at the end of the stream is
+//! dropped. `Event::json` and `Event::decode` carry JSON payloads in the
+//! data.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::sse::{Event, EventReader};
+//!
+//! fn main() {
+//!     let mut event = Event::json(&vec![1, 2]).unwrap();
+//!     event.event = Some("update".to_string());
+//!     event.id = Some("7".to_string());
+//!     let mut body = Vec::new();
+//!     event.write(&mut body).unwrap();
+//!     Event::new("two\nlines").write(&mut body).unwrap();
+//!     assert_eq!(body, &b"event: update\nid: 7\ndata: [1,2]\n\ndata: two\ndata: lines\n\n"[..]);
+//!
+//!     let events: Vec<Event> = EventReader::new(&body[..]).map(|e| e.unwrap()).collect();
+//!     assert_eq!(events[0], event);
+//!     assert_eq!(events[0].decode::<Vec<u32>>().unwrap(), [1, 2]);
+//!     assert_eq!(events[1].data, "two\nlines");
+//!     assert_eq!(events[1].id, Some("7".to_string()));
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+
+use Decodable;
+use Encodable;
+use json::{self, DecodeResult, EncodeResult};
+
+/// An error from writing an event, reported as an `io::Error` of kind
+/// `InvalidInput` wrapping it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SseError {
+    /// The event type or ID holds a line break, which would end its field
+    LineBreak,
+    /// The ID holds a NUL character, for which readers ignore it
+    NulInId,
+}
+
+impl fmt::Display for SseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SseError::LineBreak => write!(f, "line break in event type or ID"),
+            SseError::NulInId => write!(f, "NUL in event ID"),
+        }
+    }
+}
+
+impl error::Error for SseError {
+    fn description(&self) -> &str { "invalid server-sent event" }
+}
+
+/// A server-sent event.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Event {
+    /// The type of the event, which readers take as `message` if it is not
+    /// given
+    pub event: Option<String>,
+    /// The data of the event
+    pub data: String,
+    /// The ID of the event; read events have the last ID given in the
+    /// stream
+    pub id: Option<String>,
+    /// The time, in milliseconds, the reader should wait before
+    /// reconnecting; read events have the retry field given with them
+    pub retry: Option<u64>,
+}
+
+impl Event {
+    /// Creates an event with the data `data`.
+    pub fn new(data: &str) -> Event {
+        Event { data: data.to_string(), ..Event::default() }
+    }
+
+    /// Creates an event with the JSON encoding of `value` as its data.
+    pub fn json<T: Encodable>(value: &T) -> EncodeResult<Event> {
+        Ok(Event { data: try!(json::encode(value)), ..Event::default() })
+    }
+
+    /// Decodes a `T` from the JSON in the data of the event.
+    pub fn decode<T: Decodable>(&self) -> DecodeResult<T> {
+        json::decode(&self.data)
+    }
+
+    /// Writes the event as a frame to `writer`, in a single write. Lines of
+    /// the data may end with CR, LF or CRLF; each is written as a `data:`
+    /// field.
+    pub fn write<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        let mut frame = String::new();
+        if let Some(ref event) = self.event {
+            try!(check(event));
+            push_field(&mut frame, "event", event);
+        }
+        if let Some(ref id) = self.id {
+            try!(check(id));
+            if id.contains('\0') {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, SseError::NulInId));
+            }
+            push_field(&mut frame, "id", id);
+        }
+        if let Some(retry) = self.retry {
+            push_field(&mut frame, "retry", &retry.to_string());
+        }
+        for line in Lines(Some(&self.data)) {
+            push_field(&mut frame, "data", line);
+        }
+        frame.push('\n');
+        writer.write_all(frame.as_bytes())
+    }
+}
+
+/// Writes a comment, which readers ignore, to `writer`, as servers do to
+/// keep connections open. Each line of `text` is written as a comment.
+pub fn write_comment<W: Write + ?Sized>(writer: &mut W, text: &str) -> io::Result<()> {
+    let mut frame = String::new();
+    for line in Lines(Some(text)) {
+        push_field(&mut frame, "", line);
+    }
+    writer.write_all(frame.as_bytes())
+}
+
+fn check(value: &str) -> io::Result<()> {
+    if value.contains(&['\r', '\n'][..]) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, SseError::LineBreak));
+    }
+    Ok(())
+}
+
+fn push_field(frame: &mut String, name: &str, value: &str) {
+    frame.push_str(name);
+    frame.push(':');
+    if !value.is_empty() {
+        frame.push(' ');
+        frame.push_str(value);
+    }
+    frame.push('\n');
+}
+
+// The lines of a string whose lines may end with CR, LF or CRLF; there is
+// always at least one.
+struct Lines<'a>(Option<&'a str>);
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let s = match self.0 {
+            Some(s) => s,
+            None => return None,
+        };
+        match s.find(&['\r', '\n'][..]) {
+            Some(i) => {
+                let end = if s[i..].starts_with("\r\n") { i + 2 } else { i + 1 };
+                self.0 = Some(&s[end..]);
+                Some(&s[..i])
+            }
+            None => {
+                self.0 = None;
+                Some(s)
+            }
+        }
+    }
+}
+
+/// A reader of the events of a `text/event-stream` body.
+pub struct EventReader<R: Read> {
+    reader: R,
+    buf: Vec<u8>,
+    pos: usize,
+    // Whether the last line ended with CR, so that an LF next is part of
+    // its line ending.
+    after_cr: bool,
+    started: bool,
+    done: bool,
+    last_id: Option<String>,
+}
+
+impl<R: Read> EventReader<R> {
+    /// Creates a reader of the events read from `reader`.
+    pub fn new(reader: R) -> EventReader<R> {
+        EventReader {
+            reader: reader,
+            buf: Vec::new(),
+            pos: 0,
+            after_cr: false,
+            started: false,
+            done: false,
+            last_id: None,
+        }
+    }
+
+    /// Returns the last event ID given in the stream so far, which a client
+    /// reconnecting sends as `Last-Event-ID`.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_id.as_ref().map(|id| &id[..])
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // Reads the next line, without its line ending, or `None` at the end
+    // of the stream, where a last line without a line ending is dropped.
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = Vec::new();
+        loop {
+            if self.pos == self.buf.len() {
+                self.buf.resize(8192, 0);
+                let n = loop {
+                    match self.reader.read(&mut self.buf) {
+                        Ok(n) => break n,
+                        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(e) => {
+                            self.buf.clear();
+                            return Err(e);
+                        }
+                    }
+                };
+                self.buf.truncate(n);
+                self.pos = 0;
+                if n == 0 {
+                    return Ok(None);
+                }
+            }
+            if mem::replace(&mut self.after_cr, false) && self.buf[self.pos] == b'\n' {
+                self.pos += 1;
+                continue;
+            }
+            let rest = &self.buf[self.pos..];
+            match rest.iter().position(|&b| b == b'\r' || b == b'\n') {
+                Some(i) => {
+                    line.extend_from_slice(&rest[..i]);
+                    self.after_cr = rest[i] == b'\r';
+                    self.pos += i + 1;
+                    if !self.started {
+                        self.started = true;
+                        if line.starts_with(b"\xef\xbb\xbf") {
+                            line.drain(..3);
+                        }
+                    }
+                    return Ok(Some(line));
+                }
+                None => {
+                    line.extend_from_slice(rest);
+                    self.pos = self.buf.len();
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for EventReader<R> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<io::Result<Event>> {
+        if self.done {
+            return None;
+        }
+        let mut event = Event::default();
+        let mut data = String::new();
+        loop {
+            let line = match self.read_line() {
+                Ok(Some(line)) => line,
+                Ok(None) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(e) => return Some(Err(e)),
+            };
+            let line = String::from_utf8_lossy(&line);
+            if line.is_empty() {
+                if data.is_empty() {
+                    event = Event::default();
+                    continue;
+                }
+                data.pop();
+                event.data = data;
+                event.id = self.last_id.clone();
+                return Some(Ok(event));
+            }
+            let (name, value) = match line.find(':') {
+                Some(0) => continue,
+                Some(i) => {
+                    let value = &line[i + 1..];
+                    (&line[..i], value.strip_prefix(' ').unwrap_or(value))
+                }
+                None => (&line[..], ""),
+            };
+            match name {
+                "event" => event.event = Some(value.to_string()),
+                "data" => {
+                    data.push_str(value);
+                    data.push('\n');
+                }
+                "id" if !value.contains('\0') => {
+                    self.last_id = if value.is_empty() { None } else { Some(value.to_string()) };
+                }
+                "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                    event.retry = value.parse().ok();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read};
+    use sse::{self, Event, EventReader, SseError};
+
+    fn read(body: &[u8]) -> Vec<Event> {
+        EventReader::new(body).map(|e| e.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_write() {
+        let mut out = Vec::new();
+        let event = Event {
+            event: Some("e".to_string()),
+            data: "a\r\nb\rc\n\nd".to_string(),
+            id: Some(String::new()),
+            retry: Some(3000),
+        };
+        event.write(&mut out).unwrap();
+        sse::write_comment(&mut out, "keep\nalive").unwrap();
+        Event::new("").write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out.clone()).unwrap(),
+                   "event: e\nid:\nretry: 3000\ndata: a\ndata: b\ndata: c\ndata:\ndata: d\n\n\
+                    : keep\n: alive\ndata:\n\n");
+        let events = read(&out);
+        assert_eq!(events, vec![Event { data: "a\nb\nc\n\nd".to_string(), id: None, ..event },
+                                Event::default()]);
+
+        for &(event, id, err) in &[("a\nb", "", SseError::LineBreak),
+                                   ("", "\r", SseError::LineBreak),
+                                   ("", "\0", SseError::NulInId)] {
+            let event = Event {
+                event: Some(event.to_string()),
+                id: Some(id.to_string()),
+                ..Event::default()
+            };
+            let e = event.write(&mut Vec::new()).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(*e.get_ref().unwrap().downcast_ref::<SseError>().unwrap(), err);
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        let body = b"\xef\xbb\xbfdata:a\r\ndata:  b\r\rid: 1\nevent: x\ndata\n\n:comment\n\
+                     retry: 10\nretry: 1x\nfoo: bar\nid: 2\0\ndata: c\n\n\
+                     event: empty\nid\n\ndata: \xff\n\ndata: unfinished\n";
+        let events = read(body);
+        assert_eq!(events, vec![
+            Event { data: "a\n b".to_string(), ..Event::default() },
+            Event { event: Some("x".to_string()), id: Some("1".to_string()), ..Event::default() },
+            Event { data: "c".to_string(), id: Some("1".to_string()), retry: Some(10),
+                    ..Event::default() },
+            Event { data: "\u{fffd}".to_string(), ..Event::default() },
+        ]);
+
+        // Line endings split across reads.
+        struct Bytes<'a>(&'a [u8]);
+        impl<'a> Read for Bytes<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.0.is_empty() || buf.is_empty() {
+                    return Ok(0);
+                }
+                buf[0] = self.0[0];
+                self.0 = &self.0[1..];
+                Ok(1)
+            }
+        }
+        let mut reader = EventReader::new(Bytes(b"id: 9\r\ndata: a\r\r\ndata: b\r\n\r\n"));
+        let events: Vec<_> = reader.by_ref().map(|e| e.unwrap().data).collect();
+        assert_eq!(events, ["a", "b"]);
+        assert_eq!(reader.last_event_id(), Some("9"));
+    }
+}
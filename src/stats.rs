@@ -0,0 +1,485 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Measuring what values are made of while encoding them
+//!
+//! `encode` encodes any `Encodable` value into any `Encoder`, passing the
+//! encoder everything unchanged, and returns `Stats` about what was
+//! encoded: the number of values of each type, the total size of the
+//! strings, how deeply values were nested and how often each struct field
+//! name and map key appeared. `to_json` does the same while encoding as
+//! JSON, whose length gives the size of the payload, so a change in it can
+//! be traced to what caused it.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use std::collections::BTreeMap;
+//! use rustc_serialize::stats;
+//!
+//! fn main() {
+//!     let mut tags = BTreeMap::new();
+//!     tags.insert("env".to_string(), vec!["prod".to_string()]);
+//!     tags.insert("team".to_string(), vec![]);
+//!     let (json, stats) = stats::to_json(&tags).unwrap();
+//!     assert_eq!(json, r#"{"env":["prod"],"team":[]}"#);
+//!     assert_eq!((stats.maps, stats.seqs, stats.strings), (1, 2, 1));
+//!     assert_eq!((stats.string_bytes, stats.max_depth), (4, 2));
+//!     assert_eq!(stats.keys["team"], 1);
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::mem;
+use std::path::Path;
+
+use json;
+use serialize::{Encodable, Encoder};
+use wtf8::Wtf8Buf;
+
+/// What a value was made of; see the module documentation.
+///
+/// Map keys are counted in `keys` alone, not as values.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// The number of units (`()`) and `None`s
+    pub nulls: u64,
+    /// The number of booleans
+    pub booleans: u64,
+    /// The number of integers
+    pub integers: u64,
+    /// The number of floating point numbers
+    pub floats: u64,
+    /// The number of strings and characters
+    pub strings: u64,
+    /// The total length of the strings and characters, in bytes of UTF-8
+    pub string_bytes: u64,
+    /// The number of sequences
+    pub seqs: u64,
+    /// The number of maps
+    pub maps: u64,
+    /// The number of structs and tuple structs
+    pub structs: u64,
+    /// The number of tuples
+    pub tuples: u64,
+    /// The number of enum variants
+    pub variants: u64,
+    /// The most sequences, maps, structs, tuples and enum variants with
+    /// arguments or fields nested in each other: 0 for a scalar, 1 for a
+    /// sequence of scalars and so on
+    pub max_depth: usize,
+    /// The number of occurrences of each struct field name and map key,
+    /// with keys which are not strings in their `Display` form. The fields
+    /// of struct-like enum variants count only if they are emitted with
+    /// `emit_enum_struct_variant_field`; derived impls emit them as
+    /// positional arguments, without their names.
+    pub keys: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    /// Returns the total number of values.
+    pub fn values(&self) -> u64 {
+        self.nulls + self.booleans + self.integers + self.floats + self.strings + self.seqs +
+            self.maps + self.structs + self.tuples + self.variants
+    }
+}
+
+/// Encodes `value` into `e`, returning what it was made of.
+pub fn encode<T, E>(value: &T, e: &mut E) -> Result<Stats, E::Error>
+    where T: Encodable + ?Sized, E: Encoder
+{
+    let state = RefCell::new(State { stats: Stats::default(), depth: 0, key: None });
+    try!(value.encode(&mut Counter { inner: e, state: &state }));
+    Ok(state.into_inner().stats)
+}
+
+/// Encodes `value` as JSON, returning the JSON and what it was made of.
+pub fn to_json<T: Encodable + ?Sized>(value: &T) -> json::EncodeResult<(String, Stats)> {
+    let mut s = String::new();
+    let stats = {
+        let mut encoder = json::Encoder::new(&mut s);
+        try!(encode(value, &mut encoder))
+    };
+    Ok((s, stats))
+}
+
+struct State {
+    stats: Stats,
+    // The number of compound values enclosing the value being encoded.
+    depth: usize,
+    // The text of the map key being encoded, if one is.
+    key: Option<String>,
+}
+
+// The `Encoder` handed to `Encodable::encode` by `encode`, which, like
+// `Redactor` in `redact`, is only reachable through the `&mut` borrows
+// passed to `Encodable` implementations.
+struct Counter<'a, E: 'a> {
+    inner: &'a mut E,
+    state: &'a RefCell<State>,
+}
+
+// Stretches the lifetime of the encoder passed to a nested callback to that
+// of the enclosing `Counter`. As in `redact`, the reference only lives for
+// the duration of the callback, and the encoder behind it outlives `'a`.
+unsafe fn rebind<'a, E>(e: &mut E) -> &'a mut E {
+    &mut *(e as *mut E)
+}
+
+// Adapts an `FnOnce(&mut Counter)` into the `FnOnce(&mut E)` taken by the
+// inner encoder's compound methods.
+macro_rules! nested {
+    ($c:ident, $f:expr) => ({
+        let (state, f) = ($c.state, $f);
+        move |e: &mut E| f(&mut Counter { inner: unsafe { rebind(e) }, state: state })
+    })
+}
+
+macro_rules! scalar {
+    ($name:ident, $ty:ty, $count:ident) => (
+        fn $name(&mut self, v: $ty) -> Result<(), E::Error> {
+            if !self.key(&v.to_string()) {
+                self.state.borrow_mut().stats.$count += 1;
+            }
+            self.inner.$name(v)
+        }
+    )
+}
+
+// Encodes a compound value through the inner encoder's `$method`, counting
+// it in `$count` and, if `$nests`, as a level of nesting.
+macro_rules! compound {
+    ($c:ident, $count:ident, $nests:expr, $f:ident, $method:ident($($arg:expr),*)) => ({
+        let nests = $nests && $c.enter();
+        if $c.state.borrow().key.is_none() {
+            $c.state.borrow_mut().stats.$count += 1;
+        }
+        let result = $c.inner.$method($($arg,)* nested!($c, $f));
+        if nests {
+            $c.state.borrow_mut().depth -= 1;
+        }
+        result
+    })
+}
+
+impl<'a, E: Encoder> Counter<'a, E> {
+    // Records the text of a scalar in the map key being encoded, returning
+    // whether there is one.
+    fn key(&self, text: &str) -> bool {
+        match self.state.borrow_mut().key {
+            Some(ref mut key) => {
+                key.push_str(text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Enters a level of nesting, returning whether it counts, which it
+    // does outside map keys.
+    fn enter(&self) -> bool {
+        let mut state = self.state.borrow_mut();
+        if state.key.is_some() {
+            return false;
+        }
+        state.depth += 1;
+        state.stats.max_depth = state.stats.max_depth.max(state.depth);
+        true
+    }
+
+    fn count_key(&self, key: &str) {
+        let mut state = self.state.borrow_mut();
+        if state.key.is_none() {
+            *state.stats.keys.entry(key.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    fn count_str(&self, v: &str) {
+        if !self.key(v) {
+            let mut state = self.state.borrow_mut();
+            state.stats.strings += 1;
+            state.stats.string_bytes += v.len() as u64;
+        }
+    }
+}
+
+impl<'a, E: Encoder> Encoder for Counter<'a, E> {
+    type Error = E::Error;
+
+    fn emit_nil(&mut self) -> Result<(), E::Error> {
+        if !self.key("null") {
+            self.state.borrow_mut().stats.nulls += 1;
+        }
+        self.inner.emit_nil()
+    }
+    scalar!(emit_usize, usize, integers);
+    scalar!(emit_u64, u64, integers);
+    scalar!(emit_u32, u32, integers);
+    scalar!(emit_u16, u16, integers);
+    scalar!(emit_u8, u8, integers);
+    scalar!(emit_isize, isize, integers);
+    scalar!(emit_i64, i64, integers);
+    scalar!(emit_i32, i32, integers);
+    scalar!(emit_i16, i16, integers);
+    scalar!(emit_i8, i8, integers);
+    scalar!(emit_u128, u128, integers);
+    scalar!(emit_i128, i128, integers);
+    scalar!(emit_bool, bool, booleans);
+    scalar!(emit_f64, f64, floats);
+    scalar!(emit_f32, f32, floats);
+    fn emit_char(&mut self, v: char) -> Result<(), E::Error> {
+        self.count_str(v.encode_utf8(&mut [0; 4]));
+        self.inner.emit_char(v)
+    }
+    fn emit_str(&mut self, v: &str) -> Result<(), E::Error> {
+        self.count_str(v);
+        self.inner.emit_str(v)
+    }
+    fn emit_wtf8(&mut self, v: &Wtf8Buf) -> Result<(), E::Error> {
+        self.count_str(&v.to_string_lossy());
+        self.inner.emit_wtf8(v)
+    }
+    fn emit_path(&mut self, v: &Path) -> Result<(), E::Error> {
+        self.count_str(&v.to_string_lossy());
+        self.inner.emit_path(v)
+    }
+
+    fn emit_enum<F>(&mut self, name: &str, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        self.inner.emit_enum(name, nested!(self, f))
+    }
+    fn emit_enum_variant<F>(&mut self, v_name: &str, v_id: usize, len: usize, f: F)
+                            -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        compound!(self, variants, len > 0, f, emit_enum_variant(v_name, v_id, len))
+    }
+    fn emit_enum_variant_arg<F>(&mut self, a_idx: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        self.inner.emit_enum_variant_arg(a_idx, nested!(self, f))
+    }
+    fn emit_enum_struct_variant<F>(&mut self, v_name: &str, v_id: usize, len: usize, f: F)
+                                   -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        compound!(self, variants, len > 0, f, emit_enum_struct_variant(v_name, v_id, len))
+    }
+    fn emit_enum_struct_variant_field<F>(&mut self, f_name: &str, f_idx: usize, f: F)
+                                         -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        self.count_key(f_name);
+        self.inner.emit_enum_struct_variant_field(f_name, f_idx, nested!(self, f))
+    }
+
+    fn emit_struct<F>(&mut self, name: &str, len: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        compound!(self, structs, true, f, emit_struct(name, len))
+    }
+    fn emit_struct_field<F>(&mut self, f_name: &str, f_idx: usize, f: F)
+                            -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        self.count_key(f_name);
+        self.inner.emit_struct_field(f_name, f_idx, nested!(self, f))
+    }
+
+    fn emit_tuple<F>(&mut self, len: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        compound!(self, tuples, true, f, emit_tuple(len))
+    }
+    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        self.inner.emit_tuple_arg(idx, nested!(self, f))
+    }
+    fn emit_tuple_struct<F>(&mut self, name: &str, len: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        compound!(self, structs, true, f, emit_tuple_struct(name, len))
+    }
+    fn emit_tuple_struct_arg<F>(&mut self, f_idx: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        self.inner.emit_tuple_struct_arg(f_idx, nested!(self, f))
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        self.inner.emit_option(nested!(self, f))
+    }
+    fn emit_option_none(&mut self) -> Result<(), E::Error> {
+        if !self.key("null") {
+            self.state.borrow_mut().stats.nulls += 1;
+        }
+        self.inner.emit_option_none()
+    }
+    fn emit_option_some<F>(&mut self, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        self.inner.emit_option_some(nested!(self, f))
+    }
+
+    fn emit_seq<F>(&mut self, len: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        compound!(self, seqs, true, f, emit_seq(len))
+    }
+    fn emit_seq_elt<F>(&mut self, idx: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        self.inner.emit_seq_elt(idx, nested!(self, f))
+    }
+
+    fn emit_map<F>(&mut self, len: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        compound!(self, maps, true, f, emit_map(len))
+    }
+    fn emit_map_elt_key<F>(&mut self, idx: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        let outer = self.state.borrow_mut().key.replace(String::new());
+        let result = self.inner.emit_map_elt_key(idx, nested!(self, f));
+        let key = mem::replace(&mut self.state.borrow_mut().key, outer).unwrap();
+        self.count_key(&key);
+        result
+    }
+    fn emit_map_elt_val<F>(&mut self, idx: usize, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        self.inner.emit_map_elt_val(idx, nested!(self, f))
+    }
+
+    fn emit_tagged<F>(&mut self, tag: u64, f: F) -> Result<(), E::Error>
+        where F: FnOnce(&mut Self) -> Result<(), E::Error>
+    {
+        self.inner.emit_tagged(tag, nested!(self, f))
+    }
+
+    fn format(&self) -> Option<&'static str> {
+        self.inner.format()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use cbor;
+    use json::{self, Json};
+    use stats::{self, Stats};
+    use {Encodable, Encoder};
+
+    struct Event {
+        name: String,
+        at: (u32, f64),
+        by: Option<Actor>,
+        labels: BTreeMap<u8, bool>,
+    }
+
+    impl Encodable for Event {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_struct("Event", 4, |s| {
+                try!(s.emit_struct_field("name", 0, |s| self.name.encode(s)));
+                try!(s.emit_struct_field("at", 1, |s| self.at.encode(s)));
+                try!(s.emit_struct_field("by", 2, |s| self.by.encode(s)));
+                s.emit_struct_field("labels", 3, |s| self.labels.encode(s))
+            })
+        }
+    }
+
+    enum Actor {
+        System,
+        User { id: u64, name: char },
+    }
+
+    impl Encodable for Actor {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_enum("Actor", |s| {
+                match *self {
+                    Actor::System => s.emit_enum_variant("System", 0, 0, |_| Ok(())),
+                    Actor::User { ref id, ref name } => s.emit_enum_variant("User", 1, 2, |s| {
+                        try!(s.emit_enum_variant_arg(0, |s| id.encode(s)));
+                        s.emit_enum_variant_arg(1, |s| name.encode(s))
+                    }),
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut labels = BTreeMap::new();
+        labels.insert(1, true);
+        labels.insert(2, false);
+        let events = vec![
+            Event { name: "héllo".to_string(), at: (1, 0.5), by: None, labels: labels },
+            Event {
+                name: "x".to_string(),
+                at: (2, 1.5),
+                by: Some(Actor::User { id: 3, name: 'é' }),
+                labels: BTreeMap::new(),
+            },
+            Event { name: String::new(), at: (3, 2.5), by: Some(Actor::System),
+                    labels: BTreeMap::new() },
+        ];
+        let (json, stats) = stats::to_json(&events).unwrap();
+        let mut keys = BTreeMap::new();
+        // `Actor::User` encodes its fields as positional arguments, as a
+        // derived impl would, so `id` and its `name` are not counted.
+        for &(key, n) in [("1", 1), ("2", 1), ("at", 3), ("by", 3), ("labels", 3),
+                          ("name", 3)].iter() {
+            keys.insert(key.to_string(), n);
+        }
+        assert_eq!(stats, Stats {
+            nulls: 1,
+            booleans: 2,
+            integers: 4,
+            floats: 3,
+            strings: 4,
+            string_bytes: 6 + 1 + 2,
+            seqs: 1,
+            maps: 3,
+            structs: 3,
+            tuples: 3,
+            variants: 2,
+            max_depth: 3,
+            keys: keys,
+        });
+        assert_eq!(stats.values(), 26);
+
+        // The encoder gets the value unchanged.
+        let mut encoder = cbor::Encoder::new();
+        assert_eq!(stats::encode(&events, &mut encoder).unwrap(), stats);
+        assert_eq!(Json::from_str(&json).unwrap().as_array().unwrap().len(), 3);
+        assert_eq!(stats::to_json(&7).unwrap().1.max_depth, 0);
+    }
+
+    #[test]
+    fn test_path_strings() {
+        let path = Path::new("/tmp/a");
+        let mut out = String::new();
+        let stats = {
+            let mut encoder = json::Encoder::new(&mut out);
+            encoder.set_path_strings(true);
+            stats::encode(path, &mut encoder).unwrap()
+        };
+        assert_eq!(out, "\"/tmp/a\"");
+        assert_eq!((stats.strings, stats.string_bytes, stats.seqs), (1, 6, 0));
+    }
+}
@@ -0,0 +1,667 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tar headers
+//!
+//! A tar archive is a series of entries, each a 512-byte header followed
+//! by its contents, padded to a multiple of 512 bytes, and ends with two
+//! blocks of zeros. `Header` holds the fields of a header, and converts
+//! it to and from the ustar format of POSIX: numbers written in octal,
+//! names of up to 255 bytes split between the name and prefix fields,
+//! and a checksum of the block.
+//!
+//! Fields which the format cannot hold are written as extensions:
+//! `Header::encode` writes a pax extended header, of type `x`, before the
+//! header for longer names, link names, user and group names, and numbers
+//! too large for their octal fields, which are also written in the base-256
+//! form of GNU tar. `read_header` reads pax extended headers and the long
+//! names of GNU tar (types `L` and `K`) back into the header they precede.
+//! Global pax headers (type `g`) are skipped.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use std::io::Read;
+//! use rustc_serialize::tarhdr::{self, EntryType, Header};
+//!
+//! fn main() {
+//!     let mut header = Header::new("backup/index.json", 2);
+//!     header.mode = 0o640;
+//!     header.mtime = 1496318400;
+//!     let mut archive = header.encode().unwrap();
+//!     assert_eq!(archive.len(), 512);
+//!     archive.extend_from_slice(b"{}");
+//!     archive.extend_from_slice(&[0; 510]);
+//!     tarhdr::write_end(&mut archive).unwrap();
+//!
+//!     let mut reader = &archive[..];
+//!     let read = tarhdr::read_header(&mut reader).unwrap().unwrap();
+//!     assert_eq!(read, header);
+//!     assert_eq!(read.entry_type, EntryType::Regular);
+//!     let mut contents = vec![0; read.size as usize + tarhdr::padding(read.size)];
+//!     reader.read_exact(&mut contents).unwrap();
+//!     assert_eq!(&contents[..2], b"{}");
+//!     assert_eq!(tarhdr::read_header(&mut reader).unwrap(), None);
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::str;
+
+/// The size of a block of an archive, and of a header.
+pub const BLOCK_SIZE: usize = 512;
+
+/// The largest pax extended header or GNU long name `read_header` accepts,
+/// in bytes.
+pub const MAX_EXTENSION: u64 = 1 << 20;
+
+/// Errors from decoding and encoding headers. `read_header` reports them
+/// as `io::Error`s of kind `InvalidData` wrapping a `TarError`, except that
+/// an archive ending within an entry is an `UnexpectedEof` error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TarError {
+    /// The checksum of the header did not match its bytes
+    InvalidChecksum,
+    /// The numeric field named was not an octal or base-256 number, or
+    /// was too large for its type
+    InvalidNumber(&'static str),
+    /// A name was not UTF-8
+    InvalidUtf8,
+    /// A name was too long for a single ustar header; returned by
+    /// `to_block`, whereas `encode` writes an extension
+    NameTooLong,
+    /// A name held a NUL byte
+    NulInName,
+    /// A pax extended header or GNU long name was malformed, or larger
+    /// than `MAX_EXTENSION`
+    InvalidExtension,
+}
+
+impl TarError {
+    /// Returns the `TarError` wrapped by `err`, if any.
+    pub fn from_io_error(err: &io::Error) -> Option<TarError> {
+        err.get_ref().and_then(|e| e.downcast_ref::<TarError>()).cloned()
+    }
+}
+
+impl fmt::Display for TarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TarError::InvalidChecksum => write!(f, "invalid header checksum"),
+            TarError::InvalidNumber(field) => write!(f, "invalid {} field", field),
+            TarError::InvalidUtf8 => write!(f, "name is not UTF-8"),
+            TarError::NameTooLong => write!(f, "name too long for a ustar header"),
+            TarError::NulInName => write!(f, "NUL in name"),
+            TarError::InvalidExtension => write!(f, "invalid extended header"),
+        }
+    }
+}
+
+impl error::Error for TarError {
+    fn description(&self) -> &str { "invalid tar header" }
+}
+
+/// The type of an entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryType {
+    /// A regular file, `0` (or NUL in old archives)
+    Regular,
+    /// A hard link to the entry named by the link name, `1`
+    HardLink,
+    /// A symbolic link to the link name, `2`
+    Symlink,
+    /// A character device, `3`
+    CharDevice,
+    /// A block device, `4`
+    BlockDevice,
+    /// A directory, `5`
+    Directory,
+    /// A FIFO, `6`
+    Fifo,
+    /// A contiguous file, `7`, which readers treat as a regular file
+    Contiguous,
+    /// Any other type, given by its byte
+    Other(u8),
+}
+
+impl EntryType {
+    /// Returns the type with the type flag `flag`.
+    pub fn from_byte(flag: u8) -> EntryType {
+        match flag {
+            b'0' | b'\0' => EntryType::Regular,
+            b'1' => EntryType::HardLink,
+            b'2' => EntryType::Symlink,
+            b'3' => EntryType::CharDevice,
+            b'4' => EntryType::BlockDevice,
+            b'5' => EntryType::Directory,
+            b'6' => EntryType::Fifo,
+            b'7' => EntryType::Contiguous,
+            flag => EntryType::Other(flag),
+        }
+    }
+
+    /// Returns the type flag of the type.
+    pub fn as_byte(&self) -> u8 {
+        match *self {
+            EntryType::Regular => b'0',
+            EntryType::HardLink => b'1',
+            EntryType::Symlink => b'2',
+            EntryType::CharDevice => b'3',
+            EntryType::BlockDevice => b'4',
+            EntryType::Directory => b'5',
+            EntryType::Fifo => b'6',
+            EntryType::Contiguous => b'7',
+            EntryType::Other(flag) => flag,
+        }
+    }
+}
+
+/// The fields of a header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// The path of the entry
+    pub name: String,
+    /// The permission bits of the entry
+    pub mode: u32,
+    /// The user ID of the owner
+    pub uid: u64,
+    /// The group ID of the owner
+    pub gid: u64,
+    /// The size of the contents following the header, in bytes
+    pub size: u64,
+    /// The modification time, in seconds since the epoch
+    pub mtime: u64,
+    /// The type of the entry
+    pub entry_type: EntryType,
+    /// The target of a link
+    pub link_name: String,
+    /// The user name of the owner
+    pub uname: String,
+    /// The group name of the owner
+    pub gname: String,
+    /// The major number of a device
+    pub dev_major: u32,
+    /// The minor number of a device
+    pub dev_minor: u32,
+}
+
+// The offsets and lengths of the fields of a ustar header.
+const NAME: (usize, usize) = (0, 100);
+const MODE: (usize, usize) = (100, 8);
+const UID: (usize, usize) = (108, 8);
+const GID: (usize, usize) = (116, 8);
+const SIZE: (usize, usize) = (124, 12);
+const MTIME: (usize, usize) = (136, 12);
+const CHKSUM: (usize, usize) = (148, 8);
+const TYPEFLAG: usize = 156;
+const LINKNAME: (usize, usize) = (157, 100);
+const MAGIC: (usize, usize) = (257, 8);
+const UNAME: (usize, usize) = (265, 32);
+const GNAME: (usize, usize) = (297, 32);
+const DEVMAJOR: (usize, usize) = (329, 8);
+const DEVMINOR: (usize, usize) = (337, 8);
+const PREFIX: (usize, usize) = (345, 155);
+
+impl Header {
+    /// Creates the header of a regular file `name` of `size` bytes, with the
+    /// mode 0644 and the other fields 0 or empty.
+    pub fn new(name: &str, size: u64) -> Header {
+        Header {
+            name: name.to_string(),
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+            size: size,
+            mtime: 0,
+            entry_type: EntryType::Regular,
+            link_name: String::new(),
+            uname: String::new(),
+            gname: String::new(),
+            dev_major: 0,
+            dev_minor: 0,
+        }
+    }
+
+    /// Decodes a header from `block`, checking its checksum. Headers of
+    /// the ustar format, of GNU tar, and of the tar of Version 7 Unix,
+    /// without owner names or a prefix, are read.
+    pub fn from_block(block: &[u8; BLOCK_SIZE]) -> Result<Header, TarError> {
+        let stored = try!(number(field(block, CHKSUM), "chksum"));
+        let unsigned: u64 = block.iter().enumerate().map(|(i, &b)| {
+            if i >= CHKSUM.0 && i < CHKSUM.0 + CHKSUM.1 { b' ' as u64 } else { b as u64 }
+        }).sum();
+        // Some old writers summed the bytes as signed.
+        let signed: i64 = block.iter().enumerate().map(|(i, &b)| {
+            if i >= CHKSUM.0 && i < CHKSUM.0 + CHKSUM.1 { b' ' as i64 } else { b as i8 as i64 }
+        }).sum();
+        if stored != unsigned && stored as i64 != signed {
+            return Err(TarError::InvalidChecksum);
+        }
+
+        let ustar = field(block, MAGIC).starts_with(b"ustar");
+        let mut name = try!(text(field(block, NAME)));
+        if ustar && block[PREFIX.0] != 0 && &field(block, MAGIC)[..6] == b"ustar\0" {
+            name = format!("{}/{}", try!(text(field(block, PREFIX))), name);
+        }
+        let owner = |f| if ustar { text(field(block, f)) } else { Ok(String::new()) };
+        let device = |f, n| if ustar { number(field(block, f), n) } else { Ok(0) };
+        Ok(Header {
+            name: name,
+            mode: try!(small(try!(number(field(block, MODE), "mode")), "mode")),
+            uid: try!(number(field(block, UID), "uid")),
+            gid: try!(number(field(block, GID), "gid")),
+            size: try!(number(field(block, SIZE), "size")),
+            mtime: try!(number(field(block, MTIME), "mtime")),
+            entry_type: EntryType::from_byte(block[TYPEFLAG]),
+            link_name: try!(text(field(block, LINKNAME))),
+            uname: try!(owner(UNAME)),
+            gname: try!(owner(GNAME)),
+            dev_major: try!(small(try!(device(DEVMAJOR, "devmajor")), "devmajor")),
+            dev_minor: try!(small(try!(device(DEVMINOR, "devminor")), "devminor")),
+        })
+    }
+
+    /// Encodes the header as a single ustar block. Numbers too large for
+    /// their octal fields are written in base 256.
+    ///
+    /// Fails with `TarError::NameTooLong` if a name does not fit its
+    /// fields; `encode` writes an extension for it instead.
+    pub fn to_block(&self) -> Result<[u8; BLOCK_SIZE], TarError> {
+        for name in [&self.name, &self.link_name, &self.uname, &self.gname].iter() {
+            if name.contains('\0') {
+                return Err(TarError::NulInName);
+            }
+        }
+        let (prefix, name) = try!(split_name(&self.name).ok_or(TarError::NameTooLong));
+        if self.link_name.len() > LINKNAME.1 || self.uname.len() > UNAME.1 ||
+           self.gname.len() > GNAME.1 {
+            return Err(TarError::NameTooLong);
+        }
+        let mut block = [0; BLOCK_SIZE];
+        put(&mut block, NAME, name.as_bytes());
+        put_number(&mut block, MODE, self.mode as u64);
+        put_number(&mut block, UID, self.uid);
+        put_number(&mut block, GID, self.gid);
+        put_number(&mut block, SIZE, self.size);
+        put_number(&mut block, MTIME, self.mtime);
+        block[TYPEFLAG] = self.entry_type.as_byte();
+        put(&mut block, LINKNAME, self.link_name.as_bytes());
+        put(&mut block, MAGIC, b"ustar\x0000");
+        put(&mut block, UNAME, self.uname.as_bytes());
+        put(&mut block, GNAME, self.gname.as_bytes());
+        put_number(&mut block, DEVMAJOR, self.dev_major as u64);
+        put_number(&mut block, DEVMINOR, self.dev_minor as u64);
+        put(&mut block, PREFIX, prefix.as_bytes());
+
+        put(&mut block, CHKSUM, b"        ");
+        let sum: u64 = block.iter().map(|&b| b as u64).sum();
+        put(&mut block, CHKSUM, format!("{:06o}\0 ", sum).as_bytes());
+        Ok(block)
+    }
+
+    /// Encodes the header as ustar blocks, preceded by a pax extended
+    /// header for the fields a ustar header cannot hold, if any.
+    pub fn encode(&self) -> Result<Vec<u8>, TarError> {
+        let mut records = String::new();
+        if split_name(&self.name).is_none() {
+            push_record(&mut records, "path", &self.name);
+        }
+        for &(key, value, len) in &[("linkpath", &self.link_name, LINKNAME.1),
+                                    ("uname", &self.uname, UNAME.1),
+                                    ("gname", &self.gname, GNAME.1)] {
+            if value.len() > len {
+                push_record(&mut records, key, value);
+            }
+        }
+        for &(key, value, len) in &[("uid", self.uid, UID.1), ("gid", self.gid, GID.1),
+                                    ("size", self.size, SIZE.1), ("mtime", self.mtime, MTIME.1)] {
+            if value >= 1 << (3 * (len - 1)) {
+                push_record(&mut records, key, &value.to_string());
+            }
+        }
+
+        let mut short = self.clone();
+        if split_name(&short.name).is_none() {
+            short.name = truncate(&short.name, NAME.1);
+        }
+        short.link_name = truncate(&short.link_name, LINKNAME.1);
+        short.uname = truncate(&short.uname, UNAME.1);
+        short.gname = truncate(&short.gname, GNAME.1);
+        let block = try!(short.to_block());
+        if records.is_empty() {
+            return Ok(block.to_vec());
+        }
+
+        let mut pax = Header::new(&truncate(&format!("PaxHeaders/{}", short.name), NAME.1),
+                                  records.len() as u64);
+        pax.entry_type = EntryType::Other(b'x');
+        let mut out = try!(pax.to_block()).to_vec();
+        out.extend_from_slice(records.as_bytes());
+        out.extend_from_slice(&[0; BLOCK_SIZE][..padding(records.len() as u64)]);
+        out.extend_from_slice(&block);
+        Ok(out)
+    }
+}
+
+/// Returns the number of bytes of padding after contents of `size` bytes.
+pub fn padding(size: u64) -> usize {
+    ((BLOCK_SIZE as u64 - size % BLOCK_SIZE as u64) % BLOCK_SIZE as u64) as usize
+}
+
+/// Writes the two blocks of zeros which end an archive.
+pub fn write_end<W: Write + ?Sized>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(&[0; 2 * BLOCK_SIZE])
+}
+
+/// Reads the next header from `reader`, with the pax extended headers and
+/// GNU long names before it applied, leaving `reader` at the start of its
+/// contents. Returns `None` at a block of zeros, which ends an archive, or
+/// at the end of the stream.
+pub fn read_header<R: Read + ?Sized>(reader: &mut R) -> io::Result<Option<Header>> {
+    let mut pax = Vec::new();
+    let mut long_name = None;
+    let mut long_link = None;
+    loop {
+        let mut block = [0; BLOCK_SIZE];
+        let read = try!(read_full(reader, &mut block));
+        if read == 0 && pax.is_empty() && long_name.is_none() && long_link.is_none() {
+            return Ok(None);
+        }
+        if read < BLOCK_SIZE {
+            return Err(truncated());
+        }
+        if block.iter().all(|&b| b == 0) {
+            if !pax.is_empty() || long_name.is_some() || long_link.is_some() {
+                return Err(invalid_data(TarError::InvalidExtension));
+            }
+            return Ok(None);
+        }
+        let mut header = try!(Header::from_block(&block).map_err(invalid_data));
+        match header.entry_type {
+            EntryType::Other(flag @ b'x') | EntryType::Other(flag @ b'g') |
+            EntryType::Other(flag @ b'L') | EntryType::Other(flag @ b'K') => {
+                if header.size > MAX_EXTENSION {
+                    return Err(invalid_data(TarError::InvalidExtension));
+                }
+                let mut data = vec![0; header.size as usize + padding(header.size)];
+                if try!(read_full(reader, &mut data)) < data.len() {
+                    return Err(truncated());
+                }
+                data.truncate(header.size as usize);
+                match flag {
+                    b'x' => pax = try!(parse_records(&data).map_err(invalid_data)),
+                    b'L' | b'K' => {
+                        let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+                        let name = try!(text(&data[..end]).map_err(invalid_data));
+                        if flag == b'L' { long_name = Some(name) } else { long_link = Some(name) }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if let Some(name) = long_name {
+            header.name = name;
+        }
+        if let Some(name) = long_link {
+            header.link_name = name;
+        }
+        for (key, value) in pax {
+            let number = || {
+                let whole = value.split('.').next().unwrap_or("");
+                whole.parse::<u64>().map_err(|_| invalid_data(TarError::InvalidExtension))
+            };
+            match &key[..] {
+                "path" => header.name = value.clone(),
+                "linkpath" => header.link_name = value.clone(),
+                "uname" => header.uname = value.clone(),
+                "gname" => header.gname = value.clone(),
+                "uid" => header.uid = try!(number()),
+                "gid" => header.gid = try!(number()),
+                "size" => header.size = try!(number()),
+                "mtime" => header.mtime = try!(number()),
+                _ => {}
+            }
+        }
+        return Ok(Some(header));
+    }
+}
+
+fn invalid_data(err: TarError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated tar archive")
+}
+
+// Reads until `buf` is full or the end of the stream, returning the number
+// of bytes read.
+fn read_full<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+fn field(block: &[u8], (offset, len): (usize, usize)) -> &[u8] {
+    &block[offset..offset + len]
+}
+
+// The text of a field, up to its first NUL.
+fn text(field: &[u8]) -> Result<String, TarError> {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    str::from_utf8(&field[..end]).map(|s| s.to_string()).map_err(|_| TarError::InvalidUtf8)
+}
+
+// The number in a numeric field: octal digits, between optional spaces and
+// ended by a NUL or space, or base 256 after a first byte of 0x80.
+fn number(field: &[u8], name: &'static str) -> Result<u64, TarError> {
+    if field[0] == 0x80 {
+        let bytes = &field[1..];
+        let skip = bytes.len().saturating_sub(8);
+        if bytes[..skip].iter().any(|&b| b != 0) {
+            return Err(TarError::InvalidNumber(name));
+        }
+        return Ok(bytes[skip..].iter().fold(0, |n, &b| n << 8 | b as u64));
+    }
+    let digits = field.iter().skip_while(|&&b| b == b' ')
+                      .take_while(|&&b| b != 0 && b != b' ');
+    let mut n = 0u64;
+    for &b in digits {
+        if !(b'0'..=b'7').contains(&b) {
+            return Err(TarError::InvalidNumber(name));
+        }
+        n = try!(n.checked_mul(8).ok_or(TarError::InvalidNumber(name))) + (b - b'0') as u64;
+    }
+    Ok(n)
+}
+
+fn small(n: u64, name: &'static str) -> Result<u32, TarError> {
+    if n > u32::MAX as u64 {
+        return Err(TarError::InvalidNumber(name));
+    }
+    Ok(n as u32)
+}
+
+fn put(block: &mut [u8], (offset, len): (usize, usize), bytes: &[u8]) {
+    block[offset..offset + bytes.len().min(len)].copy_from_slice(&bytes[..bytes.len().min(len)]);
+}
+
+// Writes `n` in octal, with a NUL after, or in base 256 if it is too large.
+fn put_number(block: &mut [u8], (offset, len): (usize, usize), n: u64) {
+    if n < 1 << (3 * (len - 1)) {
+        let digits = format!("{:0width$o}\0", n, width = len - 1);
+        put(block, (offset, len), digits.as_bytes());
+    } else {
+        block[offset] = 0x80;
+        for i in 0..8 {
+            block[offset + len - 1 - i] = (n >> (8 * i)) as u8;
+        }
+    }
+}
+
+// Splits `name` into the prefix and name fields of a ustar header, if it
+// fits them.
+fn split_name(name: &str) -> Option<(&str, &str)> {
+    if name.len() <= NAME.1 {
+        return Some(("", name));
+    }
+    // A directory's trailing slash is kept in the name field.
+    let search = name.strip_suffix('/').unwrap_or(name);
+    search.char_indices().filter(|&(_, c)| c == '/').map(|(i, _)| i)
+          .find(|&i| i <= PREFIX.1 && name.len() - i - 1 <= NAME.1 && i > 0)
+          .map(|i| (&name[..i], &name[i + 1..]))
+}
+
+// The longest start of `s` of at most `len` bytes.
+fn truncate(s: &str, len: usize) -> String {
+    let mut end = len.min(s.len());
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s[..end].to_string()
+}
+
+// Appends a pax record, `<length> <key>=<value>\n`, its length counting
+// its own digits.
+fn push_record(records: &mut String, key: &str, value: &str) {
+    let rest = key.len() + value.len() + 3;
+    let mut len = rest + 1;
+    while len != rest + len.to_string().len() {
+        len = rest + len.to_string().len();
+    }
+    records.push_str(&format!("{} {}={}\n", len, key, value));
+}
+
+fn parse_records(data: &[u8]) -> Result<Vec<(String, String)>, TarError> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = try!(rest.iter().position(|&b| b == b' ').ok_or(TarError::InvalidExtension));
+        let len = try!(str::from_utf8(&rest[..space]).ok().and_then(|s| s.parse::<usize>().ok())
+                           .ok_or(TarError::InvalidExtension));
+        if len <= space + 1 || len > rest.len() || rest[len - 1] != b'\n' {
+            return Err(TarError::InvalidExtension);
+        }
+        let record = try!(str::from_utf8(&rest[space + 1..len - 1])
+                              .map_err(|_| TarError::InvalidUtf8));
+        let eq = try!(record.find('=').ok_or(TarError::InvalidExtension));
+        records.push((record[..eq].to_string(), record[eq + 1..].to_string()));
+        rest = &rest[len..];
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use tarhdr::{self, EntryType, Header, TarError, BLOCK_SIZE};
+
+    fn round_trip(header: &Header) -> Header {
+        let archive = header.encode().unwrap();
+        let mut reader = &archive[..];
+        let read = tarhdr::read_header(&mut reader).unwrap().unwrap();
+        assert!(reader.is_empty());
+        read
+    }
+
+    #[test]
+    fn test_block() {
+        let mut header = Header::new("dir/", 0);
+        header.entry_type = EntryType::Directory;
+        header.mode = 0o755;
+        header.uid = 1000;
+        header.uname = "ada".to_string();
+        header.mtime = 0o77777777777;
+        let block = header.to_block().unwrap();
+        assert_eq!(&block[..5], b"dir/\0");
+        assert_eq!(&block[100..108], b"0000755\0");
+        assert_eq!(&block[136..148], b"77777777777\0");
+        assert_eq!(&block[156..165], b"5\0\0\0\0\0\0\0\0");
+        assert_eq!(&block[257..265], b"ustar\x0000");
+        assert_eq!(Header::from_block(&block), Ok(header.clone()));
+        assert_eq!(header.encode().unwrap(), &block[..]);
+
+        let mut bad = block;
+        bad[0] = b'e';
+        assert_eq!(Header::from_block(&bad), Err(TarError::InvalidChecksum));
+        let mut bad = block;
+        bad[101] = b'8';
+        bad[148..156].copy_from_slice(b"        ");
+        let sum: u32 = bad.iter().map(|&b| b as u32).sum();
+        bad[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+        assert_eq!(Header::from_block(&bad), Err(TarError::InvalidNumber("mode")));
+
+        let long = format!("{}/{}", "p".repeat(150), "n".repeat(100));
+        let block = Header::new(&long, 0).to_block().unwrap();
+        assert_eq!(&block[345..348], b"ppp");
+        assert_eq!(Header::from_block(&block).unwrap().name, long);
+        let longer = format!("{}/{}", "p".repeat(156), "n".repeat(10));
+        assert_eq!(Header::new(&longer, 0).to_block(), Err(TarError::NameTooLong));
+        assert_eq!(Header::new("a\0", 0).to_block(), Err(TarError::NulInName));
+    }
+
+    #[test]
+    fn test_extensions() {
+        let mut header = Header::new(&"é".repeat(200), 1 << 34);
+        header.link_name = "l".repeat(101);
+        header.gname = "g".repeat(33);
+        header.uid = 1 << 21;
+        let encoded = header.encode().unwrap();
+        assert_eq!(encoded[156], b'x');
+        assert_eq!(encoded.len(), 4 * BLOCK_SIZE);
+        assert_eq!(&encoded[3 * BLOCK_SIZE + 124..3 * BLOCK_SIZE + 128], b"\x80\0\0\0");
+        assert_eq!(round_trip(&header), header);
+
+        // A GNU long name, and a global pax header, which is skipped.
+        let mut archive = Vec::new();
+        let mut global = Header::new("global", 12);
+        global.entry_type = EntryType::Other(b'g');
+        archive.extend_from_slice(&global.to_block().unwrap());
+        archive.extend_from_slice(b"12 path=no\n");
+        archive.extend_from_slice(&[0; 501]);
+        let mut long = Header::new("././@LongLink", 301);
+        long.entry_type = EntryType::Other(b'L');
+        archive.extend_from_slice(&long.to_block().unwrap());
+        archive.extend_from_slice(&"x".repeat(300).into_bytes());
+        archive.extend_from_slice(&[0; 212]);
+        archive.extend_from_slice(&Header::new("short", 0).to_block().unwrap());
+        let read = tarhdr::read_header(&mut &archive[..]).unwrap().unwrap();
+        assert_eq!(read.name, "x".repeat(300));
+
+        for &(data, err) in &[(&b"8 path=a\n"[..], TarError::InvalidExtension),
+                              (b"3 a\n", TarError::InvalidExtension),
+                              (b"9 path=\xff\n", TarError::InvalidUtf8)] {
+            let mut pax = Header::new("pax", data.len() as u64);
+            pax.entry_type = EntryType::Other(b'x');
+            let mut archive = pax.to_block().unwrap().to_vec();
+            archive.extend_from_slice(data);
+            archive.extend_from_slice(&[0; 1024]);
+            let e = tarhdr::read_header(&mut &archive[..]).unwrap_err();
+            assert_eq!(TarError::from_io_error(&e), Some(err));
+        }
+        let block = Header::new("a", 0).to_block().unwrap();
+        let e = tarhdr::read_header(&mut &block[..100]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(tarhdr::read_header(&mut &[][..]).unwrap(), None);
+    }
+}
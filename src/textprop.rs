@@ -0,0 +1,340 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Content lines of iCalendar (RFC 5545) and vCard (RFC 6350) data
+//!
+//! `unfold` and `fold` handle the folding of long lines, `ContentLine`
+//! parses and writes one `NAME;PARAM=value:value` line (parameter values
+//! use the RFC 6868 `^` escapes), and `escape_text`/`unescape_text`
+//! handle the backslash escapes of TEXT values.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::textprop::{self, ContentLine};
+//!
+//! fn main() {
+//!     let data = "SUMMARY;LANGUAGE=en:Lunch\\, then a long\r\n  walk\r\n";
+//!     let line = ContentLine::parse(textprop::unfold(data).trim()).unwrap();
+//!     assert_eq!(line.name, "SUMMARY");
+//!     assert_eq!(line.param("language"), Some("en"));
+//!     assert_eq!(textprop::unescape_text(&line.value), "Lunch, then a long walk");
+//!     assert_eq!(line.to_string(), "SUMMARY;LANGUAGE=en:Lunch\\, then a long walk");
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+
+use base64::{FromBase64, FromBase64Error};
+use utf8::split_at_char_boundary;
+
+/// The longest line `fold` writes, in bytes, excluding the line break.
+pub const MAX_LINE_LEN: usize = 75;
+
+/// Joins folded lines back together, removing each line break followed by
+/// a space or tab.
+pub fn unfold(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(idx) = rest.find('\n') {
+        let (line, tail) = rest.split_at(idx);
+        let tail = &tail[1..];
+        if tail.starts_with(' ') || tail.starts_with('\t') {
+            out.push_str(line.strip_suffix('\r').unwrap_or(line));
+            rest = &tail[1..];
+        } else {
+            out.push_str(line);
+            out.push('\n');
+            rest = tail;
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Folds one unfolded content line into lines of at most `MAX_LINE_LEN`
+/// bytes, breaking between characters. Continuation lines start with a
+/// space. The result does not end in a line break.
+pub fn fold(line: &str) -> String {
+    let mut out = String::with_capacity(line.len() + line.len() / 25);
+    let mut rest = line.as_bytes();
+    let mut max_len = MAX_LINE_LEN;
+    loop {
+        let (head, tail) = split_at_char_boundary(rest, max_len);
+        // Both halves are valid UTF-8 as `line` is split between characters.
+        out.push_str(::std::str::from_utf8(head).unwrap());
+        if tail.is_empty() {
+            return out;
+        }
+        out.push_str("\r\n ");
+        rest = tail;
+        max_len = MAX_LINE_LEN - 1;
+    }
+}
+
+/// Escapes `s` for use as a TEXT value: backslashes, semicolons and commas
+/// are escaped with a backslash, and line breaks become `\n`.
+pub fn escape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' | ';' | ',' => { out.push('\\'); out.push(c); }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Reverses `escape_text`. `\N` is accepted for `\n`, and a backslash
+/// before any other character is dropped.
+pub fn unescape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Errors that can occur when parsing a content line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentLineError {
+    /// The property or a parameter name was empty or contained characters
+    /// other than letters, digits and `-`
+    InvalidName,
+    /// A quoted parameter value was not closed
+    UnterminatedQuote,
+    /// The line had no `:` before its value
+    MissingValue,
+}
+
+impl fmt::Display for ContentLineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContentLineError::InvalidName => write!(f, "invalid property or parameter name"),
+            ContentLineError::UnterminatedQuote => write!(f, "unterminated quoted parameter"),
+            ContentLineError::MissingValue => write!(f, "missing `:` before value"),
+        }
+    }
+}
+
+impl error::Error for ContentLineError {
+    fn description(&self) -> &str { "invalid content line" }
+}
+
+/// One unfolded content line: a property name, its parameters and its
+/// value.
+///
+/// The value is kept as written, since how it is escaped depends on its
+/// type; use `unescape_text` for TEXT values and `binary_value` for
+/// base64-encoded ones. `Display` writes the line back, unfolded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentLine {
+    /// The property name, e.g. `DTSTART`
+    pub name: String,
+    /// The parameters as `(name, value)` pairs, with `^` escapes decoded.
+    /// Multiple values of one parameter stay comma-separated.
+    pub params: Vec<(String, String)>,
+    /// The raw value
+    pub value: String,
+}
+
+impl ContentLine {
+    /// Parses an unfolded content line, without its line break.
+    pub fn parse(line: &str) -> Result<ContentLine, ContentLineError> {
+        let end = line.find(&[';', ':'][..]).unwrap_or(line.len());
+        let name = try!(parse_name(&line[..end]));
+        let mut params = Vec::new();
+        let mut rest = &line[end..];
+        while rest.starts_with(';') {
+            let eq = match rest.find('=') {
+                Some(eq) => eq,
+                None => return Err(ContentLineError::MissingValue),
+            };
+            let param = try!(parse_name(&rest[1..eq]));
+            rest = &rest[eq + 1..];
+            let mut value = String::new();
+            loop {
+                if rest.starts_with('"') {
+                    let close = match rest[1..].find('"') {
+                        Some(close) => close + 1,
+                        None => return Err(ContentLineError::UnterminatedQuote),
+                    };
+                    value.push_str(&rest[..close + 1]);
+                    rest = &rest[close + 1..];
+                } else {
+                    let end = rest.find(&[';', ':', ',', '"'][..]).unwrap_or(rest.len());
+                    value.push_str(&rest[..end]);
+                    rest = &rest[end..];
+                }
+                if !rest.starts_with(',') {
+                    break;
+                }
+                value.push(',');
+                rest = &rest[1..];
+            }
+            params.push((param, unescape_param(&value)));
+        }
+        if !rest.starts_with(':') {
+            return Err(ContentLineError::MissingValue);
+        }
+        Ok(ContentLine { name: name, params: params, value: rest[1..].to_string() })
+    }
+
+    /// Returns the value of the parameter `name`, compared ignoring ASCII
+    /// case.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|p| p.0.eq_ignore_ascii_case(name)).map(|p| &p.1[..])
+    }
+
+    /// Decodes the value as base64 if the line has an `ENCODING=b` (vCard)
+    /// or `ENCODING=BASE64` (iCalendar) parameter, returning None otherwise.
+    ///
+    /// To write such a value, encode it with `base64::STANDARD`.
+    pub fn binary_value(&self) -> Option<Result<Vec<u8>, FromBase64Error>> {
+        match self.param("ENCODING") {
+            Some(enc) if enc.eq_ignore_ascii_case("b") || enc.eq_ignore_ascii_case("base64") => {
+                Some(self.value.from_base64())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ContentLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}", self.name));
+        for (name, value) in &self.params {
+            try!(write!(f, ";{}=", name));
+            let mut first = true;
+            for part in value.split(',') {
+                if !first {
+                    try!(write!(f, ","));
+                }
+                first = false;
+                try!(write_param_value(f, part));
+            }
+        }
+        write!(f, ":{}", self.value)
+    }
+}
+
+fn parse_name(name: &str) -> Result<String, ContentLineError> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(ContentLineError::InvalidName);
+    }
+    Ok(name.to_string())
+}
+
+// Removes the quotes from the parts of a parameter value and decodes its
+// RFC 6868 escapes.
+fn unescape_param(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars().filter(|&c| c != '"');
+    while let Some(c) = chars.next() {
+        if c != '^' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('\'') => out.push('"'),
+            Some('^') => out.push('^'),
+            Some(c) => { out.push('^'); out.push(c); }
+            None => out.push('^'),
+        }
+    }
+    out
+}
+
+// Writes one part of a parameter value with RFC 6868 escapes, quoted if it
+// contains characters which end an unquoted value.
+fn write_param_value(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    let quote = value.contains(&[':', ';'][..]);
+    if quote {
+        try!(write!(f, "\""));
+    }
+    for c in value.chars() {
+        try!(match c {
+            '^' => write!(f, "^^"),
+            '\n' => write!(f, "^n"),
+            '"' => write!(f, "^'"),
+            c => write!(f, "{}", c),
+        });
+    }
+    if quote {
+        try!(write!(f, "\""));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use textprop::{escape_text, fold, unescape_text, unfold, ContentLine, ContentLineError};
+    use textprop::MAX_LINE_LEN;
+
+    #[test]
+    fn test_fold_unfold() {
+        assert_eq!(unfold("DESCRIPTION:a\r\n b\r\n\tc\r\nX:y\n z"), "DESCRIPTION:abc\r\nX:yz");
+        assert_eq!(fold("SHORT:x"), "SHORT:x");
+
+        let line = format!("DESCRIPTION:{}", "\u{e9}t\u{e9} \u{1F600} ".repeat(15));
+        let folded = fold(&line);
+        let lines: Vec<_> = folded.split("\r\n").collect();
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|l| l.len() <= MAX_LINE_LEN));
+        assert!(lines[1..].iter().all(|l| l.starts_with(' ')));
+        assert_eq!(unfold(&folded), line);
+    }
+
+    #[test]
+    fn test_escape_text() {
+        let s = "a\\b;c,d\r\ne\nf";
+        assert_eq!(escape_text(s), "a\\\\b\\;c\\,d\\ne\\nf");
+        assert_eq!(unescape_text(&escape_text(s)), "a\\b;c,d\ne\nf");
+        assert_eq!(unescape_text("x\\Ny\\:\\"), "x\ny:\\");
+    }
+
+    #[test]
+    fn test_content_line() {
+        let src = "ATTENDEE;ROLE=REQ-PARTICIPANT;DELEGATED-FROM=\"mailto:a@x\",\"mailto:b@x\";\
+                   CN=George Herman ^'Babe^' Ruth:mailto:c@x";
+        let line = ContentLine::parse(src).unwrap();
+        assert_eq!(line.name, "ATTENDEE");
+        assert_eq!(line.param("delegated-from"), Some("mailto:a@x,mailto:b@x"));
+        assert_eq!(line.param("CN"), Some("George Herman \"Babe\" Ruth"));
+        assert_eq!(line.value, "mailto:c@x");
+        assert_eq!(ContentLine::parse(&line.to_string()).unwrap(), line);
+
+        let line = ContentLine::parse("PHOTO;ENCODING=b;TYPE=JPEG:Zm9vYg==").unwrap();
+        assert_eq!(line.binary_value().unwrap().unwrap(), b"foob");
+        let line = ContentLine::parse("X-EMPTY:").unwrap();
+        assert!(line.params.is_empty() && line.value.is_empty());
+        assert!(line.binary_value().is_none());
+
+        assert_eq!(ContentLine::parse("NO VALUE"), Err(ContentLineError::InvalidName));
+        assert_eq!(ContentLine::parse("NAME"), Err(ContentLineError::MissingValue));
+        assert_eq!(ContentLine::parse("A;B=\"x:1"), Err(ContentLineError::UnterminatedQuote));
+        assert_eq!(ContentLine::parse(":x"), Err(ContentLineError::InvalidName));
+    }
+}
@@ -0,0 +1,1213 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! UTF-8 helpers for working with byte buffers
+//!
+//! `split_at_char_boundary` and `truncate_to_boundary` split or shorten
+//! UTF-8 text to a maximum number of bytes without cutting a code point in
+//! two, e.g. to chunk a string into fixed-size frames.
+//!
+//! `CharIndicesBuffer` decodes the characters of a reader incrementally,
+//! along with their byte offsets, for lexers working on streamed input.
+//! `Utf8ChunkWriter` is its dual on the write side, checking that bytes
+//! written in arbitrary chunks are valid UTF-8, and `Utf8SanitizingReader`
+//! makes any reader's bytes valid UTF-8, replacing what is not.
+//!
+//! `NormalizeNewlines` normalizes the line endings of any iterator of
+//! characters to LF, for text parsers, and maps offsets in its output back
+//! to the input. `ExpandTabs` and `EscapeControls` make decoded text safe
+//! to show on a terminal, expanding tabs to spaces and making control
+//! characters visible.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::utf8;
+//!
+//! fn main() {
+//!     let (head, tail) = utf8::split_at_char_boundary("h\u{e9}llo".as_bytes(), 2);
+//!     assert_eq!(head, b"h");
+//!     assert_eq!(tail, "\u{e9}llo".as_bytes());
+//! }
+//! ```
+
+use std::char;
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::ops::Range;
+use std::str;
+
+/// The error wrapped by the `io::Error`s reporting invalid UTF-8 input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidUtf8 {
+    /// The byte offset of the first byte of the invalid sequence
+    pub position: usize,
+}
+
+impl InvalidUtf8 {
+    /// Returns the `InvalidUtf8` error wrapped by `err`, if any.
+    pub fn from_io_error(err: &io::Error) -> Option<InvalidUtf8> {
+        err.get_ref().and_then(|e| e.downcast_ref::<InvalidUtf8>()).cloned()
+    }
+}
+
+impl fmt::Display for InvalidUtf8 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid UTF-8 at byte {}", self.position)
+    }
+}
+
+impl error::Error for InvalidUtf8 {
+    fn description(&self) -> &str { "invalid UTF-8" }
+}
+
+/// The error returned when a `CharIndicesBuffer` has no room for more
+/// pushed-back characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PushbackFull;
+
+impl fmt::Display for PushbackFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at most {} characters can be pushed back", PUSHBACK_LIMIT)
+    }
+}
+
+impl error::Error for PushbackFull {
+    fn description(&self) -> &str { "pushback buffer full" }
+}
+
+/// The number of characters which can be pushed back into a
+/// `CharIndicesBuffer`.
+pub const PUSHBACK_LIMIT: usize = 8;
+
+fn invalid_utf8(position: usize) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, InvalidUtf8 { position: position })
+}
+
+// The length of the UTF-8 sequence starting with `lead`, or None if `lead`
+// cannot start one.
+fn char_width(lead: u8) -> Option<usize> {
+    match lead {
+        0x00...0x7f => Some(1),
+        0xc2...0xdf => Some(2),
+        0xe0...0xef => Some(3),
+        0xf0...0xf4 => Some(4),
+        _ => None,
+    }
+}
+
+/// Returns true if `idx` is at the start or end of `bytes`, or if the byte
+/// at `idx` is not a UTF-8 continuation byte.
+///
+/// For valid UTF-8 this means `idx` falls between two characters.
+pub fn is_utf8_char_boundary(bytes: &[u8], idx: usize) -> bool {
+    if idx == 0 || idx >= bytes.len() {
+        return idx <= bytes.len();
+    }
+    bytes[idx] & 0xc0 != 0x80
+}
+
+/// Splits `bytes` into a head of at most `max_len` bytes which does not end
+/// in the middle of a UTF-8 character, and the rest.
+///
+/// The head is as long as possible, and empty only if the first character
+/// is longer than `max_len`. Invalid UTF-8 is split at `max_len` unless it
+/// looks like the start of a character cut short there.
+pub fn split_at_char_boundary(bytes: &[u8], max_len: usize) -> (&[u8], &[u8]) {
+    if max_len >= bytes.len() {
+        return (bytes, &[]);
+    }
+    // Back off to the byte starting the character `max_len` falls in, and
+    // split there if that character would not fit. A UTF-8 character has at
+    // most three continuation bytes.
+    let mut idx = max_len;
+    while idx > 0 && max_len - idx < 3 && bytes[idx] & 0xc0 == 0x80 {
+        idx -= 1;
+    }
+    let width = match bytes[idx] {
+        0xc0...0xdf => 2,
+        0xe0...0xef => 3,
+        0xf0...0xf7 => 4,
+        _ => 1,
+    };
+    if idx + width <= max_len {
+        idx = max_len;
+    }
+    bytes.split_at(idx)
+}
+
+/// Shortens `s` to at most `max_bytes` bytes, removing whole characters from
+/// its end.
+pub fn truncate_to_boundary(s: &mut String, max_bytes: usize) {
+    if max_bytes >= s.len() {
+        return;
+    }
+    let mut idx = max_bytes;
+    while !s.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    s.truncate(idx);
+}
+
+/// An iterator over the characters of a reader, with their byte offsets.
+///
+/// Like `str::char_indices`, but the input is read and decoded a block at a
+/// time, and the lexing helpers `skip_while_chars` and `take_span_while`
+/// consume runs of characters in one call. Invalid UTF-8 is reported as an
+/// `io::Error` of kind `InvalidData` wrapping `InvalidUtf8`.
+///
+/// Up to `PUSHBACK_LIMIT` characters can be pushed back with `unread` and
+/// `unread_str`, so parsers can backtrack simple decisions.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::utf8::CharIndicesBuffer;
+///
+/// fn main() {
+///     let mut chars = CharIndicesBuffer::new(&b"  caf\xc3\xa9 = 1"[..]);
+///     assert_eq!(chars.skip_while_chars(char::is_whitespace).unwrap(), 2);
+///     let (span, ident) = chars.take_span_while(char::is_alphabetic).unwrap();
+///     assert_eq!((span, &ident[..]), (2..7, "caf\u{e9}"));
+///     assert_eq!(chars.next().unwrap().unwrap(), (7, ' '));
+/// }
+/// ```
+pub struct CharIndicesBuffer<R> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+    end: usize,
+    // The input offset of `buf[pos]`.
+    offset: usize,
+    // Pushed back (or peeked) characters, the next one last.
+    pushback: Vec<(usize, char)>,
+}
+
+impl<R: Read> CharIndicesBuffer<R> {
+    /// Creates a new buffer decoding the contents of `inner`.
+    pub fn new(inner: R) -> CharIndicesBuffer<R> {
+        CharIndicesBuffer {
+            inner: inner,
+            buf: vec![0; 4096],
+            pos: 0,
+            end: 0,
+            offset: 0,
+            pushback: Vec::with_capacity(PUSHBACK_LIMIT),
+        }
+    }
+
+    /// Returns the byte offset of the next character.
+    pub fn offset(&self) -> usize {
+        match self.pushback.last() {
+            Some(&(offset, _)) => offset,
+            None => self.offset,
+        }
+    }
+
+    /// Returns the next character and its offset without consuming it.
+    pub fn peek(&mut self) -> io::Result<Option<(usize, char)>> {
+        if self.pushback.is_empty() {
+            match try!(self.decode()) {
+                Some(next) => self.pushback.push(next),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.pushback.last().cloned())
+    }
+
+    /// Pushes `c` back, so it is the next character read. Its offset is
+    /// that of the next character minus its length, so for offsets to stay
+    /// right `c` should be the last character read.
+    ///
+    /// Fails if `PUSHBACK_LIMIT` characters are already pushed back, or if
+    /// `c` is longer than the input read so far.
+    pub fn unread(&mut self, c: char) -> Result<(), PushbackFull> {
+        let offset = self.offset();
+        if self.pushback.len() == PUSHBACK_LIMIT || offset < c.len_utf8() {
+            return Err(PushbackFull);
+        }
+        self.pushback.push((offset - c.len_utf8(), c));
+        Ok(())
+    }
+
+    /// Pushes back all of `s`, so it is read next, or nothing if there is no
+    /// room for all of it; see `unread`.
+    pub fn unread_str(&mut self, s: &str) -> Result<(), PushbackFull> {
+        if self.pushback.len() + s.chars().count() > PUSHBACK_LIMIT || self.offset() < s.len() {
+            return Err(PushbackFull);
+        }
+        for c in s.chars().rev() {
+            try!(self.unread(c));
+        }
+        Ok(())
+    }
+
+    /// Consumes characters while `pred` holds, returning the number of
+    /// bytes consumed.
+    pub fn skip_while_chars<P>(&mut self, mut pred: P) -> io::Result<usize>
+        where P: FnMut(char) -> bool
+    {
+        let start = self.offset();
+        while let Some((_, c)) = try!(self.peek()) {
+            if !pred(c) {
+                break;
+            }
+            self.pushback.pop();
+        }
+        Ok(self.offset() - start)
+    }
+
+    /// Consumes characters while `pred` holds, returning them along with the
+    /// byte range they occupied in the input.
+    pub fn take_span_while<P>(&mut self, mut pred: P) -> io::Result<(Range<usize>, String)>
+        where P: FnMut(char) -> bool
+    {
+        let start = self.offset();
+        let mut s = String::new();
+        while let Some((_, c)) = try!(self.peek()) {
+            if !pred(c) {
+                break;
+            }
+            s.push(c);
+            self.pushback.pop();
+        }
+        Ok((start..self.offset(), s))
+    }
+
+    /// Returns an iterator passing each character through `f`, which may
+    /// replace it or drop it by returning `None`, e.g. to strip control
+    /// characters as the input is decoded.
+    ///
+    /// The iterator yields `(input_offset, output_offset, char)` for each
+    /// output character: the offset of the input character it came from,
+    /// and its own offset in the UTF-8 encoding of the output so far.
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// use rustc_serialize::utf8::CharIndicesBuffer;
+    ///
+    /// fn main() {
+    ///     let mut chars = CharIndicesBuffer::new(&b"a\x00\xc3\xa9b"[..]);
+    ///     let out: Vec<_> = chars.map_chars(|c| if c.is_control() { None } else { Some(c) })
+    ///                            .map(|r| r.unwrap()).collect();
+    ///     assert_eq!(out, [(0, 0, 'a'), (2, 1, '\u{e9}'), (4, 3, 'b')]);
+    /// }
+    /// ```
+    pub fn map_chars<'a, F>(&'a mut self, f: F) -> MapChars<'a, R, F>
+        where F: FnMut(char) -> Option<char>
+    {
+        MapChars { chars: self, f: f, output_offset: 0 }
+    }
+
+    /// Unwraps this buffer, returning the underlying reader. Any buffered
+    /// input is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Unwraps this buffer, returning the underlying reader along with the
+    /// input read from it but not yet consumed, including pushed-back
+    /// characters. The reader continues where these bytes end.
+    pub fn into_reader_state(self) -> (R, Vec<u8>) {
+        let mut rest = String::new();
+        for &(_, c) in self.pushback.iter().rev() {
+            rest.push(c);
+        }
+        let mut rest = rest.into_bytes();
+        rest.extend_from_slice(&self.buf[self.pos..self.end]);
+        (self.inner, rest)
+    }
+
+    // Makes at least `n` bytes available unless the input ends first, and
+    // returns the number available.
+    fn fill(&mut self, n: usize) -> io::Result<usize> {
+        if self.end - self.pos < n {
+            let len = self.end - self.pos;
+            for i in 0..len {
+                self.buf[i] = self.buf[self.pos + i];
+            }
+            self.pos = 0;
+            self.end = len;
+            while self.end < n {
+                match try!(self.inner.read(&mut self.buf[self.end..])) {
+                    0 => break,
+                    read => self.end += read,
+                }
+            }
+        }
+        Ok(self.end - self.pos)
+    }
+
+    fn decode(&mut self) -> io::Result<Option<(usize, char)>> {
+        if try!(self.fill(1)) == 0 {
+            return Ok(None);
+        }
+        let width = match char_width(self.buf[self.pos]) {
+            Some(width) => width,
+            None => return Err(invalid_utf8(self.offset)),
+        };
+        if try!(self.fill(width)) < width {
+            return Err(invalid_utf8(self.offset));
+        }
+        let c = match str::from_utf8(&self.buf[self.pos..self.pos + width]) {
+            Ok(s) => s.chars().next().unwrap(),
+            Err(_) => return Err(invalid_utf8(self.offset)),
+        };
+        let offset = self.offset;
+        self.pos += width;
+        self.offset += width;
+        Ok(Some((offset, c)))
+    }
+}
+
+impl<R: Read> Iterator for CharIndicesBuffer<R> {
+    type Item = io::Result<(usize, char)>;
+
+    fn next(&mut self) -> Option<io::Result<(usize, char)>> {
+        match self.pushback.pop() {
+            Some(next) => Some(Ok(next)),
+            None => match self.decode() {
+                Ok(next) => next.map(Ok),
+                Err(e) => Some(Err(e)),
+            },
+        }
+    }
+}
+
+/// The iterator returned by `CharIndicesBuffer::map_chars`.
+pub struct MapChars<'a, R: 'a, F> {
+    chars: &'a mut CharIndicesBuffer<R>,
+    f: F,
+    output_offset: usize,
+}
+
+impl<'a, R: Read, F> MapChars<'a, R, F> {
+    /// Returns the offset of the next input character.
+    pub fn input_offset(&self) -> usize {
+        self.chars.offset()
+    }
+
+    /// Returns the length of the output so far, in bytes.
+    pub fn output_offset(&self) -> usize {
+        self.output_offset
+    }
+}
+
+impl<'a, R: Read, F> Iterator for MapChars<'a, R, F>
+    where F: FnMut(char) -> Option<char>
+{
+    type Item = io::Result<(usize, usize, char)>;
+
+    fn next(&mut self) -> Option<io::Result<(usize, usize, char)>> {
+        loop {
+            let (input_offset, c) = match self.chars.next() {
+                Some(Ok(next)) => next,
+                Some(Err(e)) => return Some(Err(e)),
+                None => return None,
+            };
+            if let Some(c) = (self.f)(c) {
+                let output_offset = self.output_offset;
+                self.output_offset += c.len_utf8();
+                return Some(Ok((input_offset, output_offset, c)));
+            }
+        }
+    }
+}
+
+/// A writer which checks that everything written to it is valid UTF-8,
+/// passing on only complete characters.
+///
+/// Chunks may split a character anywhere; its first bytes are held back
+/// until the rest arrives. Writing invalid UTF-8 fails with an `io::Error`
+/// of kind `InvalidData` wrapping `InvalidUtf8`, whose position counts the
+/// bytes written to this writer. Any valid bytes before the invalid ones
+/// are passed on first, by a shorter successful write. Call `finish` at
+/// the end to check that the input did not end in the middle of a
+/// character.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use std::io::Write;
+/// use rustc_serialize::utf8::Utf8ChunkWriter;
+///
+/// fn main() {
+///     let mut writer = Utf8ChunkWriter::new(Vec::new());
+///     writer.write_all(b"caf\xc3").unwrap();
+///     assert_eq!(writer.get_ref(), b"caf");
+///     writer.write_all(b"\xa9!").unwrap();
+///     assert_eq!(writer.finish().unwrap(), "caf\u{e9}!".as_bytes());
+///
+///     let mut writer = Utf8ChunkWriter::new(Vec::new());
+///     assert!(writer.write_all(b"ab\xff").is_err());
+/// }
+/// ```
+pub struct Utf8ChunkWriter<W: Write> {
+    inner: W,
+    pending: [u8; 4],
+    pending_len: usize,
+    // The number of bytes accepted so far, including those pending.
+    position: usize,
+}
+
+impl<W: Write> Utf8ChunkWriter<W> {
+    /// Creates a new writer checking the bytes passed on to `inner`.
+    pub fn new(inner: W) -> Utf8ChunkWriter<W> {
+        Utf8ChunkWriter { inner: inner, pending: [0; 4], pending_len: 0, position: 0 }
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Checks that the input did not end in the middle of a character and
+    /// returns the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        if self.pending_len != 0 {
+            return Err(invalid_utf8(self.position - self.pending_len));
+        }
+        Ok(self.inner)
+    }
+
+    /// Unwraps this writer without checking for an incomplete final
+    /// character, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    // Adds bytes from `buf` to the pending character, returning how many
+    // were used.
+    fn complete_pending(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let start = self.position - self.pending_len;
+        let width = char_width(self.pending[0]).unwrap();
+        let mut used = 0;
+        while self.pending_len < width && used < buf.len() {
+            self.pending[self.pending_len] = buf[used];
+            self.pending_len += 1;
+            used += 1;
+        }
+        match str::from_utf8(&self.pending[..self.pending_len]) {
+            Ok(_) => {
+                try!(self.inner.write_all(&self.pending[..self.pending_len]));
+                self.pending_len = 0;
+            }
+            Err(ref e) if e.error_len().is_none() => {}
+            Err(_) => {
+                self.pending_len = 0;
+                self.position = start;
+                return Err(invalid_utf8(start));
+            }
+        }
+        self.position += used;
+        Ok(used)
+    }
+}
+
+impl<W: Write> Write for Utf8ChunkWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.pending_len != 0 {
+            return self.complete_pending(buf);
+        }
+        let (valid, rest) = match str::from_utf8(buf) {
+            Ok(_) => (buf.len(), None),
+            Err(e) => (e.valid_up_to(), Some(e.error_len())),
+        };
+        if valid > 0 {
+            try!(self.inner.write_all(&buf[..valid]));
+            self.position += valid;
+            return Ok(valid);
+        }
+        match rest {
+            None => Ok(0),
+            // The start of a character cut short by the end of `buf`.
+            Some(None) => {
+                self.pending[..buf.len()].copy_from_slice(buf);
+                self.pending_len = buf.len();
+                self.position += buf.len();
+                Ok(buf.len())
+            }
+            Some(Some(_)) => Err(invalid_utf8(self.position)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The UTF-8 encoding of U+FFFD REPLACEMENT CHARACTER.
+const REPLACEMENT: &[u8] = b"\xef\xbf\xbd";
+
+/// A reader passing on the bytes of another, with each invalid UTF-8
+/// sequence replaced by U+FFFD, so that what it reads is always valid
+/// UTF-8.
+///
+/// Sequences are replaced as by `String::from_utf8_lossy`: each maximal
+/// prefix of a valid sequence, or each byte which starts none, becomes one
+/// U+FFFD. A sequence cut short by the end of the input is replaced too,
+/// but one cut short by the end of a read from the underlying reader is
+/// held back until the rest is read, so the bytes read are the same
+/// however the input arrives.
+///
+/// Reads may end in the middle of a character, as with any other reader;
+/// the characters are only whole once all the bytes are read.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use std::io::Read;
+/// use rustc_serialize::utf8::Utf8SanitizingReader;
+///
+/// fn main() {
+///     let mut reader = Utf8SanitizingReader::new(&b"caf\xc3\xa9 \xff\xfe!"[..]);
+///     let mut s = String::new();
+///     reader.read_to_string(&mut s).unwrap();
+///     assert_eq!(s, "caf\u{e9} \u{fffd}\u{fffd}!");
+///     assert_eq!(reader.replacements(), 2);
+/// }
+/// ```
+pub struct Utf8SanitizingReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+    end: usize,
+    // The end of the bytes from `pos` known to be valid.
+    valid: usize,
+    // The number of bytes of a replacement character already read, or
+    // `REPLACEMENT.len()` if none is being read.
+    replacing: usize,
+    replacements: u64,
+}
+
+impl<R: Read> Utf8SanitizingReader<R> {
+    /// Creates a new reader sanitizing the bytes of `inner`.
+    pub fn new(inner: R) -> Utf8SanitizingReader<R> {
+        Utf8SanitizingReader {
+            inner: inner,
+            buf: vec![0; 4096],
+            pos: 0,
+            end: 0,
+            valid: 0,
+            replacing: REPLACEMENT.len(),
+            replacements: 0,
+        }
+    }
+
+    /// Returns the number of invalid sequences replaced so far.
+    pub fn replacements(&self) -> u64 {
+        self.replacements
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwraps this reader, returning the underlying reader. Bytes read
+    /// from it but not yet passed on are lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // Reads more input after the bytes buffered, returning false at the
+    // end of the input.
+    fn fill(&mut self) -> io::Result<bool> {
+        let len = self.end - self.pos;
+        for i in 0..len {
+            self.buf[i] = self.buf[self.pos + i];
+        }
+        self.valid -= self.pos;
+        self.pos = 0;
+        self.end = len;
+        loop {
+            match self.inner.read(&mut self.buf[len..]) {
+                Ok(read) => {
+                    self.end += read;
+                    return Ok(read > 0);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Reads as much of a replacement character as fits in `out`.
+    fn replace(&mut self, out: &mut [u8]) -> usize {
+        let rest = &REPLACEMENT[self.replacing..];
+        let n = rest.len().min(out.len());
+        out[..n].copy_from_slice(&rest[..n]);
+        self.replacing += n;
+        n
+    }
+}
+
+impl<R: Read> Read for Utf8SanitizingReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        if self.replacing < REPLACEMENT.len() {
+            return Ok(self.replace(out));
+        }
+        loop {
+            if self.valid == self.pos {
+                if self.pos == self.end && !try!(self.fill()) {
+                    return Ok(0);
+                }
+                let error_len = match str::from_utf8(&self.buf[self.pos..self.end]) {
+                    Ok(_) => {
+                        self.valid = self.end;
+                        None
+                    }
+                    Err(e) => {
+                        self.valid = self.pos + e.valid_up_to();
+                        Some(e.error_len())
+                    }
+                };
+                if self.valid == self.pos {
+                    match error_len.unwrap() {
+                        Some(len) => self.pos += len,
+                        // Cut short, by the end of the buffer or the input.
+                        None => {
+                            if try!(self.fill()) {
+                                continue;
+                            }
+                            self.pos = self.end;
+                        }
+                    }
+                    self.valid = self.pos;
+                    self.replacements += 1;
+                    self.replacing = 0;
+                    return Ok(self.replace(out));
+                }
+            }
+            let n = (self.valid - self.pos).min(out.len());
+            out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+            self.pos += n;
+            return Ok(n);
+        }
+    }
+}
+
+/// An iterator over the characters of another, with its CRLF and lone CR
+/// line endings replaced by LF, and optionally without a leading byte
+/// order mark.
+///
+/// Offsets in the output, such as the position of a parse error, can be
+/// translated back to offsets in the input with `to_input_offset`. Offsets
+/// count the bytes of the UTF-8 encodings of the characters, as
+/// `Parser::bytes_consumed` does, and so can be used on the text's bytes.
+/// The translation takes a little memory for each CRLF in the input.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::utf8::NormalizeNewlines;
+///
+/// fn main() {
+///     let text = "\u{feff}a\r\nb\rc";
+///     let mut chars = NormalizeNewlines::new(text.chars());
+///     chars.set_strip_bom(true);
+///     let normalized: String = chars.by_ref().collect();
+///     assert_eq!(normalized, "a\nb\nc");
+///     // The `b` at offset 2 of the output is at offset 6 of the input.
+///     assert_eq!(chars.to_input_offset(2), 6);
+/// }
+/// ```
+pub struct NormalizeNewlines<I> {
+    inner: I,
+    strip_bom: bool,
+    after_cr: bool,
+    input_offset: usize,
+    output_offset: usize,
+    // The output and input offsets at each point characters were dropped,
+    // in order.
+    shifts: Vec<(usize, usize)>,
+}
+
+impl<I: Iterator<Item = char>> NormalizeNewlines<I> {
+    /// Creates a new iterator normalizing the line endings of `inner`.
+    pub fn new(inner: I) -> NormalizeNewlines<I> {
+        NormalizeNewlines {
+            inner: inner,
+            strip_bom: false,
+            after_cr: false,
+            input_offset: 0,
+            output_offset: 0,
+            shifts: Vec::new(),
+        }
+    }
+
+    /// Set whether a U+FEFF byte order mark starting the input is dropped.
+    /// This should be set before reading.
+    pub fn set_strip_bom(&mut self, strip: bool) {
+        self.strip_bom = strip;
+    }
+
+    /// Returns the number of bytes of input consumed so far.
+    pub fn input_offset(&self) -> usize {
+        self.input_offset
+    }
+
+    /// Returns the length of the output so far, in bytes.
+    pub fn output_offset(&self) -> usize {
+        self.output_offset
+    }
+
+    /// Returns the offset in the input of the character at `offset` in the
+    /// output. An LF replacing a CRLF is at the offset of the CR, and an
+    /// offset past what has been read is translated as if the rest of the
+    /// input had no line endings to normalize.
+    pub fn to_input_offset(&self, offset: usize) -> usize {
+        let i = match self.shifts.binary_search_by(|&(output, _)| output.cmp(&offset)) {
+            Ok(i) => i,
+            Err(0) => return offset,
+            Err(i) => i - 1,
+        };
+        let (output, input) = self.shifts[i];
+        input + (offset - output)
+    }
+
+    fn drop_char(&mut self, c: char) {
+        self.input_offset += c.len_utf8();
+        self.shifts.push((self.output_offset, self.input_offset));
+    }
+}
+
+impl<I: Iterator<Item = char>> Iterator for NormalizeNewlines<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            let c = match self.inner.next() {
+                Some(c) => c,
+                None => return None,
+            };
+            let after_cr = self.after_cr;
+            self.after_cr = c == '\r';
+            if (c == '\n' && after_cr) ||
+               (c == '\u{feff}' && self.strip_bom && self.input_offset == 0) {
+                self.drop_char(c);
+                continue;
+            }
+            self.input_offset += c.len_utf8();
+            self.output_offset += c.len_utf8();
+            return Some(if c == '\r' { '\n' } else { c });
+        }
+    }
+}
+
+/// An iterator over the characters of another, with each tab replaced by
+/// spaces up to the next tab stop.
+///
+/// Tab stops are every `tab_width` columns, and columns count characters
+/// since the last line break, CR or LF, so characters which take two
+/// columns on a terminal, or none, put later tabs out of line.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::utf8::ExpandTabs;
+///
+/// fn main() {
+///     let expanded: String = ExpandTabs::new("a\tbc\td\n\te".chars(), 4).collect();
+///     assert_eq!(expanded, "a   bc  d\n    e");
+/// }
+/// ```
+pub struct ExpandTabs<I> {
+    inner: I,
+    tab_width: usize,
+    column: usize,
+    // The spaces still to yield for the last tab.
+    spaces: usize,
+}
+
+impl<I: Iterator<Item = char>> ExpandTabs<I> {
+    /// Creates a new iterator expanding the tabs of `inner` to tab stops
+    /// every `tab_width` columns. With a `tab_width` of 0, tabs are
+    /// dropped.
+    pub fn new(inner: I, tab_width: usize) -> ExpandTabs<I> {
+        ExpandTabs { inner: inner, tab_width: tab_width, column: 0, spaces: 0 }
+    }
+}
+
+impl<I: Iterator<Item = char>> Iterator for ExpandTabs<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            if self.spaces > 0 {
+                self.spaces -= 1;
+                self.column += 1;
+                return Some(' ');
+            }
+            match self.inner.next() {
+                Some('\t') => {
+                    if self.tab_width > 0 {
+                        self.spaces = self.tab_width - self.column % self.tab_width;
+                    }
+                }
+                Some(c) => {
+                    self.column = if c == '\n' || c == '\r' { 0 } else { self.column + 1 };
+                    return Some(c);
+                }
+                None => return None,
+            }
+        }
+    }
+}
+
+/// How `EscapeControls` shows control characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlStyle {
+    /// As the matching character of the Control Pictures block, U+2400 to
+    /// U+2421, such as U+241B for ESC.
+    Pictures,
+    /// As an escape of its code in hex, such as `\x1b` for ESC.
+    Hex,
+}
+
+/// An iterator over the characters of another, with the C0 control
+/// characters, U+0000 to U+001F, and DEL, U+007F, replaced by printable
+/// ones in a `ControlStyle`, so that text can be shown on a terminal
+/// without it acting on them.
+///
+/// Line feeds are kept as they are unless `set_escape_newlines` says
+/// otherwise. Tabs are replaced like any other control character, so for
+/// them to be spaces instead, expand them with `ExpandTabs` first.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::utf8::{ControlStyle, EscapeControls, ExpandTabs};
+///
+/// fn main() {
+///     let text = "\x1b[2Jhi\x07\n";
+///     let pictures: String = EscapeControls::new(text.chars(), ControlStyle::Pictures).collect();
+///     assert_eq!(pictures, "\u{241b}[2Jhi\u{2407}\n");
+///     let hex: String = EscapeControls::new(text.chars(), ControlStyle::Hex).collect();
+///     assert_eq!(hex, "\\x1b[2Jhi\\x07\n");
+///
+///     let tabs = ExpandTabs::new("\ta\x00".chars(), 2);
+///     let safe: String = EscapeControls::new(tabs, ControlStyle::Hex).collect();
+///     assert_eq!(safe, "  a\\x00");
+/// }
+/// ```
+pub struct EscapeControls<I> {
+    inner: I,
+    style: ControlStyle,
+    escape_newlines: bool,
+    // The characters of the last hex escape, after its backslash, and how
+    // many have been yielded.
+    escape: [char; 3],
+    escape_pos: usize,
+}
+
+impl<I: Iterator<Item = char>> EscapeControls<I> {
+    /// Creates a new iterator replacing the control characters of `inner`
+    /// in `style`.
+    pub fn new(inner: I, style: ControlStyle) -> EscapeControls<I> {
+        EscapeControls {
+            inner: inner,
+            style: style,
+            escape_newlines: false,
+            escape: ['x'; 3],
+            escape_pos: 3,
+        }
+    }
+
+    /// Set whether line feeds are replaced too, for text shown on one
+    /// line. This should be set before reading.
+    pub fn set_escape_newlines(&mut self, escape: bool) {
+        self.escape_newlines = escape;
+    }
+}
+
+impl<I: Iterator<Item = char>> Iterator for EscapeControls<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.escape_pos < self.escape.len() {
+            self.escape_pos += 1;
+            return Some(self.escape[self.escape_pos - 1]);
+        }
+        let c = match self.inner.next() {
+            Some(c) => c,
+            None => return None,
+        };
+        let code = c as u32;
+        if (code >= 0x20 && code != 0x7f) || (c == '\n' && !self.escape_newlines) {
+            return Some(c);
+        }
+        match self.style {
+            ControlStyle::Pictures => {
+                let picture = if code == 0x7f { 0x2421 } else { 0x2400 + code };
+                char::from_u32(picture)
+            }
+            ControlStyle::Hex => {
+                let hex = b"0123456789abcdef";
+                self.escape = ['x', hex[(code >> 4) as usize] as char,
+                               hex[(code & 0xf) as usize] as char];
+                self.escape_pos = 0;
+                Some('\\')
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::prelude::*;
+    use utf8::{is_utf8_char_boundary, split_at_char_boundary, truncate_to_boundary};
+    use utf8::{CharIndicesBuffer, InvalidUtf8, PushbackFull, PUSHBACK_LIMIT};
+    use utf8::{ControlStyle, EscapeControls, ExpandTabs, NormalizeNewlines};
+    use utf8::{Utf8ChunkWriter, Utf8SanitizingReader};
+    use json::Json;
+
+    // A reader handing out at most one byte per call.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() { return Ok(0) }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_char_indices_buffer() {
+        let s = "a\u{e9}\u{20ac}\u{1F600} x";
+        let expected: Vec<_> = s.char_indices().collect();
+        let chars: Vec<_> = CharIndicesBuffer::new(Trickle(s.as_bytes()))
+            .map(|r| r.unwrap()).collect();
+        assert_eq!(chars, expected);
+
+        let mut chars = CharIndicesBuffer::new(Trickle(s.as_bytes()));
+        assert_eq!(chars.skip_while_chars(|c| c != ' ').unwrap(), 10);
+        assert_eq!(chars.take_span_while(|c| c == ' ').unwrap(), (10..11, " ".to_string()));
+        assert_eq!(chars.take_span_while(|c| c == ' ').unwrap(), (11..11, "".to_string()));
+        assert_eq!(chars.skip_while_chars(|_| true).unwrap(), 1);
+        assert!(chars.next().is_none());
+
+        for bad in [&b"ab\xc3"[..], b"ab\xc3(", b"ab\x80", b"ab\xed\xa0\x80"].iter() {
+            let mut chars = CharIndicesBuffer::new(*bad);
+            let err = chars.skip_while_chars(|_| true).unwrap_err();
+            assert_eq!(InvalidUtf8::from_io_error(&err), Some(InvalidUtf8 { position: 2 }));
+        }
+    }
+
+    #[test]
+    fn test_map_chars() {
+        let mut chars = CharIndicesBuffer::new(Trickle("\u{e9}\r\n\u{1F600}\rx".as_bytes()));
+        let mut after_cr = false;
+        let out: Vec<_> = {
+            // Normalize CRLF and lone CR to LF.
+            let mut mapped = chars.map_chars(|c| {
+                let prev = after_cr;
+                after_cr = c == '\r';
+                match c {
+                    '\n' if prev => None,
+                    '\r' => Some('\n'),
+                    c => Some(c),
+                }
+            });
+            let out = mapped.by_ref().take(3).map(|r| r.unwrap()).collect::<Vec<_>>();
+            assert_eq!((mapped.input_offset(), mapped.output_offset()), (8, 7));
+            out.into_iter().chain(mapped.map(|r| r.unwrap())).collect()
+        };
+        assert_eq!(out, [(0, 0, '\u{e9}'), (2, 2, '\n'), (4, 3, '\u{1F600}'),
+                         (8, 7, '\n'), (9, 8, 'x')]);
+        assert!(chars.next().is_none());
+    }
+
+    #[test]
+    fn test_unread() {
+        let mut chars = CharIndicesBuffer::new(&b"<\xc3\xa9>x"[..]);
+        assert_eq!(chars.unread('a'), Err(PushbackFull));
+        let (_, s) = chars.take_span_while(|c| c != 'x').unwrap();
+        assert_eq!(s, "<\u{e9}>");
+        chars.unread('>').unwrap();
+        chars.unread_str("<\u{e9}").unwrap();
+        let all: Vec<_> = chars.by_ref().map(|r| r.unwrap()).collect();
+        assert_eq!(all, [(0, '<'), (1, '\u{e9}'), (3, '>'), (4, 'x')]);
+
+        chars.unread('x').unwrap();
+        assert_eq!(chars.peek().unwrap(), Some((4, 'x')));
+        assert_eq!(chars.unread_str("\u{e9}>"), Ok(()));
+        assert_eq!(chars.offset(), 1);
+        assert_eq!(chars.unread_str("12"), Err(PushbackFull));
+        assert_eq!(chars.next().unwrap().unwrap(), (1, '\u{e9}'));
+
+        let mut chars = CharIndicesBuffer::new(&[b' '; 20][..]);
+        chars.skip_while_chars(|_| true).unwrap();
+        for _ in 0..PUSHBACK_LIMIT {
+            chars.unread(' ').unwrap();
+        }
+        assert_eq!(chars.unread(' '), Err(PushbackFull));
+        assert_eq!(chars.count(), PUSHBACK_LIMIT);
+
+        let mut chars = CharIndicesBuffer::new(&b"ab\xc3\xa9cd"[..]);
+        chars.take_span_while(|c| c != 'c').unwrap();
+        chars.unread('\u{e9}').unwrap();
+        let (rdr, rest) = chars.into_reader_state();
+        assert_eq!((rdr, &rest[..]), (&b""[..], &b"\xc3\xa9cd"[..]));
+    }
+
+    #[test]
+    fn test_utf8_chunk_writer() {
+        let s = "a\u{e9}\u{20ac}\u{1F600} x".as_bytes();
+        for size in 1..5 {
+            let mut writer = Utf8ChunkWriter::new(Vec::new());
+            for chunk in s.chunks(size) {
+                writer.write_all(chunk).unwrap();
+                assert!(::std::str::from_utf8(writer.get_ref()).is_ok());
+            }
+            assert_eq!(writer.finish().unwrap(), s);
+        }
+
+        let position = |data: &[&[u8]]| {
+            let mut writer = Utf8ChunkWriter::new(Vec::new());
+            for chunk in data {
+                if let Err(e) = writer.write_all(chunk) {
+                    return InvalidUtf8::from_io_error(&e).unwrap().position;
+                }
+            }
+            InvalidUtf8::from_io_error(&writer.finish().unwrap_err()).unwrap().position
+        };
+        assert_eq!(position(&[b"ab\xff"]), 2);
+        assert_eq!(position(&[b"ab", b"\xe2\x82", b"("]), 2);
+        assert_eq!(position(&[b"abc\xe2", b"\x82"]), 3);
+        assert_eq!(position(&[b"abcd\xed\xa0\x80"]), 4);
+    }
+
+    #[test]
+    fn test_utf8_sanitizing_reader() {
+        let inputs: [&[u8]; 8] = [b"a\xc3\xa9\xe2\x82\xac\xf0\x9f\x98\x80 x", b"ab\xff\xfecd",
+                                  b"ab\xe2\x82", b"\xe2\x82(\xe2", b"\xed\xa0\x80z", b"\x80",
+                                  b"\xf0\x9f\x98", b""];
+        for input in inputs.iter() {
+            let expected = String::from_utf8_lossy(input).into_owned();
+            let mut s = String::new();
+            Utf8SanitizingReader::new(*input).read_to_string(&mut s).unwrap();
+            assert_eq!(s, expected);
+
+            // However the input arrives and is read.
+            let mut reader = Utf8SanitizingReader::new(Trickle(input));
+            let mut bytes = Vec::new();
+            let mut byte = [0];
+            while reader.read(&mut byte).unwrap() == 1 {
+                bytes.push(byte[0]);
+            }
+            assert_eq!(bytes, expected.as_bytes());
+            assert_eq!(reader.replacements() as usize, expected.matches('\u{fffd}').count());
+        }
+
+        let long: Vec<u8> = (0..10000).map(|i| if i % 1000 == 999 { 0xff } else { b'a' }).collect();
+        let mut s = String::new();
+        let mut reader = Utf8SanitizingReader::new(&long[..]);
+        reader.read_to_string(&mut s).unwrap();
+        assert_eq!((s.len(), reader.replacements()), (10020, 10));
+
+        let mut reader = Utf8SanitizingReader::new(&b"{\"name\": \"caf\xe9\"}"[..]);
+        let json = Json::from_reader(&mut reader).unwrap();
+        assert_eq!(json["name"], Json::String("caf\u{fffd}".into()));
+    }
+
+    #[test]
+    fn test_normalize_newlines() {
+        let text = "\u{feff}\u{e9}\r\n\r\n\rx\n\u{feff}\r";
+        let mut chars = NormalizeNewlines::new(text.chars());
+        chars.set_strip_bom(true);
+        let out: String = chars.by_ref().collect();
+        assert_eq!(out, "\u{e9}\n\n\nx\n\u{feff}\n");
+        assert_eq!((chars.input_offset(), chars.output_offset()), (text.len(), out.len()));
+        for (offset, c) in out.char_indices() {
+            let input = chars.to_input_offset(offset);
+            let expected = if c == '\n' { ['\r', '\n'] } else { [c, c] };
+            assert!(text[input..].starts_with(&expected[..]), "{} {:?}", offset, c);
+        }
+        assert_eq!(chars.to_input_offset(0), 3);
+        assert_eq!(chars.to_input_offset(3), 7);
+        assert_eq!(chars.to_input_offset(out.len() + 2), text.len() + 2);
+
+        let kept: String = NormalizeNewlines::new("\u{feff}a\r".chars()).collect();
+        assert_eq!(kept, "\u{feff}a\n");
+    }
+
+    #[test]
+    fn test_expand_tabs() {
+        let expand = |s: &str, width| ExpandTabs::new(s.chars(), width).collect::<String>();
+        assert_eq!(expand("\tx\t\t\u{e9}\t!", 4), "    x       \u{e9}   !");
+        assert_eq!(expand("abcd\te\r\tf", 4), "abcd    e\r    f");
+        assert_eq!(expand("a\tb", 1), "a b");
+        assert_eq!(expand("a\tb\t", 0), "ab");
+        assert_eq!(expand("", 8), "");
+    }
+
+    #[test]
+    fn test_escape_controls() {
+        let text = "\u{0}a\t\u{1f}\u{7f}\u{80}\n\r";
+        let pictures: String = EscapeControls::new(text.chars(), ControlStyle::Pictures).collect();
+        assert_eq!(pictures, "\u{2400}a\u{2409}\u{241f}\u{2421}\u{80}\n\u{240d}");
+        let mut hex = EscapeControls::new(text.chars(), ControlStyle::Hex);
+        hex.set_escape_newlines(true);
+        assert_eq!(hex.collect::<String>(), "\\x00a\\x09\\x1f\\x7f\u{80}\\x0a\\x0d");
+
+        let mut pictures = EscapeControls::new("a\nb".chars(), ControlStyle::Pictures);
+        pictures.set_escape_newlines(true);
+        assert_eq!(pictures.collect::<String>(), "a\u{240a}b");
+    }
+
+    #[test]
+    fn test_split_at_char_boundary() {
+        let s = "a\u{e9}\u{20ac}\u{1F600}".as_bytes(); // 1 + 2 + 3 + 4 bytes
+        let expected = [0, 1, 1, 3, 3, 3, 6, 6, 6, 6, 10, 10];
+        for (max_len, &head_len) in expected.iter().enumerate() {
+            let (head, tail) = split_at_char_boundary(s, max_len);
+            assert_eq!(head.len(), head_len, "max_len {}", max_len);
+            assert_eq!([head, tail].concat(), s);
+        }
+
+        // Runs of continuation bytes longer than a character.
+        let bad = [b'a', 0x80, 0x80, 0x80, 0x80, 0x80];
+        assert_eq!(split_at_char_boundary(&bad, 5).0.len(), 5);
+        assert_eq!(split_at_char_boundary(&bad, 3).0.len(), 3);
+        assert_eq!(split_at_char_boundary(&[0x80, 0x80], 1).0.len(), 1);
+
+        assert!(is_utf8_char_boundary(b"", 0));
+        assert!(!is_utf8_char_boundary(b"a", 2));
+    }
+
+    #[test]
+    fn test_truncate_to_boundary() {
+        let mut s = "a\u{e9}\u{20ac}".to_string();
+        truncate_to_boundary(&mut s, 10);
+        assert_eq!(s, "a\u{e9}\u{20ac}");
+        truncate_to_boundary(&mut s, 5);
+        assert_eq!(s, "a\u{e9}");
+        truncate_to_boundary(&mut s, 2);
+        assert_eq!(s, "a");
+        truncate_to_boundary(&mut s, 0);
+        assert_eq!(s, "");
+    }
+}
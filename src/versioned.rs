@@ -0,0 +1,307 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Versioned JSON documents
+//!
+//! A type implementing `Versioned` is persisted as a JSON object with a
+//! `"version"` field alongside its own. Its `Decodable` implementation,
+//! usually derived, only has to read the current layout: `decode` first
+//! runs the document through the type's chain of migrations, each of which
+//! rewrites an object of one version into the layout of the next.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::json::{Json, Object};
+//! use rustc_serialize::versioned::{self, Migration, Versioned};
+//! # use rustc_serialize::{Decodable, Decoder};
+//!
+//! // Version 1 had `name`; version 2 split it into `first` and `last`,
+//! // and version 3 added `admin`.
+//! struct User {
+//!     first: String,
+//!     last: String,
+//!     admin: bool,
+//! }
+//! # impl Decodable for User {
+//! #     fn decode<D: Decoder>(d: &mut D) -> Result<User, D::Error> {
+//! #         d.read_struct("User", 3, |d| Ok(User {
+//! #             first: try!(d.read_struct_field("first", 0, Decodable::decode)),
+//! #             last: try!(d.read_struct_field("last", 1, Decodable::decode)),
+//! #             admin: try!(d.read_struct_field("admin", 2, Decodable::decode)),
+//! #         }))
+//! #     }
+//! # }
+//!
+//! fn split_name(user: &mut Object) -> Result<(), String> {
+//!     let name = match user.remove("name") {
+//!         Some(Json::String(name)) => name,
+//!         _ => return Err("no name".to_string()),
+//!     };
+//!     let mut parts = name.splitn(2, ' ');
+//!     let first = parts.next().unwrap_or("").to_string();
+//!     let last = parts.next().unwrap_or("").to_string();
+//!     user.insert("first".to_string(), Json::from(first));
+//!     user.insert("last".to_string(), Json::from(last));
+//!     Ok(())
+//! }
+//!
+//! fn add_admin(user: &mut Object) -> Result<(), String> {
+//!     user.insert("admin".to_string(), Json::Boolean(false));
+//!     Ok(())
+//! }
+//!
+//! impl Versioned for User {
+//!     const VERSION: u64 = 3;
+//!     const MIGRATIONS: &'static [Migration] = &[split_name, add_admin];
+//! }
+//!
+//! fn main() {
+//!     let user: User = versioned::decode(r#"{"version": 1, "name": "Ada Lovelace"}"#)
+//!                          .unwrap();
+//!     assert_eq!(user.last, "Lovelace");
+//!     assert!(!user.admin);
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+
+use {Decodable, Encodable};
+use json::{self, Json, Object};
+
+/// The name of the field holding a document's version
+pub const VERSION_FIELD: &str = "version";
+
+/// A migration, which rewrites an object in the layout of one version
+/// into that of the next. It need not update the version field.
+pub type Migration = fn(&mut Object) -> Result<(), String>;
+
+/// A type whose JSON layout is versioned.
+pub trait Versioned {
+    /// The version of the current layout, the one the type decodes
+    const VERSION: u64;
+
+    /// The migrations up to the current version, oldest first: the last
+    /// upgrades version `VERSION - 1`, the one before it `VERSION - 2`,
+    /// and so on. Versions older than the first are not supported.
+    const MIGRATIONS: &'static [Migration] = &[];
+}
+
+/// Errors that can occur decoding or encoding versioned documents
+#[derive(Debug)]
+pub enum VersionError {
+    /// The document was not a JSON object
+    NotAnObject,
+    /// The document had no version field, or it was not an unsigned integer
+    MissingVersion,
+    /// The document's version was older than any with a migration, or
+    /// newer than the current one
+    UnsupportedVersion(u64),
+    /// The migration from the given version failed with the given message
+    MigrationFailed(u64, String),
+    /// The migrated document could not be parsed or decoded
+    DecodeError(json::DecoderError),
+    /// The value could not be encoded
+    EncodeError(json::EncoderError),
+}
+
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VersionError::NotAnObject => write!(f, "document is not an object"),
+            VersionError::MissingVersion => write!(f, "document has no version"),
+            VersionError::UnsupportedVersion(v) => write!(f, "unsupported version {}", v),
+            VersionError::MigrationFailed(v, ref msg) => {
+                write!(f, "migration from version {} failed: {}", v, msg)
+            }
+            VersionError::DecodeError(ref e) => fmt::Display::fmt(e, f),
+            VersionError::EncodeError(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl error::Error for VersionError {
+    fn description(&self) -> &str { "versioned document error" }
+}
+
+/// Migrates `doc` from whatever version it has to `T::VERSION`, updating
+/// its version field.
+pub fn migrate<T: Versioned>(doc: &mut Object) -> Result<(), VersionError> {
+    let version = match doc.get(VERSION_FIELD).and_then(|v| v.as_u64()) {
+        Some(version) => version,
+        None => return Err(VersionError::MissingVersion),
+    };
+    let oldest = T::VERSION.saturating_sub(T::MIGRATIONS.len() as u64);
+    if version < oldest || version > T::VERSION {
+        return Err(VersionError::UnsupportedVersion(version));
+    }
+    for (i, migration) in T::MIGRATIONS[(version - oldest) as usize..].iter().enumerate() {
+        let from = version + i as u64;
+        try!(migration(doc).map_err(|msg| VersionError::MigrationFailed(from, msg)));
+    }
+    doc.insert(VERSION_FIELD.to_string(), Json::U64(T::VERSION));
+    Ok(())
+}
+
+/// Decodes a `T` from a JSON document of any supported version.
+pub fn decode<T: Versioned + Decodable>(s: &str) -> Result<T, VersionError> {
+    let json = try!(Json::from_str(s).map_err(|e| {
+        VersionError::DecodeError(json::DecoderError::ParseError(e))
+    }));
+    from_json(json)
+}
+
+/// Decodes a `T` from a `Json` document of any supported version.
+pub fn from_json<T: Versioned + Decodable>(json: Json) -> Result<T, VersionError> {
+    let mut doc = match json {
+        Json::Object(doc) => doc,
+        _ => return Err(VersionError::NotAnObject),
+    };
+    try!(migrate::<T>(&mut doc));
+    let mut decoder = json::Decoder::new(Json::Object(doc));
+    Decodable::decode(&mut decoder).map_err(VersionError::DecodeError)
+}
+
+/// Encodes `value` as a JSON document of the current version.
+///
+/// `value` must encode as an object, without a field of its own named
+/// `VERSION_FIELD`.
+pub fn encode<T: Versioned + Encodable>(value: &T) -> Result<String, VersionError> {
+    let fields = try!(json::encode(value).map_err(VersionError::EncodeError));
+    if !fields.starts_with('{') {
+        return Err(VersionError::NotAnObject);
+    }
+    let sep = if fields == "{}" { "" } else { "," };
+    Ok(format!("{{\"{}\":{}{}{}", VERSION_FIELD, T::VERSION, sep, &fields[1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use json::{Json, Object};
+    use versioned::{decode, encode, from_json, Migration, VersionError, Versioned};
+    use {Decodable, Decoder, Encodable, Encoder};
+
+    #[derive(Debug, PartialEq)]
+    struct Settings {
+        width: u32,
+        height: u32,
+    }
+
+    impl Encodable for Settings {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_struct("Settings", 2, |s| {
+                try!(s.emit_struct_field("width", 0, |s| self.width.encode(s)));
+                s.emit_struct_field("height", 1, |s| self.height.encode(s))
+            })
+        }
+    }
+
+    impl Decodable for Settings {
+        fn decode<D: Decoder>(d: &mut D) -> Result<Settings, D::Error> {
+            d.read_struct("Settings", 2, |d| {
+                Ok(Settings {
+                    width: try!(d.read_struct_field("width", 0, Decodable::decode)),
+                    height: try!(d.read_struct_field("height", 1, Decodable::decode)),
+                })
+            })
+        }
+    }
+
+    // Version 2 renamed `w` and `h`, and version 3 added `height`.
+    fn rename(doc: &mut Object) -> Result<(), String> {
+        match doc.remove("w") {
+            Some(w) => doc.insert("width".to_string(), w),
+            None => return Err("no `w`".to_string()),
+        };
+        Ok(())
+    }
+
+    fn add_height(doc: &mut Object) -> Result<(), String> {
+        doc.insert("height".to_string(), Json::U64(480));
+        Ok(())
+    }
+
+    impl Versioned for Settings {
+        const VERSION: u64 = 3;
+        const MIGRATIONS: &'static [Migration] = &[rename, add_height];
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Empty {}
+
+    impl Encodable for Empty {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_struct("Empty", 0, |_| Ok(()))
+        }
+    }
+
+    impl Decodable for Empty {
+        fn decode<D: Decoder>(d: &mut D) -> Result<Empty, D::Error> {
+            d.read_struct("Empty", 0, |_| Ok(Empty {}))
+        }
+    }
+
+    impl Versioned for Empty {
+        const VERSION: u64 = 7;
+    }
+
+    #[test]
+    fn test_decode() {
+        let settings = Settings { width: 640, height: 480 };
+        assert_eq!(decode::<Settings>("{\"version\":1,\"w\":640}").unwrap(), settings);
+        assert_eq!(decode::<Settings>("{\"version\":2,\"width\":640}").unwrap(), settings);
+        assert_eq!(decode::<Settings>("{\"version\":3,\"width\":640,\"height\":480}").unwrap(),
+                   settings);
+        let encoded = encode(&settings).unwrap();
+        assert_eq!(encoded, "{\"version\":3,\"width\":640,\"height\":480}");
+        assert_eq!(decode::<Settings>(&encoded).unwrap(), settings);
+
+        assert_eq!(encode(&Empty {}).unwrap(), "{\"version\":7}");
+        assert_eq!(decode::<Empty>("{\"version\":7}").unwrap(), Empty {});
+    }
+
+    #[test]
+    fn test_errors() {
+        match decode::<Settings>("{\"version\":0,\"w\":640}") {
+            Err(VersionError::UnsupportedVersion(0)) => {}
+            other => panic!("{:?}", other),
+        }
+        match decode::<Settings>("{\"version\":4,\"width\":640,\"height\":480}") {
+            Err(VersionError::UnsupportedVersion(4)) => {}
+            other => panic!("{:?}", other),
+        }
+        match decode::<Settings>("{\"version\":1,\"width\":640}") {
+            Err(VersionError::MigrationFailed(1, ref msg)) if msg == "no `w`" => {}
+            other => panic!("{:?}", other),
+        }
+        match decode::<Settings>("{\"version\":\"3\"}") {
+            Err(VersionError::MissingVersion) => {}
+            other => panic!("{:?}", other),
+        }
+        match from_json::<Settings>(Json::Array(vec![])) {
+            Err(VersionError::NotAnObject) => {}
+            other => panic!("{:?}", other),
+        }
+        match decode::<Settings>("{\"version\":3,\"width\":640}") {
+            Err(VersionError::DecodeError(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        match encode(&vec![1u64]) {
+            Err(VersionError::NotAnObject) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    impl Versioned for Vec<u64> {
+        const VERSION: u64 = 1;
+    }
+}
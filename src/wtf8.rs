@@ -0,0 +1,280 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Strings which may hold unpaired UTF-16 surrogates
+//!
+//! JavaScript strings are sequences of UTF-16 code units, which need not
+//! pair up their surrogates, so JSON written by JavaScript can contain
+//! escapes such as `"\uD800"` that no Rust `String` can hold. A `Wtf8Buf`
+//! holds them in [WTF-8](https://simonsapin.github.io/wtf-8/), the
+//! generalization of UTF-8 encoding surrogates as any other code point, so
+//! that they can be written back out as they were read.
+//!
+//! A `Wtf8Buf` without lone surrogates is plain UTF-8, and `as_str`
+//! borrows it as a `str`; `to_string_lossy` replaces any lone surrogates
+//! with U+FFFD.
+//!
+//! The `json` parser keeps lone surrogates as `Json::Wtf8` values when set
+//! to `SurrogatePolicy::Wtf8`, and the `json` encoder writes them back as
+//! escapes. Other encoders write the string lossily.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use rustc_serialize::wtf8::Wtf8Buf;
+//!
+//! fn main() {
+//!     let units = [0x61, 0xD800, 0x62];
+//!     let s = Wtf8Buf::from_utf16(&units);
+//!     assert_eq!(s.as_str(), None);
+//!     assert_eq!(s.to_string_lossy(), "a\u{FFFD}b");
+//!     assert_eq!(s.to_utf16(), units);
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::char;
+use std::fmt;
+use std::str;
+
+use {Decodable, Decoder, Encodable, Encoder};
+
+/// An owned WTF-8 string: a sequence of Unicode code points, surrogates
+/// included, with no surrogate pairs.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Wtf8Buf {
+    bytes: Vec<u8>,
+}
+
+impl Wtf8Buf {
+    /// Creates an empty string.
+    pub fn new() -> Wtf8Buf {
+        Wtf8Buf { bytes: Vec::new() }
+    }
+
+    /// Decodes UTF-16 code units, keeping unpaired surrogates.
+    pub fn from_utf16(units: &[u16]) -> Wtf8Buf {
+        let mut s = Wtf8Buf { bytes: Vec::with_capacity(units.len()) };
+        for &unit in units {
+            s.push_code_point(unit as u32);
+        }
+        s
+    }
+
+    /// Appends a string.
+    pub fn push_str(&mut self, s: &str) {
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    /// Appends a character.
+    pub fn push_char(&mut self, c: char) {
+        let mut buf = [0; 4];
+        self.bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    }
+
+    /// Appends a code point, which may be a surrogate. A trailing surrogate
+    /// appended to a leading one combines with it into the character they
+    /// encode together in UTF-16.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `code_point` is above U+10FFFF.
+    pub fn push_code_point(&mut self, code_point: u32) {
+        if let Some(c) = char::from_u32(code_point) {
+            return self.push_char(c);
+        }
+        assert!(code_point <= 0xDFFF, "code point out of range: {:#X}", code_point);
+        if code_point >= 0xDC00 {
+            if let Some(lead) = self.final_leading_surrogate() {
+                let len = self.bytes.len();
+                self.bytes.truncate(len - 3);
+                let c = 0x1_0000 + ((lead - 0xD800) << 10 | (code_point - 0xDC00));
+                return self.push_char(char::from_u32(c).unwrap());
+            }
+        }
+        self.bytes.push(0xE0 | (code_point >> 12) as u8);
+        self.bytes.push(0x80 | (code_point >> 6 & 0x3F) as u8);
+        self.bytes.push(0x80 | (code_point & 0x3F) as u8);
+    }
+
+    fn final_leading_surrogate(&self) -> Option<u32> {
+        match self.bytes.len().checked_sub(3).map(|i| &self.bytes[i..]) {
+            Some(&[0xED, b1 @ 0xA0...0xAF, b2]) => {
+                Some(0xD000 | (b1 as u32 & 0x3F) << 6 | (b2 as u32 & 0x3F))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the string holds no lone surrogates, so that it is also
+    /// UTF-8.
+    pub fn is_well_formed(&self) -> bool {
+        self.as_str().is_some()
+    }
+
+    /// Borrows the string as a `str`, unless it holds lone surrogates.
+    pub fn as_str(&self) -> Option<&str> {
+        // UTF-8 is WTF-8 without the encodings of surrogates, which
+        // `from_utf8` rejects.
+        str::from_utf8(&self.bytes).ok()
+    }
+
+    /// Converts the string into a `String`, or gives it back if it holds
+    /// lone surrogates.
+    pub fn into_string(self) -> Result<String, Wtf8Buf> {
+        String::from_utf8(self.bytes).map_err(|e| Wtf8Buf { bytes: e.into_bytes() })
+    }
+
+    /// Converts the string to UTF-8, replacing each lone surrogate with
+    /// U+FFFD.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        match self.as_str() {
+            Some(s) => Cow::Borrowed(s),
+            None => {
+                let chars = self.code_points().map(|c| {
+                    char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER)
+                });
+                Cow::Owned(chars.collect())
+            }
+        }
+    }
+
+    /// Encodes the string in UTF-16, lone surrogates included.
+    pub fn to_utf16(&self) -> Vec<u16> {
+        let mut units = Vec::with_capacity(self.bytes.len());
+        for c in self.code_points() {
+            if c >= 0x1_0000 {
+                units.push((0xD800 + ((c - 0x1_0000) >> 10)) as u16);
+                units.push((0xDC00 + ((c - 0x1_0000) & 0x3FF)) as u16);
+            } else {
+                units.push(c as u16);
+            }
+        }
+        units
+    }
+
+    /// The string's WTF-8 encoding.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// An iterator over the string's code points, surrogates included.
+    pub fn code_points(&self) -> CodePoints<'_> {
+        CodePoints { bytes: &self.bytes }
+    }
+}
+
+impl<'a> From<&'a str> for Wtf8Buf {
+    fn from(s: &'a str) -> Wtf8Buf {
+        Wtf8Buf { bytes: s.as_bytes().to_vec() }
+    }
+}
+
+impl From<String> for Wtf8Buf {
+    fn from(s: String) -> Wtf8Buf {
+        Wtf8Buf { bytes: s.into_bytes() }
+    }
+}
+
+/// Writes the string lossily, as `to_string_lossy` converts it.
+impl fmt::Display for Wtf8Buf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.to_string_lossy(), f)
+    }
+}
+
+/// Writes the string as a quoted literal, with lone surrogates as `\u{..}`
+/// escapes.
+impl fmt::Debug for Wtf8Buf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(f.write_str("\""));
+        for c in self.code_points() {
+            match char::from_u32(c) {
+                Some(c) => {
+                    for c in c.escape_debug() {
+                        try!(fmt::Write::write_char(f, c));
+                    }
+                }
+                None => try!(write!(f, "\\u{{{:x}}}", c)),
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+/// An iterator over the code points of a `Wtf8Buf`.
+pub struct CodePoints<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for CodePoints<'a> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let b0 = match self.bytes.first() {
+            Some(&b) => b as u32,
+            None => return None,
+        };
+        let (len, init) = match b0 {
+            0x00...0x7F => (1, b0),
+            0xC0...0xDF => (2, b0 & 0x1F),
+            0xE0...0xEF => (3, b0 & 0x0F),
+            _ => (4, b0 & 0x07),
+        };
+        let c = self.bytes[1..len].iter().fold(init, |c, &b| c << 6 | (b as u32 & 0x3F));
+        self.bytes = &self.bytes[len..];
+        Some(c)
+    }
+}
+
+impl Encodable for Wtf8Buf {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_wtf8(self)
+    }
+}
+
+impl Decodable for Wtf8Buf {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Wtf8Buf, D::Error> {
+        d.read_wtf8()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Wtf8Buf;
+
+    #[test]
+    fn test_wtf8() {
+        let mut s = Wtf8Buf::from("a");
+        s.push_code_point(0xD83D);
+        assert_eq!(s.as_str(), None);
+        assert_eq!(s.to_string_lossy(), "a\u{FFFD}");
+        assert_eq!(format!("{:?}", s), "\"a\\u{d83d}\"");
+        assert_eq!(s.code_points().collect::<Vec<_>>(), [0x61, 0xD83D]);
+
+        // Appending the trailing surrogate completes the pair.
+        s.push_code_point(0xDE00);
+        assert_eq!(s.as_str(), Some("a\u{1F600}"));
+        assert_eq!(s.clone().into_string(), Ok("a\u{1F600}".to_string()));
+
+        s.push_code_point(0xDE00);
+        s.push_str("\u{e9}");
+        assert_eq!(s.to_string_lossy(), "a\u{1F600}\u{FFFD}\u{e9}");
+        let units = s.to_utf16();
+        assert_eq!(units, [0x61, 0xD83D, 0xDE00, 0xDE00, 0xE9]);
+        assert_eq!(Wtf8Buf::from_utf16(&units), s);
+        assert!(s.clone().into_string().is_err());
+
+        let lone = Wtf8Buf::from_utf16(&[0xDBFF, 0x41, 0xD800]);
+        assert_eq!(lone.code_points().collect::<Vec<_>>(), [0xDBFF, 0x41, 0xD800]);
+        assert_eq!(lone.as_bytes(), b"\xED\xAF\xBFA\xED\xA0\x80");
+    }
+}
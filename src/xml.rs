@@ -0,0 +1,1163 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! XML reading and writing
+//!
+//! `EventReader` is a streaming pull parser, returning the elements and
+//! text of a document from a reader as `Event`s. It checks that the
+//! document is well-formed, expands the predefined entities and character
+//! references, normalizes line ends to `\n` and whitespace in attribute
+//! values to spaces, and skips a leading byte order mark, the XML
+//! declaration, processing instructions, comments and the document type
+//! declaration. The internal subset of the document type declaration is
+//! not checked, and the entities it declares are reported as unknown.
+//! Namespaces are not interpreted: a prefixed name such as `xs:element` is
+//! returned as is.
+//!
+//! `Encoder` writes an `Encodable` value as an element. How the data model
+//! maps onto XML is set by `EncoderConfig`:
+//!
+//! * Struct fields and map entries become child elements, or attributes
+//!   for primitive values if `attributes` is set. The value of the field
+//!   named `text_field` becomes the text of the element instead.
+//! * Sequence items become `item_name` elements, or if it is `None`,
+//!   sequences in fields repeat the field's element for each item.
+//! * `None` is omitted from structs and maps and is an empty element
+//!   elsewhere.
+//! * A variant without fields is its name as text. Other variants are
+//!   written as a struct with one field named after the variant, holding
+//!   the variant's field, its fields as a sequence, or for a struct variant
+//!   emitted with `emit_enum_struct_variant_field` a struct of its fields.
+//!   Derived impls emit the fields of struct variants as positional
+//!   arguments, so those are written as a sequence too.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate rustc_serialize;
+//! use std::collections::BTreeMap;
+//! use rustc_serialize::xml::{self, EncoderConfig, Event, EventReader};
+//!
+//! fn main() {
+//!     let doc = "<?xml version=\"1.0\"?><a x='1'>fish &amp; chips<b/></a>";
+//!     let events: Vec<Event> = EventReader::new(doc.as_bytes()).map(|e| e.unwrap()).collect();
+//!     assert_eq!(events, [
+//!         Event::StartElement { name: "a".to_string(),
+//!                               attributes: vec![("x".to_string(), "1".to_string())] },
+//!         Event::Text("fish & chips".to_string()),
+//!         Event::StartElement { name: "b".to_string(), attributes: vec![] },
+//!         Event::EndElement { name: "b".to_string() },
+//!         Event::EndElement { name: "a".to_string() },
+//!     ]);
+//!
+//!     let mut map = BTreeMap::new();
+//!     map.insert("lang", "en");
+//!     let config = EncoderConfig { attributes: true, ..EncoderConfig::default() };
+//!     assert_eq!(xml::encode_with_config(&map, &config).unwrap(), "<root lang=\"en\"></root>");
+//!     assert_eq!(xml::encode(&vec![1, 2]).unwrap(),
+//!                "<root><item>1</item><item>2</item></root>");
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::io::{self, Read};
+
+use Encodable;
+use utf8::{CharIndicesBuffer, InvalidUtf8};
+
+use self::ErrorCode::*;
+use self::ParserError::*;
+
+/// An event in an XML document.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A start tag, or an empty-element tag (which is followed by its
+    /// `EndElement`)
+    StartElement {
+        /// The element name
+        name: String,
+        /// The attributes, in order, with their values unescaped
+        attributes: Vec<(String, String)>,
+    },
+    /// An end tag
+    EndElement {
+        /// The element name
+        name: String,
+    },
+    /// Text, unescaped, from character data or a CDATA section
+    Text(String),
+}
+
+/// The ways in which a document can be malformed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    UnexpectedEof,
+    UnexpectedCharacter,
+    InvalidName,
+    MismatchedEndTag,
+    DuplicateAttribute,
+    UnknownEntity,
+    InvalidCharReference,
+    ContentOutsideRoot,
+    MissingRoot,
+    NotUtf8,
+    InvalidCharacter,
+    DuplicateDoctype,
+}
+
+/// Returns a readable error string for a given error code.
+pub fn error_str(error: ErrorCode) -> &'static str {
+    match error {
+        UnexpectedEof => "unexpected end of input",
+        UnexpectedCharacter => "unexpected character",
+        InvalidName => "invalid name",
+        MismatchedEndTag => "end tag does not match start tag",
+        DuplicateAttribute => "duplicate attribute",
+        UnknownEntity => "unknown entity",
+        InvalidCharReference => "invalid character reference",
+        ContentOutsideRoot => "content outside the root element",
+        MissingRoot => "no root element",
+        NotUtf8 => "contents not utf-8",
+        InvalidCharacter => "character not allowed in XML",
+        DuplicateDoctype => "more than one document type declaration",
+    }
+}
+
+#[derive(Debug)]
+pub enum ParserError {
+    /// code, byte offset
+    SyntaxError(ErrorCode, usize),
+    IoError(io::Error),
+}
+
+impl PartialEq for ParserError {
+    fn eq(&self, other: &ParserError) -> bool {
+        match (self, other) {
+            (&SyntaxError(code0, offset0), &SyntaxError(code1, offset1)) =>
+                code0 == code1 && offset0 == offset1,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SyntaxError(code, offset) => write!(f, "{} at byte {}", error_str(code), offset),
+            IoError(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for ParserError {
+    fn description(&self) -> &str { "failed to parse XML" }
+}
+
+pub type ParseResult<T> = Result<T, ParserError>;
+
+/// A pull parser returning the events of an XML document read from `R`.
+///
+/// Parsing stops at the end of the input, which must complete the root
+/// element, or at the first error; the iterator then returns `None`.
+pub struct EventReader<R> {
+    chars: CharIndicesBuffer<R>,
+    // The open elements, innermost last.
+    stack: Vec<String>,
+    // Whether the innermost element came from an empty-element tag, whose
+    // `EndElement` is still to be returned.
+    pending_end: bool,
+    seen_root: bool,
+    seen_doctype: bool,
+    done: bool,
+}
+
+impl<R: Read> EventReader<R> {
+    /// Creates a parser reading a document from `rdr`.
+    pub fn new(rdr: R) -> EventReader<R> {
+        EventReader {
+            chars: CharIndicesBuffer::new(rdr),
+            stack: Vec::new(),
+            pending_end: false,
+            seen_root: false,
+            seen_doctype: false,
+            done: false,
+        }
+    }
+
+    /// Returns the number of elements open.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Returns the byte offset of the next input to parse.
+    pub fn offset(&self) -> usize {
+        self.chars.offset()
+    }
+
+    /// Unwraps this parser, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.chars.into_inner()
+    }
+
+    /// Returns the next event, or `None` at the end of the document.
+    pub fn next_event(&mut self) -> ParseResult<Option<Event>> {
+        if self.pending_end {
+            self.pending_end = false;
+            let name = self.stack.pop().unwrap();
+            return Ok(Some(Event::EndElement { name: name }));
+        }
+        if self.chars.offset() == 0 && try!(self.peek_char()) == Some('\u{feff}') {
+            try!(self.next_char());
+        }
+        loop {
+            let c = match try!(self.peek_char()) {
+                Some(c) => c,
+                None if !self.stack.is_empty() => return self.error(UnexpectedEof),
+                None if !self.seen_root => return self.error(MissingRoot),
+                None => return Ok(None),
+            };
+            if c != '<' {
+                if self.stack.is_empty() {
+                    try!(self.skip_whitespace());
+                    match try!(self.peek_char()) {
+                        Some('<') | None => continue,
+                        Some(_) => return self.error(ContentOutsideRoot),
+                    }
+                }
+                return self.text().map(|text| Some(Event::Text(text)));
+            }
+            let start = self.chars.offset();
+            try!(self.next_char());
+            match try!(self.peek_char()) {
+                Some('?') => {
+                    try!(self.skip_past("?>"));
+                }
+                Some('!') => {
+                    try!(self.next_char());
+                    match try!(self.peek_char()) {
+                        Some('-') => {
+                            try!(self.expect_str("--"));
+                            try!(self.skip_comment());
+                        }
+                        Some('[') => {
+                            try!(self.expect_str("[CDATA["));
+                            if self.stack.is_empty() {
+                                return Err(SyntaxError(ContentOutsideRoot, start));
+                            }
+                            let text = try!(self.skip_past("]]>"));
+                            return Ok(Some(Event::Text(text)));
+                        }
+                        _ => {
+                            try!(self.expect_str("DOCTYPE"));
+                            if self.seen_root {
+                                return Err(SyntaxError(ContentOutsideRoot, start));
+                            }
+                            if self.seen_doctype {
+                                return Err(SyntaxError(DuplicateDoctype, start));
+                            }
+                            self.seen_doctype = true;
+                            try!(self.skip_doctype());
+                        }
+                    }
+                }
+                Some('/') => {
+                    try!(self.next_char());
+                    let name = try!(self.name());
+                    try!(self.skip_whitespace());
+                    try!(self.expect('>'));
+                    return match self.stack.pop() {
+                        Some(ref open) if *open == name => {
+                            Ok(Some(Event::EndElement { name: name }))
+                        }
+                        _ => Err(SyntaxError(MismatchedEndTag, start)),
+                    };
+                }
+                _ => {
+                    if self.stack.is_empty() && self.seen_root {
+                        return Err(SyntaxError(ContentOutsideRoot, start));
+                    }
+                    return self.start_tag().map(Some);
+                }
+            }
+        }
+    }
+
+    // Parses a start tag after its `<`.
+    fn start_tag(&mut self) -> ParseResult<Event> {
+        let name = try!(self.name());
+        let mut attributes: Vec<(String, String)> = Vec::new();
+        loop {
+            let space = try!(self.skip_whitespace());
+            match try!(self.peek_char()) {
+                Some('>') => {
+                    try!(self.next_char());
+                    break;
+                }
+                Some('/') => {
+                    try!(self.next_char());
+                    try!(self.expect('>'));
+                    self.pending_end = true;
+                    break;
+                }
+                Some(_) if space > 0 => {
+                    let start = self.chars.offset();
+                    let attr = try!(self.name());
+                    try!(self.skip_whitespace());
+                    try!(self.expect('='));
+                    try!(self.skip_whitespace());
+                    let value = try!(self.attribute_value());
+                    if attributes.iter().any(|a| a.0 == attr) {
+                        return Err(SyntaxError(DuplicateAttribute, start));
+                    }
+                    attributes.push((attr, value));
+                }
+                Some(_) => return self.error(UnexpectedCharacter),
+                None => return self.error(UnexpectedEof),
+            }
+        }
+        self.seen_root = true;
+        self.stack.push(name.clone());
+        Ok(Event::StartElement { name: name, attributes: attributes })
+    }
+
+    fn text(&mut self) -> ParseResult<String> {
+        let mut text = String::new();
+        // The number of `]` just read, as `]]>` may not appear in text.
+        let mut brackets = 0;
+        loop {
+            match try!(self.peek_char()) {
+                Some('<') | None => return Ok(text),
+                Some('&') => {
+                    try!(self.next_char());
+                    text.push(try!(self.reference()));
+                    brackets = 0;
+                }
+                Some('>') if brackets >= 2 => return self.error(UnexpectedCharacter),
+                Some(_) => {
+                    let c = try!(self.next_normalized_char());
+                    brackets = if c == ']' { brackets + 1 } else { 0 };
+                    text.push(c);
+                }
+            }
+        }
+    }
+
+    fn attribute_value(&mut self) -> ParseResult<String> {
+        let quote = match try!(self.peek_char()) {
+            Some(c @ '"') | Some(c @ '\'') => c,
+            _ => return self.error(UnexpectedCharacter),
+        };
+        try!(self.next_char());
+        let mut value = String::new();
+        loop {
+            match try!(self.peek_char()) {
+                Some('<') => return self.error(UnexpectedCharacter),
+                Some('&') => {
+                    try!(self.next_char());
+                    value.push(try!(self.reference()));
+                }
+                Some(_) => {
+                    let c = try!(self.next_normalized_char());
+                    if c == quote {
+                        return Ok(value);
+                    }
+                    value.push(if c == '\t' || c == '\n' { ' ' } else { c });
+                }
+                None => return self.error(UnexpectedEof),
+            }
+        }
+    }
+
+    // Parses an entity or character reference after its `&`.
+    fn reference(&mut self) -> ParseResult<char> {
+        let start = self.chars.offset() - 1;
+        let (_, name) = try!(self.chars.take_span_while(|c| c.is_alphanumeric() || c == '#')
+                                       .map_err(io_error));
+        try!(self.expect(';'));
+        let c = match &name[..] {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ if name.starts_with('#') => {
+                let code = if let Some(hex) = name.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16)
+                } else {
+                    name[1..].parse()
+                };
+                match code.ok().and_then(::std::char::from_u32) {
+                    Some(c) if is_xml_char(c) => c,
+                    _ => return Err(SyntaxError(InvalidCharReference, start)),
+                }
+            }
+            _ => return Err(SyntaxError(UnknownEntity, start)),
+        };
+        Ok(c)
+    }
+
+    fn name(&mut self) -> ParseResult<String> {
+        let start = self.chars.offset();
+        let (_, name) = try!(self.chars.take_span_while(is_name_char).map_err(io_error));
+        if !name.starts_with(is_name_start_char) {
+            return Err(SyntaxError(InvalidName, start));
+        }
+        Ok(name)
+    }
+
+    // Skips the rest of a document type declaration, which may have an
+    // internal subset in brackets.
+    fn skip_doctype(&mut self) -> ParseResult<()> {
+        let mut depth = 0;
+        loop {
+            match try!(self.next_char()) {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '>' if depth == 0 => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    // Skips the rest of a comment after its `<!--`. Comments may not contain
+    // `--` other than in the `-->` ending them.
+    fn skip_comment(&mut self) -> ParseResult<()> {
+        loop {
+            if try!(self.next_char()) == '-' && try!(self.peek_char()) == Some('-') {
+                try!(self.next_char());
+                return self.expect('>');
+            }
+        }
+    }
+
+    // Consumes input up to and including `end`, returning the input before
+    // it with its line ends normalized.
+    fn skip_past(&mut self, end: &str) -> ParseResult<String> {
+        let mut s = String::new();
+        while !s.ends_with(end) {
+            s.push(try!(self.next_normalized_char()));
+        }
+        let len = s.len() - end.len();
+        s.truncate(len);
+        Ok(s)
+    }
+
+    fn skip_whitespace(&mut self) -> ParseResult<usize> {
+        self.chars.skip_while_chars(|c| c == ' ' || c == '\t' || c == '\n' || c == '\r')
+                  .map_err(io_error)
+    }
+
+    fn expect(&mut self, expected: char) -> ParseResult<()> {
+        match try!(self.peek_char()) {
+            Some(c) if c == expected => {
+                try!(self.next_char());
+                Ok(())
+            }
+            Some(_) => self.error(UnexpectedCharacter),
+            None => self.error(UnexpectedEof),
+        }
+    }
+
+    fn expect_str(&mut self, expected: &str) -> ParseResult<()> {
+        for c in expected.chars() {
+            try!(self.expect(c));
+        }
+        Ok(())
+    }
+
+    fn peek_char(&mut self) -> ParseResult<Option<char>> {
+        match self.chars.peek() {
+            Ok(next) => Ok(next.map(|(_, c)| c)),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    fn next_char(&mut self) -> ParseResult<char> {
+        match self.chars.next() {
+            Some(Ok((_, c))) if is_xml_char(c) => Ok(c),
+            Some(Ok((offset, _))) => Err(SyntaxError(InvalidCharacter, offset)),
+            Some(Err(e)) => Err(io_error(e)),
+            None => self.error(UnexpectedEof),
+        }
+    }
+
+    // Returns the next character, reading `\r\n` and a lone `\r` as `\n`.
+    fn next_normalized_char(&mut self) -> ParseResult<char> {
+        let c = try!(self.next_char());
+        if c == '\r' {
+            if try!(self.peek_char()) == Some('\n') {
+                try!(self.next_char());
+            }
+            return Ok('\n');
+        }
+        Ok(c)
+    }
+
+    fn error<T>(&self, code: ErrorCode) -> ParseResult<T> {
+        Err(SyntaxError(code, self.chars.offset()))
+    }
+}
+
+impl<R: Read> Iterator for EventReader<R> {
+    type Item = ParseResult<Event>;
+
+    fn next(&mut self) -> Option<ParseResult<Event>> {
+        if self.done {
+            return None;
+        }
+        match self.next_event() {
+            Ok(Some(event)) => Some(Ok(event)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+fn io_error(err: io::Error) -> ParserError {
+    match InvalidUtf8::from_io_error(&err) {
+        Some(e) => SyntaxError(NotUtf8, e.position),
+        None => IoError(err),
+    }
+}
+
+// Returns whether `c` may appear in a document: the control characters other
+// than tab and the line ends, and the noncharacters U+FFFE and U+FFFF, may
+// not.
+fn is_xml_char(c: char) -> bool {
+    match c {
+        '\t' | '\n' | '\r' => true,
+        '\u{fffe}' | '\u{ffff}' => false,
+        _ => c >= ' ',
+    }
+}
+
+fn is_name_start_char(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == ':'
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c) || c.is_numeric() || c == '-' || c == '.'
+}
+
+/// Returns whether `name` is a valid element or attribute name.
+pub fn is_valid_name(name: &str) -> bool {
+    name.starts_with(is_name_start_char) && name.chars().all(is_name_char)
+}
+
+fn escape(wr: &mut fmt::Write, s: &str, attribute: bool) -> EncodeResult {
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        let escaped = match c {
+            '&' => "&amp;",
+            '<' => "&lt;",
+            '>' => "&gt;",
+            '"' if attribute => "&quot;",
+            _ => continue,
+        };
+        try!(wr.write_str(&s[start..i]));
+        try!(wr.write_str(escaped));
+        start = i + 1;
+    }
+    try!(wr.write_str(&s[start..]));
+    Ok(())
+}
+
+/// Sets how `Encoder` maps values onto XML.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncoderConfig {
+    /// The name of the element holding the encoded value
+    pub root: String,
+    /// The name of the elements for sequence items, or `None` for
+    /// sequences in struct fields and map entries to repeat the field's
+    /// element instead
+    pub item_name: Option<String>,
+    /// True to write primitive values in structs and maps as attributes
+    pub attributes: bool,
+    /// The struct field or map key whose primitive value is written as the
+    /// text of the element
+    pub text_field: Option<String>,
+}
+
+impl Default for EncoderConfig {
+    fn default() -> EncoderConfig {
+        EncoderConfig {
+            root: "root".to_string(),
+            item_name: Some("item".to_string()),
+            attributes: false,
+            text_field: None,
+        }
+    }
+}
+
+/// Errors that can occur when encoding a value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EncoderError {
+    FmtError(fmt::Error),
+    /// A field name or map key was not a valid element or attribute name
+    InvalidName(String),
+    /// A map key was not a primitive value
+    BadMapKey,
+}
+
+impl fmt::Display for EncoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncoderError::FmtError(ref e) => write!(f, "{}", e),
+            EncoderError::InvalidName(ref name) => write!(f, "invalid XML name {:?}", name),
+            EncoderError::BadMapKey => write!(f, "map key is not a primitive value"),
+        }
+    }
+}
+
+impl error::Error for EncoderError {
+    fn description(&self) -> &str { "failed to encode XML" }
+}
+
+impl From<fmt::Error> for EncoderError {
+    fn from(err: fmt::Error) -> EncoderError { EncoderError::FmtError(err) }
+}
+
+pub type EncodeResult = Result<(), EncoderError>;
+
+/// Encodes `object` as an element named `root` with the default
+/// configuration.
+pub fn encode<T: Encodable>(object: &T) -> Result<String, EncoderError> {
+    encode_with_config(object, &EncoderConfig::default())
+}
+
+/// Encodes `object` as an element using `config`.
+pub fn encode_with_config<T: Encodable>(object: &T, config: &EncoderConfig)
+                                        -> Result<String, EncoderError> {
+    let mut s = String::new();
+    {
+        let mut encoder = Encoder::with_config(&mut s, config.clone());
+        try!(object.encode(&mut encoder));
+    }
+    Ok(s)
+}
+
+// A value being encoded, once the data model is reduced to what the
+// element structure needs.
+enum Value {
+    Nil,
+    Text(String),
+    Seq(Vec<Value>),
+    Element(Vec<(String, Value)>),
+}
+
+enum Frame {
+    Seq(Vec<Value>),
+    Element(Vec<(String, Value)>),
+    // A map being built, and the key of the value to come.
+    Map(Vec<(String, Value)>, Option<String>),
+}
+
+/// An encoder writing a value as an XML element.
+///
+/// The value is gathered as it is encoded and written when it is complete,
+/// so nothing is written if encoding fails.
+pub struct Encoder<'a> {
+    writer: &'a mut (fmt::Write + 'a),
+    config: EncoderConfig,
+    stack: Vec<Frame>,
+    value: Option<Value>,
+}
+
+impl<'a> Encoder<'a> {
+    /// Creates a new encoder writing to `writer` with the default
+    /// configuration.
+    pub fn new(writer: &'a mut fmt::Write) -> Encoder<'a> {
+        Encoder::with_config(writer, EncoderConfig::default())
+    }
+
+    /// Creates a new encoder writing to `writer` using `config`.
+    pub fn with_config(writer: &'a mut fmt::Write, config: EncoderConfig) -> Encoder<'a> {
+        Encoder { writer: writer, config: config, stack: Vec::new(), value: None }
+    }
+
+    fn set(&mut self, value: Value) -> EncodeResult {
+        if self.stack.is_empty() {
+            let root = self.config.root.clone();
+            write_element(self.writer, &self.config, &root, &value)
+        } else {
+            self.value = Some(value);
+            Ok(())
+        }
+    }
+
+    // Runs `f` and returns the value it emitted.
+    fn capture<F>(&mut self, f: F) -> Result<Value, EncoderError>
+        where F: FnOnce(&mut Encoder<'a>) -> EncodeResult
+    {
+        try!(f(self));
+        Ok(self.value.take().unwrap_or(Value::Nil))
+    }
+
+    fn collect<F>(&mut self, frame: Frame, f: F) -> Result<Frame, EncoderError>
+        where F: FnOnce(&mut Encoder<'a>) -> EncodeResult
+    {
+        self.stack.push(frame);
+        try!(f(self));
+        Ok(self.stack.pop().unwrap())
+    }
+
+    fn push_item<F>(&mut self, f: F) -> EncodeResult
+        where F: FnOnce(&mut Encoder<'a>) -> EncodeResult
+    {
+        let value = try!(self.capture(f));
+        if let Some(&mut Frame::Seq(ref mut items)) = self.stack.last_mut() {
+            items.push(value);
+        }
+        Ok(())
+    }
+
+    fn push_field<F>(&mut self, name: &str, f: F) -> EncodeResult
+        where F: FnOnce(&mut Encoder<'a>) -> EncodeResult
+    {
+        let value = try!(self.capture(f));
+        if let Some(&mut Frame::Element(ref mut fields)) = self.stack.last_mut() {
+            fields.push((name.to_string(), value));
+        }
+        Ok(())
+    }
+
+    fn text<T: fmt::Display>(&mut self, v: T) -> EncodeResult {
+        self.set(Value::Text(v.to_string()))
+    }
+}
+
+fn write_element(wr: &mut fmt::Write, config: &EncoderConfig, name: &str, value: &Value)
+                 -> EncodeResult {
+    if !is_valid_name(name) {
+        return Err(EncoderError::InvalidName(name.to_string()));
+    }
+    try!(write!(wr, "<{}", name));
+    match *value {
+        Value::Nil => {
+            try!(wr.write_str("/>"));
+            return Ok(());
+        }
+        Value::Text(ref s) => {
+            try!(wr.write_str(">"));
+            try!(escape(wr, s, false));
+        }
+        Value::Seq(ref items) => {
+            try!(wr.write_str(">"));
+            let item = config.item_name.as_ref().map(|s| &s[..]).unwrap_or("item");
+            for v in items {
+                try!(write_element(wr, config, item, v));
+            }
+        }
+        Value::Element(ref fields) => {
+            let is_text_field = |k: &str| config.text_field.as_ref().map_or(false, |t| t == k);
+            if config.attributes {
+                for (k, v) in fields {
+                    if let Value::Text(ref s) = *v {
+                        if is_text_field(k) {
+                            continue;
+                        }
+                        if !is_valid_name(k) {
+                            return Err(EncoderError::InvalidName(k.to_string()));
+                        }
+                        try!(write!(wr, " {}=\"", k));
+                        try!(escape(wr, s, true));
+                        try!(wr.write_str("\""));
+                    }
+                }
+            }
+            try!(wr.write_str(">"));
+            for (k, v) in fields {
+                match *v {
+                    Value::Nil => {}
+                    Value::Text(ref s) if is_text_field(k) => try!(escape(wr, s, false)),
+                    Value::Text(_) if config.attributes => {}
+                    Value::Seq(ref items) if config.item_name.is_none() => {
+                        for item in items {
+                            try!(write_element(wr, config, k, item));
+                        }
+                    }
+                    _ => try!(write_element(wr, config, k, v)),
+                }
+            }
+        }
+    }
+    try!(write!(wr, "</{}>", name));
+    Ok(())
+}
+
+impl<'a> ::Encoder for Encoder<'a> {
+    type Error = EncoderError;
+
+    fn emit_nil(&mut self) -> EncodeResult { self.set(Value::Nil) }
+
+    fn emit_usize(&mut self, v: usize) -> EncodeResult { self.text(v) }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult { self.text(v) }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult { self.text(v) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult { self.text(v) }
+    fn emit_u8(&mut self, v: u8) -> EncodeResult { self.text(v) }
+
+    fn emit_isize(&mut self, v: isize) -> EncodeResult { self.text(v) }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult { self.text(v) }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult { self.text(v) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult { self.text(v) }
+    fn emit_i8(&mut self, v: i8) -> EncodeResult { self.text(v) }
+
+    fn emit_bool(&mut self, v: bool) -> EncodeResult { self.text(v) }
+    fn emit_f64(&mut self, v: f64) -> EncodeResult { self.text(v) }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult { self.text(v) }
+    fn emit_char(&mut self, v: char) -> EncodeResult { self.text(v) }
+    fn emit_str(&mut self, v: &str) -> EncodeResult { self.text(v) }
+
+    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_enum_variant<F>(&mut self, name: &str, _id: usize, cnt: usize, f: F)
+                            -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        if cnt == 0 {
+            return self.text(name);
+        }
+        match try!(self.collect(Frame::Seq(Vec::new()), f)) {
+            Frame::Seq(mut args) => {
+                let value = if args.len() == 1 { args.pop().unwrap() } else { Value::Seq(args) };
+                self.set(Value::Element(vec![(name.to_string(), value)]))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn emit_enum_variant_arg<F>(&mut self, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.push_item(f)
+    }
+
+    fn emit_enum_struct_variant<F>(&mut self, name: &str, _id: usize, cnt: usize, f: F)
+                                   -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        if cnt == 0 {
+            return self.text(name);
+        }
+        match try!(self.collect(Frame::Element(Vec::new()), f)) {
+            Frame::Element(fields) => {
+                self.set(Value::Element(vec![(name.to_string(), Value::Element(fields))]))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn emit_enum_struct_variant_field<F>(&mut self, name: &str, _idx: usize, f: F)
+                                         -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.push_field(name, f)
+    }
+
+    fn emit_struct<F>(&mut self, _name: &str, _len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        match try!(self.collect(Frame::Element(Vec::new()), f)) {
+            Frame::Element(fields) => self.set(Value::Element(fields)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn emit_struct_field<F>(&mut self, name: &str, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.push_field(name, f)
+    }
+
+    fn emit_tuple<F>(&mut self, len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_seq(len, f)
+    }
+
+    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_tuple_struct<F>(&mut self, _: &str, len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_seq(len, f)
+    }
+
+    fn emit_tuple_struct_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_option_none(&mut self) -> EncodeResult { self.emit_nil() }
+
+    fn emit_option_some<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_seq<F>(&mut self, _len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        match try!(self.collect(Frame::Seq(Vec::new()), f)) {
+            Frame::Seq(items) => self.set(Value::Seq(items)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn emit_seq_elt<F>(&mut self, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.push_item(f)
+    }
+
+    fn emit_map<F>(&mut self, _len: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        match try!(self.collect(Frame::Map(Vec::new(), None), f)) {
+            Frame::Map(entries, _) => self.set(Value::Element(entries)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn emit_map_elt_key<F>(&mut self, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        let key = match try!(self.capture(f)) {
+            Value::Text(s) => s,
+            _ => return Err(EncoderError::BadMapKey),
+        };
+        if let Some(&mut Frame::Map(_, ref mut pending)) = self.stack.last_mut() {
+            *pending = Some(key);
+        }
+        Ok(())
+    }
+
+    fn emit_map_elt_val<F>(&mut self, _idx: usize, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        let value = try!(self.capture(f));
+        if let Some(&mut Frame::Map(ref mut entries, ref mut pending)) = self.stack.last_mut() {
+            if let Some(key) = pending.take() {
+                entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_tagged<F>(&mut self, tag: u64, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        // `$tag`, the default, is not a valid XML name.
+        self.emit_struct("tagged", 2, |s| {
+            try!(s.emit_struct_field("tag", 0, |s| ::Encoder::emit_u64(s, tag)));
+            s.emit_struct_field("value", 1, f)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use xml::{encode, encode_with_config, EncoderConfig, EncoderError, ErrorCode, Event};
+    use xml::{EventReader, ParserError};
+    use {Encodable, Encoder};
+
+    fn events(doc: &str) -> Result<Vec<Event>, ParserError> {
+        EventReader::new(doc.as_bytes()).collect()
+    }
+
+    fn start(name: &str, attributes: &[(&str, &str)]) -> Event {
+        Event::StartElement {
+            name: name.to_string(),
+            attributes: attributes.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    fn end(name: &str) -> Event {
+        Event::EndElement { name: name.to_string() }
+    }
+
+    fn text(s: &str) -> Event {
+        Event::Text(s.to_string())
+    }
+
+    #[test]
+    fn test_reader() {
+        let doc = "<?xml version=\"1.0\"?>\n<!DOCTYPE a [<!ENTITY x \"y\">]>\n\
+                   <!-- c --><a b=\"&lt;&#65;&#x42;\" c='\"'>\n  t<![CDATA[<&>]]><d:e/>\
+                   <f>\u{e9}</f ></a>\n<!-- end -->\n";
+        assert_eq!(events(doc).unwrap(),
+                   [start("a", &[("b", "<AB"), ("c", "\"")]), text("\n  t"), text("<&>"),
+                    start("d:e", &[]), end("d:e"), start("f", &[]), text("\u{e9}"), end("f"),
+                    end("a")]);
+
+        let mut reader = EventReader::new(&b"<a><b/></a>"[..]);
+        assert_eq!(reader.next_event().unwrap(), Some(start("a", &[])));
+        assert_eq!(reader.next_event().unwrap(), Some(start("b", &[])));
+        assert_eq!(reader.depth(), 2);
+        assert_eq!(reader.next_event().unwrap(), Some(end("b")));
+        assert_eq!(reader.next_event().unwrap(), Some(end("a")));
+        assert_eq!(reader.next_event().unwrap(), None);
+    }
+
+    #[test]
+    fn test_reader_normalization() {
+        let doc = "\u{feff}<a b='x\r\ny\tz&#13;'>1\r\n2\r3<![CDATA[\r\n]]></a>";
+        assert_eq!(events(doc).unwrap(),
+                   [start("a", &[("b", "x y z\r")]), text("1\n2\n3"), text("\n"), end("a")]);
+    }
+
+    #[test]
+    fn test_reader_errors() {
+        let bad = [("", ErrorCode::MissingRoot, 0),
+                   ("<!-- -->", ErrorCode::MissingRoot, 8),
+                   ("<a>", ErrorCode::UnexpectedEof, 3),
+                   ("<a></b>", ErrorCode::MismatchedEndTag, 3),
+                   ("</a>", ErrorCode::MismatchedEndTag, 0),
+                   ("<a/><b/>", ErrorCode::ContentOutsideRoot, 4),
+                   ("x<a/>", ErrorCode::ContentOutsideRoot, 0),
+                   ("<a/>x", ErrorCode::ContentOutsideRoot, 4),
+                   ("<1/>", ErrorCode::InvalidName, 1),
+                   ("<a b='1'c='2'/>", ErrorCode::UnexpectedCharacter, 8),
+                   ("<a b=1/>", ErrorCode::UnexpectedCharacter, 5),
+                   ("<a b='<'/>", ErrorCode::UnexpectedCharacter, 6),
+                   ("<a b='' b=''/>", ErrorCode::DuplicateAttribute, 8),
+                   ("<a>&nbsp;</a>", ErrorCode::UnknownEntity, 3),
+                   ("<a>&#0;</a>", ErrorCode::InvalidCharReference, 3),
+                   ("<a>&#xd800;</a>", ErrorCode::InvalidCharReference, 3),
+                   ("<a>&#1;</a>", ErrorCode::InvalidCharReference, 3),
+                   ("<a>&amp</a>", ErrorCode::UnexpectedCharacter, 7),
+                   ("<a>]]></a>", ErrorCode::UnexpectedCharacter, 5),
+                   ("<a><!-- a -- b --></a>", ErrorCode::UnexpectedCharacter, 12),
+                   ("<a>\u{1}</a>", ErrorCode::InvalidCharacter, 3),
+                   ("<a b='\u{1b}'/>", ErrorCode::InvalidCharacter, 6),
+                   ("<!DOCTYPE a><!DOCTYPE a><a/>", ErrorCode::DuplicateDoctype, 12),
+                   ("\u{feff}\u{feff}<a/>", ErrorCode::ContentOutsideRoot, 3),
+                   ("<a><!x></a>", ErrorCode::UnexpectedCharacter, 5)];
+        for &(doc, code, offset) in bad.iter() {
+            match events(doc) {
+                Err(ParserError::SyntaxError(c, o)) => assert_eq!((c, o), (code, offset), "{}", doc),
+                other => panic!("{}: {:?}", doc, other),
+            }
+        }
+        match EventReader::new(&b"<a>\xff</a>"[..]).collect::<Result<Vec<_>, _>>() {
+            Err(ParserError::SyntaxError(ErrorCode::NotUtf8, 3)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    enum Shape {
+        Point,
+        Circle(f64),
+        Rect { w: u32, h: u32 },
+    }
+
+    impl Encodable for Shape {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_enum("Shape", |s| {
+                match *self {
+                    Shape::Point => s.emit_enum_variant("Point", 0, 0, |_| Ok(())),
+                    Shape::Circle(ref v) => s.emit_enum_variant("Circle", 1, 1, |s| {
+                        s.emit_enum_variant_arg(0, |s| v.encode(s))
+                    }),
+                    Shape::Rect { ref w, ref h } => s.emit_enum_variant("Rect", 2, 2, |s| {
+                        try!(s.emit_enum_variant_arg(0, |s| w.encode(s)));
+                        s.emit_enum_variant_arg(1, |s| h.encode(s))
+                    }),
+                }
+            })
+        }
+    }
+
+    struct Item {
+        id: u32,
+        name: String,
+        note: Option<String>,
+        tags: Vec<&'static str>,
+        shapes: Vec<Shape>,
+    }
+
+    impl Encodable for Item {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_struct("Item", 5, |s| {
+                try!(s.emit_struct_field("id", 0, |s| self.id.encode(s)));
+                try!(s.emit_struct_field("name", 1, |s| self.name.encode(s)));
+                try!(s.emit_struct_field("note", 2, |s| self.note.encode(s)));
+                try!(s.emit_struct_field("tags", 3, |s| self.tags.encode(s)));
+                s.emit_struct_field("shapes", 4, |s| self.shapes.encode(s))
+            })
+        }
+    }
+
+    fn item() -> Item {
+        Item {
+            id: 7,
+            name: "a<b".to_string(),
+            note: None,
+            tags: vec!["x", "y"],
+            shapes: vec![Shape::Point, Shape::Circle(1.5), Shape::Rect { w: 2, h: 3 }],
+        }
+    }
+
+    #[test]
+    fn test_encoder() {
+        assert_eq!(encode(&item()).unwrap(),
+                   "<root><id>7</id><name>a&lt;b</name><tags><item>x</item><item>y</item></tags>\
+                    <shapes><item>Point</item><item><Circle>1.5</Circle></item>\
+                    <item><Rect><item>2</item><item>3</item></Rect></item></shapes></root>");
+
+        let config = EncoderConfig {
+            root: "entry".to_string(),
+            item_name: None,
+            attributes: true,
+            text_field: Some("name".to_string()),
+        };
+        assert_eq!(encode_with_config(&item(), &config).unwrap(),
+                   "<entry id=\"7\">a&lt;b<tags>x</tags><tags>y</tags><shapes>Point</shapes>\
+                    <shapes Circle=\"1.5\"></shapes><shapes><Rect>2</Rect><Rect>3</Rect>\
+                    </shapes></entry>");
+
+        assert_eq!(encode(&Some(vec![None, Some("\"&\"")])).unwrap(),
+                   "<root><item/><item>\"&amp;\"</item></root>");
+        let mut map = BTreeMap::new();
+        map.insert(1, "one");
+        assert_eq!(encode(&map), Err(EncoderError::InvalidName("1".to_string())));
+        let mut map = BTreeMap::new();
+        map.insert(vec![1], "one");
+        assert_eq!(encode(&map), Err(EncoderError::BadMapKey));
+
+        // The output parses back.
+        let out = encode_with_config(&item(), &config).unwrap();
+        assert_eq!(EventReader::new(out.as_bytes()).count(), 22);
+    }
+}